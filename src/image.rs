@@ -1,24 +1,700 @@
 //! image module
 //! responsible for interacting with images, such as manipulating, saving and loading
 
-use std::io::{self, Write};
+use std::fmt::Display;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
 use std::iter::zip;
+use std::ops::Range;
 use std::path::Path;
+use std::time::Duration;
+use std::{env, thread};
 use std::{fs::File, io::BufWriter, path::PathBuf};
 
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 
 use crate::input::InputError;
+use crate::math::{lerp, Color, Vec3};
 
 /// Represents a pixel in Rgb with 3 values from 0 to 255
 pub type Rgb = [u8; 3];
 
+/// Delay before a save that failed with a transient error is tried again
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Error while reading or writing an image file
+#[derive(Debug)]
+pub enum ImageError {
+    /// the file couldn't be read or isn't a valid image
+    Load { path: PathBuf, source: io::Error },
+    /// the file couldn't be written or the image couldn't be encoded
+    Save { path: PathBuf, source: io::Error },
+}
+
+impl ImageError {
+    /// Return the path of the file that couldn't be read or written
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Load { path, .. } | Self::Save { path, .. } => path,
+        }
+    }
+
+    /// Return true if saving again after a moment may succeed, e.g. after an interrupted write
+    /// Full disks and missing permissions are not transient
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Load { .. } => false,
+            Self::Save { source, .. } => matches!(
+                source.kind(),
+                ErrorKind::Interrupted
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::TimedOut
+                    | ErrorKind::ResourceBusy
+            ),
+        }
+    }
+
+    /// Return the title of the error, without the cause
+    fn title(&self) -> String {
+        let path = self.path().to_str().unwrap_or("<INVALID_PATH>");
+        match self {
+            Self::Load { .. } => format!("Error while loading image from {path}"),
+            Self::Save { .. } => format!("Error while saving image to {path}"),
+        }
+    }
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (Self::Load { source, .. } | Self::Save { source, .. }) = self;
+        write!(f, "{}: {source}", self.title())
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        let (Self::Load { source, .. } | Self::Save { source, .. }) = self;
+        Some(source)
+    }
+}
+
+impl From<ImageError> for InputError {
+    fn from(err: ImageError) -> Self {
+        let (ImageError::Load { source, .. } | ImageError::Save { source, .. }) = &err;
+        InputError::new(err.title(), source.to_string())
+    }
+}
+
+/// Write a file at `path` with `save`, rescuing the work if that fails
+/// Transient errors are retried once after a short delay. If the file still can't be written, e.g.
+/// because the disk is full or the directory is read-only, it is written into the temp directory
+/// of the system instead and `path` is changed to that file. The rescued file gets the id of the
+/// process and, if needed, a counter appended to its name, so it never overwrites another file
+/// Returns the error of the original path if the file was rescued, `None` if it was written where
+/// it should
+///
+/// # Errors
+///
+/// Returns the error of the original path if the file couldn't be rescued either
+pub fn save_with_rescue(
+    path: &mut PathBuf,
+    mut save: impl FnMut(&mut PathBuf) -> Result<(), ImageError>,
+) -> Result<Option<ImageError>, ImageError> {
+    let err = match save(path) {
+        Err(err) if err.is_transient() => {
+            thread::sleep(RETRY_DELAY);
+            save(path)
+        }
+        result => result,
+    };
+    let Err(err) = err else {
+        return Ok(None);
+    };
+    let Some(mut rescue) = rescue_path(path) else {
+        return Err(err);
+    };
+    if save(&mut rescue).is_err() {
+        return Err(err);
+    }
+    *path = rescue;
+    Ok(Some(err))
+}
+
+/// Return a path in the temp directory for a rescued copy of `path` that no file has yet
+/// The name is the one of `path` with the process id and a counter for repeated rescues appended
+fn rescue_path(path: &Path) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let id = std::process::id();
+    (0..u32::MAX)
+        .map(|n| match n {
+            0 => format!("{stem}_{id}{extension}"),
+            n => format!("{stem}_{id}_{n}{extension}"),
+        })
+        .map(|name| env::temp_dir().join(name))
+        .find(|rescue| !rescue.exists())
+}
+
+/// Return the direction through the point (`s`, `t`) of the cube face with index `face`
+/// `s` goes from the left to the right and `t` from the top to the bottom edge of the face, both
+/// in range -1 to 1. The faces are ordered +X, -X, +Y, -Y, +Z, -Z
+fn cube_face_direction(face: usize, s: f32, t: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1., -t, -s),
+        1 => Vec3::new(-1., -t, s),
+        2 => Vec3::new(s, 1., t),
+        3 => Vec3::new(s, -1., -t),
+        4 => Vec3::new(s, -t, 1.),
+        _ => Vec3::new(-s, -t, -1.),
+    }
+}
+
+/// Return the texel of an equirectangular image in the direction `dir`
+/// Uses the same mapping as environment backgrounds and the texture coordinates of spheres
+fn equirectangular_uv(dir: Vec3) -> (f32, f32) {
+    let d = Vec3::normal(&dir);
+    let u = 0.5 + d[0].atan2(d[2]) / (2. * std::f32::consts::PI);
+    let v = 0.5 - d[1].clamp(-1., 1.).asin() / std::f32::consts::PI;
+    // rounding can push the poles just past the edge of the image
+    (u.clamp(0., 1.), v.clamp(0., 1.))
+}
+
+/// Scale a linear color by the exposure value `ev`, every step doubles the brightness
+#[must_use]
+pub fn expose(color: Color, ev: f32) -> Color {
+    color * 2f32.powf(ev)
+}
+
+#[cfg(test)]
+thread_local! {
+    /// number of calls to `Image::get_pixel` on the current thread
+    pub(crate) static PIXEL_LOOKUPS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Playback settings for animated images
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    /// default framerate
+    pub fps: u16,
+    /// number of times the animation is played; 0 loops infinitely
+    pub loops: u32,
+    /// play the frames forwards and then backwards
+    pub pingpong: bool,
+    /// per-frame delay overrides as `(frame, milliseconds)`
+    pub delays: Vec<(usize, u16)>,
+}
+
+/// How the rendered colors are encoded in the output image and which color chunks are written
+/// into png files
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TransferFunction {
+    /// colors are written unchanged and tagged with a gamma of 1/2.2 and the sRGB primaries
+    #[default]
+    Display,
+    /// colors are encoded with the sRGB curve and tagged as sRGB
+    Srgb,
+    /// colors are written unchanged and tagged with a gamma of 1 and the sRGB primaries
+    Linear,
+    /// colors are encoded with the power curve `c^(1/gamma)` and tagged with that gamma
+    Gamma(f32),
+    /// colors are written unchanged without any color chunks
+    Untagged,
+}
+
+impl TransferFunction {
+    /// Encode a linear color with the transfer function
+    #[must_use]
+    pub fn encode(self, color: Color) -> Color {
+        let encode = |f: &dyn Fn(f32) -> f32| {
+            Color::new(
+                f(color[0].max(0.)),
+                f(color[1].max(0.)),
+                f(color[2].max(0.)),
+            )
+        };
+        match self {
+            Self::Display | Self::Linear | Self::Untagged => color,
+            Self::Srgb => encode(&|c| {
+                if c <= 0.003_130_8 {
+                    12.92 * c
+                } else {
+                    1.055 * c.powf(1. / 2.4) - 0.055
+                }
+            }),
+            Self::Gamma(gamma) => encode(&|c| c.powf(1. / gamma)),
+        }
+    }
+
+    /// Write the gamma, chromaticity and sRGB chunks that describe the encoding
+    fn tag<W: Write>(self, encoder: &mut png::Encoder<'_, W>) {
+        let srgb_primaries = png::SourceChromaticities::new(
+            (0.31270, 0.32900),
+            (0.64000, 0.33000),
+            (0.30000, 0.60000),
+            (0.15000, 0.06000),
+        );
+        let gamma = match self {
+            Self::Display => png::ScaledFloat::from_scaled(45455),
+            Self::Linear => png::ScaledFloat::new(1.),
+            Self::Gamma(gamma) => png::ScaledFloat::new(1. / gamma),
+            Self::Srgb => {
+                // decoders that don't know the sRGB chunk fall back to gamma and chromaticities
+                encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+                png::ScaledFloat::from_scaled(45455)
+            }
+            Self::Untagged => return,
+        };
+        encoder.set_source_gamma(gamma);
+        encoder.set_source_chromaticities(srgb_primaries);
+    }
+}
+
+/// Weighting of the frames when they are averaged into a single image
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShutterProfile {
+    /// all frames have the same weight
+    Box,
+    /// the weight falls off linearly from the middle frames towards the first and last frame
+    Triangle,
+    /// one weight per frame
+    Custom(Vec<f32>),
+}
+
+impl ShutterProfile {
+    /// Return the weight of each of the `frames` frames
+    ///
+    /// # Panics
+    ///
+    /// when a custom profile doesn't have exactly `frames` weights
+    #[must_use]
+    pub fn weights(&self, frames: usize) -> Vec<f64> {
+        match self {
+            Self::Box => vec![1.; frames],
+            #[allow(clippy::cast_precision_loss)]
+            Self::Triangle => (0..frames)
+                .map(|i| (i + 1).min(frames - i) as f64)
+                .collect(),
+            Self::Custom(weights) => {
+                assert_eq!(
+                    weights.len(),
+                    frames,
+                    "custom shutter profile should have one weight per frame"
+                );
+                weights.iter().map(|w| f64::from(*w)).collect()
+            }
+        }
+    }
+}
+
+/// Value of the pixels that is counted by a histogram
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistogramChannel {
+    R,
+    G,
+    B,
+    /// relative luminance of the stored rgb values
+    Luminance,
+}
+
+impl HistogramChannel {
+    /// Return the bucket of `px` in a histogram of this channel
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn bucket(self, px: Rgb) -> usize {
+        match self {
+            Self::R => px[0] as usize,
+            Self::G => px[1] as usize,
+            Self::B => px[2] as usize,
+            Self::Luminance => luminance(px).round() as usize,
+        }
+    }
+}
+
+/// Return the Rec. 709 luminance of the stored rgb values of `px`, in 0..=255
+#[must_use]
+pub fn luminance(px: Rgb) -> f32 {
+    let [r, g, b] = px.map(f32::from);
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Return the Rec. 709 luminance of the `encoded` color in 0..=1, with the channels clamped like
+/// [`Color::to_rgb`] does
+fn encoded_luminance(encoded: Color) -> f32 {
+    let [r, g, b] =
+        [encoded[0], encoded[1], encoded[2]].map(|c| if c.is_nan() { 0. } else { c.clamp(0., 1.) });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Width of the lines of [`Image::uv_grid`] as a fraction of a cell, unless that makes them
+/// thinner than two pixels
+pub const UV_GRID_LINE_WIDTH: f32 = 0.05;
+
+/// Return the color of a uv debug grid at the texel `(u, v)`
+/// The unit square is split into `cells` x `cells` cells with dark lines between them,
+/// `line_width` wide as a fraction of a cell. The hue of a cell follows its column and the
+/// brightness its row, and neighbouring cells alternate in saturation like a checker board, so
+/// every cell has its own color and stretched, flipped or rotated uvs stand out. The grid repeats
+/// outside of the unit square
+#[must_use]
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn uv_grid_color(u: f32, v: f32, cells: u32, line_width: f32) -> Color {
+    let cells = cells.max(1);
+    let count = cells as f32;
+    // position in cells
+    let (cell_u, cell_v) = (u.rem_euclid(1.) * count, v.rem_euclid(1.) * count);
+    // distance to the closest cell border, in cells
+    let border = |t: f32| (t - t.round()).abs();
+    if border(cell_u).min(border(cell_v)) < 0.5 * line_width {
+        return Color::new(0.1, 0.1, 0.1);
+    }
+    let column = (cell_u as u32).min(cells - 1);
+    let row = (cell_v as u32).min(cells - 1);
+    let hue = (column as f32 + 0.5) / count;
+    let value = 0.45 + 0.5 * (row as f32 + 0.5) / count;
+    let saturation = if (column + row).is_multiple_of(2) {
+        0.85
+    } else {
+        0.45
+    };
+    hsv_color(hue, saturation, value)
+}
+
+/// Convert a color from hue, saturation and value in 0..=1 to rgb
+fn hsv_color(hue: f32, saturation: f32, value: f32) -> Color {
+    let channel = |n: f32| {
+        let k = (n + hue * 6.) % 6.;
+        value - value * saturation * k.min(4. - k).clamp(0., 1.)
+    };
+    Color::new(channel(5.), channel(3.), channel(1.))
+}
+
+/// Width of the glyphs of the bitmap font in font pixels
+pub const GLYPH_WIDTH: u32 = 5;
+
+/// Height of the glyphs of the bitmap font in font pixels
+pub const GLYPH_HEIGHT: u32 = 7;
+
+/// Distance from one character to the next in font pixels, the glyph and one pixel of space
+pub const GLYPH_ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Glyphs of the 5x7 bitmap font, the rows of every glyph from top to bottom with the pixels in
+/// the lowest 5 bits of a row, from left to right
+#[rustfmt::skip]
+const FONT: [(char, [u8; 7]); 51] = [
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000]),
+    ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110]),
+    (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110]),
+    ('#', [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010]),
+    ('%', [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011]),
+];
+
+/// Glyph of characters that the font doesn't have
+const UNKNOWN_GLYPH: [u8; 7] = [
+    0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+];
+
+/// Return the glyph of `c`, lowercase letters use the uppercase glyphs
+fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(glyph, _)| *glyph == c)
+        .map_or(UNKNOWN_GLYPH, |(_, rows)| *rows)
+}
+
+/// Return the width in pixels of `text` drawn with [`Image::draw_text`] at `scale`
+#[must_use]
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let chars = u32::try_from(text.chars().count()).unwrap_or(u32::MAX);
+    chars
+        .saturating_mul(GLYPH_ADVANCE)
+        .saturating_sub(1)
+        .saturating_mul(scale)
+}
+
+/// Channels of saved images and their order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Channels {
+    #[default]
+    Rgb,
+    /// red and blue swapped, for tools that expect the byte order of bgr buffers
+    Bgr,
+    /// 8 bit luminance, see [`luminance`]
+    Gray,
+    /// 16 bit luminance, taken from the colors before they are quantized to 8 bits where the
+    /// image still has them
+    Gray16,
+}
+
+impl Display for Channels {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Rgb => "rgb",
+            Self::Bgr => "bgr",
+            Self::Gray => "gray",
+            Self::Gray16 => "gray16",
+        })
+    }
+}
+
+impl Channels {
+    /// Return the png color type, bit depth and samples of `pixels` in these channels
+    /// `gray` holds the luminance of the unquantized pixels, 16 bit gray uses it if it is not
+    /// empty. `alpha` holds one value per pixel and is appended to every pixel if it is given.
+    /// Samples of 16 bit images are big endian
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn encode(
+        self,
+        pixels: &[Rgb],
+        gray: &[f32],
+        alpha: Option<&[u8]>,
+    ) -> (png::ColorType, png::BitDepth, Vec<u8>) {
+        let color = match (self, alpha.is_some()) {
+            (Self::Rgb | Self::Bgr, false) => png::ColorType::Rgb,
+            (Self::Rgb | Self::Bgr, true) => png::ColorType::Rgba,
+            (Self::Gray | Self::Gray16, false) => png::ColorType::Grayscale,
+            (Self::Gray | Self::Gray16, true) => png::ColorType::GrayscaleAlpha,
+        };
+        let depth = if self == Self::Gray16 {
+            png::BitDepth::Sixteen
+        } else {
+            png::BitDepth::Eight
+        };
+        let mut data = Vec::with_capacity(pixels.len() * color.samples() * 2);
+        for (i, px) in pixels.iter().enumerate() {
+            match self {
+                Self::Rgb => data.extend(px),
+                Self::Bgr => data.extend([px[2], px[1], px[0]]),
+                Self::Gray => data.push(luminance(*px).round() as u8),
+                Self::Gray16 => {
+                    // multiplying by 257 maps 0..=255 onto 0..=65535
+                    let value = gray.get(i).map_or(luminance(*px) * 257., |g| g * 65535.);
+                    data.extend((value.round() as u16).to_be_bytes());
+                }
+            }
+            if let Some(alpha) = alpha {
+                if self == Self::Gray16 {
+                    data.extend((u16::from(alpha[i]) * 257).to_be_bytes());
+                } else {
+                    data.push(alpha[i]);
+                }
+            }
+        }
+        (color, depth, data)
+    }
+}
+
+impl Playback {
+    /// Create new playback settings with the given framerate that loop infinitely
+    #[must_use]
+    pub fn new(fps: u16) -> Playback {
+        Playback {
+            fps,
+            loops: 0,
+            pingpong: false,
+            delays: Vec::new(),
+        }
+    }
+
+    /// Return the order in which the frames of an animation with `frames` frames are played
+    /// For ping-pong playback the frames `frames - 2..=1` are appended in reverse
+    #[must_use]
+    pub fn frame_order(&self, frames: usize) -> Vec<usize> {
+        let forward = 0..frames;
+        if self.pingpong && frames > 2 {
+            forward.chain((1..frames - 1).rev()).collect()
+        } else {
+            forward.collect()
+        }
+    }
+
+    /// Return the delay of the given frame as a fraction `(numerator, denominator)` in seconds
+    #[must_use]
+    pub fn frame_delay(&self, frame: usize) -> (u16, u16) {
+        self.delays
+            .iter()
+            .find(|(f, _)| *f == frame)
+            .map_or((1, self.fps), |(_, ms)| (*ms, 1000))
+    }
+}
+
+/// A mip pyramid of successively downscaled versions of an image
+/// Used to filter minified textures
+#[derive(Debug, Clone, PartialEq)]
+pub struct MipMap {
+    levels: Vec<Image>,
+}
+
+impl MipMap {
+    /// Generate the mip pyramid of an image by halving the dimensions with a box filter until the
+    /// image is a single pixel
+    #[must_use]
+    pub fn new(image: Image) -> MipMap {
+        let mut levels = vec![image];
+        loop {
+            let (width, height) = levels[levels.len() - 1].dimensions();
+            if width <= 1 && height <= 1 {
+                break;
+            }
+            let next = levels[levels.len() - 1].resized(width.div_ceil(2), height.div_ceil(2));
+            levels.push(next);
+        }
+        MipMap { levels }
+    }
+
+    /// Return the number of levels in the pyramid
+    #[must_use]
+    pub fn level_count(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Return the full resolution image
+    #[must_use]
+    pub fn base(&self) -> &Image {
+        &self.levels[0]
+    }
+
+    /// Return the number of bytes used by all levels
+    #[must_use]
+    pub fn memory_size(&self) -> usize {
+        self.levels.iter().map(Image::memory_size).sum()
+    }
+
+    /// Sample the texture at texel `(u, v)`
+    /// `footprint` is the size of the area covered by the lookup in texture space (1 is the whole
+    /// texture). The level is chosen from the footprint and the two closest levels are blended
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn sample(&self, u: f32, v: f32, footprint: f32) -> Color {
+        let (width, height) = self.base().dimensions();
+        let texels = footprint * width.max(height) as f32;
+        let max_level = (self.levels.len() - 1) as f32;
+        let lod = if texels > 1. {
+            texels.log2().min(max_level)
+        } else {
+            0.
+        };
+
+        let lower = lod.floor();
+        let w = lod - lower;
+        let lower = lower as usize;
+        let color = Color::from(self.levels[lower].get_pixel(0, u, v));
+        if w == 0. {
+            return color;
+        }
+        let upper = Color::from(self.levels[lower + 1].get_pixel(0, u, v));
+        lerp(color, upper, w)
+    }
+}
+
+/// Tag at the start of a `.flo` file, the float 202021.25 in little endian
+const FLO_TAG: &[u8; 4] = b"PIEH";
+
+/// Save the two-channel `vectors` of an image with `width` x `height` pixels, in rows from the
+/// top, to a [Middlebury `.flo` file](https://vision.middlebury.edu/flow/code/flow-code/README.txt)
+/// The format stores 32 bit floats, so the vectors are saved without loss and can be read by
+/// optical flow and frame interpolation tools
+///
+/// # Errors
+///
+/// Returns an error if the file can't be written
+///
+/// # Panics
+///
+/// Panics if the number of vectors doesn't match the size of the image
+pub fn save_flo(
+    path: &Path,
+    width: u32,
+    height: u32,
+    vectors: &[(f32, f32)],
+) -> Result<(), ImageError> {
+    assert_eq!(vectors.len(), (width * height) as usize);
+    let write_err = |err| save_err(err, path);
+    let mut w = BufWriter::new(File::create(path).map_err(write_err)?);
+    w.write_all(FLO_TAG).map_err(write_err)?;
+    for size in [width, height] {
+        w.write_all(&size.to_le_bytes()).map_err(write_err)?;
+    }
+    for (x, y) in vectors {
+        w.write_all(&x.to_le_bytes()).map_err(write_err)?;
+        w.write_all(&y.to_le_bytes()).map_err(write_err)?;
+    }
+    w.flush().map_err(write_err)
+}
+
 /// Represents an Image which holds its width and height and the appropriate amount of Rgb pixels
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Image {
     width: u32,
     height: u32,
     buf: Vec<Vec<Rgb>>,
+    border: Rgb,
+    /// encoding of the pixels, used to tag saved png files
+    transfer: TransferFunction,
+    /// (keyword, text) pairs that are written as text chunks into saved png files
+    text: Vec<(String, String)>,
+    /// channels of saved files
+    channels: Channels,
+    /// luminance of every pixel per frame in 0..=1, taken from the colors before they were
+    /// quantized to 8 bits, see [`Image::par_init_colors`]. Frames without it are saved from the
+    /// 8 bit pixels
+    gray: Vec<Vec<f32>>,
 }
 
 impl Image {
@@ -30,27 +706,113 @@ impl Image {
             width,
             height,
             buf: vec![vec![[0; 3]; (width * height) as usize]; frames],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        }
+    }
+
+    /// Create a single frame image from its pixels in row-major order
+    ///
+    /// # Panics
+    ///
+    /// If the number of pixels doesn't match the dimensions
+    #[must_use]
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<Rgb>) -> Image {
+        assert_eq!(pixels.len(), (width * height) as usize);
+        Image {
+            width,
+            height,
+            buf: vec![pixels],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
         }
     }
 
+    /// Create a uv debug grid with `cells` x `cells` cells, see [`uv_grid_color`]
+    /// Texel lookups on the image give the same colors as the grid itself, the lines are
+    /// [`UV_GRID_LINE_WIDTH`] wide but at least two pixels
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn uv_grid(width: u32, height: u32, cells: u32) -> Image {
+        let pixels_per_cell = width.min(height).max(1) as f32 / cells.max(1) as f32;
+        let line_width = UV_GRID_LINE_WIDTH.max(2. / pixels_per_cell);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let u = (x as f32 + 0.5) / width as f32;
+                let v = (y as f32 + 0.5) / height as f32;
+                uv_grid_color(u, v, cells, line_width).to_rgb()
+            })
+            .collect();
+        Image::from_pixels(width, height, pixels)
+    }
+
+    /// Create the magenta and black checker with `cells` x `cells` cells that stands in for a
+    /// texture that could not be loaded, so the problem is obvious in the render
+    #[must_use]
+    pub fn missing_texture(size: u32, cells: u32) -> Image {
+        let cell = (size / cells.max(1)).max(1);
+        let pixels = (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size / cell, i / size / cell);
+                if (x + y).is_multiple_of(2) {
+                    [255, 0, 255]
+                } else {
+                    [0, 0, 0]
+                }
+            })
+            .collect();
+        Image::from_pixels(size, size, pixels)
+    }
+
     /// Load a png from the given path into an `Image`
     ///
     /// # Errors
     ///
-    /// returns an ``InputError`` if the file cannot be read or is not a valid png file
-    pub fn load_png(path: &PathBuf) -> Result<Image, InputError> {
-        let file = File::open(path)
-            .map_err(|err| Self::err_to_input_err(&err, path, "Error while reading image from"))?;
-        let decoder = png::Decoder::new(file);
-        let mut reader = decoder.read_info().map_err(|err| {
-            Self::err_to_input_err(&err.into(), path, "Error while decoding image")
-        })?;
+    /// returns an ``ImageError::Load`` if the file cannot be read or is not a valid png file
+    pub fn load_png(path: &PathBuf) -> Result<Image, ImageError> {
+        let file = File::open(path).map_err(|err| load_err(err, path))?;
+        Self::decode_png(BufReader::new(file)).map_err(|err| load_err(err.into(), path))
+    }
+
+    /// Read only the header of the png at `path` and return its dimensions as `(width, height)`
+    ///
+    /// # Errors
+    ///
+    /// returns an ``ImageError::Load`` if the file cannot be read or has no valid png header
+    pub fn png_dimensions(path: &Path) -> Result<(u32, u32), ImageError> {
+        let file = File::open(path).map_err(|err| load_err(err, path))?;
+        let reader = png::Decoder::new(BufReader::new(file))
+            .read_info()
+            .map_err(|err| load_err(err.into(), path))?;
+        let info = reader.info();
+        Ok((info.width, info.height))
+    }
+
+    /// Decode a png from any reader
+    /// 16-bit images are converted to 8 bit per channel
+    fn decode_png<R: Read>(r: R) -> Result<Image, png::DecodingError> {
+        let decoder = png::Decoder::new(r);
+        let mut reader = decoder.read_info()?;
 
         let mut buf = vec![0; reader.output_buffer_size()];
-        let info = reader.next_frame(&mut buf).map_err(|err| {
-            Self::err_to_input_err(&err.into(), path, "Error while decoding image")
-        })?;
+        let info = reader.next_frame(&mut buf)?;
         let bytes = &buf[..info.buffer_size()];
+        let bytes: Vec<u8> = match info.bit_depth {
+            // big endian samples, dividing by 257 maps 0..=65535 onto 0..=255
+            #[allow(clippy::cast_possible_truncation)]
+            png::BitDepth::Sixteen => bytes
+                .chunks_exact(2)
+                .map(|s| (u16::from_be_bytes([s[0], s[1]]) / 257) as u8)
+                .collect(),
+            _ => bytes.to_vec(),
+        };
         let imgbuf: Vec<_> = bytes.chunks(3).map(|a| [a[0], a[1], a[2]]).collect();
         let width = info.width;
         let height = info.height;
@@ -59,31 +821,416 @@ impl Image {
             width,
             height,
             buf: vec![imgbuf],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        })
+    }
+
+    /// Return the dimensions of the image as `(width, height)`
+    #[must_use]
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Return the number of bytes used by the pixel data of all frames
+    #[must_use]
+    pub fn memory_size(&self) -> usize {
+        self.buf.iter().map(|frame| frame.len() * 3).sum()
+    }
+
+    /// Compute the source pixels covered by each destination pixel along one axis together with
+    /// the fraction of the source pixel that is covered
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn box_weights(src: u32, dst: u32) -> Vec<Vec<(usize, f32)>> {
+        let ratio = src as f32 / dst as f32;
+        (0..dst)
+            .map(|d| {
+                let start = d as f32 * ratio;
+                let end = (d + 1) as f32 * ratio;
+                let first = start.floor() as usize;
+                let last = (end.ceil() as usize).min(src as usize);
+                (first..last)
+                    .map(|i| {
+                        let covered = end.min(i as f32 + 1.) - start.max(i as f32);
+                        (i, covered)
+                    })
+                    .filter(|(_, w)| *w > 0.)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Return a copy of the image resized to `width` x `height` using a box filter
+    /// Every destination pixel is the area weighted average of all source pixels it covers, so
+    /// non-integer ratios don't drop or duplicate pixels
+    ///
+    /// # Panics
+    ///
+    /// if `width` or `height` is 0
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn resized(&self, width: u32, height: u32) -> Image {
+        assert!(width > 0 && height > 0, "image dimensions must be positive");
+        let columns = Self::box_weights(self.width, width);
+        let rows = Self::box_weights(self.height, height);
+
+        let buf = self
+            .buf
+            .iter()
+            .map(|frame| {
+                rows.iter()
+                    .flat_map(|row| {
+                        columns.iter().map(move |column| {
+                            let mut acc = [0f32; 3];
+                            let mut total = 0.;
+                            for (y, wy) in row {
+                                for (x, wx) in column {
+                                    let px = frame[x + y * self.width as usize];
+                                    let w = wx * wy;
+                                    acc[0] += f32::from(px[0]) * w;
+                                    acc[1] += f32::from(px[1]) * w;
+                                    acc[2] += f32::from(px[2]) * w;
+                                    total += w;
+                                }
+                            }
+                            acc.map(|c| (c / total).round().clamp(0., 255.) as u8)
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Image {
+            width,
+            height,
+            buf,
+            border: self.border,
+            transfer: self.transfer,
+            text: self.text.clone(),
+            channels: self.channels,
+            gray: Vec::new(),
+        }
+    }
+
+    /// Return a thumbnail of `frame` that fits into `width` x `height`
+    /// The aspect ratio of the image is preserved, so one side of the thumbnail can be shorter.
+    /// Every thumbnail pixel is the area weighted average of the pixels it covers
+    ///
+    /// # Panics
+    ///
+    /// If the image does not contain the frame, or `width` or `height` is 0
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn generate_equirectangular_thumbnail(
+        &self,
+        frame: usize,
+        width: u32,
+        height: u32,
+    ) -> Image {
+        assert!(width > 0 && height > 0, "image dimensions must be positive");
+        let scale = (width as f32 / self.width as f32).min(height as f32 / self.height as f32);
+        let fit = |size: u32, max: u32| ((size as f32 * scale).round() as u32).clamp(1, max);
+        let mut single = Image::from_pixels(
+            self.width,
+            self.height,
+            self.buf.get(frame).expect("frame should exist").clone(),
+        );
+        single.border = self.border;
+        single.transfer = self.transfer;
+        single.resized(fit(self.width, width), fit(self.height, height))
+    }
+
+    /// Project the first frame of an equirectangular image onto the six faces of a cube
+    /// The faces are ordered +X, -X, +Y, -Y, +Z, -Z and oriented like the faces of an OpenGL
+    /// cubemap. Their size is a quarter of the width of the image, which keeps about the
+    /// resolution of the image at the equator. The image is mapped onto directions like
+    /// environment backgrounds
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn to_cubemap_faces(&self) -> [Image; 6] {
+        let size = (self.width / 4).max(1);
+        std::array::from_fn(|face| {
+            let pixels = (0..size * size)
+                .map(|i| {
+                    // face coordinates of the pixel center in -1..1
+                    let s = 2. * ((i % size) as f32 + 0.5) / size as f32 - 1.;
+                    let t = 2. * ((i / size) as f32 + 0.5) / size as f32 - 1.;
+                    let (u, v) = equirectangular_uv(cube_face_direction(face, s, t));
+                    self.get_pixel(0, u, v)
+                })
+                .collect();
+            let mut img = Image::from_pixels(size, size, pixels);
+            img.transfer = self.transfer;
+            img
         })
     }
 
+    /// Downscale the image so that neither width nor height exceed `max_size`
+    /// The aspect ratio is preserved. Images that already fit are returned unchanged
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn limit_size(self, max_size: u32) -> Image {
+        let max_size = max_size.max(1);
+        let largest = self.width.max(self.height);
+        if largest <= max_size {
+            return self;
+        }
+        let scale = max_size as f32 / largest as f32;
+        let width = ((self.width as f32 * scale).round() as u32).clamp(1, max_size);
+        let height = ((self.height as f32 * scale).round() as u32).clamp(1, max_size);
+        self.resized(width, height)
+    }
+
+    /// Set the color that is returned for texel lookups outside of the image
+    pub fn set_border_color(&mut self, color: Rgb) {
+        self.border = color;
+    }
+
+    /// Set the transfer function the pixels were encoded with
+    /// The png chunks written when saving the image are chosen according to it
+    pub fn set_transfer_function(&mut self, transfer: TransferFunction) {
+        self.transfer = transfer;
+    }
+
+    /// Set the channels of saved files
+    pub fn set_channels(&mut self, channels: Channels) {
+        self.channels = channels;
+    }
+
+    /// Create a single frame image from linear `colors`, exposed with `ev` and encoded with
+    /// `transfer`
+    /// The luminance of the colors is kept for saving with [`Channels::Gray16`]
+    ///
+    /// # Panics
+    ///
+    /// If the number of colors doesn't match the dimensions
+    #[must_use]
+    pub fn from_colors(
+        width: u32,
+        height: u32,
+        colors: &[Color],
+        ev: f32,
+        transfer: TransferFunction,
+    ) -> Image {
+        let encoded: Vec<_> = colors
+            .iter()
+            .map(|c| transfer.encode(expose(*c, ev)))
+            .collect();
+        let mut img =
+            Image::from_pixels(width, height, encoded.iter().map(|c| c.to_rgb()).collect());
+        img.set_transfer_function(transfer);
+        img.gray = vec![encoded.into_iter().map(encoded_luminance).collect()];
+        img
+    }
+
+    /// Add a text chunk with `keyword` and `text` that is written into saved png files
+    /// Both should only contain latin-1 characters, the keyword is limited to 79 characters
+    pub fn add_text(&mut self, keyword: &str, text: &str) {
+        self.text.push((keyword.to_string(), text.to_string()));
+    }
+
+    /// Draw `text` into `frame` with the 5x7 bitmap font, with the top left corner of the first
+    /// character at (`x`, `y`)
+    /// Every font pixel becomes a square of `scale` x `scale` pixels, see [`text_width`]. Only the
+    /// pixels of the glyphs are drawn, parts outside of the image are cut off
+    ///
+    /// # Panics
+    ///
+    /// will panic if the image does not contain the frame
+    pub fn draw_text(&mut self, frame: usize, x: u32, y: u32, text: &str, color: Rgb, scale: u32) {
+        let (width, height) = (self.width, self.height);
+        let pixels = self.buf.get_mut(frame).expect("frame should exist");
+        let mut gray = self.gray.get_mut(frame).filter(|gray| !gray.is_empty());
+        let mut left = x;
+        for c in text.chars() {
+            for (row, bits) in (0..).zip(glyph(c)) {
+                for column in
+                    (0..GLYPH_WIDTH).filter(|col| bits & (1 << (GLYPH_WIDTH - 1 - col)) != 0)
+                {
+                    for (dx, dy) in (0..scale * scale).map(|i| (i % scale, i / scale)) {
+                        let px = left + column * scale + dx;
+                        let py = y + row * scale + dy;
+                        if px < width && py < height {
+                            let i = (px + py * width) as usize;
+                            pixels[i] = color;
+                            if let Some(gray) = gray.as_mut() {
+                                gray[i] = luminance(color) / 255.;
+                            }
+                        }
+                    }
+                }
+            }
+            left = left.saturating_add(GLYPH_ADVANCE * scale);
+        }
+    }
+
+    /// Blend `color` with the opacity `alpha` over the rectangle of `width` x `height` pixels with
+    /// the top left corner at (`x`, `y`), in every frame
+    /// `alpha` is clamped to 0..=1, parts outside of the image are cut off
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn fill_rect(&mut self, x: u32, y: u32, width: u32, height: u32, color: Rgb, alpha: f32) {
+        let alpha = alpha.clamp(0., 1.);
+        let columns = x.min(self.width)..x.saturating_add(width).min(self.width);
+        let rows = y.min(self.height)..y.saturating_add(height).min(self.height);
+        for (frame, pixels) in self.buf.iter_mut().enumerate() {
+            for py in rows.clone() {
+                for px in columns.clone() {
+                    let i = (px + py * self.width) as usize;
+                    for (channel, target) in pixels[i].iter_mut().zip(color) {
+                        let blended = lerp(f32::from(*channel), f32::from(target), alpha);
+                        *channel = blended.round() as u8;
+                    }
+                    if let Some(g) = self.gray.get_mut(frame).and_then(|gray| gray.get_mut(i)) {
+                        *g = lerp(*g, luminance(color) / 255., alpha);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Arrange `thumbnails` in a labeled grid, for example to preview a directory of scenes
+    /// Every cell holds an image of at most `size` x `size` pixels, centered, with its label below.
+    /// Labels that are too long are cut off, cells without an image are left empty apart from a
+    /// note. The grid is about as wide as it is high and filled row by row
+    #[must_use]
+    pub fn contact_sheet(thumbnails: &[(String, Option<Image>)], size: u32) -> Image {
+        const BACKGROUND: Rgb = [32; 3];
+        const LABEL: Rgb = [220; 3];
+        let size = size.max(1);
+        let scale = if size >= 256 { 2 } else { 1 };
+        let padding = (size / 16).max(2);
+        let (cell_width, cell_height) = (
+            size + 2 * padding,
+            size + GLYPH_HEIGHT * scale + 3 * padding,
+        );
+        let count = u32::try_from(thumbnails.len()).unwrap_or(u32::MAX).max(1);
+        let columns = count.isqrt() + u32::from(count.isqrt().pow(2) < count);
+        let rows = count.div_ceil(columns);
+        let mut sheet = Image::from_pixels(
+            columns * cell_width,
+            rows * cell_height,
+            vec![BACKGROUND; (columns * cell_width * rows * cell_height) as usize],
+        );
+        let max_chars = (size + 1) / (GLYPH_ADVANCE * scale);
+        for ((label, thumbnail), i) in thumbnails.iter().zip(0..) {
+            let (left, top) = ((i % columns) * cell_width, (i / columns) * cell_height);
+            let label: String = if label.chars().count() > max_chars as usize {
+                let mut cut: String = label
+                    .chars()
+                    .take(max_chars.saturating_sub(2) as usize)
+                    .collect();
+                cut.push_str("..");
+                cut
+            } else {
+                label.clone()
+            };
+            let label_top = top + size + 2 * padding;
+            sheet.draw_text(0, left + padding, label_top, &label, LABEL, scale);
+            let Some(thumbnail) = thumbnail else {
+                sheet.draw_text(0, left + padding, top + padding, "no image", LABEL, scale);
+                continue;
+            };
+            let thumbnail = thumbnail.clone().limit_size(size);
+            let (width, height) = thumbnail.dimensions();
+            let x0 = left + padding + (size - width) / 2;
+            let y0 = top + padding + (size - height) / 2;
+            let sheet_width = sheet.width;
+            let pixels = &mut sheet.buf[0];
+            for (j, px) in (0..).zip(&thumbnail.buf[0]) {
+                let (x, y) = (x0 + j % width, y0 + j / width);
+                pixels[(x + y * sheet_width) as usize] = *px;
+            }
+        }
+        sheet
+    }
+
     /// Return the images `Rgb` value at the given Texel `(u, v)`
+    /// Texels outside of the range 0..=1 return the border color
     ///
     /// # Panics
     ///
-    /// will panic if `u` or `v` are not in range 0..1
+    /// will panic if the image does not contain the frame
     #[must_use]
     pub fn get_pixel(&self, frame: usize, u: f32, v: f32) -> Rgb {
+        #[cfg(test)]
+        PIXEL_LOOKUPS.with(|n| n.set(n.get() + 1));
+        if !(0. ..=1.).contains(&u) || !(0. ..=1.).contains(&v) {
+            return self.border;
+        }
         #[allow(
             clippy::cast_possible_truncation,
             clippy::cast_sign_loss,
             clippy::cast_precision_loss
         )]
         let (x, y) = (
-            (u * self.width as f32) as u32,
-            (v * self.height as f32) as u32,
+            // u or v = 1 would be one pixel past the edge
+            ((u * self.width as f32) as u32).min(self.width - 1),
+            ((v * self.height as f32) as u32).min(self.height - 1),
         );
-        *self
-            .buf
-            .get(frame)
-            .unwrap()
-            .get((x + self.width * y) as usize)
-            .unwrap()
+        self.buf.get(frame).expect("frame should exist")[(x + self.width * y) as usize]
+    }
+
+    /// Count the pixels of `frame` by their value in `channel`
+    /// Each of the 256 buckets holds the number of pixels with that value
+    ///
+    /// # Panics
+    ///
+    /// will panic if the image does not contain the frame
+    #[must_use]
+    pub fn compute_histogram(&self, frame: usize, channel: HistogramChannel) -> [u32; 256] {
+        let mut histogram = [0; 256];
+        for px in self.buf.get(frame).expect("frame should exist") {
+            histogram[channel.bucket(*px)] += 1;
+        }
+        histogram
+    }
+
+    /// Return the luminance below which the fraction `p` of the pixels of `frame` lie, scaled to
+    /// 0..=1
+    /// `p = 0` returns the darkest and `p = 1` the brightest pixel, `p` is clamped to 0..=1
+    ///
+    /// # Panics
+    ///
+    /// will panic if the image does not contain the frame
+    #[must_use]
+    pub fn percentile_brightness(&self, frame: usize, p: f32) -> f32 {
+        let histogram = self.compute_histogram(frame, HistogramChannel::Luminance);
+        let total: u32 = histogram.iter().sum();
+        // nearest rank of the percentile among all pixels
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = (p.clamp(0., 1.) * total.saturating_sub(1) as f32).round() as u32;
+        let mut seen = 0;
+        for (bucket, count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen > rank {
+                #[allow(clippy::cast_precision_loss)]
+                return bucket as f32 / 255.;
+            }
+        }
+        0.
     }
 
     /// Set each pixel from the corresponding x and y value
@@ -96,131 +1243,163 @@ impl Image {
     where
         OP: Fn(&mut (u32, u32)) -> Rgb + Sync + Send,
     {
-        assert!(self.buf.len() >= frame);
-        let mut x = 0;
-        let mut y = 0;
+        assert!(self.buf.len() > frame);
+        let pixels = self.par_map_pixels(op);
+        let f = self.buf.get_mut(frame).unwrap();
+        *f = pixels;
+        if let Some(gray) = self.gray.get_mut(frame) {
+            gray.clear();
+        }
+    }
 
-        let mut coords: Vec<_> = self
-            .buf
-            .get(frame)
-            .unwrap()
-            .iter()
-            .map(|_| {
-                if x < self.width - 1 {
-                    x += 1;
-                } else {
-                    y += 1;
-                    x = 0;
-                }
+    /// Initialize every pixel of `frame` with the linear color `op` returns for its coordinates,
+    /// encoded with the transfer function of the image
+    /// Images saved with [`Channels::Gray16`] keep the luminance of the colors before they are
+    /// quantized, so the extra bits hold more than the 8 bit pixels
+    ///
+    /// # Panics
+    ///
+    /// when the image does not have enough frames
+    pub fn par_init_colors<OP>(&mut self, frame: usize, op: OP)
+    where
+        OP: Fn(&mut (u32, u32)) -> Color + Sync + Send,
+    {
+        assert!(self.buf.len() > frame);
+        let transfer = self.transfer;
+        let encoded = self.par_map_pixels(|coords| transfer.encode(op(coords)));
+        self.buf[frame] = encoded.iter().map(|c| c.to_rgb()).collect();
+        self.gray.resize(self.buf.len(), Vec::new());
+        self.gray[frame] = if self.channels == Channels::Gray16 {
+            encoded.into_iter().map(encoded_luminance).collect()
+        } else {
+            Vec::new()
+        };
+    }
 
-                (x, y)
-            })
-            .collect();
-        let f = self.buf.get_mut(frame).unwrap();
-        *f = coords.par_iter_mut().map(op).collect();
+    /// Return the luminance of the unquantized pixels of `frame`, empty if it wasn't kept
+    fn frame_gray(&self, frame: usize) -> &[f32] {
+        self.gray.get(frame).map_or(&[], Vec::as_slice)
     }
 
-    /// format io error to input error
-    fn err_to_input_err(err: &io::Error, path: &Path, msg: &str) -> InputError {
-        InputError::new(
-            format!("{} {}", msg, path.to_str().unwrap_or("<INVALID_PATH>")),
-            err.to_string(),
-        )
+    /// Call `op` with the coordinates of every pixel and collect the results in pixel order
+    /// Uses the same coordinates as `par_init_pixels`
+    /// Every result is computed independently and placed by its pixel index, so the output does
+    /// not depend on the number of threads or on the order in which they finish
+    pub fn par_map_pixels<T, OP>(&self, op: OP) -> Vec<T>
+    where
+        T: Send,
+        OP: Fn(&mut (u32, u32)) -> T + Sync + Send,
+    {
+        // pixel `i` lies in column `i % width` of row `i / width`, counted from the top left
+        let mut coords: Vec<_> = (0..self.width * self.height)
+            .map(|i| (i % self.width, i / self.width))
+            .collect();
+        coords.par_iter_mut().map(op).collect()
     }
 
-    /// average all frames in the image and place the result in the first frame
-    /// for single frame images this shouldn't change anything. For images with multiple frames
-    /// (animations) this will 'blur' any movement between the images
+    /// Combine the frames in `frame_range` into a new single frame image, weighted by `profile`
+    /// For images with multiple frames (animations) this will 'blur' any movement between the
+    /// frames. The frames of this image are left untouched
     ///
     /// # Panics
     ///
-    /// when the image contains no frames
-    pub fn average_frames(&mut self) {
-        let mut t = self
+    /// when `frame_range` is empty or not inside the image, when a custom profile doesn't have
+    /// one weight per frame, or when the weights sum up to zero
+    #[must_use]
+    pub fn average_frames(&self, profile: &ShutterProfile, frame_range: Range<usize>) -> Image {
+        let frames = self
             .buf
-            .iter_mut()
-            // convert to a data type that can hold higher numbers, so no overflow happens when
-            // adding (u64::max() / u8::max() ~= 7.2e16; should be enough frames)
-            .map(|frame| {
-                frame
-                    .iter()
-                    .map(|px| [u64::from(px[0]), u64::from(px[1]), u64::from(px[2])])
-                    .collect()
-            })
-            .reduce(|acc: Vec<[u64; 3]>, frame| {
-                zip(acc, frame)
-                    .map(|z| [z.0[0] + z.1[0], z.0[1] + z.1[1], z.0[2] + z.1[2]])
-                    .collect()
-            })
-            .expect("Image should contain atleast one frame");
-        let frames = self.buf.len() as u64;
-        #[allow(clippy::cast_possible_truncation)]
-        let t: Vec<_> = t
-            .iter_mut()
-            .map(|px| [px[0] / frames, px[1] / frames, px[2] / frames])
-            .map(|px| [px[0] as u8, px[1] as u8, px[2] as u8])
+            .get(frame_range.clone())
+            .filter(|frames| !frames.is_empty())
+            .expect("frame range should contain atleast one frame of the image");
+        let weights = profile.weights(frames.len());
+        let total: f64 = weights.iter().sum();
+        assert!(total > 0., "shutter weights should not sum up to zero");
+
+        // accumulate in floating point and round at the end, so the result isn't biased darker
+        let mut acc = vec![[0f64; 3]; frames[0].len()];
+        for (frame, weight) in zip(frames, &weights) {
+            for (acc, px) in zip(&mut acc, frame) {
+                for c in 0..3 {
+                    acc[c] += weight * f64::from(px[c]);
+                }
+            }
+        }
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let pixels = acc
+            .iter()
+            .map(|px| px.map(|c| (c / total).round() as u8))
             .collect();
-        self.buf[0] = t;
+        // the luminance before quantizing is only kept if every frame has it
+        let grays: Vec<_> = frame_range.map(|frame| self.frame_gray(frame)).collect();
+        let gray = if grays.iter().all(|gray| !gray.is_empty()) {
+            #[allow(clippy::cast_possible_truncation)]
+            let gray = (0..grays[0].len())
+                .map(|i| {
+                    let sum: f64 = zip(&grays, &weights)
+                        .map(|(gray, weight)| weight * f64::from(gray[i]))
+                        .sum();
+                    (sum / total) as f32
+                })
+                .collect();
+            vec![gray]
+        } else {
+            Vec::new()
+        };
+
+        Image {
+            width: self.width,
+            height: self.height,
+            buf: vec![pixels],
+            border: self.border,
+            transfer: self.transfer,
+            text: self.text.clone(),
+            channels: self.channels,
+            gray,
+        }
     }
 
-    /// Save the image as an animated png with the specified framerate
+    /// Save the image as an animated png with the specified playback settings
     /// for this to have any effect, the buffer should contain multiple frames
     ///
     /// # Errors
     ///
-    /// Returns an ``InputError`` when the file couldn't be created or written to, or an error
+    /// Returns an ``ImageError::Save`` when the file couldn't be created or written to, or an error
     /// occured while encoding
-    pub fn save_apng(self, path: &mut PathBuf, fps: u16) -> Result<(), InputError> {
+    pub fn save_apng(&self, path: &mut PathBuf, playback: &Playback) -> Result<(), ImageError> {
         path.set_extension("png");
-        let file = File::create(&path)
-            .map_err(|err| Self::err_to_input_err(&err, path, "Error while saving image to"))?;
-        let w = &mut BufWriter::new(file);
-
-        let mut encoder = png::Encoder::new(w, self.width, self.height);
-        encoder.set_color(png::ColorType::Rgb);
-        encoder.set_depth(png::BitDepth::Eight);
-        encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
-        let source_chromaticities = png::SourceChromaticities::new(
-            (0.31270, 0.32900),
-            (0.64000, 0.33000),
-            (0.30000, 0.60000),
-            (0.15000, 0.06000),
-        );
-        encoder.set_source_chromaticities(source_chromaticities);
+        let err = |err| save_err(err, path);
+        let file = File::create(&path).map_err(err)?;
+        let mut w = BufWriter::new(file);
+
+        let order = playback.frame_order(self.buf.len());
+
+        let (color, depth, _) = self.channels.encode(&[], &[], None);
+        let mut encoder = png::Encoder::new(&mut w, self.width, self.height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        self.tag(&mut encoder, path)?;
         encoder
             .set_animated(
-                u32::try_from(self.buf.len()).map_err(|err| {
-                    InputError::new(
-                        format!(
-                            "Error while saving image to {}",
-                            path.to_str().unwrap_or("<INVALID_PATH>")
-                        ),
-                        err.to_string(),
-                    )
-                })?,
-                0,
+                u32::try_from(order.len()).map_err(|e| err(io::Error::other(e)))?,
+                playback.loops,
             )
-            .map_err(|err| {
-                Self::err_to_input_err(&err.into(), path, "Error while saving image to")
-            })?;
-        encoder.set_frame_delay(1, fps).map_err(|err| {
-            Self::err_to_input_err(&err.into(), path, "Error while saving image to")
-        })?;
-        let mut writer = encoder.write_header().map_err(|err| {
-            Self::err_to_input_err(&err.into(), path, "Error while saving image to")
-        })?;
-
-        for frame in self.buf {
+            .map_err(|e| err(e.into()))?;
+        let mut writer = encoder.write_header().map_err(|e| err(e.into()))?;
+
+        for frame in order {
+            let (num, den) = playback.frame_delay(frame);
             writer
-                .write_image_data(frame.as_flattened())
-                .map_err(|err| {
-                    Self::err_to_input_err(&err.into(), path, "Error while saving image to")
-                })?;
+                .set_frame_delay(num, den)
+                .map_err(|e| err(e.into()))?;
+            let (_, _, data) = self
+                .channels
+                .encode(&self.buf[frame], self.frame_gray(frame), None);
+            writer.write_image_data(&data).map_err(|e| err(e.into()))?;
         }
 
-        writer
-            .finish()
-            .map_err(|err| Self::err_to_input_err(&err.into(), path, "Error while saving image to"))
+        writer.finish().map_err(|e| err(e.into()))?;
+        w.flush().map_err(err)
     }
 
     /// Saves the image as a png image to the specified path
@@ -228,79 +1407,895 @@ impl Image {
     ///
     /// # Errors
     ///
-    /// Returns an ``InputError`` when the file couldn't be created or written to, or an error
+    /// Returns an ``ImageError::Save`` when the file couldn't be created or written to, or an error
     /// occured while encoding
     ///
     /// # Panics
     ///
     /// If the image contains less than one frame
-    pub fn save_png(self, path: &mut PathBuf) -> Result<(), InputError> {
+    pub fn save_png(&self, path: &mut PathBuf) -> Result<(), ImageError> {
+        let frame = self
+            .buf
+            .first()
+            .expect("image should contain atleast one frame");
+        self.write_png(path, self.channels.encode(frame, self.frame_gray(0), None))
+    }
+
+    /// Saves the first frame of the image together with an alpha channel as a png
+    /// `alpha` holds one value per pixel
+    /// If the path does not already have the .png extension, it will be added
+    ///
+    /// # Errors
+    ///
+    /// Returns an ``ImageError::Save`` when the file couldn't be created or written to, or an error
+    /// occured while encoding
+    ///
+    /// # Panics
+    ///
+    /// If the image contains less than one frame or `alpha` has the wrong size
+    pub fn save_png_with_alpha(&self, path: &mut PathBuf, alpha: &[u8]) -> Result<(), ImageError> {
+        let frame = self
+            .buf
+            .first()
+            .expect("image should contain atleast one frame");
+        assert_eq!(frame.len(), alpha.len());
+        self.write_png(
+            path,
+            self.channels.encode(frame, self.frame_gray(0), Some(alpha)),
+        )
+    }
+
+    /// Write the color chunks of the transfer function and the text chunks of the image
+    fn tag<W: Write>(
+        &self,
+        encoder: &mut png::Encoder<'_, W>,
+        path: &Path,
+    ) -> Result<(), ImageError> {
+        self.transfer.tag(encoder);
+        for (keyword, text) in &self.text {
+            encoder
+                .add_text_chunk(keyword.clone(), text.clone())
+                .map_err(|err| save_err(err.into(), path))?;
+        }
+        Ok(())
+    }
+
+    /// Encode the samples of `encoded` as a png with the dimensions of the image, see
+    /// [`Channels::encode`]
+    fn write_png(
+        &self,
+        path: &mut PathBuf,
+        (color, depth, data): (png::ColorType, png::BitDepth, Vec<u8>),
+    ) -> Result<(), ImageError> {
         path.set_extension("png");
-        let file = File::create(&path)
-            .map_err(|err| Self::err_to_input_err(&err, path, "Error while saving image to"))?;
-        let w = &mut BufWriter::new(file);
-
-        let mut encoder = png::Encoder::new(w, self.width, self.height);
-        encoder.set_color(png::ColorType::Rgb);
-        encoder.set_depth(png::BitDepth::Eight);
-        encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
-        let source_chromaticities = png::SourceChromaticities::new(
-            (0.31270, 0.32900),
-            (0.64000, 0.33000),
-            (0.30000, 0.60000),
-            (0.15000, 0.06000),
-        );
-        encoder.set_source_chromaticities(source_chromaticities);
-        let mut writer = encoder.write_header().map_err(|err| {
-            Self::err_to_input_err(&err.into(), path, "Error while saving image to")
-        })?;
-
-        writer
-            .write_image_data(
-                self.buf
-                    .first()
-                    .expect("image should contain atleast one frame")
-                    .as_flattened(),
-            )
-            .map_err(|err| {
-                Self::err_to_input_err(&err.into(), path, "Error while saving image to")
-            })?;
+        let err = |err| save_err(err, path);
+        let file = File::create(&path).map_err(err)?;
+        let mut w = BufWriter::new(file);
 
-        writer
-            .finish()
-            .map_err(|err| Self::err_to_input_err(&err.into(), path, "Error while saving image to"))
+        let mut encoder = png::Encoder::new(&mut w, self.width, self.height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        self.tag(&mut encoder, path)?;
+        let mut writer = encoder.write_header().map_err(|e| err(e.into()))?;
+
+        writer.write_image_data(&data).map_err(|e| err(e.into()))?;
+
+        writer.finish().map_err(|e| err(e.into()))?;
+        // errors while writing the buffered end of the file would be lost when it is dropped
+        w.flush().map_err(err)
     }
 
     /// Saves the image as a ppm image to the specified path
-    /// If the path does not already have the .ppm extension, it will be added
+    /// The extension of the path is set to .ppm, gray images are written as pgm data with the
+    /// .pgm extension instead, with a maximum value of 65535 for 16 bit
     ///
     /// # Errors
     ///
-    /// Returns an ``InputError`` when the file couldn't be created or written to, or an error
+    /// Returns an ``ImageError::Save`` when the file couldn't be created or written to, or an error
     /// occured while encoding
     ///
     /// # Panics
     ///
     /// If the image contains less than one frame
-    pub fn save_ppm(self, path: &mut PathBuf) -> Result<(), InputError> {
-        path.set_extension("ppm");
-        let file = File::create(&path)
-            .map_err(|err| Self::err_to_input_err(&err, path, "Error while saving image to"))?;
+    pub fn save_ppm(&self, path: &mut PathBuf) -> Result<(), ImageError> {
+        let frame = self
+            .buf
+            .first()
+            .expect("image should contain atleast one frame");
+        let (color, depth, data) = self.channels.encode(frame, self.frame_gray(0), None);
+        let (magic, extension) = if color == png::ColorType::Grayscale {
+            ("P5", "pgm")
+        } else {
+            ("P6", "ppm")
+        };
+        path.set_extension(extension);
+        let err = |err| save_err(err, path);
+        let file = File::create(&path).map_err(err)?;
         let mut w = BufWriter::new(file);
 
-        w.write_all(format!("P6 {} {} 255\n", self.width, self.height).as_bytes())
-            .map_err(|err| Self::err_to_input_err(&err, path, "Error while saving image to"))?;
+        let max = if depth == png::BitDepth::Sixteen {
+            65535
+        } else {
+            255
+        };
+        w.write_all(format!("{magic} {} {} {max}\n", self.width, self.height).as_bytes())
+            .map_err(err)?;
+        w.write_all(&data).map_err(err)?;
 
-        for pixel in self
-            .buf
-            .first()
-            .expect("image should contain atleast one frame")
-            .as_slice()
+        w.flush().map_err(err)
+    }
+}
+
+/// Wrap an error while reading the image at `path`
+fn load_err(source: io::Error, path: &Path) -> ImageError {
+    ImageError::Load {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+/// Wrap an error while writing the image at `path`
+fn save_err(source: io::Error, path: &Path) -> ImageError {
+    ImageError::Save {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_of_gradient_is_uniform() {
+        let gradient = (0..=255).map(|i| [i, 255 - i, i]).collect();
+        let img = Image::from_pixels(256, 1, gradient);
+        for channel in [
+            HistogramChannel::R,
+            HistogramChannel::G,
+            HistogramChannel::B,
+        ] {
+            assert_eq!(img.compute_histogram(0, channel), [1; 256]);
+        }
+        let gray = Image::from_pixels(256, 1, (0..=255).map(|i| [i; 3]).collect());
+        assert_eq!(
+            gray.compute_histogram(0, HistogramChannel::Luminance),
+            [1; 256]
+        );
+
+        assert!(gray.percentile_brightness(0, 0.).abs() < 1e-6);
+        assert!((gray.percentile_brightness(0, 1.) - 1.).abs() < 1e-6);
+        assert!((gray.percentile_brightness(0, 0.5) - 128. / 255.).abs() < 1e-6);
+        assert!((gray.percentile_brightness(0, 2.) - 1.).abs() < 1e-6);
+    }
+
+    /// decode an apng and return the animation control chunk
+    fn read_animation_control(path: &Path) -> png::AnimationControl {
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let reader = decoder.read_info().unwrap();
+        reader.info().animation_control.unwrap()
+    }
+
+    #[test]
+    fn save_apng_with_loops_and_pingpong() {
+        let dir = std::env::temp_dir().join("rt_apng_test");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let img = Image::new(2, 2, 5);
+        let mut playback = Playback::new(10);
+        playback.loops = 3;
+        let mut path = dir.join("forward.png");
+        img.clone().save_apng(&mut path, &playback).unwrap();
+
+        let actl = read_animation_control(&path);
+        assert_eq!(actl.num_frames, 5);
+        assert_eq!(actl.num_plays, 3);
+
+        playback.pingpong = true;
+        playback.delays.push((0, 500));
+        let mut path = dir.join("pingpong.png");
+        img.save_apng(&mut path, &playback).unwrap();
+
+        let actl = read_animation_control(&path);
+        assert_eq!(actl.num_frames, 8);
+        assert_eq!(actl.num_plays, 3);
+    }
+
+    #[test]
+    fn equirectangular_thumbnail_keeps_aspect_ratio() {
+        let img = Image::new(64, 32, 2);
+        let thumb = img.generate_equirectangular_thumbnail(1, 16, 16);
+        assert_eq!(thumb.dimensions(), (16, 8));
+        assert_eq!(thumb.buf.len(), 1);
+
+        // area averaging of a black and white half
+        let pixels = (0..64 * 32)
+            .map(|i| if i % 64 < 32 { [0; 3] } else { [255; 3] })
+            .collect();
+        let img = Image::from_pixels(64, 32, pixels);
+        let thumb = img.generate_equirectangular_thumbnail(0, 2, 100);
+        assert_eq!(thumb.dimensions(), (2, 1));
+        assert_eq!(thumb.buf[0], [[0; 3], [255; 3]]);
+    }
+
+    #[test]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn cubemap_faces_cover_equirectangular_image() {
+        use std::f32::consts::PI;
+        let (width, height) = (64u32, 32u32);
+        // direction of the center of a source pixel
+        let direction = |x: u32, y: u32| {
+            let phi = ((x as f32 + 0.5) / width as f32 - 0.5) * 2. * PI;
+            let theta = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+            Vec3::new(
+                theta.cos() * phi.sin(),
+                theta.sin(),
+                theta.cos() * phi.cos(),
+            )
+        };
+        // encode the direction as the color, which is smooth over the whole sphere
+        let encode = |d: Vec3| [0, 1, 2].map(|i| ((d[i] + 1.) * 127.5) as u8);
+        let pixels = (0..width * height)
+            .map(|i| encode(direction(i % width, i / width)))
+            .collect();
+        let faces = Image::from_pixels(width, height, pixels).to_cubemap_faces();
+        let size = faces[0].dimensions().0;
+        assert_eq!(size, width / 4);
+
+        // every source pixel can be found on the face its direction points at
+        for (x, y) in (0..width).flat_map(|x| (0..height).map(move |y| (x, y))) {
+            let dir = direction(x, y);
+            let major = dir[0].abs().max(dir[1].abs()).max(dir[2].abs());
+            let face = (0..6)
+                .find(|&face| {
+                    let axis = face / 2;
+                    let sign = if face % 2 == 0 { 1. } else { -1. };
+                    (dir[axis] * sign - major).abs() < f32::EPSILON
+                })
+                .unwrap();
+            // invert `cube_face_direction` by searching the face pixel closest to the direction
+            let closest = (0..size * size)
+                .min_by(|&a, &b| {
+                    let distance = |i: u32| {
+                        let s = 2. * ((i % size) as f32 + 0.5) / size as f32 - 1.;
+                        let t = 2. * ((i / size) as f32 + 0.5) / size as f32 - 1.;
+                        (Vec3::normal(&cube_face_direction(face, s, t)) - dir).length()
+                    };
+                    distance(a).total_cmp(&distance(b))
+                })
+                .unwrap();
+            let found = faces[face].buf[0][closest as usize];
+            let expected = encode(dir);
+            for c in 0..3 {
+                assert!(
+                    found[c].abs_diff(expected[c]) <= 16,
+                    "{found:?} {expected:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn exposure_brackets_scale_brightness() {
+        // dark enough that the brightest bracket does not clip
+        let gradient: Vec<_> = (0..64u8)
+            .map(|i| {
+                let c = f32::from(i) / 256.;
+                Color::new(c, c, c)
+            })
+            .collect();
+        let bracket = |ev| {
+            Image::from_colors(8, 8, &gradient, ev, TransferFunction::Linear).buf[0]
+                .iter()
+                .map(|p| f32::from(p[0]))
+                .sum::<f32>()
+        };
+        let (dark, normal, bright) = (bracket(-1.), bracket(0.), bracket(1.));
+        assert!((normal / dark - 2.).abs() < 0.05, "{normal} / {dark}");
+        assert!((bright / normal - 2.).abs() < 0.05, "{bright} / {normal}");
+    }
+
+    #[test]
+    fn text_chunks_are_saved() {
+        let dir = std::env::temp_dir().join("rt_text_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut img = Image::new(2, 2, 1);
+        img.add_text("Render settings", "samples: 4");
+        let mut path = dir.join("text.png");
+        img.save_png(&mut path).unwrap();
+
+        let reader = png::Decoder::new(File::open(&path).unwrap())
+            .read_info()
+            .unwrap();
+        let text = &reader.info().uncompressed_latin1_text;
+        assert_eq!(text.len(), 1);
+        assert_eq!(text[0].keyword, "Render settings");
+        assert_eq!(text[0].text, "samples: 4");
+    }
+
+    #[test]
+    fn draw_text_sets_the_pixels_of_the_glyphs() {
+        let white = [255; 3];
+        let mut img = Image::new(20, 10, 1);
+        img.draw_text(0, 1, 2, "Hi", white, 1);
+        let lit = |img: &Image, x: u32, y: u32| img.buf[0][(x + y * img.width) as usize] == white;
+        // H has both vertical strokes and the bar in its middle row
+        assert!(lit(&img, 1, 2) && lit(&img, 5, 2) && !lit(&img, 2, 2));
+        assert!((1..=5).all(|x| lit(&img, x, 5)));
+        // lowercase letters use the uppercase glyphs, one pixel of space between characters
+        assert!(!lit(&img, 6, 2) && lit(&img, 8, 2) && lit(&img, 10, 2));
+        assert_eq!(text_width("Hi", 1), 11);
+        assert_eq!(text_width("Hi", 2), 22);
+        assert_eq!(glyph('~'), glyph('?'));
+
+        // scaled text, cut off at the border of the image
+        let mut img = Image::new(8, 8, 1);
+        img.draw_text(0, 0, 0, "H", white, 2);
+        assert!(lit(&img, 0, 0) && lit(&img, 1, 1) && !lit(&img, 2, 0));
+        assert!(lit(&img, 7, 7) && !lit(&img, 7, 0));
+    }
+
+    #[test]
+    fn fill_rect_blends_every_frame() {
+        let mut img = Image::new(4, 3, 2);
+        img.buf[1] = vec![[200, 100, 0]; 12];
+        img.fill_rect(1, 1, 10, 1, [255, 255, 255], 0.5);
+        // the first column and the rows above and below are left alone
+        for frame in 0..2 {
+            assert_eq!(img.buf[frame][4], img.buf[frame][0]);
+            assert_eq!(img.buf[frame][8 + 1], img.buf[frame][1]);
+        }
+        // the rectangle is cut off at the right border
+        assert_eq!(img.buf[0][4 + 1..4 + 4], [[128; 3]; 3]);
+        assert_eq!(img.buf[1][4 + 3], [228, 178, 128]);
+
+        let before = img.clone();
+        img.fill_rect(5, 0, 2, 2, [0; 3], 1.);
+        img.fill_rect(0, 0, 2, 2, [0; 3], 0.);
+        assert_eq!(img, before);
+        img.fill_rect(0, 0, 1, 1, [9; 3], 2.);
+        assert_eq!(img.buf[0][0], [9; 3]);
+    }
+
+    #[test]
+    fn contact_sheet_arranges_thumbnails_in_a_grid() {
+        let red = Image::from_pixels(16, 8, vec![[255, 0, 0]; 16 * 8]);
+        let cells: Vec<_> = (0..5)
+            .map(|i| (format!("scene_{i}.xml"), (i != 3).then(|| red.clone())))
+            .collect();
+        let sheet = Image::contact_sheet(&cells, 16);
+        // 5 thumbnails fill two rows of three cells, each with padding and a label
+        let (cell_width, cell_height) = (16 + 2 * 2, 16 + 7 + 3 * 2);
+        assert_eq!(sheet.dimensions(), (3 * cell_width, 2 * cell_height));
+        let pixel = |x: u32, y: u32| sheet.buf[0][(x + y * sheet.width) as usize];
+        // thumbnails are centered in their cell
+        assert_eq!(pixel(2, 2 + 4), [255, 0, 0]);
+        assert_eq!(pixel(2, 2 + 3), [32; 3]);
+        assert_eq!(pixel(cell_width + 17, cell_height + 2 + 11), [255, 0, 0]);
+        // the fourth cell has no image
+        assert!((0..16).all(|y| pixel(2 + 15, cell_height + 2 + y) != [255, 0, 0]));
+        // labels are drawn below the thumbnails, cut off to the width of a thumbnail
+        let label = (16 + 2 * 2..cell_height).flat_map(|y| (0..cell_width).map(move |x| (x, y)));
+        assert!(label.clone().any(|(x, y)| pixel(x, y) == [220; 3]));
+        assert!(label
+            .clone()
+            .all(|(x, y)| x < 2 + 16 || pixel(x, y) != [220; 3]));
+    }
+
+    #[test]
+    fn uv_grid_has_a_line_at_every_cell_border() {
+        let grid = Image::uv_grid(64, 64, 4);
+        let line = Color::new(0.1, 0.1, 0.1).to_rgb();
+        let px = |x: u32, y: u32| grid.buf[0][(x + 64 * y) as usize];
+        // cells are 16 pixels wide, the lines cover the two pixels around every border
+        for x in [0, 15, 16, 31, 32, 47, 48, 63] {
+            assert_eq!(px(x, 8), line, "column {x}");
+            assert_eq!(px(8, x), line, "row {x}");
+        }
+        for x in [1, 14, 17, 30] {
+            assert_ne!(px(x, 8), line, "column {x}");
+        }
+
+        // every cell has its own color, which fills the cell
+        let mut colors = Vec::new();
+        for row in 0..4 {
+            for column in 0..4 {
+                let (x, y) = (16 * column + 8, 16 * row + 8);
+                assert_eq!(px(x - 5, y + 5), px(x, y));
+                colors.push(px(x, y));
+            }
+        }
+        colors.sort_unstable();
+        colors.dedup();
+        assert_eq!(colors.len(), 16);
+        assert!(!colors.contains(&line));
+
+        // texel lookups give the colors of the grid
+        let color = uv_grid_color(0.6, 0.3, 4, UV_GRID_LINE_WIDTH);
+        assert_eq!(grid.get_pixel(0, 0.6, 0.3), color.to_rgb());
+    }
+
+    #[test]
+    fn missing_texture_is_a_magenta_checker() {
+        let checker = Image::missing_texture(64, 8);
+        let px = |x: u32, y: u32| checker.buf[0][(x + 64 * y) as usize];
+        let (magenta, black) = ([255, 0, 255], [0, 0, 0]);
+        assert_eq!((px(0, 0), px(7, 7)), (magenta, magenta));
+        assert_eq!((px(8, 0), px(0, 8)), (black, black));
+        assert_eq!(px(8, 8), magenta);
+        assert_eq!(px(63, 63), magenta);
+    }
+
+    /// decode the png at `path` and return its color type, bit depth and samples
+    fn decode_raw(path: &Path) -> (png::ColorType, png::BitDepth, Vec<u8>) {
+        let mut reader = png::Decoder::new(File::open(path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(info.buffer_size());
+        (info.color_type, info.bit_depth, buf)
+    }
+
+    #[test]
+    fn saved_channels_round_trip() {
+        let dir = std::env::temp_dir().join("rt_channels_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut img = Image::from_pixels(2, 1, vec![[255, 0, 0], [10, 20, 30]]);
+
+        let mut path = dir.join("rgb.png");
+        img.save_png(&mut path).unwrap();
+        let (color, _, data) = decode_raw(&path);
+        assert_eq!(color, png::ColorType::Rgb);
+        assert_eq!(data, [255, 0, 0, 10, 20, 30]);
+
+        img.set_channels(Channels::Bgr);
+        let mut path = dir.join("bgr.png");
+        img.save_png(&mut path).unwrap();
+        assert_eq!(decode_raw(&path).2, [0, 0, 255, 30, 20, 10]);
+
+        // pure red has a luminance of 0.2126
+        img.set_channels(Channels::Gray);
+        let mut path = dir.join("gray.png");
+        img.save_png(&mut path).unwrap();
+        let (color, depth, data) = decode_raw(&path);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::Grayscale, png::BitDepth::Eight)
+        );
+        assert_eq!(data, [54, 19]);
+
+        img.set_channels(Channels::Gray16);
+        let mut path = dir.join("gray16.png");
+        img.save_png_with_alpha(&mut path, &[255, 0]).unwrap();
+        let (color, depth, data) = decode_raw(&path);
+        assert_eq!(
+            (color, depth),
+            (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen)
+        );
+        let red = u16::from_be_bytes([data[0], data[1]]);
+        // 0.2126 * 65535
+        assert_eq!(red, 13933);
+        assert_eq!(data[2..4], [255, 255]);
+        assert_eq!(data[6..8], [0, 0]);
+
+        img.set_channels(Channels::Gray);
+        let mut path = dir.join("gray.ppm");
+        img.save_ppm(&mut path).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"P5 2 1 255\n\x36\x13");
+    }
+
+    #[test]
+    fn gray_ppm_is_saved_as_pgm() {
+        let dir = std::env::temp_dir().join("rt_pgm_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut img = Image::from_pixels(1, 1, vec![[255, 255, 255]]);
+        for (channels, name, header) in [
+            (Channels::Rgb, "rgb.ppm", "P6 1 1 255\n"),
+            (Channels::Gray, "gray.pgm", "P5 1 1 255\n"),
+            (Channels::Gray16, "gray16.pgm", "P5 1 1 65535\n"),
+        ] {
+            img.set_channels(channels);
+            let mut path = dir.join(name).with_extension("ppm");
+            img.save_ppm(&mut path).unwrap();
+            assert_eq!(path, dir.join(name));
+            assert!(std::fs::read(&path).unwrap().starts_with(header.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn gray16_keeps_the_luminance_before_quantizing() {
+        let dir = std::env::temp_dir().join("rt_gray16_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let gray16 = |img: &Image, name: &str| {
+            let mut path = dir.join(name);
+            img.save_png(&mut path).unwrap();
+            let data = decode_raw(&path).2;
+            data.chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>()
+        };
+        let colors = [Color::new(0.3, 0.3, 0.3), Color::new(0., 0.5, 0.)];
+        // 0.3 * 65535 and 0.7152 * 0.5 * 65535, the 8 bit pixels would give 76 * 257 and
+        // luminance([0, 127, 0]) * 257
+        let expected = [19661, 23435];
+
+        let mut img = Image::new(2, 1, 1);
+        img.set_transfer_function(TransferFunction::Linear);
+        img.set_channels(Channels::Gray16);
+        img.par_init_colors(0, |(x, _)| colors[*x as usize]);
+        assert_eq!(img.buf[0], [[76; 3], [0, 127, 0]]);
+        assert_eq!(gray16(&img, "colors.png"), expected);
+
+        let mut bracket = Image::from_colors(2, 1, &colors, 0., TransferFunction::Linear);
+        bracket.set_channels(Channels::Gray16);
+        assert_eq!(gray16(&bracket, "bracket.png"), expected);
+
+        // drawing over the pixels replaces their luminance
+        img.fill_rect(1, 0, 1, 1, [255; 3], 1.);
+        assert_eq!(gray16(&img, "filled.png"), [19661, 65535]);
+        // pixels set from 8 bit values fall back to them
+        img.par_init_pixels(0, |_| [76; 3]);
+        assert_eq!(gray16(&img, "pixels.png"), [76 * 257; 2]);
+    }
+
+    /// save a png with the given transfer function and return the gamma, whether it has
+    /// chromaticities and its srgb rendering intent
+    fn saved_color_chunks(
+        transfer: TransferFunction,
+    ) -> (Option<u32>, bool, Option<png::SrgbRenderingIntent>) {
+        let dir = std::env::temp_dir().join("rt_transfer_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut img = Image::new(2, 2, 1);
+        img.set_transfer_function(transfer);
+        let mut path = dir.join(format!("{transfer:?}.png"));
+        img.save_png(&mut path).unwrap();
+
+        let reader = png::Decoder::new(File::open(&path).unwrap())
+            .read_info()
+            .unwrap();
+        let info = reader.info();
+        (
+            info.source_gamma.map(png::ScaledFloat::into_scaled),
+            info.source_chromaticities.is_some(),
+            info.srgb,
+        )
+    }
+
+    #[test]
+    fn png_color_chunks_match_transfer_function() {
+        assert_eq!(
+            saved_color_chunks(TransferFunction::Display),
+            (Some(45455), true, None)
+        );
+        assert_eq!(
+            saved_color_chunks(TransferFunction::Srgb),
+            (
+                Some(45455),
+                true,
+                Some(png::SrgbRenderingIntent::Perceptual)
+            )
+        );
+        assert_eq!(
+            saved_color_chunks(TransferFunction::Linear),
+            (Some(100_000), true, None)
+        );
+        assert_eq!(
+            saved_color_chunks(TransferFunction::Gamma(2.)),
+            (Some(50000), true, None)
+        );
+        assert_eq!(
+            saved_color_chunks(TransferFunction::Untagged),
+            (None, false, None)
+        );
+    }
+
+    #[test]
+    fn transfer_function_encoding() {
+        let grey = Color::new(0.5, 0.5, 0.5);
+        assert_eq!(TransferFunction::Display.encode(grey), grey);
+        assert_eq!(TransferFunction::Linear.encode(grey), grey);
+        assert!((TransferFunction::Srgb.encode(grey)[0] - 0.735_357).abs() < 1e-5);
+        assert!((TransferFunction::Gamma(2.).encode(grey)[0] - 0.5_f32.sqrt()).abs() < 1e-6);
+        // the linear segment of the srgb curve and the end points
+        let dark = TransferFunction::Srgb.encode(Color::new(0.001, 0., 1.));
+        assert!((dark[0] - 0.01292).abs() < 1e-6);
+        assert!(dark[1].abs() < 1e-6 && (dark[2] - 1.).abs() < 1e-6);
+    }
+
+    /// two frame image with a single pixel
+    fn two_frames(first: Rgb, second: Rgb) -> Image {
+        let mut img = Image::new(1, 1, 2);
+        img.buf[0][0] = first;
+        img.buf[1][0] = second;
+        img
+    }
+
+    #[test]
+    fn average_frames_box() {
+        let img = two_frames([0, 10, 255], [1, 20, 0]);
+        let avg = img.average_frames(&ShutterProfile::Box, 0..2);
+
+        // 0.5 and 127.5 round up instead of being truncated
+        assert_eq!(avg.buf, vec![vec![[1, 15, 128]]]);
+        // the original frames are kept
+        assert_eq!(img, two_frames([0, 10, 255], [1, 20, 0]));
+    }
+
+    #[test]
+    fn average_frames_triangle() {
+        let mut img = Image::new(1, 1, 3);
+        img.buf[0][0] = [0, 0, 0];
+        img.buf[1][0] = [100, 200, 40];
+        img.buf[2][0] = [100, 0, 0];
+        let avg = img.average_frames(&ShutterProfile::Triangle, 0..3);
+
+        // weights 1, 2, 1
+        assert_eq!(avg.buf, vec![vec![[75, 100, 20]]]);
+        assert_eq!(ShutterProfile::Triangle.weights(4), [1., 2., 2., 1.]);
+        // with two frames triangle and box are equal
+        let img = two_frames([0, 10, 255], [1, 20, 0]);
+        assert_eq!(
+            img.average_frames(&ShutterProfile::Triangle, 0..2),
+            img.average_frames(&ShutterProfile::Box, 0..2)
+        );
+    }
+
+    #[test]
+    fn average_frames_custom_and_range() {
+        let img = two_frames([0, 10, 200], [100, 20, 0]);
+        let avg = img.average_frames(&ShutterProfile::Custom(vec![3., 1.]), 0..2);
+        assert_eq!(avg.buf, vec![vec![[25, 13, 150]]]);
+
+        // a single frame range returns that frame
+        let second = img.average_frames(&ShutterProfile::Box, 1..2);
+        assert_eq!(second.buf, vec![vec![[100, 20, 0]]]);
+    }
+
+    #[test]
+    fn decode_sixteen_bit_png() {
+        let samples: [u16; 6] = [0, 257, 65535, 0x1234, 32767, 65534];
+        let mut data = Vec::new();
         {
-            w.write_all(pixel)
-                .map_err(|err| Self::err_to_input_err(&err, path, "Error while saving image to"))?;
+            let mut encoder = png::Encoder::new(&mut data, 2, 1);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Sixteen);
+            let mut writer = encoder.write_header().unwrap();
+            let bytes: Vec<_> = samples.iter().flat_map(|s| s.to_be_bytes()).collect();
+            writer.write_image_data(&bytes).unwrap();
         }
 
-        Ok(())
+        let img = Image::decode_png(data.as_slice()).unwrap();
+
+        assert_eq!(img.dimensions(), (2, 1));
+        assert_eq!(img.buf[0], vec![[0, 1, 255], [18, 127, 254]]);
+    }
+
+    #[test]
+    fn get_pixel_out_of_bounds_returns_border() {
+        let mut img = Image {
+            width: 2,
+            height: 2,
+            buf: vec![vec![[255; 3]; 4]],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        };
+
+        assert_eq!(img.get_pixel(0, 1.001, 0.5), [0, 0, 0]);
+        assert_eq!(img.get_pixel(0, 0.5, -0.1), [0, 0, 0]);
+        assert_eq!(img.get_pixel(0, 1., 1.), [255, 255, 255]);
+
+        img.set_border_color([1, 2, 3]);
+        assert_eq!(img.get_pixel(0, 1.001, 0.5), [1, 2, 3]);
+    }
+
+    #[test]
+    fn mipmap_of_checkerboard_fades_to_grey() {
+        let size = 16;
+        let buf = (0..size * size)
+            .map(|i| {
+                if (i % size + i / size) % 2 == 0 {
+                    [255; 3]
+                } else {
+                    [0; 3]
+                }
+            })
+            .collect();
+        let img = Image {
+            width: size,
+            height: size,
+            buf: vec![buf],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        };
+        let mipmap = MipMap::new(img);
+
+        assert_eq!(mipmap.level_count(), 5);
+        assert_eq!(mipmap.sample(0.01, 0.01, 0.), Color::from([255; 3]));
+
+        let far = mipmap.sample(0.01, 0.01, 1.).to_rgb();
+        assert!(far.iter().all(|c| (127..=128).contains(c)));
+
+        // between two levels the colors are blended
+        let blended = mipmap.sample(0.01, 0.01, 1.5 / 16.).to_rgb();
+        assert!(blended[0] > 128 && blended[0] < 255);
+    }
+
+    #[test]
+    fn resize_integer_ratio() {
+        let img = Image {
+            width: 4,
+            height: 2,
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+            buf: vec![vec![
+                [0, 0, 0],
+                [100, 100, 100],
+                [10, 20, 30],
+                [10, 20, 30],
+                [100, 100, 100],
+                [200, 200, 200],
+                [10, 20, 30],
+                [10, 20, 30],
+            ]],
+        };
+
+        let resized = img.resized(2, 1);
+        assert_eq!(resized.dimensions(), (2, 1));
+        assert_eq!(resized.buf[0], vec![[100, 100, 100], [10, 20, 30]]);
+    }
+
+    #[test]
+    fn resize_non_integer_ratio() {
+        let img = Image {
+            width: 3,
+            height: 1,
+            buf: vec![vec![[0, 0, 0], [90, 90, 90], [180, 180, 180]]],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        };
+
+        let resized = img.resized(2, 1);
+        assert_eq!(resized.buf[0], vec![[30, 30, 30], [150, 150, 150]]);
+    }
+
+    #[test]
+    fn resize_single_pixel_images() {
+        let single = Image {
+            width: 1,
+            height: 1,
+            buf: vec![vec![[7, 8, 9]]],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        };
+        let up = single.resized(3, 2);
+        assert_eq!(up.buf[0], vec![[7, 8, 9]; 6]);
+
+        let img = Image {
+            width: 3,
+            height: 1,
+            buf: vec![vec![[0, 0, 0], [90, 90, 90], [180, 180, 180]]],
+            border: [0; 3],
+            transfer: TransferFunction::Display,
+            text: Vec::new(),
+            channels: Channels::Rgb,
+            gray: Vec::new(),
+        };
+        assert_eq!(img.resized(1, 1).buf[0], vec![[90, 90, 90]]);
+    }
+
+    #[test]
+    fn limit_size_preserves_aspect() {
+        let img = Image::new(400, 100, 1);
+        assert_eq!(img.clone().limit_size(1000).dimensions(), (400, 100));
+        assert_eq!(img.limit_size(200).dimensions(), (200, 50));
+    }
+
+    #[test]
+    fn playback_frame_order_and_delays() {
+        let mut playback = Playback::new(24);
+        assert_eq!(playback.frame_order(4), vec![0, 1, 2, 3]);
+
+        playback.pingpong = true;
+        assert_eq!(playback.frame_order(4), vec![0, 1, 2, 3, 2, 1]);
+        assert_eq!(playback.frame_order(2), vec![0, 1]);
+
+        playback.delays.push((3, 750));
+        assert_eq!(playback.frame_delay(3), (750, 1000));
+        assert_eq!(playback.frame_delay(1), (1, 24));
+    }
+
+    #[test]
+    fn flo_file_stores_vectors_as_floats() {
+        let path = std::env::temp_dir().join(format!("rt_flow_{}.flo", std::process::id()));
+        save_flo(&path, 2, 1, &[(1.5, -2.), (0., 0.25)]).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes.len(), 12 + 2 * 8);
+        assert_eq!(&bytes[0..4], 202_021.25f32.to_le_bytes().as_slice());
+        assert_eq!(u32::from_le_bytes(bytes[4..8].try_into().unwrap()), 2);
+        assert_eq!(u32::from_le_bytes(bytes[8..12].try_into().unwrap()), 1);
+        let floats: Vec<_> = [1.5f32, -2., 0., 0.25]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        assert_eq!(&bytes[12..], floats.as_slice());
+    }
+
+    #[test]
+    fn failed_save_is_rescued_into_temp_dir() {
+        let id = std::process::id();
+        // a read-only directory doesn't stop root, a file in place of the directory fails for
+        // everyone
+        let blocker = env::temp_dir().join(format!("rt_rescue_blocker_{id}"));
+        std::fs::write(&blocker, b"not a directory").unwrap();
+        let mut path = blocker.join(format!("rt_rescue_{id}.png"));
+        let img = Image::from_pixels(2, 1, vec![[255, 0, 0], [0, 0, 255]]);
+
+        let err = save_with_rescue(&mut path, |p| img.save_png(p))
+            .unwrap()
+            .expect("the save should have failed");
+        assert!(matches!(err, ImageError::Save { .. }), "{err}");
+        assert!(!err.is_transient());
+        assert_eq!(
+            path,
+            env::temp_dir().join(format!("rt_rescue_{id}_{id}.png"))
+        );
+        let rescued = Image::load_png(&path).unwrap();
+        assert_eq!(rescued.buf, img.buf);
+
+        // a second rescue of the same file doesn't overwrite the first one
+        let mut second = blocker.join(format!("rt_rescue_{id}.png"));
+        save_with_rescue(&mut second, |p| img.save_png(p))
+            .unwrap()
+            .expect("the save should have failed");
+        assert_eq!(
+            second,
+            env::temp_dir().join(format!("rt_rescue_{id}_{id}_1.png"))
+        );
+        assert!(Image::load_png(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&second).unwrap();
+        std::fs::remove_file(&blocker).unwrap();
+
+        // transient errors are retried where the file should go
+        let mut path = env::temp_dir().join(format!("rt_retry_{id}.png"));
+        let mut attempts = 0;
+        let rescue = save_with_rescue(&mut path, |p| {
+            attempts += 1;
+            if attempts == 1 {
+                return Err(save_err(ErrorKind::Interrupted.into(), p));
+            }
+            img.save_png(p)
+        })
+        .unwrap();
+        assert!(rescue.is_none());
+        assert_eq!(attempts, 2);
+        assert!(Image::load_png(&path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+
+        let missing = env::temp_dir().join(format!("rt_missing_{id}.png"));
+        let err = InputError::from(Image::load_png(&missing).unwrap_err());
+        assert!(err.title().starts_with("Error while loading image"));
     }
 }
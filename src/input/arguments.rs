@@ -1,6 +1,19 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use super::InputError;
+use crate::{image::Channels, objects::Material};
+
+use super::{
+    config_file::{self, ConfigValue},
+    env_vars::expand_env_vars,
+    paths,
+    template::check_template,
+    InputError, MissingTextures,
+};
 
 #[derive(Debug, Clone)]
 enum OptAction {
@@ -19,8 +32,22 @@ struct CliOption {
     action: OptAction,
 }
 
+/// Extra outputs that can be saved next to the image with `--aov`
+/// Besides the motion vectors, these are the components of [`crate::objects::RadianceSplit`]
+const AOVS: [&str; 6] = [
+    "motion",
+    "diffuse",
+    "specular",
+    "reflection",
+    "refraction",
+    "emission",
+];
+
+/// Debug renders that can be turned on with `--debug`
+const DEBUG_MODES: [&str; 2] = ["lights", "time"];
+
 /// All cli options that should be parsed
-const OPTIONS: [CliOption; 6] = [
+const OPTIONS: [CliOption; 49] = [
     CliOption {
         long: "ppm",
         description: "Export the image as a ppm",
@@ -39,6 +66,355 @@ const OPTIONS: [CliOption; 6] = [
         short: Some('p'),
         action: OptAction::Toggle,
     },
+    CliOption {
+        long: "strict",
+        description: "Treat warnings as errors",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "strict-schema",
+        description: "Reject scene files with unknown elements or attributes",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "no-env-expansion",
+        description: "Keep ${VAR} in file names and the output directory instead of expanding it",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "no-color",
+        description: "Print errors and warnings without colors",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "info",
+        description: "Print a summary of the scene before rendering",
+        short: Some('i'),
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "verbose",
+        description: "Print the effective render settings before rendering",
+        short: Some('v'),
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "auto-frame",
+        description: "Move the camera back or forward until the whole scene is visible",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "merge-static",
+        description: "Merge static meshes with the same material into one mesh",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "detect-nan",
+        description: "Report pixels with NaN or infinite color values",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "highlight-nan",
+        description: "Like --detect-nan, but also color these pixels magenta",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "max-texture-size",
+        description: "Downscale textures larger than N pixels in width or height",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "max-texture-pixels",
+        description: "Refuse to load textures with more than N pixels",
+        short: None,
+        action: OptAction::Set {
+            default: "268435456",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "missing-textures",
+        description: "What happens when a texture file can't be loaded: MODE error fails to load \
+                      the scene, placeholder renders a magenta and black checker instead",
+        short: None,
+        action: OptAction::Set {
+            default: "error",
+            placeholder: "<MODE>",
+        },
+    },
+    CliOption {
+        long: "max-triangles",
+        description: "Refuse to load scenes whose meshes have more than N triangles in total",
+        short: None,
+        action: OptAction::Set {
+            default: "50000000",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "decimate-to",
+        description: "Reduce meshes with more than N triangles to about N triangles for previews",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "max-seconds",
+        description:
+            "Stop rendering new pixels after N seconds, the rest is filled with the background",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "max-samples-total",
+        description: "Stop rendering new pixels after N camera samples",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "samples",
+        description: "Override the super-sampling of the scene with N samples per pixel",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "max-feature-rays",
+        description: "Limit the rays of glossy reflections and soft shadows to N per camera \
+                      sample. Their samples multiply along a path, and a pixel spends up to its \
+                      samples times N",
+        short: None,
+        action: OptAction::Set {
+            default: "256",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "pass-samples",
+        description: "Render every frame with N samples per pixel first and refine all frames \
+                      in further passes of N samples, saving the image after each pass",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "max-resident-frames",
+        description: "Refine at most N frames at once with --pass-samples, longer animations \
+                      are refined in groups of N frames",
+        short: None,
+        action: OptAction::Set {
+            default: "64",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "resolution",
+        description: "Override the resolution of the scene",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<WxH>",
+        },
+    },
+    CliOption {
+        long: "scale",
+        description: "Scale the resolution by F for quick tests, keeping the framing of the \
+                      camera",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<F>",
+        },
+    },
+    CliOption {
+        long: "max-bounces",
+        description: "Override the maximum number of bounces of the scene",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "channels",
+        description: "Save images with the channels CHANNELS (rgb, bgr, or the Rec. 709 \
+                      luminance as gray or gray16 with 8 or 16 bits)",
+        short: None,
+        action: OptAction::Set {
+            default: "rgb",
+            placeholder: "<CHANNELS>",
+        },
+    },
+    CliOption {
+        long: "bracket",
+        description: "Save one image per exposure value in the comma separated list EVS",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<EVS>",
+        },
+    },
+    CliOption {
+        long: "seed",
+        description: "Seed the random sampling to make renders reproducible",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "camera",
+        description: "Render the scene with the camera called NAME",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<NAME>",
+        },
+    },
+    CliOption {
+        long: "layers",
+        description: "Render one image per layer in the comma separated list LAYERS",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<LAYERS>",
+        },
+    },
+    CliOption {
+        long: "aov",
+        description: "Save the extra outputs in the comma separated list AOVS next to the image \
+                      (motion: motion vectors of animations as .flo files; diffuse, specular, \
+                      reflection, refraction, emission: components of the image)",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<AOVS>",
+        },
+    },
+    CliOption {
+        long: "debug",
+        description: "Turn on the debug renders in the comma separated list MODES (lights: show \
+                      the lights as gizmos that cast no shadows and don't show up in reflections, \
+                      time: show the time at which each pixel sees the animation in gray)",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<MODES>",
+        },
+    },
+    CliOption {
+        long: "builtin",
+        description: "Render the builtin scene NAME (cornell, spheres, caustic or julia)",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<NAME>",
+        },
+    },
+    CliOption {
+        long: "preview-material",
+        description: "Render only the material NAME from the material library of the scene",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<NAME>",
+        },
+    },
+    CliOption {
+        long: "override-material",
+        description:
+            "Replace the material of all surfaces, or of surface ID, with the builtin material NAME",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<[ID=]NAME>",
+        },
+    },
+    CliOption {
+        long: "tweak",
+        description:
+            "Multiply the material property NAME of every surface by FACTOR, can be repeated",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<NAME=FACTOR>",
+        },
+    },
+    CliOption {
+        long: "turntable",
+        description: "Render the material preview as a turntable animation",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "jobs",
+        description: "Number of scenes that are rendered at the same time",
+        short: Some('j'),
+        action: OptAction::Set {
+            default: "1",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "burn-in",
+        description: "Draw the scene, frame, date, samples and seed into a strip along the bottom \
+                      of the image, for review renders (can't be set in a config file)",
+        short: None,
+        action: OptAction::Toggle,
+    },
+    CliOption {
+        long: "contact-sheet",
+        description: "Render a thumbnail of every scene instead of the images and save them as a \
+                      labeled grid to FILE in the output directory",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<FILE>",
+        },
+    },
+    CliOption {
+        long: "thumb-size",
+        description: "Set the largest width and height of the thumbnails of a contact sheet",
+        short: None,
+        action: OptAction::Set {
+            default: "128",
+            placeholder: "<N>",
+        },
+    },
+    CliOption {
+        long: "report",
+        description: "Write a json report of all rendered scenes to FILE",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<FILE>",
+        },
+    },
     CliOption {
         long: "outdir",
         description: "Set the directory to save the image to",
@@ -48,6 +424,25 @@ const OPTIONS: [CliOption; 6] = [
             placeholder: "<DIR>",
         },
     },
+    CliOption {
+        long: "output-template",
+        description: "Save the image to the path TEMPLATE with {scene}, {frame}, {date}, \
+                      {resolution} and {seed} filled in, instead of into the output directory",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<TEMPLATE>",
+        },
+    },
+    CliOption {
+        long: "config",
+        description: "Read default values for the options from FILE instead of the default config",
+        short: None,
+        action: OptAction::Set {
+            default: "",
+            placeholder: "<FILE>",
+        },
+    },
     CliOption {
         long: "help",
         description: "Print this help message",
@@ -62,6 +457,13 @@ const OPTIONS: [CliOption; 6] = [
     },
 ];
 
+/// Options that can't be set in a config file
+/// The burn-in must never end up in final renders because of a forgotten config file
+const CLI_ONLY: [&str; 4] = ["config", "help", "version", "burn-in"];
+
+/// Options that can be given more than once, their values are joined into a comma separated list
+const REPEATABLE: [&str; 1] = ["tweak"];
+
 /// return the maximum length of long name + default value
 fn max_option_length() -> usize {
     OPTIONS
@@ -93,7 +495,8 @@ fn print_version() {
 /// print help text for the program
 fn print_help() {
     println!("{} {}\n", name(), version());
-    println!("Usage: {} [OPTIONS] FILE\n", name());
+    println!("Usage: {} [OPTIONS] FILE...", name());
+    println!("       {} [OPTIONS] --builtin <NAME> [FILE...]\n", name());
 
     let maxlen = max_option_length();
     println!("Arguments:");
@@ -104,7 +507,8 @@ fn print_help() {
             OptAction::Set {
                 default,
                 placeholder,
-            } => (format!("(default: '{default}')"), placeholder),
+            } if !default.is_empty() => (format!("(default: '{default}')"), placeholder),
+            OptAction::Set { placeholder, .. } => (String::new(), placeholder),
             OptAction::Toggle => (String::new(), ""),
         };
         let length = maxlen - opt.long.len() + 2 - placeholder.len();
@@ -122,28 +526,201 @@ fn print_help() {
     }
 }
 
+/// Return the path of the default config file, `$XDG_CONFIG_HOME/rt/config.toml` or
+/// `~/.config/rt/config.toml` if `XDG_CONFIG_HOME` is not set
+fn default_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("rt").join("config.toml"))
+}
+
 /// Struct to hold configuration for the ray tracer
-#[derive(Debug)]
+/// Every option is parsed and validated once while building the config
+#[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Config {
-    /// file containing the scene
-    input_file: String,
-    options: HashMap<&'static str, String>,
+    /// files containing the scenes
+    input_files: Vec<String>,
+    ppm: bool,
+    blur: bool,
+    progress_bar: bool,
+    strict: bool,
+    strict_schema: bool,
+    no_env_expansion: bool,
+    no_color: bool,
+    info: bool,
+    verbose: bool,
+    auto_frame: bool,
+    merge_static: bool,
+    detect_nan: bool,
+    highlight_nan: bool,
+    turntable: bool,
+    max_texture_size: Option<u32>,
+    max_texture_pixels: u64,
+    missing_textures: MissingTextures,
+    max_triangles: usize,
+    decimate_to: Option<usize>,
+    max_seconds: Option<f64>,
+    max_samples_total: Option<u64>,
+    samples: Option<u32>,
+    max_feature_rays: u32,
+    pass_samples: Option<u32>,
+    max_resident_frames: usize,
+    resolution: Option<(u32, u32)>,
+    scale: Option<f32>,
+    max_bounces: Option<u32>,
+    channels: Channels,
+    bracket: Vec<f32>,
+    seed: Option<u64>,
+    camera: Option<String>,
+    layers: Vec<String>,
+    aovs: Vec<String>,
+    debug: Vec<String>,
+    builtin: Option<String>,
+    preview_material: Option<String>,
+    override_material: Option<(Option<String>, String)>,
+    tweaks: Vec<(String, f32)>,
+    jobs: usize,
+    burn_in: bool,
+    contact_sheet: Option<String>,
+    thumb_size: u32,
+    report: Option<String>,
+    outdir: String,
+    output_template: Option<String>,
+    /// problems in the config file, reported once warnings can be printed
+    warnings: Vec<String>,
+}
+
+/// Parse `value` of the option `name`
+/// `expected` describes valid values in the error message, values that `valid` rejects are
+/// errors as well
+fn parse_value<T: FromStr>(
+    name: &str,
+    value: &str,
+    expected: &str,
+    valid: impl Fn(&T) -> bool,
+) -> Result<T, InputError> {
+    value
+        .trim()
+        .parse()
+        .ok()
+        .filter(valid)
+        .ok_or(Config::parse_err(&format!(
+            "Expected {expected} for {name} but got '{value}'"
+        )))
+}
+
+/// Parse a resolution of the form `WxH` with positive integers
+fn parse_resolution(value: &str) -> Result<(u32, u32), InputError> {
+    value
+        .split_once(['x', 'X'])
+        .and_then(|(w, h)| Some((w.trim().parse().ok()?, h.trim().parse().ok()?)))
+        .filter(|&(w, h): &(u32, u32)| w > 0 && h > 0)
+        .ok_or(Config::parse_err(&format!(
+            "Expected a resolution like 640x480 for resolution but got '{value}'"
+        )))
+}
+
+/// Parse the channels of saved images
+fn parse_channels(value: &str) -> Result<Channels, InputError> {
+    match value.trim() {
+        "rgb" => Ok(Channels::Rgb),
+        "bgr" => Ok(Channels::Bgr),
+        "gray" => Ok(Channels::Gray),
+        "gray16" => Ok(Channels::Gray16),
+        _ => Err(Config::parse_err(&format!(
+            "Expected one of rgb, bgr, gray, gray16 for channels but got '{value}'"
+        ))),
+    }
+}
+
+/// Parse what happens when a texture file can't be loaded
+fn parse_missing_textures(value: &str) -> Result<MissingTextures, InputError> {
+    match value.trim() {
+        "error" => Ok(MissingTextures::Error),
+        "placeholder" => Ok(MissingTextures::Placeholder),
+        _ => Err(Config::parse_err(&format!(
+            "Expected one of error, placeholder for missing-textures but got '{value}'"
+        ))),
+    }
+}
+
+/// Parse a comma separated list of exposure values
+fn parse_bracket(value: &str) -> Result<Vec<f32>, InputError> {
+    value
+        .split(',')
+        .map(|ev| ev.trim().parse::<f32>().ok().filter(|ev| ev.is_finite()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Config::parse_err(&format!(
+            "Expected a comma separated list of exposure values for bracket but got '{value}'"
+        )))
+}
+
+/// Parse a comma separated list of material property multipliers like `reflectance=0.5`
+fn parse_tweaks(value: &str) -> Result<Vec<(String, f32)>, InputError> {
+    parse_list(value)
+        .into_iter()
+        .map(|tweak| {
+            let (name, factor) = tweak.split_once('=').ok_or(Config::parse_err(&format!(
+                "Expected NAME=FACTOR for tweak but got '{tweak}'"
+            )))?;
+            let name = name.trim();
+            if !Material::PROPERTIES.contains(&name) {
+                return Err(Config::parse_err(&format!(
+                    "Unknown material property '{name}' for tweak, expected one of: {}",
+                    Material::PROPERTIES.join(", ")
+                )));
+            }
+            let valid = |f: &f32| f.is_finite() && *f >= 0.;
+            let factor = parse_value("tweak", factor, "a non-negative number", valid)?;
+            Ok((name.to_string(), factor))
+        })
+        .collect()
+}
+
+/// Split a comma separated list of names and drop empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Parse a comma separated list of names that all have to be in `known`
+/// `describe` names an unknown entry in the error message
+fn parse_known(
+    value: &str,
+    known: &[&str],
+    describe: impl Fn(&str) -> String,
+) -> Result<Vec<String>, InputError> {
+    let names = parse_list(value);
+    match names.iter().find(|n| !known.contains(&n.as_str())) {
+        Some(unknown) => Err(Config::parse_err(&format!(
+            "Unknown {}, expected one of: {}",
+            describe(unknown),
+            known.join(", ")
+        ))),
+        None => Ok(names),
+    }
 }
 
 impl Config {
-    fn default() -> Config {
-        let options: HashMap<_, _> = OPTIONS
+    /// Return the values of all options with a default value
+    fn defaults() -> HashMap<&'static str, String> {
+        OPTIONS
             .iter()
             .filter_map(|opt| match opt.action {
-                OptAction::Set { default, .. } => Some((opt.long, default.to_string())),
-                OptAction::Toggle => None,
+                // options with an empty default are only set when given
+                OptAction::Set { default, .. } if !default.is_empty() => {
+                    Some((opt.long, default.to_string()))
+                }
+                OptAction::Set { .. } | OptAction::Toggle => None,
             })
-            .collect();
-
-        Config {
-            input_file: String::new(),
-            options,
-        }
+            .collect()
     }
 
     /// Convert a message to a argument specific ``InputError``
@@ -151,68 +728,272 @@ impl Config {
         InputError::new("Error while parsing Arguments".to_string(), msg.to_string())
     }
 
-    fn insert_options<'a, I>(&mut self, opt: &CliOption, iter: &mut I) -> Result<(), InputError>
+    fn insert_options<'a, I>(
+        options: &mut HashMap<&'static str, String>,
+        opt: &CliOption,
+        iter: &mut I,
+    ) -> Result<(), InputError>
     where
         I: Iterator<Item = &'a String>,
     {
         match opt.action {
-            OptAction::Toggle => self.options.insert(opt.long, String::new()),
-            OptAction::Set { .. } => self.options.insert(
-                opt.long,
-                iter.next()
-                    .ok_or(Self::parse_err(&format!(
-                        "Expected value for option {}",
-                        opt.long,
-                    )))?
-                    .clone(),
-            ),
-        };
+            OptAction::Toggle => {
+                options.insert(opt.long, String::new());
+            }
+            OptAction::Set { .. } => {
+                let value = iter.next().ok_or(Self::parse_err(&format!(
+                    "Expected value for option {}",
+                    opt.long,
+                )))?;
+                match options.get_mut(opt.long) {
+                    Some(list) if REPEATABLE.contains(&opt.long) => {
+                        list.push(',');
+                        list.push_str(value);
+                    }
+                    _ => {
+                        options.insert(opt.long, value.clone());
+                    }
+                }
+            }
+        }
         Ok(())
     }
 
-    /// Build a config from a slice of Strings containing the arguments
-    /// If this function returns Ok but with a None value, the program should exit early
-    ///
-    /// # Errors
-    ///
-    /// Returns an ``InputError`` when there are errors in the arguments, such as missing required
-    /// required arguments or unknown options
-    pub fn build(args: &[String]) -> Result<Option<Config>, InputError> {
-        let mut config = Config::default();
-        let mut unparsed = Vec::new();
-
-        // skip first arg (the binary name)
-        let mut iter = args.iter().skip(1);
-        while let Some(arg) = iter.next() {
-            if let Some(longopt) = arg.strip_prefix("--") {
-                let opt = Config::parse_longopt(longopt)?;
-                config.insert_options(opt, &mut iter)?;
-            } else if let Some(shortopt) = arg.strip_prefix("-") {
-                let opts = Config::parse_shortopt(shortopt)?;
+    /// Set the option `name` from its raw `value`
+    /// The value of toggles is ignored, they are turned on
+    fn set(&mut self, name: &str, value: &str) -> Result<(), InputError> {
+        let non_negative = "a non-negative integer";
+        match name {
+            "ppm" => self.ppm = true,
+            "blur" => self.blur = true,
+            "progress-bar" => self.progress_bar = true,
+            "strict" => self.strict = true,
+            "strict-schema" => self.strict_schema = true,
+            "no-env-expansion" => self.no_env_expansion = true,
+            "no-color" => self.no_color = true,
+            "info" => self.info = true,
+            "verbose" => self.verbose = true,
+            "auto-frame" => self.auto_frame = true,
+            "merge-static" => self.merge_static = true,
+            "detect-nan" => self.detect_nan = true,
+            "highlight-nan" => self.highlight_nan = true,
+            "turntable" => self.turntable = true,
+            "max-texture-size" => {
+                let size = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+                self.max_texture_size = Some(size);
+            }
+            "max-texture-pixels" => {
+                self.max_texture_pixels =
+                    parse_value(name, value, "a positive integer", |n| *n > 0)?;
+            }
+            "missing-textures" => self.missing_textures = parse_missing_textures(value)?,
+            "max-triangles" => {
+                self.max_triangles = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+            }
+            "decimate-to" => {
+                let target = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+                self.decimate_to = Some(target);
+            }
+            "max-seconds" => {
+                let valid = |n: &f64| n.is_finite() && *n > 0.;
+                self.max_seconds = Some(parse_value(name, value, "a positive number", valid)?);
+            }
+            "max-samples-total" => {
+                let total = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+                self.max_samples_total = Some(total);
+            }
+            "samples" => self.samples = Some(parse_value(name, value, non_negative, |_| true)?),
+            "max-feature-rays" => {
+                self.max_feature_rays = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+            }
+            "pass-samples" => {
+                let samples = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+                self.pass_samples = Some(samples);
+            }
+            "max-resident-frames" => {
+                self.max_resident_frames =
+                    parse_value(name, value, "a positive integer", |n| *n > 0)?;
+            }
+            "resolution" => self.resolution = Some(parse_resolution(value)?),
+            "scale" => {
+                let valid = |f: &f32| f.is_finite() && *f > 0.;
+                self.scale = Some(parse_value(name, value, "a positive number", valid)?);
+            }
+            "max-bounces" => {
+                self.max_bounces = Some(parse_value(name, value, non_negative, |_| true)?);
+            }
+            "channels" => self.channels = parse_channels(value)?,
+            "bracket" => self.bracket = parse_bracket(value)?,
+            "seed" => self.seed = Some(parse_value(name, value, non_negative, |_| true)?),
+            "camera" => self.camera = Some(value.to_string()),
+            "layers" => self.layers = parse_list(value),
+            "aov" => {
+                self.aovs = parse_known(value, &AOVS, |aov| format!("output '{aov}' for aov"))?;
+            }
+            "debug" => {
+                self.debug =
+                    parse_known(value, &DEBUG_MODES, |mode| format!("debug mode '{mode}'"))?;
+            }
+            "builtin" => self.builtin = Some(value.to_string()),
+            "preview-material" => self.preview_material = Some(value.to_string()),
+            "override-material" => {
+                self.override_material = Some(match value.split_once('=') {
+                    Some((id, name)) => (Some(id.trim().to_string()), name.trim().to_string()),
+                    None => (None, value.trim().to_string()),
+                });
+            }
+            "tweak" => self.tweaks = parse_tweaks(value)?,
+            "jobs" => self.jobs = parse_value(name, value, "a positive integer", |n| *n > 0)?,
+            "burn-in" => self.burn_in = true,
+            "contact-sheet" => self.contact_sheet = Some(value.to_string()),
+            "thumb-size" => {
+                self.thumb_size = parse_value(name, value, "a positive integer", |n| *n > 0)?;
+            }
+            "report" => self.report = Some(value.to_string()),
+            "outdir" => self.outdir = value.to_string(),
+            "output-template" => self.output_template = Some(value.to_string()),
+            // only change how the arguments are read
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Read the config file at `path` into `options`
+    /// Keys are the long names of the options, `_` can be used instead of `-`. Toggles are set
+    /// with `true` or `false`. Keys that are no options only cause a warning, so newer config
+    /// files still work with older versions
+    fn read_file(
+        path: &Path,
+        options: &mut HashMap<&'static str, String>,
+        warnings: &mut Vec<String>,
+    ) -> Result<(), InputError> {
+        let file_err = |msg: String| {
+            InputError::new(
+                format!("Error while reading config file '{}'", path.display()),
+                msg,
+            )
+        };
+        let text = fs::read_to_string(path).map_err(|err| file_err(err.to_string()))?;
+        for (key, value) in config_file::parse(&text).map_err(file_err)? {
+            let name = key.replace('_', "-");
+            let Some(opt) = OPTIONS
+                .iter()
+                .find(|opt| opt.long == name && !CLI_ONLY.contains(&opt.long))
+            else {
+                warnings.push(format!(
+                    "Unknown key '{key}' in config file '{}' is ignored",
+                    path.display()
+                ));
+                continue;
+            };
+            match (&opt.action, value) {
+                (OptAction::Toggle, ConfigValue::Bool(true)) => {
+                    options.insert(opt.long, String::new());
+                }
+                (OptAction::Toggle, ConfigValue::Bool(false)) => {
+                    options.remove(opt.long);
+                }
+                (OptAction::Toggle, ConfigValue::Text(text)) => {
+                    return Err(file_err(format!(
+                        "Expected true or false for {key} but got '{text}'"
+                    )));
+                }
+                (OptAction::Set { .. }, ConfigValue::Bool(b)) => {
+                    options.insert(opt.long, b.to_string());
+                }
+                (OptAction::Set { .. }, ConfigValue::Text(text)) => {
+                    options.insert(opt.long, text);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Build a config from a slice of Strings containing the arguments
+    /// Options that are not given on the command line are read from the config file given with
+    /// `--config`, or from `~/.config/rt/config.toml` if it exists
+    /// If this function returns Ok but with a None value, the program should exit early
+    ///
+    /// # Errors
+    ///
+    /// Returns an ``InputError`` when there are errors in the arguments or the config file, such
+    /// as missing required arguments, unknown options or invalid values
+    pub fn build(args: &[String]) -> Result<Option<Config>, InputError> {
+        Config::build_with_default(args, default_config_path().filter(|path| path.is_file()))
+    }
+
+    /// Build a config like [`Config::build`], with `default_file` as the config file that is
+    /// read if no other one is given
+    pub(super) fn build_with_default(
+        args: &[String],
+        default_file: Option<PathBuf>,
+    ) -> Result<Option<Config>, InputError> {
+        let mut cli = HashMap::new();
+        let mut unparsed = Vec::new();
+
+        // skip first arg (the binary name)
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            if let Some(longopt) = arg.strip_prefix("--") {
+                let opt = Config::parse_longopt(longopt)?;
+                Config::insert_options(&mut cli, opt, &mut iter)?;
+            } else if let Some(shortopt) = arg.strip_prefix("-") {
+                let opts = Config::parse_shortopt(shortopt)?;
 
                 for opt in opts {
-                    config.insert_options(opt, &mut iter)?;
+                    Config::insert_options(&mut cli, opt, &mut iter)?;
                 }
             } else {
                 unparsed.push(arg);
             }
         }
 
-        if config.help() {
+        if cli.contains_key("help") {
             print_help();
             return Ok(None);
         }
 
-        if config.version() {
+        if cli.contains_key("version") {
             print_version();
             return Ok(None);
         }
 
-        let file = unparsed
-            .first()
-            .ok_or(Self::parse_err("Missing input path"))?;
+        // the command line overrides the config file, which overrides the defaults
+        let mut options = Config::defaults();
+        let mut config = Config::default();
+        if let Some(path) = cli.get("config").map(PathBuf::from).or(default_file) {
+            Config::read_file(&path, &mut options, &mut config.warnings)?;
+        }
+        options.extend(cli);
+        for (name, value) in &options {
+            config.set(name, value)?;
+        }
+        if !config.no_env_expansion {
+            config.outdir = expand_env_vars(&config.outdir)
+                .map_err(|msg| InputError::new("Invalid output directory".to_string(), msg))?;
+            if let Some(template) = &mut config.output_template {
+                *template = expand_env_vars(template)
+                    .map_err(|msg| InputError::new("Invalid output template".to_string(), msg))?;
+            }
+        }
+        if let Some(template) = &config.output_template {
+            check_template(template)
+                .map_err(|msg| InputError::new("Invalid output template".to_string(), msg))?;
+        }
 
-        config.input_file = (*file).to_string();
+        if unparsed.is_empty() && config.builtin.is_none() {
+            return Err(Self::parse_err("Missing input path"));
+        }
+
+        config.input_files = unparsed.into_iter().cloned().collect();
+        if config.contact_sheet.is_some()
+            && !(config.bracket.is_empty() && config.layers.is_empty() && config.aovs.is_empty())
+        {
+            return Err(Self::parse_err(
+                "A contact sheet only shows the images of the scenes, it can't be combined with \
+                 --bracket, --layers or --aov",
+            ));
+        }
 
         Ok(Some(config))
     }
@@ -241,47 +1022,320 @@ impl Config {
             .collect()
     }
 
+    /// Return the problems found in the config file, which should be reported as warnings
+    #[must_use]
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
     #[must_use]
     pub fn progress_bar(&self) -> bool {
-        self.options.contains_key("progress-bar")
+        self.progress_bar
     }
 
     #[must_use]
     pub fn ppm(&self) -> bool {
-        self.options.contains_key("ppm")
+        self.ppm
     }
 
     #[must_use]
     pub fn blur(&self) -> bool {
-        self.options.contains_key("blur")
+        self.blur
+    }
+
+    #[must_use]
+    pub fn strict(&self) -> bool {
+        self.strict
+    }
+
+    #[must_use]
+    pub fn strict_schema(&self) -> bool {
+        self.strict_schema
+    }
+
+    /// Return true if `${VAR}` in paths should be kept instead of replaced with the environment
+    /// variable
+    #[must_use]
+    pub fn no_env_expansion(&self) -> bool {
+        self.no_env_expansion
+    }
+
+    #[must_use]
+    pub fn no_color(&self) -> bool {
+        self.no_color
+    }
+
+    /// Return true if pixels with NaN or infinite values should be reported
+    #[must_use]
+    pub fn detect_nan(&self) -> bool {
+        self.detect_nan || self.highlight_nan()
+    }
+
+    #[must_use]
+    pub fn highlight_nan(&self) -> bool {
+        self.highlight_nan
+    }
+
+    #[must_use]
+    pub fn info(&self) -> bool {
+        self.info
+    }
+
+    #[must_use]
+    pub fn auto_frame(&self) -> bool {
+        self.auto_frame
+    }
+
+    #[must_use]
+    pub fn merge_static(&self) -> bool {
+        self.merge_static
+    }
+
+    #[must_use]
+    pub fn verbose(&self) -> bool {
+        self.verbose
+    }
+
+    /// Return the number of samples per pixel that overrides the scene if one was given
+    #[must_use]
+    pub fn samples(&self) -> Option<u32> {
+        self.samples
+    }
+
+    /// Return the most rays a camera sample may spend on glossy reflections and soft shadows
+    #[must_use]
+    pub fn max_feature_rays(&self) -> u32 {
+        self.max_feature_rays
+    }
+
+    /// Return the samples per pixel of a progressive pass if the render should be progressive
+    #[must_use]
+    pub fn pass_samples(&self) -> Option<u32> {
+        self.pass_samples
+    }
+
+    /// Return the most frames that are refined at once in a progressive render
+    #[must_use]
+    pub fn max_resident_frames(&self) -> usize {
+        self.max_resident_frames
+    }
+
+    /// Return the resolution that overrides the scene if one was given
+    #[must_use]
+    pub fn resolution(&self) -> Option<(u32, u32)> {
+        self.resolution
+    }
+
+    /// Return the factor the resolution is scaled by if one was given
+    #[must_use]
+    pub fn scale(&self) -> Option<f32> {
+        self.scale
+    }
+
+    /// Return the maximum number of bounces that overrides the scene if one was given
+    #[must_use]
+    pub fn max_bounces(&self) -> Option<u32> {
+        self.max_bounces
+    }
+
+    /// Return the exposure values of the bracketed images if any were given
+    /// Returns an empty list if the render should not be bracketed
+    #[must_use]
+    pub fn bracket(&self) -> &[f32] {
+        &self.bracket
+    }
+
+    /// Return the seed of the random sampling if one was given
+    #[must_use]
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Return the maximum texture size if one was given
+    #[must_use]
+    pub fn max_texture_size(&self) -> Option<u32> {
+        self.max_texture_size
+    }
+
+    /// Return the maximum number of pixels of a texture
+    #[must_use]
+    pub fn max_texture_pixels(&self) -> u64 {
+        self.max_texture_pixels
+    }
+
+    /// Return what happens when a texture file can't be loaded
+    #[must_use]
+    pub fn missing_textures(&self) -> MissingTextures {
+        self.missing_textures
+    }
+
+    /// Return the maximum number of triangles of all meshes in a scene
+    #[must_use]
+    pub fn max_triangles(&self) -> usize {
+        self.max_triangles
+    }
+
+    /// Return the number of triangles that large meshes are reduced to, if one was given
+    #[must_use]
+    pub fn decimate_to(&self) -> Option<usize> {
+        self.decimate_to
+    }
+
+    /// Return the time limit of a render in seconds if one was given
+    #[must_use]
+    pub fn max_seconds(&self) -> Option<f64> {
+        self.max_seconds
+    }
+
+    /// Return the maximum number of camera samples of a render if one was given
+    #[must_use]
+    pub fn max_samples_total(&self) -> Option<u64> {
+        self.max_samples_total
+    }
+
+    /// Return the number of scenes that should be rendered at the same time
+    #[must_use]
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Return the name of the camera that should be used if one was given
+    #[must_use]
+    pub fn camera(&self) -> Option<&str> {
+        self.camera.as_deref()
+    }
+
+    /// Return the render layers that should be rendered separately
+    /// Returns an empty list if no layers were given
+    #[must_use]
+    pub fn layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    /// Return true if the extra output `aov` should be saved
+    #[must_use]
+    pub fn aov(&self, aov: &str) -> bool {
+        self.aovs.iter().any(|a| a == aov)
+    }
+
+    /// Return the channels of saved images
+    #[must_use]
+    pub fn channels(&self) -> Channels {
+        self.channels
+    }
+
+    /// Return true if the debug render `mode` is turned on
+    #[must_use]
+    pub fn debug(&self, mode: &str) -> bool {
+        self.debug.iter().any(|m| m == mode)
+    }
+
+    /// Return the name of the builtin scene that should be rendered if one was given
+    #[must_use]
+    pub fn builtin(&self) -> Option<&str> {
+        self.builtin.as_deref()
+    }
+
+    /// Return the name of the material that should be previewed if one was given
+    #[must_use]
+    pub fn preview_material(&self) -> Option<&str> {
+        self.preview_material.as_deref()
+    }
+
+    /// Return the id of the surface whose material should be overridden, if only one should be,
+    /// and the name of the override material if one was given
+    #[must_use]
+    pub fn override_material(&self) -> Option<(Option<&str>, &str)> {
+        self.override_material
+            .as_ref()
+            .map(|(id, name)| (id.as_deref(), name.as_str()))
+    }
+
+    /// Return the material properties with the factors they should be multiplied by, in the
+    /// order they were given
+    #[must_use]
+    pub fn tweaks(&self) -> &[(String, f32)] {
+        &self.tweaks
+    }
+
+    #[must_use]
+    pub fn turntable(&self) -> bool {
+        self.turntable
+    }
+
+    /// Return the path of the json report if one was given
+    #[must_use]
+    pub fn report(&self) -> Option<&str> {
+        self.report.as_deref()
+    }
+
+    /// Return true if the render info should be burned into the saved images
+    #[must_use]
+    pub fn burn_in(&self) -> bool {
+        self.burn_in
+    }
+
+    /// Return the file of the contact sheet, if thumbnails should be rendered instead of images
+    #[must_use]
+    pub fn contact_sheet(&self) -> Option<&str> {
+        self.contact_sheet.as_deref()
+    }
+
+    /// Return the largest width and height of the thumbnails of a contact sheet
+    #[must_use]
+    pub fn thumb_size(&self) -> u32 {
+        self.thumb_size
     }
 
-    #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn outdir(&self) -> &str {
-        self.options
-            .get("outdir")
-            .expect("outdir should always be inside")
+        &self.outdir
     }
 
-    fn help(&self) -> bool {
-        self.options.contains_key("help")
+    /// Return the template of the path of the images, which replaces the output directory and the
+    /// output file of the scene
+    #[must_use]
+    pub fn output_template(&self) -> Option<&str> {
+        self.output_template.as_deref()
     }
 
-    fn version(&self) -> bool {
-        self.options.contains_key("version")
+    /// Return the path of the output file `file` inside the output directory
+    /// Both `/` and `\` are accepted as separators, an absolute `file` ignores the directory
+    #[must_use]
+    pub fn output_path(&self, file: &str) -> PathBuf {
+        paths::join(&self.outdir, file)
     }
 
-    /// get a referencee to the provided input file path
+    /// get a referencee to the first provided input file path
+    /// Returns an empty string if only a builtin scene was given
     #[must_use]
     pub fn get_input(&self) -> &str {
-        &self.input_file
+        self.input_files.first().map_or("", String::as_str)
+    }
+
+    /// get all provided input file paths
+    #[must_use]
+    pub fn get_inputs(&self) -> &[String] {
+        &self.input_files
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::input::env_vars::tests::EnvGuard;
+
+    /// Build the config without reading the config file of the user
+    fn build(args: &[String]) -> Result<Option<Config>, InputError> {
+        Config::build_with_default(args, None)
+    }
+
+    /// Write `content` to a config file in the temporary directory and return its path
+    fn config_file(name: &str, content: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("rt_config_{}_{name}.toml", std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
 
     #[test]
     fn parse_input_args() {
@@ -294,7 +1348,7 @@ mod tests {
             "--progress-bar".to_string(),
         ];
 
-        let config = Config::build(args).unwrap().unwrap();
+        let config = build(args).unwrap().unwrap();
 
         assert_eq!(config.get_input(), "input.obj");
         assert_eq!(config.outdir(), "output");
@@ -302,14 +1356,363 @@ mod tests {
         assert!(config.progress_bar());
     }
 
+    #[test]
+    fn parse_multiple_inputs() {
+        let args = &[
+            "test".to_string(),
+            "a.xml".to_string(),
+            "-j".to_string(),
+            "2".to_string(),
+            "b.xml".to_string(),
+            "--report".to_string(),
+            "report.json".to_string(),
+        ];
+
+        let config = build(args).unwrap().unwrap();
+
+        assert_eq!(config.get_inputs(), ["a.xml", "b.xml"]);
+        assert_eq!(config.get_input(), "a.xml");
+        assert_eq!(config.jobs(), 2);
+        assert_eq!(config.report(), Some("report.json"));
+        assert!(config.layers().is_empty());
+    }
+
+    #[test]
+    fn parse_layers() {
+        let args = &[
+            "test".to_string(),
+            "a.xml".to_string(),
+            "--layers".to_string(),
+            "hero, env,,".to_string(),
+        ];
+
+        let config = build(args).unwrap().unwrap();
+
+        assert_eq!(config.layers(), ["hero", "env"]);
+    }
+
+    #[test]
+    fn parse_aovs() {
+        let args = |aov: &str| {
+            [
+                "test".to_string(),
+                "a.xml".to_string(),
+                "--aov".to_string(),
+                aov.to_string(),
+            ]
+        };
+        let config = build(&args("motion")).unwrap().unwrap();
+        assert!(config.aov("motion"));
+        assert!(!config.aov("depth"));
+        let config = build(&args("diffuse, emission")).unwrap().unwrap();
+        assert!(config.aov("diffuse") && config.aov("emission") && !config.aov("motion"));
+
+        let err = build(&args("motion,depth")).unwrap_err();
+        assert!(
+            err.msg().contains("Unknown output 'depth'"),
+            "{}",
+            err.msg()
+        );
+    }
+
+    #[test]
+    fn parse_debug_modes() {
+        let args = |modes: &str| {
+            [
+                "test".to_string(),
+                "a.xml".to_string(),
+                "--debug".to_string(),
+                modes.to_string(),
+            ]
+        };
+        let config = build(&args("lights")).unwrap().unwrap();
+        assert!(config.debug("lights") && !config.debug("time"));
+        let config = build(&args("lights,time")).unwrap().unwrap();
+        assert!(config.debug("lights") && config.debug("time"));
+        assert!(!build(&["test".to_string(), "a.xml".to_string()])
+            .unwrap()
+            .unwrap()
+            .debug("lights"));
+
+        let err = build(&args("lights,bvh")).unwrap_err();
+        assert!(
+            err.msg().contains("Unknown debug mode 'bvh'"),
+            "{}",
+            err.msg()
+        );
+    }
+
+    #[test]
+    fn parse_missing_textures() {
+        let args = |mode: &str| {
+            [
+                "test".to_string(),
+                "a.xml".to_string(),
+                "--missing-textures".to_string(),
+                mode.to_string(),
+            ]
+        };
+        let default = build(&["test".to_string(), "a.xml".to_string()]);
+        assert_eq!(
+            default.unwrap().unwrap().missing_textures(),
+            MissingTextures::Error
+        );
+        assert_eq!(
+            build(&args("placeholder"))
+                .unwrap()
+                .unwrap()
+                .missing_textures(),
+            MissingTextures::Placeholder
+        );
+        let err = build(&args("skip")).unwrap_err();
+        assert!(err.msg().contains("'skip'"), "{}", err.msg());
+    }
+
+    #[test]
+    fn parse_channels() {
+        let args = |channels: &str| {
+            [
+                "test".to_string(),
+                "a.xml".to_string(),
+                "--channels".to_string(),
+                channels.to_string(),
+            ]
+        };
+        let default = build(&["test".to_string(), "a.xml".to_string()]);
+        assert_eq!(default.unwrap().unwrap().channels(), Channels::Rgb);
+        assert_eq!(
+            build(&args("bgr")).unwrap().unwrap().channels(),
+            Channels::Bgr
+        );
+        assert_eq!(
+            build(&args("gray16")).unwrap().unwrap().channels(),
+            Channels::Gray16
+        );
+        assert!(build(&args("rgba")).is_err());
+    }
+
+    #[test]
+    fn parse_tweaks() {
+        let args = |tweaks: &[&str]| {
+            let mut args = vec!["test".to_string(), "a.xml".to_string()];
+            for tweak in tweaks {
+                args.push("--tweak".to_string());
+                args.push((*tweak).to_string());
+            }
+            args
+        };
+        let config = build(&args(&["reflectance=0.5", "roughness = 2,ks=0"]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            config.tweaks(),
+            [
+                ("reflectance".to_string(), 0.5),
+                ("roughness".to_string(), 2.),
+                ("ks".to_string(), 0.)
+            ]
+        );
+        assert!(build(&args(&[])).unwrap().unwrap().tweaks().is_empty());
+
+        let err = build(&args(&["shininess=2"])).unwrap_err();
+        assert!(
+            err.msg().contains("'shininess'") && err.msg().contains("reflectance, transmittance"),
+            "{}",
+            err.msg()
+        );
+        assert!(build(&args(&["ka=-1"])).is_err());
+        assert!(build(&args(&["ka"])).is_err());
+    }
+
+    #[test]
+    fn parse_override_material() {
+        let args = &[
+            "test".to_string(),
+            "a.xml".to_string(),
+            "--override-material".to_string(),
+            "sphere0=clay".to_string(),
+        ];
+        let config = build(args).unwrap().unwrap();
+        assert_eq!(config.override_material(), Some((Some("sphere0"), "clay")));
+
+        let args = &[
+            args[0].clone(),
+            args[1].clone(),
+            args[2].clone(),
+            "chrome".to_string(),
+        ];
+        let config = build(args).unwrap().unwrap();
+        assert_eq!(config.override_material(), Some((None, "chrome")));
+        assert_eq!(
+            build(&args[..2]).unwrap().unwrap().override_material(),
+            None
+        );
+    }
+
+    #[test]
+    fn builtin_without_input_file() {
+        let args = &[
+            "test".to_string(),
+            "--builtin".to_string(),
+            "cornell".to_string(),
+        ];
+
+        let config = build(args).unwrap().unwrap();
+
+        assert_eq!(config.builtin(), Some("cornell"));
+        assert!(config.get_inputs().is_empty());
+        assert!(build(&args[..1]).is_err());
+    }
+
     #[test]
     fn help_version_early_exit() {
         let args = &["test".to_string(), "--help".to_string()];
-        let config = Config::build(args).unwrap();
+        let config = build(args).unwrap();
         assert!(config.is_none());
 
         let args = &["test".to_string(), "--version".to_string()];
-        let config = Config::build(args).unwrap();
+        let config = build(args).unwrap();
         assert!(config.is_none());
     }
+
+    #[test]
+    fn config_file_only() {
+        let path = config_file(
+            "file_only",
+            "outdir = \"renders\"\njobs = 3\nprogress_bar = true\nseed = 42\nppm = false\n",
+        );
+        let args = ["test".to_string(), "a.xml".to_string()];
+        let config = Config::build_with_default(&args, Some(path.clone()))
+            .unwrap()
+            .unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.outdir(), "renders");
+        assert_eq!(config.jobs(), 3);
+        assert_eq!(config.seed(), Some(42));
+        assert!(config.progress_bar() && !config.ppm());
+        assert!(config.warnings().is_empty());
+    }
+
+    #[test]
+    fn burn_in_is_only_set_on_the_command_line() {
+        let path = config_file(
+            "burn_in",
+            "burn_in = true
+",
+        );
+        let args = ["test".to_string(), "a.xml".to_string()];
+        let config = Config::build_with_default(&args, Some(path.clone()))
+            .unwrap()
+            .unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(!config.burn_in());
+        assert!(config.warnings()[0].contains("burn_in"));
+
+        let args = [&args[..], &["--burn-in".to_string()]].concat();
+        assert!(build(&args).unwrap().unwrap().burn_in());
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let path = config_file(
+            "both",
+            "outdir = \"renders\"\njobs = 3\nthreads = 8\nlayers = [\"hero\", \"env\"]\n",
+        );
+        let args = [
+            "test".to_string(),
+            "a.xml".to_string(),
+            "--config".to_string(),
+            path.display().to_string(),
+            "-j".to_string(),
+            "2".to_string(),
+            "--ppm".to_string(),
+        ];
+        let config = build(&args).unwrap().unwrap();
+
+        assert_eq!(config.jobs(), 2);
+        assert_eq!(config.outdir(), "renders");
+        assert_eq!(config.layers(), ["hero", "env"]);
+        assert!(config.ppm());
+        // unknown keys are only warnings
+        assert_eq!(config.warnings().len(), 1);
+        assert!(config.warnings()[0].contains("threads"));
+
+        // values from the file are validated like the command line
+        fs::write(&path, "jobs = 0").unwrap();
+        let err = build(&args[..4]).unwrap_err();
+        assert!(err.to_string().contains("jobs"), "{err}");
+        fs::write(&path, "ppm = \"yes\"").unwrap();
+        assert!(build(&args[..4]).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(build(&args[..4]).is_err());
+    }
+
+    #[test]
+    fn invalid_values_fail_while_building() {
+        let args = &[
+            "test".to_string(),
+            "a.xml".to_string(),
+            "--resolution".to_string(),
+            "640x0".to_string(),
+        ];
+        assert!(build(args).is_err());
+
+        let config = build(&args[..2]).unwrap().unwrap();
+        assert_eq!(config.jobs(), 1);
+        assert_eq!(config.outdir(), "output");
+        assert_eq!(config.resolution(), None);
+    }
+
+    #[test]
+    fn outdir_expands_environment_variables() {
+        let _env = EnvGuard::set(&[("RT_TEST_OUTDIR_JOB", "job3")]);
+        let args = |extra: &[&str]| {
+            [
+                "test",
+                "input.xml",
+                "--outdir",
+                "renders/${RT_TEST_OUTDIR_JOB}",
+            ]
+            .iter()
+            .chain(extra)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+        };
+
+        let config = build(&args(&[])).unwrap().unwrap();
+        assert_eq!(config.outdir(), "renders/job3");
+
+        let config = build(&args(&["--no-env-expansion"])).unwrap().unwrap();
+        assert_eq!(config.outdir(), "renders/${RT_TEST_OUTDIR_JOB}");
+
+        let unset = ["test", "input.xml", "-o", "${RT_TEST_OUTDIR_UNSET}"].map(String::from);
+        let err = build(&unset).unwrap_err();
+        assert!(err.msg().contains("RT_TEST_OUTDIR_UNSET"));
+    }
+
+    #[test]
+    fn output_template_is_checked() {
+        let _env = EnvGuard::set(&[("RT_TEST_TEMPLATE_FARM", "farm")]);
+        let args =
+            |template: &str| ["test", "input.xml", "--output-template", template].map(String::from);
+        let default = build(&["test", "input.xml"].map(String::from))
+            .unwrap()
+            .unwrap();
+        assert_eq!(default.output_template(), None);
+
+        let config = build(&args(
+            "${RT_TEST_TEMPLATE_FARM}/{scene}/{date}/{frame:04}.png",
+        ))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            config.output_template(),
+            Some("farm/{scene}/{date}/{frame:04}.png")
+        );
+
+        let err = build(&args("renders/{camera}.png")).unwrap_err();
+        assert_eq!(err.title(), "Invalid output template");
+        assert!(err.msg().contains("{camera}"));
+    }
 }
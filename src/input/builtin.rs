@@ -0,0 +1,768 @@
+//! Builtin scenes and materials
+//! Well-known test scenes, that are constructed entirely in code without any input files
+//! Each scene exercises a specific set of features
+//! The builtin materials can replace the materials of any scene to isolate problems with lighting
+
+use std::f32::consts::PI;
+
+use crate::{
+    image::{Image, Playback},
+    math::{to_radians, Color, Point3, Quat, Vec3},
+    objects::{Camera, Light, Material, Scene, ShadingModel, Surface, Texture, Triangle},
+};
+
+use super::InputError;
+
+/// Names of all builtin scenes
+pub const BUILTIN_SCENES: [&str; 4] = ["cornell", "spheres", "caustic", "julia"];
+
+/// Names of all builtin override materials
+pub const OVERRIDE_MATERIALS: [&str; 4] = ["clay", "chrome", "glass", "normal-debug"];
+
+/// Resolution of material previews
+const PREVIEW_RESOLUTION: (u32, u32) = (256, 256);
+
+/// Number of frames of a turntable preview
+const TURNTABLE_FRAMES: usize = 24;
+
+/// Construct the builtin scene `name` with the given image dimensions
+///
+/// # Errors
+///
+/// Returns an ``InputError`` if there is no builtin scene with that name
+pub fn builtin_scene(name: &str, (width, height): (u32, u32)) -> Result<Scene, InputError> {
+    let scene = match name {
+        "cornell" => cornell(width, height),
+        "spheres" => spheres(width, height),
+        "caustic" => caustic(width, height),
+        "julia" => julia(width, height),
+        _ => {
+            return Err(InputError::new(
+                "Error while loading builtin scene".to_string(),
+                format!(
+                    "Unknown builtin scene '{name}'. Available scenes are: {}",
+                    BUILTIN_SCENES.join(", ")
+                ),
+            ))
+        }
+    };
+    Ok(scene)
+}
+
+/// Construct the builtin override material `name`
+///
+/// # Errors
+///
+/// Returns an ``InputError`` if there is no builtin material with that name
+pub fn override_material(name: &str) -> Result<Material, InputError> {
+    let material = match name {
+        "clay" => Material::new(
+            Texture::Color(Color::new(0.7, 0.7, 0.7)),
+            0.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 0.3,
+                kd: 0.9,
+                ks: 0.,
+                exp: 1,
+            },
+        ),
+        "chrome" => Material::new(
+            Texture::Color(Color::new(0.8, 0.8, 0.8)),
+            0.9,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 0.05,
+                kd: 0.1,
+                ks: 1.0,
+                exp: 400,
+            },
+        ),
+        "glass" => glass(),
+        "normal-debug" => Material::new(
+            Texture::Color(Color::zero()),
+            0.,
+            0.,
+            0.,
+            ShadingModel::Normals,
+        ),
+        _ => {
+            return Err(InputError::new(
+                "Error while overriding materials".to_string(),
+                format!(
+                    "Unknown override material '{name}'. Available materials are: {}",
+                    OVERRIDE_MATERIALS.join(", ")
+                ),
+            ))
+        }
+    };
+    Ok(material)
+}
+
+/// A diffuse phong material
+fn diffuse(color: Color) -> Material {
+    Material::new(
+        Texture::Color(color),
+        0.,
+        0.,
+        0.,
+        ShadingModel::Phong {
+            ka: 0.3,
+            kd: 0.9,
+            ks: 0.1,
+            exp: 10,
+        },
+    )
+}
+
+/// A mostly reflective material
+fn mirror() -> Material {
+    Material::new(
+        Texture::Color(Color::new(0.9, 0.9, 0.9)),
+        0.8,
+        0.,
+        0.,
+        ShadingModel::Phong {
+            ka: 0.1,
+            kd: 0.2,
+            ks: 1.0,
+            exp: 200,
+        },
+    )
+}
+
+/// A clear glass material
+fn glass() -> Material {
+    Material::new(
+        Texture::Color(Color::new(1., 1., 1.)),
+        0.1,
+        0.85,
+        1.5,
+        ShadingModel::Phong {
+            ka: 0.,
+            kd: 0.1,
+            ks: 1.0,
+            exp: 200,
+        },
+    )
+}
+
+/// Two triangles spanning the parallelogram `origin`, `origin + u`, `origin + u + v`, `origin + v`
+/// The normal points along `u x v`
+fn quad(origin: Point3, u: Vec3, v: Vec3) -> [Triangle; 2] {
+    let normal = Vec3::normal(&u.cross(&v));
+    let corners = [origin, origin + u, origin + u + v, origin + v];
+    let texels = [(0., 0.), (1., 0.), (1., 1.), (0., 1.)];
+    [[0, 1, 2], [0, 2, 3]]
+        .map(|i| Triangle::new(i.map(|i| corners[i]), [normal; 3], i.map(|i| texels[i])))
+}
+
+/// The triangles of an axis aligned box with normals pointing outwards
+fn cuboid(min: Point3, max: Point3) -> Vec<Triangle> {
+    let d = max - min;
+    let (x, y, z) = (
+        Vec3::new(d[0], 0., 0.),
+        Vec3::new(0., d[1], 0.),
+        Vec3::new(0., 0., d[2]),
+    );
+    [
+        quad(min, z, y),
+        quad(min + x, y, z),
+        quad(min, x, z),
+        quad(min + y, z, x),
+        quad(min, y, x),
+        quad(min + z, x, y),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// A large floor plane at height `y`
+fn floor(y: f32, material: Material) -> Surface {
+    let size = 20.;
+    Surface::mesh(
+        quad(
+            Point3::new(-size / 2., y, -size / 2.),
+            Vec3::new(0., 0., size),
+            Vec3::new(size, 0., 0.),
+        )
+        .into(),
+        material,
+    )
+}
+
+/// Render `material` on a sphere over a checker floor, lit by a three point light rig
+/// The output file is named after the material. With `turntable`, the sphere makes a full turn
+/// over an animation of `TURNTABLE_FRAMES` frames
+#[must_use]
+pub fn material_preview(name: &str, material: Material, turntable: bool) -> Scene {
+    // 8x8 checker pattern with squares of one unit on the floor
+    let (light, dark, size, square) = ([200; 3], [90; 3], 256, 32);
+    let checker = (0..size * size)
+        .map(|i| {
+            if (i % size / square + i / size / square) % 2 == 0 {
+                light
+            } else {
+                dark
+            }
+        })
+        .collect();
+    let floor_material = Material::new(
+        Texture::image(Image::from_pixels(size, size, checker)),
+        0.,
+        0.,
+        0.,
+        ShadingModel::Phong {
+            ka: 0.3,
+            kd: 0.9,
+            ks: 0.,
+            exp: 1,
+        },
+    );
+    let floor = Surface::mesh(
+        quad(
+            Point3::new(-4., -1., -4.),
+            Vec3::new(0., 0., 8.),
+            Vec3::new(8., 0., 0.),
+        )
+        .into(),
+        floor_material,
+    );
+    let mut sphere = Surface::sphere(Point3::zero(), 1., material);
+    if turntable {
+        // stop one frame short of a full turn, so the animation loops without a repeated frame
+        #[allow(clippy::cast_precision_loss)]
+        let angle = 2. * PI * (TURNTABLE_FRAMES - 1) as f32 / TURNTABLE_FRAMES as f32;
+        sphere.set_spin(angle);
+    }
+
+    let lights = vec![
+        Light::Ambient {
+            color: Color::new(0.2, 0.2, 0.2),
+            hemisphere: None,
+        },
+        // key light
+        Light::Point {
+            color: Color::new(0.9, 0.85, 0.8),
+            position: Point3::new(-3., 4., 3.),
+            radius: 0.,
+            samples: None,
+        },
+        // fill light
+        Light::Point {
+            color: Color::new(0.3, 0.32, 0.35),
+            position: Point3::new(4., 1., 3.),
+            radius: 0.,
+            samples: None,
+        },
+        // rim light
+        Light::Point {
+            color: Color::new(0.6, 0.6, 0.6),
+            position: Point3::new(1., 3., -4.),
+            radius: 0.,
+            samples: None,
+        },
+    ];
+
+    let (width, height) = PREVIEW_RESOLUTION;
+    let camera = Camera::new(
+        Point3::new(0., 1.5, 7.),
+        Point3::new(0., -0.2, 0.),
+        Vec3::new(0., 1., 0.),
+        to_radians(18.),
+        width,
+        height,
+        4,
+    );
+    let mut scene = Scene::new(
+        format!("preview_{name}.png"),
+        Color::new(0.15, 0.15, 0.15),
+        camera,
+        lights,
+        vec![floor, sphere],
+    );
+    if turntable {
+        scene.set_animation(TURNTABLE_FRAMES, Playback::new(24));
+    }
+    scene
+}
+
+/// Cornell box with a red and green wall, a box and a sphere
+/// Tests diffuse shading and soft shadows from an area light. Since the renderer is a whitted
+/// style ray tracer, the area light is approximated by a grid of point lights and the ambient
+/// light stands in for global illumination
+fn cornell(width: u32, height: u32) -> Scene {
+    let white = Color::new(0.8, 0.8, 0.8);
+    let min = Point3::new(-1., -1., -1.);
+    let walls = [
+        // floor, ceiling and back wall
+        (
+            quad(min, Vec3::new(0., 0., 2.), Vec3::new(2., 0., 0.)),
+            white,
+        ),
+        (
+            quad(
+                Point3::new(-1., 1., -1.),
+                Vec3::new(2., 0., 0.),
+                Vec3::new(0., 0., 2.),
+            ),
+            white,
+        ),
+        (
+            quad(min, Vec3::new(2., 0., 0.), Vec3::new(0., 2., 0.)),
+            white,
+        ),
+        // left (red) and right (green) wall
+        (
+            quad(min, Vec3::new(0., 2., 0.), Vec3::new(0., 0., 2.)),
+            Color::new(0.75, 0.1, 0.1),
+        ),
+        (
+            quad(
+                Point3::new(1., -1., -1.),
+                Vec3::new(0., 0., 2.),
+                Vec3::new(0., 2., 0.),
+            ),
+            Color::new(0.1, 0.75, 0.1),
+        ),
+    ];
+    let mut surfaces: Vec<_> = walls
+        .into_iter()
+        .map(|(triangles, color)| Surface::mesh(triangles.into(), diffuse(color)))
+        .collect();
+    surfaces.push(Surface::mesh(
+        cuboid(Point3::new(-0.7, -1., -0.6), Point3::new(-0.1, 0.2, 0.)),
+        diffuse(white),
+    ));
+    surfaces.push(Surface::sphere(
+        Point3::new(0.45, -0.65, 0.2),
+        0.35,
+        diffuse(white),
+    ));
+
+    // 3x3 grid of point lights just below the ceiling
+    let mut lights = vec![Light::Ambient {
+        color: Color::new(0.6, 0.6, 0.6),
+        hemisphere: None,
+    }];
+    for i in 0..3 {
+        for j in 0..3 {
+            #[allow(clippy::cast_precision_loss)]
+            let (x, z) = ((i - 1) as f32 * 0.2, (j - 1) as f32 * 0.2 + 0.3);
+            lights.push(Light::Point {
+                color: Color::new(0.9, 0.85, 0.75) / 9.,
+                position: Point3::new(x, 0.95, z),
+                radius: 0.,
+                samples: None,
+            });
+        }
+    }
+
+    let camera = Camera::new(
+        Point3::new(0., 0., 3.),
+        Point3::new(0., 0., 0.),
+        Vec3::new(0., 1., 0.),
+        to_radians(18.),
+        width,
+        height,
+        4,
+    );
+    Scene::new(
+        "cornell.png".to_string(),
+        Color::zero(),
+        camera,
+        lights,
+        surfaces,
+    )
+}
+
+/// A reflective, a glass and a diffuse sphere at different distances on a floor
+/// Tests reflection, refraction, super-sampling and depth of field
+fn spheres(width: u32, height: u32) -> Scene {
+    let surfaces = vec![
+        floor(-1., diffuse(Color::new(0.6, 0.6, 0.6))),
+        Surface::sphere(Point3::new(-1.4, -0.2, -1.), 0.8, mirror()),
+        Surface::sphere(Point3::new(0., -0.3, 0.5), 0.7, glass()),
+        Surface::sphere(
+            Point3::new(1.6, -0.1, -2.5),
+            0.9,
+            diffuse(Color::new(0.2, 0.3, 0.8)),
+        ),
+    ];
+    let lights = vec![
+        Light::Ambient {
+            color: Color::new(0.4, 0.4, 0.4),
+            hemisphere: None,
+        },
+        Light::Point {
+            color: Color::new(0.8, 0.8, 0.8),
+            position: Point3::new(3., 6., 4.),
+            radius: 0.,
+            samples: None,
+        },
+        Light::Parallel {
+            color: Color::new(0.3, 0.3, 0.3),
+            direction: Vec3::new(-1., -1., -1.),
+        },
+    ];
+
+    let mut camera = Camera::new(
+        Point3::new(0., 0.8, 5.),
+        Point3::new(0., -0.3, 0.),
+        Vec3::new(0., 1., 0.),
+        to_radians(30.),
+        width,
+        height,
+        8,
+    );
+    // in focus on the glass sphere
+    camera.add_dof(4.6, 0.15);
+    let mut scene = Scene::new(
+        "spheres.png".to_string(),
+        Color::new(0.5, 0.7, 0.9),
+        camera,
+        lights,
+        surfaces,
+    );
+    scene.add_samples(16);
+    scene
+}
+
+/// A glass sphere on a diffuse floor, lit by a single point light
+/// Tests refraction together with shadows of transparent objects
+fn caustic(width: u32, height: u32) -> Scene {
+    let surfaces = vec![
+        floor(-1., diffuse(Color::new(0.9, 0.9, 0.85))),
+        Surface::sphere(Point3::new(0., 0., 0.), 1., glass()),
+    ];
+    let lights = vec![
+        Light::Ambient {
+            color: Color::new(0.2, 0.2, 0.2),
+            hemisphere: None,
+        },
+        Light::Point {
+            color: Color::new(1., 1., 1.),
+            position: Point3::new(1.5, 4., 1.),
+            radius: 0.,
+            samples: None,
+        },
+    ];
+
+    let camera = Camera::new(
+        Point3::new(0., 1.5, 5.),
+        Point3::new(0., -0.3, 0.),
+        Vec3::new(0., 1., 0.),
+        to_radians(25.),
+        width,
+        height,
+        8,
+    );
+    Scene::new(
+        "caustic.png".to_string(),
+        Color::zero(),
+        camera,
+        lights,
+        surfaces,
+    )
+}
+
+/// An animated quaternion julia set above a floor
+/// Tests the julia set surface and animations
+fn julia(width: u32, height: u32) -> Scene {
+    let mut julia = Surface::julia_set(
+        Point3::new(0., 0., -2.),
+        Quat::new(-0.5, -0.2, 0.3, 0.05),
+        8,
+        0.006,
+        Material::new(
+            Texture::Color(Color::new(0.9, 0.3, 0.4)),
+            0.5,
+            0.,
+            0.,
+            ShadingModel::CookTorrance {
+                ka: 0.2,
+                ks: 0.4,
+                roughness: 0.3,
+            },
+        ),
+    );
+    julia.set_julia_end(Quat::new(0.5, -0.4, 0.9, -0.05));
+    let surfaces = vec![floor(-1.5, diffuse(Color::new(0.7, 0.7, 0.7))), julia];
+    let lights = vec![
+        Light::Ambient {
+            color: Color::new(1., 1., 1.),
+            hemisphere: None,
+        },
+        Light::Point {
+            color: Color::new(0.7, 0.7, 0.7),
+            position: Point3::new(1.5, 3., -2.5),
+            radius: 0.,
+            samples: None,
+        },
+        Light::Point {
+            color: Color::new(0.7, 0.7, 0.7),
+            position: Point3::new(-1.5, 3., -2.5),
+            radius: 0.,
+            samples: None,
+        },
+    ];
+
+    let camera = Camera::new(
+        Point3::new(0., 0., 1.),
+        Point3::new(0., 0., -2.5),
+        Vec3::new(0., 1., 0.),
+        to_radians(45.),
+        width,
+        height,
+        8,
+    );
+    let mut scene = Scene::new(
+        "julia.png".to_string(),
+        Color::zero(),
+        camera,
+        lights,
+        surfaces,
+    );
+    scene.set_animation(60, Playback::new(30));
+    scene
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{file_to_material, LoadOptions};
+
+    /// render the first frame of a scene pixel by pixel
+    fn render(scene: &Scene) -> Vec<Color> {
+        let (width, height) = scene.get_dimensions();
+        (0..height)
+            .flat_map(|v| (0..width).map(move |u| (u, v)))
+            .map(|(u, v)| scene.trace_pixel(u, v))
+            .collect()
+    }
+
+    #[test]
+    fn override_materials_by_surface_id() {
+        let clay = override_material("clay").unwrap();
+        let mut scene = builtin_scene("spheres", (16, 16)).unwrap();
+        let ids = scene.surface_ids();
+        assert!(ids.iter().any(|id| id == "sphere0") && ids.iter().any(|id| id == "mesh0"));
+
+        assert_eq!(scene.override_materials(Some("sphere0"), &clay), 1);
+        let overridden = |scene: &Scene| {
+            scene
+                .get_surfaces()
+                .iter()
+                .filter(|s| *s.material() == clay)
+                .count()
+        };
+        assert_eq!(overridden(&scene), 1);
+        assert_eq!(scene.override_materials(Some("sphere99"), &clay), 0);
+        assert_eq!(scene.override_materials(None, &clay), ids.len());
+        assert_eq!(overridden(&scene), ids.len());
+
+        for name in OVERRIDE_MATERIALS {
+            assert!(override_material(name).is_ok());
+        }
+        assert!(override_material("plastic").is_err());
+    }
+
+    #[test]
+    fn scale_material_property_multiplies_and_clamps() {
+        let mut scene = builtin_scene("spheres", (16, 16)).unwrap();
+        let before: Vec<_> = scene
+            .get_surfaces()
+            .iter()
+            .map(|s| s.material().clone())
+            .collect();
+        scene.scale_material_property("reflectance", 0.5).unwrap();
+        scene
+            .scale_material_property("transmittance", 100.)
+            .unwrap();
+        for (surface, old) in scene.get_surfaces().iter().zip(&before) {
+            let material = surface.material();
+            assert!((material.reflectance() - old.reflectance() * 0.5).abs() < 1e-6);
+            let expected = if old.transmittance() > 0. { 1. } else { 0. };
+            assert!((material.transmittance() - expected).abs() < 1e-6);
+        }
+
+        let err = scene.scale_material_property("shine", 2.).unwrap_err();
+        assert!(
+            err.contains("'shine'") && err.contains("roughness, exponent"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn normal_debug_ignores_lights() {
+        let mut scene = material_preview("normals", diffuse(Color::zero()), false);
+        scene.override_materials(None, &override_material("normal-debug").unwrap());
+        let (width, height) = scene.get_dimensions();
+        // the center of the preview sphere faces the camera
+        let color = scene.trace_pixel(width / 2, height / 2);
+        assert!(color[2] > 0.9 && (color[0] - 0.5).abs() < 0.1);
+    }
+
+    /// FNV-1a hash of the 8 bit pixels of `colors`
+    fn checksum(colors: &[Color]) -> u64 {
+        colors
+            .iter()
+            .flat_map(|c| c.to_rgb())
+            .fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+            })
+    }
+
+    #[test]
+    fn builtin_scenes_render_deterministically() {
+        // renders of the scenes at 64x64 with seed 42, update them when a scene or the renderer
+        // changes on purpose
+        let golden = [
+            ("cornell", 0xe6e5_3141_4353_81ab),
+            ("spheres", 0x8960_ce0f_bcd3_3889),
+            ("caustic", 0x6d34_f073_db7c_f21c),
+            ("julia", 0x8d78_6431_2898_ca2d),
+        ];
+        assert_eq!(golden.map(|(name, _)| name), BUILTIN_SCENES);
+        for (name, expected) in golden {
+            let mut scene = builtin_scene(name, (64, 64)).unwrap();
+            scene.set_seed(42);
+            let first = render(&scene);
+            let second = render(&scene);
+
+            assert_eq!(first, second, "builtin scene '{name}' is not deterministic");
+            assert!(
+                first.iter().any(|c| *c != first[0]),
+                "builtin scene '{name}' rendered a uniform image"
+            );
+            assert_eq!(
+                checksum(&first),
+                expected,
+                "builtin scene '{name}' rendered a different image"
+            );
+        }
+    }
+
+    /// write a scene with a material library that has a solid and a textured material into the
+    /// temporary directory `dir`
+    /// Returns the path of the scene file
+    fn write_preview_library(dir: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        // vertical stripes, so a rotation of the sphere is visible
+        let stripes = (0..16 * 16)
+            .map(|i| if i % 4 < 2 { [255, 0, 0] } else { [0, 0, 255] })
+            .collect();
+        Image::from_pixels(16, 16, stripes)
+            .save_png(&mut dir.join("stripes.png"))
+            .unwrap();
+        let shading = r#"
+            <phong ka="0.3" kd="0.9" ks="0.5" exponent="20"/>
+            <reflectance r="0.2"/>
+            <transmittance t="0.0"/>
+            <refraction iof="1.0"/>
+        "#;
+        std::fs::write(
+            dir.join("materials.xml"),
+            format!(
+                r#"
+                <materials>
+                    <named_material name="solid">
+                        <material_solid><color r="0.2" g="0.8" b="0.3"/>{shading}</material_solid>
+                    </named_material>
+                    <named_material name="textured">
+                        <material_textured><texture name="stripes.png"/>{shading}</material_textured>
+                    </named_material>
+                </materials>
+                "#
+            ),
+        )
+        .unwrap();
+        let scene = dir.join("scene.xml");
+        std::fs::write(
+            &scene,
+            r#"
+            <scene output_file="scene.png">
+                <background_color r="0.0" g="0.0" b="0.0"/>
+                <material_library file="materials.xml"/>
+                <camera>
+                    <position x="0" y="0" z="1"/>
+                    <lookat x="0" y="0" z="0"/>
+                    <up x="0" y="1" z="0"/>
+                    <horizontal_fov angle="45"/>
+                    <resolution horizontal="16" vertical="16"/>
+                    <max_bounces n="1"/>
+                </camera>
+                <lights></lights>
+                <surfaces></surfaces>
+            </scene>
+            "#,
+        )
+        .unwrap();
+        scene
+    }
+
+    #[test]
+    fn material_preview_renders_library_materials() {
+        let scene_path = write_preview_library("rt_material_preview_test");
+        let options = LoadOptions::default();
+        for name in ["solid", "textured"] {
+            let (material, missing) =
+                file_to_material(scene_path.to_str().unwrap(), name, &options).unwrap();
+            assert!(missing.is_empty());
+            let scene = material_preview(name, material, false);
+            assert!(!scene.is_animated());
+            assert_eq!(scene.get_output(), format!("preview_{name}.png"));
+
+            let (width, height) = scene.get_dimensions();
+            let mut img = Image::new(width, height, 1);
+            img.par_init_pixels(0, |(x, y)| {
+                scene
+                    .trace_pixel(*x, Camera::row_from_image(*y, height))
+                    .to_rgb()
+            });
+            let mut path = scene_path.with_file_name(scene.get_output());
+            img.save_png(&mut path).unwrap();
+            assert!(Image::load_png(&path).is_ok());
+        }
+
+        let err = file_to_material(scene_path.to_str().unwrap(), "chrome", &options).unwrap_err();
+        assert!(err.msg().contains("'solid', 'textured'"));
+    }
+
+    #[test]
+    fn turntable_rotates_the_sphere() {
+        let scene_path = write_preview_library("rt_turntable_test");
+        let (material, _) = file_to_material(
+            scene_path.to_str().unwrap(),
+            "textured",
+            &LoadOptions::default(),
+        )
+        .unwrap();
+        let mut scene = material_preview("textured", material, true);
+        assert_eq!(scene.get_frames(), TURNTABLE_FRAMES);
+
+        // a row of pixels across the sphere
+        let (width, height) = scene.get_dimensions();
+        let row = |scene: &Scene| -> Vec<Color> {
+            (width / 4..width * 3 / 4)
+                .map(|u| scene.trace_pixel(u, height / 2))
+                .collect()
+        };
+        let first = row(&scene);
+        scene.set_frame(TURNTABLE_FRAMES / 4);
+        assert_ne!(row(&scene), first);
+        scene.set_frame(0);
+        assert_eq!(row(&scene), first);
+    }
+
+    #[test]
+    fn unknown_builtin_lists_available_scenes() {
+        let err = builtin_scene("teapot", (64, 64)).unwrap_err();
+        assert!(err.msg().contains("teapot"));
+        assert!(BUILTIN_SCENES.iter().all(|name| err.msg().contains(name)));
+    }
+}
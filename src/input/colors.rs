@@ -0,0 +1,185 @@
+//! color attributes of the xml format
+//! Colors can be given as `r`, `g` and `b` components, as a hex code or by a css color name
+
+use serde::{Deserialize, Deserializer};
+
+use crate::math::Color;
+
+/// Css color names that can be used with the `name` attribute, with their 8 bit rgb values
+const CSS_COLORS: [(&str, [u8; 3]); 32] = [
+    ("black", [0x00, 0x00, 0x00]),
+    ("silver", [0xC0, 0xC0, 0xC0]),
+    ("gray", [0x80, 0x80, 0x80]),
+    ("grey", [0x80, 0x80, 0x80]),
+    ("white", [0xFF, 0xFF, 0xFF]),
+    ("maroon", [0x80, 0x00, 0x00]),
+    ("red", [0xFF, 0x00, 0x00]),
+    ("purple", [0x80, 0x00, 0x80]),
+    ("fuchsia", [0xFF, 0x00, 0xFF]),
+    ("magenta", [0xFF, 0x00, 0xFF]),
+    ("green", [0x00, 0x80, 0x00]),
+    ("lime", [0x00, 0xFF, 0x00]),
+    ("olive", [0x80, 0x80, 0x00]),
+    ("yellow", [0xFF, 0xFF, 0x00]),
+    ("navy", [0x00, 0x00, 0x80]),
+    ("blue", [0x00, 0x00, 0xFF]),
+    ("teal", [0x00, 0x80, 0x80]),
+    ("aqua", [0x00, 0xFF, 0xFF]),
+    ("cyan", [0x00, 0xFF, 0xFF]),
+    ("orange", [0xFF, 0xA5, 0x00]),
+    ("gold", [0xFF, 0xD7, 0x00]),
+    ("khaki", [0xF0, 0xE6, 0x8C]),
+    ("brown", [0xA5, 0x2A, 0x2A]),
+    ("chocolate", [0xD2, 0x69, 0x1E]),
+    ("tan", [0xD2, 0xB4, 0x8C]),
+    ("beige", [0xF5, 0xF5, 0xDC]),
+    ("ivory", [0xFF, 0xFF, 0xF0]),
+    ("pink", [0xFF, 0xC0, 0xCB]),
+    ("salmon", [0xFA, 0x80, 0x72]),
+    ("coral", [0xFF, 0x7F, 0x50]),
+    ("skyblue", [0x87, 0xCE, 0xEB]),
+    ("indigo", [0x4B, 0x00, 0x82]),
+];
+
+/// Color element with either all three components, a `hex` code or a css color `name`
+/// If more than one form is given, the components take precedence over the hex code, and the hex
+/// code over the name
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct SerialColor {
+    #[serde(rename = "@r")]
+    pub r: Option<f32>,
+    #[serde(rename = "@g")]
+    pub g: Option<f32>,
+    #[serde(rename = "@b")]
+    pub b: Option<f32>,
+    #[serde(rename = "@hex")]
+    pub hex: Option<String>,
+    #[serde(rename = "@name")]
+    pub name: Option<String>,
+}
+
+impl SerialColor {
+    /// Convert to a color
+    /// 8 bit hex and named colors are mapped to the range 0 to 1
+    /// Fails on missing components, malformed hex codes and unknown names
+    pub fn convert_to_color(self) -> Result<Color, String> {
+        match (self.r, self.g, self.b) {
+            (Some(r), Some(g), Some(b)) => return Ok(Color::new(r, g, b)),
+            (None, None, None) => {}
+            _ => return Err("A color needs all of the 'r', 'g' and 'b' attributes".to_string()),
+        }
+        if let Some(hex) = self.hex {
+            return parse_hex(&hex).map(rgb_to_color);
+        }
+        if let Some(name) = self.name {
+            return CSS_COLORS
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+                .map(|(_, rgb)| rgb_to_color(*rgb))
+                .ok_or(format!("Unknown color name '{name}'"));
+        }
+        Err("A color needs either 'r', 'g' and 'b', 'hex' or 'name' attributes".to_string())
+    }
+}
+
+/// Map an 8 bit rgb value to a color with components in range 0 to 1
+fn rgb_to_color(rgb: [u8; 3]) -> Color {
+    let [r, g, b] = rgb.map(|c| f32::from(c) / 255.);
+    Color::new(r, g, b)
+}
+
+/// Parse a hex color code of the form `RRGGBB` or `RGB`, with an optional leading `#`
+fn parse_hex(code: &str) -> Result<[u8; 3], String> {
+    let err = || format!("Invalid hex color '{code}'");
+    let digits = code.strip_prefix('#').unwrap_or(code);
+    if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(err());
+    }
+    let digit = |i: usize| u8::from_str_radix(&digits[i..=i], 16).map_err(|_| err());
+    match digits.len() {
+        3 => Ok([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17]),
+        6 => Ok([
+            digit(0)? * 16 + digit(1)?,
+            digit(2)? * 16 + digit(3)?,
+            digit(4)? * 16 + digit(5)?,
+        ]),
+        _ => Err(err()),
+    }
+}
+
+/// Deserialize a color element in any of the forms of `SerialColor`
+pub(super) fn deserialize_color<'de, D>(deserializer: D) -> Result<Color, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    SerialColor::deserialize(deserializer)?
+        .convert_to_color()
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(code: &str) -> Result<Color, String> {
+        SerialColor {
+            hex: Some(code.to_string()),
+            ..Default::default()
+        }
+        .convert_to_color()
+    }
+
+    #[test]
+    fn hex_codes() {
+        let khaki = rgb_to_color([0xF0, 0xE6, 0x8C]);
+        assert_eq!(hex("#F0E68C"), Ok(khaki));
+        assert_eq!(hex("f0e68c"), Ok(khaki));
+        assert_eq!(hex("#fa0"), hex("#FFAA00"));
+        assert_eq!(hex("fff"), Ok(Color::new(1., 1., 1.)));
+
+        for invalid in ["", "#", "#F0E68", "F0E68C0", "#GGGGGG", "ff\u{e9}"] {
+            assert!(hex(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn color_names() {
+        let name = |name: &str| {
+            SerialColor {
+                name: Some(name.to_string()),
+                ..Default::default()
+            }
+            .convert_to_color()
+        };
+        assert_eq!(name("khaki"), hex("#F0E68C"));
+        assert_eq!(name("Khaki"), hex("#F0E68C"));
+        let err = name("khakii").unwrap_err();
+        assert!(err.contains("khakii"), "{err}");
+    }
+
+    #[test]
+    fn color_form_precedence() {
+        #[derive(Deserialize)]
+        struct Element {
+            #[serde(deserialize_with = "deserialize_color")]
+            color: Color,
+        }
+        let parse = |attributes: &str| {
+            quick_xml::de::from_str::<Element>(&format!("<e><color {attributes}/></e>"))
+                .map(|e| e.color)
+        };
+
+        assert_eq!(
+            parse(r##"r="0.1" g="0.2" b="0.3" hex="#FFFFFF" name="red""##).unwrap(),
+            Color::new(0.1, 0.2, 0.3)
+        );
+        assert_eq!(
+            parse(r##"hex="#FFFFFF" name="red""##).unwrap(),
+            Color::new(1., 1., 1.)
+        );
+        assert_eq!(parse(r#"name="red""#).unwrap(), Color::new(1., 0., 0.));
+        // incomplete components are not silently replaced by the other forms
+        assert!(parse(r##"r="0.1" hex="#FFFFFF""##).is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,303 @@
+//! compatibility
+//! Scene files can state the version of the scene format they were written for with
+//! `<scene version="..">`. Files of older versions are upgraded to the current version before
+//! they are parsed, by applying the shims of every later version. Files without a version are
+//! read as the current version
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader, Writer,
+};
+
+/// Version of the scene format that is read without any shims
+pub const SCENE_FORMAT_VERSION: u32 = 2;
+
+/// Change to the elements of a scene file
+enum Shim {
+    /// Set `attribute` of every `element` to `value`, if the element doesn't have it
+    DefaultAttribute {
+        element: &'static str,
+        attribute: &'static str,
+        value: &'static str,
+    },
+    /// Rename the attribute `from` of every `element` to `to`
+    RenameAttribute {
+        element: &'static str,
+        from: &'static str,
+        to: &'static str,
+    },
+    /// Add the empty element `child` with `attributes` to every `element` without one
+    DefaultChild {
+        element: &'static str,
+        child: &'static str,
+        attributes: &'static [(&'static str, &'static str)],
+    },
+}
+
+/// Shims for the changes of the scene format, with the version that introduced the change and a
+/// description for the upgrade notice
+const SHIMS: [(u32, Shim, &str); 3] = [
+    (
+        2,
+        Shim::DefaultAttribute {
+            element: "phong",
+            attribute: "exponent",
+            value: "1",
+        },
+        "phong exponent defaults to 1",
+    ),
+    (
+        2,
+        Shim::RenameAttribute {
+            element: "refraction",
+            from: "ior",
+            to: "iof",
+        },
+        "refraction ior is now iof",
+    ),
+    (
+        2,
+        Shim::DefaultChild {
+            element: "spot_light",
+            child: "falloff",
+            attributes: &[("alpha1", "89"), ("alpha2", "90")],
+        },
+        "spot lights without falloff light the half space in front of them",
+    ),
+];
+
+/// Return the version of the scene format that `content` states on its root element
+/// Returns `None` if the root element has no version
+///
+/// # Errors
+///
+/// Returns a message if the version is not a positive integer, or newer than the current version
+fn scene_version(content: &str) -> Result<Option<u32>, String> {
+    let mut reader = Reader::from_str(content);
+    let root = loop {
+        match reader.read_event().map_err(|err| err.to_string())? {
+            Event::Start(tag) | Event::Empty(tag) => break tag,
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    };
+    let Some(version) = root
+        .try_get_attribute("version")
+        .map_err(|err| err.to_string())?
+    else {
+        return Ok(None);
+    };
+    let version = String::from_utf8_lossy(&version.value).into_owned();
+    match version.trim().parse() {
+        Ok(0) | Err(_) => Err(format!(
+            "Invalid scene format version '{version}', expected a positive integer"
+        )),
+        Ok(v) if v > SCENE_FORMAT_VERSION => Err(format!(
+            "The scene was written for version {v} of the scene format, but this renderer only \
+             supports versions up to {SCENE_FORMAT_VERSION}. Update the renderer to load it"
+        )),
+        Ok(v) => Ok(Some(v)),
+    }
+}
+
+/// Apply the attribute shims to the element `tag`
+/// Returns the changed element, or `None` if no shim applies to it
+fn shim_attributes(
+    tag: &BytesStart,
+    shims: &[&(u32, Shim, &'static str)],
+    applied: &mut Vec<&'static str>,
+) -> Result<Option<BytesStart<'static>>, String> {
+    let name = String::from_utf8_lossy(tag.name().as_ref()).into_owned();
+    let mut attributes = tag
+        .attributes()
+        .map(|a| {
+            let a = a.map_err(|err| err.to_string())?;
+            let key = String::from_utf8_lossy(a.key.as_ref()).into_owned();
+            let value = a.unescape_value().map_err(|err| err.to_string())?;
+            Ok((key, value.into_owned()))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut changed = false;
+    for (_, shim, description) in shims {
+        let shimmed = match shim {
+            Shim::DefaultAttribute {
+                element,
+                attribute,
+                value,
+            } if *element == name && attributes.iter().all(|(k, _)| k != attribute) => {
+                attributes.push(((*attribute).to_string(), (*value).to_string()));
+                true
+            }
+            Shim::RenameAttribute { element, from, to } if *element == name => {
+                let renamed = attributes.iter_mut().find(|(k, _)| k == from);
+                renamed.map(|(k, _)| *k = (*to).to_string()).is_some()
+            }
+            _ => false,
+        };
+        if shimmed {
+            changed = true;
+            if !applied.contains(description) {
+                applied.push(description);
+            }
+        }
+    }
+    Ok(changed.then(|| {
+        let mut upgraded = BytesStart::new(name);
+        upgraded.extend_attributes(attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        upgraded
+    }))
+}
+
+/// Upgrade `content` from the scene format `version` to the current version
+/// Returns the upgraded file and the descriptions of the shims that changed it
+fn apply_shims(content: &str, version: u32) -> Result<(String, Vec<&'static str>), String> {
+    let shims: Vec<_> = SHIMS.iter().filter(|(v, _, _)| *v > version).collect();
+    let mut reader = Reader::from_str(content);
+    let mut writer = Writer::new(Vec::with_capacity(content.len()));
+    let mut applied = Vec::new();
+    // open elements with the names of their children
+    let mut open: Vec<(String, Vec<String>)> = Vec::new();
+    let write_err = |err: std::io::Error| err.to_string();
+    loop {
+        let event = reader.read_event().map_err(|err| err.to_string())?;
+        match &event {
+            Event::Start(tag) | Event::Empty(tag) => {
+                let name = String::from_utf8_lossy(tag.name().as_ref()).into_owned();
+                if let Some((_, children)) = open.last_mut() {
+                    children.push(name.clone());
+                }
+                if matches!(event, Event::Start(_)) {
+                    open.push((name, Vec::new()));
+                }
+                if let Some(upgraded) = shim_attributes(tag, &shims, &mut applied)? {
+                    let upgraded = match event {
+                        Event::Start(_) => Event::Start(upgraded),
+                        _ => Event::Empty(upgraded),
+                    };
+                    writer.write_event(upgraded).map_err(write_err)?;
+                    continue;
+                }
+            }
+            Event::End(_) => {
+                let (name, children) = open.pop().unwrap_or_default();
+                for (_, shim, description) in &shims {
+                    if let Shim::DefaultChild {
+                        element,
+                        child,
+                        attributes,
+                    } = shim
+                    {
+                        if *element == name && !children.iter().any(|c| c == child) {
+                            let mut tag = BytesStart::new(*child);
+                            tag.extend_attributes(attributes.iter().copied());
+                            writer.write_event(Event::Empty(tag)).map_err(write_err)?;
+                            if !applied.contains(description) {
+                                applied.push(description);
+                            }
+                        }
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        writer.write_event(event).map_err(write_err)?;
+    }
+    let upgraded = String::from_utf8(writer.into_inner()).map_err(|err| err.to_string())?;
+    Ok((upgraded, applied))
+}
+
+/// Upgrade the scene file `content` to the current version of the scene format
+/// Returns `None` if the file already has the current version or doesn't state one, otherwise
+/// the upgraded file and a one line notice of what was changed
+///
+/// # Errors
+///
+/// Returns a message if the version is invalid or newer than the current version, or the file is
+/// not well formed
+pub(super) fn upgrade(content: &str) -> Result<Option<(String, String)>, String> {
+    let version = match scene_version(content)? {
+        Some(version) if version < SCENE_FORMAT_VERSION => version,
+        _ => return Ok(None),
+    };
+    let (upgraded, applied) = apply_shims(content, version)?;
+    let changes = if applied.is_empty() {
+        "nothing had to be changed".to_string()
+    } else {
+        applied.join(", ")
+    };
+    let notice = format!(
+        "the scene was written for version {version} of the scene format and was upgraded to \
+         version {SCENE_FORMAT_VERSION} ({changes}). Update the file and set version=\"{SCENE_FORMAT_VERSION}\" \
+         to skip the upgrade"
+    );
+    Ok(Some((upgraded, notice)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::input::{file_to_scene, LoadOptions};
+
+    /// Load the fixture of the scene format `version`
+    fn fixture(version: u32) -> crate::objects::Scene {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(format!("scenes/compat/version{version}.xml"));
+        let options = LoadOptions {
+            strict_schema: true,
+            ..LoadOptions::default()
+        };
+        file_to_scene(path.to_str().unwrap(), &options).unwrap()
+    }
+
+    #[test]
+    fn every_version_loads_the_same_scene() {
+        let current = fixture(SCENE_FORMAT_VERSION);
+        let (width, height) = current.get_dimensions();
+        for version in 1..SCENE_FORMAT_VERSION {
+            let old = fixture(version);
+            assert_eq!(old.get_dimensions(), (width, height));
+            for (u, v) in (0..width).flat_map(|u| (0..height).map(move |v| (u, v))) {
+                assert_eq!(old.trace_pixel(u, v), current.trace_pixel(u, v), "{u}, {v}");
+            }
+        }
+    }
+
+    #[test]
+    fn shims_fill_defaults_and_rename_attributes() {
+        let old = r#"<scene version="1" output_file="a.png">
+            <phong ka="0.1" kd="0.9" ks="0.2"/>
+            <phong ka="0.1" kd="0.9" ks="0.2" exponent="20"/>
+            <refraction ior="1.5"/>
+            <spot_light><color r="1" g="1" b="1"/></spot_light>
+            <spot_light><falloff alpha1="10" alpha2="20"/></spot_light>
+        </scene>"#;
+        let (upgraded, notice) = upgrade(old).unwrap().unwrap();
+        assert!(upgraded.contains(r#"<phong ka="0.1" kd="0.9" ks="0.2" exponent="1"/>"#));
+        assert!(upgraded.contains(r#"<phong ka="0.1" kd="0.9" ks="0.2" exponent="20"/>"#));
+        assert!(upgraded.contains(r#"<refraction iof="1.5"/>"#));
+        assert!(upgraded.contains(
+            r#"<color r="1" g="1" b="1"/><falloff alpha1="89" alpha2="90"/></spot_light>"#
+        ));
+        assert_eq!(upgraded.matches("<falloff").count(), 2);
+        assert!(notice.contains("version 1") && notice.contains("phong exponent"));
+        assert!(!notice.contains('\n'));
+
+        // the current version and files without a version are not changed
+        assert!(upgrade(r#"<scene version="2" output_file="a.png"/>"#)
+            .unwrap()
+            .is_none());
+        assert!(upgrade(r#"<scene output_file="a.png"/>"#)
+            .unwrap()
+            .is_none());
+
+        let err = upgrade(r#"<scene version="3" output_file="a.png"/>"#).unwrap_err();
+        assert!(
+            err.contains("version 3") && err.contains("Update the renderer"),
+            "{err}"
+        );
+        assert!(upgrade(r#"<scene version="one" output_file="a.png"/>"#).is_err());
+    }
+}
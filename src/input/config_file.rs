@@ -0,0 +1,159 @@
+//! config file
+//! Reads default values for the commandline options from a small subset of toml: one
+//! `key = value` pair per line with strings, numbers, booleans or flat arrays of them as values,
+//! and `#` comments. Tables are not supported
+
+/// Value of a key in the config file
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum ConfigValue {
+    Bool(bool),
+    /// strings without their quotes, numbers as written and arrays joined with commas
+    Text(String),
+}
+
+/// Remove a trailing comment from `line`, `#` inside of strings is kept
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Parse a single string, number or boolean
+fn parse_scalar(value: &str) -> Option<ConfigValue> {
+    let value = value.trim();
+    if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        let mut text = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            text.push(match c {
+                '\\' => match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                },
+                '"' => return None,
+                c => c,
+            });
+        }
+        return Some(ConfigValue::Text(text));
+    }
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        return (!inner.contains('\'')).then(|| ConfigValue::Text(inner.to_string()));
+    }
+    match value {
+        "true" => Some(ConfigValue::Bool(true)),
+        "false" => Some(ConfigValue::Bool(false)),
+        // numbers are passed on as written and checked by the option they belong to
+        _ if value.parse::<f64>().is_ok() => Some(ConfigValue::Text(value.to_string())),
+        _ => None,
+    }
+}
+
+/// Parse a scalar or a flat array of scalars, the elements of arrays are joined with commas
+/// Arrays have to be written on a single line
+fn parse_value(value: &str) -> Option<ConfigValue> {
+    let value = value.trim();
+    let Some(inner) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) else {
+        return parse_scalar(value);
+    };
+    let elements = inner
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty())
+        .map(|e| match parse_scalar(e)? {
+            ConfigValue::Text(text) if !text.contains(',') => Some(text),
+            ConfigValue::Text(_) | ConfigValue::Bool(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(ConfigValue::Text(elements.join(",")))
+}
+
+/// Parse the content of a config file into its keys and values, in the order of the file
+/// Keys are returned as written, later keys should override earlier ones
+///
+/// # Errors
+///
+/// Returns a message with the line number if a line is not a valid `key = value` pair
+pub(super) fn parse(text: &str) -> Result<Vec<(String, ConfigValue)>, String> {
+    let mut pairs = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let number = number + 1;
+        if line.starts_with('[') {
+            return Err(format!("Line {number}: tables are not supported"));
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!(
+                "Line {number}: expected 'key = value' but got '{line}'"
+            ));
+        };
+        let key = key.trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(format!("Line {number}: missing key"));
+        }
+        let value = parse_value(value)
+            .ok_or_else(|| format!("Line {number}: invalid value for '{key}'"))?;
+        pairs.push((key.to_string(), value));
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> ConfigValue {
+        ConfigValue::Text(value.to_string())
+    }
+
+    #[test]
+    fn parse_flat_toml() {
+        let pairs = parse(
+            r#"
+            # defaults for every render
+            outdir = "renders # final"  # the comment is dropped
+            jobs = 4
+            progress_bar = true
+            max-seconds = 1.5e2
+            layers = ["hero", 'env']
+            ppm = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            pairs,
+            [
+                ("outdir".to_string(), text("renders # final")),
+                ("jobs".to_string(), text("4")),
+                ("progress_bar".to_string(), ConfigValue::Bool(true)),
+                ("max-seconds".to_string(), text("1.5e2")),
+                ("layers".to_string(), text("hero,env")),
+                ("ppm".to_string(), ConfigValue::Bool(false)),
+            ]
+        );
+
+        assert!(parse("[render]\njobs = 2").unwrap_err().contains("Line 1"));
+        assert!(parse("jobs = 2\noutdir = renders")
+            .unwrap_err()
+            .contains("Line 2"));
+        assert!(parse("jobs").is_err());
+        assert!(parse("= 2").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! environment variables
+//! Expands `${VAR}` placeholders in paths of scenes and the commandline
+
+use std::env;
+
+/// Replace every `${VAR}` in `text` with the value of the environment variable `VAR`
+/// A `$` that is not followed by `{` is kept as it is
+///
+/// # Errors
+///
+/// Returns a message listing all unset variables, or if a placeholder is not closed
+pub(super) fn expand_env_vars(text: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut unset = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(format!("Unclosed '${{' in '{text}'"));
+        };
+        let name = &rest[start + 2..start + 2 + len];
+        match env::var(name) {
+            Ok(value) => expanded.push_str(&value),
+            Err(_) if !unset.contains(&name) => unset.push(name),
+            Err(_) => {}
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+
+    if unset.is_empty() {
+        Ok(expanded)
+    } else {
+        Err(format!(
+            "'{text}' uses the unset environment variables: {}. Set them or turn off the \
+             expansion with --no-env-expansion",
+            unset.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    /// Serializes the tests that change the environment
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Sets environment variables for the duration of a test and removes them when dropped
+    pub(in crate::input) struct EnvGuard {
+        names: Vec<&'static str>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl EnvGuard {
+        pub(in crate::input) fn set(vars: &[(&'static str, &str)]) -> EnvGuard {
+            let lock = ENV_LOCK
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            for (name, value) in vars {
+                env::set_var(name, value);
+            }
+            EnvGuard {
+                names: vars.iter().map(|(name, _)| *name).collect(),
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for name in &self.names {
+                env::remove_var(name);
+            }
+        }
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let _env = EnvGuard::set(&[("RT_TEST_JOB", "job42"), ("RT_TEST_ROOT", "/assets")]);
+        assert_eq!(
+            expand_env_vars("${RT_TEST_JOB}/beauty.png").unwrap(),
+            "job42/beauty.png"
+        );
+        assert_eq!(
+            expand_env_vars("${RT_TEST_ROOT}/${RT_TEST_JOB}/${RT_TEST_JOB}.obj").unwrap(),
+            "/assets/job42/job42.obj"
+        );
+        assert_eq!(expand_env_vars("$5 and $HOME").unwrap(), "$5 and $HOME");
+
+        let err = expand_env_vars(
+            "${RT_TEST_UNSET_A}/${RT_TEST_JOB}/${RT_TEST_UNSET_B}/${RT_TEST_UNSET_A}",
+        )
+        .unwrap_err();
+        assert!(err.contains("RT_TEST_UNSET_A, RT_TEST_UNSET_B."), "{err}");
+        assert!(expand_env_vars("${RT_TEST_JOB").is_err());
+    }
+}
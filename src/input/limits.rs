@@ -0,0 +1,67 @@
+//! limits
+//! Messages for scenes that exceed the triangle or texture limits, with an estimate of the memory
+//! they would need
+
+use std::mem::size_of;
+
+use crate::objects::Triangle;
+
+/// Bytes of a texture pixel, including the smaller mip levels
+const TEXTURE_PIXEL_BYTES: u64 = 4;
+
+/// Format a number of bytes with a binary unit
+#[allow(clippy::cast_precision_loss)]
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = "B";
+    for next in UNITS {
+        if size < 1024. {
+            break;
+        }
+        size /= 1024.;
+        unit = next;
+    }
+    format!("{size:.1} {unit}")
+}
+
+/// Message for a mesh with `count` triangles, when only `allowed` more fit into the limit
+pub(super) fn too_many_triangles(count: usize, allowed: usize) -> String {
+    let bytes = u64::try_from(count.saturating_mul(size_of::<Triangle>())).unwrap_or(u64::MAX);
+    format!(
+        "The mesh has {count} triangles, but only {allowed} more are allowed by the triangle \
+         limit. Loading it would need about {} of memory. Raise the limit with --max-triangles \
+         or reduce the mesh with --decimate-to",
+        format_bytes(bytes)
+    )
+}
+
+/// Message for a texture of `width` x `height` pixels, when at most `limit` pixels are allowed
+pub(super) fn too_many_pixels(width: u32, height: u32, limit: u64) -> String {
+    let pixels = u64::from(width) * u64::from(height);
+    format!(
+        "The texture has {width}x{height} = {pixels} pixels, but at most {limit} are allowed. \
+         Loading it would need about {} of memory. Raise the limit with --max-texture-pixels",
+        format_bytes(pixels.saturating_mul(TEXTURE_PIXEL_BYTES))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_state_limit_count_and_memory() {
+        assert_eq!(format_bytes(512), "512.0 B");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024 / 2), "1.5 GiB");
+
+        let msg = too_many_triangles(500_000_000, 50_000_000);
+        assert!(msg.contains("500000000 triangles"));
+        assert!(msg.contains("only 50000000 more"));
+        assert!(msg.contains("GiB"));
+
+        let msg = too_many_pixels(65536, 65536, 1 << 28);
+        assert!(msg.contains("65536x65536 = 4294967296 pixels"));
+        assert!(msg.contains("16.0 GiB"));
+    }
+}
@@ -2,12 +2,25 @@
 //! responsible for receiving and parsing input files
 
 mod arguments;
+mod builtin;
+mod colors;
+mod compat;
+mod config_file;
+mod env_vars;
+mod limits;
 mod objparser;
+mod paths;
+mod plyparser;
+mod schema;
 mod serial_types;
+mod settings;
+mod template;
 mod xml;
 
 use std::fmt::Display;
 
+use crate::misc::log;
+
 /// Struct for any kind of input error
 /// (includes, commandline arguments, xml, and obj parsing)
 #[derive(Debug)]
@@ -21,19 +34,56 @@ impl InputError {
     pub fn new(title: String, msg: String) -> InputError {
         InputError { title, msg }
     }
+
+    /// Return the title of the error
+    #[must_use]
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Return the message of the error
+    #[must_use]
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
 }
 
 const ERROR_COLOR: &str = "\x1b[31m";
-const RESET: &str = "\x1b[0m";
 
 impl Display for InputError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!(
-            "{}:\n    {ERROR_COLOR}{}{RESET}",
-            self.title, self.msg
+            "{}:\n    {}",
+            self.title,
+            log::colored(ERROR_COLOR, &self.msg)
         ))
     }
 }
 
 pub use arguments::Config;
+pub use builtin::{
+    builtin_scene, material_preview, override_material, BUILTIN_SCENES, OVERRIDE_MATERIALS,
+};
+pub use compat::SCENE_FORMAT_VERSION;
+pub use settings::{OutputFormat, RenderSettings};
+pub use template::{output_template_path, OutputVars};
 pub use xml::*;
+
+#[cfg(fuzzing)]
+pub use objparser::fuzz_parse;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::env_vars::tests::EnvGuard;
+
+    #[test]
+    fn no_color_env_disables_escape_codes() {
+        let _env = EnvGuard::set(&[("NO_COLOR", "1")]);
+        let err = InputError::new("Title".to_string(), "message".to_string());
+
+        let out = err.to_string();
+        assert!(!out.contains("\x1b["));
+        assert_eq!(out, "Title:\n    message");
+    }
+}
@@ -1,30 +1,96 @@
+use std::{io::BufRead, str::SplitAsciiWhitespace};
+
 use crate::{math::Point3, objects::Triangle};
 
-use super::InputError;
+use super::{limits, InputError};
 
 /// three positive integers
 type Triple = (u32, u32, u32);
 
+/// Number of skipped lines above which the report contains a warning
+const SKIPPED_LINES_WARNING_THRESHOLD: usize = 10;
+
+/// Summary of what was read from a `.obj` file
+#[derive(Debug, Default, PartialEq)]
+pub struct ParseReport {
+    /// number of triangles that were read
+    pub triangles: usize,
+    /// line number and directive of lines with directives that are not supported
+    pub skipped_lines: Vec<(usize, String)>,
+}
+
+impl ParseReport {
+    /// Return a warning listing the skipped directives of the file `name`, if more lines than
+    /// the threshold were skipped
+    #[must_use]
+    pub fn warning(&self, name: &str) -> Option<String> {
+        if self.skipped_lines.len() <= SKIPPED_LINES_WARNING_THRESHOLD {
+            return None;
+        }
+        // count the lines per directive, in order of their first occurence
+        let mut directives: Vec<(&str, usize)> = Vec::new();
+        for (_, directive) in &self.skipped_lines {
+            match directives.iter_mut().find(|(d, _)| d == directive) {
+                Some((_, count)) => *count += 1,
+                None => directives.push((directive, 1)),
+            }
+        }
+        let directives = directives
+            .iter()
+            .map(|(d, count)| format!("'{d}' ({count})"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Some(format!(
+            "Read {} triangles from '{name}', but skipped {} lines with unsupported directives: {directives}",
+            self.triangles,
+            self.skipped_lines.len(),
+        ))
+    }
+}
+
+/// Average number of bytes per line of a `.obj` file, used to estimate the size of the mesh
+const BYTES_PER_LINE: usize = 32;
+
 /// parses a `.obj` file to a list of triangles
-pub fn parse(src: &str) -> Result<Vec<Triangle>, InputError> {
-    let mut vertices = Vec::new();
-    let mut normals = Vec::new();
+/// The file is read line by line from `reader`, `size_hint` is the expected size of the file in
+/// bytes and is used to reserve memory for the mesh up front (0 if unknown)
+/// Lines with unsupported directives are skipped and listed in the report, comments are ignored
+/// Files with more than `max_triangles` faces are rejected. Once the limit is reached, the rest
+/// of the file is only counted, so the error can report the size of the mesh
+pub fn parse(
+    mut reader: impl BufRead,
+    size_hint: usize,
+    max_triangles: usize,
+) -> Result<(Vec<Triangle>, ParseReport), InputError> {
+    // roughly a quarter of the lines are vertices and normals, half of them faces
+    let lines = size_hint / BYTES_PER_LINE;
+    let mut vertices = Vec::with_capacity(lines / 4);
+    let mut normals = Vec::with_capacity(lines / 4);
     let mut texture = Vec::new();
-    let mut triangles = Vec::new();
+    let mut triangles = Vec::with_capacity((lines / 2).min(max_triangles));
+    let mut skipped_lines = Vec::new();
+    let mut skipped_faces = 0;
 
-    for (current_line, line) in src.lines().enumerate() {
-        let mut words = line.split_whitespace();
+    let mut line = String::new();
+    for current_line in 0.. {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => return Err(err(current_line, &e.to_string())),
+        }
+        let mut words = line.split_ascii_whitespace();
         if let Some(t) = words.next() {
-            let args: Vec<_> = words.collect();
             match t {
-                "v" => vertices.push(parse_point(&args).map_err(|s| err(current_line, &s))?),
-                "vn" => normals.push(parse_point(&args).map_err(|s| err(current_line, &s))?),
+                "v" => vertices.push(parse_point(&mut words).map_err(|s| err(current_line, &s))?),
+                "vn" => normals.push(parse_point(&mut words).map_err(|s| err(current_line, &s))?),
                 "vt" => {
-                    texture.push(parse_texel(&args).map_err(|s| err(current_line, &s))?);
+                    texture.push(parse_texel(&mut words).map_err(|s| err(current_line, &s))?);
                 }
+                "f" if triangles.len() == max_triangles => skipped_faces += 1,
                 "f" => {
                     let (verts, tex, norm) =
-                        parse_face(&args).map_err(|s| err(current_line, &s))?;
+                        parse_face(&mut words).map_err(|s| err(current_line, &s))?;
 
                     let texcoords = if tex == (0, 0, 0) {
                         [(0., 0.); 3]
@@ -39,39 +105,75 @@ pub fn parse(src: &str) -> Result<Vec<Triangle>, InputError> {
                     );
                     triangles.push(tri);
                 }
-                _ => {}
+                _ if t.starts_with('#') => {}
+                _ => skipped_lines.push((current_line + 1, t.to_string())),
             }
         }
     }
 
-    Ok(triangles)
+    if skipped_faces > 0 {
+        return Err(InputError::new(
+            String::new(),
+            limits::too_many_triangles(triangles.len() + skipped_faces, max_triangles),
+        ));
+    }
+
+    let report = ParseReport {
+        triangles: triangles.len(),
+        skipped_lines,
+    };
+    Ok((triangles, report))
+}
+
+/// Entry point for fuzzing the parser
+/// Any input has to be either parsed or rejected with an error, but never cause a panic
+#[cfg(fuzzing)]
+pub fn fuzz_parse(data: &[u8]) {
+    let _ = parse(
+        String::from_utf8_lossy(data).as_bytes(),
+        data.len(),
+        usize::MAX,
+    );
 }
 
-/// Get 3 elements from a slice using a triple of indices
+/// Get 3 elements from a slice using a triple of 1-based indices
 fn get_elements<T>(from: &[T], indices: Triple) -> Result<[T; 3], String>
 where
     T: Copy,
 {
-    Ok([
-        *from
-            .get((indices.0 - 1) as usize)
-            .ok_or(format!("Invalid index {} for face data", indices.0))?,
-        *from
-            .get((indices.1 - 1) as usize)
-            .ok_or(format!("Invalid index {} for face data", indices.1))?,
-        *from
-            .get((indices.2 - 1) as usize)
-            .ok_or(format!("Invalid index {} for face data", indices.2))?,
-    ])
+    let get = |index: u32| {
+        index
+            .checked_sub(1)
+            .and_then(|i| from.get(i as usize))
+            .copied()
+            .ok_or_else(|| format!("Invalid index {index} for face data"))
+    };
+    Ok([get(indices.0)?, get(indices.1)?, get(indices.2)?])
+}
+
+/// Take exactly `N` arguments from the rest of the line
+fn take_args<'a, const N: usize>(
+    words: &mut SplitAsciiWhitespace<'a>,
+) -> Result<[&'a str; N], String> {
+    let mut args = [""; N];
+    let mut count = 0;
+    for word in words {
+        if count < N {
+            args[count] = word;
+        }
+        count += 1;
+    }
+    if count != N {
+        return Err(format!("Expected {N} elements but got {count}"));
+    }
+    Ok(args)
 }
 
 /// parse a face line in the format:
 /// `v/vt/vn v/vt/vn v/vt/vn`
 /// where `v` is the vertex index, `vt` is the texture index and `vn` is the normal index
-fn parse_face(line: &[&str]) -> Result<(Triple, Triple, Triple), String> {
-    if line.len() != 3 {
-        return Err(format!("Expected 3 elements but got {}", line.len()));
-    }
+fn parse_face(words: &mut SplitAsciiWhitespace) -> Result<(Triple, Triple, Triple), String> {
+    let line = take_args::<3>(words)?;
 
     let mut vertices = [0, 0, 0];
     let mut texture = [0, 0, 0];
@@ -84,17 +186,17 @@ fn parse_face(line: &[&str]) -> Result<(Triple, Triple, Triple), String> {
             return Err(String::from("Face data contains more than 3 elements"));
         }
         vertices[i] = v
-            .ok_or(String::from("Expected vertices data"))?
+            .ok_or_else(|| String::from("Expected vertices data"))?
             .parse::<u32>()
             .map_err(|r| r.to_string())?;
 
         texture[i] = t
-            .ok_or(String::from("Expected texture coordinate data"))?
+            .ok_or_else(|| String::from("Expected texture coordinate data"))?
             .parse::<u32>()
             .unwrap_or_default();
 
         normals[i] = n
-            .ok_or(String::from("Expected normal data"))?
+            .ok_or_else(|| String::from("Expected normal data"))?
             .parse::<u32>()
             .map_err(|r| r.to_string())?;
     }
@@ -103,32 +205,30 @@ fn parse_face(line: &[&str]) -> Result<(Triple, Triple, Triple), String> {
 }
 
 /// parse a single point in the format: `x y z`
-fn parse_point(line: &[&str]) -> Result<Point3, String> {
-    if line.len() != 3 {
-        return Err(format!("Expected 3 elements but got {}", line.len()));
-    }
-
-    let (x, y, z) = (&line[0], &line[1], &line[2]);
+fn parse_point(words: &mut SplitAsciiWhitespace) -> Result<Point3, String> {
+    let [x, y, z] = take_args(words)?;
 
     Ok(Point3::new(
-        x.parse::<f32>().map_err(|r| r.to_string())?,
-        y.parse::<f32>().map_err(|r| r.to_string())?,
-        z.parse::<f32>().map_err(|r| r.to_string())?,
+        parse_float(x)?,
+        parse_float(y)?,
+        parse_float(z)?,
     ))
 }
 
 /// parse a texel in the format: `u v`
-fn parse_texel(line: &[&str]) -> Result<(f32, f32), String> {
-    if line.len() != 2 {
-        return Err(format!("Expected 2 elements but got {}", line.len()));
-    }
+fn parse_texel(words: &mut SplitAsciiWhitespace) -> Result<(f32, f32), String> {
+    let [u, v] = take_args(words)?;
 
-    let (u, v) = (&line[0], &line[1]);
+    Ok((parse_float(u)?, parse_float(v)?))
+}
 
-    Ok((
-        u.parse::<f32>().map_err(|r| r.to_string())?,
-        v.parse::<f32>().map_err(|r| r.to_string())?,
-    ))
+/// parse a finite number, `nan` and values that overflow to infinity are rejected
+fn parse_float(word: &str) -> Result<f32, String> {
+    let value = word.parse::<f32>().map_err(|r| r.to_string())?;
+    if !value.is_finite() {
+        return Err(format!("Expected a finite number but got '{word}'"));
+    }
+    Ok(value)
 }
 
 /// construct an appropriate error message
@@ -141,7 +241,8 @@ fn err(current_line: usize, msg: &str) -> InputError {
 
 #[cfg(test)]
 mod tests {
-    use crate::math::Vec3;
+    use crate::math::{Color, Vec3};
+    use crate::objects::{Material, ShadingModel, Surface, Texture};
 
     use super::*;
 
@@ -170,11 +271,19 @@ mod tests {
         "
         .to_string();
 
-        let mesh = parse(&filecontents);
+        let mesh = parse(filecontents.as_bytes(), filecontents.len(), usize::MAX);
 
         assert!(mesh.is_ok());
 
-        let triangles = mesh.unwrap();
+        let (triangles, report) = mesh.unwrap();
+        assert_eq!(
+            report,
+            ParseReport {
+                triangles: 2,
+                skipped_lines: vec![(13, "usemtl".to_string()), (14, "s".to_string())],
+            }
+        );
+        assert!(report.warning("plane.obj").is_none());
 
         let expected = vec![
             Triangle::new(
@@ -207,4 +316,159 @@ mod tests {
 
         assert!(vec_cmp(&triangles, &expected));
     }
+
+    #[test]
+    fn fuzz_corpus_does_not_panic() {
+        let corpus = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/objparser");
+        let mut entries = 0;
+        for entry in std::fs::read_dir(corpus).unwrap() {
+            let data = std::fs::read(entry.unwrap().path()).unwrap();
+            // a parsed mesh has to be usable for rendering too
+            if let Ok((triangles, _)) = parse(
+                String::from_utf8_lossy(&data).as_bytes(),
+                data.len(),
+                usize::MAX,
+            ) {
+                let material = Material::new(
+                    Texture::Color(Color::zero()),
+                    0.,
+                    0.,
+                    1.,
+                    ShadingModel::Phong {
+                        ka: 0.,
+                        kd: 1.,
+                        ks: 0.,
+                        exp: 1,
+                    },
+                );
+                let _ = Surface::mesh(triangles, material);
+            }
+            entries += 1;
+        }
+        assert!(entries > 0);
+    }
+
+    #[test]
+    fn non_finite_coordinates_are_errors() {
+        let lines = [
+            "v nan 0 -3",
+            "v 0 inf 0",
+            "v 1e39 0 0",
+            "vn 0 0 -inf",
+            "vt NaN 0",
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            let err = parse(format!("v 0 0 0\n{line}").as_bytes(), 0, usize::MAX).unwrap_err();
+            assert!(
+                err.msg().starts_with("Error on line 2: "),
+                "{i}: {}",
+                err.msg()
+            );
+            assert!(err.msg().contains("finite"), "{i}: {}", err.msg());
+        }
+    }
+
+    #[test]
+    fn zero_and_huge_face_indices_are_errors() {
+        let header = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\n";
+        assert!(parse(
+            format!("{header}f 1//1 2//1 3//1").as_bytes(),
+            0,
+            usize::MAX
+        )
+        .is_ok());
+        let err = parse(
+            format!("{header}f 0//1 2//1 3//1").as_bytes(),
+            0,
+            usize::MAX,
+        )
+        .unwrap_err();
+        assert!(err.msg().contains("Invalid index 0"));
+        assert!(parse(
+            format!("{header}f 1//1 2//1 4294967295//1").as_bytes(),
+            0,
+            usize::MAX
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn many_skipped_lines_are_reported() {
+        let parts = (0..6)
+            .map(|i| format!("o part{i}\ng group{i}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let filecontents = format!(
+            "mtllib scene.mtl\n# comment\n{parts}\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n"
+        );
+
+        let (triangles, report) = parse(filecontents.as_bytes(), 0, usize::MAX).unwrap();
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(report.skipped_lines.len(), 13);
+        assert_eq!(report.skipped_lines[1], (3, "o".to_string()));
+        assert_eq!(
+            report.warning("parts.obj").unwrap(),
+            "Read 1 triangles from 'parts.obj', but skipped 13 lines with unsupported directives: \
+             'mtllib' (1), 'o' (6), 'g' (6)"
+        );
+    }
+
+    /// Write an `n` x `n` grid of quads with vertex normals as obj
+    fn grid_obj(n: usize, out: &mut impl std::io::Write) {
+        for y in 0..=n {
+            for x in 0..=n {
+                writeln!(out, "v {x}.000000 {y}.000000 0.{}00000", x * y % 7).unwrap();
+                writeln!(out, "vn 0.000000 0.000000 1.000000").unwrap();
+            }
+        }
+        for y in 0..n {
+            for x in 0..n {
+                let (a, b) = (y * (n + 1) + x + 1, (y + 1) * (n + 1) + x + 1);
+                writeln!(out, "f {a}//{a} {}//{} {b}//{b}", a + 1, a + 1).unwrap();
+                writeln!(out, "f {}//{} {}//{} {b}//{b}", a + 1, a + 1, b + 1, b + 1).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn parse_synthetic_grid() {
+        let mut src = Vec::new();
+        grid_obj(20, &mut src);
+        let (triangles, report) = parse(src.as_slice(), src.len(), usize::MAX).unwrap();
+        assert_eq!(triangles.len(), 800);
+        assert_eq!(report.triangles, 800);
+        assert!(report.skipped_lines.is_empty());
+
+        assert!(parse(src.as_slice(), src.len(), 800).is_ok());
+        let err = parse(src.as_slice(), src.len(), 500).unwrap_err();
+        assert!(err
+            .msg()
+            .contains("The mesh has 800 triangles, but only 500 more"));
+    }
+
+    /// Benchmark for large meshes, run with `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore = "benchmark, writes and parses a ~100 MB file"]
+    fn bench_parse_large_mesh() {
+        let path = std::env::temp_dir().join(format!("rt_bench_{}.obj", std::process::id()));
+        let mut out = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        grid_obj(850, &mut out);
+        drop(out);
+
+        let file = std::fs::File::open(&path).unwrap();
+        let size = usize::try_from(file.metadata().unwrap().len()).unwrap();
+        let start = std::time::Instant::now();
+        let (triangles, _) = parse(std::io::BufReader::new(file), size, usize::MAX).unwrap();
+        let elapsed = start.elapsed();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(triangles.len(), 2 * 850 * 850);
+        #[allow(clippy::cast_precision_loss)]
+        let mb = size as f64 / 1e6;
+        println!(
+            "parsed {mb:.0} MB ({} triangles) in {elapsed:?}, {:.0} MB/s",
+            triangles.len(),
+            mb / elapsed.as_secs_f64()
+        );
+    }
 }
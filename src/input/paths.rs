@@ -0,0 +1,67 @@
+//! paths
+//! Scene files and the commandline may be written on another platform than the one that renders,
+//! so both `/` and `\` are accepted as separators in the paths they contain
+
+use std::path::{Path, PathBuf};
+
+/// Convert `path` to a ``PathBuf`` of the current platform, splitting it at `/` and `\`
+/// A leading separator is kept, so absolute paths stay absolute
+#[cfg(windows)]
+pub(super) fn portable_path(path: &str) -> PathBuf {
+    // windows already accepts both separators, and handles drive letters
+    PathBuf::from(path)
+}
+
+/// Convert `path` to a ``PathBuf`` of the current platform, splitting it at `/` and `\`
+/// A leading separator is kept, so absolute paths stay absolute
+#[cfg(not(windows))]
+pub(super) fn portable_path(path: &str) -> PathBuf {
+    let mut result = PathBuf::new();
+    if path.starts_with(['/', '\\']) {
+        result.push(std::path::MAIN_SEPARATOR_STR);
+    }
+    for component in path.split(['/', '\\']).filter(|c| !c.is_empty()) {
+        result.push(component);
+    }
+    result
+}
+
+/// Join `path` onto the directory `base`, both may use either separator
+pub(super) fn join(base: &str, path: &str) -> PathBuf {
+    let path = portable_path(path);
+    if path.has_root() {
+        return path;
+    }
+    let base = portable_path(base);
+    if base.as_os_str().is_empty() {
+        path
+    } else {
+        Path::join(&base, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expected(components: &[&str]) -> PathBuf {
+        components.iter().collect()
+    }
+
+    #[test]
+    fn paths_accept_both_separators() {
+        let out = expected(&["renders", "job", "beauty.png"]);
+        assert_eq!(join("renders", "job/beauty.png"), out);
+        assert_eq!(join("renders", "job\\beauty.png"), out);
+        assert_eq!(join("renders/job", "beauty.png"), out);
+        assert_eq!(join("renders\\job\\", "beauty.png"), out);
+        assert_eq!(join("", "job/beauty.png"), expected(&["job", "beauty.png"]));
+
+        let root = std::path::MAIN_SEPARATOR_STR;
+        assert_eq!(
+            join("renders", "/tmp\\beauty.png"),
+            expected(&[root, "tmp", "beauty.png"])
+        );
+        assert!(portable_path("\\assets/mesh.obj").has_root());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{
+    math::{Point3, Vec3},
+    objects::{Texel, Triangle},
+};
+
+use super::{limits, InputError};
+
+/// a property of an element as declared in the header
+enum Property {
+    Scalar(String),
+    List(String),
+}
+
+/// an element as declared in the header, e.g. `element vertex 8`
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    /// Return the position of the scalar property with one of the given names
+    fn scalar(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|p| matches!(p, Property::Scalar(n) if names.contains(&n.as_str())))
+    }
+}
+
+/// a single vertex with its optional normal and texture coordinates
+#[derive(Clone, Copy)]
+struct Vertex {
+    position: Point3,
+    normal: Option<Vec3>,
+    texel: Texel,
+}
+
+/// parses an ascii `.ply` file to a list of triangles
+/// Supports the `vertex` element with `x`, `y`, `z` (and optionally `nx`, `ny`, `nz` and `u`, `v`
+/// or `s`, `t`) properties and the `face` element with a `vertex_indices` list
+/// Faces with more than 3 vertices are split into triangles. Without vertex normals, the face
+/// normal is used
+/// Files with more than `max_triangles` triangles are rejected, the number of faces in the header
+/// is checked before any of them are read
+pub fn parse_ply(src: &str, max_triangles: usize) -> Result<Vec<Triangle>, InputError> {
+    let mut lines = src
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i, l.trim()))
+        .filter(|(_, l)| !l.is_empty());
+
+    let elements = parse_header(&mut lines)?;
+    let too_many = |count| {
+        InputError::new(
+            String::new(),
+            limits::too_many_triangles(count, max_triangles),
+        )
+    };
+    let faces = elements
+        .iter()
+        .filter(|e| e.name == "face")
+        .fold(0usize, |acc, e| acc.saturating_add(e.count));
+    if faces > max_triangles {
+        return Err(too_many(faces));
+    }
+
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            let (current_line, line) = lines.next().ok_or_else(|| {
+                err(
+                    src.lines().count(),
+                    &format!("Expected {} '{}' elements", element.count, element.name),
+                )
+            })?;
+            let values: Vec<_> = line.split_whitespace().collect();
+            match element.name.as_str() {
+                "vertex" => vertices
+                    .push(parse_vertex(element, &values).map_err(|s| err(current_line, &s))?),
+                "face" => parse_face(element, &values, &vertices, &mut triangles)
+                    .map_err(|s| err(current_line, &s))?,
+                _ => {}
+            }
+        }
+    }
+
+    // faces with more than 3 vertices can still exceed the limit
+    if triangles.len() > max_triangles {
+        return Err(too_many(triangles.len()));
+    }
+    Ok(triangles)
+}
+
+/// parse the header up to and including `end_header`
+fn parse_header<'a, I>(lines: &mut I) -> Result<Vec<Element>, InputError>
+where
+    I: Iterator<Item = (usize, &'a str)>,
+{
+    match lines.next() {
+        Some((_, "ply")) => {}
+        Some((current_line, _)) => return Err(err(current_line, "Expected 'ply'")),
+        None => return Err(err(0, "File is empty")),
+    }
+
+    let mut elements: Vec<Element> = Vec::new();
+    for (current_line, line) in lines.by_ref() {
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] | ["comment" | "obj_info", ..] => {}
+            ["format", format, _] => {
+                return Err(err(
+                    current_line,
+                    &format!("Unsupported format '{format}', only ascii is supported"),
+                ))
+            }
+            ["element", name, count] => elements.push(Element {
+                name: (*name).to_string(),
+                count: count
+                    .parse()
+                    .map_err(|e: std::num::ParseIntError| err(current_line, &e.to_string()))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", _, _, name] | ["property", _, name] => {
+                let property = if words[1] == "list" {
+                    Property::List((*name).to_string())
+                } else {
+                    Property::Scalar((*name).to_string())
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| err(current_line, "Property before any element"))?
+                    .properties
+                    .push(property);
+            }
+            ["end_header"] => return Ok(elements),
+            _ => return Err(err(current_line, &format!("Invalid header line '{line}'"))),
+        }
+    }
+
+    Err(err(0, "Missing 'end_header'"))
+}
+
+/// parse the values of a single vertex
+fn parse_vertex(element: &Element, values: &[&str]) -> Result<Vertex, String> {
+    if element
+        .properties
+        .iter()
+        .any(|p| matches!(p, Property::List(_)))
+    {
+        return Err(String::from(
+            "List properties on vertices are not supported",
+        ));
+    }
+    if values.len() != element.properties.len() {
+        return Err(format!(
+            "Expected {} elements but got {}",
+            element.properties.len(),
+            values.len()
+        ));
+    }
+    let get = |names: &[&str]| -> Result<Option<f32>, String> {
+        element
+            .scalar(names)
+            .map(|i| values[i].parse::<f32>().map_err(|r| r.to_string()))
+            .transpose()
+    };
+    let position = match (get(&["x"])?, get(&["y"])?, get(&["z"])?) {
+        (Some(x), Some(y), Some(z)) => Point3::new(x, y, z),
+        _ => return Err(String::from("Vertex needs x, y and z properties")),
+    };
+    let normal = match (get(&["nx"])?, get(&["ny"])?, get(&["nz"])?) {
+        (Some(x), Some(y), Some(z)) => Some(Vec3::new(x, y, z)),
+        _ => None,
+    };
+    let texel = (
+        get(&["u", "s"])?.unwrap_or_default(),
+        get(&["v", "t"])?.unwrap_or_default(),
+    );
+
+    Ok(Vertex {
+        position,
+        normal,
+        texel,
+    })
+}
+
+/// parse a face and append its triangles
+/// The face is split into a fan of triangles around its first vertex
+fn parse_face(
+    element: &Element,
+    values: &[&str],
+    vertices: &[Vertex],
+    triangles: &mut Vec<Triangle>,
+) -> Result<(), String> {
+    if element.properties.len() != 1
+        || !matches!(&element.properties[0], Property::List(n) if n == "vertex_indices" || n == "vertex_index")
+    {
+        return Err(String::from(
+            "Faces need exactly one 'vertex_indices' list property",
+        ));
+    }
+    let (count, indices) = values
+        .split_first()
+        .ok_or_else(|| String::from("Expected face data"))?;
+    let count = count.parse::<usize>().map_err(|r| r.to_string())?;
+    if count < 3 || indices.len() != count {
+        return Err(format!(
+            "Expected at least 3 and exactly {count} indices but got {}",
+            indices.len()
+        ));
+    }
+    let face = indices
+        .iter()
+        .map(|i| {
+            let i = i.parse::<usize>().map_err(|r| r.to_string())?;
+            vertices
+                .get(i)
+                .copied()
+                .ok_or_else(|| format!("Invalid index {i} for face data"))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    for i in 1..count - 1 {
+        let corners = [face[0], face[i], face[i + 1]];
+        let points = corners.map(|v| v.position);
+        let face_normal = (points[1] - points[0]).cross(&(points[2] - points[0]));
+        // skip degenerate triangles, they can't be hit anyway
+        if face_normal.length_squared() == 0. {
+            continue;
+        }
+        let face_normal = Vec3::normal(&face_normal);
+        triangles.push(Triangle::new(
+            points,
+            corners.map(|v| v.normal.unwrap_or(face_normal)),
+            corners.map(|v| v.texel),
+        ));
+    }
+    Ok(())
+}
+
+/// construct an appropriate error message
+fn err(current_line: usize, msg: &str) -> InputError {
+    InputError::new(
+        String::new(),
+        format!("Error on line {}: {msg}", current_line + 1),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_ply_expect_plane_triangles() {
+        let filecontents = r"
+            ply
+            format ascii 1.0
+            comment a unit square in the xy plane
+            element vertex 4
+            property float x
+            property float y
+            property float z
+            element face 2
+            property list uchar int vertex_indices
+            end_header
+            0 0 0
+            1 0 0
+            1 1 0
+            0 1 0
+            3 0 1 2
+            3 0 2 3
+        ";
+
+        let triangles = parse_ply(filecontents, usize::MAX).unwrap();
+
+        let normal = Vec3::new(0., 0., 1.);
+        let expected = [
+            Triangle::new(
+                [
+                    Point3::new(0., 0., 0.),
+                    Point3::new(1., 0., 0.),
+                    Point3::new(1., 1., 0.),
+                ],
+                [normal; 3],
+                [(0., 0.); 3],
+            ),
+            Triangle::new(
+                [
+                    Point3::new(0., 0., 0.),
+                    Point3::new(1., 1., 0.),
+                    Point3::new(0., 1., 0.),
+                ],
+                [normal; 3],
+                [(0., 0.); 3],
+            ),
+        ];
+        assert_eq!(triangles.len(), expected.len());
+        assert!(triangles.iter().zip(&expected).all(|(l, r)| l == r));
+
+        // a quad is split into the same two triangles
+        let quad = filecontents
+            .replace("element face 2", "element face 1")
+            .replace("3 0 1 2\n", "4 0 1 2 3\n")
+            .replace("3 0 2 3\n", "");
+        let triangles = parse_ply(&quad, usize::MAX).unwrap();
+        assert!(triangles.iter().zip(&expected).all(|(l, r)| l == r));
+
+        let err = parse_ply(filecontents, 1).unwrap_err();
+        assert!(err
+            .msg()
+            .contains("The mesh has 2 triangles, but only 1 more"));
+        let err = parse_ply(&quad, 1).unwrap_err();
+        assert!(err.msg().contains("The mesh has 2 triangles"));
+    }
+
+    #[test]
+    fn parse_ply_rejects_invalid_files() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        let vertices = "0 0 0\n1 0 0\n0 1 0\n";
+
+        assert!(parse_ply(&format!("{header}{vertices}3 0 1 2\n"), usize::MAX).is_ok());
+        // index out of range
+        assert!(parse_ply(&format!("{header}{vertices}3 0 1 3\n"), usize::MAX).is_err());
+        // missing face
+        assert!(parse_ply(&format!("{header}{vertices}"), usize::MAX).is_err());
+        // binary files are not supported
+        let binary = header.replace("ascii", "binary_little_endian");
+        assert!(parse_ply(&format!("{binary}{vertices}3 0 1 2\n"), usize::MAX).is_err());
+    }
+}
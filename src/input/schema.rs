@@ -0,0 +1,768 @@
+//! schema of the xml scene format
+//! Mirrors `scenes/scene.dtd`, so scene files can be checked for unknown elements and attributes
+//! before they are deserialized. serde ignores unknown elements, so a typo would otherwise only
+//! show up as a missing object
+
+use quick_xml::{events::Event, Reader};
+
+/// An element of the scene format with the attributes and child elements it may have
+struct ElementSchema {
+    name: &'static str,
+    attributes: &'static [&'static str],
+    children: &'static [&'static str],
+}
+
+const COLOR: &[&str] = &["r", "g", "b", "hex", "name"];
+const XYZ: &[&str] = &["x", "y", "z"];
+const XYZW: &[&str] = &["x", "y", "z", "w"];
+const BACKGROUND_KINDS: &[&str] = &["color", "gradient", "environment"];
+const MESH_CHILDREN: &[&str] = &[
+    "material_solid",
+    "material_textured",
+    "material_gradient",
+    "material_ref",
+    "transform",
+    "clip",
+];
+const MESH_ATTRIBUTES: &[&str] = &["name", "smooth_shading", "both_sides", "layer"];
+
+/// Every element of the scene format
+/// Has to be kept in sync with `scenes/scene.dtd`, a test compares both
+const SCHEMA: &[ElementSchema] = &[
+    ElementSchema {
+        name: "scene",
+        attributes: &["output_file", "active_camera", "version"],
+        children: &[
+            "background_color",
+            "background",
+            "material_library",
+            "textures",
+            "super_sampling",
+            "ray_differentials",
+            "output",
+            "animated",
+            "camera",
+            "cameras",
+            "camera_switch",
+            "lights",
+            "surfaces",
+        ],
+    },
+    ElementSchema {
+        name: "material_library",
+        attributes: &["file"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "textures",
+        attributes: &["max_size"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "background_color",
+        attributes: COLOR,
+        children: &[],
+    },
+    ElementSchema {
+        name: "background",
+        attributes: &[],
+        children: &["color", "gradient", "environment", "secondary"],
+    },
+    ElementSchema {
+        name: "secondary",
+        attributes: &[],
+        children: BACKGROUND_KINDS,
+    },
+    ElementSchema {
+        name: "gradient",
+        attributes: &[],
+        children: &["bottom", "top"],
+    },
+    ElementSchema {
+        name: "bottom",
+        attributes: COLOR,
+        children: &[],
+    },
+    ElementSchema {
+        name: "top",
+        attributes: COLOR,
+        children: &[],
+    },
+    ElementSchema {
+        name: "environment",
+        attributes: &["name"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "super_sampling",
+        attributes: &["samples"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "ray_differentials",
+        attributes: &[],
+        children: &[],
+    },
+    ElementSchema {
+        name: "output",
+        attributes: &["gamma", "template"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "animated",
+        attributes: &["frames", "fps", "loops", "pingpong"],
+        children: &["delay"],
+    },
+    ElementSchema {
+        name: "delay",
+        attributes: &["frame", "ms"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "cameras",
+        attributes: &[],
+        children: &["camera"],
+    },
+    ElementSchema {
+        name: "camera_switch",
+        attributes: &["frame"],
+        children: &["camera"],
+    },
+    ElementSchema {
+        name: "camera",
+        attributes: &["name"],
+        children: &[
+            "position",
+            "lookat",
+            "up",
+            "horizontal_fov",
+            "depth_of_field",
+            "resolution",
+            "max_bounces",
+        ],
+    },
+    ElementSchema {
+        name: "position",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "lookat",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "up",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "horizontal_fov",
+        attributes: &["angle"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "depth_of_field",
+        attributes: &["focal_length", "aperture"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "resolution",
+        attributes: &["horizontal", "vertical"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "max_bounces",
+        attributes: &["n", "at_limit"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "lights",
+        attributes: &[],
+        children: &[
+            "ambient_light",
+            "point_light",
+            "parallel_light",
+            "spot_light",
+            "sun",
+        ],
+    },
+    ElementSchema {
+        name: "ambient_light",
+        attributes: &[],
+        children: &["color", "ground", "direction"],
+    },
+    ElementSchema {
+        name: "ground",
+        attributes: COLOR,
+        children: &[],
+    },
+    ElementSchema {
+        name: "point_light",
+        attributes: &["radius", "samples"],
+        children: &["color", "position"],
+    },
+    ElementSchema {
+        name: "sun",
+        attributes: &[
+            "latitude",
+            "longitude",
+            "date",
+            "time",
+            "utc_offset",
+            "turbidity",
+        ],
+        children: &[],
+    },
+    ElementSchema {
+        name: "parallel_light",
+        attributes: &[],
+        children: &["color", "direction"],
+    },
+    ElementSchema {
+        name: "spot_light",
+        attributes: &[],
+        children: &["color", "position", "direction", "falloff", "profile"],
+    },
+    ElementSchema {
+        name: "color",
+        attributes: COLOR,
+        children: &[],
+    },
+    ElementSchema {
+        name: "direction",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "falloff",
+        attributes: &["alpha1", "alpha2"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "profile",
+        attributes: &["values"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "surfaces",
+        attributes: &[],
+        children: &[
+            "sphere",
+            "ellipsoid",
+            "disc",
+            "quad",
+            "mesh",
+            "ply",
+            "julia_set",
+            "scatter",
+        ],
+    },
+    ElementSchema {
+        name: "scatter",
+        attributes: &[
+            "count",
+            "seed",
+            "surface_ref",
+            "scale_min",
+            "scale_max",
+            "align_to_normal",
+        ],
+        children: &[
+            "sphere",
+            "ellipsoid",
+            "disc",
+            "quad",
+            "mesh",
+            "ply",
+            "julia_set",
+        ],
+    },
+    ElementSchema {
+        name: "sphere",
+        attributes: &["radius", "endradius", "layer"],
+        children: &[
+            "position",
+            "endposition",
+            "material_solid",
+            "material_textured",
+            "material_gradient",
+            "material_ref",
+            "transform",
+            "clip",
+        ],
+    },
+    ElementSchema {
+        name: "ellipsoid",
+        attributes: &["rx", "ry", "rz", "layer"],
+        children: &[
+            "position",
+            "material_solid",
+            "material_textured",
+            "material_gradient",
+            "material_ref",
+            "transform",
+            "clip",
+        ],
+    },
+    ElementSchema {
+        name: "disc",
+        attributes: &["radius", "both_sides", "layer"],
+        children: &[
+            "position",
+            "normal",
+            "material_solid",
+            "material_textured",
+            "material_gradient",
+            "material_ref",
+            "transform",
+            "clip",
+        ],
+    },
+    ElementSchema {
+        name: "normal",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "quad",
+        attributes: &["both_sides", "layer"],
+        children: &[
+            "corner",
+            "edge_u",
+            "edge_v",
+            "waves",
+            "material_solid",
+            "material_textured",
+            "material_gradient",
+            "material_ref",
+            "transform",
+            "clip",
+        ],
+    },
+    ElementSchema {
+        name: "corner",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "edge_u",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "edge_v",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "endposition",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "mesh",
+        attributes: MESH_ATTRIBUTES,
+        children: MESH_CHILDREN,
+    },
+    ElementSchema {
+        name: "ply",
+        attributes: MESH_ATTRIBUTES,
+        children: MESH_CHILDREN,
+    },
+    ElementSchema {
+        name: "julia_set",
+        attributes: &["max_iteration", "epsilon", "layer"],
+        children: &[
+            "position",
+            "constant",
+            "endconstant",
+            "material_solid",
+            "transform",
+            "clip",
+        ],
+    },
+    ElementSchema {
+        name: "constant",
+        attributes: XYZW,
+        children: &[],
+    },
+    ElementSchema {
+        name: "endconstant",
+        attributes: XYZW,
+        children: &[],
+    },
+    ElementSchema {
+        name: "material_solid",
+        attributes: &[],
+        children: &[
+            "color",
+            "phong",
+            "cook_torrance",
+            "reflectance",
+            "transmittance",
+            "refraction",
+            "thin_film",
+            "clearcoat",
+        ],
+    },
+    ElementSchema {
+        name: "material_textured",
+        attributes: &[],
+        children: &[
+            "texture",
+            "phong",
+            "cook_torrance",
+            "reflectance",
+            "transmittance",
+            "refraction",
+            "thin_film",
+            "clearcoat",
+        ],
+    },
+    ElementSchema {
+        name: "material_gradient",
+        attributes: &["axis"],
+        children: &[
+            "stop",
+            "phong",
+            "cook_torrance",
+            "reflectance",
+            "transmittance",
+            "refraction",
+            "thin_film",
+            "clearcoat",
+        ],
+    },
+    ElementSchema {
+        name: "stop",
+        attributes: &["position", "r", "g", "b", "hex", "name"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "material_ref",
+        attributes: &["name"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "phong",
+        attributes: &["ka", "kd", "ks", "exponent"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "cook_torrance",
+        attributes: &["ka", "ks", "roughness"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "reflectance",
+        attributes: &["r", "roughness", "samples"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "transmittance",
+        attributes: &["t"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "refraction",
+        attributes: &["iof"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "thin_film",
+        attributes: &["thickness", "ior", "endthickness"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "clearcoat",
+        attributes: &["strength", "roughness"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "waves",
+        attributes: &["amplitude", "frequency", "speed"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "texture",
+        attributes: &["name", "builtin", "cells", "line_width"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "clip",
+        attributes: &[],
+        children: &["plane"],
+    },
+    ElementSchema {
+        name: "plane",
+        attributes: &["px", "py", "pz", "nx", "ny", "nz"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "transform",
+        attributes: &[],
+        children: &["translate", "scale", "rotateX", "rotateY", "rotateZ"],
+    },
+    ElementSchema {
+        name: "translate",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "scale",
+        attributes: XYZ,
+        children: &[],
+    },
+    ElementSchema {
+        name: "rotateX",
+        attributes: &["theta"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "rotateY",
+        attributes: &["theta"],
+        children: &[],
+    },
+    ElementSchema {
+        name: "rotateZ",
+        attributes: &["theta"],
+        children: &[],
+    },
+];
+
+/// Return the schema of the element `name`
+fn element(name: &str) -> Option<&'static ElementSchema> {
+    SCHEMA.iter().find(|e| e.name == name)
+}
+
+/// Return the line and column of the byte `offset` in `content`, both starting at 1
+fn location(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+        .chars()
+        .count()
+        + 1;
+    (line, column)
+}
+
+/// Convert a position of the xml reader into an offset into the content
+fn offset(position: u64) -> usize {
+    usize::try_from(position).unwrap_or(usize::MAX)
+}
+
+/// Return the name out of `candidates` that is closest to `name`, if it could be a typo
+fn suggestion<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    // levenshtein distance of the two names
+    let distance = |other: &str| {
+        let other: Vec<_> = other.chars().collect();
+        let mut row: Vec<_> = (0..=other.len()).collect();
+        for (i, a) in name.chars().enumerate() {
+            let mut diagonal = row[0];
+            row[0] = i + 1;
+            for (j, b) in other.iter().enumerate() {
+                let substitution = diagonal + usize::from(a != *b);
+                diagonal = row[j + 1];
+                row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+            }
+        }
+        row[other.len()]
+    };
+    candidates
+        .iter()
+        .map(|c| (distance(c), *c))
+        .filter(|&(d, c)| d <= name.len().max(c.len()) / 2)
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Append a hint for the closest of `candidates` to `msg`
+fn with_suggestion(msg: String, name: &str, candidates: &[&str]) -> String {
+    match suggestion(name, candidates) {
+        Some(hint) => format!("{msg}, did you mean '{hint}'?"),
+        None => msg,
+    }
+}
+
+/// Check `content` against the schema of the scene format
+/// Reports every element or attribute that is not part of the schema, and elements that are not
+/// allowed where they appear. The children of unknown elements are not checked
+/// Does not check the order or number of elements, or the values of attributes
+///
+/// # Errors
+///
+/// Returns a list of all problems with their location, one per line, or the error of the xml
+/// reader if the file is not well formed
+pub(super) fn validate(content: &str) -> Result<(), String> {
+    let mut reader = Reader::from_str(content);
+    reader.config_mut().trim_text(true);
+    let mut problems = Vec::new();
+    // schema of the open elements, `None` for unknown elements
+    let mut open: Vec<Option<&ElementSchema>> = Vec::new();
+    loop {
+        let event = reader.read_event().map_err(|err| {
+            let (line, column) = location(content, offset(reader.error_position()));
+            format!("{err} at line {line}, column {column}")
+        })?;
+        let (tag, empty) = match event {
+            Event::Start(tag) => (tag, false),
+            Event::Empty(tag) => (tag, true),
+            Event::End(_) => {
+                open.pop();
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        let end = offset(reader.buffer_position());
+        let (line, column) = location(content, content[..end].rfind('<').unwrap_or(0));
+        let name = String::from_utf8_lossy(tag.name().as_ref()).into_owned();
+        let at = format!("at line {line}, column {column}");
+
+        let schema = match open.last() {
+            // children of unknown elements are not checked
+            Some(None) => None,
+            Some(Some(parent)) if !parent.children.contains(&name.as_str()) => {
+                let msg = match element(&name) {
+                    Some(_) => format!("Element '{name}' is not allowed in '{}' {at}", parent.name),
+                    None => format!("Unknown element '{name}' in '{}' {at}", parent.name),
+                };
+                problems.push(with_suggestion(msg, &name, parent.children));
+                None
+            }
+            Some(Some(_)) => element(&name),
+            None if name == "scene" => element(&name),
+            None => {
+                problems.push(format!(
+                    "Unknown root element '{name}' {at}, expected 'scene'"
+                ));
+                None
+            }
+        };
+        if let Some(schema) = schema {
+            for attribute in tag.attributes().with_checks(false).flatten() {
+                let key = String::from_utf8_lossy(attribute.key.as_ref()).into_owned();
+                if !schema.attributes.contains(&key.as_str()) {
+                    let msg = format!("Unknown attribute '{key}' of '{name}' {at}");
+                    problems.push(with_suggestion(msg, &key, schema.attributes));
+                }
+            }
+        }
+        if !empty {
+            open.push(schema);
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("\n    "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    /// Parse the element and attribute declarations of a dtd into
+    /// `(name, attributes, children)`
+    fn parse_dtd(dtd: &str) -> Vec<(String, Vec<String>, Vec<String>)> {
+        let mut elements: Vec<(String, Vec<String>, Vec<String>)> = Vec::new();
+        for declaration in dtd.split("<!").skip(1) {
+            let declaration = declaration.trim_end().trim_end_matches('>');
+            if let Some(rest) = declaration.strip_prefix("ELEMENT") {
+                let (name, content) = rest.trim().split_once(char::is_whitespace).unwrap();
+                let children = content
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .filter(|word| !word.is_empty() && *word != "EMPTY")
+                    .map(ToString::to_string)
+                    .collect();
+                elements.push((name.to_string(), Vec::new(), children));
+            } else if let Some(rest) = declaration.strip_prefix("ATTLIST") {
+                let mut tokens = rest.split_whitespace();
+                let name = tokens.next().unwrap();
+                let element = elements
+                    .iter_mut()
+                    .find(|e| e.0 == name)
+                    .unwrap_or_else(|| panic!("'{name}' has attributes, but is not declared"));
+                while let Some(attribute) = tokens.next() {
+                    element.1.push(attribute.to_string());
+                    // skip the type, which can be an enumeration with spaces
+                    let kind = tokens.next().unwrap();
+                    if kind.starts_with('(') {
+                        let mut token = kind;
+                        while !token.ends_with(')') {
+                            token = tokens.next().unwrap();
+                        }
+                    }
+                    // skip the default
+                    tokens.next();
+                }
+            }
+        }
+        elements
+    }
+
+    #[test]
+    fn schema_matches_dtd() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/scene.dtd");
+        let dtd = parse_dtd(&fs::read_to_string(path).unwrap());
+        assert_eq!(dtd.len(), SCHEMA.len());
+        for (name, attributes, children) in dtd {
+            let schema = element(&name).unwrap_or_else(|| panic!("'{name}' is missing"));
+            let mut expected = schema.attributes.to_vec();
+            let mut found: Vec<_> = attributes.iter().map(String::as_str).collect();
+            expected.sort_unstable();
+            found.sort_unstable();
+            assert_eq!(expected, found, "attributes of '{name}'");
+
+            let mut expected = schema.children.to_vec();
+            let mut found: Vec<_> = children.iter().map(String::as_str).collect();
+            expected.sort_unstable();
+            found.sort_unstable();
+            found.dedup();
+            assert_eq!(expected, found, "children of '{name}'");
+        }
+    }
+
+    #[test]
+    fn example_scenes_are_valid() {
+        let scenes = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in fs::read_dir(scenes).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "xml") {
+                let content = fs::read_to_string(&path).unwrap();
+                assert_eq!(validate(&content), Ok(()), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn typos_are_reported_with_location() {
+        let xml = r#"<scene output_file="out.png">
+  <surfaces>
+    <spherical radius="1">
+      <positon x="0" y="0" z="0" />
+    </spherical>
+    <sphere radius="1" raduis="2">
+      <color r="1" g="1" b="1" />
+    </sphere>
+  </surfaces>
+</scene>"#;
+        let problems = validate(xml).unwrap_err();
+        let problems: Vec<_> = problems.split("\n    ").collect();
+        assert_eq!(
+            problems,
+            [
+                "Unknown element 'spherical' in 'surfaces' at line 3, column 5, did you mean 'sphere'?",
+                "Unknown attribute 'raduis' of 'sphere' at line 6, column 5, did you mean 'radius'?",
+                "Element 'color' is not allowed in 'sphere' at line 7, column 7",
+            ]
+        );
+
+        assert!(validate("<scene><lights></scene>").is_err());
+        assert!(validate("<scenery/>").unwrap_err().contains("root element"));
+    }
+}
@@ -1,18 +1,43 @@
-use std::{fs, path::PathBuf};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    image::Image,
+    image::{Image, Playback, TransferFunction, UV_GRID_LINE_WIDTH},
     math::{to_radians, Color, Mat4, Point3, Quat, Vec3},
-    objects::{Camera, Light, Material, Scene, ShadingModel, Surface, Texture},
+    misc::{
+        log,
+        solar::{self, SunPosition, UtcTime},
+    },
+    objects::{
+        decimate, surface_ids, Background, BounceLimit, Camera, ClearCoat, ClipPlane, GradientAxis,
+        Hemisphere, Light, Material, Scatter, Scene, ShadingModel, SpotProfile, Surface, Texture,
+        ThinFilm, Triangle, TriangleShading, Waves,
+    },
 };
 use serde::Deserialize;
 
-use super::{objparser::parse, InputError};
+use super::{
+    colors::{deserialize_color, SerialColor},
+    env_vars::expand_env_vars,
+    limits,
+    objparser::parse,
+    paths,
+    plyparser::parse_ply,
+    template::check_template,
+    InputError, LoadOptions, MissingTextures,
+};
 
 // --- Camera serial types ---
 
 #[derive(Debug, Deserialize)]
 pub(super) struct SerialCamera {
+    #[serde(rename = "@name")]
+    name: Option<String>,
     position: Vec3,
     lookat: Vec3,
     up: Vec3,
@@ -48,6 +73,28 @@ pub(super) struct Resolution {
 pub(super) struct MaxBounces {
     #[serde(rename = "@n")]
     n: u32,
+    #[serde(rename = "@at_limit")]
+    #[serde(default)]
+    at_limit: SerialBounceLimit,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum SerialBounceLimit {
+    #[default]
+    Black,
+    Background,
+    Ambient,
+}
+
+impl From<SerialBounceLimit> for BounceLimit {
+    fn from(limit: SerialBounceLimit) -> BounceLimit {
+        match limit {
+            SerialBounceLimit::Black => BounceLimit::Black,
+            SerialBounceLimit::Background => BounceLimit::Background,
+            SerialBounceLimit::Ambient => BounceLimit::Ambient,
+        }
+    }
 }
 
 impl From<SerialCamera> for Camera {
@@ -62,6 +109,7 @@ impl From<SerialCamera> for Camera {
             inp.resolution.vertical,
             inp.max_bounces.n,
         );
+        c.set_bounce_limit(inp.max_bounces.at_limit.into());
         if let Some(dof) = inp.depth_of_field {
             c.add_dof(dof.focal_length, dof.aperture);
         }
@@ -69,16 +117,137 @@ impl From<SerialCamera> for Camera {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub(super) struct CameraList {
+    #[serde(default)]
+    camera: Vec<SerialCamera>,
+}
+
+/// Switch to another camera from `frame` onwards
+#[derive(Debug, Deserialize)]
+pub(super) struct CameraSwitch {
+    #[serde(rename = "@frame")]
+    frame: usize,
+    camera: SerialCamera,
+}
+
+impl SerialCamera {
+    /// Return the name of the camera or a placeholder for unnamed cameras
+    fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("<unnamed>")
+    }
+}
+
+impl CameraSwitch {
+    /// Convert the camera of the switch
+    /// The camera has to have the same `resolution` as the camera of the scene, switches that
+    /// happen after the last of the `frames` frames are never used and only cause a warning
+    fn convert_to_camera(
+        self,
+        resolution: (u32, u32),
+        frames: usize,
+    ) -> Result<Camera, InputError> {
+        let camera_resolution = (
+            self.camera.resolution.horizontal,
+            self.camera.resolution.vertical,
+        );
+        if camera_resolution != resolution {
+            return Err(InputError::new(
+                "Error while loading camera switch".to_string(),
+                format!(
+                    "Camera '{}' at frame {} has a resolution of {}x{}, but the scene is rendered in {}x{}",
+                    self.camera.display_name(),
+                    self.frame,
+                    camera_resolution.0,
+                    camera_resolution.1,
+                    resolution.0,
+                    resolution.1
+                ),
+            ));
+        }
+        if self.frame >= frames {
+            log::warning(&format!(
+                "Camera switch at frame {} is never used, the scene only has {frames} frames",
+                self.frame
+            ))?;
+        }
+        Ok(self.camera.into())
+    }
+}
+
+/// Choose the camera that renders the scene
+/// A scene has either a single `camera` or a list of `cameras`. If `requested` is given, the
+/// camera with that name is chosen, otherwise the first one
+/// Every camera may have its own resolution, but it can't be empty
+fn select_camera(
+    camera: Option<SerialCamera>,
+    cameras: Option<CameraList>,
+    requested: Option<&str>,
+) -> Result<SerialCamera, InputError> {
+    let err = |msg: String| InputError::new("Error while selecting camera".to_string(), msg);
+    let cameras = match (camera, cameras) {
+        (Some(camera), None) => vec![camera],
+        (None, Some(list)) if !list.camera.is_empty() => list.camera,
+        (None, Some(_)) => return Err(err("'cameras' contains no camera".to_string())),
+        (None, None) => return Err(err("Scene has no camera".to_string())),
+        (Some(_), Some(_)) => {
+            return Err(err(
+                "Scene can't have both a 'camera' and a 'cameras' field".to_string(),
+            ))
+        }
+    };
+
+    for (i, cam) in cameras.iter().enumerate() {
+        if cam.resolution.horizontal == 0 || cam.resolution.vertical == 0 {
+            return Err(err(format!(
+                "Camera '{}' has an empty resolution of {}x{}",
+                cam.display_name(),
+                cam.resolution.horizontal,
+                cam.resolution.vertical
+            )));
+        }
+        if cam.name.is_some() && cameras[..i].iter().any(|other| other.name == cam.name) {
+            return Err(err(format!(
+                "Camera name '{}' is used more than once",
+                cam.display_name()
+            )));
+        }
+    }
+
+    let Some(requested) = requested else {
+        return Ok(cameras.into_iter().next().expect("cameras is not empty"));
+    };
+    let names: Vec<_> = cameras
+        .iter()
+        .filter_map(|c| c.name.as_deref())
+        .map(|name| format!("'{name}'"))
+        .collect();
+    let names = if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    };
+    cameras
+        .into_iter()
+        .find(|c| c.name.as_deref() == Some(requested))
+        .ok_or(err(format!(
+            "Camera '{requested}' not found. Available cameras: {names}"
+        )))
+}
+
 // --- Material serial types ---
 
 #[derive(Debug, Deserialize)]
 pub(super) struct MaterialSolid {
+    #[serde(deserialize_with = "deserialize_color")]
     color: Color,
     #[serde(rename = "$value")]
     shading: SerialShadingModel,
     reflectance: Reflectance,
     transmittance: Transmittance,
     refraction: Refraction,
+    thin_film: Option<SerialThinFilm>,
+    clearcoat: Option<SerialClearCoat>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -89,12 +258,137 @@ pub(super) struct MaterialTextured {
     reflectance: Reflectance,
     transmittance: Transmittance,
     refraction: Refraction,
+    thin_film: Option<SerialThinFilm>,
+    clearcoat: Option<SerialClearCoat>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct MaterialGradient {
+    #[serde(rename = "@axis")]
+    #[serde(default)]
+    axis: SerialGradientAxis,
+    #[serde(default)]
+    stop: Vec<GradientStop>,
+    #[serde(rename = "$value")]
+    shading: SerialShadingModel,
+    reflectance: Reflectance,
+    transmittance: Transmittance,
+    refraction: Refraction,
+    thin_film: Option<SerialThinFilm>,
+    clearcoat: Option<SerialClearCoat>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum SerialGradientAxis {
+    #[default]
+    U,
+    V,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct GradientStop {
+    #[serde(rename = "@position")]
+    position: f32,
+    #[serde(rename = "@r")]
+    r: Option<f32>,
+    #[serde(rename = "@g")]
+    g: Option<f32>,
+    #[serde(rename = "@b")]
+    b: Option<f32>,
+    #[serde(rename = "@hex")]
+    hex: Option<String>,
+    #[serde(rename = "@name")]
+    name: Option<String>,
+}
+
+impl GradientStop {
+    /// Convert to the position and color of the stop
+    fn convert_to_stop(self) -> Result<(f32, Color), String> {
+        let color = SerialColor {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+            hex: self.hex,
+            name: self.name,
+        };
+        Ok((self.position, color.convert_to_color()?))
+    }
 }
 
+/// Texture read from a png file `name` or generated from the `builtin` pattern
 #[derive(Debug, Deserialize)]
 pub(super) struct SerialTexture {
     #[serde(rename = "@name")]
-    name: String,
+    name: Option<String>,
+    #[serde(rename = "@builtin")]
+    builtin: Option<String>,
+    #[serde(rename = "@cells")]
+    #[serde(default = "default_grid_cells")]
+    cells: u32,
+    #[serde(rename = "@line_width")]
+    #[serde(default = "default_grid_line_width")]
+    line_width: f32,
+}
+
+fn default_grid_cells() -> u32 {
+    8
+}
+
+fn default_grid_line_width() -> f32 {
+    UV_GRID_LINE_WIDTH
+}
+
+/// Resolution of environment maps generated from builtin textures
+const BUILTIN_ENVIRONMENT_SIZE: (u32, u32) = (2048, 1024);
+
+impl SerialTexture {
+    /// Return the file name of the texture, `None` for the builtin uv grid
+    /// Fails if the texture has neither or both a file and a builtin, the builtin is unknown or
+    /// the grid settings are invalid
+    fn file(&self) -> Result<Option<&str>, InputError> {
+        let err = |msg: String| InputError::new("Error while loading texture".to_string(), msg);
+        match (&self.name, self.builtin.as_deref()) {
+            (Some(name), None) => Ok(Some(name)),
+            (None, Some("uv_grid")) => {
+                if self.cells == 0 || !(0. ..1.).contains(&self.line_width) {
+                    return Err(err(format!(
+                        "The uv grid needs at least one cell and a line_width in [0, 1), got \
+                         cells={} and line_width={}",
+                        self.cells, self.line_width
+                    )));
+                }
+                Ok(None)
+            }
+            (None, Some(builtin)) => Err(err(format!(
+                "Unknown builtin texture '{builtin}', expected uv_grid"
+            ))),
+            _ => Err(err(
+                "A texture needs either a file name or a builtin, but not both".to_string(),
+            )),
+        }
+    }
+
+    /// Load the texture relative to the converted file or generate the builtin one
+    fn convert_to_texture(&self, ctx: &ConversionContext) -> Result<Texture, InputError> {
+        Ok(match self.file()? {
+            Some(name) => Texture::image(ctx.load_texture(name)?),
+            None => Texture::DebugGrid {
+                cells: self.cells,
+                line_width: self.line_width,
+            },
+        })
+    }
+
+    /// Load the texture relative to the converted file as an image, builtin textures are
+    /// rendered into an image of [`BUILTIN_ENVIRONMENT_SIZE`]
+    fn convert_to_image(&self, ctx: &ConversionContext) -> Result<Image, InputError> {
+        if let Some(name) = self.file()? {
+            return ctx.load_texture(name);
+        }
+        let (width, height) = BUILTIN_ENVIRONMENT_SIZE;
+        Ok(Image::uv_grid(width, height, self.cells))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -148,6 +442,28 @@ impl From<SerialShadingModel> for ShadingModel {
 pub(super) struct Reflectance {
     #[serde(rename = "@r")]
     r: f32,
+    #[serde(rename = "@roughness", default)]
+    roughness: f32,
+    #[serde(rename = "@samples")]
+    samples: Option<u32>,
+}
+
+impl Reflectance {
+    /// Give `material` a glossy reflection if the reflectance has a roughness
+    /// Fails if the roughness is not between 0 and 1 or the samples are 0
+    fn apply(&self, material: Material) -> Result<Material, InputError> {
+        if !(0. ..=1.).contains(&self.roughness) || self.samples == Some(0) {
+            return Err(InputError::new(
+                "Invalid reflectance".to_string(),
+                format!(
+                    "The roughness has to be between 0 and 1 and the samples at least 1, got roughness={} and samples={}",
+                    self.roughness,
+                    self.samples.unwrap_or_default()
+                ),
+            ));
+        }
+        Ok(material.with_glossy_reflection(self.roughness, self.samples))
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -162,30 +478,382 @@ pub(super) struct Refraction {
     iof: f32,
 }
 
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialClearCoat {
+    #[serde(rename = "@strength")]
+    strength: f32,
+    #[serde(rename = "@roughness")]
+    roughness: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialThinFilm {
+    #[serde(rename = "@thickness")]
+    thickness: f32,
+    #[serde(rename = "@ior")]
+    ior: f32,
+    #[serde(rename = "@endthickness")]
+    endthickness: Option<f32>,
+}
+
+impl SerialThinFilm {
+    /// Put the thin film on `material`, if there is one
+    /// Fails if a thickness is negative or the index of refraction is smaller than 1
+    fn apply(film: Option<Self>, material: Material) -> Result<Material, InputError> {
+        let Some(film) = film else {
+            return Ok(material);
+        };
+        let end = film.endthickness.unwrap_or(film.thickness);
+        if film.thickness < 0. || end < 0. || film.ior < 1. {
+            return Err(InputError::new(
+                "Invalid thin film".to_string(),
+                format!(
+                    "The thickness has to be at least 0 and the ior at least 1, got thickness={}, endthickness={end} and ior={}",
+                    film.thickness, film.ior
+                ),
+            ));
+        }
+        let thin_film = ThinFilm::new(film.thickness, film.ior);
+        Ok(material.with_thin_film(match film.endthickness {
+            Some(end) => thin_film.with_end(end),
+            None => thin_film,
+        }))
+    }
+}
+
+impl SerialClearCoat {
+    /// Put the clear coat over `material`, if there is one
+    /// Fails if the strength is not between 0 and 1 or the roughness is not between 0 (exclusive)
+    /// and 1
+    fn apply(coat: Option<Self>, material: Material) -> Result<Material, InputError> {
+        let Some(coat) = coat else {
+            return Ok(material);
+        };
+        if !(0. ..=1.).contains(&coat.strength) || coat.roughness <= 0. || coat.roughness > 1. {
+            return Err(InputError::new(
+                "Invalid clear coat".to_string(),
+                format!(
+                    "The strength has to be between 0 and 1 and the roughness between 0 (exclusive) and 1, got strength={} and roughness={}",
+                    coat.strength, coat.roughness
+                ),
+            ));
+        }
+        Ok(material.with_clearcoat(ClearCoat {
+            strength: coat.strength,
+            roughness: coat.roughness,
+        }))
+    }
+}
+
 impl MaterialTextured {
-    fn convert_to_material(self, path: &mut PathBuf) -> Result<Material, InputError> {
-        path.set_file_name(self.texture.name);
-        let image = Image::load_png(path)?;
-        Ok(Material::new(
-            Texture::Image(image),
+    /// Load the texture relative to the converted file and convert to a material
+    /// Textures larger than the size limit of the context are downscaled while loading, builtin
+    /// textures are generated. Texture files that can't be loaded are replaced with a placeholder,
+    /// if the options of the context ask for it
+    fn convert_to_material(self, ctx: &ConversionContext) -> Result<Material, InputError> {
+        let texture = match (self.texture.convert_to_texture(ctx), self.texture.file()?) {
+            (Err(err), Some(name))
+                if ctx.options.missing_textures == MissingTextures::Placeholder =>
+            {
+                ctx.placeholder_texture(name, &err)
+            }
+            (texture, _) => texture?,
+        };
+        let material = Material::new(
+            texture,
+            self.reflectance.r,
+            self.transmittance.t,
+            self.refraction.iof,
+            self.shading.into(),
+        );
+        let material = self.reflectance.apply(material)?;
+        let material = SerialThinFilm::apply(self.thin_film, material)?;
+        SerialClearCoat::apply(self.clearcoat, material)
+    }
+}
+
+impl MaterialGradient {
+    /// Sort the stops and convert to a material
+    /// Fails if the gradient has no stops or a stop has an invalid color
+    fn convert_to_material(self, path: &Path) -> Result<Material, InputError> {
+        let err = |msg: String| {
+            InputError::new(
+                format!(
+                    "Error while reading file '{}'",
+                    path.to_str().unwrap_or("<INVALID PATH>")
+                ),
+                msg,
+            )
+        };
+        if self.stop.is_empty() {
+            return Err(err(
+                "A gradient material needs at least one stop.".to_string()
+            ));
+        }
+        let mut colors = self
+            .stop
+            .into_iter()
+            .map(GradientStop::convert_to_stop)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(err)?;
+        colors.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let axis = match self.axis {
+            SerialGradientAxis::U => GradientAxis::U,
+            SerialGradientAxis::V => GradientAxis::V,
+        };
+        let material = Material::new(
+            Texture::Gradient { colors, axis },
+            self.reflectance.r,
+            self.transmittance.t,
+            self.refraction.iof,
+            self.shading.into(),
+        );
+        let material = self.reflectance.apply(material)?;
+        let material = SerialThinFilm::apply(self.thin_film, material)?;
+        SerialClearCoat::apply(self.clearcoat, material)
+    }
+}
+
+impl MaterialSolid {
+    /// Convert to a material
+    /// Fails if the clear coat is invalid
+    fn convert_to_material(self) -> Result<Material, InputError> {
+        let material = Material::new(
+            Texture::Color(self.color),
             self.reflectance.r,
             self.transmittance.t,
             self.refraction.iof,
             self.shading.into(),
+        );
+        let material = self.reflectance.apply(material)?;
+        let material = SerialThinFilm::apply(self.thin_film, material)?;
+        SerialClearCoat::apply(self.clearcoat, material)
+    }
+}
+
+// --- Material library serial types ---
+
+/// Width and height in pixels of the placeholder for textures that could not be loaded
+const MISSING_TEXTURE_SIZE: u32 = 64;
+
+/// Number of cells per side of the placeholder for textures that could not be loaded
+const MISSING_TEXTURE_CELLS: u32 = 8;
+
+/// Factor by which meshes may exceed the triangle limit when they are decimated after loading
+const DECIMATION_HEADROOM: usize = 2;
+
+/// Named materials that can be referenced by surfaces
+type MaterialLibrary = HashMap<String, Material>;
+
+/// Shared state that is needed while converting surfaces
+struct ConversionContext<'a> {
+    library: MaterialLibrary,
+    max_texture_size: Option<u32>,
+    /// file that is converted, used in error messages
+    file: &'a Path,
+    /// directory of `file`, referenced files are looked up relative to it
+    base_dir: &'a Path,
+    /// options the scene is loaded with
+    options: &'a LoadOptions,
+    /// number of triangles of all meshes that were loaded so far
+    triangles: Cell<usize>,
+    /// textures that could not be loaded and were replaced with a placeholder
+    missing_textures: RefCell<Vec<String>>,
+}
+
+impl<'a> ConversionContext<'a> {
+    /// Create a context without any materials for converting `file`
+    fn new(file: &'a Path, options: &'a LoadOptions, max_texture_size: Option<u32>) -> Self {
+        ConversionContext {
+            library: MaterialLibrary::new(),
+            max_texture_size,
+            file,
+            base_dir: file.parent().unwrap_or(Path::new("")),
+            options,
+            triangles: Cell::new(0),
+            missing_textures: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Replace environment variables in the file name `name`, unless expansion is turned off
+    fn expand(&self, name: &str) -> Result<String, InputError> {
+        if self.options.no_env_expansion {
+            return Ok(name.to_string());
+        }
+        expand_env_vars(name).map_err(|msg| {
+            InputError::new(
+                format!("Error while reading file '{}'", self.file.display()),
+                msg,
+            )
+        })
+    }
+
+    /// Return the path of the file `name` referenced by the converted file
+    /// Relative paths, including ones into subdirectories, are resolved against the directory of
+    /// the converted file. Absolute paths are used as they are, but emit a warning because they
+    /// make the scene depend on the machine it was written on
+    fn resolve(&self, name: &str) -> Result<PathBuf, InputError> {
+        let name = self.expand(name)?;
+        let path = paths::portable_path(&name);
+        if path.is_absolute() {
+            log::warning(&format!(
+                "'{}' references the absolute path '{name}', the scene will not be portable",
+                self.file.display()
+            ))?;
+        }
+        Ok(self.base_dir.join(path))
+    }
+
+    /// Load the png texture `name` relative to the converted file
+    /// Textures with more pixels than the limit of the options are rejected before they are
+    /// decoded, textures larger than the size limit are downscaled
+    fn load_texture(&self, name: &str) -> Result<Image, InputError> {
+        let path = self.resolve(name)?;
+        if let Some(limit) = self.options.max_texture_pixels {
+            let (width, height) = Image::png_dimensions(&path)?;
+            if u64::from(width) * u64::from(height) > limit {
+                return Err(InputError::new(
+                    format!("Error while loading texture '{name}'"),
+                    limits::too_many_pixels(width, height, limit),
+                ));
+            }
+        }
+        let image = Image::load_png(&path)?;
+        Ok(match self.max_texture_size {
+            Some(max_size) => image.limit_size(max_size),
+            None => image,
+        })
+    }
+
+    /// Return the placeholder for the texture `name`, which failed to load with `err`
+    /// The texture is remembered as missing and a warning is printed, also in strict mode, as
+    /// the missing textures are summarized once the scene is rendered
+    fn placeholder_texture(&self, name: &str, err: &InputError) -> Texture {
+        log::print_warning(&format!(
+            "The texture '{name}' could not be loaded ({}), it is replaced with a placeholder",
+            err.msg()
+        ));
+        self.add_missing_textures([name.to_string()]);
+        Texture::image(Image::missing_texture(
+            MISSING_TEXTURE_SIZE,
+            MISSING_TEXTURE_CELLS,
         ))
     }
+
+    /// Remember the textures `names` as missing, each name is listed once
+    fn add_missing_textures(&self, names: impl IntoIterator<Item = String>) {
+        let mut missing = self.missing_textures.borrow_mut();
+        for name in names {
+            if !missing.contains(&name) {
+                missing.push(name);
+            }
+        }
+    }
+
+    /// Return how many triangles the next mesh may have, after all meshes loaded so far
+    fn remaining_triangles(&self) -> usize {
+        self.options
+            .max_triangles
+            .map_or(usize::MAX, |max| max.saturating_sub(self.triangles.get()))
+    }
 }
 
-impl From<MaterialSolid> for Material {
-    fn from(inp: MaterialSolid) -> Material {
-        Material::new(
-            Texture::Color(inp.color),
-            inp.reflectance.r,
-            inp.transmittance.t,
-            inp.refraction.iof,
-            inp.shading.into(),
-        )
+#[derive(Debug, Deserialize)]
+pub(super) struct MaterialLibraryFile {
+    #[serde(rename = "@file")]
+    file: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct MaterialRef {
+    #[serde(rename = "@name")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct NamedMaterial {
+    #[serde(rename = "@name")]
+    name: String,
+    material_solid: Option<MaterialSolid>,
+    material_textured: Option<MaterialTextured>,
+    material_gradient: Option<MaterialGradient>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialMaterialLibrary {
+    #[serde(default)]
+    named_material: Vec<NamedMaterial>,
+}
+
+impl MaterialLibraryFile {
+    /// Read and convert the material library
+    /// The library file is looked up relative to the xml file, textures are looked up relative to
+    /// the library file. Textures of the library that are missing are added to those of `scene`
+    fn load(self, scene: &ConversionContext) -> Result<MaterialLibrary, InputError> {
+        let path = scene.resolve(&self.file)?;
+        let content = fs::read_to_string(&path).map_err(|err| {
+            InputError::new(
+                format!("Error while reading file '{}'", &self.file),
+                err.to_string(),
+            )
+        })?;
+        let library: SerialMaterialLibrary = quick_xml::de::from_str(&content).map_err(|err| {
+            InputError::new(
+                format!("Error while parsing file '{}'", &self.file),
+                err.to_string(),
+            )
+        })?;
+
+        let mut ctx = ConversionContext::new(&path, scene.options, scene.max_texture_size);
+        for named in library.named_material {
+            let material = resolve_material(
+                named.material_solid,
+                named.material_textured,
+                named.material_gradient,
+                None,
+                &ctx,
+            )?;
+            ctx.library.insert(named.name, material);
+        }
+        scene.add_missing_textures(ctx.missing_textures.into_inner());
+        Ok(ctx.library)
+    }
+}
+
+/// Resolve the material of a surface from either an inline material or a reference into the
+/// material library
+fn resolve_material(
+    material_solid: Option<MaterialSolid>,
+    material_textured: Option<MaterialTextured>,
+    material_gradient: Option<MaterialGradient>,
+    material_ref: Option<MaterialRef>,
+    ctx: &ConversionContext,
+) -> Result<Material, InputError> {
+    if let Some(m) = material_solid {
+        return m.convert_to_material();
+    }
+    if let Some(m) = material_textured {
+        return m.convert_to_material(ctx);
+    }
+    if let Some(m) = material_gradient {
+        return m.convert_to_material(ctx.file);
     }
+    if let Some(r) = material_ref {
+        return ctx.library.get(&r.name).cloned().ok_or(InputError::new(
+            format!(
+                "Error while reading file '{}':",
+                ctx.file.to_str().unwrap_or("<INVALID PATH>")
+            ),
+            format!("Unknown material reference '{}'.", r.name),
+        ));
+    }
+    Err(InputError::new(
+        format!(
+            "Error while reading file '{}':",
+            ctx.file.to_str().unwrap_or("<INVALID PATH>")
+        ),
+        "No material was given.".to_string(),
+    ))
 }
 
 // --- Transform serial types ---
@@ -242,6 +910,8 @@ impl From<Transform> for Mat4 {
 #[serde(rename_all = "snake_case")]
 pub(super) enum SerialSurface {
     Sphere {
+        #[serde(rename = "@layer")]
+        layer: Option<String>,
         #[serde(rename = "@radius")]
         radius: f32,
         #[serde(rename = "@endradius")]
@@ -250,26 +920,330 @@ pub(super) enum SerialSurface {
         endposition: Option<Vec3>,
         material_solid: Option<MaterialSolid>,
         material_textured: Option<MaterialTextured>,
+        material_gradient: Option<MaterialGradient>,
+        material_ref: Option<MaterialRef>,
         transform: Option<TransformList>,
+        clip: Option<SerialClip>,
     },
+    Ellipsoid(SerialEllipsoid),
+    Disc(SerialDisc),
+    Quad(SerialQuad),
+    Scatter(SerialScatter),
     Mesh {
+        #[serde(rename = "@layer")]
+        layer: Option<String>,
         #[serde(rename = "@name")]
         name: String,
+        #[serde(rename = "@smooth_shading")]
+        smooth_shading: Option<bool>,
+        #[serde(rename = "@both_sides")]
+        both_sides: Option<bool>,
         material_solid: Option<MaterialSolid>,
         material_textured: Option<MaterialTextured>,
+        material_gradient: Option<MaterialGradient>,
+        material_ref: Option<MaterialRef>,
         transform: Option<TransformList>,
+        clip: Option<SerialClip>,
     },
-    JuliaSet {
-        #[serde(rename = "@max_iteration")]
-        max_iterations: u32,
-        #[serde(rename = "@epsilon")]
-        epsilon: f32,
-        position: Point3,
-        constant: SerialQuat,
-        endconstant: Option<SerialQuat>,
-        material_solid: MaterialSolid,
+    Ply {
+        #[serde(rename = "@layer")]
+        layer: Option<String>,
+        #[serde(rename = "@name")]
+        name: String,
+        #[serde(rename = "@smooth_shading")]
+        smooth_shading: Option<bool>,
+        #[serde(rename = "@both_sides")]
+        both_sides: Option<bool>,
+        material_solid: Option<MaterialSolid>,
+        material_textured: Option<MaterialTextured>,
+        material_gradient: Option<MaterialGradient>,
+        material_ref: Option<MaterialRef>,
         transform: Option<TransformList>,
+        clip: Option<SerialClip>,
     },
+    JuliaSet(SerialJuliaSet),
+}
+
+/// Julia set of the quaternion `constant`, optionally animated towards `endconstant`
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialJuliaSet {
+    #[serde(rename = "@layer")]
+    layer: Option<String>,
+    #[serde(rename = "@max_iteration")]
+    max_iterations: u32,
+    #[serde(rename = "@epsilon")]
+    epsilon: f32,
+    position: Point3,
+    constant: SerialQuat,
+    endconstant: Option<SerialQuat>,
+    material_solid: MaterialSolid,
+    transform: Option<TransformList>,
+    clip: Option<SerialClip>,
+}
+
+impl SerialJuliaSet {
+    /// Convert to a julia set surface
+    fn convert_to_surface(self) -> Result<Surface, InputError> {
+        let c = self.constant;
+        let c = Quat::new(c.x, c.y, c.z, c.w);
+        let material = self.material_solid.convert_to_material()?;
+        let mut julia = Surface::julia_set(
+            self.position,
+            c,
+            self.max_iterations,
+            self.epsilon,
+            material,
+        );
+        SerialSurface::apply_transform(&mut julia, self.transform);
+        SerialClip::apply(&mut julia, self.clip)?;
+        if let Some(ec) = self.endconstant {
+            let ec = Quat::new(ec.x, ec.y, ec.z, ec.w);
+            julia.set_julia_end(ec);
+        }
+        julia.set_layer(self.layer);
+        Ok(julia)
+    }
+}
+
+/// Axis aligned ellipsoid with the radii `rx`, `ry` and `rz`
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialEllipsoid {
+    #[serde(rename = "@layer")]
+    layer: Option<String>,
+    #[serde(rename = "@rx")]
+    rx: f32,
+    #[serde(rename = "@ry")]
+    ry: f32,
+    #[serde(rename = "@rz")]
+    rz: f32,
+    position: Vec3,
+    material_solid: Option<MaterialSolid>,
+    material_textured: Option<MaterialTextured>,
+    material_gradient: Option<MaterialGradient>,
+    material_ref: Option<MaterialRef>,
+    transform: Option<TransformList>,
+    clip: Option<SerialClip>,
+}
+
+impl SerialEllipsoid {
+    /// Convert to an ellipsoid surface
+    /// Fails if one of the radii is not positive
+    fn convert_to_surface(self, ctx: &ConversionContext) -> Result<Surface, InputError> {
+        let (rx, ry, rz) = (self.rx, self.ry, self.rz);
+        if rx <= 0. || ry <= 0. || rz <= 0. {
+            return Err(InputError::new(
+                "Invalid ellipsoid".to_string(),
+                format!(
+                    "The radii of an ellipsoid have to be positive, got rx={rx}, ry={ry} and rz={rz}"
+                ),
+            ));
+        }
+        let material = resolve_material(
+            self.material_solid,
+            self.material_textured,
+            self.material_gradient,
+            self.material_ref,
+            ctx,
+        )?;
+        let mut ellipsoid = Surface::ellipsoid(self.position, Vec3::new(rx, ry, rz), material);
+        SerialSurface::apply_transform(&mut ellipsoid, self.transform);
+        SerialClip::apply(&mut ellipsoid, self.clip)?;
+        ellipsoid.set_layer(self.layer);
+        Ok(ellipsoid)
+    }
+}
+
+/// Flat disc with the radius `radius`, facing in the direction of `normal`
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialDisc {
+    #[serde(rename = "@layer")]
+    layer: Option<String>,
+    #[serde(rename = "@radius")]
+    radius: f32,
+    #[serde(rename = "@both_sides")]
+    both_sides: Option<bool>,
+    position: Vec3,
+    normal: Vec3,
+    material_solid: Option<MaterialSolid>,
+    material_textured: Option<MaterialTextured>,
+    material_gradient: Option<MaterialGradient>,
+    material_ref: Option<MaterialRef>,
+    transform: Option<TransformList>,
+    clip: Option<SerialClip>,
+}
+
+impl SerialDisc {
+    /// Convert to a disc surface
+    /// Fails if the radius is not positive or the normal is zero
+    fn convert_to_surface(self, ctx: &ConversionContext) -> Result<Surface, InputError> {
+        if self.radius <= 0. || self.normal.length_squared() == 0. {
+            return Err(InputError::new(
+                "Invalid disc".to_string(),
+                format!(
+                    "A disc needs a positive radius and a non-zero normal, got radius={} and normal {:?}",
+                    self.radius, self.normal
+                ),
+            ));
+        }
+        let material = resolve_material(
+            self.material_solid,
+            self.material_textured,
+            self.material_gradient,
+            self.material_ref,
+            ctx,
+        )?;
+        let mut disc = Surface::disc(self.position, self.normal, self.radius, material);
+        SerialSurface::apply_transform(&mut disc, self.transform);
+        SerialClip::apply(&mut disc, self.clip)?;
+        disc.set_both_sides(self.both_sides.unwrap_or(false));
+        disc.set_layer(self.layer);
+        Ok(disc)
+    }
+}
+
+/// Parallelogram spanned by `edge_u` and `edge_v` from `corner`
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialQuad {
+    #[serde(rename = "@layer")]
+    layer: Option<String>,
+    #[serde(rename = "@both_sides")]
+    both_sides: Option<bool>,
+    corner: Vec3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    waves: Option<SerialWaves>,
+    material_solid: Option<MaterialSolid>,
+    material_textured: Option<MaterialTextured>,
+    material_gradient: Option<MaterialGradient>,
+    material_ref: Option<MaterialRef>,
+    transform: Option<TransformList>,
+    clip: Option<SerialClip>,
+}
+
+impl SerialQuad {
+    /// Convert to a quad surface
+    /// Fails if the edges are parallel or one of them is zero
+    fn convert_to_surface(self, ctx: &ConversionContext) -> Result<Surface, InputError> {
+        if self.edge_u.cross(&self.edge_v).length_squared() == 0. {
+            return Err(InputError::new(
+                "Invalid quad".to_string(),
+                format!(
+                    "The edges of a quad must not be zero or parallel, got {:?} and {:?}",
+                    self.edge_u, self.edge_v
+                ),
+            ));
+        }
+        let material = resolve_material(
+            self.material_solid,
+            self.material_textured,
+            self.material_gradient,
+            self.material_ref,
+            ctx,
+        )?;
+        let mut quad = Surface::quad(self.corner, self.edge_u, self.edge_v, material);
+        if let Some(waves) = self.waves {
+            quad.set_quad_waves(waves.convert()?);
+        }
+        SerialSurface::apply_transform(&mut quad, self.transform);
+        SerialClip::apply(&mut quad, self.clip)?;
+        quad.set_both_sides(self.both_sides.unwrap_or(false));
+        quad.set_layer(self.layer);
+        Ok(quad)
+    }
+}
+
+/// Copies of a prototype surface, scattered over the area of the surface `surface_ref`
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialScatter {
+    #[serde(rename = "@count")]
+    count: usize,
+    #[serde(rename = "@seed")]
+    seed: Option<u64>,
+    #[serde(rename = "@surface_ref")]
+    surface_ref: String,
+    #[serde(rename = "@scale_min")]
+    scale_min: Option<f32>,
+    #[serde(rename = "@scale_max")]
+    scale_max: Option<f32>,
+    #[serde(rename = "@align_to_normal")]
+    align_to_normal: Option<bool>,
+    #[serde(rename = "$value")]
+    prototype: Vec<SerialSurface>,
+}
+
+impl SerialScatter {
+    /// Error for a scatter that is used as the prototype of another scatter
+    fn nested_error() -> InputError {
+        InputError::new(
+            "Invalid scatter".to_string(),
+            "A scatter can't be the prototype of another scatter".to_string(),
+        )
+    }
+
+    /// Convert the prototype and scatter copies of it over the surface `surface_ref` of `surfaces`
+    /// The referenced surface has to come before the scatter
+    fn convert_to_surfaces(
+        self,
+        surfaces: &[Surface],
+        ctx: &ConversionContext,
+    ) -> Result<Vec<Surface>, InputError> {
+        let error = |msg| InputError::new("Invalid scatter".to_string(), msg);
+        let Ok([prototype]) = <[SerialSurface; 1]>::try_from(self.prototype) else {
+            return Err(error(
+                "A scatter needs exactly one prototype surface".to_string(),
+            ));
+        };
+        let prototype = prototype.convert_to_surface(ctx)?;
+        let target = surface_ids(surfaces)
+            .iter()
+            .position(|id| *id == self.surface_ref)
+            .ok_or_else(|| {
+                error(format!(
+                    "Unknown surface '{}', the surface to scatter on has to come before the scatter",
+                    self.surface_ref
+                ))
+            })?;
+        let scatter = Scatter {
+            count: self.count,
+            seed: self.seed.unwrap_or(0),
+            scale: (self.scale_min.unwrap_or(1.), self.scale_max.unwrap_or(1.)),
+            align_to_normal: self.align_to_normal.unwrap_or(false),
+        };
+        scatter
+            .instances(&prototype, &surfaces[target])
+            .map_err(error)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialWaves {
+    #[serde(rename = "@amplitude")]
+    amplitude: f32,
+    #[serde(rename = "@frequency")]
+    frequency: f32,
+    #[serde(rename = "@speed")]
+    speed: Option<f32>,
+}
+
+impl SerialWaves {
+    /// Convert to waves
+    /// Fails if the amplitude is negative or the frequency is not positive
+    fn convert(self) -> Result<Waves, InputError> {
+        if self.amplitude < 0. || self.frequency <= 0. {
+            return Err(InputError::new(
+                "Invalid waves".to_string(),
+                format!(
+                    "The amplitude has to be at least 0 and the frequency positive, got amplitude={} and frequency={}",
+                    self.amplitude, self.frequency
+                ),
+            ));
+        }
+        Ok(Waves {
+            amplitude: self.amplitude,
+            frequency: self.frequency,
+            speed: self.speed.unwrap_or(0.),
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -301,109 +1275,250 @@ impl From<TransformList> for Mat4 {
     }
 }
 
+/// Planes that cut away the parts of a surface on the side their normal points to
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialClip {
+    #[serde(rename = "plane")]
+    planes: Vec<SerialClipPlane>,
+}
+
+/// Plane through the point `(px, py, pz)` with the normal `(nx, ny, nz)`
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialClipPlane {
+    #[serde(rename = "@px")]
+    px: f32,
+    #[serde(rename = "@py")]
+    py: f32,
+    #[serde(rename = "@pz")]
+    pz: f32,
+    #[serde(rename = "@nx")]
+    nx: f32,
+    #[serde(rename = "@ny")]
+    ny: f32,
+    #[serde(rename = "@nz")]
+    nz: f32,
+}
+
+impl SerialClip {
+    /// Cut `surface` with the clip planes, if there are any
+    /// Fails if the normal of a plane is zero
+    fn apply(surface: &mut Surface, clip: Option<Self>) -> Result<(), InputError> {
+        let Some(clip) = clip else {
+            return Ok(());
+        };
+        let planes = clip
+            .planes
+            .into_iter()
+            .map(|p| {
+                let normal = Vec3::new(p.nx, p.ny, p.nz);
+                if normal.length_squared() == 0. {
+                    return Err(InputError::new(
+                        "Invalid clip plane".to_string(),
+                        "The normal of a clip plane must not be zero".to_string(),
+                    ));
+                }
+                Ok(ClipPlane::new(Point3::new(p.px, p.py, p.pz), normal))
+            })
+            .collect::<Result<_, _>>()?;
+        surface.set_clip_planes(planes);
+        Ok(())
+    }
+}
+
+/// Parser for a mesh file, gets the file name, a reader for its contents, its size in bytes and
+/// the maximum number of triangles
+type MeshParser = fn(&str, BufReader<File>, usize, usize) -> Result<Vec<Triangle>, InputError>;
+
 impl SerialSurface {
+    /// Parse the obj file `name` from `reader`
+    /// Warns if many lines of the file were skipped
+    fn parse_obj(
+        name: &str,
+        reader: BufReader<File>,
+        size: usize,
+        max_triangles: usize,
+    ) -> Result<Vec<Triangle>, InputError> {
+        let (triangles, report) = parse(reader, size, max_triangles)?;
+        if let Some(msg) = report.warning(name) {
+            log::warning(&msg)?;
+        }
+        Ok(triangles)
+    }
+
+    /// Read the whole ply file from `reader` and parse it
+    fn read_ply(
+        _: &str,
+        mut reader: BufReader<File>,
+        size: usize,
+        max_triangles: usize,
+    ) -> Result<Vec<Triangle>, InputError> {
+        let mut src = String::with_capacity(size);
+        reader
+            .read_to_string(&mut src)
+            .map_err(|err| InputError::new(String::new(), err.to_string()))?;
+        parse_ply(&src, max_triangles)
+    }
+
+    /// Return the parser for the mesh file of the surface
+    fn mesh_parser(&self) -> MeshParser {
+        if matches!(self, SerialSurface::Ply { .. }) {
+            Self::read_ply
+        } else {
+            Self::parse_obj
+        }
+    }
+
+    /// Transform `surface` by the inverse of `transform`, if there is one
+    fn apply_transform(surface: &mut Surface, transform: Option<TransformList>) {
+        if let Some(t) = transform {
+            let inv_transform = t.into();
+            // normal matrix is the inverse transpose
+            let normal_transform = Mat4::transpose(&inv_transform);
+            surface.set_transform(inv_transform, normal_transform);
+        }
+    }
+
+    /// Read the mesh file `name` relative to the converted file and parse it with `parser`
+    /// The mesh has to fit into the triangle limit of the scene. If the options ask for
+    /// decimation, meshes up to twice the remaining limit are read and then decimated
+    /// Unless `smooth_shading` is false, the vertex normals of the triangles are interpolated
+    /// If `both_sides` is true, both sides of the mesh are shaded as front faces
+    fn mesh_from_file(
+        name: &str,
+        parser: MeshParser,
+        smooth_shading: Option<bool>,
+        both_sides: Option<bool>,
+        material: Material,
+        transform: Option<TransformList>,
+        ctx: &ConversionContext,
+    ) -> Result<Surface, InputError> {
+        let read_err = |err: std::io::Error| {
+            InputError::new(
+                format!("Error while reading file '{name}'"),
+                err.to_string(),
+            )
+        };
+        let file = File::open(ctx.resolve(name)?).map_err(read_err)?;
+        let size = file
+            .metadata()
+            .map_or(0, |m| usize::try_from(m.len()).unwrap_or(usize::MAX));
+        let parse_err = |msg| InputError::new(format!("Error while parsing file '{name}'"), msg);
+        let remaining = ctx.remaining_triangles();
+        let max_triangles = match ctx.options.decimate_to {
+            Some(_) => remaining.saturating_mul(DECIMATION_HEADROOM),
+            None => remaining,
+        };
+        let mut triangles = parser(name, BufReader::new(file), size, max_triangles)
+            .map_err(|err| parse_err(err.msg))?;
+        if let Some(target) = ctx.options.decimate_to {
+            if triangles.len() > target {
+                triangles = decimate(&triangles, target);
+            }
+        }
+        if triangles.len() > remaining {
+            return Err(parse_err(limits::too_many_triangles(
+                triangles.len(),
+                remaining,
+            )));
+        }
+        ctx.triangles.set(ctx.triangles.get() + triangles.len());
+        if smooth_shading == Some(false) {
+            for triangle in &mut triangles {
+                triangle.set_shading(TriangleShading::Flat);
+            }
+        }
+        let mut surface = Surface::mesh(triangles, material);
+        surface.set_both_sides(both_sides.unwrap_or(false));
+        Self::apply_transform(&mut surface, transform);
+        Ok(surface)
+    }
+
     /// Converts deserialized surface to a surface
-    /// Takes a pathbuf from the path of the xml file, because it will look for obj files in the
-    /// same directory
-    fn convert_to_surface(self, path: &mut PathBuf) -> Result<Surface, InputError> {
+    /// Mesh files are looked up relative to the converted file and material references in the
+    /// material library of the context
+    fn convert_to_surface(self, ctx: &ConversionContext) -> Result<Surface, InputError> {
+        let parser = self.mesh_parser();
         match self {
             SerialSurface::Sphere {
+                layer,
                 radius,
                 endradius,
                 position,
                 endposition,
                 material_solid,
                 material_textured,
+                material_gradient,
+                material_ref,
                 transform,
+                clip,
             } => {
-                let material = if let Some(m) = material_solid {
-                    m.into()
-                } else {
-                    material_textured
-                        .map(|m| m.convert_to_material(path))
-                        .ok_or(InputError::new(
-                            format!(
-                                "Error while reading file '{}':",
-                                path.to_str().unwrap_or("<INVALID PATH>")
-                            ),
-                            "No material was given.".to_string(),
-                        ))??
-                };
+                let material = resolve_material(
+                    material_solid,
+                    material_textured,
+                    material_gradient,
+                    material_ref,
+                    ctx,
+                )?;
                 let mut sphere = Surface::sphere(position, radius, material);
-                if let Some(t) = transform {
-                    let inv_transform = t.into();
-                    let normal_transform = Mat4::transpose(&inv_transform);
-                    sphere.set_transform(inv_transform, normal_transform);
-                }
+                Self::apply_transform(&mut sphere, transform);
+                SerialClip::apply(&mut sphere, clip)?;
                 if endradius.is_some() || endposition.is_some() {
                     let ec = endposition.unwrap_or(position);
                     let er = endradius.unwrap_or(radius);
                     sphere.set_sphere_end((ec, er));
                 }
+                sphere.set_layer(layer);
                 Ok(sphere)
             }
+            SerialSurface::Ellipsoid(ellipsoid) => ellipsoid.convert_to_surface(ctx),
+            SerialSurface::Disc(disc) => disc.convert_to_surface(ctx),
+            SerialSurface::Quad(quad) => quad.convert_to_surface(ctx),
+            SerialSurface::Scatter(_) => Err(SerialScatter::nested_error()),
             SerialSurface::Mesh {
+                layer,
                 name,
+                smooth_shading,
+                both_sides,
                 material_solid,
                 material_textured,
+                material_gradient,
+                material_ref,
                 transform,
-            } => {
-                path.set_file_name(&name);
-                let file = fs::read_to_string(&mut *path).map_err(|err| {
-                    InputError::new(
-                        format!("Error while reading file '{}'", &name),
-                        err.to_string(),
-                    )
-                })?;
-                let material = if let Some(m) = material_solid {
-                    m.into()
-                } else {
-                    material_textured
-                        .map(|m| m.convert_to_material(path))
-                        .ok_or(InputError::new(
-                            format!(
-                                "Error while reading file '{}':",
-                                path.to_str().unwrap_or("<INVALID PATH>")
-                            ),
-                            "No material was given.".to_string(),
-                        ))??
-                };
-                let triangles = parse(&file).map_err(|err| {
-                    InputError::new(format!("Error while parsing file '{}'", &name), err.msg)
-                })?;
-                let mut surface = Surface::mesh(triangles, material);
-                if let Some(t) = transform {
-                    let inv_transform = t.into();
-                    // normal matrix is the inverse transpose
-                    let normal_transform = Mat4::transpose(&inv_transform);
-                    surface.set_transform(inv_transform, normal_transform);
-                }
-                Ok(surface)
+                clip,
             }
-            Self::JuliaSet {
-                position,
-                max_iterations,
-                epsilon,
-                constant,
-                endconstant,
+            | SerialSurface::Ply {
+                layer,
+                name,
+                smooth_shading,
+                both_sides,
                 material_solid,
+                material_textured,
+                material_gradient,
+                material_ref,
                 transform,
+                clip,
             } => {
-                let c = Quat::new(constant.x, constant.y, constant.z, constant.w);
-                let mut julia =
-                    Surface::julia_set(position, c, max_iterations, epsilon, material_solid.into());
-                if let Some(t) = transform {
-                    let inv_transform = t.into();
-                    // normal matrix is the inverse transpose
-                    let normal_transform = Mat4::transpose(&inv_transform);
-                    julia.set_transform(inv_transform, normal_transform);
-                }
-                if let Some(ec) = endconstant {
-                    let ec = Quat::new(ec.x, ec.y, ec.z, ec.w);
-                    julia.set_julia_end(ec);
-                }
-                Ok(julia)
+                let material = resolve_material(
+                    material_solid,
+                    material_textured,
+                    material_gradient,
+                    material_ref,
+                    ctx,
+                )?;
+                let mut surface = Self::mesh_from_file(
+                    &name,
+                    parser,
+                    smooth_shading,
+                    both_sides,
+                    material,
+                    transform,
+                    ctx,
+                )?;
+                SerialClip::apply(&mut surface, clip)?;
+                surface.set_layer(layer);
+                Ok(surface)
             }
+            SerialSurface::JuliaSet(julia) => julia.convert_to_surface(),
         }
     }
 }
@@ -413,9 +1528,60 @@ impl SerialSurface {
 #[derive(Debug, Deserialize)]
 pub(super) struct Falloff {
     #[serde(rename = "@alpha1")]
-    alpha1: u32,
+    alpha1: f32,
     #[serde(rename = "@alpha2")]
-    alpha2: u32,
+    alpha2: f32,
+}
+
+impl Falloff {
+    /// Check the angles and return the cosines of the inner and outer angle
+    /// The inner angle has to be smaller than the outer one and both have to lie between 0 and 90
+    /// degrees
+    fn cosines(&self) -> Result<(f32, f32), InputError> {
+        let (inner, outer) = (self.alpha1, self.alpha2);
+        let error = |msg| {
+            Err(InputError::new(
+                "Invalid spot light falloff".to_string(),
+                msg,
+            ))
+        };
+        if !(0. ..=90.).contains(&inner) || !(0. ..=90.).contains(&outer) {
+            return error(format!(
+                "The falloff angles have to be between 0 and 90 degrees, got alpha1={inner} and alpha2={outer}"
+            ));
+        }
+        if inner >= outer {
+            return error(format!(
+                "alpha1 ({inner}) has to be smaller than alpha2 ({outer}), otherwise the light would be inverted"
+            ));
+        }
+        Ok((to_radians(inner).cos(), to_radians(outer).cos()))
+    }
+}
+
+/// Measured intensity profile of a spot light, a whitespace separated list of values
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialProfile {
+    #[serde(rename = "@values")]
+    values: String,
+}
+
+impl SerialProfile {
+    /// Parse the values of the profile
+    /// Fails if a value is not a number, or there are no values or one isn't between 0 and 1
+    fn convert_to_profile(&self) -> Result<SpotProfile, InputError> {
+        let error = |msg| InputError::new("Invalid spot light profile".to_string(), msg);
+        let values = self
+            .values
+            .split_whitespace()
+            .map(|value| {
+                value
+                    .parse::<f32>()
+                    .map_err(|_| error(format!("Expected a number but got '{value}'")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        SpotProfile::new(values).map_err(error)
+    }
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -423,46 +1589,190 @@ pub(super) struct Falloff {
 #[serde(rename_all = "snake_case")]
 pub(super) enum SerialLight {
     AmbientLight {
+        #[serde(deserialize_with = "deserialize_color")]
         color: Color,
+        ground: Option<SerialColor>,
+        direction: Option<Vec3>,
     },
     ParallelLight {
+        #[serde(deserialize_with = "deserialize_color")]
         color: Color,
         direction: Vec3,
     },
     PointLight {
+        #[serde(deserialize_with = "deserialize_color")]
         color: Color,
         position: Vec3,
+        #[serde(rename = "@radius", default)]
+        radius: f32,
+        #[serde(rename = "@samples")]
+        samples: Option<u32>,
     },
     SpotLight {
+        #[serde(deserialize_with = "deserialize_color")]
         color: Color,
         position: Vec3,
         direction: Vec3,
         falloff: Falloff,
+        profile: Option<SerialProfile>,
     },
+    Sun(SerialSun),
 }
 
-impl From<SerialLight> for Light {
-    fn from(inp: SerialLight) -> Light {
-        match inp {
-            SerialLight::AmbientLight { color } => Light::Ambient { color },
+impl SerialLight {
+    /// Return the ground half of a hemispheric ambient light, if it has a ground color
+    /// The up direction defaults to +y. Fails if the ground color is invalid, the direction is
+    /// zero or a direction is given without a ground color
+    fn hemisphere(
+        ground: Option<SerialColor>,
+        direction: Option<Vec3>,
+    ) -> Result<Option<Hemisphere>, InputError> {
+        let error = |msg: String| Err(InputError::new("Invalid ambient light".to_string(), msg));
+        let Some(ground) = ground else {
+            if direction.is_some() {
+                return error("The direction of an ambient light needs a ground color".to_string());
+            }
+            return Ok(None);
+        };
+        let up = direction.unwrap_or(Vec3::new(0., 1., 0.));
+        if up.length_squared() == 0. {
+            return error("The up direction of an ambient light must not be zero".to_string());
+        }
+        match ground.convert_to_color() {
+            Ok(ground) => Ok(Some(Hemisphere { ground, up })),
+            Err(msg) => error(msg),
+        }
+    }
+
+    /// Converts the deserialized light to a light
+    /// Fails if the falloff angles or the profile of a spot light are invalid
+    fn convert_to_light(self) -> Result<Light, InputError> {
+        Ok(match self {
+            SerialLight::AmbientLight {
+                color,
+                ground,
+                direction,
+            } => Light::Ambient {
+                color,
+                hemisphere: Self::hemisphere(ground, direction)?,
+            },
             SerialLight::ParallelLight { color, direction } => Light::Parallel { color, direction },
-            SerialLight::PointLight { color, position } => Light::Point { color, position },
-            SerialLight::SpotLight {
+            SerialLight::PointLight {
                 color,
                 position,
-                direction,
-                falloff,
-            } => Light::Spot {
+                radius,
+                samples,
+            } => {
+                if !radius.is_finite() || radius < 0. || samples == Some(0) {
+                    return Err(InputError::new(
+                        "Invalid point light".to_string(),
+                        format!(
+                            "The radius has to be at least 0 and the samples at least 1, got radius={radius} and samples={}",
+                            samples.unwrap_or_default()
+                        ),
+                    ));
+                }
+                Light::Point {
+                    color,
+                    position,
+                    radius,
+                    samples,
+                }
+            }
+            SerialLight::SpotLight {
                 color,
                 position,
                 direction,
-                #[allow(clippy::cast_precision_loss)]
-                falloff: (
-                    to_radians(falloff.alpha1 as f32).cos(),
-                    to_radians(falloff.alpha2 as f32).cos(),
-                ),
-            },
+                falloff,
+                profile,
+            } => {
+                let (cos_inner, cos_outer) = falloff.cosines()?;
+                Light::Spot {
+                    color,
+                    position,
+                    direction,
+                    cos_inner,
+                    cos_outer,
+                    profile: profile
+                        .map(|profile| profile.convert_to_profile())
+                        .transpose()?,
+                }
+            }
+            SerialLight::Sun(sun) => sun.convert_to_light()?,
+        })
+    }
+}
+
+/// Parallel light from the sun at a date and time, seen from a place on earth
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialSun {
+    #[serde(rename = "@latitude")]
+    latitude: f64,
+    #[serde(rename = "@longitude")]
+    longitude: f64,
+    #[serde(rename = "@date")]
+    date: String,
+    #[serde(rename = "@time")]
+    time: String,
+    /// hours that the local time is ahead of UTC
+    #[serde(rename = "@utc_offset")]
+    utc_offset: Option<f64>,
+    #[serde(rename = "@turbidity")]
+    turbidity: Option<f64>,
+}
+
+impl SerialSun {
+    /// Compute the direction and color of the sun
+    /// Fails if the place, date, time or turbidity are invalid. A sun below the horizon only
+    /// causes a warning and gives no light
+    fn convert_to_light(self) -> Result<Light, InputError> {
+        let error = |msg: String| Err(InputError::new("Invalid sun".to_string(), msg));
+        if !(-90. ..=90.).contains(&self.latitude) || !(-180. ..=180.).contains(&self.longitude) {
+            return error(format!(
+                "The latitude has to be between -90 and 90 and the longitude between -180 and 180, got {} and {}",
+                self.latitude, self.longitude
+            ));
+        }
+        let Some((year, month, day)) = solar::parse_date(&self.date) else {
+            return error(format!(
+                "'{}' is not a valid date, expected YYYY-MM-DD",
+                self.date
+            ));
+        };
+        let Some(hours) = solar::parse_time(&self.time) else {
+            return error(format!(
+                "'{}' is not a valid time, expected HH:MM or HH:MM:SS",
+                self.time
+            ));
+        };
+        let turbidity = self.turbidity.unwrap_or(2.);
+        if turbidity < 1. {
+            return error(format!(
+                "The turbidity has to be at least 1, got {turbidity}"
+            ));
         }
+
+        let time = UtcTime {
+            year,
+            month,
+            day,
+            hours: hours - self.utc_offset.unwrap_or(0.),
+        };
+        let sun = SunPosition::at(&time, self.latitude, self.longitude);
+        let color = if sun.elevation > 0. {
+            solar::kelvin_to_color(sun.color_temperature(turbidity))
+        } else {
+            log::warning(&format!(
+                "The sun is {:.1} degrees below the horizon on {} at {}, so it gives no light",
+                -sun.elevation, self.date, self.time
+            ))?;
+            Color::zero()
+        };
+        // the light travels from the sun towards the ground
+        Ok(Light::Parallel {
+            color,
+            direction: -sun.direction(),
+        })
     }
 }
 
@@ -472,10 +1782,21 @@ impl From<SerialLight> for Light {
 pub(super) struct SerialScene {
     #[serde(rename = "@output_file")]
     output_file: String,
+    #[serde(rename = "@active_camera")]
+    active_camera: Option<String>,
+    #[serde(deserialize_with = "deserialize_color")]
     background_color: Color,
+    background: Option<SerialBackground>,
+    material_library: Option<MaterialLibraryFile>,
+    textures: Option<Textures>,
     super_sampling: Option<SuperSampling>,
+    ray_differentials: Option<RayDifferentials>,
+    output: Option<Output>,
     animated: Option<Animated>,
-    camera: SerialCamera,
+    camera: Option<SerialCamera>,
+    cameras: Option<CameraList>,
+    #[serde(default)]
+    camera_switch: Vec<CameraSwitch>,
     lights: LightList,
     surfaces: SurfaceList,
 }
@@ -486,6 +1807,122 @@ pub(super) struct Animated {
     frames: usize,
     #[serde(rename = "@fps")]
     fps: u16,
+    #[serde(rename = "@loops")]
+    loops: Option<u32>,
+    #[serde(rename = "@pingpong")]
+    pingpong: Option<bool>,
+    #[serde(default)]
+    delay: Vec<FrameDelay>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct FrameDelay {
+    #[serde(rename = "@frame")]
+    frame: usize,
+    #[serde(rename = "@ms")]
+    ms: u16,
+}
+
+impl From<&Animated> for Playback {
+    fn from(anim: &Animated) -> Playback {
+        let mut playback = Playback::new(anim.fps);
+        playback.loops = anim.loops.unwrap_or(0);
+        playback.pingpong = anim.pingpong.unwrap_or(false);
+        playback.delays = anim.delay.iter().map(|d| (d.frame, d.ms)).collect();
+        playback
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Output {
+    #[serde(rename = "@gamma")]
+    gamma: Option<String>,
+    #[serde(rename = "@template")]
+    template: Option<String>,
+}
+
+impl Output {
+    /// Parse the gamma attribute, which is either `srgb`, `linear`, `none` or a positive number
+    /// Returns `None` if the attribute is missing
+    fn transfer_function(&self) -> Result<Option<TransferFunction>, InputError> {
+        let Some(gamma) = self.gamma.as_deref() else {
+            return Ok(None);
+        };
+        match gamma {
+            "srgb" => Ok(Some(TransferFunction::Srgb)),
+            "linear" => Ok(Some(TransferFunction::Linear)),
+            "none" => Ok(Some(TransferFunction::Untagged)),
+            gamma => gamma
+                .parse::<f32>()
+                .ok()
+                .filter(|g| g.is_finite() && *g > 0.)
+                .map(|gamma| Some(TransferFunction::Gamma(gamma)))
+                .ok_or(InputError::new(
+                    "Invalid output gamma".to_string(),
+                    format!(
+                        "Expected 'srgb', 'linear', 'none' or a positive number but got '{gamma}'"
+                    ),
+                )),
+        }
+    }
+
+    /// Return the template of the output path with the environment variables expanded
+    /// Fails if the template uses unknown placeholders, see [`check_template`]
+    fn template(&self, ctx: &ConversionContext) -> Result<Option<String>, InputError> {
+        let Some(template) = &self.template else {
+            return Ok(None);
+        };
+        let template = ctx.expand(template)?;
+        check_template(&template)
+            .map_err(|msg| InputError::new("Invalid output template".to_string(), msg))?;
+        Ok(Some(template))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct Textures {
+    #[serde(rename = "@max_size")]
+    max_size: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct SerialBackground {
+    #[serde(rename = "$value")]
+    primary: Option<SerialBackgroundKind>,
+    secondary: Option<SecondaryBackground>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(super) struct SecondaryBackground {
+    #[serde(rename = "$value")]
+    kind: SerialBackgroundKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum SerialBackgroundKind {
+    #[serde(deserialize_with = "deserialize_color")]
+    Color(Color),
+    Gradient {
+        #[serde(deserialize_with = "deserialize_color")]
+        bottom: Color,
+        #[serde(deserialize_with = "deserialize_color")]
+        top: Color,
+    },
+    Environment(SerialTexture),
+}
+
+impl SerialBackgroundKind {
+    /// Convert to a background
+    /// Environment maps are looked up relative to the xml file and downscaled to the texture size
+    /// limit of the context
+    fn convert_to_background(self, ctx: &ConversionContext) -> Result<Background, InputError> {
+        Ok(match self {
+            Self::Color(color) => Background::Solid(color),
+            Self::Gradient { bottom, top } => Background::VerticalGradient { bottom, top },
+            Self::Environment(texture) => Background::Environment(texture.convert_to_image(ctx)?),
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -494,6 +1931,9 @@ pub(super) struct SuperSampling {
     samples: u32,
 }
 
+#[derive(Debug, Deserialize)]
+pub(super) struct RayDifferentials {}
+
 #[derive(Debug, Deserialize)]
 pub(super) struct LightList {
     #[serde(default)]
@@ -508,28 +1948,142 @@ pub(super) struct SurfaceList {
     surfaces: Vec<SerialSurface>,
 }
 
+impl SurfaceList {
+    /// Convert all surfaces in order, scatters are expanded into their copies
+    fn convert_to_surfaces(self, ctx: &ConversionContext) -> Result<Vec<Surface>, InputError> {
+        let mut surfaces = Vec::with_capacity(self.surfaces.len());
+        for serial in self.surfaces {
+            match serial {
+                SerialSurface::Scatter(scatter) => {
+                    let copies = scatter.convert_to_surfaces(&surfaces, ctx)?;
+                    surfaces.extend(copies);
+                }
+                serial => surfaces.push(serial.convert_to_surface(ctx)?),
+            }
+        }
+        Ok(surfaces)
+    }
+}
+
 impl SerialScene {
+    /// Return the texture size limit of the scene and the `options`
+    /// If both limit the texture size, the smaller limit is used
+    fn max_texture_size(&self, options: &LoadOptions) -> Option<u32> {
+        match (
+            options.max_texture_size,
+            self.textures.as_ref().and_then(|t| t.max_size),
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Load the material `name` from the material library of the scene
+    /// Takes the path of the xml file, because the library is looked up relative to it
+    /// Returns the material together with the textures of the library that were replaced with a
+    /// placeholder
+    pub fn load_material(
+        self,
+        path: &Path,
+        options: &LoadOptions,
+        name: &str,
+    ) -> Result<(Material, Vec<String>), InputError> {
+        let max_texture_size = self.max_texture_size(options);
+        let title = format!(
+            "Error while loading material '{name}' from '{}'",
+            path.to_str().unwrap_or("<INVALID PATH>")
+        );
+        let Some(library) = self.material_library else {
+            return Err(InputError::new(
+                title,
+                "The scene has no material library".to_string(),
+            ));
+        };
+        let ctx = ConversionContext::new(path, options, max_texture_size);
+        let mut library = library.load(&ctx)?;
+        let material = library.remove(name).ok_or_else(|| {
+            let mut names: Vec<_> = library.keys().map(|n| format!("'{n}'")).collect();
+            names.sort();
+            InputError::new(
+                title,
+                format!(
+                    "Unknown material. Available materials are: {}",
+                    names.join(", ")
+                ),
+            )
+        })?;
+        Ok((material, ctx.missing_textures.into_inner()))
+    }
+
     /// Converts deserialized scene to a scene
-    /// Takes a pathbuf from the path of the xml file, because it will look for other files in the
-    /// same directory
-    pub fn convert_to_scene(self, path: &mut PathBuf) -> Result<Scene, InputError> {
+    /// Takes the path of the xml file, referenced files are looked up relative to its directory
+    /// If both the scene and the `options` limit the texture size, the smaller limit is used
+    /// A camera chosen in the `options` overrides the active camera of the scene
+    pub fn convert_to_scene(self, path: &Path, options: &LoadOptions) -> Result<Scene, InputError> {
+        let mut ctx = ConversionContext::new(path, options, self.max_texture_size(options));
+        if let Some(lib) = self.material_library {
+            ctx.library = lib.load(&ctx)?;
+        }
+        // the camera given on the commandline takes precedence over the one in the scene
+        let camera = select_camera(
+            self.camera,
+            self.cameras,
+            options.camera.as_deref().or(self.active_camera.as_deref()),
+        )?;
+        let resolution = (camera.resolution.horizontal, camera.resolution.vertical);
         let mut s = Scene::new(
-            self.output_file,
+            ctx.expand(&self.output_file)?,
             self.background_color,
-            self.camera.into(),
-            self.lights.lights.into_iter().map(Into::into).collect(),
-            self.surfaces
-                .surfaces
+            camera.into(),
+            self.lights
+                .lights
                 .into_iter()
-                .map(|serial| serial.convert_to_surface(path))
+                .map(SerialLight::convert_to_light)
                 .collect::<Result<Vec<_>, InputError>>()?,
+            self.surfaces.convert_to_surfaces(&ctx)?,
         );
+        if let Some(background) = self.background {
+            if let Some(primary) = background.primary {
+                s.set_background(primary.convert_to_background(&ctx)?);
+            }
+            if let Some(secondary) = background.secondary {
+                s.set_secondary_background(secondary.kind.convert_to_background(&ctx)?);
+            }
+        }
         if let Some(ssaa) = self.super_sampling {
             s.add_samples(ssaa.samples);
         }
+        if self.ray_differentials.is_some() {
+            s.enable_ray_differentials();
+        }
+        if let Some(output) = self.output {
+            if let Some(transfer) = output.transfer_function()? {
+                s.set_transfer_function(transfer);
+            }
+            s.set_output_template(output.template(&ctx)?);
+        }
         if let Some(anim) = self.animated {
-            s.set_animation(anim.frames, anim.fps);
+            if anim.frames == 0 || anim.fps == 0 {
+                return Err(InputError::new(
+                    "Invalid animation".to_string(),
+                    format!(
+                        "An animation needs at least one frame and one frame per second, got frames={} and fps={}",
+                        anim.frames, anim.fps
+                    ),
+                ));
+            }
+            s.set_animation(anim.frames, (&anim).into());
+        }
+        for switch in self.camera_switch {
+            s.add_camera_switch(
+                switch.frame,
+                switch.convert_to_camera(resolution, s.get_frames())?,
+            );
+        }
+        if let Some(msg) = s.animation_warning() {
+            log::warning(msg)?;
         }
+        s.set_missing_textures(ctx.missing_textures.into_inner());
 
         Ok(s)
     }
@@ -0,0 +1,353 @@
+//! effective settings of a render
+//! Settings come from the scene file and can be overridden on the commandline. Commandline
+//! options always take precedence over the values of the scene
+
+use std::fmt::Display;
+
+use crate::{
+    image::{Channels, TransferFunction},
+    objects::{Camera, Scene},
+};
+
+use super::{Config, InputError};
+
+/// Most bounces of the rays of thumbnails
+const THUMBNAIL_MAX_BOUNCES: u32 = 3;
+
+/// Most rays per camera sample of thumbnails for glossy reflections and soft shadows
+const THUMBNAIL_MAX_FEATURE_RAYS: u32 = 16;
+
+/// File format of the rendered image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Ppm,
+    /// animated png with one frame per frame of the scene
+    Apng,
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+            Self::Apng => "apng",
+        })
+    }
+}
+
+/// Settings of a render, merged from the scene and the commandline
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    /// samples per pixel, 0 if the render is not super-sampled
+    pub samples: u32,
+    pub resolution: (u32, u32),
+    /// factor of `--scale` and the resolution before scaling, `resolution` holds the scaled one
+    pub scale: Option<(f32, (u32, u32))>,
+    pub max_bounces: u32,
+    /// most rays per camera sample for glossy reflections and soft shadows
+    pub max_feature_rays: u32,
+    pub frames: usize,
+    /// average all frames of an animation into a single image
+    pub blur: bool,
+    pub format: OutputFormat,
+    /// channels of the saved images
+    pub channels: Channels,
+    pub seed: Option<u64>,
+    pub transfer: TransferFunction,
+    /// exposure values of the bracketed images, empty if only a single image is saved
+    pub brackets: Vec<f32>,
+    /// name of the scene in the burn-in overlay of review renders, `None` for final renders
+    pub burn_in: Option<String>,
+}
+
+impl RenderSettings {
+    /// Collect the settings of `scene`
+    /// Animated scenes are saved as apng, all others as png
+    #[must_use]
+    pub fn from_scene(scene: &Scene) -> RenderSettings {
+        let format = if scene.is_animated() {
+            OutputFormat::Apng
+        } else {
+            OutputFormat::Png
+        };
+        RenderSettings {
+            samples: scene.get_samples(),
+            resolution: scene.get_dimensions(),
+            scale: None,
+            max_bounces: scene.get_max_bounces(),
+            max_feature_rays: scene.get_max_feature_rays(),
+            frames: scene.get_frames(),
+            blur: false,
+            format,
+            channels: Channels::Rgb,
+            seed: scene.get_seed(),
+            transfer: scene.transfer_function(),
+            brackets: Vec::new(),
+            burn_in: None,
+        }
+    }
+
+    /// Collect the settings of `scene` and override them with the options given in `config`
+    /// Blurred animations are saved as a single image, so they are never saved as apng
+    ///
+    /// # Errors
+    ///
+    /// Returns an ``InputError`` if exposure brackets are requested for an animation or render
+    /// layers
+    pub fn merge(scene: &Scene, config: &Config) -> Result<RenderSettings, InputError> {
+        let mut settings = Self::from_scene(scene);
+        if let Some(samples) = config.samples() {
+            settings.samples = samples;
+        }
+        if let Some(resolution) = config.resolution() {
+            settings.resolution = resolution;
+        }
+        if let Some(factor) = config.scale() {
+            settings.scale = Some((factor, settings.resolution));
+            settings.resolution = Camera::scaled_resolution(settings.resolution, factor);
+        }
+        if let Some(max_bounces) = config.max_bounces() {
+            settings.max_bounces = max_bounces;
+        }
+        if let Some(seed) = config.seed() {
+            settings.seed = Some(seed);
+        }
+        settings.max_feature_rays = config.max_feature_rays();
+        settings.blur = config.blur();
+        if settings.blur && settings.format == OutputFormat::Apng {
+            settings.format = OutputFormat::Png;
+        }
+        if config.ppm() && settings.format != OutputFormat::Apng {
+            settings.format = OutputFormat::Ppm;
+        }
+        settings.channels = config.channels();
+        settings.brackets = config.bracket().to_vec();
+        if !settings.brackets.is_empty() && (settings.frames > 1 || !config.layers().is_empty()) {
+            return Err(InputError::new(
+                "Error while bracketing exposures".to_string(),
+                "Exposure brackets are only supported for still images without render layers"
+                    .to_string(),
+            ));
+        }
+        Ok(settings)
+    }
+
+    /// Reduce the settings to render a thumbnail that fits into `size` x `size` pixels
+    /// The resolution is scaled like with `--scale`, so the thumbnail shows the same part of the
+    /// scene. Only the first frame is rendered, without super-sampling and with fewer bounces and
+    /// feature rays. Renders without a seed use the seed 0, so thumbnails are reproducible
+    #[allow(clippy::cast_precision_loss)]
+    pub fn reduce_to_thumbnail(&mut self, size: u32) {
+        let full = self.scale.map_or(self.resolution, |(_, full)| full);
+        let factor = size as f32 / full.0.max(full.1).max(1) as f32;
+        if factor < 1. {
+            self.scale = Some((factor, full));
+            self.resolution = Camera::scaled_resolution(full, factor);
+        }
+        self.samples = 0;
+        self.max_bounces = self.max_bounces.min(THUMBNAIL_MAX_BOUNCES);
+        self.max_feature_rays = self.max_feature_rays.min(THUMBNAIL_MAX_FEATURE_RAYS);
+        self.frames = 1;
+        self.blur = false;
+        self.seed.get_or_insert(0);
+    }
+
+    /// Change `scene` to render with these settings
+    pub fn apply(&self, scene: &mut Scene) {
+        scene.add_samples(self.samples);
+        let (full, factor) = match self.scale {
+            Some((factor, full)) => (full, Some(factor)),
+            None => (self.resolution, None),
+        };
+        if scene.get_dimensions() != full {
+            scene.set_dimensions(full.0, full.1);
+        }
+        if let Some(factor) = factor {
+            scene.scale_dimensions(factor);
+        }
+        scene.set_max_bounces(self.max_bounces);
+        scene.set_max_feature_rays(self.max_feature_rays);
+        if let Some(seed) = self.seed {
+            scene.set_seed(seed);
+        }
+    }
+}
+
+impl Display for RenderSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (width, height) = self.resolution;
+        writeln!(f, "Render settings:")?;
+        writeln!(f, "    resolution:  {width}x{height}")?;
+        if let Some((factor, (width, height))) = self.scale {
+            writeln!(f, "    scale:       {factor} of {width}x{height}")?;
+        }
+        writeln!(f, "    samples:     {}", self.samples)?;
+        writeln!(f, "    max bounces: {}", self.max_bounces)?;
+        writeln!(f, "    ray budget:  {}", self.max_feature_rays)?;
+        writeln!(f, "    frames:      {}", self.frames)?;
+        writeln!(f, "    blur:        {}", self.blur)?;
+        writeln!(f, "    format:      {}", self.format)?;
+        writeln!(f, "    channels:    {}", self.channels)?;
+        match self.seed {
+            Some(seed) => writeln!(f, "    seed:        {seed}")?,
+            None => writeln!(f, "    seed:        none")?,
+        }
+        if !self.brackets.is_empty() {
+            let evs: Vec<_> = self.brackets.iter().map(ToString::to_string).collect();
+            writeln!(f, "    brackets:    {}", evs.join(", "))?;
+        }
+        if let Some(name) = &self.burn_in {
+            writeln!(f, "    burn-in:     {name}")?;
+        }
+        write!(f, "    transfer:    {:?}", self.transfer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::builtin_scene;
+
+    fn build(args: &[&str]) -> Result<Option<Config>, InputError> {
+        let args: Vec<_> = ["test", "scene.xml"]
+            .iter()
+            .chain(args)
+            .map(ToString::to_string)
+            .collect();
+        Config::build_with_default(&args, None)
+    }
+
+    fn config(args: &[&str]) -> Config {
+        build(args).unwrap().unwrap()
+    }
+
+    #[test]
+    fn scene_values_are_used_without_options() {
+        let scene = builtin_scene("spheres", (64, 48)).unwrap();
+        let settings = RenderSettings::merge(&scene, &config(&[])).unwrap();
+        assert_eq!(settings, RenderSettings::from_scene(&scene));
+        assert_eq!(settings.resolution, (64, 48));
+        assert_eq!(settings.samples, scene.get_samples());
+        assert_eq!(settings.format, OutputFormat::Png);
+    }
+
+    #[test]
+    fn commandline_overrides_scene() {
+        let mut scene = builtin_scene("spheres", (64, 48)).unwrap();
+        let settings = RenderSettings::merge(
+            &scene,
+            &config(&[
+                "--samples",
+                "3",
+                "--resolution",
+                "32x16",
+                "--max-bounces",
+                "1",
+                "--seed",
+                "7",
+                "--ppm",
+            ]),
+        )
+        .unwrap();
+        assert_eq!(settings.samples, 3);
+        assert_eq!(settings.resolution, (32, 16));
+        assert_eq!(settings.max_bounces, 1);
+        assert_eq!(settings.seed, Some(7));
+        assert_eq!(settings.format, OutputFormat::Ppm);
+
+        settings.apply(&mut scene);
+        assert_eq!(RenderSettings::from_scene(&scene).samples, 3);
+        assert_eq!(scene.get_dimensions(), (32, 16));
+        assert_eq!(scene.get_max_bounces(), 1);
+        assert_eq!(scene.get_seed(), Some(7));
+
+        // invalid values are rejected while building the config
+        for invalid in [
+            &["--samples", "-1"][..],
+            &["--resolution", "32"],
+            &["--resolution", "0x16"],
+            &["--max-bounces", "many"],
+            &["--seed", "-7"],
+        ] {
+            assert!(build(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn scale_keeps_the_framing() {
+        // pixel (37, 52) of 125x75 is centered at the normalized coordinate (0.3, 0.7)
+        let full = builtin_scene("cornell", (125, 75)).unwrap();
+        let ray = full.camera().get_ray_through(37, 52, &mut rand::rng());
+        let point = ray.at(5.).unwrap();
+        for (factor, resolution) in [("0.5", (63, 38)), ("0.25", (31, 19)), ("0.3", (38, 23))] {
+            let mut scene = builtin_scene("cornell", (125, 75)).unwrap();
+            let settings = RenderSettings::merge(&scene, &config(&["--scale", factor])).unwrap();
+            assert_eq!(settings.resolution, resolution);
+            assert!(settings.to_string().contains("of 125x75"));
+            settings.apply(&mut scene);
+            assert_eq!(scene.get_dimensions(), resolution);
+
+            let (u, v) = scene.camera().project(&point).unwrap();
+            #[allow(clippy::cast_precision_loss)]
+            let (u, v) = (u / resolution.0 as f32, v / resolution.1 as f32);
+            assert!((u - 0.3).abs() < 1e-4 && (v - 0.7).abs() < 1e-4, "{u}, {v}");
+        }
+        // the scale applies to an overridden resolution as well
+        let scene = builtin_scene("cornell", (125, 75)).unwrap();
+        let config = config(&["--scale", "0.5", "--resolution", "64x32"]);
+        let settings = RenderSettings::merge(&scene, &config).unwrap();
+        assert_eq!(settings.resolution, (32, 16));
+        assert!(build(&["--scale", "0"]).is_err());
+    }
+
+    #[test]
+    fn thumbnails_fit_the_size_and_are_reproducible() {
+        let scene = builtin_scene("julia", (300, 200)).unwrap();
+        let sheet = config(&["--contact-sheet", "sheet.png", "--samples", "4"]);
+        assert_eq!(sheet.thumb_size(), 128);
+        let mut settings = RenderSettings::merge(&scene, &sheet).unwrap();
+        settings.reduce_to_thumbnail(sheet.thumb_size());
+        assert_eq!(settings.resolution, (128, 85));
+        assert_eq!(settings.scale.map(|(_, full)| full), Some((300, 200)));
+        assert_eq!((settings.samples, settings.frames), (0, 1));
+        assert!(settings.max_bounces <= THUMBNAIL_MAX_BOUNCES);
+        assert_eq!(settings.seed, Some(0));
+
+        // small scenes keep their resolution, seeds are kept
+        let small = builtin_scene("spheres", (64, 48)).unwrap();
+        let mut settings = RenderSettings::merge(&small, &config(&["--seed", "5"])).unwrap();
+        settings.reduce_to_thumbnail(128);
+        assert_eq!((settings.resolution, settings.seed), ((64, 48), Some(5)));
+
+        assert!(build(&["--thumb-size", "0"]).is_err());
+        let combined = build(&["--contact-sheet", "sheet.png", "--aov", "motion"]);
+        assert!(combined.unwrap_err().msg().contains("--aov"));
+    }
+
+    #[test]
+    fn brackets_are_rejected_for_animations() {
+        let still = builtin_scene("spheres", (8, 8)).unwrap();
+        let settings = RenderSettings::merge(&still, &config(&["--bracket", "-2, 0,2"])).unwrap();
+        assert_eq!(settings.brackets, [-2., 0., 2.]);
+        assert!(build(&["--bracket", "-2,bright"]).is_err());
+        assert!(
+            RenderSettings::merge(&still, &config(&["--bracket", "1", "--layers", "a"])).is_err()
+        );
+
+        let animated = builtin_scene("julia", (8, 8)).unwrap();
+        assert!(RenderSettings::merge(&animated, &config(&["--bracket", "1"])).is_err());
+    }
+
+    #[test]
+    fn animations_are_saved_as_apng_unless_blurred() {
+        let scene = builtin_scene("julia", (8, 8)).unwrap();
+        let settings = RenderSettings::merge(&scene, &config(&["--ppm"])).unwrap();
+        assert_eq!(settings.format, OutputFormat::Apng);
+
+        let settings = RenderSettings::merge(&scene, &config(&["--blur"])).unwrap();
+        assert_eq!(settings.format, OutputFormat::Png);
+        let settings = RenderSettings::merge(&scene, &config(&["--blur", "--ppm"])).unwrap();
+        assert_eq!(settings.format, OutputFormat::Ppm);
+    }
+}
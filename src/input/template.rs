@@ -0,0 +1,220 @@
+//! output templates
+//! Expands placeholders like `{scene}` or `{frame:04}` in templates of output paths, so a render
+//! farm can sort the images into directories by scene and date
+
+use std::{fmt::Write, path::PathBuf};
+
+use crate::misc::solar::UtcTime;
+
+use super::{paths::portable_path, InputError};
+
+/// Names of the placeholders that templates may use
+const PLACEHOLDERS: [&str; 5] = ["scene", "frame", "date", "resolution", "seed"];
+
+/// Values of the placeholders of an output template
+#[derive(Clone, Debug)]
+pub struct OutputVars {
+    /// name of the scene file without its extension
+    pub scene: String,
+    /// number of the first frame in the output file, starting at 1
+    pub frame: usize,
+    /// date of the render
+    pub date: UtcTime,
+    /// width and height of the image
+    pub resolution: (u32, u32),
+    /// seed of the render, if it has one
+    pub seed: Option<u64>,
+}
+
+impl OutputVars {
+    /// Return the value of the placeholder `name`, and whether it is a number
+    /// `None` if there is no such placeholder
+    fn value(&self, name: &str) -> Option<(String, bool)> {
+        let (w, h) = self.resolution;
+        let date = &self.date;
+        Some(match name {
+            "scene" => (self.scene.clone(), false),
+            "frame" => (self.frame.to_string(), true),
+            "date" => (
+                format!("{:04}-{:02}-{:02}", date.year, date.month, date.day),
+                false,
+            ),
+            "resolution" => (format!("{w}x{h}"), false),
+            "seed" => match self.seed {
+                Some(seed) => (seed.to_string(), true),
+                None => ("none".to_string(), false),
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Replace every placeholder `{name}` in `template` with its value from `vars`
+/// Numbers can be padded with zeros to a width `N` with `{name:0N}`, e.g. `{frame:04}`. A `}`
+/// outside of a placeholder is kept as it is
+///
+/// # Errors
+///
+/// Returns a message if a placeholder is unknown, not closed or has an invalid format
+pub(super) fn expand_template(template: &str, vars: &OutputVars) -> Result<String, String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let Some(len) = rest[start + 1..].find('}') else {
+            return Err(format!("Unclosed '{{' in '{template}'"));
+        };
+        let placeholder = &rest[start + 1..start + 1 + len];
+        let (name, format) = match placeholder.split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (placeholder, None),
+        };
+        let Some((value, is_number)) = vars.value(name) else {
+            return Err(format!(
+                "Unknown placeholder '{{{placeholder}}}' in '{template}', expected one of {}",
+                PLACEHOLDERS.map(|p| format!("{{{p}}}")).join(", ")
+            ));
+        };
+        match format {
+            None => expanded.push_str(&value),
+            Some(format) => {
+                let width = format
+                    .strip_prefix('0')
+                    .and_then(|width| width.parse::<usize>().ok())
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid format '{format}' of '{{{name}}}' in '{template}', expected a \
+                             zero and the width to pad to, like '{{{name}:04}}'"
+                        )
+                    })?;
+                if !matches!(name, "frame" | "seed") {
+                    return Err(format!(
+                        "'{{{name}}}' in '{template}' can't be padded, only {{frame}} and {{seed}} can"
+                    ));
+                }
+                if is_number {
+                    // writing to a string can't fail
+                    let _ = write!(expanded, "{value:0>width$}");
+                } else {
+                    expanded.push_str(&value);
+                }
+            }
+        }
+        rest = &rest[start + 2 + len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Check that `template` only uses known placeholders in a valid format, without expanding it
+///
+/// # Errors
+///
+/// Returns the message of [`expand_template`] for the first invalid placeholder
+pub(super) fn check_template(template: &str) -> Result<(), String> {
+    let vars = OutputVars {
+        scene: String::new(),
+        frame: 1,
+        date: UtcTime::from_unix_seconds(0),
+        resolution: (1, 1),
+        seed: None,
+    };
+    expand_template(template, &vars).map(|_| ())
+}
+
+/// Return the path of an output file from `template`, expanded with `vars`
+/// Both `/` and `\` separate the directories of the path
+///
+/// # Errors
+///
+/// Returns an error if the template is invalid, see [`expand_template`]
+pub fn output_template_path(template: &str, vars: &OutputVars) -> Result<PathBuf, InputError> {
+    expand_template(template, vars)
+        .map(|path| portable_path(&path))
+        .map_err(|msg| InputError::new("Invalid output template".to_string(), msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> OutputVars {
+        OutputVars {
+            scene: "chess".to_string(),
+            frame: 7,
+            // 2024-03-05 12:00 UTC
+            date: UtcTime::from_unix_seconds(1_709_640_000),
+            resolution: (640, 480),
+            seed: Some(42),
+        }
+    }
+
+    fn expand(template: &str) -> Result<String, String> {
+        expand_template(template, &vars())
+    }
+
+    #[test]
+    fn placeholders_are_expanded() {
+        assert_eq!(expand("{scene}").unwrap(), "chess");
+        assert_eq!(expand("{frame}").unwrap(), "7");
+        assert_eq!(expand("{date}").unwrap(), "2024-03-05");
+        assert_eq!(expand("{resolution}").unwrap(), "640x480");
+        assert_eq!(expand("{seed}").unwrap(), "42");
+        let unseeded = OutputVars {
+            seed: None,
+            ..vars()
+        };
+        assert_eq!(expand_template("{seed}", &unseeded).unwrap(), "none");
+        assert_eq!(expand_template("{seed:03}", &unseeded).unwrap(), "none");
+
+        assert_eq!(
+            expand("renders/{scene}/{date}/{frame:04}.png").unwrap(),
+            "renders/chess/2024-03-05/0007.png"
+        );
+        assert_eq!(expand("no placeholders}").unwrap(), "no placeholders}");
+    }
+
+    #[test]
+    fn numbers_are_padded_with_zeros() {
+        assert_eq!(expand("{frame:04}").unwrap(), "0007");
+        assert_eq!(expand("{frame:01}").unwrap(), "7");
+        assert_eq!(expand("{seed:05}").unwrap(), "00042");
+        // numbers wider than the width are kept whole
+        let late = OutputVars {
+            frame: 12_345,
+            ..vars()
+        };
+        assert_eq!(expand_template("{frame:03}", &late).unwrap(), "12345");
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        let err = expand("renders/{camera}.png").unwrap_err();
+        assert!(
+            err.contains("'{camera}'") && err.contains("{scene}"),
+            "{err}"
+        );
+        assert!(expand("renders/{scene").unwrap_err().contains("Unclosed"));
+        assert!(expand("{frame:4}").unwrap_err().contains("Invalid format"));
+        assert!(expand("{frame:0x}").is_err());
+        assert!(expand("{frame:}").is_err());
+        assert!(expand("{scene:04}")
+            .unwrap_err()
+            .contains("can't be padded"));
+        assert!(expand("{}").is_err());
+
+        assert!(check_template("renders/{scene}/{frame:04}").is_ok());
+        assert!(check_template("renders/{camera}").is_err());
+    }
+
+    #[test]
+    fn template_paths_accept_both_separators() {
+        let expected: PathBuf = ["renders", "chess", "0007.png"].iter().collect();
+        assert_eq!(
+            output_template_path("renders\\{scene}/{frame:04}.png", &vars()).unwrap(),
+            expected
+        );
+        let err = output_template_path("{nope}", &vars()).unwrap_err();
+        assert_eq!(err.title(), "Invalid output template");
+    }
+}
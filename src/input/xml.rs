@@ -1,12 +1,9 @@
 use quick_xml;
-use std::{
-    error::Error,
-    fs,
-    path::{Path, PathBuf},
-};
+use std::{error::Error, fs, path::Path};
 
-use super::{serial_types::SerialScene, InputError};
-use crate::objects::Scene;
+use super::{compat, schema, serial_types::SerialScene, InputError};
+use crate::misc::log;
+use crate::objects::{Material, Scene};
 
 /// convert any error to a specific input error
 fn err_to_input_err<E>(err: E, path: &Path) -> InputError
@@ -22,25 +19,253 @@ where
     )
 }
 
+/// What happens when a texture of a material can't be loaded
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MissingTextures {
+    /// loading the scene fails
+    #[default]
+    Error,
+    /// the texture is replaced with a magenta and black checker, see
+    /// [`crate::image::Image::missing_texture`]
+    Placeholder,
+}
+
+/// Options that influence how a scene is loaded
+#[derive(Debug, Default, Clone)]
+pub struct LoadOptions {
+    /// textures with a width or height above this limit are downscaled while loading
+    pub max_texture_size: Option<u32>,
+    /// name of the camera that renders the scene, if the scene defines multiple cameras
+    pub camera: Option<String>,
+    /// check the file against the schema of the scene format before it is parsed
+    pub strict_schema: bool,
+    /// keep `${VAR}` in file names as it is, instead of replacing it with the environment
+    /// variable
+    pub no_env_expansion: bool,
+    /// maximum number of triangles of all meshes in the scene
+    pub max_triangles: Option<usize>,
+    /// maximum number of pixels of a single texture, checked before it is decoded
+    pub max_texture_pixels: Option<u64>,
+    /// meshes with more triangles are reduced to about this many triangles
+    pub decimate_to: Option<usize>,
+    /// what happens when the texture of a material can't be loaded
+    pub missing_textures: MissingTextures,
+}
+
+/// Read the scene file at `path` and check it against the schema, if `options` ask for it
+/// Files written for an older version of the scene format are upgraded to the current one
+fn read_scene_file(path: &Path, options: &LoadOptions) -> Result<String, InputError> {
+    let mut content = fs::read_to_string(path).map_err(|err| err_to_input_err(err, path))?;
+    let upgraded = compat::upgrade(&content).map_err(|msg| {
+        InputError::new(
+            format!(
+                "Error while reading file '{}'",
+                path.to_str().unwrap_or("<INVALID PATH>")
+            ),
+            msg,
+        )
+    })?;
+    if let Some((upgraded, notice)) = upgraded {
+        log::note(&format!("'{}': {notice}", path.display()));
+        content = upgraded;
+    }
+    if options.strict_schema {
+        schema::validate(&content).map_err(|msg| {
+            InputError::new(
+                format!(
+                    "Error while validating xml file {}",
+                    path.to_str().unwrap_or("<INVALID PATH>")
+                ),
+                msg,
+            )
+        })?;
+    }
+    Ok(content)
+}
+
 /// Read in an xml fie from the specified path and parse to a scene object
 /// The xml file should have the correct format as specified [here](https://teaching.vda.univie.ac.at/graphics/25s/Labs/Lab3/lab2_file_specification.html)
 ///
 /// # Errors
 ///
-/// Returns an error when the file could not be read or parsed correctly
-pub fn file_to_scene(path: &str) -> Result<Scene, InputError> {
-    let mut path = PathBuf::from(path);
-    let content = fs::read_to_string(&path).map_err(|err| err_to_input_err(err, &path))?;
+/// Returns an error when the file could not be read or parsed correctly, or if it does not match
+/// the schema and `options` ask for strict checking
+pub fn file_to_scene(path: &str, options: &LoadOptions) -> Result<Scene, InputError> {
+    let path = Path::new(path);
+    let content = read_scene_file(path, options)?;
+
+    let scene: SerialScene =
+        quick_xml::de::from_str(&content).map_err(|err| err_to_input_err(err, path))?;
+
+    scene.convert_to_scene(path, options)
+}
+
+/// Parse the scene file `content` as if it was read from `path`
+/// Files referenced by the scene are looked up relative to `path`
+///
+/// # Errors
+///
+/// Returns an error when the scene could not be parsed correctly
+#[cfg(any(test, feature = "testing"))]
+pub fn str_to_scene(
+    content: &str,
+    path: &Path,
+    options: &LoadOptions,
+) -> Result<Scene, InputError> {
+    let scene: SerialScene =
+        quick_xml::de::from_str(content).map_err(|err| err_to_input_err(err, path))?;
+    scene.convert_to_scene(path, options)
+}
+
+/// Entry point for fuzzing the scene parser
+/// Any input has to be either converted to a scene or rejected with an error, but never cause a
+/// panic. Files referenced by the scene are looked up relative to the working directory
+#[cfg(fuzzing)]
+pub fn fuzz_file_to_scene(data: &[u8]) {
+    let content = String::from_utf8_lossy(data);
+    if let Ok(scene) = quick_xml::de::from_str::<SerialScene>(&content) {
+        let _ = scene.convert_to_scene(Path::new(""), &LoadOptions::default());
+    }
+}
+
+/// Read in the xml scene file at the specified path and load the material `name` from its
+/// material library
+///
+/// # Errors
+///
+/// Returns an error when the scene or the library could not be read or parsed correctly, or the
+/// library has no material with that name
+/// Besides the material, returns the textures of the library that were replaced with a placeholder
+pub fn file_to_material(
+    path: &str,
+    name: &str,
+    options: &LoadOptions,
+) -> Result<(Material, Vec<String>), InputError> {
+    let path = Path::new(path);
+    let content = read_scene_file(path, options)?;
 
     let scene: SerialScene =
-        quick_xml::de::from_str(&content).map_err(|err| err_to_input_err(err, &path))?;
+        quick_xml::de::from_str(&content).map_err(|err| err_to_input_err(err, path))?;
 
-    scene.convert_to_scene(&mut path)
+    scene.load_material(path, options, name)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::image::{uv_grid_color, UV_GRID_LINE_WIDTH};
+    use crate::input::env_vars::tests::EnvGuard;
+    use crate::input::serial_types::SerialCamera;
+    use crate::math::{Color, Point3, Ray, Vec3};
+    use crate::objects::{BounceLimit, Camera, ClearCoat, Light, ThinFilm};
+
+    /// white phong material for surfaces whose material doesn't matter
+    const MATERIAL: &str = r#"
+        <material_solid>
+            <color r="1" g="1" b="1"/>
+            <phong ka="1.0" kd="1.0" ks="1.0" exponent="1"/>
+            <reflectance r="0"/>
+            <transmittance t="0"/>
+            <refraction iof="1"/>
+        </material_solid>"#;
+
+    /// the parts of a scene file a test cares about, [`TestScene::xml`] fills in the rest
+    #[derive(Default)]
+    struct TestScene<'a> {
+        /// attributes of the `scene` element besides the output file
+        attributes: &'a str,
+        /// the output file, `myImage.png` if empty
+        output_file: &'a str,
+        /// attributes of the background color, black if empty
+        background_color: &'a str,
+        /// elements before the camera, like `animated`, `output` or `material_library`
+        header: &'a str,
+        /// the camera elements, the camera of [`camera_xml`] if empty
+        cameras: &'a str,
+        lights: &'a str,
+        surfaces: &'a str,
+    }
+
+    impl TestScene<'_> {
+        fn xml(&self) -> String {
+            let or = |part: &str, default: &str| {
+                if part.is_empty() {
+                    default.to_string()
+                } else {
+                    part.to_string()
+                }
+            };
+            format!(
+                r#"
+                <scene output_file="{}" {}>
+                    <background_color {}/>
+                    {}
+                    {}
+                    <lights>{}</lights>
+                    <surfaces>{}</surfaces>
+                </scene>
+                "#,
+                or(self.output_file, "myImage.png"),
+                self.attributes,
+                or(self.background_color, r#"r="0.0" g="0.0" b="0.0""#),
+                self.header,
+                or(self.cameras, &camera_xml("camera", 4)),
+                self.lights,
+                self.surfaces
+            )
+        }
+
+        fn load(&self) -> Result<Scene, InputError> {
+            self.load_with(&LoadOptions::default())
+        }
+
+        fn load_with(&self, options: &LoadOptions) -> Result<Scene, InputError> {
+            let serial_scene: SerialScene = quick_xml::de::from_str(&self.xml()).unwrap();
+            serial_scene.convert_to_scene(Path::new(""), options)
+        }
+    }
+
+    /// load a scene with only the given `surfaces`
+    fn load_surfaces(surfaces: &str) -> Result<Scene, InputError> {
+        TestScene {
+            surfaces,
+            ..TestScene::default()
+        }
+        .load()
+    }
+
+    fn camera_xml(name: &str, horizontal: u32) -> String {
+        format!(
+            r#"
+            <camera name="{name}">
+                <position x="0" y="0" z="1"/>
+                <lookat x="0" y="0" z="0"/>
+                <up x="0" y="1" z="0"/>
+                <horizontal_fov angle="45"/>
+                <resolution horizontal="{horizontal}" vertical="16"/>
+                <max_bounces n="1"/>
+            </camera>
+            "#
+        )
+    }
+
+    #[test]
+    fn fuzz_corpus_does_not_panic() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus/scene");
+        let mut entries = 0;
+        for entry in fs::read_dir(corpus).unwrap() {
+            let path = entry.unwrap().path();
+            let data = fs::read(&path).unwrap();
+            let loaded = quick_xml::de::from_str::<SerialScene>(&String::from_utf8_lossy(&data))
+                .ok()
+                .map(|scene| scene.convert_to_scene(Path::new(""), &LoadOptions::default()));
+            if path.file_name().is_some_and(|name| name == "minimal.xml") {
+                assert!(matches!(loaded, Some(Ok(_))));
+            }
+            entries += 1;
+        }
+        assert!(entries > 0);
+    }
 
     #[test]
     fn parse_full_example_no_panic() {
@@ -165,7 +390,9 @@ mod tests {
         "#;
 
         let serial_scene: SerialScene = quick_xml::de::from_str(xml).unwrap();
-        let scene: Scene = serial_scene.convert_to_scene(&mut PathBuf::new()).unwrap();
+        let scene: Scene = serial_scene
+            .convert_to_scene(Path::new(""), &LoadOptions::default())
+            .unwrap();
 
         assert_eq!(scene.get_output(), "myImage.png");
         assert_eq!(scene.get_dimensions(), (1920, 1080));
@@ -180,6 +407,9 @@ mod tests {
         <scene output_file="myImage.png">
             <background_color r="1.0" g="0.0" b="0.0"/>
             <super_sampling samples="16" />
+            <animated frames="2" fps="24" loops="3" pingpong="true">
+                <delay frame="0" ms="500" />
+            </animated>
             <camera>
                 <position x="1.0" y="-2.0E-10" z="-3"/>
                 <lookat x="1" y="2" z="3"/>
@@ -215,6 +445,1074 @@ mod tests {
 
         let serial_scene: SerialScene = quick_xml::de::from_str(xml).unwrap();
 
-        assert!(serial_scene.convert_to_scene(&mut PathBuf::new()).is_ok());
+        assert!(serial_scene
+            .convert_to_scene(Path::new(""), &LoadOptions::default())
+            .is_ok());
+    }
+
+    #[test]
+    fn animation_mismatch_warnings() {
+        let animation_warning = |animated: bool, endposition: bool| {
+            let endposition = if endposition {
+                r#"<endposition x="1" y="0" z="-3"/>"#
+            } else {
+                ""
+            };
+            TestScene {
+                header: if animated {
+                    r#"<animated frames="10" fps="10"/>"#
+                } else {
+                    ""
+                },
+                surfaces: &format!(
+                    r#"<sphere radius="1"><position x="0" y="0" z="-3"/>{endposition}{MATERIAL}</sphere>"#
+                ),
+                ..TestScene::default()
+            }
+            .load()
+            .unwrap()
+            .animation_warning()
+        };
+        assert!(animation_warning(false, false).is_none());
+        assert!(animation_warning(true, true).is_none());
+        assert!(animation_warning(true, false).is_some());
+        assert!(animation_warning(false, true).is_some());
+    }
+
+    #[test]
+    fn material_reference_matches_inline_material() {
+        let material = r#"
+            <material_solid>
+                <color r="0.5" g="0.2" b="0.1"/>
+                <phong ka="0.3" kd="0.9" ks="1.0" exponent="20"/>
+                <reflectance r="0.4"/>
+                <transmittance t="0.0"/>
+                <refraction iof="1.0"/>
+            </material_solid>
+        "#;
+        let library = format!(
+            r#"
+            <materials>
+                <named_material name="rust_metal">{material}</named_material>
+            </materials>
+            "#
+        );
+        let scene = |header: &str, surface_material: &str| {
+            TestScene {
+                header,
+                surfaces: &format!(
+                    r#"<sphere radius="1"><position x="0" y="0" z="-3"/>{surface_material}</sphere>"#
+                ),
+                ..TestScene::default()
+            }
+            .xml()
+        };
+
+        let dir = std::env::temp_dir().join("rt_material_library_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("materials.xml"), library).unwrap();
+        let referenced = dir.join("referenced.xml");
+        fs::write(
+            &referenced,
+            scene(
+                r#"<material_library file="materials.xml"/>"#,
+                r#"<material_ref name="rust_metal"/>"#,
+            ),
+        )
+        .unwrap();
+
+        let serial_scene: SerialScene = quick_xml::de::from_str(&scene("", material)).unwrap();
+        let inline = serial_scene
+            .convert_to_scene(Path::new(""), &LoadOptions::default())
+            .unwrap();
+        let referenced =
+            file_to_scene(referenced.to_str().unwrap(), &LoadOptions::default()).unwrap();
+
+        assert_eq!(
+            inline.get_surfaces()[0].material(),
+            referenced.get_surfaces()[0].material()
+        );
+    }
+
+    #[test]
+    fn assets_are_resolved_from_subdirectories() {
+        let dir = std::env::temp_dir().join("rt_asset_subdirectories_test");
+        fs::create_dir_all(dir.join("meshes")).unwrap();
+        fs::create_dir_all(dir.join("textures")).unwrap();
+        fs::write(
+            dir.join("meshes/triangle.obj"),
+            "v 0 0 -3\nv 1 0 -3\nv 0 1 -3\nvn 0 0 1\nvt 0 0\nf 1/1/1 2/1/1 3/1/1\n",
+        )
+        .unwrap();
+        crate::image::Image::new(2, 2, 1)
+            .save_png(&mut dir.join("textures/checker.png"))
+            .unwrap();
+
+        let textured = r#"
+            <material_textured>
+                <texture name="textures/checker.png"/>
+                <phong ka="0.3" kd="0.9" ks="1.0" exponent="20"/>
+                <reflectance r="0.0"/>
+                <transmittance t="0.0"/>
+                <refraction iof="1.0"/>
+            </material_textured>"#;
+        let scene = |extra: &str| {
+            TestScene {
+                surfaces: &format!(
+                    r#"
+                    <mesh name="meshes/triangle.obj">{textured}</mesh>
+                    <sphere radius="1"><position x="0" y="0" z="-3"/>{textured}</sphere>
+                    {extra}
+                    "#
+                ),
+                ..TestScene::default()
+            }
+            .xml()
+        };
+        let path = dir.join("scene.xml");
+        fs::write(&path, scene("")).unwrap();
+        let loaded = file_to_scene(path.to_str().unwrap(), &LoadOptions::default()).unwrap();
+        assert_eq!(loaded.get_surfaces().len(), 2);
+
+        // errors of later surfaces still name the scene file
+        let unknown = r#"
+            <sphere radius="1">
+                <position x="0" y="0" z="-3"/>
+                <material_ref name="missing"/>
+            </sphere>"#;
+        fs::write(&path, scene(unknown)).unwrap();
+        let err = file_to_scene(path.to_str().unwrap(), &LoadOptions::default()).unwrap_err();
+        assert!(err.title().ends_with("scene.xml':"), "{}", err.title());
+    }
+
+    #[test]
+    fn missing_textures_fail_or_are_replaced_with_placeholders() {
+        let xml = TestScene {
+            surfaces: r#"
+                <sphere radius="1">
+                    <position x="0" y="0" z="-3"/>
+                    <material_textured>
+                        <texture name="textures/does_not_exist.png"/>
+                        <phong ka="0.3" kd="0.9" ks="1.0" exponent="20"/>
+                        <reflectance r="0.0"/>
+                        <transmittance t="0.0"/>
+                        <refraction iof="1.0"/>
+                    </material_textured>
+                </sphere>"#,
+            ..TestScene::default()
+        }
+        .xml();
+        let path = std::env::temp_dir().join("rt_missing_textures_test/scene.xml");
+        let load = |missing_textures| {
+            let options = LoadOptions {
+                missing_textures,
+                ..LoadOptions::default()
+            };
+            str_to_scene(&xml, &path, &options)
+        };
+
+        let err = load(MissingTextures::Error).unwrap_err();
+        assert!(err.title().contains("does_not_exist.png"), "{err}");
+
+        let scene = load(MissingTextures::Placeholder).unwrap();
+        assert_eq!(
+            scene.get_missing_textures(),
+            ["textures/does_not_exist.png"]
+        );
+        let material = scene.get_surfaces()[0].material();
+        let (magenta, black) = (Color::new(1., 0., 1.), Color::zero());
+        // the first cell of the checker is magenta and its neighbour black
+        let first = material.texture_color((0.01, 0.01), 0.);
+        let second = material.texture_color((0.135, 0.01), 0.);
+        assert!((first - magenta).length() < 1e-2, "{first:?}");
+        assert!((second - black).length() < 1e-2, "{second:?}");
+    }
+
+    #[test]
+    fn missing_textures_of_the_material_library_are_reported() {
+        let textured = r#"
+            <material_textured>
+                <texture name="gone.png"/>
+                <phong ka="0.3" kd="0.9" ks="1.0" exponent="20"/>
+                <reflectance r="0.0"/>
+                <transmittance t="0.0"/>
+                <refraction iof="1.0"/>
+            </material_textured>
+        "#;
+        let dir = std::env::temp_dir().join("rt_missing_library_textures_test");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("materials.xml"),
+            format!(
+                r#"
+                <materials>
+                    <named_material name="first">{textured}</named_material>
+                    <named_material name="second">{textured}</named_material>
+                </materials>
+                "#
+            ),
+        )
+        .unwrap();
+        let path = dir.join("scene.xml");
+        let xml = TestScene {
+            header: r#"<material_library file="materials.xml"/>"#,
+            surfaces: &format!(
+                r#"
+                <sphere radius="1">
+                    <position x="0" y="0" z="-3"/>
+                    <material_ref name="first"/>
+                </sphere>
+                <sphere radius="1">
+                    <position x="0" y="0" z="-6"/>
+                    {}
+                </sphere>"#,
+                textured.replace("gone.png", "also_gone.png")
+            ),
+            ..TestScene::default()
+        }
+        .xml();
+        fs::write(&path, xml).unwrap();
+        let options = LoadOptions {
+            missing_textures: MissingTextures::Placeholder,
+            ..LoadOptions::default()
+        };
+
+        // the texture used by both library materials is listed once
+        let scene = file_to_scene(path.to_str().unwrap(), &options).unwrap();
+        assert_eq!(scene.get_missing_textures(), ["gone.png", "also_gone.png"]);
+        let (_, missing) = file_to_material(path.to_str().unwrap(), "second", &options).unwrap();
+        assert_eq!(missing, ["gone.png"]);
+    }
+
+    #[test]
+    fn environment_variables_in_output_file() {
+        let _env = EnvGuard::set(&[("RT_TEST_SCENE_JOB", "job7")]);
+        let convert = |output_file: &str, options: &LoadOptions| {
+            let xml = TestScene {
+                output_file,
+                ..TestScene::default()
+            }
+            .xml();
+            str_to_scene(&xml, Path::new("scene.xml"), options)
+        };
+
+        let expanded = convert("${RT_TEST_SCENE_JOB}/beauty.png", &LoadOptions::default()).unwrap();
+        assert_eq!(expanded.get_output(), "job7/beauty.png");
+
+        let err =
+            convert("${RT_TEST_SCENE_UNSET}/beauty.png", &LoadOptions::default()).unwrap_err();
+        assert!(err.msg().contains("RT_TEST_SCENE_UNSET"));
+
+        let options = LoadOptions {
+            no_env_expansion: true,
+            ..Default::default()
+        };
+        let kept = convert("${RT_TEST_SCENE_UNSET}/beauty.png", &options).unwrap();
+        assert_eq!(kept.get_output(), "${RT_TEST_SCENE_UNSET}/beauty.png");
+    }
+
+    #[test]
+    fn mesh_and_texture_limits() {
+        use std::fmt::Write;
+
+        let dir = std::env::temp_dir().join("rt_limits_test");
+        fs::create_dir_all(&dir).unwrap();
+        // 20 x 20 quads = 800 triangles
+        let mut obj = String::from("vn 0 0 1\nvt 0 0\n");
+        for y in 0..=20 {
+            for x in 0..=20 {
+                writeln!(obj, "v {x} {y} -5").unwrap();
+            }
+        }
+        for y in 0..20 {
+            for x in 0..20 {
+                let (a, b) = (y * 21 + x + 1, (y + 1) * 21 + x + 1);
+                writeln!(obj, "f {a}/1/1 {}/1/1 {b}/1/1", a + 1).unwrap();
+                writeln!(obj, "f {}/1/1 {}/1/1 {b}/1/1", a + 1, b + 1).unwrap();
+            }
+        }
+        fs::write(dir.join("grid.obj"), obj).unwrap();
+        crate::image::Image::new(4, 4, 1)
+            .save_png(&mut dir.join("small.png"))
+            .unwrap();
+        let path = dir.join("scene.xml");
+        let xml = TestScene {
+            surfaces: r#"
+                <mesh name="grid.obj">
+                    <material_textured>
+                        <texture name="small.png"/>
+                        <phong ka="0.3" kd="0.9" ks="1.0" exponent="20"/>
+                        <reflectance r="0.0"/>
+                        <transmittance t="0.0"/>
+                        <refraction iof="1.0"/>
+                    </material_textured>
+                </mesh>"#,
+            ..TestScene::default()
+        }
+        .xml();
+        fs::write(&path, xml).unwrap();
+        let load = |options: LoadOptions| file_to_scene(path.to_str().unwrap(), &options);
+
+        let scene = load(LoadOptions {
+            max_triangles: Some(800),
+            max_texture_pixels: Some(16),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(scene.get_surfaces()[0].triangle_count(), Some(800));
+
+        let err = load(LoadOptions {
+            max_triangles: Some(500),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err
+            .msg()
+            .contains("The mesh has 800 triangles, but only 500 more"));
+
+        let scene = load(LoadOptions {
+            max_triangles: Some(500),
+            decimate_to: Some(300),
+            ..Default::default()
+        })
+        .unwrap();
+        let count = scene.get_surfaces()[0].triangle_count().unwrap();
+        assert!(count.abs_diff(300) <= 30, "{count}");
+
+        let err = load(LoadOptions {
+            max_texture_pixels: Some(15),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(err.msg().contains("4x4 = 16 pixels, but at most 15"));
+    }
+
+    #[test]
+    fn gradient_material_sorts_stops() {
+        let gradient_scene = |stops: &str| {
+            TestScene {
+                surfaces: &format!(
+                    r#"
+                    <sphere radius="1">
+                        <position x="0" y="0" z="-3"/>
+                        <material_gradient axis="v">
+                            {stops}
+                            <phong ka="0.3" kd="0.9" ks="1.0" exponent="20"/>
+                            <reflectance r="0.0"/>
+                            <transmittance t="0.0"/>
+                            <refraction iof="1.0"/>
+                        </material_gradient>
+                    </sphere>"#
+                ),
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let scene = gradient_scene(
+            r#"
+            <stop position="1.0" r="0" g="0" b="1"/>
+            <stop position="0.0" r="1" g="0" b="0"/>
+            "#,
+        )
+        .unwrap();
+        let material = scene.get_surfaces()[0].material();
+        assert_eq!(material.texture_color((1., 0.), 0.), Color::new(1., 0., 0.));
+        assert_eq!(material.texture_color((0., 1.), 0.), Color::new(0., 0., 1.));
+        assert_eq!(
+            material.texture_color((0., 0.5), 0.),
+            Color::new(0.5, 0., 0.5)
+        );
+
+        assert!(gradient_scene("").is_err());
+    }
+
+    #[test]
+    fn background_per_ray_type() {
+        // a mirror sphere in the center reflects the secondary background
+        let background_scene = |header: &str| {
+            TestScene {
+                background_color: r#"r="0.0" g="1.0" b="0.0""#,
+                header,
+                cameras: r#"
+                    <camera>
+                        <position x="0" y="0" z="0"/>
+                        <lookat x="0" y="0" z="-1"/>
+                        <up x="0" y="1" z="0"/>
+                        <horizontal_fov angle="45"/>
+                        <resolution horizontal="9" vertical="9"/>
+                        <max_bounces n="1"/>
+                    </camera>"#,
+                surfaces: r#"
+                    <sphere radius="1">
+                        <position x="0" y="0" z="-3"/>
+                        <material_solid>
+                            <color r="1.0" g="1.0" b="1.0"/>
+                            <phong ka="0.0" kd="0.0" ks="0.0" exponent="1"/>
+                            <reflectance r="1.0"/>
+                            <transmittance t="0.0"/>
+                            <refraction iof="1.0"/>
+                        </material_solid>
+                    </sphere>"#,
+                ..TestScene::default()
+            }
+            .load()
+            .unwrap()
+        };
+        let green = Color::new(0., 1., 0.);
+        let red = Color::new(1., 0., 0.);
+
+        let plain = background_scene("");
+        assert_eq!(plain.trace_pixel(4, 4), green);
+        assert_eq!(plain.trace_pixel(0, 0), green);
+
+        let secondary = background_scene(
+            r#"<background><secondary><color r="1" g="0" b="0"/></secondary></background>"#,
+        );
+        assert_eq!(secondary.trace_pixel(4, 4), red);
+        assert_eq!(secondary.trace_pixel(0, 0), green);
+
+        let gradient = background_scene(
+            r#"
+            <background>
+                <gradient>
+                    <bottom r="1" g="0" b="0"/>
+                    <top r="0" g="0" b="1"/>
+                </gradient>
+                <secondary><color r="1" g="0" b="0"/></secondary>
+            </background>
+            "#,
+        );
+        let (bottom, top) = (gradient.trace_pixel(0, 0), gradient.trace_pixel(0, 8));
+        assert!(bottom[0] > bottom[2] && top[2] > top[0]);
+        assert_eq!(gradient.trace_pixel(4, 4), red);
+    }
+
+    #[test]
+    fn parse_bounce_limit() {
+        let camera = |max_bounces: &str| {
+            let xml = camera_xml("camera", 4).replace(r#"<max_bounces n="1"/>"#, max_bounces);
+            quick_xml::de::from_str::<SerialCamera>(&xml).map(Camera::from)
+        };
+        let limit = |max_bounces| camera(max_bounces).unwrap().get_bounce_limit();
+        assert_eq!(limit(r#"<max_bounces n="8"/>"#), BounceLimit::Black);
+        assert_eq!(
+            limit(r#"<max_bounces n="8" at_limit="background"/>"#),
+            BounceLimit::Background
+        );
+        assert_eq!(
+            limit(r#"<max_bounces n="8" at_limit="ambient"/>"#),
+            BounceLimit::Ambient
+        );
+        assert!(camera(r#"<max_bounces n="8" at_limit="grey"/>"#).is_err());
+    }
+
+    /// load `scene`, asking for the camera `camera` on the commandline
+    fn with_camera_option(scene: &TestScene, camera: Option<&str>) -> Result<Scene, InputError> {
+        scene.load_with(&LoadOptions {
+            camera: camera.map(ToString::to_string),
+            ..LoadOptions::default()
+        })
+    }
+
+    #[test]
+    fn select_named_camera() {
+        let cameras = format!(
+            "<cameras>{}{}</cameras>",
+            camera_xml("wide", 32),
+            camera_xml("closeup", 8)
+        );
+        let scene = |attributes| TestScene {
+            attributes,
+            cameras: &cameras,
+            ..TestScene::default()
+        };
+
+        let first = scene("").load().unwrap();
+        assert_eq!(first.get_dimensions(), (32, 16));
+
+        let active = scene(r#"active_camera="closeup""#).load().unwrap();
+        assert_eq!(active.get_dimensions(), (8, 16));
+
+        // the commandline overrides the scene
+        let cli = with_camera_option(&scene(r#"active_camera="closeup""#), Some("wide")).unwrap();
+        assert_eq!(cli.get_dimensions(), (32, 16));
+
+        // a single camera is still valid
+        let single = TestScene {
+            cameras: &camera_xml("only", 4),
+            ..TestScene::default()
+        };
+        assert_eq!(single.load().unwrap().get_dimensions(), (4, 16));
+    }
+
+    #[test]
+    fn camera_switch_from_frame() {
+        let switch = |frame: usize, horizontal: u32| {
+            format!(
+                r#"
+                <camera_switch frame="{frame}">
+                    <camera name="side">
+                        <position x="4" y="0" z="0"/>
+                        <lookat x="0" y="0" z="0"/>
+                        <up x="0" y="1" z="0"/>
+                        <horizontal_fov angle="45"/>
+                        <resolution horizontal="{horizontal}" vertical="16"/>
+                        <max_bounces n="1"/>
+                    </camera>
+                </camera_switch>
+                "#
+            )
+        };
+        let scene = |switch: &str| {
+            TestScene {
+                header: r#"<animated frames="10" fps="10"/>"#,
+                cameras: &format!("{}{switch}", camera_xml("front", 16)),
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let mut scene_with_switch = scene(&switch(5, 16)).unwrap();
+
+        let position = |scene: &Scene| {
+            *scene
+                .camera()
+                .get_ray_through(8, 8, &mut rand::rng())
+                .orig()
+        };
+        for frame in 0..10 {
+            scene_with_switch.set_frame(frame);
+            let expected = if frame < 5 {
+                Point3::new(0., 0., 1.)
+            } else {
+                Point3::new(4., 0., 0.)
+            };
+            assert_eq!(position(&scene_with_switch), expected, "frame {frame}");
+        }
+
+        let err = scene(&switch(5, 8)).unwrap_err();
+        assert!(err.msg().contains("8x16"));
+    }
+
+    #[test]
+    fn animation_needs_frames_and_fps() {
+        let animated = |attributes: &str| {
+            TestScene {
+                header: &format!("<animated {attributes}/>"),
+                ..TestScene::default()
+            }
+            .load()
+        };
+        assert_eq!(animated(r#"frames="3" fps="1""#).unwrap().get_frames(), 3);
+        for invalid in [r#"frames="0" fps="10""#, r#"frames="10" fps="0""#] {
+            let err = animated(invalid).unwrap_err();
+            assert!(err.to_string().contains("Invalid animation"), "{err}");
+        }
+    }
+
+    #[test]
+    fn missing_camera_lists_available_names() {
+        let load = |cameras: &[String], camera| {
+            let scene = TestScene {
+                cameras: &format!("<cameras>{}</cameras>", cameras.concat()),
+                ..TestScene::default()
+            };
+            with_camera_option(&scene, camera)
+        };
+
+        let named = [camera_xml("wide", 32), camera_xml("closeup", 8)];
+        let err = load(&named, Some("top")).unwrap_err();
+        assert!(err.msg().contains("'top'"));
+        assert!(err.msg().contains("'wide', 'closeup'"));
+
+        let duplicate = [camera_xml("wide", 32), camera_xml("wide", 8)];
+        assert!(load(&duplicate, None).is_err());
+
+        assert!(load(&[camera_xml("empty", 0)], None).is_err());
+    }
+
+    #[test]
+    fn output_gamma_attribute() {
+        use crate::image::TransferFunction;
+
+        let with_output = |gamma: &str| {
+            TestScene {
+                header: &format!(r#"<output gamma="{gamma}"/>"#),
+                ..TestScene::default()
+            }
+            .load()
+        };
+
+        let default = TestScene::default().load().unwrap();
+        assert_eq!(default.transfer_function(), TransferFunction::Display);
+        for (gamma, expected) in [
+            ("srgb", TransferFunction::Srgb),
+            ("linear", TransferFunction::Linear),
+            ("none", TransferFunction::Untagged),
+            ("2.2", TransferFunction::Gamma(2.2)),
+        ] {
+            assert_eq!(with_output(gamma).unwrap().transfer_function(), expected);
+        }
+        assert!(with_output("0").is_err());
+        let err = with_output("rec709").unwrap_err();
+        assert!(err.msg().contains("'rec709'"));
+    }
+
+    #[test]
+    fn output_template_attribute() {
+        let with_output = |header| {
+            TestScene {
+                header,
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let default = TestScene::default().load().unwrap();
+        assert_eq!(default.get_output_template(), None);
+
+        let scene = with_output(r#"<output template="renders/{scene}/{frame:04}"/>"#).unwrap();
+        assert_eq!(
+            scene.get_output_template(),
+            Some("renders/{scene}/{frame:04}")
+        );
+        // the gamma can be left out
+        assert_eq!(
+            scene.transfer_function(),
+            crate::image::TransferFunction::Display
+        );
+
+        let err = with_output(r#"<output template="{scene:04}"/>"#).unwrap_err();
+        assert!(err.msg().contains("can't be padded"), "{}", err.msg());
+    }
+
+    /// load a scene with a spot light with the given `falloff` and profile
+    fn spot_light_scene(falloff: &str) -> Result<Scene, InputError> {
+        TestScene {
+            lights: &format!(
+                r#"
+                <spot_light>
+                    <color r="1" g="1" b="1"/>
+                    <position x="0" y="0" z="0"/>
+                    <direction x="0" y="0" z="-1"/>
+                    {falloff}
+                </spot_light>"#
+            ),
+            ..TestScene::default()
+        }
+        .load()
+    }
+
+    #[test]
+    fn spot_light_falloff_angles() {
+        assert!(spot_light_scene(r#"<falloff alpha1="0.5" alpha2="1.25"/>"#).is_ok());
+        assert!(spot_light_scene(r#"<falloff alpha1="0" alpha2="90"/>"#).is_ok());
+
+        let swapped = spot_light_scene(r#"<falloff alpha1="30" alpha2="20"/>"#).unwrap_err();
+        assert!(swapped.msg().contains("alpha1 (30)"));
+        assert!(spot_light_scene(r#"<falloff alpha1="20" alpha2="20"/>"#).is_err());
+
+        let out_of_range = spot_light_scene(r#"<falloff alpha1="20" alpha2="120"/>"#).unwrap_err();
+        assert!(out_of_range.msg().contains("between 0 and 90"));
+        assert!(spot_light_scene(r#"<falloff alpha1="-5" alpha2="20"/>"#).is_err());
+    }
+
+    #[test]
+    fn spot_light_profile_is_validated() {
+        let falloff = r#"<falloff alpha1="10" alpha2="30"/>"#;
+        let with_profile =
+            |values: &str| spot_light_scene(&format!(r#"{falloff}<profile values="{values}"/>"#));
+        let scene = with_profile(" 1.0 0.9\n0.7 0.4 0.1 0.0 ").unwrap();
+        match &scene.get_lights()[0] {
+            Light::Spot {
+                profile: Some(profile),
+                ..
+            } => assert_eq!(profile.values(), [1., 0.9, 0.7, 0.4, 0.1, 0.]),
+            light => panic!("expected a spot light with a profile, got {light:?}"),
+        }
+        assert!(matches!(
+            spot_light_scene(falloff).unwrap().get_lights()[0],
+            Light::Spot { profile: None, .. }
+        ));
+
+        assert!(with_profile("").unwrap_err().msg().contains("at least one"));
+        assert!(with_profile("1 1.2").unwrap_err().msg().contains("1.2"));
+        assert!(with_profile("1 -0.5").is_err());
+        assert!(with_profile("1 half").unwrap_err().msg().contains("'half'"));
+    }
+
+    #[test]
+    fn parse_ellipsoid() {
+        let ellipsoid_scene = |radii: &str| {
+            TestScene {
+                surfaces: &format!(
+                    r#"<ellipsoid {radii} layer="egg"><position x="0" y="0" z="-5"/>{MATERIAL}</ellipsoid>"#
+                ),
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let scene = ellipsoid_scene(r#"rx="1" ry="2" rz="3""#).unwrap();
+        let surface = &scene.get_surfaces()[0];
+        assert_eq!(surface.kind(), "ellipsoid");
+        assert_eq!(surface.layer(), Some("egg"));
+        let hit = scene
+            .closest_intersection(&Ray::new(Point3::zero(), Vec3::new(0., 0., -1.)))
+            .unwrap();
+        assert!((hit.t - 2.).abs() < 1e-5);
+
+        let err = ellipsoid_scene(r#"rx="1" ry="0" rz="3""#).unwrap_err();
+        assert!(err.msg().contains("ry=0"), "{}", err.msg());
+    }
+
+    #[test]
+    fn parse_clip_planes() {
+        let sphere = |normal: &str| {
+            load_surfaces(&format!(
+                r#"<sphere radius="1">
+                    <position x="0" y="0" z="-5"/>
+                    {MATERIAL}
+                    <clip>
+                        <plane px="0" py="0" pz="-5" {normal}/>
+                        <plane px="0" py="0.5" pz="0" nx="0" ny="1" nz="0"/>
+                    </clip>
+                </sphere>"#
+            ))
+        };
+        let scene = sphere(r#"nx="0" ny="0" nz="2""#).unwrap();
+        let planes = scene.get_surfaces()[0].clip_planes();
+        assert_eq!(planes.len(), 2);
+        assert_eq!(planes[0].normal(), Vec3::new(0., 0., 1.));
+        // the front half is cut away, the ray hits the cap through the center
+        let hit = scene
+            .closest_intersection(&Ray::new(Point3::zero(), Vec3::new(0., 0., -1.)))
+            .unwrap();
+        assert!((hit.t - 5.).abs() < 1e-5);
+        assert!((hit.normal - Vec3::new(0., 0., 1.)).length() < 1e-5);
+
+        let err = sphere(r#"nx="0" ny="0" nz="0""#).unwrap_err();
+        assert!(err.msg().contains("zero"), "{}", err.msg());
+    }
+
+    #[test]
+    fn parse_disc_and_quad() {
+        let disc = |radius: &str, normal: &str| {
+            load_surfaces(&format!(
+                r#"<disc radius="{radius}" both_sides="true" layer="panel">
+                    <position x="0" y="0" z="-5"/>
+                    <normal {normal}/>
+                    {MATERIAL}
+                </disc>"#
+            ))
+        };
+        let scene = disc("1", r#"x="0" y="0" z="1""#).unwrap();
+        let surface = &scene.get_surfaces()[0];
+        assert_eq!(surface.kind(), "disc");
+        assert_eq!(surface.layer(), Some("panel"));
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        assert!((scene.closest_intersection(&ray).unwrap().t - 5.).abs() < 1e-5);
+        assert!(disc("0", r#"x="0" y="0" z="1""#).is_err());
+        assert!(disc("1", r#"x="0" y="0" z="0""#).is_err());
+
+        let quad = |edge_v: &str| {
+            load_surfaces(&format!(
+                r#"<quad>
+                    <corner x="-1" y="-1" z="-3"/>
+                    <edge_u x="2" y="0" z="0"/>
+                    <edge_v {edge_v}/>
+                    {MATERIAL}
+                    <transform><translate x="0" y="0" z="-1"/></transform>
+                </quad>"#
+            ))
+        };
+        let scene = quad(r#"x="0" y="2" z="0""#).unwrap();
+        assert_eq!(scene.get_surfaces()[0].kind(), "quad");
+        assert!((scene.closest_intersection(&ray).unwrap().t - 4.).abs() < 1e-5);
+        let err = quad(r#"x="-4" y="0" z="0""#).unwrap_err();
+        assert!(err.msg().contains("parallel"), "{}", err.msg());
+    }
+
+    #[test]
+    fn parse_scatter() {
+        let scatter = |surface_ref: &str| {
+            load_surfaces(&format!(
+                r#"<quad>
+                    <corner x="-1" y="0" z="1"/>
+                    <edge_u x="2" y="0" z="0"/>
+                    <edge_v x="0" y="0" z="-2"/>
+                    {MATERIAL}
+                </quad>
+                <scatter count="5" seed="7" surface_ref="{surface_ref}" scale_min="0.5" scale_max="2">
+                    <sphere radius="0.1">
+                        <position x="0" y="0" z="0"/>
+                        {MATERIAL}
+                    </sphere>
+                </scatter>"#
+            ))
+        };
+        let scene = scatter("quad0").unwrap();
+        assert_eq!(
+            scene.surface_ids(),
+            ["quad0", "sphere0", "sphere1", "sphere2", "sphere3", "sphere4"]
+        );
+        let boxes: Vec<_> = scene
+            .get_surfaces()
+            .iter()
+            .map(|s| s.bounding_box().unwrap())
+            .collect();
+        assert!(boxes[1..]
+            .iter()
+            .all(|(min, max)| (min[1] + max[1]).abs() < 1e-4));
+        let again: Vec<_> = scatter("quad0")
+            .unwrap()
+            .get_surfaces()
+            .iter()
+            .map(|s| s.bounding_box().unwrap())
+            .collect();
+        assert_eq!(boxes, again);
+
+        let err = scatter("quad1").unwrap_err();
+        assert!(err.msg().contains("Unknown surface"), "{}", err.msg());
+    }
+
+    #[test]
+    fn parse_builtin_uv_grid_texture() {
+        let grid = |texture: &str| {
+            load_surfaces(&format!(
+                r#"<sphere radius="1">
+                    <position x="0" y="0" z="-3"/>
+                    <material_textured>
+                        {texture}
+                        <phong ka="1.0" kd="1.0" ks="1.0" exponent="1"/>
+                        <reflectance r="0"/>
+                        <transmittance t="0"/>
+                        <refraction iof="1"/>
+                    </material_textured>
+                </sphere>"#
+            ))
+        };
+        let scene = grid(r#"<texture builtin="uv_grid" cells="4"/>"#).unwrap();
+        let material = scene.get_surfaces()[0].material();
+        assert_eq!(
+            material.texture_color((0.6, 0.3), 0.),
+            uv_grid_color(0.6, 0.3, 4, UV_GRID_LINE_WIDTH)
+        );
+        // the cell borders are lines
+        assert_eq!(
+            material.texture_color((0.5, 0.3), 0.),
+            Color::new(0.1, 0.1, 0.1)
+        );
+
+        for (texture, msg) in [
+            (r#"<texture builtin="checker"/>"#, "Unknown builtin texture"),
+            ("<texture/>", "either a file name or a builtin"),
+            (
+                r#"<texture name="a.png" builtin="uv_grid"/>"#,
+                "either a file name or a builtin",
+            ),
+            (
+                r#"<texture builtin="uv_grid" cells="0"/>"#,
+                "at least one cell",
+            ),
+        ] {
+            let err = grid(texture).unwrap_err();
+            assert!(err.msg().contains(msg), "{}", err.msg());
+        }
+    }
+
+    #[test]
+    fn parse_sun() {
+        let sun_scene = |lights| {
+            TestScene {
+                lights,
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let scene = sun_scene(
+            r#"<sun latitude="48.2" longitude="16.37" date="2024-06-21" time="15:30" utc_offset="2" turbidity="3"/>"#,
+        )
+        .unwrap();
+        let Light::Parallel { color, direction } = scene.get_lights()[0] else {
+            panic!("the sun should be a parallel light");
+        };
+        // afternoon sun in the south west, shining down towards the north east
+        assert!(direction[1] < 0. && direction[0] > 0. && direction[2] < 0.);
+        assert!(color[0] >= color[2]);
+
+        let at_night =
+            sun_scene(r#"<sun latitude="48.2" longitude="16.37" date="2024-06-21" time="23:30"/>"#)
+                .unwrap();
+        assert!(
+            matches!(at_night.get_lights()[0], Light::Parallel { color, .. } if color.length() == 0.)
+        );
+
+        for invalid in [
+            r#"<sun latitude="95" longitude="0" date="2024-06-21" time="12:00"/>"#,
+            r#"<sun latitude="0" longitude="0" date="2024-02-30" time="12:00"/>"#,
+            r#"<sun latitude="0" longitude="0" date="2024-06-21" time="12:61"/>"#,
+            r#"<sun latitude="0" longitude="0" date="2024-06-21" time="12:00" turbidity="0.5"/>"#,
+        ] {
+            let err = sun_scene(invalid).unwrap_err();
+            assert!(err.to_string().contains("Invalid sun"), "{err}");
+        }
+    }
+
+    /// load a scene with a red sphere, whose material has the given extra `layers`
+    fn layered_material_scene(layers: &str) -> Result<Scene, InputError> {
+        TestScene {
+            surfaces: &format!(
+                r#"
+                <sphere radius="1">
+                    <position x="0" y="0" z="-5"/>
+                    <material_solid>
+                        <color r="0.6" g="0" b="0"/>
+                        <phong ka="0.1" kd="0.9" ks="0" exponent="1"/>
+                        <reflectance r="0"/>
+                        <transmittance t="0"/>
+                        <refraction iof="1"/>
+                        {layers}
+                    </material_solid>
+                </sphere>"#
+            ),
+            ..TestScene::default()
+        }
+        .load()
+    }
+
+    #[test]
+    fn parse_clearcoat() {
+        let scene = layered_material_scene("").unwrap();
+        assert!(scene.get_surfaces()[0].material().clearcoat().is_none());
+
+        let scene =
+            layered_material_scene(r#"<clearcoat strength="0.5" roughness="0.05"/>"#).unwrap();
+        let coat = scene.get_surfaces()[0].material().clearcoat().copied();
+        assert_eq!(
+            coat,
+            Some(ClearCoat {
+                strength: 0.5,
+                roughness: 0.05
+            })
+        );
+
+        for invalid in [
+            r#"<clearcoat strength="1.5" roughness="0.05"/>"#,
+            r#"<clearcoat strength="0.5" roughness="0"/>"#,
+        ] {
+            let err = layered_material_scene(invalid).unwrap_err();
+            assert!(err.to_string().contains("Invalid clear coat"), "{err}");
+        }
+    }
+
+    #[test]
+    fn parse_thin_film() {
+        let scene = layered_material_scene(
+            r#"<thin_film thickness="300" ior="1.33" endthickness="500"/>
+            <clearcoat strength="0.5" roughness="0.05"/>"#,
+        )
+        .unwrap();
+        let material = scene.get_surfaces()[0].material();
+        assert_eq!(
+            material.thin_film().copied(),
+            Some(ThinFilm::new(300., 1.33).with_end(500.))
+        );
+        assert!(material.clearcoat().is_some());
+        assert!(scene.has_animated_objects());
+
+        for invalid in [
+            r#"<thin_film thickness="-1" ior="1.33"/>"#,
+            r#"<thin_film thickness="300" ior="0.5"/>"#,
+            r#"<thin_film thickness="300" ior="1.33" endthickness="-5"/>"#,
+        ] {
+            let err = layered_material_scene(invalid).unwrap_err();
+            assert!(err.to_string().contains("Invalid thin film"), "{err}");
+        }
+    }
+
+    #[test]
+    fn parse_feature_samples() {
+        let feature_samples_scene = |light: &str, reflectance: &str| {
+            TestScene {
+                lights: &format!(
+                    r#"
+                    <point_light {light}>
+                        <color r="1" g="1" b="1"/>
+                        <position x="0" y="3" z="0"/>
+                    </point_light>"#
+                ),
+                surfaces: &format!(
+                    r#"
+                    <sphere radius="1">
+                        <position x="0" y="0" z="-5"/>
+                        <material_solid>
+                            <color r="0.6" g="0" b="0"/>
+                            <phong ka="0.1" kd="0.9" ks="0" exponent="1"/>
+                            <reflectance r="0.8" {reflectance}/>
+                            <transmittance t="0"/>
+                            <refraction iof="1"/>
+                        </material_solid>
+                    </sphere>"#
+                ),
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let scene = feature_samples_scene("", "").unwrap();
+        assert!(!scene.get_lights()[0].is_area_light());
+        assert!(scene.get_surfaces()[0].material().reflection_roughness() <= 0.);
+
+        let scene = feature_samples_scene(
+            r#"radius="0.5" samples="16""#,
+            r#"roughness="0.2" samples="8""#,
+        )
+        .unwrap();
+        let light = &scene.get_lights()[0];
+        assert!(light.is_area_light());
+        assert_eq!(light.samples(), Some(16));
+        let material = scene.get_surfaces()[0].material();
+        assert!((material.reflection_roughness() - 0.2).abs() < 1e-6);
+        assert_eq!(material.reflection_samples(), Some(8));
+
+        for (light, reflectance, error) in [
+            (r#"radius="-1""#, "", "Invalid point light"),
+            (r#"radius="1" samples="0""#, "", "Invalid point light"),
+            ("", r#"roughness="1.5""#, "Invalid reflectance"),
+            ("", r#"roughness="0.2" samples="0""#, "Invalid reflectance"),
+        ] {
+            let err = feature_samples_scene(light, reflectance).unwrap_err();
+            assert!(err.to_string().contains(error), "{err}");
+        }
+    }
+
+    #[test]
+    fn parse_hemispheric_ambient_light() {
+        let ambient_scene = |ambient: &str| {
+            TestScene {
+                lights: &format!(
+                    r#"<ambient_light><color r="0.5" g="0.7" b="1"/>{ambient}</ambient_light>"#
+                ),
+                ..TestScene::default()
+            }
+            .load()
+        };
+        let hemisphere = |scene: Scene| match scene.get_lights()[0] {
+            Light::Ambient { hemisphere, .. } => hemisphere,
+            _ => panic!("expected an ambient light"),
+        };
+        assert_eq!(hemisphere(ambient_scene("").unwrap()), None);
+
+        let ground = hemisphere(ambient_scene(r##"<ground hex="#332211"/>"##).unwrap()).unwrap();
+        assert_eq!(ground.up, Vec3::new(0., 1., 0.));
+        let tilted =
+            ambient_scene(r#"<ground r="0.2" g="0.1" b="0"/><direction x="0" y="0" z="1"/>"#);
+        assert_eq!(
+            hemisphere(tilted.unwrap()).unwrap().up,
+            Vec3::new(0., 0., 1.)
+        );
+
+        assert!(ambient_scene(r#"<direction x="0" y="1" z="0"/>"#).is_err());
+        assert!(
+            ambient_scene(r#"<ground r="0.2" g="0.1" b="0"/><direction x="0" y="0" z="0"/>"#)
+                .is_err()
+        );
     }
 }
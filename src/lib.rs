@@ -3,3 +3,5 @@ pub mod input;
 pub mod math;
 pub mod misc;
 pub mod objects;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -1,14 +1,57 @@
-use std::{env, path::PathBuf, process, sync::mpsc};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    sync::{mpsc, Mutex},
+    thread,
+};
 
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use rt::{
-    image,
-    input::{file_to_scene, Config, InputError},
-    misc::progress::ProgressBar,
+    image::{self, ImageError},
+    input::{
+        builtin_scene, file_to_material, file_to_scene, material_preview, output_template_path,
+        override_material, Config, InputError, LoadOptions, OutputFormat, OutputVars,
+        RenderSettings,
+    },
+    math::{lerp, Color},
+    misc::{
+        budget::RenderBudget,
+        console, interrupt, log,
+        nancheck::NanDetector,
+        passes::{self, Accumulator, PassPlan},
+        progress::ProgressBar,
+        queue::{self, JobStatus},
+        solar::UtcTime,
+    },
+    objects::{Camera, IntersectionStats, RadianceSplit, Scene},
 };
 
+/// prefix of the queue entries for builtin scenes
+const BUILTIN_PREFIX: &str = "builtin:";
+
+/// resolution of builtin scenes
+const BUILTIN_RESOLUTION: (u32, u32) = (512, 512);
+
+/// color of the stripes over pixels that were skipped because the render budget ran out
+const WATERMARK: Color = Color::new(1., 0.8, 0.);
+
+/// width of the watermark stripes in pixels
+const WATERMARK_STRIPE: u32 = 8;
+
+/// width of the image per step of the text scale of the burn-in, wider images get larger text
+const BURN_IN_WIDTH_PER_SCALE: u32 = 640;
+
+/// keyword of the png text chunk that holds the render settings
+const SETTINGS_KEYWORD: &str = "Render settings";
+
+/// bold red, so a rescued file isn't missed among the other output
+const RESCUE_COLOR: &str = "\x1b[1;31m";
+
 fn main() -> process::ExitCode {
     match run() {
-        Ok(()) => process::ExitCode::SUCCESS,
+        Ok(code) => code,
         Err(err) => {
             eprintln!("{err}");
             process::ExitCode::FAILURE
@@ -16,92 +59,1005 @@ fn main() -> process::ExitCode {
     }
 }
 
-fn run() -> Result<(), InputError> {
+/// print what is about to be rendered, with the scene info and settings if the config asks for
+/// them
+fn print_start(input: &str, scene: &Scene, config: &Config, settings: &RenderSettings) {
+    let ((width, height), frames) = (settings.resolution, settings.frames);
+    println!(
+        "Loaded file '{input}'; Starting render of {frames} frames with dimensions {width}x{height}..."
+    );
+    if config.info() {
+        print_info(scene, config.verbose());
+    }
+    if config.verbose() {
+        println!("{settings}");
+    }
+}
+
+/// print a short summary of the loaded scene
+#[allow(clippy::cast_precision_loss)]
+fn print_info(scene: &Scene, verbose: bool) {
+    println!("Scene info:");
+    println!("    surfaces:       {}", scene.get_surfaces().len());
+    println!("    lights:         {}", scene.light_count());
+    println!(
+        "    texture memory: {:.2} MiB",
+        scene.texture_memory() as f64 / (1024. * 1024.)
+    );
+    if let Some((min, max)) = scene.bounding_box() {
+        let size = max - min;
+        println!(
+            "    extent:         ({:.2}, {:.2}, {:.2}) to ({:.2}, {:.2}, {:.2}), size {:.2} x {:.2} x {:.2}",
+            min[0], min[1], min[2], max[0], max[1], max[2], size[0], size[1], size[2]
+        );
+    }
+    if verbose {
+        let triangles = scene.triangle_counts();
+        println!(
+            "    meshes:         {} ({} triangles, at most {} in one mesh)",
+            triangles.len(),
+            triangles.iter().sum::<usize>(),
+            triangles.iter().max().unwrap_or(&0)
+        );
+    }
+}
+
+fn run() -> Result<process::ExitCode, InputError> {
+    console::init();
     let args: Vec<_> = env::args().collect();
     let config = Config::build(&args)?;
     if config.is_none() {
-        return Ok(());
+        return Ok(process::ExitCode::SUCCESS);
     }
     // is safe, since we asserted that config is not None
     let config = unsafe { config.unwrap_unchecked() };
+    log::set_strict(config.strict());
+    log::set_no_color(config.no_color());
+    for warning in config.warnings() {
+        log::warning(warning)?;
+    }
 
-    let mut scene = file_to_scene(config.get_input())?;
-    let frames = scene.get_frames();
-    let (width, height) = scene.get_dimensions();
-    println!(
-        "Loaded file '{}'; Starting render of {} frames with dimensions {}x{}...",
-        config.get_input(),
-        frames,
-        width,
-        height
-    );
+    let options = LoadOptions {
+        max_texture_size: config.max_texture_size(),
+        camera: config.camera().map(ToString::to_string),
+        strict_schema: config.strict_schema(),
+        no_env_expansion: config.no_env_expansion(),
+        max_triangles: Some(config.max_triangles()),
+        max_texture_pixels: Some(config.max_texture_pixels()),
+        decimate_to: config.decimate_to(),
+        missing_textures: config.missing_textures(),
+    };
+    let jobs = config.jobs();
+    // progress bars of concurrent scenes would overwrite each other
+    let progress_bar = config.progress_bar() && jobs == 1;
 
-    let mut img = image::Image::new(width, height, scene.get_frames());
+    let mut inputs = config.get_inputs().to_vec();
+    if let Some(name) = config.builtin() {
+        inputs.push(format!("{BUILTIN_PREFIX}{name}"));
+    }
 
-    let (tx, rx) = mpsc::channel();
+    interrupt::install_handler();
+    // thumbnails of a contact sheet by input
+    let thumbnails = config.contact_sheet().map(|_| Mutex::new(HashMap::new()));
+    let reports = queue::run(&inputs, jobs, interrupt::flag(), |input| {
+        let result = match &thumbnails {
+            Some(thumbnails) => render_thumbnail(input, &config, &options).map(|img| {
+                if let Ok(mut thumbnails) = thumbnails.lock() {
+                    thumbnails.insert(input.to_string(), img);
+                }
+                Vec::new()
+            }),
+            None => render_scene(input, &config, &options, progress_bar),
+        };
+        if let Err(err) = &result {
+            eprintln!("{err}");
+        }
+        result
+    });
+    if let (Some(file), Some(thumbnails)) = (config.contact_sheet(), thumbnails) {
+        let mut thumbnails = thumbnails.into_inner().unwrap_or_default();
+        let cells: Vec<_> = inputs
+            .iter()
+            .map(|input| (scene_label(input), thumbnails.remove(input)))
+            .collect();
+        let mut path = config.output_path(file);
+        create_output_dir(&path)?;
+        let sheet = image::Image::contact_sheet(&cells, config.thumb_size());
+        save_rescued(&mut path, |path| sheet.save_png(path))?;
+        println!(
+            "Saved contact sheet of {} scenes to {}",
+            cells.len(),
+            path.to_str().unwrap_or("<INVALID PATH>")
+        );
+    }
 
-    // start thread for printing progress bar
-    // necessary, since `img.par_init_each_pixel(..)` blocks the main thread
-    let progress_thread = if config.progress_bar() {
-        let mut frame = 1;
-        let mut pixels_processed = 0;
-        let mut progress = ProgressBar::new((width * height) as usize, String::from("Frame 1:"));
-
-        let handle = std::thread::spawn(move || {
-            while rx.recv().is_ok() {
-                pixels_processed += 1;
-                progress.next();
-                if pixels_processed >= (width * height) {
-                    pixels_processed = 0;
-                    frame += 1;
-                    if frame > frames {
-                        break;
+    if let Some(path) = config.report() {
+        queue::write_report(path, &reports)?;
+    }
+    if reports.len() > 1 {
+        let done = reports
+            .iter()
+            .filter(|r| r.status == JobStatus::Done)
+            .count();
+        println!("Rendered {done} of {} scenes", reports.len());
+    }
+
+    if reports.iter().all(|r| r.status == JobStatus::Done) {
+        Ok(process::ExitCode::SUCCESS)
+    } else {
+        Ok(process::ExitCode::FAILURE)
+    }
+}
+
+/// Spawn a thread that shows a progress bar for one step per label, of `pixels` pixels each
+/// Every message on `rx` advances the bar by one pixel, the thread stops once all steps are done
+/// or all senders are dropped
+fn spawn_progress_bar(
+    pixels: u32,
+    labels: Vec<String>,
+    rx: mpsc::Receiver<()>,
+) -> thread::JoinHandle<()> {
+    let mut labels = labels.into_iter();
+    let mut pixels_processed = 0;
+    let first = labels.next().unwrap_or_default();
+    let mut progress = ProgressBar::new(pixels as usize, first);
+
+    thread::spawn(move || {
+        while rx.recv().is_ok() {
+            pixels_processed += 1;
+            progress.next();
+            if pixels_processed >= pixels {
+                pixels_processed = 0;
+                let Some(label) = labels.next() else {
+                    break;
+                };
+                progress.reset(label);
+            }
+        }
+    })
+}
+
+/// Trace the pixel (`x`, `y`) of the image as far as the render `budget` allows
+/// Super-sampled pixels are always traced, but with fewer samples once the budget runs low, and
+/// stop early when it runs out while they are traced. Other pixels are skipped once the budget is
+/// exhausted and show the background with a striped watermark instead, which counts as emission
+fn trace_within_budget(
+    scene: &Scene,
+    budget: &RenderBudget,
+    (x, y): (u32, u32),
+    height: u32,
+) -> RadianceSplit {
+    // invert y to 'unflip' the image
+    let (u, v) = (x, Camera::row_from_image(y, height));
+    let samples = scene.get_samples();
+    if samples > 1 {
+        let mut sum = RadianceSplit::zero();
+        let traced = budget.trace_samples(budget.samples_for_pixel(samples), |batch| {
+            sum += scene.trace_pixel_split_samples(u, v, batch);
+        });
+        #[allow(clippy::cast_precision_loss)]
+        return sum / traced as f32;
+    }
+    if budget.is_exhausted() {
+        budget.skip();
+        let background = scene.background_pixel(u, v);
+        return RadianceSplit::emission(if ((x + y) / WATERMARK_STRIPE).is_multiple_of(2) {
+            background
+        } else {
+            lerp(background, WATERMARK, 0.5)
+        });
+    }
+    budget.spend(1);
+    scene.trace_pixel_split(u, v)
+}
+
+/// Trace the pixel (`x`, `y`) of `frame` within the render `budget` and check it for NaN values
+/// with the `nan_detector`
+/// Returns the color split into its components, so the component aovs add up to the image.
+/// Returns black once the render was interrupted
+fn shade_pixel(
+    scene: &Scene,
+    budget: &RenderBudget,
+    nan_detector: Option<&NanDetector>,
+    frame: usize,
+    (x, y): (u32, u32),
+) -> RadianceSplit {
+    if interrupt::is_interrupted() {
+        return RadianceSplit::zero();
+    }
+    let (_, height) = scene.get_dimensions();
+    let split = trace_within_budget(scene, budget, (x, y), height);
+    match nan_detector {
+        Some(detector) if !split.total().is_finite() => {
+            RadianceSplit::emission(detector.check(frame, (x, y), split.total()))
+        }
+        _ => split,
+    }
+}
+
+/// Trace the color and alpha of every layer in `layers` for each pixel of `img`
+/// Pixels that are skipped, because the render was interrupted or the `budget` is exhausted, are
+/// transparent
+/// Sends a message on `tx` for every finished pixel
+fn render_layers(
+    scene: &Scene,
+    img: &image::Image,
+    layers: &[String],
+    budget: &RenderBudget,
+    tx: &mpsc::Sender<()>,
+) -> Vec<Vec<(Color, f32)>> {
+    let (_, height) = scene.get_dimensions();
+    img.par_map_pixels(|(x, y)| {
+        let tx = tx.clone();
+        let ret = if interrupt::is_interrupted() {
+            vec![(Color::zero(), 0.); layers.len()]
+        } else if budget.is_exhausted() {
+            budget.skip();
+            vec![(Color::zero(), 0.); layers.len()]
+        } else {
+            budget.spend(scene.get_samples().max(1));
+            scene.trace_pixel_layers(*x, Camera::row_from_image(*y, height), layers)
+        };
+        let _ = tx.send(());
+        ret
+    })
+}
+
+/// Create the directory of `outpath`, including directories in the output file of the scene
+fn create_output_dir(outpath: &Path) -> Result<(), InputError> {
+    match outpath.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => fs::create_dir_all(dir).map_err(|err| {
+            InputError::new(
+                format!("Error while creating output directory '{}'", dir.display()),
+                err.to_string(),
+            )
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Save one png with alpha channel per render layer
+/// `pixels` holds the premultiplied color and alpha of every layer for each pixel
+/// The layer name is appended to the file name of `outpath`
+/// The colors are encoded with the transfer function of the `settings`, which are also embedded
+/// into the files
+fn save_layers(
+    settings: &RenderSettings,
+    pixels: &[Vec<(Color, f32)>],
+    layers: &[String],
+    outpath: &Path,
+) -> Result<Vec<PathBuf>, InputError> {
+    let ((width, height), transfer) = (settings.resolution, settings.transfer);
+    let stem = outpath
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    layers
+        .iter()
+        .enumerate()
+        .map(|(i, layer)| {
+            let (colors, alpha): (Vec<_>, Vec<_>) = pixels
+                .iter()
+                .map(|p| {
+                    let (color, alpha) = p[i];
+                    // png stores straight alpha
+                    let color = if alpha > 0. { color / alpha } else { color };
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    (color, (alpha * 255.).round() as u8)
+                })
+                .unzip();
+            let mut path = outpath.with_file_name(format!("{stem}_{layer}.png"));
+            let mut img = image::Image::from_colors(width, height, &colors, 0., transfer);
+            img.set_channels(settings.channels);
+            img.add_text(SETTINGS_KEYWORD, &settings.to_string());
+            save_rescued(&mut path, |path| img.save_png_with_alpha(path, &alpha))?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Save one image per exposure value of the `settings` from the `linear` colors of the render
+/// The exposure value is appended to the file name of `outpath`, e.g. `out_ev-2.png`
+fn save_brackets(
+    settings: &RenderSettings,
+    linear: &[Color],
+    playback: &image::Playback,
+    outpath: &Path,
+) -> Result<Vec<PathBuf>, InputError> {
+    let stem = outpath
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let (width, height) = settings.resolution;
+    settings
+        .brackets
+        .iter()
+        .map(|ev| {
+            let img = image::Image::from_colors(width, height, linear, *ev, settings.transfer);
+            // fractional exposure values contain a dot, so add a placeholder extension that is
+            // replaced by the one of the output format
+            let mut path = outpath.with_file_name(format!("{stem}_ev{ev}.png"));
+            save_image(img, settings, playback, &mut path)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Save the rendered `img` in the format of the `settings`, which are also embedded into the file
+/// Blurred animations are averaged into a single image first
+fn save_image(
+    mut img: image::Image,
+    settings: &RenderSettings,
+    playback: &image::Playback,
+    outpath: &mut PathBuf,
+) -> Result<(), InputError> {
+    if settings.blur {
+        img = img.average_frames(&image::ShutterProfile::Box, 0..settings.frames);
+    }
+    if let Some(name) = &settings.burn_in {
+        burn_in(&mut img, name, settings);
+    }
+    img.set_channels(settings.channels);
+    img.add_text(SETTINGS_KEYWORD, &settings.to_string());
+    save_rescued(outpath, |path| match settings.format {
+        OutputFormat::Apng => img.save_apng(path, playback),
+        OutputFormat::Ppm => img.save_ppm(path),
+        OutputFormat::Png => img.save_png(path),
+    })
+}
+
+/// Draw the burn-in overlay of review renders along the bottom of every frame of `img`: a dark
+/// strip with the `name` of the scene, the frame, the current date and the samples and seed of
+/// the `settings`
+fn burn_in(img: &mut image::Image, name: &str, settings: &RenderSettings) {
+    let (width, height) = img.dimensions();
+    let scale = (width / BURN_IN_WIDTH_PER_SCALE).max(1);
+    let padding = 2 * scale;
+    let strip = image::GLYPH_HEIGHT * scale + 2 * padding;
+    let top = height.saturating_sub(strip);
+    img.fill_rect(0, top, width, strip, [0; 3], 0.6);
+
+    let date = UtcTime::now();
+    let seed = settings
+        .seed
+        .map_or_else(|| "none".to_string(), |seed| seed.to_string());
+    let frames = if settings.blur { 1 } else { settings.frames };
+    for frame in 0..frames {
+        let shown = if settings.blur {
+            format!("frames 1-{}", settings.frames)
+        } else {
+            format!("frame {}/{}", frame + 1, settings.frames)
+        };
+        let text = format!(
+            "{name}  {shown}  {date}  samples {}  seed {seed}",
+            // renders that are not super-sampled trace one sample per pixel
+            settings.samples.max(1)
+        );
+        img.draw_text(frame, padding, top + padding, &text, [255; 3], scale);
+    }
+}
+
+/// Write a file at `path` with `save`, see [`image::save_with_rescue`]
+/// If the file had to be rescued into the temp directory, `path` is changed to the rescued file
+/// and the user is told where it is, even in strict mode
+fn save_rescued(
+    path: &mut PathBuf,
+    save: impl FnMut(&mut PathBuf) -> Result<(), ImageError>,
+) -> Result<(), InputError> {
+    if let Some(err) = image::save_with_rescue(path, save)? {
+        eprintln!(
+            "{}",
+            log::colored(
+                RESCUE_COLOR,
+                &format!(
+                    "{err}\nThe file was saved to {} instead, move it somewhere safe before it is cleaned up",
+                    path.display()
+                )
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Schedule of a progressive render
+struct Progressive {
+    plan: PassPlan,
+    /// most frames whose sums are held in memory at once
+    max_resident: usize,
+}
+
+impl Progressive {
+    /// Return the label of the progress bar for every frame of every pass, in render order
+    fn labels(&self, frames: usize) -> Vec<String> {
+        let passes = self.plan.passes();
+        passes::frame_groups(frames, self.max_resident)
+            .flat_map(|group| {
+                (1..=passes).flat_map(move |pass| {
+                    group
+                        .clone()
+                        .map(move |frame| format!("Pass {pass}/{passes}, frame {}:", frame + 1))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Return the schedule of a progressive render, if the config asks for one and the scene has more
+/// samples than a single pass
+fn progressive_plan(
+    config: &Config,
+    settings: &RenderSettings,
+) -> Result<Option<Progressive>, InputError> {
+    let Some(pass_samples) = config.pass_samples() else {
+        return Ok(None);
+    };
+    if !config.layers().is_empty() || !settings.brackets.is_empty() {
+        log::warning("--pass-samples is ignored for render layers and exposure brackets")?;
+        return Ok(None);
+    }
+    let plan = PassPlan::new(settings.samples, pass_samples);
+    if plan.passes() < 2 {
+        return Ok(None);
+    }
+    let max_resident = config.max_resident_frames().min(settings.frames);
+    if config.verbose() {
+        let (width, height) = settings.resolution;
+        #[allow(clippy::cast_precision_loss)]
+        let memory = (passes::frame_memory((width * height) as usize) * max_resident) as f64;
+        println!(
+            "Progressive render: {} passes of {pass_samples} samples, {max_resident} frames at once ({:.2} MiB of sums)",
+            plan.passes(),
+            memory / (1024. * 1024.)
+        );
+    }
+    Ok(Some(Progressive { plan, max_resident }))
+}
+
+/// Render the frames of `img` in the progressive passes of `progressive`
+/// The frames are refined in groups of at most `max_resident` frames. Every pass of a group traces
+/// the next samples of all its frames, then `img` is saved to `outpath` with the `settings`, so
+/// the file always holds a complete image. Frames of later groups stay black until their group
+/// starts. The last pass is not saved, that is left to the caller
+/// Returns a note on the saved image if the render was interrupted or ran out of budget before
+/// the last pass
+fn render_passes(
+    scene: &mut Scene,
+    img: &mut image::Image,
+    progressive: &Progressive,
+    (settings, outpath): (&RenderSettings, &Path),
+    budget: &RenderBudget,
+    nan_detector: Option<&NanDetector>,
+    tx: &mpsc::Sender<()>,
+) -> Result<Option<String>, InputError> {
+    let (width, height) = settings.resolution;
+    let pixels = (width * height) as usize;
+    let (plan, passes) = (progressive.plan, progressive.plan.passes());
+    let total = settings.samples.max(1);
+    let groups: Vec<_> = passes::frame_groups(settings.frames, progressive.max_resident).collect();
+    let stopped = |saved: Option<u32>| {
+        Ok(Some(match saved {
+            Some(samples) => format!(
+                "'{}' holds the last finished pass with {samples} of {total} samples per pixel",
+                outpath.display()
+            ),
+            None => "no pass was finished".to_string(),
+        }))
+    };
+    let mut saved = None;
+    for (g, group) in groups.iter().enumerate() {
+        let mut sums: Vec<_> = group.clone().map(|_| Accumulator::new(pixels)).collect();
+        for pass in 0..passes {
+            if pass > 0 && budget.is_exhausted_now() {
+                return stopped(saved);
+            }
+            let samples = plan.samples_of(pass);
+            let last = pass + 1 == passes;
+            for (frame, sum) in group.clone().zip(&mut sums) {
+                scene.set_frame(frame);
+                let pass_sums = img.par_map_pixels(|(x, y)| {
+                    let _ = tx.clone().send(());
+                    if interrupt::is_interrupted() {
+                        return Color::zero();
                     }
-                    progress.reset(format!("Frame {frame}:"));
+                    let v = Camera::row_from_image(*y, height);
+                    scene.trace_pixel_samples(*x, v, samples.clone())
+                });
+                if interrupt::is_interrupted() {
+                    return stopped(saved);
                 }
+                let count = samples.end - samples.start;
+                budget.spend_samples(pixels as u64 * u64::from(count));
+                sum.add(&pass_sums, count);
+                let means: Vec<_> = sum.mean().collect();
+                img.par_init_colors(frame, |(x, y)| {
+                    let color = means[(*y * width + *x) as usize];
+                    match nan_detector.filter(|_| last) {
+                        Some(detector) => detector.check(frame, (*x, *y), color),
+                        None => color,
+                    }
+                });
             }
-        });
-        Some(handle)
-    } else {
-        None
+            if !(last && g + 1 == groups.len()) {
+                save_image(
+                    img.clone(),
+                    settings,
+                    scene.get_playback(),
+                    &mut outpath.to_path_buf(),
+                )?;
+                saved = Some(sums[0].samples());
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Save the extra outputs of the config next to the image at `outpath`
+/// `components` are the images of the component aovs that were rendered together with the image
+/// Returns the paths of the saved files
+fn save_aovs(
+    scene: &mut Scene,
+    config: &Config,
+    settings: &RenderSettings,
+    components: Vec<(usize, image::Image)>,
+    outpath: &Path,
+) -> Result<Vec<PathBuf>, InputError> {
+    let mut paths = save_components(scene, settings, components, outpath)?;
+    if config.aov("motion") {
+        paths.extend(save_motion(scene, settings.resolution, outpath)?);
+    }
+    Ok(paths)
+}
+
+/// Return an empty image for each component of the beauty render that the config asks for,
+/// together with the index of the component in [`RadianceSplit::components`]
+/// Components are only rendered together with a plain image, render layers, exposure brackets
+/// and progressive renders leave them out with a warning
+fn component_images(
+    config: &Config,
+    settings: &RenderSettings,
+    progressive: bool,
+) -> Result<Vec<(usize, image::Image)>, InputError> {
+    let indices: Vec<_> = (0..RadianceSplit::NAMES.len())
+        .filter(|index| config.aov(RadianceSplit::NAMES[*index]))
+        .collect();
+    if indices.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !config.layers().is_empty() || !settings.brackets.is_empty() || progressive {
+        log::warning(
+            "The diffuse, specular, reflection, refraction and emission aovs are not saved for \
+             render layers, exposure brackets and progressive renders",
+        )?;
+        return Ok(Vec::new());
+    }
+    let (width, height) = settings.resolution;
+    Ok(indices
+        .into_iter()
+        .map(|index| {
+            let mut img = image::Image::new(width, height, settings.frames);
+            img.set_transfer_function(settings.transfer);
+            img.set_channels(settings.channels);
+            (index, img)
+        })
+        .collect())
+}
+
+/// Save the images of the components of the beauty render, named `<image>_<component>.png`
+/// The components were traced from the same samples as the image, so they add up to it in linear
+/// colors
+fn save_components(
+    scene: &Scene,
+    settings: &RenderSettings,
+    components: Vec<(usize, image::Image)>,
+    outpath: &Path,
+) -> Result<Vec<PathBuf>, InputError> {
+    let stem = outpath
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    components
+        .into_iter()
+        .map(|(index, img)| {
+            let name = RadianceSplit::NAMES[index];
+            let mut path = outpath.with_file_name(format!("{stem}_{name}.png"));
+            save_image(img, settings, scene.get_playback(), &mut path)?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Save the motion vectors of an animation as one `.flo` file per frame, named
+/// `<image>_motion_<frame>.flo`
+fn save_motion(
+    scene: &mut Scene,
+    (width, height): (u32, u32),
+    outpath: &Path,
+) -> Result<Vec<PathBuf>, InputError> {
+    if !scene.is_animated() {
+        log::warning("Motion vectors are only saved for animations")?;
+        return Ok(Vec::new());
+    }
+    let stem = outpath
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("output");
+    let frames = scene.get_frames();
+    let digits = frames.to_string().len();
+    (0..frames)
+        .map(|frame| {
+            scene.set_frame(frame);
+            let vectors: Vec<_> = (0..width * height)
+                .into_par_iter()
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    let (du, dv) = scene.motion_vector(x, Camera::row_from_image(y, height));
+                    // image rows count from the top
+                    (du, -dv)
+                })
+                .collect();
+            let mut path =
+                outpath.with_file_name(format!("{stem}_motion_{:0digits$}.flo", frame + 1));
+            save_rescued(&mut path, |path| {
+                image::save_flo(path, width, height, &vectors)
+            })?;
+            Ok(path)
+        })
+        .collect()
+}
+
+/// Load the scene at `input` and check it against the render layers of the config
+/// `input` is either a path or the name of a builtin scene prefixed with `builtin:`
+/// If a material should be previewed, the preview scene is built with the material from the
+/// library of the scene at `input` instead
+/// Materials are overridden and static meshes merged after loading, if the config asks for it
+fn load_scene(
+    input: &str,
+    config: &Config,
+    options: &LoadOptions,
+    layers: &[String],
+) -> Result<Scene, InputError> {
+    let mut scene = match (
+        input.strip_prefix(BUILTIN_PREFIX),
+        config.preview_material(),
+    ) {
+        (Some(name), _) => builtin_scene(name, BUILTIN_RESOLUTION)?,
+        (None, Some(name)) => {
+            let (material, missing) = file_to_material(input, name, options)?;
+            let mut scene = material_preview(name, material, config.turntable());
+            scene.set_missing_textures(missing);
+            scene
+        }
+        (None, None) => file_to_scene(input, options)?,
     };
+    if !layers.is_empty() && (scene.is_animated() || config.blur()) {
+        return Err(InputError::new(
+            "Error while rendering layers".to_string(),
+            "Render layers are only supported for still images".to_string(),
+        ));
+    }
+    for layer in layers.iter().filter(|l| !scene.has_layer(l)) {
+        log::warning(&format!(
+            "No surface is tagged with layer '{layer}', its image will be empty"
+        ))?;
+    }
+    if let Some((filter, name)) = config.override_material() {
+        let material = override_material(name)?;
+        if scene.override_materials(filter, &material) == 0 {
+            log::warning(&format!(
+                "No surface has the id '{}', no material was overridden",
+                filter.unwrap_or_default()
+            ))?;
+        }
+    }
+    for (name, factor) in config.tweaks() {
+        scene
+            .scale_material_property(name, *factor)
+            .map_err(|msg| InputError::new("Invalid tweak".to_string(), msg))?;
+    }
+    if config.merge_static() {
+        let (before, after) = scene.merge_static_meshes();
+        println!("Merged static meshes: {before} surfaces before, {after} after");
+    }
+    scene.set_debug_time(config.debug("time"));
+    if config.debug("lights") && scene.add_light_gizmos() == 0 {
+        log::warning("The scene has no lights with a position or direction to show")?;
+    }
+    Ok(scene)
+}
 
-    // render image
-    for frame in 0..frames {
+/// Render every frame of `img` as far as the render `budget` allows
+/// `components` pairs the index of a component of [`RadianceSplit`] with the image it is saved
+/// to, those images are filled from the same samples as `img`
+/// Sends a message on `tx` for every finished pixel and stops after the frame in which the
+/// render was interrupted
+fn render_frames(
+    scene: &mut Scene,
+    img: &mut image::Image,
+    components: &mut [(usize, image::Image)],
+    budget: &RenderBudget,
+    nan_detector: Option<&NanDetector>,
+    tx: &mpsc::Sender<()>,
+) {
+    let transfer = scene.transfer_function();
+    let (width, _) = img.dimensions();
+    for frame in 0..scene.get_frames() {
+        if interrupt::is_interrupted() {
+            break;
+        }
+        scene.set_frame(frame);
+        if components.is_empty() {
+            img.par_init_pixels(frame, |(x, y)| {
+                let color = shade_pixel(scene, budget, nan_detector, frame, (*x, *y));
+                let _ = tx.clone().send(());
+                transfer.encode(color.total()).to_rgb()
+            });
+            continue;
+        }
+        let splits = img.par_map_pixels(|(x, y)| {
+            let split = shade_pixel(scene, budget, nan_detector, frame, (*x, *y));
+            let _ = tx.clone().send(());
+            split
+        });
+        let split_at = |x: u32, y: u32| &splits[(y * width + x) as usize];
         img.par_init_pixels(frame, |(x, y)| {
-            let tx = tx.clone();
-            // invert y to 'unflip' the image
-            let ret = scene.trace_pixel(*x, height - *y).to_rgb();
-            let _ = tx.send(());
-            ret
+            transfer.encode(split_at(*x, *y).total()).to_rgb()
         });
-        scene.next_frame();
+        for (index, component) in components.iter_mut() {
+            component.par_init_pixels(frame, |(x, y)| {
+                transfer
+                    .encode(split_at(*x, *y).components()[*index])
+                    .to_rgb()
+            });
+        }
+    }
+}
+
+/// Print the warnings about the finished render of `scene`, and the intersection stats since
+/// `stats_before` if `verbose` is set
+fn report_render(
+    scene: &Scene,
+    budget: &RenderBudget,
+    verbose: bool,
+    stats_before: Option<IntersectionStats>,
+) -> Result<(), InputError> {
+    if let Some(msg) = budget.report(scene.get_samples()) {
+        log::warning(&msg)?;
+    }
+    if let Some(msg) = scene.render_stats().warning() {
+        log::warning(&msg)?;
+    }
+    if let (true, Some(before), Some(after)) = (verbose, stats_before, IntersectionStats::collect())
+    {
+        println!("{}", after.since(&before));
     }
+    Ok(())
+}
+
+/// Summarize the textures of `scene` that were replaced with placeholders as a warning, which
+/// fails the render in strict mode
+fn report_missing_textures(scene: &Scene) -> Result<(), InputError> {
+    let missing = scene.get_missing_textures();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    log::warning(&format!(
+        "{} texture(s) could not be loaded and were rendered as placeholders: {}",
+        missing.len(),
+        missing.join(", ")
+    ))
+}
+
+/// Load the scene at `input` and render its first frame as a thumbnail for a contact sheet, see
+/// [`RenderSettings::reduce_to_thumbnail`]
+fn render_thumbnail(
+    input: &str,
+    config: &Config,
+    options: &LoadOptions,
+) -> Result<image::Image, InputError> {
+    let mut scene = load_scene(input, config, options, &[])?;
+    let mut settings = RenderSettings::merge(&scene, config)?;
+    settings.reduce_to_thumbnail(config.thumb_size());
+    settings.apply(&mut scene);
+    scene.set_frame(0);
+    let (width, height) = settings.resolution;
+    println!("Rendering thumbnail of '{input}' with dimensions {width}x{height}...");
+
+    let transfer = scene.transfer_function();
+    let mut img = image::Image::new(width, height, 1);
+    img.par_init_pixels(0, |(x, y)| {
+        if interrupt::is_interrupted() {
+            return [0; 3];
+        }
+        let color = scene.trace_pixel(*x, Camera::row_from_image(*y, height));
+        transfer.encode(color).to_rgb()
+    });
+    if interrupt::is_interrupted() {
+        return Err(cancelled(input, None));
+    }
+    report_missing_textures(&scene)?;
+    Ok(img)
+}
+
+/// Return the name of the scene at `input` for labels, its file name
+fn scene_label(input: &str) -> String {
+    if input.starts_with(BUILTIN_PREFIX) {
+        return input.to_string();
+    }
+    Path::new(input).file_name().map_or_else(
+        || input.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Return the path of the image of the scene at `input`
+/// An output template replaces the output directory and the output file of the scene, the one
+/// of the commandline takes precedence over the one of the scene
+fn output_path(
+    input: &str,
+    config: &Config,
+    scene: &Scene,
+    settings: &RenderSettings,
+) -> Result<PathBuf, InputError> {
+    let Some(template) = config.output_template().or(scene.get_output_template()) else {
+        return Ok(config.output_path(scene.get_output()));
+    };
+    let name = input.strip_prefix(BUILTIN_PREFIX).map_or_else(
+        || {
+            Path::new(input).file_stem().map_or_else(
+                || input.to_string(),
+                |stem| stem.to_string_lossy().into_owned(),
+            )
+        },
+        str::to_string,
+    );
+    let vars = OutputVars {
+        scene: name,
+        // animations are saved into a single file
+        frame: 1,
+        date: UtcTime::now(),
+        resolution: settings.resolution,
+        seed: settings.seed,
+    };
+    output_template_path(template, &vars)
+}
+
+/// Return the error for the interrupted render of `input`, with the `note` of a progressive render
+/// on what was saved
+fn cancelled(input: &str, note: Option<String>) -> InputError {
+    let saved = note.map(|note| format!(", {note}")).unwrap_or_default();
+    InputError::new(
+        "Render cancelled".to_string(),
+        format!("Rendering of '{input}' was interrupted{saved}"),
+    )
+}
+
+/// Print the paths of the saved images
+fn print_saved(paths: &[PathBuf]) {
+    for path in paths {
+        println!(
+            "Successfully saved image to {}",
+            path.to_str().unwrap_or("<INVALID PATH>")
+        );
+    }
+}
+
+/// Load and render the scene at `input` and save the image
+/// Returns the paths of the saved images
+fn render_scene(
+    input: &str,
+    config: &Config,
+    options: &LoadOptions,
+    progress_bar: bool,
+) -> Result<Vec<PathBuf>, InputError> {
+    let layers = config.layers();
+    let mut scene = load_scene(input, config, options, layers)?;
+    let mut settings = RenderSettings::merge(&scene, config)?;
+    settings.burn_in = config.burn_in().then(|| scene_label(input));
+    settings.apply(&mut scene);
+    // frame after applying the settings, the resolution changes the field of view
+    if config.auto_frame() && !scene.auto_frame() {
+        log::warning("The scene has no bounding box, the camera was not moved")?;
+    }
+    let frames = settings.frames;
+    let (width, height) = settings.resolution;
+    print_start(input, &scene, config, &settings);
+
+    let stats_before = IntersectionStats::collect();
+    let mut img = image::Image::new(width, height, frames);
+    img.set_transfer_function(settings.transfer);
+    img.set_channels(settings.channels);
+    let nan_detector = config
+        .detect_nan()
+        .then(|| NanDetector::new(config.highlight_nan()));
+    // layers are rendered in a single pass
+    let passes = if layers.is_empty() { frames } else { 1 };
+    let budget = RenderBudget::new(
+        config.max_seconds(),
+        config.max_samples_total(),
+        u64::from(width * height) * passes as u64,
+    );
+
+    let (tx, rx) = mpsc::channel();
+
+    // start thread for printing progress bar
+    // necessary, since `img.par_init_each_pixel(..)` blocks the main thread
+    let progressive = progressive_plan(config, &settings)?;
+    let mut components = component_images(config, &settings, progressive.is_some())?;
+    let progress_thread = progress_bar.then(|| {
+        let labels = progressive.as_ref().map_or_else(
+            || (1..=frames).map(|f| format!("Frame {f}:")).collect(),
+            |plan| plan.labels(frames),
+        );
+        spawn_progress_bar(width * height, labels, rx)
+    });
 
-    let mut outpath = PathBuf::new();
-    outpath.push(config.outdir());
-    outpath.push(scene.get_output());
+    // render all layers at once, the linear colors of a bracketed image, or the image
+    let (mut layer_pixels, mut linear_pixels) = (Vec::new(), Vec::new());
+    let bracketed = !settings.brackets.is_empty();
+    if !layers.is_empty() {
+        layer_pixels = render_layers(&scene, &img, layers, &budget, &tx);
+    } else if bracketed {
+        linear_pixels = img.par_map_pixels(|(x, y)| {
+            let split = shade_pixel(&scene, &budget, nan_detector.as_ref(), 0, (*x, *y));
+            let _ = tx.clone().send(());
+            split.total()
+        });
+    }
+    let mut outpath = output_path(input, config, &scene, &settings)?;
+    let mut progress_note = None;
+    if let Some(plan) = &progressive {
+        create_output_dir(&outpath)?;
+        progress_note = render_passes(
+            &mut scene,
+            &mut img,
+            plan,
+            (&settings, &outpath),
+            &budget,
+            nan_detector.as_ref(),
+            &tx,
+        )?;
+    } else if layers.is_empty() && !bracketed {
+        render_frames(
+            &mut scene,
+            &mut img,
+            &mut components,
+            &budget,
+            nan_detector.as_ref(),
+            &tx,
+        );
+    }
+    // stops the progress thread if the render was interrupted
+    drop(tx);
 
     if let Some(handle) = progress_thread {
         let _ = handle.join();
     }
+    if interrupt::is_interrupted() {
+        return Err(cancelled(input, progress_note));
+    }
     println!("Finished rendering, saving image...");
-
-    if config.blur() {
-        img.average_frames();
+    if let Some(note) = progress_note.filter(|_| budget.is_exhausted()) {
+        log::warning(&format!("Render budget exceeded, {note}"))?;
     }
-    if !config.blur() && scene.is_animated() {
-        img.save_apng(&mut outpath, scene.get_fps())?;
-    } else if config.ppm() {
-        img.save_ppm(&mut outpath)?;
+    report_render(&scene, &budget, config.verbose(), stats_before)?;
+
+    create_output_dir(&outpath)?;
+    let mut paths = if !layers.is_empty() {
+        save_layers(&settings, &layer_pixels, layers, &outpath)?
+    } else if bracketed {
+        save_brackets(&settings, &linear_pixels, scene.get_playback(), &outpath)?
     } else {
-        img.save_png(&mut outpath)?;
+        save_image(img, &settings, scene.get_playback(), &mut outpath)?;
+        vec![outpath.clone()]
+    };
+    paths.extend(save_aovs(
+        &mut scene, config, &settings, components, &outpath,
+    )?);
+    print_saved(&paths);
+    if let Some(msg) = nan_detector.and_then(|d| d.report()) {
+        log::warning(&msg)?;
     }
+    report_missing_textures(&scene)?;
 
-    println!(
-        "Successfully saved image to {}",
-        outpath.to_str().unwrap_or("<INVALID PATH>")
-    );
-
-    Ok(())
+    Ok(paths)
 }
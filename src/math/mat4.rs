@@ -2,7 +2,7 @@ use super::{Point3, Vec3};
 use std::ops;
 
 /// Struct to represent a 4D Matrix
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Mat4 {
     vals: [f32; 16],
 }
@@ -25,11 +25,29 @@ impl Mat4 {
 
     /// create a mat4 look at function for camera transformations
     /// takes in the camera position `from`, the point to look at `at`, and the `up` vector
+    /// If `from` and `at` coincide, the matrix looks down the negative z-axis from `from`
+    /// If `up` is parallel to the view direction, another up vector is picked
     #[inline]
     #[must_use]
     pub fn look_at(from: Point3, at: Point3, up: Vec3) -> Mat4 {
-        let z = Vec3::normal(&(from - at));
-        let x = Vec3::normal(&up.cross(&z));
+        let dir = from - at;
+        if dir.length_squared() < f32::EPSILON {
+            return Mat4::from_translation(from);
+        }
+        let z = Vec3::normal(&dir);
+        let mut x = up.cross(&z);
+        if x.length_squared() < f32::EPSILON {
+            // use the axis that is least aligned with the view direction as up
+            let fallback = if z[0].abs() < z[1].abs().min(z[2].abs()) {
+                Vec3::new(1., 0., 0.)
+            } else if z[1].abs() < z[2].abs() {
+                Vec3::new(0., 1., 0.)
+            } else {
+                Vec3::new(0., 0., 1.)
+            };
+            x = fallback.cross(&z);
+        }
+        let x = Vec3::normal(&x);
         let y = Vec3::normal(&z.cross(&x));
 
         #[rustfmt::skip]
@@ -146,6 +164,43 @@ impl Mat4 {
         Mat4 { vals }
     }
 
+    /// Invert an affine matrix (bottom row 0, 0, 0, 1), like all transformations of surfaces
+    /// Returns `None` if the matrix is singular
+    #[must_use]
+    pub fn inverse_affine(&self) -> Option<Mat4> {
+        let a = self.vals;
+        // cofactors of the upper 3x3 matrix
+        let c00 = a[5] * a[10] - a[6] * a[9];
+        let c01 = a[6] * a[8] - a[4] * a[10];
+        let c02 = a[4] * a[9] - a[5] * a[8];
+        let det = a[0] * c00 + a[1] * c01 + a[2] * c02;
+        if det.abs() < f32::EPSILON * f32::EPSILON {
+            return None;
+        }
+        let inv = 1. / det;
+
+        #[rustfmt::skip]
+        let m = [
+            c00 * inv, (a[2] * a[9] - a[1] * a[10]) * inv, (a[1] * a[6] - a[2] * a[5]) * inv,
+            c01 * inv, (a[0] * a[10] - a[2] * a[8]) * inv, (a[2] * a[4] - a[0] * a[6]) * inv,
+            c02 * inv, (a[1] * a[8] - a[0] * a[9]) * inv,  (a[0] * a[5] - a[1] * a[4]) * inv,
+        ];
+        // the translation is undone after the linear part
+        let t = [a[3], a[7], a[11]];
+        let translation =
+            |row: usize| -(m[3 * row] * t[0] + m[3 * row + 1] * t[1] + m[3 * row + 2] * t[2]);
+
+        #[rustfmt::skip]
+        let vals = [
+            m[0], m[1], m[2], translation(0),
+            m[3], m[4], m[5], translation(1),
+            m[6], m[7], m[8], translation(2),
+              0.,   0.,   0.,             1.,
+        ];
+
+        Some(Mat4 { vals })
+    }
+
     /// Multiply a point (w = 1) with the matrix
     #[inline]
     #[must_use]
@@ -268,6 +323,53 @@ mod tests {
         assert_eq!(mat, expected);
     }
 
+    fn has_nan(mat: &Mat4) -> bool {
+        mat.vals.iter().any(|v| !v.is_finite())
+    }
+
+    #[test]
+    fn look_at_own_position() {
+        let pos = Point3::new(1., 2., 3.);
+        let mat = Mat4::look_at(pos, pos, Vec3::new(0., 1., 0.));
+
+        assert!(!has_nan(&mat));
+        assert_eq!(mat, Mat4::from_translation(Vec3::new(1., 2., 3.)));
+    }
+
+    #[test]
+    fn look_at_along_up() {
+        let pos = Point3::new(0., 5., 0.);
+        let mat = Mat4::look_at(pos, Point3::zero(), Vec3::new(0., 1., 0.));
+
+        assert!(!has_nan(&mat));
+        // the camera still looks towards `at`
+        let forward = mat.transform_vector(&Vec3::new(0., 0., -1.));
+        assert!((forward - Vec3::new(0., -1., 0.)).length() < 1e-6);
+    }
+
+    #[test]
+    fn inverse_of_affine_transformation() {
+        let mat = &(&Mat4::from_translation(Vec3::new(1., -2., 3.)) * &Mat4::from_y_rotation(0.7))
+            * &Mat4::from_scaling(Vec3::new(2., 0.5, 3.));
+        let inverse = mat.inverse_affine().unwrap();
+
+        let product = &mat * &inverse;
+        let identity = Mat4::identity();
+        assert!(zip_vals(&product, &identity).all(|(a, b)| (a - b).abs() < 1e-5));
+
+        let p = Point3::new(0.3, 4., -1.);
+        let back = inverse.transform_point(&mat.transform_point(&p));
+        assert!((back - p).length() < 1e-5);
+
+        assert!(Mat4::from_scaling(Vec3::new(1., 0., 1.))
+            .inverse_affine()
+            .is_none());
+    }
+
+    fn zip_vals<'a>(a: &'a Mat4, b: &'a Mat4) -> impl Iterator<Item = (f32, f32)> + 'a {
+        a.vals.iter().copied().zip(b.vals.iter().copied())
+    }
+
     #[test]
     fn matrix_multiplication() {
         #[rustfmt::skip]
@@ -315,4 +417,130 @@ mod tests {
 
         assert_eq!(expected, transform.transform_point(&p));
     }
+
+    /// Algebraic laws checked on random well-conditioned matrices
+    /// The inputs are seeded, so failures are reproducible
+    mod laws {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        /// number of random inputs per law
+        const CASES: usize = 256;
+
+        /// Random rotation around all three axes, scaled by factors in [0.5, 2] and translated
+        fn matrix(rng: &mut StdRng) -> Mat4 {
+            let mut angle = || rng.random_range(-std::f32::consts::PI..std::f32::consts::PI);
+            let rotation = &(&Mat4::from_x_rotation(angle()) * &Mat4::from_y_rotation(angle()))
+                * &Mat4::from_z_rotation(angle());
+            let scaling = Mat4::from_scaling(Vec3::new(
+                rng.random_range(0.5..=2.),
+                rng.random_range(0.5..=2.),
+                rng.random_range(0.5..=2.),
+            ));
+            let translation = Mat4::from_translation(point(rng));
+            &(&translation * &rotation) * &scaling
+        }
+
+        fn point(rng: &mut StdRng) -> Point3 {
+            Point3::new(
+                rng.random_range(-100. ..=100.),
+                rng.random_range(-100. ..=100.),
+                rng.random_range(-100. ..=100.),
+            )
+        }
+
+        /// Check `law` for `CASES` random inputs
+        fn check(seed: u64, law: impl Fn(&mut StdRng)) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for _ in 0..CASES {
+                law(&mut rng);
+            }
+        }
+
+        fn assert_close(a: &Mat4, b: &Mat4) {
+            assert!(
+                zip_vals(a, b).all(|(x, y)| (x - y).abs() <= 1e-4 * x.abs().max(1.)),
+                "{a:?} != {b:?}"
+            );
+        }
+
+        #[test]
+        fn identity_is_neutral() {
+            check(1, |rng| {
+                let m = matrix(rng);
+                assert_eq!(&Mat4::identity() * &m, m);
+                assert_eq!(&m * &Mat4::identity(), m);
+            });
+        }
+
+        #[test]
+        fn inverse_undoes_the_matrix() {
+            check(2, |rng| {
+                let m = matrix(rng);
+                let inverse = m.inverse_affine().expect("matrix should not be singular");
+                assert_close(&(&inverse * &m), &Mat4::identity());
+                assert_close(&(&m * &inverse), &Mat4::identity());
+            });
+        }
+
+        #[test]
+        fn inverse_undoes_point_transformation() {
+            check(3, |rng| {
+                let (m, p) = (matrix(rng), point(rng));
+                let inverse = m.inverse_affine().expect("matrix should not be singular");
+                let back = inverse.transform_point(&m.transform_point(&p));
+                assert!((back - p).length() <= 1e-3, "{back:?} != {p:?}");
+            });
+        }
+
+        #[test]
+        fn transpose_is_an_involution() {
+            check(4, |rng| {
+                let m = matrix(rng);
+                assert_eq!(Mat4::transpose(&Mat4::transpose(&m)), m);
+            });
+        }
+
+        #[test]
+        fn identity_keeps_points_and_vectors() {
+            check(5, |rng| {
+                let p = point(rng);
+                assert_eq!(Mat4::identity().transform_point(&p), p);
+                assert_eq!(Mat4::identity().transform_vector(&p), p);
+            });
+        }
+
+        #[test]
+        fn multiplication_is_associative() {
+            check(6, |rng| {
+                let (a, b, c) = (matrix(rng), matrix(rng), matrix(rng));
+                let lhs = &(&a * &b) * &c;
+                let rhs = &a * &(&b * &c);
+                assert_close(&lhs, &rhs);
+            });
+        }
+
+        #[test]
+        fn mul_assign_matches_mul() {
+            check(7, |rng| {
+                let (a, b) = (matrix(rng), matrix(rng));
+                let mut assigned = a;
+                assigned *= &b;
+                assert_eq!(assigned, &a * &b);
+            });
+        }
+
+        #[test]
+        fn product_transforms_like_composition() {
+            check(8, |rng| {
+                let (a, b, p) = (matrix(rng), matrix(rng), point(rng));
+                let composed = a.transform_point(&b.transform_point(&p));
+                let product = (&a * &b).transform_point(&p);
+                assert!(
+                    (composed - product).length() <= 1e-4 * composed.length().max(1.),
+                    "{composed:?} != {product:?}"
+                );
+            });
+        }
+    }
 }
@@ -4,11 +4,13 @@
 mod mat4;
 mod quat;
 mod ray;
+mod sampling;
 mod util;
 mod vec3;
 
 pub use mat4::*;
 pub use quat::*;
 pub use ray::*;
+pub use sampling::*;
 pub use util::*;
 pub use vec3::*;
@@ -19,6 +19,35 @@ impl Quat {
         }
     }
 
+    /// Create the unit quaternion of the shortest rotation that turns the direction `a` into the
+    /// direction `b`
+    /// The rotation by twice the angle between `a` and the half-vector of `a` and `b` is the
+    /// rotation from `a` to `b`. If `a` and `b` point in opposite directions, the rotation is a
+    /// half turn around an arbitrary axis perpendicular to `a`
+    #[must_use]
+    pub fn from_rotation_between(a: Vec3, b: Vec3) -> Quat {
+        let a = Vec3::normal(&a);
+        let b = Vec3::normal(&b);
+        let half = a + b;
+        if half.length_squared() < 1e-12 {
+            // any axis that is not parallel to `a` gives a perpendicular axis
+            let other = if a[0].abs() < 0.9 {
+                Vec3::new(1., 0., 0.)
+            } else {
+                Vec3::new(0., 1., 0.)
+            };
+            return Quat {
+                r: 0.,
+                v: Vec3::normal(&a.cross(&other)),
+            };
+        }
+        let half = Vec3::normal(&half);
+        Quat {
+            r: a.dot(&half),
+            v: a.cross(&half),
+        }
+    }
+
     /// Computes the square of the quaternion
     /// Same as `q * q` but more efficient
     #[must_use]
@@ -104,6 +133,31 @@ mod tests {
         assert_eq!(expected, &lhs * &rhs);
     }
 
+    fn assert_quat_eq(a: Quat, b: Quat) {
+        assert!((a - b).length() < 1e-6, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn rotation_between_directions() {
+        let z = Vec3::new(0., 0., 1.);
+        assert_quat_eq(Quat::from_rotation_between(z, z), Quat::new(1., 0., 0., 0.));
+
+        // 90 degrees around the z axis
+        let half_angle = std::f32::consts::FRAC_PI_4;
+        assert_quat_eq(
+            Quat::from_rotation_between(Vec3::new(1., 0., 0.), Vec3::new(0., 2., 0.)),
+            Quat::new(half_angle.cos(), 0., 0., half_angle.sin()),
+        );
+
+        // opposite directions are a half turn around a perpendicular axis
+        for a in [Vec3::new(1., 0., 0.), Vec3::new(0., 0.6, 0.8)] {
+            let q = Quat::from_rotation_between(a, -a);
+            assert!(q.r.abs() < 1e-6);
+            assert!((q.length() - 1.).abs() < 1e-6);
+            assert!(q.v.dot(&a).abs() < 1e-6);
+        }
+    }
+
     #[test]
     fn quat_square() {
         let q = Quat::new(1., 2., 3., 4.);
@@ -2,13 +2,41 @@ use crate::math::Mat4;
 
 use super::{Point3, Vec3};
 
+/// Derivatives of the origin and direction of a ray with respect to the screen coordinates
+/// See [Igehy, Tracing Ray Differentials](https://graphics.stanford.edu/papers/trd/)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Differentials {
+    pub dodx: Vec3,
+    pub dody: Vec3,
+    pub dddx: Vec3,
+    pub dddy: Vec3,
+}
+
+impl Differentials {
+    /// Scale all derivatives by `s`
+    #[must_use]
+    pub fn scaled(self, s: f32) -> Differentials {
+        Differentials {
+            dodx: self.dodx * s,
+            dody: self.dody * s,
+            dddx: self.dddx * s,
+            dddy: self.dddy * s,
+        }
+    }
+}
+
 /// Struct to represent a ray that goes through `origin` in direction `direction`
 /// The ray goes only in the positive direction and can be bounded
+/// A ray can carry the time at which it sees the scene, so that animated surfaces are evaluated
+/// at that time instead of the current frame
 #[derive(Clone, Copy)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
     max_t: f32,
+    differentials: Option<Differentials>,
+    /// percentage of the animation that is finished when the ray is shot
+    time: Option<f32>,
 }
 
 impl Ray {
@@ -20,6 +48,8 @@ impl Ray {
             origin,
             direction,
             max_t: f32::INFINITY,
+            differentials: None,
+            time: None,
         }
     }
 
@@ -27,18 +57,58 @@ impl Ray {
     #[inline]
     #[must_use]
     pub fn set_bounds(self, max_t: f32) -> Ray {
+        Ray { max_t, ..self }
+    }
+
+    /// Attach ray differentials to the ray
+    #[inline]
+    #[must_use]
+    pub fn with_differentials(self, differentials: Differentials) -> Ray {
         Ray {
-            origin: self.origin,
-            direction: self.direction,
-            max_t,
+            differentials: Some(differentials),
+            ..self
         }
     }
 
+    /// Set the time of the ray as the percentage of the animation that is finished
+    /// `None` makes the ray see the scene in its current frame
+    #[inline]
+    #[must_use]
+    pub fn with_time(self, time: Option<f32>) -> Ray {
+        Ray { time, ..self }
+    }
+
+    /// get the time of the ray as the percentage of the animation that is finished, if it has one
+    #[inline]
+    #[must_use]
+    pub fn time(&self) -> Option<f32> {
+        self.time
+    }
+
+    /// get the ray differentials if the ray carries them
+    #[inline]
+    #[must_use]
+    pub fn differentials(&self) -> Option<&Differentials> {
+        self.differentials.as_ref()
+    }
+
+    /// Return the width of the area covered by the ray at `t` as given by its differentials
+    /// Returns 0 if the ray doesn't carry differentials
+    #[must_use]
+    pub fn footprint_at(&self, t: f32) -> f32 {
+        self.differentials.map_or(0., |d| {
+            let dx = (d.dodx + t * d.dddx).length();
+            let dy = (d.dody + t * d.dddy).length();
+            dx.max(dy)
+        })
+    }
+
     /// calculate the point on the ray for `t`
+    /// Returns `None` if `t` is not in range for this ray
     #[inline]
     #[must_use]
     pub fn at(&self, t: f32) -> Option<Point3> {
-        if (0.0..=self.max_t).contains(&t) {
+        if self.t_in_range(t) {
             Some(self.origin + t * self.direction)
         } else {
             None
@@ -52,20 +122,48 @@ impl Ray {
     pub fn transform(&self, t: &Mat4) -> Ray {
         let orig = t.transform_point(&self.origin);
         let dir = t.transform_vector(&self.direction);
-        Ray::new(orig, dir).set_bounds(self.max_t)
+        Ray {
+            origin: orig,
+            direction: dir,
+            max_t: self.max_t,
+            differentials: self.differentials.map(|d| Differentials {
+                dodx: t.transform_vector(&d.dodx),
+                dody: t.transform_vector(&d.dody),
+                dddx: t.transform_vector(&d.dddx),
+                dddy: t.transform_vector(&d.dddy),
+            }),
+            time: self.time,
+        }
     }
 
     /// Normalize the ray direction
+    /// The direction differentials are adjusted to the normalized direction
     #[must_use]
     pub fn normal(&self) -> Ray {
-        Ray::new(self.origin, Vec3::normal(&self.direction))
+        let length = self.direction.length();
+        let dir = self.direction / length;
+        // derivative of d / |d|
+        let normalize = |dd: Vec3| (dd - dir.dot(&dd) * dir) / length;
+        Ray {
+            origin: self.origin,
+            direction: dir,
+            max_t: f32::INFINITY,
+            differentials: self.differentials.map(|d| Differentials {
+                dddx: normalize(d.dddx),
+                dddy: normalize(d.dddy),
+                ..d
+            }),
+            time: self.time,
+        }
     }
 
     /// determine if t value is in range for this ray
+    /// `t = 0` is excluded, so a ray starting on a surface doesn't hit that surface again at its
+    /// origin
     #[inline]
     #[must_use]
     pub fn t_in_range(&self, t: f32) -> bool {
-        (0.0..=self.max_t).contains(&t)
+        t > f32::EPSILON && t <= self.max_t
     }
 
     /// get the direction of the ray
@@ -106,6 +204,43 @@ mod tests {
         assert_eq!(p.unwrap(), expected);
     }
 
+    #[test]
+    fn normalize_keeps_differentials_orthogonal() {
+        let d = Differentials {
+            dodx: Vec3::zero(),
+            dody: Vec3::zero(),
+            dddx: Vec3::new(0.1, 0., 0.),
+            dddy: Vec3::new(0., 0.1, 0.),
+        };
+        let ray = Ray::new(Point3::zero(), Vec3::new(1., 0., -1.))
+            .with_differentials(d)
+            .normal();
+        let d = ray.differentials().unwrap();
+
+        assert!(d.dddx.dot(ray.dir()).abs() < 1e-6);
+        assert!(d.dddy.dot(ray.dir()).abs() < 1e-6);
+        assert!((ray.footprint_at(2.) - 0.2 / 2f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn time_survives_transformations() {
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -2.)).with_time(Some(0.25));
+        assert_eq!(ray.time(), Some(0.25));
+        assert_eq!(ray.normal().time(), Some(0.25));
+        let moved = ray.transform(&Mat4::from_translation(Vec3::new(1., 0., 0.)));
+        assert_eq!(moved.time(), Some(0.25));
+        assert_eq!(Ray::new(Point3::zero(), Vec3::new(1., 0., 0.)).time(), None);
+    }
+
+    #[test]
+    fn origin_not_in_range() {
+        let ray = Ray::new(Point3::zero(), Vec3::new(1., 0., 0.)).set_bounds(1.0);
+
+        assert!(!ray.t_in_range(0.));
+        assert!(ray.t_in_range(1e-3));
+        assert!(ray.t_in_range(1.));
+    }
+
     #[test]
     fn point_notat_ray_with_bounds() {
         let ray = Ray::new(Point3::new(1., 0., 0.), Vec3::new(1., 1., 1.)).set_bounds(1.0);
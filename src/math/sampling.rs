@@ -0,0 +1,187 @@
+use std::f32::consts::{PI, TAU};
+
+use super::{Point3, Vec3};
+
+/// Angle between two consecutive points of a golden angle spiral
+const GOLDEN_ANGLE: f32 = PI * 0.763_932;
+
+/// Return `count` points that cover the unit disc evenly, as offsets along two axes
+/// The points lie on a golden angle spiral (Vogel's method) that is turned by `rotation` turns,
+/// so neighbouring sample sites can use different points without a random number generator
+pub fn disc_samples(count: u32, rotation: f32) -> impl Iterator<Item = (f32, f32)> {
+    #[allow(clippy::cast_precision_loss)]
+    let n = count.max(1) as f32;
+    (0..count.max(1)).map(move |i| {
+        #[allow(clippy::cast_precision_loss)]
+        let i = i as f32;
+        let radius = ((i + 0.5) / n).sqrt();
+        let angle = i * GOLDEN_ANGLE + rotation * TAU;
+        (radius * angle.cos(), radius * angle.sin())
+    })
+}
+
+/// Return a rotation in [0, 1) that is derived from the position of `point`
+/// Used to turn the sample pattern of [`disc_samples`] differently at every point
+#[must_use]
+pub fn point_rotation(point: &Point3) -> f32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for i in 0..3 {
+        hash = (hash ^ point[i].to_bits()).wrapping_mul(0x0100_0193);
+    }
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    #[allow(clippy::cast_precision_loss)]
+    let rotation = (hash >> 8) as f32 / (1 << 24) as f32;
+    rotation
+}
+
+/// Prime bases of the dimensions of [`halton_sample`]: the position in the pixel and on the lens
+const HALTON_BASES: [u32; 4] = [2, 3, 5, 7];
+
+/// Return the element `index` of the Halton sequence in `base`, the digits of `index` in `base`
+/// mirrored at the decimal point
+#[must_use]
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    #[allow(clippy::cast_precision_loss)]
+    let inv_base = 1. / base as f32;
+    let mut scale = inv_base;
+    let mut result = 0.;
+    while index > 0 {
+        #[allow(clippy::cast_precision_loss)]
+        let digit = (index % base) as f32;
+        result += digit * scale;
+        scale *= inv_base;
+        index /= base;
+    }
+    result
+}
+
+/// Return the shift of the samples of pixel `(u, v)` in every dimension of [`halton_sample`]
+/// Derived from the pixel and `seed`, so neighbouring pixels use differently shifted sample sets
+#[must_use]
+pub fn pixel_shift(u: u32, v: u32, seed: u64) -> [f32; 4] {
+    let mut state = seed ^ (u64::from(u) << 32 | u64::from(v));
+    std::array::from_fn(|_| {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        #[allow(clippy::cast_precision_loss)]
+        let shift = (z >> 40) as f32 / (1u64 << 24) as f32;
+        shift
+    })
+}
+
+/// Return the sample `index` of a pixel, a point in `[0, 1)^4` of the Halton sequence shifted by
+/// `shift` (a Cranley-Patterson rotation)
+/// The first two dimensions place the sample in the pixel, the last two on the lens. The samples
+/// of a pixel cover all dimensions evenly, and the shift of [`pixel_shift`] keeps pixels from
+/// repeating the same pattern, which would show as bands instead of noise
+#[must_use]
+pub fn halton_sample(index: u32, shift: [f32; 4]) -> [f32; 4] {
+    std::array::from_fn(|d| {
+        let x = halton(index, HALTON_BASES[d]) + shift[d];
+        // rounding can land exactly on 1
+        if x >= 1. {
+            (x - 1.).min(1. - f32::EPSILON)
+        } else {
+            x
+        }
+    })
+}
+
+/// Return two unit vectors that are orthogonal to each other and to the unit vector `normal`
+#[must_use]
+pub fn tangent_frame(normal: &Vec3) -> (Vec3, Vec3) {
+    // use the axis least aligned with the normal to build the frame
+    let helper = if normal[0].abs() < 0.9 {
+        Vec3::new(1., 0., 0.)
+    } else {
+        Vec3::new(0., 1., 0.)
+    };
+    let tangent = Vec3::normal(&helper.cross(normal));
+    let bitangent = normal.cross(&tangent);
+    (tangent, bitangent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disc_samples_cover_the_disc() {
+        let samples: Vec<_> = disc_samples(64, 0.3).collect();
+        assert_eq!(samples.len(), 64);
+        assert!(samples.iter().all(|(x, y)| x * x + y * y <= 1.));
+        // every quadrant gets about a quarter of the points
+        for (sx, sy) in [(1., 1.), (-1., 1.), (-1., -1.), (1., -1.)] {
+            let count = samples
+                .iter()
+                .filter(|(x, y)| x * sx >= 0. && y * sy >= 0.)
+                .count();
+            assert!((12..=20).contains(&count), "{count}");
+        }
+        let (cx, cy) = samples
+            .iter()
+            .fold((0., 0.), |(ax, ay), (x, y)| (ax + x, ay + y));
+        assert!(cx.abs() < 1. && cy.abs() < 1.);
+
+        let rotation = point_rotation(&Point3::new(0.5, 1., -2.));
+        assert!((0. ..1.).contains(&rotation));
+        assert!((rotation - point_rotation(&Point3::new(0.5, 1., -2.001))).abs() > 1e-6);
+    }
+
+    /// Return the star discrepancy of `points` in the unit square, the largest difference between
+    /// the share of points in a box at the origin and the area of the box
+    #[allow(clippy::cast_precision_loss)]
+    fn star_discrepancy(points: &[(f32, f32)]) -> f32 {
+        let n = points.len() as f32;
+        let corners = |i: fn(&(f32, f32)) -> f32| {
+            let mut c: Vec<f32> = points.iter().map(i).chain([1.]).collect();
+            c.sort_by(f32::total_cmp);
+            c
+        };
+        let (xs, ys) = (corners(|p| p.0), corners(|p| p.1));
+        let mut worst = 0f32;
+        for x in &xs {
+            for y in &ys {
+                let open = points.iter().filter(|p| p.0 < *x && p.1 < *y).count() as f32;
+                let closed = points.iter().filter(|p| p.0 <= *x && p.1 <= *y).count() as f32;
+                let area = x * y;
+                worst = worst.max(area - open / n).max(closed / n - area);
+            }
+        }
+        worst
+    }
+
+    #[test]
+    fn pixel_samples_are_decorrelated_and_well_distributed() {
+        assert!((halton(1, 2) - 0.5).abs() < 1e-6);
+        assert!((halton(6, 3) - 2. / 9.).abs() < 1e-6);
+
+        let samples = |u, v| -> Vec<[f32; 4]> {
+            let shift = pixel_shift(u, v, 7);
+            (0..64).map(|i| halton_sample(i, shift)).collect()
+        };
+        let first = samples(0, 0);
+        for (u, v) in [(1, 0), (0, 1), (5, 9), (200, 3)] {
+            let set = samples(u, v);
+            assert!(set.iter().flatten().all(|x| (0. ..1.).contains(x)));
+            // neighbouring pixels don't share the pattern
+            assert!(set
+                .iter()
+                .zip(&first)
+                .all(|(a, b)| (a[0] - b[0]).abs() > 1e-6 || (a[2] - b[2]).abs() > 1e-6));
+            // 64 random points have a discrepancy above 0.1, the shifted Halton points stay below
+            for dims in [(0, 1), (2, 3)] {
+                let points: Vec<_> = set.iter().map(|s| (s[dims.0], s[dims.1])).collect();
+                let discrepancy = star_discrepancy(&points);
+                assert!(discrepancy < 0.1, "{discrepancy} in {dims:?} of ({u}, {v})");
+            }
+        }
+        assert!((pixel_shift(3, 4, 1)[0] - pixel_shift(3, 4, 2)[0]).abs() > 1e-6);
+    }
+}
@@ -21,19 +21,15 @@ pub type Color = Vec3;
 
 impl Color {
     /// Convert a color with values in range 0 to 1 to an RGB value with values from 0 to 255
-    /// The components get clamped at 0 and 1
+    /// The components get clamped at 0 and 1, NaN components become 0
     #[inline]
     #[must_use]
-    #[allow(
-        clippy::cast_possible_truncation,
-        clippy::cast_sign_loss,
-        clippy::cast_precision_loss
-    )]
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
     pub fn to_rgb(self) -> image::Rgb {
-        let r = (255.999 * self.x.clamp(0.0, 1.0)) as u8;
-        let g = (255.999 * self.y.clamp(0.0, 1.0)) as u8;
-        let b = (255.999 * self.z.clamp(0.0, 1.0)) as u8;
-        [r, g, b]
+        [self.x, self.y, self.z].map(|c| {
+            let c = if c.is_nan() { 0.0 } else { c.clamp(0.0, 1.0) };
+            (255.999 * c) as u8
+        })
     }
 
     /// Construct a color with values in range 0..1 from an Rgb value with values in range 0..255
@@ -45,13 +41,37 @@ impl Color {
         let b = f32::from(rgb[2]) / 255.999;
         Color { x: r, y: g, z: b }
     }
+
+    /// Return the relative luminance of a linear srgb color
+    #[inline]
+    #[must_use]
+    pub fn luminance(self) -> f32 {
+        0.2126 * self.x + 0.7152 * self.y + 0.0722 * self.z
+    }
+
+    /// Scale the chroma of the color by `amount` while keeping its luminance
+    /// An amount of 0 returns a gray with the same luminance, 1 returns the color unchanged
+    #[inline]
+    #[must_use]
+    pub fn saturate(self, amount: f32) -> Color {
+        let l = self.luminance();
+        let gray = Color::new(l, l, l);
+        gray + (self - gray) * amount
+    }
+
+    /// Change the exposure of the color by `stops` (each stop doubles the brightness)
+    #[inline]
+    #[must_use]
+    pub fn exposure(self, stops: f32) -> Color {
+        self * stops.exp2()
+    }
 }
 
 impl Vec3 {
     /// Create a new Vector from 3 floats
     #[inline]
     #[must_use]
-    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Vec3 {
         Vec3 { x, y, z }
     }
 
@@ -67,6 +87,13 @@ impl Vec3 {
         }
     }
 
+    /// Return true if no component is NaN or infinite
+    #[inline]
+    #[must_use]
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
     /// Creates a Vector with all components = 0
     #[inline]
     #[must_use]
@@ -377,4 +404,195 @@ mod test {
 
         assert_eq!(pixel, expected);
     }
+
+    #[test]
+    fn color_luminance() {
+        assert!((Color::new(1., 1., 1.).luminance() - 1.).abs() < 1e-6);
+        assert!((Color::new(0., 1., 0.).luminance() - 0.7152).abs() < 1e-6);
+    }
+
+    #[test]
+    fn color_saturate_and_exposure() {
+        let c = Color::new(0.8, 0.4, 0.1);
+
+        let gray = c.saturate(0.);
+        assert!((gray.x - gray.y).abs() < 1e-6 && (gray.y - gray.z).abs() < 1e-6);
+        assert!((gray.luminance() - c.luminance()).abs() < 1e-6);
+        assert!((c.saturate(1.) - c).length() < 1e-6);
+
+        assert_eq!(c.exposure(1.), c * 2.);
+        assert_eq!(c.exposure(-2.), c * 0.25);
+    }
+
+    #[test]
+    fn nan_color_to_rgb_is_black() {
+        let c = Color::new(f32::NAN, 0.5, f32::INFINITY);
+        assert_eq!(c.to_rgb(), [0, 127, 255]);
+        assert!(!c.is_finite());
+        assert!(Color::new(0.1, 0.2, 0.3).is_finite());
+    }
+
+    /// Algebraic laws checked on random vectors
+    /// The inputs are seeded, so failures are reproducible
+    mod laws {
+        use super::*;
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        /// number of random inputs per law
+        const CASES: usize = 256;
+        /// bound of the components, small enough to not overflow products
+        const RANGE: f32 = 1e6;
+
+        fn scalar(rng: &mut StdRng) -> f32 {
+            rng.random_range(-RANGE..=RANGE)
+        }
+
+        fn vector(rng: &mut StdRng) -> Vec3 {
+            Vec3::new(scalar(rng), scalar(rng), scalar(rng))
+        }
+
+        /// Check `law` for `CASES` random inputs
+        fn check(seed: u64, law: impl Fn(&mut StdRng)) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            for _ in 0..CASES {
+                law(&mut rng);
+            }
+        }
+
+        /// Assert that `a` and `b` only differ by rounding errors relative to `scale`
+        fn assert_close(a: Vec3, b: Vec3, scale: f32) {
+            let tolerance = 1e-5 * scale.max(1.);
+            assert!(
+                (0..3).all(|i| (a[i] - b[i]).abs() <= tolerance),
+                "{a:?} != {b:?}"
+            );
+        }
+
+        #[test]
+        fn addition_is_commutative() {
+            check(1, |rng| {
+                let (a, b) = (vector(rng), vector(rng));
+                assert_eq!(a + b, b + a);
+            });
+        }
+
+        #[test]
+        fn addition_is_associative() {
+            check(2, |rng| {
+                let (a, b, c) = (vector(rng), vector(rng), vector(rng));
+                let scale = a.length() + b.length() + c.length();
+                assert_close((a + b) + c, a + (b + c), scale);
+            });
+        }
+
+        #[test]
+        fn zero_is_neutral() {
+            check(3, |rng| {
+                let a = vector(rng);
+                assert_eq!(a + Vec3::zero(), a);
+                assert_eq!(a - Vec3::zero(), a);
+            });
+        }
+
+        #[test]
+        fn subtraction_is_addition_of_negation() {
+            check(4, |rng| {
+                let (a, b) = (vector(rng), vector(rng));
+                assert_eq!(a - a, Vec3::zero());
+                assert_eq!(a - b, a + -b);
+                assert_eq!(-(-a), a);
+            });
+        }
+
+        #[test]
+        fn scalar_multiplication_distributes_over_vectors() {
+            check(5, |rng| {
+                let (a, b, s) = (vector(rng), vector(rng), scalar(rng));
+                let scale = s.abs() * (a.length() + b.length());
+                assert_close(s * (a + b), s * a + s * b, scale);
+            });
+        }
+
+        #[test]
+        fn scalar_multiplication_distributes_over_scalars() {
+            check(6, |rng| {
+                let (a, s, t) = (vector(rng), scalar(rng), scalar(rng));
+                let scale = (s.abs() + t.abs()) * a.length();
+                assert_close((s + t) * a, s * a + t * a, scale);
+            });
+        }
+
+        #[test]
+        fn multiplication_by_zero_and_one() {
+            check(7, |rng| {
+                let a = vector(rng);
+                assert_eq!(a * 0., Vec3::zero());
+                assert_eq!(a * 1., a);
+                assert_eq!(a * 2., 2. * a);
+            });
+        }
+
+        #[test]
+        fn division_undoes_multiplication() {
+            check(8, |rng| {
+                let (a, s) = (vector(rng), scalar(rng));
+                if s.abs() < 1e-3 {
+                    return;
+                }
+                assert_close(a * s / s, a, a.length());
+            });
+        }
+
+        #[test]
+        fn dot_product_is_commutative_and_matches_length() {
+            check(9, |rng| {
+                let (a, b) = (vector(rng), vector(rng));
+                assert!((a.dot(&b) - b.dot(&a)).abs() <= f32::EPSILON);
+                assert!((a.dot(&a) - a.length_squared()).abs() <= f32::EPSILON);
+            });
+        }
+
+        #[test]
+        fn normalized_vectors_have_unit_length() {
+            check(10, |rng| {
+                let a = vector(rng);
+                if a.length() < 1e-3 {
+                    return;
+                }
+                assert!((Vec3::normal(&a).length() - 1.).abs() < 1e-6);
+            });
+        }
+
+        #[test]
+        fn cross_product_is_anticommutative() {
+            check(11, |rng| {
+                let (a, b) = (vector(rng), vector(rng));
+                assert_eq!(a.cross(&b), -b.cross(&a));
+                assert_eq!(a.cross(&a), Vec3::zero());
+            });
+        }
+
+        #[test]
+        fn cross_product_is_orthogonal() {
+            check(12, |rng| {
+                let (a, b) = (vector(rng), vector(rng));
+                let c = a.cross(&b);
+                let scale = a.length() * b.length();
+                assert!(c.dot(&a).abs() <= 1e-5 * scale * a.length());
+                assert!(c.dot(&b).abs() <= 1e-5 * scale * b.length());
+            });
+        }
+
+        #[test]
+        fn reflection_keeps_length() {
+            check(13, |rng| {
+                let (a, n) = (vector(rng), vector(rng));
+                if n.length() < 1e-3 {
+                    return;
+                }
+                let r = Vec3::reflect(&a, &Vec3::normal(&n));
+                assert!((r.length() - a.length()).abs() <= 1e-5 * a.length().max(1.));
+            });
+        }
+    }
 }
@@ -0,0 +1,269 @@
+//! Render budget
+//! Limits the wall time and the number of camera samples spent on a render, so that renders
+//! finish within a time box. Once the budget is used up, the remaining pixels are skipped, or
+//! rendered with fewer samples if the scene is super-sampled
+
+use std::{
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use super::interrupt::{self, StopToken};
+
+/// Tracks the time and samples spent on a render
+/// Can be shared between the threads rendering the image
+#[derive(Debug)]
+pub struct RenderBudget {
+    start: Instant,
+    max_time: Option<Duration>,
+    max_samples: Option<u64>,
+    /// number of pixels of the whole render
+    total_pixels: u64,
+    /// camera samples spent so far
+    spent: AtomicU64,
+    rendered: AtomicU64,
+    skipped: AtomicU64,
+    /// number of budget checks, the clock is only read on every `CLOCK_INTERVAL`th one
+    checks: AtomicU64,
+    /// stopped once the budget is used up
+    exhausted: StopToken,
+}
+
+impl RenderBudget {
+    /// Number of checks between two reads of the clock
+    const CLOCK_INTERVAL: u64 = 64;
+    /// Number of samples of a pixel that are traced between two checks of the budget
+    const SAMPLE_BATCH: u32 = 16;
+
+    /// Create a new budget for a render of `total_pixels` pixels
+    /// Without any limit, the budget is never exhausted
+    #[must_use]
+    pub fn new(
+        max_seconds: Option<f64>,
+        max_samples: Option<u64>,
+        total_pixels: u64,
+    ) -> RenderBudget {
+        RenderBudget {
+            start: Instant::now(),
+            max_time: max_seconds.map(Duration::from_secs_f64),
+            max_samples,
+            total_pixels,
+            spent: AtomicU64::new(0),
+            rendered: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            checks: AtomicU64::new(0),
+            exhausted: StopToken::new(),
+        }
+    }
+
+    /// Return true if the render has a time or sample limit
+    #[must_use]
+    pub fn is_limited(&self) -> bool {
+        self.max_time.is_some() || self.max_samples.is_some()
+    }
+
+    /// Return true if the time or the samples of the budget are used up
+    /// The clock is only read every few calls, so this is cheap enough to call for every pixel
+    #[must_use]
+    pub fn is_exhausted(&self) -> bool {
+        if self.exhausted.is_stopped() {
+            return true;
+        }
+        let over_samples = self
+            .max_samples
+            .is_some_and(|max| self.spent.load(Ordering::Relaxed) >= max);
+        let over_time = self.max_time.is_some_and(|max| {
+            self.checks
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(Self::CLOCK_INTERVAL)
+                && self.start.elapsed() >= max
+        });
+        if over_samples || over_time {
+            self.exhausted.stop();
+        }
+        over_samples || over_time
+    }
+
+    /// Return true if the time or the samples of the budget are used up, always reading the clock
+    /// Meant for the checks between progressive passes, which are too rare for the clock that
+    /// [`RenderBudget::is_exhausted`] only reads every few calls
+    #[must_use]
+    pub fn is_exhausted_now(&self) -> bool {
+        let over_time = self.max_time.is_some_and(|max| self.start.elapsed() >= max);
+        if over_time {
+            self.exhausted.stop();
+        }
+        over_time || self.is_exhausted()
+    }
+
+    /// Record `samples` camera samples of a progressive pass
+    /// Unlike [`RenderBudget::spend`], no pixel is counted as rendered, a pass refines pixels
+    /// that were already rendered
+    pub fn spend_samples(&self, samples: u64) {
+        self.spent.fetch_add(samples, Ordering::Relaxed);
+    }
+
+    /// Return the number of samples to spend on the next pixel, if every pixel should get `full`
+    /// samples
+    /// The rest of the budget is spread evenly over the pixels that are left, but every pixel gets
+    /// at least one sample
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn samples_for_pixel(&self, full: u32) -> u32 {
+        if !self.is_limited() {
+            return full;
+        }
+        let done = self.rendered.load(Ordering::Relaxed) + self.skipped.load(Ordering::Relaxed);
+        let pixels_left = self.total_pixels.saturating_sub(done).max(1);
+        let spent = self.spent.load(Ordering::Relaxed);
+        let mut affordable = f64::from(full) * pixels_left as f64;
+        if let Some(max) = self.max_samples {
+            affordable = affordable.min(max.saturating_sub(spent) as f64);
+        }
+        if let Some(max) = self.max_time {
+            // estimate the cost of a sample from the samples spent so far
+            let elapsed = self.start.elapsed();
+            if spent > 0 && elapsed > Duration::ZERO {
+                let per_second = spent as f64 / elapsed.as_secs_f64();
+                affordable = affordable.min(max.saturating_sub(elapsed).as_secs_f64() * per_second);
+            }
+        }
+        ((affordable / pixels_left as f64) as u32).clamp(1, full.max(1))
+    }
+
+    /// Trace up to `planned` samples of a pixel in batches, calling `trace` with the range of
+    /// samples of each batch, and record the pixel as rendered
+    /// The deadline is checked after every batch, so the budget also holds while a pixel with many
+    /// samples is traced. The pixel stops early once the budget is used up or the render is
+    /// interrupted, but gets at least one batch. Returns the number of samples that were traced
+    pub fn trace_samples(&self, planned: u32, mut trace: impl FnMut(Range<u32>)) -> u32 {
+        let mut traced = 0;
+        while traced < planned {
+            let batch = traced..planned.min(traced + Self::SAMPLE_BATCH);
+            traced = batch.end;
+            self.spend_samples(u64::from(batch.end - batch.start));
+            trace(batch);
+            if interrupt::is_interrupted() || (self.is_limited() && self.is_exhausted_now()) {
+                break;
+            }
+        }
+        self.rendered.fetch_add(1, Ordering::Relaxed);
+        traced
+    }
+
+    /// Record a pixel that was rendered with `samples` samples
+    pub fn spend(&self, samples: u32) {
+        self.spent.fetch_add(u64::from(samples), Ordering::Relaxed);
+        self.rendered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a pixel that was skipped, because the budget was exhausted
+    pub fn skip(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Return a message stating how much of the render was left out, or None if the whole render
+    /// fit into the budget
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn report(&self, full_samples: u32) -> Option<String> {
+        let rendered = self.rendered.load(Ordering::Relaxed);
+        let skipped = self.skipped.load(Ordering::Relaxed);
+        let spent = self.spent.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64();
+        if skipped > 0 {
+            return Some(format!(
+                "Render budget exceeded after {elapsed:.1}s: {skipped} of {} pixels were skipped and filled with the background",
+                rendered + skipped
+            ));
+        }
+        let full = rendered * u64::from(full_samples.max(1));
+        (spent < full).then(|| {
+            format!(
+                "Render budget exceeded after {elapsed:.1}s: pixels got {:.1} samples on average instead of {full_samples}",
+                spent as f64 / rendered.max(1) as f64
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_budget_skips_remaining_pixels() {
+        let budget = RenderBudget::new(None, Some(10), 20);
+        let mut rendered = 0;
+        for _ in 0..20 {
+            if budget.is_exhausted() {
+                budget.skip();
+            } else {
+                budget.spend(1);
+                rendered += 1;
+            }
+        }
+        assert_eq!(rendered, 10);
+        let report = budget.report(1).unwrap();
+        assert!(report.contains("10 of 20 pixels"), "{report}");
+    }
+
+    #[test]
+    fn sample_budget_reduces_samples_per_pixel() {
+        // 16 samples per pixel would need 160 samples
+        let budget = RenderBudget::new(None, Some(40), 10);
+        let samples: Vec<_> = (0..10)
+            .map(|_| {
+                let n = budget.samples_for_pixel(16);
+                budget.spend(n);
+                n
+            })
+            .collect();
+        assert!(samples.iter().all(|n| (1..=16).contains(n)));
+        assert_eq!(samples.iter().sum::<u32>(), 40);
+        assert!(budget
+            .report(16)
+            .unwrap()
+            .contains("4.0 samples on average"));
+    }
+
+    #[test]
+    fn deadline_stops_pixels_between_sample_batches() {
+        let batches = |budget: &RenderBudget| {
+            let mut batches = Vec::new();
+            let traced = budget.trace_samples(40, |batch| batches.push((batch.start, batch.end)));
+            (traced, batches)
+        };
+        assert_eq!(
+            batches(&RenderBudget::new(None, None, 1)),
+            (40, vec![(0, 16), (16, 32), (32, 40)])
+        );
+
+        // the time is up after the first batch
+        let budget = RenderBudget::new(Some(0.), None, 1);
+        assert_eq!(batches(&budget), (16, vec![(0, 16)]));
+        assert!(budget
+            .report(40)
+            .unwrap()
+            .contains("16.0 samples on average"));
+    }
+
+    #[test]
+    fn unlimited_budget_is_never_exhausted() {
+        let budget = RenderBudget::new(None, None, 4);
+        for _ in 0..4 {
+            assert!(!budget.is_exhausted());
+            assert_eq!(budget.samples_for_pixel(8), 8);
+            budget.spend(8);
+        }
+        assert!(budget.report(8).is_none());
+
+        let no_time = RenderBudget::new(Some(0.), None, 4);
+        assert!(no_time.is_exhausted());
+    }
+}
@@ -0,0 +1,68 @@
+//! Capabilities of the console
+//! Consoles on windows only understand ansi escape codes (colors, `\r` redraws) once virtual
+//! terminal processing is enabled. Where that fails, output falls back to plain text
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// whether the console understands ansi escape codes
+static ANSI: AtomicBool = AtomicBool::new(true);
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::c_void;
+
+    /// `(DWORD)-11` and `(DWORD)-12`
+    const STD_OUTPUT_HANDLE: u32 = 0xFFFF_FFF5;
+    const STD_ERROR_HANDLE: u32 = 0xFFFF_FFF4;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(std_handle: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    /// Enable virtual terminal processing for the standard handle `std_handle`
+    /// Returns false if the handle is not a console or the console doesn't support it
+    fn enable(std_handle: u32) -> bool {
+        unsafe {
+            let handle = GetStdHandle(std_handle);
+            // INVALID_HANDLE_VALUE is -1
+            if handle.is_null() || handle as isize == -1 {
+                return false;
+            }
+            let mut mode = 0;
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                return false;
+            }
+            mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+                || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+        }
+    }
+
+    /// Enable virtual terminal processing for stdout and stderr
+    pub(super) fn enable_virtual_terminal() -> bool {
+        let stdout = enable(STD_OUTPUT_HANDLE);
+        let stderr = enable(STD_ERROR_HANDLE);
+        stdout && stderr
+    }
+}
+
+/// Prepare the console for colored output, should be called once at startup
+/// On windows this enables virtual terminal processing, other platforms always support ansi codes
+pub fn init() {
+    #[cfg(windows)]
+    ANSI.store(windows::enable_virtual_terminal(), Ordering::Relaxed);
+}
+
+/// Return true if the console understands ansi escape codes
+#[must_use]
+pub fn supports_ansi() -> bool {
+    ANSI.load(Ordering::Relaxed)
+}
+
+/// Override whether the console understands ansi escape codes
+pub fn set_supports_ansi(ansi: bool) {
+    ANSI.store(ansi, Ordering::Relaxed);
+}
@@ -0,0 +1,65 @@
+//! Handling of Ctrl-C
+//! The first interrupt only sets a flag, so the running render can be cancelled cleanly
+//! A second interrupt terminates the program
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Flag that asks running work to stop, shared between the threads doing it
+/// Ctrl-C sets the global one, see [`is_interrupted`]. Render budgets set their own once they are
+/// used up
+#[derive(Debug, Default)]
+pub struct StopToken(AtomicBool);
+
+impl StopToken {
+    /// Create a token that has not been stopped yet
+    #[must_use]
+    pub const fn new() -> StopToken {
+        StopToken(AtomicBool::new(false))
+    }
+
+    /// Ask the work to stop
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Return true once the work was asked to stop
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+static INTERRUPTED: StopToken = StopToken::new();
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.stop();
+    // restore the default handler, so the next Ctrl-C exits immediately
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Install the handler for Ctrl-C
+/// Does nothing on platforms other than unix
+pub fn install_handler() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Return the flag that is set when the program is interrupted
+#[must_use]
+pub fn flag() -> &'static AtomicBool {
+    &INTERRUPTED.0
+}
+
+/// Return true if the program was interrupted
+#[must_use]
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.is_stopped()
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{input::InputError, misc::console};
+
+/// whether warnings should be treated as errors
+static STRICT: AtomicBool = AtomicBool::new(false);
+/// whether output should be printed without ansi color codes
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+
+const WARNING_COLOR: &str = "\x1b[33m";
+const NOTE_COLOR: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// Enable or disable strict mode
+/// In strict mode every warning is turned into an error
+pub fn set_strict(strict: bool) {
+    STRICT.store(strict, Ordering::Relaxed);
+}
+
+/// Return true if strict mode is enabled
+#[must_use]
+pub fn is_strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Disable colored output
+pub fn set_no_color(no_color: bool) {
+    NO_COLOR.store(no_color, Ordering::Relaxed);
+}
+
+/// Return true if output should be colored
+/// Colors are disabled by `set_no_color`, by a non-empty `NO_COLOR` environment variable
+/// (see [no-color.org](https://no-color.org)) or if the console doesn't understand ansi codes
+#[must_use]
+pub fn use_color() -> bool {
+    !NO_COLOR.load(Ordering::Relaxed)
+        && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+        && console::supports_ansi()
+}
+
+/// Wrap `text` in the ansi escape code `color`, if colored output is enabled
+#[must_use]
+pub fn colored(color: &str, text: &str) -> String {
+    if use_color() {
+        format!("{color}{text}{RESET}")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Emit a warning to stderr
+///
+/// # Errors
+///
+/// Returns the warning as an ``InputError`` instead of printing it if strict mode is enabled
+pub fn warning(msg: &str) -> Result<(), InputError> {
+    if is_strict() {
+        return Err(InputError::new(
+            "Warning treated as error (--strict)".to_string(),
+            msg.to_string(),
+        ));
+    }
+    print_warning(msg);
+    Ok(())
+}
+
+/// Emit a warning to stderr, also in strict mode
+/// For problems that don't stop the render and are summarized with [`warning`] once it is
+/// finished, so strict mode only fails after the render
+pub fn print_warning(msg: &str) {
+    eprintln!("{}: {msg}", colored(WARNING_COLOR, "Warning"));
+}
+
+/// Emit a note to stderr
+/// For information about the input that needs no action, so it is printed also in strict mode
+pub fn note(msg: &str) {
+    eprintln!("{}: {msg}", colored(NOTE_COLOR, "Note"));
+}
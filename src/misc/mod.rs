@@ -1,4 +1,12 @@
 //! misc module
 //! Contains structs and functions that dont fit in elsewhere
 
+pub mod budget;
+pub mod console;
+pub mod interrupt;
+pub mod log;
+pub mod nancheck;
+pub mod passes;
 pub mod progress;
+pub mod queue;
+pub mod solar;
@@ -0,0 +1,178 @@
+//! NaN detection
+//! Checks rendered pixels for NaN or infinite values before they get quantized, which would
+//! otherwise silently turn them into black or white speckles
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+use crate::math::Color;
+
+/// Color that offending pixels are replaced with when highlighting is enabled
+const HIGHLIGHT: Color = Color::new(1., 0., 1.);
+
+/// Counts pixels with NaN or infinite color values
+/// Can be shared between the threads rendering the image
+#[derive(Debug)]
+pub struct NanDetector {
+    count: AtomicUsize,
+    /// (frame, x, y) of the first offending pixels
+    first: Mutex<Vec<(usize, u32, u32)>>,
+    highlight: bool,
+}
+
+impl NanDetector {
+    /// Number of pixels whose coordinates are listed in the report
+    pub const MAX_LOGGED: usize = 10;
+
+    /// Create a new detector
+    /// With `highlight` set, offending pixels are replaced with magenta
+    #[must_use]
+    pub fn new(highlight: bool) -> NanDetector {
+        NanDetector {
+            count: AtomicUsize::new(0),
+            first: Mutex::new(Vec::new()),
+            highlight,
+        }
+    }
+
+    /// Check the color of pixel (`x`, `y`) in `frame` and record it if it is not finite
+    /// Returns the color that should be stored in the image
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while recording a pixel
+    #[must_use]
+    pub fn check(&self, frame: usize, (x, y): (u32, u32), color: Color) -> Color {
+        if color.is_finite() {
+            return color;
+        }
+        if self.count.fetch_add(1, Ordering::Relaxed) < Self::MAX_LOGGED {
+            self.first
+                .lock()
+                .expect("nan lock should not be poisoned")
+                .push((frame, x, y));
+        }
+        if self.highlight {
+            HIGHLIGHT
+        } else {
+            color
+        }
+    }
+
+    /// Return the number of pixels that were not finite
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Return a message listing the offending pixels, or None if all pixels were finite
+    ///
+    /// # Panics
+    ///
+    /// Panics if another thread panicked while recording a pixel
+    #[must_use]
+    pub fn report(&self) -> Option<String> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let mut first = self
+            .first
+            .lock()
+            .expect("nan lock should not be poisoned")
+            .clone();
+        // pixels are rendered in parallel, so sort them for a stable output
+        first.sort_unstable();
+        let pixels = first
+            .iter()
+            .map(|(frame, x, y)| format!("({x}, {y}) in frame {}", frame + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let more = if count > first.len() { ", ..." } else { "" };
+        Some(format!(
+            "{count} pixel(s) had NaN or infinite color values: {pixels}{more}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        math::{Point3, Vec3},
+        objects::{Camera, Light, Material, Scene, ShadingModel, Surface, Texture, Triangle},
+    };
+
+    #[test]
+    fn degenerate_spot_light_is_reported() {
+        // a spot light without a direction produces NaNs during shading
+        let triangle = Triangle::new(
+            [
+                Point3::new(-1., -1., -1.),
+                Point3::new(1., -1., -1.),
+                Point3::new(0., 1., -1.),
+            ],
+            [Vec3::new(0., 0., 1.); 3],
+            [(0., 0.); 3],
+        );
+        let material = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 0.3,
+                kd: 0.9,
+                ks: 0.5,
+                exp: 20,
+            },
+        );
+        let camera = Camera::new(
+            Point3::new(0., 0., 1.),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            5,
+            5,
+            1,
+        );
+        let light = Light::Spot {
+            color: Color::new(1., 1., 1.),
+            position: Point3::new(0., 0., 1.),
+            direction: Vec3::zero(),
+            cos_inner: 0.9,
+            cos_outer: 0.8,
+            profile: None,
+        };
+        let scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![light],
+            vec![Surface::mesh(vec![triangle], material)],
+        );
+
+        let detector = NanDetector::new(true);
+        let color = detector.check(0, (2, 2), scene.trace_pixel(2, 2));
+        // a miss is fine
+        let _ = detector.check(0, (0, 0), Color::zero());
+
+        assert_eq!(color, HIGHLIGHT);
+        assert_eq!(detector.count(), 1);
+        let report = detector.report().unwrap();
+        assert!(report.starts_with("1 pixel(s)"));
+        assert!(report.contains("(2, 2) in frame 1"));
+    }
+
+    #[test]
+    fn finite_pixels_are_not_reported() {
+        let detector = NanDetector::new(false);
+        assert_eq!(
+            detector.check(0, (0, 0), Color::new(0.5, 2., 0.)),
+            Color::new(0.5, 2., 0.)
+        );
+        assert!(detector.report().is_none());
+    }
+}
@@ -0,0 +1,127 @@
+//! Progressive passes
+//! Renders every frame of an animation with a few samples per pixel first and refines all frames
+//! in later passes, so a complete animation is available early. The sums of the samples of every
+//! pixel are kept between the passes, which costs `size_of::<Color>()` bytes per pixel and frame
+
+use std::ops::Range;
+
+use crate::math::Color;
+
+/// Split of the super-samples of a pixel into passes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PassPlan {
+    samples: u32,
+    pass_samples: u32,
+}
+
+impl PassPlan {
+    /// Create a plan that traces `samples` samples per pixel, `pass_samples` in every pass
+    /// Both are raised to at least 1
+    #[must_use]
+    pub fn new(samples: u32, pass_samples: u32) -> PassPlan {
+        PassPlan {
+            samples: samples.max(1),
+            pass_samples: pass_samples.max(1),
+        }
+    }
+
+    /// Return the number of passes
+    #[must_use]
+    pub fn passes(&self) -> u32 {
+        self.samples.div_ceil(self.pass_samples)
+    }
+
+    /// Return the samples that are traced in `pass`
+    /// Pass `k` traces the samples `k * n..(k + 1) * n`, the last pass may trace fewer
+    #[must_use]
+    pub fn samples_of(&self, pass: u32) -> Range<u32> {
+        let start = (pass * self.pass_samples).min(self.samples);
+        start..(start + self.pass_samples).min(self.samples)
+    }
+}
+
+/// Split `frames` frames into groups of at most `max_resident` frames
+/// The frames of a group are refined together, so only their sums are held in memory at once
+pub fn frame_groups(frames: usize, max_resident: usize) -> impl Iterator<Item = Range<usize>> {
+    let size = max_resident.max(1);
+    (0..frames)
+        .step_by(size)
+        .map(move |start| start..(start + size).min(frames))
+}
+
+/// Return the memory in bytes that the sums of a frame of `pixels` pixels take up
+#[must_use]
+pub fn frame_memory(pixels: usize) -> usize {
+    pixels * size_of::<Color>()
+}
+
+/// Sums of the samples of every pixel of a frame over the passes done so far
+#[derive(Clone, Debug)]
+pub struct Accumulator {
+    sums: Vec<Color>,
+    samples: u32,
+}
+
+impl Accumulator {
+    /// Create an empty accumulator for a frame of `pixels` pixels
+    #[must_use]
+    pub fn new(pixels: usize) -> Accumulator {
+        Accumulator {
+            sums: vec![Color::zero(); pixels],
+            samples: 0,
+        }
+    }
+
+    /// Add the sums of `samples` samples per pixel of a pass
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pass has a different number of pixels than the frame
+    pub fn add(&mut self, pass: &[Color], samples: u32) {
+        assert_eq!(pass.len(), self.sums.len(), "pass should cover the frame");
+        for (sum, color) in self.sums.iter_mut().zip(pass) {
+            *sum += *color;
+        }
+        self.samples += samples;
+    }
+
+    /// Return the number of samples per pixel added so far
+    #[must_use]
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Return the average color of every pixel
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean(&self) -> impl Iterator<Item = Color> + '_ {
+        let samples = self.samples.max(1) as f32;
+        self.sums.iter().map(move |sum| *sum / samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_cover_every_sample_once() {
+        let plan = PassPlan::new(10, 4);
+        assert_eq!(plan.passes(), 3);
+        let ranges: Vec<_> = (0..plan.passes()).map(|p| plan.samples_of(p)).collect();
+        assert_eq!(ranges, [0..4, 4..8, 8..10]);
+        assert_eq!(PassPlan::new(16, 4).passes(), 4);
+        assert_eq!(PassPlan::new(0, 4).samples_of(0), 0..1);
+
+        let groups: Vec<_> = frame_groups(7, 3).collect();
+        assert_eq!(groups, [0..3, 3..6, 6..7]);
+        assert_eq!(frame_groups(2, 0).count(), 2);
+
+        let mut accumulator = Accumulator::new(2);
+        accumulator.add(&[Color::new(4., 0., 0.), Color::zero()], 4);
+        accumulator.add(&[Color::new(0., 2., 0.), Color::new(1., 1., 1.)], 2);
+        assert_eq!(accumulator.samples(), 6);
+        let mean: Vec<_> = accumulator.mean().collect();
+        assert!((mean[0] - Color::new(4., 2., 0.) / 6.).length() < 1e-6);
+        assert_eq!(frame_memory(10), 10 * size_of::<Color>());
+    }
+}
@@ -1,12 +1,17 @@
 use std::fmt::Write;
 
+use crate::misc::console;
+
 /// Manages a simple Progressbar that prints to stdout
+/// If the console doesn't understand ansi codes, the bar can't be redrawn in place, so a new line
+/// is printed for every tenth of progress instead
 pub struct ProgressBar {
     buffer: String, // reuse buffer for formatting to avoid allocations
     curr: usize,
     max: usize,
     msg: String,
     last_percent: f64,
+    redraw: bool,
 }
 
 impl ProgressBar {
@@ -14,24 +19,28 @@ impl ProgressBar {
     const FULL_CHAR: &'static str = "#";
     const EMPTY_CHAR: &'static str = "-";
     const WIDTH: f64 = 50.;
+    /// Progress between two lines, if the bar can't be redrawn
+    const LINE_STEP: f64 = 0.1;
 
     /// Create a new ``ProgressBar`` with the given maximum
     #[must_use]
-    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
     pub fn new(max: usize, msg: String) -> ProgressBar {
-        print!(
-            "{} [{}{}] 0.00% (0/{})",
-            msg,
-            Self::RUNNER,
-            Self::EMPTY_CHAR.repeat((Self::WIDTH - 1.) as usize),
-            max
-        );
+        let mut bar = ProgressBar::with_redraw(max, msg, console::supports_ansi());
+        if bar.redraw {
+            bar.format(0.);
+            print!("{}", bar.buffer);
+        }
+        bar
+    }
+
+    fn with_redraw(max: usize, msg: String, redraw: bool) -> ProgressBar {
         ProgressBar {
             buffer: String::with_capacity(80),
             curr: 0,
             max,
             msg,
             last_percent: 0.,
+            redraw,
         }
     }
 
@@ -45,25 +54,49 @@ impl ProgressBar {
     /// Advances the progress bar by 1
     /// Only prints, if the difference of percentage exceeds some threshold
     pub fn next(&mut self) {
+        if self.advance() {
+            print!("{}", self.buffer);
+        }
+    }
+
+    /// Advance the progress bar by 1 and format it into the buffer
+    /// Returns false if the progress didn't change enough to be printed
+    fn advance(&mut self) -> bool {
         self.curr += 1;
         #[allow(clippy::cast_precision_loss)]
         let percent = self.curr as f64 / self.max as f64;
-        if self.curr != self.max && percent - self.last_percent <= 0.001 {
-            return;
+        let threshold = if self.redraw { 0.001 } else { Self::LINE_STEP };
+        if self.curr != self.max && percent - self.last_percent < threshold {
+            return false;
         }
+        self.format(percent);
+        self.last_percent = if self.redraw {
+            percent
+        } else {
+            // keep lines at whole steps, even if a single update skips over one
+            (percent / Self::LINE_STEP).floor() * Self::LINE_STEP
+        };
+        true
+    }
 
+    /// Format the bar at `percent` into the buffer
+    fn format(&mut self, percent: f64) {
         #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
         let full = (Self::WIDTH * percent) as usize;
         #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
         let empty = Self::WIDTH as usize - full;
         let runner = if empty > 0 { Self::RUNNER } else { "" };
         let empty = if empty > 0 { empty - 1 } else { 0 };
+        let (start, end) = match (self.redraw, self.curr == self.max) {
+            (true, false) => ("\r", ' '),
+            (true, true) => ("\r", '\n'),
+            (false, _) => ("", '\n'),
+        };
 
         self.buffer.clear();
-
         write!(
             self.buffer,
-            "\r{} [{}{}{}] {:.2}% ({}/{}){}",
+            "{start}{} [{}{}{}] {:.2}% ({}/{}){end}",
             self.msg,
             Self::FULL_CHAR.repeat(full),
             runner,
@@ -71,11 +104,32 @@ impl ProgressBar {
             percent * 100.,
             self.curr,
             self.max,
-            if self.curr == self.max { '\n' } else { ' ' }
         )
         .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_redraw_every_tenth_is_a_line() {
+        let mut bar = ProgressBar::with_redraw(200, "Frame 1:".to_string(), false);
+        let mut lines = Vec::new();
+        for _ in 0..200 {
+            if bar.advance() {
+                lines.push(bar.buffer.clone());
+            }
+        }
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|l| !l.contains('\r') && l.ends_with('\n')));
+        assert!(lines[0].starts_with("Frame 1: [#####>"));
+        assert!(lines[0].ends_with("10.00% (20/200)\n"));
+        assert!(lines[9].ends_with("100.00% (200/200)\n"));
 
-        print!("{}", self.buffer);
-        self.last_percent = percent;
+        let mut bar = ProgressBar::with_redraw(200, "Frame 1:".to_string(), true);
+        assert!(bar.advance());
+        assert!(bar.buffer.starts_with('\r') && bar.buffer.ends_with(' '));
     }
 }
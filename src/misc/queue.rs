@@ -0,0 +1,299 @@
+//! Render queue
+//! Renders multiple scenes one after another (or a few at the same time) and collects a report
+//! for every scene, that can be written as json
+
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::input::InputError;
+
+/// Outcome of a single job in the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    Done,
+    Failed,
+    Cancelled,
+    Skipped,
+}
+
+impl JobStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// Report for a single scene of the queue
+#[derive(Debug, Clone)]
+pub struct JobReport {
+    pub input: String,
+    pub status: JobStatus,
+    pub wall_time: Duration,
+    pub outputs: Vec<PathBuf>,
+    pub error: Option<String>,
+}
+
+impl JobReport {
+    fn skipped(input: &str) -> JobReport {
+        JobReport {
+            input: input.to_string(),
+            status: JobStatus::Skipped,
+            wall_time: Duration::ZERO,
+            outputs: Vec::new(),
+            error: None,
+        }
+    }
+
+    fn finished(
+        input: &str,
+        wall_time: Duration,
+        result: Result<Vec<PathBuf>, InputError>,
+        cancelled: bool,
+    ) -> JobReport {
+        let (status, outputs, error) = match result {
+            Ok(outputs) => (JobStatus::Done, outputs, None),
+            Err(err) => (
+                if cancelled {
+                    JobStatus::Cancelled
+                } else {
+                    JobStatus::Failed
+                },
+                Vec::new(),
+                Some(format!("{}: {}", err.title(), err.msg())),
+            ),
+        };
+        JobReport {
+            input: input.to_string(),
+            status,
+            wall_time,
+            outputs,
+            error,
+        }
+    }
+}
+
+/// Render all `inputs` with `render` and return a report for each of them in the same order
+/// Failing scenes don't stop the queue
+/// Up to `jobs` scenes are rendered at the same time, each in its own thread pool with an equal
+/// share of the available threads
+/// Once `cancel` is set, the running scenes are reported as cancelled and the remaining ones are
+/// skipped
+///
+/// # Panics
+///
+/// Panics if a render job panicked
+pub fn run<F>(inputs: &[String], jobs: usize, cancel: &AtomicBool, render: F) -> Vec<JobReport>
+where
+    F: Fn(&str) -> Result<Vec<PathBuf>, InputError> + Sync,
+{
+    let jobs = jobs.clamp(1, inputs.len().max(1));
+    let threads = (rayon::current_num_threads() / jobs).max(1);
+    let next = AtomicUsize::new(0);
+    let reports: Vec<Mutex<Option<JobReport>>> = inputs.iter().map(|_| Mutex::new(None)).collect();
+
+    let worker = || {
+        // a single job can use the global pool
+        let pool = if jobs > 1 {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .ok()
+        } else {
+            None
+        };
+        loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(input) = inputs.get(i) else {
+                break;
+            };
+            let report = if cancel.load(Ordering::Relaxed) {
+                JobReport::skipped(input)
+            } else {
+                let start = Instant::now();
+                let result = match &pool {
+                    Some(pool) => pool.install(|| render(input)),
+                    None => render(input),
+                };
+                JobReport::finished(
+                    input,
+                    start.elapsed(),
+                    result,
+                    cancel.load(Ordering::Relaxed),
+                )
+            };
+            *reports[i]
+                .lock()
+                .expect("report lock should not be poisoned") = Some(report);
+        }
+    };
+
+    std::thread::scope(|s| {
+        for _ in 1..jobs {
+            s.spawn(worker);
+        }
+        worker();
+    });
+
+    reports
+        .into_iter()
+        .map(|r| {
+            r.into_inner()
+                .expect("report lock should not be poisoned")
+                .expect("every job should have a report")
+        })
+        .collect()
+}
+
+/// Escape a string for use inside a json string literal
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Format an optional string as json
+fn json_string_or_null(s: Option<&str>) -> String {
+    s.map_or(String::from("null"), |s| format!("\"{}\"", json_escape(s)))
+}
+
+/// Convert the reports to a json document
+#[must_use]
+pub fn to_json(reports: &[JobReport]) -> String {
+    let scenes: Vec<_> = reports
+        .iter()
+        .map(|r| {
+            format!(
+                "    {{\"input\": \"{}\", \"status\": \"{}\", \"wall_time\": {:.3}, \"outputs\": [{}], \"error\": {}}}",
+                json_escape(&r.input),
+                r.status.as_str(),
+                r.wall_time.as_secs_f64(),
+                r.outputs
+                    .iter()
+                    .map(|p| json_string_or_null(p.to_str()))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                json_string_or_null(r.error.as_deref()),
+            )
+        })
+        .collect();
+    format!("{{\n  \"scenes\": [\n{}\n  ]\n}}\n", scenes.join(",\n"))
+}
+
+/// Write the reports as json to `path`
+///
+/// # Errors
+///
+/// Returns an ``InputError`` if the file could not be written
+pub fn write_report(path: &str, reports: &[JobReport]) -> Result<(), InputError> {
+    std::fs::write(path, to_json(reports)).map_err(|err| {
+        InputError::new(
+            format!("Error while writing report to {path}"),
+            err.to_string(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(names: &[&str]) -> Vec<String> {
+        names.iter().map(ToString::to_string).collect()
+    }
+
+    fn render(input: &str) -> Result<Vec<PathBuf>, InputError> {
+        if input.starts_with("bad") {
+            Err(InputError::new(
+                "Error".to_string(),
+                format!("cannot render {input}"),
+            ))
+        } else {
+            Ok(vec![PathBuf::from(format!("output/{input}.png"))])
+        }
+    }
+
+    #[test]
+    fn queue_continues_past_failures_in_order() {
+        let inputs = inputs(&["a", "bad", "b", "c"]);
+        for jobs in [1, 2, 8] {
+            let reports = run(&inputs, jobs, &AtomicBool::new(false), render);
+
+            let statuses: Vec<_> = reports.iter().map(|r| r.status).collect();
+            assert_eq!(
+                statuses,
+                [
+                    JobStatus::Done,
+                    JobStatus::Failed,
+                    JobStatus::Done,
+                    JobStatus::Done
+                ]
+            );
+            assert_eq!(reports[2].input, "b");
+            assert_eq!(reports[2].outputs, [PathBuf::from("output/b.png")]);
+            assert_eq!(
+                reports[1].error.as_deref(),
+                Some("Error: cannot render bad")
+            );
+        }
+    }
+
+    #[test]
+    fn cancel_skips_remaining_scenes() {
+        let inputs = inputs(&["a", "b", "c"]);
+        let cancel = AtomicBool::new(false);
+        let reports = run(&inputs, 1, &cancel, |input| {
+            if input == "b" {
+                cancel.store(true, Ordering::Relaxed);
+                return Err(InputError::new(
+                    "Render cancelled".to_string(),
+                    String::new(),
+                ));
+            }
+            render(input)
+        });
+
+        let statuses: Vec<_> = reports.iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            [JobStatus::Done, JobStatus::Cancelled, JobStatus::Skipped]
+        );
+    }
+
+    #[test]
+    fn json_report_is_escaped() {
+        let reports = run(
+            &inputs(&["bad \"quoted\"\\path"]),
+            1,
+            &AtomicBool::new(false),
+            render,
+        );
+        let json = to_json(&reports);
+
+        assert!(json.contains(r#""input": "bad \"quoted\"\\path""#));
+        assert!(json.contains(r#""status": "failed""#));
+        assert!(json.contains(r#""outputs": []"#));
+    }
+}
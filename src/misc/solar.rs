@@ -0,0 +1,329 @@
+//! solar position
+//! Computes where the sun stands for a date, time and place with the general solar position
+//! equations of the NOAA, which are accurate to a fraction of a degree between 1950 and 2050
+//! Directions use the axes of the scene: `+y` points up, `-z` north and `+x` east
+
+use std::{
+    f64::consts::PI,
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::math::{Color, Vec3};
+
+/// Position of the sun in the sky in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    /// angle above the horizon, negative if the sun has set
+    pub elevation: f64,
+    /// angle from north, clockwise (east is 90)
+    pub azimuth: f64,
+}
+
+/// Date and time in UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UtcTime {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+    /// hours since midnight, may lie outside of `0..24` after applying a time zone offset
+    pub hours: f64,
+}
+
+/// Return true if `year` is a leap year in the gregorian calendar
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+/// Return the number of days in `month` (1 to 12) of `year`
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse a date in the form `YYYY-MM-DD`
+/// Returns `None` if the date is malformed or does not exist
+#[must_use]
+pub fn parse_date(date: &str) -> Option<(i32, u32, u32)> {
+    let mut parts = date.trim().split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    let valid = parts.next().is_none()
+        && (1..=12).contains(&month)
+        && (1..=days_in_month(year, month)).contains(&day);
+    valid.then_some((year, month, day))
+}
+
+/// Parse a time of day in the form `HH:MM` or `HH:MM:SS` and return the hours since midnight
+/// Returns `None` if the time is malformed or out of range
+#[must_use]
+pub fn parse_time(time: &str) -> Option<f64> {
+    let parts: Vec<u32> = time
+        .trim()
+        .split(':')
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let (hour, minute, second) = match parts[..] {
+        [h, m] => (h, m, 0),
+        [h, m, s] => (h, m, s),
+        _ => return None,
+    };
+    (hour < 24 && minute < 60 && second < 60)
+        .then(|| f64::from(hour) + f64::from(minute) / 60. + f64::from(second) / 3600.)
+}
+
+impl UtcTime {
+    /// Return the date and time `seconds` after the start of 1970 (the unix epoch)
+    #[must_use]
+    pub fn from_unix_seconds(seconds: u64) -> UtcTime {
+        let mut days = seconds / 86_400;
+        let mut year = 1970;
+        loop {
+            let length = if is_leap_year(year) { 366 } else { 365 };
+            if days < length {
+                break;
+            }
+            days -= length;
+            year += 1;
+        }
+        let mut month = 1;
+        while days >= u64::from(days_in_month(year, month)) {
+            days -= u64::from(days_in_month(year, month));
+            month += 1;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let hours = (seconds % 86_400) as f64 / 3600.;
+        UtcTime {
+            year,
+            month,
+            day: u32::try_from(days).unwrap_or(0) + 1,
+            hours,
+        }
+    }
+
+    /// Return the current date and time
+    /// Falls back to the unix epoch if the clock is set before it
+    #[must_use]
+    pub fn now() -> UtcTime {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        UtcTime::from_unix_seconds(seconds)
+    }
+
+    /// Return the day of the year, starting with 1 on the first of january
+    fn day_of_year(&self) -> u32 {
+        (1..self.month)
+            .map(|m| days_in_month(self.year, m))
+            .sum::<u32>()
+            + self.day
+    }
+}
+
+impl Display for UtcTime {
+    /// Format as `YYYY-MM-DD HH:MM UTC`, the minutes are truncated
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let minutes = (self.hours.clamp(0., 24.) * 60.) as u32;
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+            self.year,
+            self.month,
+            self.day,
+            minutes / 60,
+            minutes % 60
+        )
+    }
+}
+
+impl SunPosition {
+    /// Compute the position of the sun at `time`, seen from `latitude` (positive north) and
+    /// `longitude` (positive east) in degrees
+    #[must_use]
+    pub fn at(time: &UtcTime, latitude: f64, longitude: f64) -> SunPosition {
+        let days_per_year = if is_leap_year(time.year) { 366. } else { 365. };
+        // fractional year in radians
+        let g = 2. * PI / days_per_year
+            * (f64::from(time.day_of_year()) - 1. + (time.hours - 12.) / 24.);
+        // equation of time in minutes and declination in radians
+        let eq_time = 229.18
+            * (0.000_075 + 0.001_868 * g.cos()
+                - 0.032_077 * g.sin()
+                - 0.014_615 * (2. * g).cos()
+                - 0.040_849 * (2. * g).sin());
+        let decl = 0.006_918 - 0.399_912 * g.cos() + 0.070_257 * g.sin()
+            - 0.006_758 * (2. * g).cos()
+            + 0.000_907 * (2. * g).sin()
+            - 0.002_697 * (3. * g).cos()
+            + 0.001_48 * (3. * g).sin();
+
+        // true solar time in minutes and the hour angle
+        let solar_time = time.hours * 60. + eq_time + 4. * longitude;
+        let hour_angle = (solar_time / 4. - 180.).to_radians();
+
+        let lat = latitude.to_radians();
+        let elevation = (lat.sin() * decl.sin() + lat.cos() * decl.cos() * hour_angle.cos())
+            .clamp(-1., 1.)
+            .asin();
+        // measured from south towards west, so half a turn is added to measure from north
+        let azimuth = hour_angle
+            .sin()
+            .atan2(hour_angle.cos() * lat.sin() - decl.tan() * lat.cos());
+        SunPosition {
+            elevation: elevation.to_degrees(),
+            azimuth: (azimuth.to_degrees() + 180.).rem_euclid(360.),
+        }
+    }
+
+    /// Return the unit vector pointing from the ground towards the sun
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn direction(&self) -> Vec3 {
+        let (elevation, azimuth) = (self.elevation.to_radians(), self.azimuth.to_radians());
+        Vec3::new(
+            (elevation.cos() * azimuth.sin()) as f32,
+            elevation.sin() as f32,
+            (-elevation.cos() * azimuth.cos()) as f32,
+        )
+    }
+
+    /// Approximate the color temperature of sunlight in kelvin
+    /// Near the horizon the light travels further through the atmosphere, which scatters the blue
+    /// light away. A higher `turbidity` (hazier air, 2 is a clear sky) keeps the light warm up to
+    /// higher elevations
+    #[must_use]
+    pub fn color_temperature(&self, turbidity: f64) -> f64 {
+        let elevation = self.elevation.max(0.);
+        1900. + 3900. * (1. - (-elevation / (5. * turbidity)).exp())
+    }
+}
+
+/// Approximate the color of a black body with the temperature `kelvin`, normalized so that the
+/// brightest channel is 1
+/// Uses the curve fit by Tanner Helland, which is valid from 1000 to 40000 kelvin
+#[must_use]
+#[allow(clippy::cast_possible_truncation)]
+pub fn kelvin_to_color(kelvin: f64) -> Color {
+    let t = kelvin.clamp(1000., 40000.) / 100.;
+    let (r, g, b) = if t <= 66. {
+        let b = if t <= 19. {
+            0.
+        } else {
+            138.517_731_223_1 * (t - 10.).ln() - 305.044_792_730_7
+        };
+        (255., 99.470_802_586_1 * t.ln() - 161.119_568_166_1, b)
+    } else {
+        (
+            329.698_727_446 * (t - 60.).powf(-0.133_204_759_2),
+            288.122_169_528_3 * (t - 60.).powf(-0.075_514_849_2),
+            255.,
+        )
+    };
+    let [r, g, b] = [r, g, b].map(|c| c.clamp(0., 255.));
+    let max = r.max(g).max(b);
+    Color::new((r / max) as f32, (g / max) as f32, (b / max) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(date: &str, time: &str, utc_offset: f64, lat: f64, lon: f64) -> SunPosition {
+        let (year, month, day) = parse_date(date).unwrap();
+        let hours = parse_time(time).unwrap() - utc_offset;
+        SunPosition::at(
+            &UtcTime {
+                year,
+                month,
+                day,
+                hours,
+            },
+            lat,
+            lon,
+        )
+    }
+
+    #[test]
+    fn unix_seconds_are_converted_to_dates() {
+        assert_eq!(
+            UtcTime::from_unix_seconds(0).to_string(),
+            "1970-01-01 00:00 UTC"
+        );
+        // the leap day of 2024 and the day after it
+        let leap = 1_709_208_000 - 12 * 3600 + 45 * 60;
+        assert_eq!(
+            UtcTime::from_unix_seconds(leap).to_string(),
+            "2024-02-29 00:45 UTC"
+        );
+        let next = UtcTime::from_unix_seconds(leap + 86_400);
+        assert_eq!((next.month, next.day), (3, 1));
+        assert_eq!(
+            UtcTime::from_unix_seconds(1_798_761_599).to_string(),
+            "2026-12-31 23:59 UTC"
+        );
+    }
+
+    #[test]
+    fn matches_published_positions() {
+        // example of the NREL solar position algorithm (Reda and Andreas, 2004), Golden, Colorado
+        let sun = position("2003-10-17", "12:30:30", -7., 39.742_476, -105.1786);
+        assert!((sun.elevation - (90. - 50.111_62)).abs() < 1., "{sun:?}");
+        assert!((sun.azimuth - 194.340_24).abs() < 1., "{sun:?}");
+
+        // summer solstice at noon: the sun stands 23.44 degrees above the equator
+        let sun = position("2024-06-21", "12:00", 0., 48.2, 0.);
+        assert!((sun.elevation - (90. - 48.2 + 23.44)).abs() < 1., "{sun:?}");
+        assert!((sun.azimuth - 180.).abs() < 2., "{sun:?}");
+
+        // afternoon in vienna, and the southern hemisphere after sunset
+        let sun = position("2024-06-21", "15:30", 2., 48.2, 16.37);
+        assert!((sun.elevation - 50.88).abs() < 1., "{sun:?}");
+        assert!((sun.azimuth - 244.56).abs() < 1., "{sun:?}");
+        let sun = position("2024-03-20", "12:00", 0., -33.87, 151.21);
+        assert!((sun.elevation + 45.72).abs() < 1., "{sun:?}");
+    }
+
+    #[test]
+    fn direction_uses_scene_axes() {
+        let east = SunPosition {
+            elevation: 0.,
+            azimuth: 90.,
+        };
+        assert!((east.direction() - Vec3::new(1., 0., 0.)).length() < 1e-5);
+        let north = SunPosition {
+            elevation: 45.,
+            azimuth: 0.,
+        };
+        let expected = Vec3::new(0., 1., -1.) * std::f32::consts::FRAC_1_SQRT_2;
+        assert!((north.direction() - expected).length() < 1e-5);
+    }
+
+    #[test]
+    fn low_sun_is_warm() {
+        let sun = |elevation| SunPosition {
+            elevation,
+            azimuth: 0.,
+        };
+        let low = kelvin_to_color(sun(3.).color_temperature(2.));
+        let high = kelvin_to_color(sun(60.).color_temperature(2.));
+        assert!(low[2] < high[2] * 0.8, "{low:?} {high:?}");
+        assert!(sun(20.).color_temperature(6.) < sun(20.).color_temperature(2.));
+    }
+
+    #[test]
+    fn invalid_dates_and_times() {
+        assert_eq!(parse_date("2024-02-29"), Some((2024, 2, 29)));
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("2024-13-01"), None);
+        assert_eq!(parse_date("2024-06"), None);
+        assert_eq!(parse_time("15:30"), Some(15.5));
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("noon"), None);
+    }
+}
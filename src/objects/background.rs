@@ -0,0 +1,81 @@
+use std::f32::consts::PI;
+
+use crate::{
+    image::Image,
+    math::{lerp, Color, Ray, Vec3},
+};
+
+/// Enum to represent what rays see when they don't hit any surface
+#[derive(Clone, Debug)]
+pub enum Background {
+    /// A single color
+    Solid(Color),
+    /// Blend from `bottom` for rays pointing straight down to `top` for rays pointing straight up
+    VerticalGradient { bottom: Color, top: Color },
+    /// An equirectangular (latitude-longitude) environment map
+    Environment(Image),
+}
+
+impl Background {
+    /// Return the color of the background in the direction of `ray`
+    #[must_use]
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Self::Solid(color) => *color,
+            Self::VerticalGradient { bottom, top } => {
+                let d = Vec3::normal(ray.dir());
+                lerp(*bottom, *top, 0.5 * (d[1] + 1.))
+            }
+            Self::Environment(image) => {
+                // same mapping as the texture coordinates of a sphere
+                let d = Vec3::normal(ray.dir());
+                let u = 0.5 + (d[0].atan2(d[2])) / (2. * PI);
+                let v = 0.5 - (d[1].clamp(-1., 1.).asin()) / PI;
+                // rounding can push the poles just past the edge of the image
+                Color::from(image.get_pixel(0, u.clamp(0., 1.), v.clamp(0., 1.)))
+            }
+        }
+    }
+
+    /// Return the approximate number of bytes used by the environment map
+    #[must_use]
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Self::Environment(image) => image.memory_size(),
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Point3;
+
+    fn ray(x: f32, y: f32, z: f32) -> Ray {
+        Ray::new(Point3::zero(), Vec3::new(x, y, z))
+    }
+
+    #[test]
+    fn gradient_blends_by_height() {
+        let background = Background::VerticalGradient {
+            bottom: Color::new(1., 0., 0.),
+            top: Color::new(0., 0., 1.),
+        };
+        assert_eq!(background.color(&ray(0., 2., 0.)), Color::new(0., 0., 1.));
+        assert_eq!(background.color(&ray(0., -1., 0.)), Color::new(1., 0., 0.));
+        assert_eq!(
+            background.color(&ray(0., 0., -1.)),
+            Color::new(0.5, 0., 0.5)
+        );
+    }
+
+    #[test]
+    fn environment_maps_up_to_top_row() {
+        // 1x2 image, white top row and black bottom row
+        let image = Image::from_pixels(1, 2, vec![[255; 3], [0; 3]]);
+        let background = Background::Environment(image);
+        assert_eq!(background.color(&ray(0., 1., 0.)), Color::from([255; 3]));
+        assert_eq!(background.color(&ray(0., -1., 0.)), Color::zero());
+    }
+}
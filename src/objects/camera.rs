@@ -1,4 +1,73 @@
-use crate::math::{Mat4, Point3, Ray, Vec3};
+use rand::Rng;
+
+use crate::math::{Differentials, Mat4, Point3, Ray, Vec3};
+
+/// The volume that can be seen by the camera
+/// A pyramid with its apex at the camera and four edges through the corners of the image
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    apex: Point3,
+    /// directions of the edges, in order around the image
+    corners: [Vec3; 4],
+    forward: Vec3,
+}
+
+impl Frustum {
+    /// Transform the frustum with a transformation matrix
+    /// The planes of the frustum stay correct for any affine transformation
+    #[must_use]
+    pub fn transform(&self, mat: &Mat4) -> Frustum {
+        Frustum {
+            apex: mat.transform_point(&self.apex),
+            corners: self.corners.map(|c| mat.transform_vector(&c)),
+            forward: mat.transform_vector(&self.forward),
+        }
+    }
+
+    /// Return the normals of the planes bounding the frustum, all pointing inwards
+    /// All planes go through the apex
+    fn plane_normals(&self) -> [Vec3; 5] {
+        let inwards = |n: Vec3| if n.dot(&self.forward) < 0. { -n } else { n };
+        let [c0, c1, c2, c3] = self.corners;
+        [
+            inwards(c0.cross(&c1)),
+            inwards(c1.cross(&c2)),
+            inwards(c2.cross(&c3)),
+            inwards(c3.cross(&c0)),
+            // the plane of the camera, nothing behind it can be seen
+            inwards((c1 - c0).cross(&(c3 - c0))),
+        ]
+    }
+
+    /// Return true if the axis aligned box from `min` to `max` lies completely outside the
+    /// frustum
+    /// Conservative: boxes that are close to an edge of the frustum might not be detected
+    #[must_use]
+    pub fn excludes_box(&self, min: Point3, max: Point3) -> bool {
+        let points: [Point3; 8] = std::array::from_fn(|i| {
+            Point3::new(
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            )
+        });
+        self.plane_normals()
+            .iter()
+            .any(|n| points.iter().all(|p| n.dot(&(*p - self.apex)) < 0.))
+    }
+}
+
+/// What secondary rays contribute once the maximum number of bounces is reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BounceLimit {
+    /// nothing, reflections and refractions end in black
+    #[default]
+    Black,
+    /// the background in the direction of the ray that is not traced anymore
+    Background,
+    /// the ambient light coming from the direction of the ray that is not traced anymore
+    Ambient,
+}
 
 /// Struct to represent a camera in 3D space
 #[derive(Debug)]
@@ -8,8 +77,10 @@ pub struct Camera {
     fov_t: f32,
     aspect: f32,
     max_bounces: u32,
+    at_limit: BounceLimit,
     transform: Mat4,
     dof: Option<(f32, f32)>,
+    differentials: bool,
 }
 
 impl Camera {
@@ -33,8 +104,10 @@ impl Camera {
             fov_t,
             aspect,
             max_bounces,
+            at_limit: BounceLimit::default(),
             transform: Mat4::look_at(pos, lookat, up),
             dof: None,
+            differentials: false,
         }
     }
 
@@ -43,6 +116,11 @@ impl Camera {
         self.dof = Some((focal_distance, aperture));
     }
 
+    /// Let camera rays carry ray differentials
+    pub fn enable_differentials(&mut self) {
+        self.differentials = true;
+    }
+
     /// Return the image dimensions of the camera
     #[must_use]
     #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
@@ -50,49 +128,214 @@ impl Camera {
         (self.width as u32, self.height as u32)
     }
 
+    /// Change the image dimensions of the camera, the horizontal field of view stays the same
+    #[allow(clippy::cast_precision_loss)]
+    pub fn set_dimensions(&mut self, horizontal: u32, vertical: u32) {
+        self.width = horizontal as f32;
+        self.height = vertical as f32;
+        self.aspect = vertical as f32 / horizontal as f32;
+    }
+
+    /// Return `resolution` scaled by `factor`, rounded to the nearest pixel and at least 1x1
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn scaled_resolution((horizontal, vertical): (u32, u32), factor: f32) -> (u32, u32) {
+        let scale = |n: u32| ((n as f32 * factor).round() as u32).max(1);
+        (scale(horizontal), scale(vertical))
+    }
+
+    /// Scale the image dimensions of the camera by `factor`, see [`Camera::scaled_resolution`]
+    /// Unlike [`Camera::set_dimensions`] the aspect ratio of the image plane is kept, so the image
+    /// shows exactly the same part of the scene even if rounding changes the ratio of the pixel
+    /// counts. The pixels are then slightly off square
+    #[allow(clippy::cast_precision_loss)]
+    pub fn scale_dimensions(&mut self, factor: f32) {
+        let (horizontal, vertical) = Self::scaled_resolution(self.get_dimensions(), factor);
+        self.width = horizontal as f32;
+        self.height = vertical as f32;
+    }
+
     /// Return the maximum bounces for the camera
     #[must_use]
     pub fn get_max_bounces(&self) -> u32 {
         self.max_bounces
     }
 
-    fn compute_camera_ray(&self, u: f32, v: f32) -> Ray {
-        let x = (((2. * u + 1.) / self.width) - 1.) * self.fov_t;
-        let y = (((2. * v + 1.) / self.height) - 1.) * self.fov_t * self.aspect;
+    /// Set the maximum bounces for the camera
+    pub fn set_max_bounces(&mut self, max_bounces: u32) {
+        self.max_bounces = max_bounces;
+    }
+
+    /// Return what secondary rays contribute after the maximum bounces
+    #[must_use]
+    pub fn get_bounce_limit(&self) -> BounceLimit {
+        self.at_limit
+    }
+
+    /// Set what secondary rays contribute after the maximum bounces
+    pub fn set_bounce_limit(&mut self, at_limit: BounceLimit) {
+        self.at_limit = at_limit;
+    }
+
+    /// Return the position of the camera
+    #[must_use]
+    pub fn position(&self) -> Point3 {
+        self.transform.transform_point(&Point3::zero())
+    }
+
+    /// Return the unit vector in the direction the camera looks
+    #[must_use]
+    pub fn direction(&self) -> Vec3 {
+        Vec3::normal(&self.transform.transform_vector(&Vec3::new(0., 0., -1.)))
+    }
+
+    /// Return the unit vector pointing up in the image
+    #[must_use]
+    pub fn up(&self) -> Vec3 {
+        Vec3::normal(&self.transform.transform_vector(&Vec3::new(0., 1., 0.)))
+    }
+
+    /// Move the camera to `pos` and turn it towards `lookat`
+    pub fn set_view(&mut self, pos: Point3, lookat: Point3, up: Vec3) {
+        self.transform = Mat4::look_at(pos, lookat, up);
+    }
+
+    /// Move the camera along its view direction, so that the box from `min` to `max` fills the
+    /// image, with `margin` (0.1 for 10%) of extra space around it
+    /// The camera looks at the center of the box and is moved until the sphere around the box fits
+    /// into the narrower field of view, so every corner is visible from any direction
+    pub fn frame_box(&mut self, min: Point3, max: Point3, margin: f32) {
+        let center = 0.5 * (min + max);
+        let radius = 0.5 * (max - min).length() * (1. + margin);
+        // half of the narrower field of view
+        let half_fov = (self.fov_t * self.aspect.min(1.)).atan();
+        let distance = radius / half_fov.sin();
+        let up = self.up();
+        self.set_view(center - distance * self.direction(), center, up);
+    }
+
+    /// Project `point` onto the image
+    /// Returns the continuous pixel coordinates through which a camera ray would hit it, so the
+    /// point lies in pixel `(u.floor(), v.floor())`. The coordinates lie outside of `[0, width)`
+    /// and `[0, height)` if the point is not visible. `None` if it lies behind the camera
+    #[must_use]
+    pub fn project(&self, point: &Point3) -> Option<(f32, f32)> {
+        let local = self.transform.inverse_affine()?.transform_point(point);
+        if local[2] >= 0. {
+            return None;
+        }
+        // invert the mapping of `compute_camera_ray` on the image plane at distance 1
+        let x = local[0] / -local[2];
+        let y = local[1] / -local[2];
+        let u = (x / self.fov_t + 1.) * self.width / 2.;
+        let v = (y / (self.fov_t * self.aspect) + 1.) * self.height / 2.;
+        Some((u, v))
+    }
+
+    /// Return the approximate width of a pixel at distance 1 from the camera
+    #[must_use]
+    pub fn pixel_spread(&self) -> f32 {
+        2. * self.fov_t / self.width
+    }
+
+    /// Return the frustum of all camera rays in world space
+    /// Includes a margin of a pixel at the borders of the image and the offsets of the depth of
+    /// field
+    #[must_use]
+    pub fn frustum(&self) -> Frustum {
+        let x = self.fov_t * (1. + 2. / self.width);
+        let y = self.fov_t * self.aspect * (1. + 2. / self.height);
+        // move the apex back, so the frustum contains the rays from every point on the lens
+        let back = self.dof.map_or(0., |(_, aperture)| {
+            aperture / (self.fov_t * self.aspect.min(1.))
+        });
+        let frustum = Frustum {
+            apex: Point3::new(0., 0., back),
+            corners: [
+                Vec3::new(-x, -y, -1.),
+                Vec3::new(x, -y, -1.),
+                Vec3::new(x, y, -1.),
+                Vec3::new(-x, y, -1.),
+            ],
+            forward: Vec3::new(0., 0., -1.),
+        };
+        frustum.transform(&self.transform)
+    }
+
+    /// Construct the camera ray through the continuous pixel coordinates `(u, v)`
+    /// Pixel `(x, y)` covers `[x, x + 1) x [y, y + 1)`, so its center lies at `(x + 0.5, y + 0.5)`.
+    /// `v` counts from the bottom of the image, see [`Camera::row_from_image`]. `lens` in
+    /// `[0, 1)^2` picks the point on the lens the ray starts from if depth of field is set
+    fn compute_camera_ray(&self, u: f32, v: f32, lens: (f32, f32)) -> Ray {
+        let x = ((2. * u / self.width) - 1.) * self.fov_t;
+        let y = ((2. * v / self.height) - 1.) * self.fov_t * self.aspect;
 
         let pcamera = Vec3::new(x, y, -1.);
         let orig = Point3::zero();
 
         // offset ray if dof is set
-        if let Some((focal_distance, aperture)) = self.dof {
+        let (ray, focal_distance) = if let Some((focal_distance, aperture)) = self.dof {
             let focal_point = focal_distance * pcamera;
             let orig = orig
                 + Vec3::new(
-                    rand::random_range(-aperture..aperture),
-                    rand::random_range(-aperture..aperture),
+                    aperture * (2. * lens.0 - 1.),
+                    aperture * (2. * lens.1 - 1.),
                     0.,
                 );
             let dir = focal_point - orig;
 
-            Ray::new(orig, dir).transform(&self.transform).normal()
+            (Ray::new(orig, dir), focal_distance)
         } else {
-            Ray::new(orig, pcamera).transform(&self.transform).normal()
-        }
+            (Ray::new(orig, pcamera), 1.)
+        };
+
+        let ray = if self.differentials {
+            // the direction changes by one pixel per pixel on the image plane
+            ray.with_differentials(Differentials {
+                dodx: Vec3::zero(),
+                dody: Vec3::zero(),
+                dddx: Vec3::new(2. / self.width * self.fov_t * focal_distance, 0., 0.),
+                dddy: Vec3::new(
+                    0.,
+                    2. / self.height * self.fov_t * self.aspect * focal_distance,
+                    0.,
+                ),
+            })
+        } else {
+            ray
+        };
+
+        ray.transform(&self.transform).normal()
     }
 
-    /// Construct a camera ray through pixel `(u, v)`
+    /// Construct a camera ray through the center of pixel `(u, v)`
+    /// `rng` is only used to offset the ray if depth of field is set
     #[must_use]
     #[allow(clippy::cast_precision_loss)]
-    pub fn get_ray_through(&self, u: u32, v: u32) -> Ray {
-        self.compute_camera_ray(u as f32, v as f32)
+    pub fn get_ray_through(&self, u: u32, v: u32, rng: &mut impl Rng) -> Ray {
+        let lens = (rng.random_range(0. ..1.), rng.random_range(0. ..1.));
+        self.compute_camera_ray(u as f32 + 0.5, v as f32 + 0.5, lens)
     }
 
-    /// Return a randomly sampled ray through the pixel `(u, v)`
+    /// Return the ray of `sample` through the pixel `(u, v)`
+    /// `sample` lies in `[0, 1)^4`, its first two coordinates pick the point in the pixel and the
+    /// last two the point on the lens, see [`crate::math::halton_sample`]. The samples cover
+    /// exactly the area of the pixel
     #[must_use]
     #[allow(clippy::cast_precision_loss)]
-    pub fn get_sample_ray_through(&self, u: u32, v: u32) -> Ray {
-        let u = u as f32 + rand::random_range(-0.5..0.5);
-        let v = v as f32 + rand::random_range(-0.5..0.5);
-        self.compute_camera_ray(u, v)
+    pub fn get_sample_ray_through(&self, u: u32, v: u32, sample: [f32; 4]) -> Ray {
+        let [du, dv, lens_u, lens_v] = sample;
+        self.compute_camera_ray(u as f32 + du, v as f32 + dv, (lens_u, lens_v))
+    }
+
+    /// Return the camera row `v` of the row `y` of an image with `height` rows
+    /// Images count their rows from the top, the camera counts them from the bottom
+    #[must_use]
+    pub fn row_from_image(y: u32, height: u32) -> u32 {
+        height - 1 - y
     }
 }
@@ -0,0 +1,208 @@
+//! Light gizmos
+//! Simple geometry that shows where the lights of a scene are and where they shine, for setting
+//! up lights in debug renders. The gizmos are unlit and only seen by camera rays, so they don't
+//! change the lighting of the scene
+
+use std::f32::consts::TAU;
+
+use crate::math::{tangent_frame, Color, Point3, Vec3};
+use crate::objects::{Light, Material, ShadingModel, Surface, Texture, Triangle, TriangleShading};
+
+/// Number of lines along the mantle of a spot light cone
+const CONE_LINES: usize = 8;
+
+/// Number of segments of circles
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// Largest opening angle of spot light cones that is drawn, wider cones are drawn at this angle
+const MAX_CONE_ANGLE: f32 = 80. * TAU / 360.;
+
+/// Return the gizmos of `lights`, sized relative to `size`
+/// Point lights and spot lights get a sphere at their position (with the radius of area lights),
+/// spot lights also the outline of their inner and outer cone. Parallel lights get an arrow that
+/// points along the light at `target` and ends `distance` before it. Ambient lights have no gizmo
+#[must_use]
+pub fn light_gizmos(lights: &[Light], size: f32, target: Point3, distance: f32) -> Vec<Surface> {
+    let width = 0.1 * size;
+    let mut gizmos = Vec::new();
+    for light in lights {
+        match light {
+            Light::Ambient { .. } => {}
+            Light::Parallel { color, direction } => {
+                let dir = Vec3::normal(direction);
+                let tip = target - distance * dir;
+                let tail = tip - 6. * size * dir;
+                let mut triangles = line(tail, tip - 2. * size * dir, width);
+                triangles.extend(cone(tip, -2. * size * dir, 0.6 * size));
+                gizmos.push(gizmo(Surface::mesh(triangles, unlit(*color))));
+            }
+            Light::Point {
+                color,
+                position,
+                radius,
+                ..
+            } => {
+                let sphere = Surface::sphere(*position, radius.max(0.5 * size), unlit(*color));
+                gizmos.push(gizmo(sphere));
+            }
+            Light::Spot {
+                color,
+                position,
+                direction,
+                cos_inner,
+                cos_outer,
+                ..
+            } => {
+                let sphere = Surface::sphere(*position, 0.5 * size, unlit(*color));
+                gizmos.push(gizmo(sphere));
+                let dir = Vec3::normal(direction);
+                let length = 10. * size;
+                let mut triangles = Vec::new();
+                for (k, cos) in [*cos_outer, *cos_inner].into_iter().enumerate() {
+                    let radius = length * cos.acos().min(MAX_CONE_ANGLE).tan();
+                    let rim = circle(*position + length * dir, dir, radius);
+                    for i in 0..CIRCLE_SEGMENTS {
+                        triangles.extend(line(rim[i], rim[(i + 1) % CIRCLE_SEGMENTS], width));
+                    }
+                    // the mantle only along the outer cone
+                    if k == 0 {
+                        let step = CIRCLE_SEGMENTS / CONE_LINES;
+                        for point in rim.iter().step_by(step) {
+                            triangles.extend(line(*position, *point, width));
+                        }
+                    }
+                }
+                gizmos.push(gizmo(Surface::mesh(triangles, unlit(*color))));
+            }
+        }
+    }
+    gizmos
+}
+
+/// Make `surface` a gizmo that only the camera sees
+fn gizmo(mut surface: Surface) -> Surface {
+    surface.set_camera_only(true);
+    surface.set_both_sides(true);
+    surface
+}
+
+/// Unlit material in the color of a light, scaled so that its brightest channel is 1
+fn unlit(color: Color) -> Material {
+    let brightest = color[0].max(color[1]).max(color[2]);
+    let color = if brightest > 0. {
+        color / brightest
+    } else {
+        Color::new(1., 1., 1.)
+    };
+    Material::new(Texture::Color(color), 0., 0., 1., ShadingModel::Unlit)
+}
+
+/// Return `CIRCLE_SEGMENTS` points on the circle around `center` with the unit normal `normal`
+fn circle(center: Point3, normal: Vec3, radius: f32) -> Vec<Point3> {
+    let (tangent, bitangent) = tangent_frame(&normal);
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| {
+            #[allow(clippy::cast_precision_loss)]
+            let angle = TAU * i as f32 / CIRCLE_SEGMENTS as f32;
+            let (sin, cos) = angle.sin_cos();
+            center + radius * (cos * tangent + sin * bitangent)
+        })
+        .collect()
+}
+
+/// Return the triangles of a thin three sided prism from `from` to `to`, which looks like a line
+/// from every side
+fn line(from: Point3, to: Point3, width: f32) -> Vec<Triangle> {
+    let dir = to - from;
+    if dir.length_squared() == 0. {
+        return Vec::new();
+    }
+    let (tangent, bitangent) = tangent_frame(&Vec3::normal(&dir));
+    let offsets: Vec<Vec3> = (0..3)
+        .map(|i| {
+            #[allow(clippy::cast_precision_loss)]
+            let (sin, cos) = (TAU * i as f32 / 3.).sin_cos();
+            width * (cos * tangent + sin * bitangent)
+        })
+        .collect();
+    (0..3)
+        .flat_map(|i| {
+            let (a, b) = (offsets[i], offsets[(i + 1) % 3]);
+            [
+                flat([from + a, to + a, to + b]),
+                flat([from + a, to + b, from + b]),
+            ]
+        })
+        .collect()
+}
+
+/// Return the triangles of the mantle of a cone with the apex `apex`, whose base is `axis` away
+/// from the apex
+fn cone(apex: Point3, axis: Vec3, radius: f32) -> Vec<Triangle> {
+    let rim = circle(apex + axis, Vec3::normal(&axis), radius);
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| flat([apex, rim[i], rim[(i + 1) % CIRCLE_SEGMENTS]]))
+        .collect()
+}
+
+/// Return a flat shaded triangle through `points`
+fn flat(points: [Point3; 3]) -> Triangle {
+    let normal = Vec3::normal(&(points[1] - points[0]).cross(&(points[2] - points[0])));
+    let mut triangle = Triangle::new(points, [normal; 3], [(0., 0.); 3]);
+    triangle.set_shading(TriangleShading::Flat);
+    triangle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Ray;
+
+    #[test]
+    fn gizmos_mark_the_lights() {
+        let white = Color::new(1., 1., 1.);
+        let lights = [
+            Light::Ambient {
+                color: white,
+                hemisphere: None,
+            },
+            Light::Point {
+                color: Color::new(2., 1., 0.),
+                position: Point3::new(0., 3., 0.),
+                radius: 0.,
+                samples: None,
+            },
+            Light::Spot {
+                color: white,
+                position: Point3::new(2., 0., 0.),
+                direction: Vec3::new(0., -1., 0.),
+                cos_inner: 0.9,
+                cos_outer: 0.8,
+                profile: None,
+            },
+            Light::Parallel {
+                color: white,
+                direction: Vec3::new(0., -1., 0.),
+            },
+        ];
+        let gizmos = light_gizmos(&lights, 1., Point3::zero(), 5.);
+        // the ambient light has none, the spot light has a sphere and a cone
+        assert_eq!(gizmos.len(), 4);
+        assert!(gizmos.iter().all(Surface::is_camera_only));
+
+        let down = Vec3::new(0., -1., 0.);
+        let hit = gizmos[0]
+            .intersection(&Ray::new(Point3::new(0., 10., 0.), down))
+            .unwrap();
+        assert!((hit.t - 6.5).abs() < 1e-4);
+        // colors are scaled to a brightest channel of 1
+        assert_eq!(hit.base_color(), Color::new(1., 0.5, 0.));
+
+        // the cone opens downwards from the spot light
+        let (min, max) = gizmos[2].bounding_box().unwrap();
+        assert!(max[1] < 0.1 && min[1] < -9.9);
+        // the arrow ends above the target and points down
+        let (min, max) = gizmos[3].bounding_box().unwrap();
+        assert!(min[1] > 4.9 && min[1] < 5.1 && max[1] > 10.9);
+    }
+}
@@ -1,10 +1,69 @@
-use crate::math::{Color, Point3, Ray, Vec3, BIAS};
+use crate::math::{disc_samples, lerp, smoothstep, tangent_frame, Color, Point3, Ray, Vec3, BIAS};
+
+/// Ground half of a hemispheric ambient light
+/// Surfaces facing `up` get the color of the ambient light (the sky), surfaces facing away get the
+/// `ground` color, everything in between a blend of both
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hemisphere {
+    pub ground: Color,
+    pub up: Vec3,
+}
+
+/// Measured intensity profile of a spot light
+/// The values are spread evenly from the axis of the spot light (the first value) to its outer
+/// cone (the last value) and interpolated linearly in between
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpotProfile {
+    values: Vec<f32>,
+}
+
+impl SpotProfile {
+    /// Create a profile from its values
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if there are no values or one of them is not between 0 and 1
+    pub fn new(values: Vec<f32>) -> Result<SpotProfile, String> {
+        if values.is_empty() {
+            return Err("A spot light profile needs at least one value".to_string());
+        }
+        if let Some(value) = values.iter().find(|v| !(0. ..=1.).contains(*v)) {
+            return Err(format!(
+                "The values of a spot light profile have to be between 0 and 1, got {value}"
+            ));
+        }
+        Ok(SpotProfile { values })
+    }
+
+    /// Return the values of the profile
+    #[must_use]
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Return the intensity at `t`, the angle to the axis relative to the angle of the outer cone
+    /// `t` is clamped to 0..=1
+    #[must_use]
+    pub fn value(&self, t: f32) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        let position = t.clamp(0., 1.) * (self.values.len() - 1) as f32;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let i = (position as usize).min(self.values.len() - 1);
+        match self.values.get(i + 1) {
+            #[allow(clippy::cast_precision_loss)]
+            Some(next) => lerp(self.values[i], *next, position - i as f32),
+            None => self.values[i],
+        }
+    }
+}
 
 /// Enum to represent different types of light
 #[derive(Clone, Debug)]
 pub enum Light {
     Ambient {
         color: Color,
+        /// the ground color and up direction, if the light is hemispheric
+        hemisphere: Option<Hemisphere>,
     },
     Parallel {
         color: Color,
@@ -13,20 +72,77 @@ pub enum Light {
     Point {
         color: Color,
         position: Point3,
+        /// radius of the spherical light, 0 for a point that casts hard shadows
+        radius: f32,
+        /// number of shadow rays for soft shadows, overrides the default of the scene
+        samples: Option<u32>,
     },
     Spot {
         color: Color,
         position: Point3,
         direction: Vec3,
-        falloff: (f32, f32),
+        /// cosine of the angle up to which the spot light has full intensity
+        cos_inner: f32,
+        /// cosine of the angle at which the spot light has faded out completely
+        cos_outer: f32,
+        /// measured intensity profile, multiplied with the falloff between the cones
+        profile: Option<SpotProfile>,
     },
 }
 
 impl Light {
-    /// Calculate the shadow ray to the object from the point `from`
+    /// Return the color of an ambient light for a surface with the normal `normal`
+    /// Returns black for all other lights
     #[must_use]
-    pub fn shadow_ray(&self, from: &Point3) -> Option<Ray> {
+    pub fn ambient_color(&self, normal: &Vec3) -> Color {
         match self {
+            Self::Ambient {
+                color,
+                hemisphere: None,
+            } => *color,
+            Self::Ambient {
+                color,
+                hemisphere: Some(Hemisphere { ground, up }),
+            } => {
+                let t = 0.5 * (1. + Vec3::normal(normal).dot(&Vec3::normal(up)));
+                lerp(*ground, *color, t)
+            }
+            _ => Color::zero(),
+        }
+    }
+
+    /// Return the fraction of the intensity of the light that shines towards `point`
+    /// Spot lights fade out between their inner and outer cone and are scaled by their profile,
+    /// all other lights shine equally in all directions
+    #[must_use]
+    pub fn intensity(&self, point: &Point3) -> f32 {
+        let Self::Spot {
+            position,
+            direction,
+            cos_inner,
+            cos_outer,
+            profile,
+            ..
+        } = self
+        else {
+            return 1.;
+        };
+        let cos = Vec3::normal(&(*point - *position)).dot(&Vec3::normal(direction));
+        let in_light = smoothstep(*cos_outer, *cos_inner, cos);
+        match profile {
+            Some(profile) if in_light > 0. => {
+                in_light * profile.value(cos.clamp(-1., 1.).acos() / cos_outer.acos())
+            }
+            _ => in_light,
+        }
+    }
+
+    /// Calculate the shadow ray to the object from the point `from`
+    /// The shadow ray sees the scene at `time`, which should be the time of the ray that hit the
+    /// point
+    #[must_use]
+    pub fn shadow_ray(&self, from: &Point3, time: Option<f32>) -> Option<Ray> {
+        let ray = match self {
             Self::Ambient { .. } => None,
             Self::Parallel {
                 color: _,
@@ -36,7 +152,7 @@ impl Light {
                 let pos = *from + BIAS * direction;
                 Some(Ray::new(pos, direction))
             }
-            Self::Point { color: _, position } => {
+            Self::Point { position, .. } => {
                 let mut direction = *position - *from;
                 let length = direction.length();
                 direction /= length; // normalize
@@ -45,10 +161,10 @@ impl Light {
                                                                   // length
             }
             Self::Spot {
-                color: _,
                 position,
                 direction,
-                falloff,
+                cos_outer,
+                ..
             } => {
                 let mut shadow_direction = *position - *from;
                 let length = shadow_direction.length();
@@ -56,14 +172,153 @@ impl Light {
 
                 // if the point is completely outside the cone, we dont have to send a shadow ray
                 let light_dir = -Vec3::normal(direction);
-                let limit = falloff.1;
-                if light_dir.dot(&shadow_direction) < limit {
+                if light_dir.dot(&shadow_direction) < *cos_outer {
                     None
                 } else {
                     let pos = *from + BIAS * shadow_direction;
                     Some(Ray::new(pos, shadow_direction).set_bounds(length))
                 }
             }
+        };
+        ray.map(|ray| ray.with_time(time))
+    }
+
+    /// Return true if the light has a size and casts soft shadows
+    #[must_use]
+    pub fn is_area_light(&self) -> bool {
+        matches!(self, Self::Point { radius, .. } if *radius > 0.)
+    }
+
+    /// Return the number of shadow rays of an area light, if the light overrides the default
+    #[must_use]
+    pub fn samples(&self) -> Option<u32> {
+        match self {
+            Self::Point { samples, .. } => *samples,
+            _ => None,
+        }
+    }
+
+    /// Return the fraction of the light that reaches the point `from`
+    /// `occluded` should return true if the shadow ray hits a surface
+    /// Area lights send `samples` shadow rays to points spread over the disc of the light that
+    /// faces `from`, turned by `rotation` (see [`crate::math::disc_samples`]). All other lights
+    /// send a single shadow ray. All shadow rays see the scene at `time`
+    #[must_use]
+    pub fn visibility(
+        &self,
+        from: &Point3,
+        samples: u32,
+        rotation: f32,
+        time: Option<f32>,
+        occluded: impl Fn(&Ray) -> bool,
+    ) -> f32 {
+        let (position, radius) = match self {
+            Self::Point {
+                position, radius, ..
+            } if *radius > 0. => (position, radius),
+            _ => {
+                return match self.shadow_ray(from, time) {
+                    Some(ray) if occluded(&ray) => 0.,
+                    _ => 1.,
+                }
+            }
+        };
+        let (tangent, bitangent) = tangent_frame(&Vec3::normal(&(*position - *from)));
+        let visible = disc_samples(samples, rotation)
+            .filter(|(x, y)| {
+                let target = *position + *radius * (*x * tangent + *y * bitangent);
+                let mut direction = target - *from;
+                let length = direction.length();
+                direction /= length;
+                let ray = Ray::new(*from + BIAS * direction, direction)
+                    .set_bounds(length)
+                    .with_time(time);
+                !occluded(&ray)
+            })
+            .count();
+        #[allow(clippy::cast_precision_loss)]
+        let fraction = visible as f32 / samples.max(1) as f32;
+        fraction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hemispheric_ambient_blends_sky_and_ground() {
+        let (sky, ground) = (Color::new(0.4, 0.6, 1.), Color::new(0.3, 0.2, 0.1));
+        let light = Light::Ambient {
+            color: sky,
+            hemisphere: Some(Hemisphere {
+                ground,
+                up: Vec3::new(0., 2., 0.),
+            }),
+        };
+        let up = light.ambient_color(&Vec3::new(0., 1., 0.));
+        assert!((up - sky).length() < 1e-6);
+        let down = light.ambient_color(&Vec3::new(0., -3., 0.));
+        assert!((down - ground).length() < 1e-6);
+        let horizontal = light.ambient_color(&Vec3::new(1., 0., 1.));
+        assert!((horizontal - (sky + ground) / 2.).length() < 1e-6);
+
+        // flat ambient lights are the same in every direction
+        let flat = Light::Ambient {
+            color: sky,
+            hemisphere: None,
+        };
+        assert_eq!(flat.ambient_color(&Vec3::new(0., -1., 0.)), sky);
+        let point = Light::Point {
+            color: sky,
+            position: Point3::zero(),
+            radius: 0.,
+            samples: None,
+        };
+        assert_eq!(point.ambient_color(&Vec3::new(0., 1., 0.)), Color::zero());
+    }
+
+    #[test]
+    fn spot_profile_interpolates_between_samples() {
+        let profile = SpotProfile::new(vec![1., 0.9, 0.7, 0.4, 0.1, 0.]).unwrap();
+        let expected = [(0., 1.), (0.2, 0.9), (0.6, 0.4), (1., 0.)];
+        for (t, value) in expected {
+            assert!((profile.value(t) - value).abs() < 1e-6, "{t}");
         }
+        // halfway between two samples
+        assert!((profile.value(0.1) - 0.95).abs() < 1e-6);
+        assert!((profile.value(0.5) - 0.55).abs() < 1e-6);
+        assert!((profile.value(0.9) - 0.05).abs() < 1e-6);
+        // outside of the cone the closest sample is used
+        assert!((profile.value(-1.) - 1.).abs() < 1e-6 && profile.value(2.) == 0.);
+        let flat = SpotProfile::new(vec![0.5]).unwrap();
+        assert!((flat.value(0.3) - 0.5).abs() < 1e-6);
+
+        assert!(SpotProfile::new(Vec::new()).is_err());
+        assert!(SpotProfile::new(vec![1., 1.5]).is_err());
+        assert!(SpotProfile::new(vec![-0.1]).is_err());
+        assert!(SpotProfile::new(vec![f32::NAN]).is_err());
+
+        // the profile scales the light inside of the inner cone and between the cones
+        let spot = |profile| Light::Spot {
+            color: Color::new(1., 1., 1.),
+            position: Point3::zero(),
+            direction: Vec3::new(0., 0., -1.),
+            cos_inner: 40f32.to_radians().cos(),
+            cos_outer: 60f32.to_radians().cos(),
+            profile,
+        };
+        let (plain, measured) = (spot(None), spot(Some(profile.clone())));
+        let at = |degrees: f32| {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            Point3::new(sin, 0., -cos)
+        };
+        assert!((plain.intensity(&at(36.)) - 1.).abs() < 1e-5);
+        assert!((measured.intensity(&at(36.)) - 0.4).abs() < 1e-4);
+        let between = plain.intensity(&at(50.));
+        assert!(between > 0.3 && between < 0.7);
+        let expected = between * profile.value(50. / 60.);
+        assert!((measured.intensity(&at(50.)) - expected).abs() < 1e-5);
+        assert!(measured.intensity(&at(70.)) == 0. && plain.intensity(&at(70.)) == 0.);
     }
 }
@@ -1,12 +1,22 @@
 //! objects module
 //! contains objects that lie inside the scene
 
+mod background;
 mod camera;
+mod gizmos;
 mod light;
+mod radiance;
 mod scene;
+mod stats;
 mod surface;
 
-pub use crate::objects::camera::Camera;
-pub use crate::objects::light::Light;
-pub use crate::objects::scene::Scene;
-pub use crate::objects::surface::{Material, ShadingModel, Surface, Texture, Triangle};
+pub use crate::objects::background::Background;
+pub use crate::objects::camera::{BounceLimit, Camera, Frustum};
+pub use crate::objects::light::{Hemisphere, Light, SpotProfile};
+pub use crate::objects::radiance::RadianceSplit;
+pub use crate::objects::scene::{surface_ids, HitInfo, RenderStats, Scene};
+pub use crate::objects::stats::IntersectionStats;
+pub use crate::objects::surface::{
+    decimate, ClearCoat, ClipPlane, GradientAxis, Material, Scatter, ShadingModel, Surface, Texel,
+    Texture, ThinFilm, Traceable, Triangle, TriangleShading, Waves,
+};
@@ -0,0 +1,131 @@
+use std::ops;
+
+use crate::math::Color;
+
+/// Color of a ray split into additive components, for compositing them separately
+/// The components add up to the color of the ray, see [`RadianceSplit::total`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RadianceSplit {
+    /// direct and ambient light scattered by the surface
+    pub diffuse: Color,
+    /// highlights of the lights on the surface and its clear coat
+    pub specular: Color,
+    /// everything the surface mirrors, including its clear coat
+    pub reflection: Color,
+    /// everything seen through the surface
+    pub refraction: Color,
+    /// light that doesn't come from the lights: the background and materials that show normals
+    pub emission: Color,
+}
+
+impl RadianceSplit {
+    /// Names of the components, in the order of [`RadianceSplit::components`]
+    pub const NAMES: [&'static str; 5] = [
+        "diffuse",
+        "specular",
+        "reflection",
+        "refraction",
+        "emission",
+    ];
+
+    /// Return a split without any light
+    #[must_use]
+    pub fn zero() -> RadianceSplit {
+        RadianceSplit {
+            diffuse: Color::zero(),
+            specular: Color::zero(),
+            reflection: Color::zero(),
+            refraction: Color::zero(),
+            emission: Color::zero(),
+        }
+    }
+
+    /// Return a split that only holds emitted light
+    #[must_use]
+    pub fn emission(color: Color) -> RadianceSplit {
+        RadianceSplit {
+            emission: color,
+            ..RadianceSplit::zero()
+        }
+    }
+
+    /// Return the components in the order of [`RadianceSplit::NAMES`]
+    #[must_use]
+    pub fn components(&self) -> [Color; 5] {
+        [
+            self.diffuse,
+            self.specular,
+            self.reflection,
+            self.refraction,
+            self.emission,
+        ]
+    }
+
+    /// Return the component `name`, `None` if there is no component with that name
+    #[must_use]
+    pub fn component(&self, name: &str) -> Option<Color> {
+        let index = Self::NAMES.iter().position(|n| *n == name)?;
+        Some(self.components()[index])
+    }
+
+    /// Return the color of the ray, the sum of all components
+    #[must_use]
+    pub fn total(&self) -> Color {
+        self.diffuse + self.specular + self.reflection + self.refraction + self.emission
+    }
+
+    /// Apply `f` to every component
+    fn map(self, f: impl Fn(Color) -> Color) -> RadianceSplit {
+        RadianceSplit {
+            diffuse: f(self.diffuse),
+            specular: f(self.specular),
+            reflection: f(self.reflection),
+            refraction: f(self.refraction),
+            emission: f(self.emission),
+        }
+    }
+}
+
+impl ops::Add for RadianceSplit {
+    type Output = RadianceSplit;
+
+    fn add(self, rhs: RadianceSplit) -> RadianceSplit {
+        RadianceSplit {
+            diffuse: self.diffuse + rhs.diffuse,
+            specular: self.specular + rhs.specular,
+            reflection: self.reflection + rhs.reflection,
+            refraction: self.refraction + rhs.refraction,
+            emission: self.emission + rhs.emission,
+        }
+    }
+}
+
+impl ops::AddAssign for RadianceSplit {
+    fn add_assign(&mut self, rhs: RadianceSplit) {
+        *self = *self + rhs;
+    }
+}
+
+impl ops::Mul<f32> for RadianceSplit {
+    type Output = RadianceSplit;
+
+    fn mul(self, rhs: f32) -> RadianceSplit {
+        self.map(|c| c * rhs)
+    }
+}
+
+impl ops::Mul<Color> for RadianceSplit {
+    type Output = RadianceSplit;
+
+    fn mul(self, rhs: Color) -> RadianceSplit {
+        self.map(|c| c * rhs)
+    }
+}
+
+impl ops::Div<f32> for RadianceSplit {
+    type Output = RadianceSplit;
+
+    fn div(self, rhs: f32) -> RadianceSplit {
+        self.map(|c| c / rhs)
+    }
+}
@@ -1,27 +1,121 @@
-use crate::math::{max, Color, Ray};
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    image::{Image, Playback, TransferFunction},
+    math::{halton_sample, max, pixel_shift, point_rotation, Color, Point3, Ray, Vec3, BIAS},
+};
 
 use super::{
-    surface::{Intersection, Surface},
-    Camera, Light,
+    gizmos,
+    radiance::RadianceSplit,
+    stats,
+    surface::{Intersection, Material, Surface, Triangle},
+    Background, BounceLimit, Camera, Light, Texel,
 };
 
+/// Rays that glossy reflections and soft shadows spend per pixel by default, divided among the
+/// super-samples of the pixel
+const FEATURE_RAYS_PER_PIXEL: u32 = 16;
+
+/// Default for the most rays that a camera sample may branch into at glossy reflections and soft
+/// shadows
+pub const DEFAULT_MAX_FEATURE_RAYS: u32 = 256;
+
+/// Information about the point where a ray hit a surface
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HitInfo {
+    /// the hit point in world space
+    pub point: Point3,
+    /// the ray parameter of the hit point
+    pub t: f32,
+    /// the normalized surface normal at the hit point
+    pub normal: Vec3,
+    /// the texture coordinates at the hit point
+    pub texel: Texel,
+    /// index of the surface that was hit in `Scene::get_surfaces`
+    pub surface_index: usize,
+}
+
+/// Number of pixels of a render that only showed the background or hit a surface
+/// A super-sampled pixel hits a surface if any of its samples does
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub background_pixels: u64,
+    pub geometry_pixels: u64,
+}
+
+impl RenderStats {
+    /// Fraction of background pixels above which the camera probably doesn't point at the scene
+    const MISPOINTED_FRACTION: f64 = 0.95;
+
+    /// Return a warning if almost all pixels only showed the background, which often means that
+    /// the camera points in the wrong direction or the objects lie outside of its view
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn warning(&self) -> Option<String> {
+        let total = self.background_pixels + self.geometry_pixels;
+        let fraction = self.background_pixels as f64 / total.max(1) as f64;
+        (fraction > Self::MISPOINTED_FRACTION).then(|| {
+            format!(
+                "{:.1}% of the pixels show only the background, the camera may not point at the scene",
+                fraction * 100.
+            )
+        })
+    }
+}
+
+/// Counters for the `RenderStats`, shared by the threads rendering the scene
+#[derive(Debug, Default)]
+struct PixelCounters {
+    background: AtomicU64,
+    geometry: AtomicU64,
+}
+
 #[derive(Debug)]
 struct Animated {
     total_frames: usize,
     curr_frame: usize,
-    fps: u16,
+    playback: Playback,
 }
 
 /// Struct to hold all data belonging to a single scene
 #[derive(Debug)]
 pub struct Scene {
     output: String,
-    background_color: Color,
+    /// template of the output path, see `--output-template`
+    output_template: Option<String>,
+    /// texture files that could not be loaded and were replaced with a placeholder
+    missing_textures: Vec<String>,
+    background: Background,
+    /// background seen by reflected and refracted rays, if it differs from the primary one
+    secondary_background: Option<Background>,
     samples: u32,
-    camera: Camera,
+    /// most rays that a camera sample may branch into at glossy reflections and soft shadows
+    max_feature_rays: u32,
+    /// show the time of the camera rays instead of shading the surfaces, see
+    /// [`Scene::set_debug_time`]
+    debug_time: bool,
+    /// cameras sorted by the frame from which on they are used, the first one is used from the
+    /// start
+    cameras: Vec<(usize, Camera)>,
+    /// index of the camera of the current frame
+    active_camera: usize,
     lights: Vec<Light>,
     surfaces: Vec<Surface>,
+    /// whether each surface can be seen by the camera in the current frame
+    visible: Vec<bool>,
     animated: Animated,
+    seed: Option<u64>,
+    /// encoding of the colors in the saved image
+    transfer: TransferFunction,
+    /// pixels traced since the scene was created or the last call to `render_with_stats`
+    counters: PixelCounters,
 }
 
 impl Scene {
@@ -34,19 +128,67 @@ impl Scene {
         lights: Vec<Light>,
         surfaces: Vec<Surface>,
     ) -> Scene {
-        Scene {
+        let mut scene = Scene {
             output,
-            background_color,
+            output_template: None,
+            missing_textures: Vec::new(),
+            background: Background::Solid(background_color),
+            secondary_background: None,
             samples: 0,
-            camera,
+            max_feature_rays: DEFAULT_MAX_FEATURE_RAYS,
+            debug_time: false,
+            cameras: vec![(0, camera)],
+            active_camera: 0,
             lights,
             surfaces,
+            visible: Vec::new(),
             animated: Animated {
                 total_frames: 1,
-                curr_frame: 1,
-                fps: 1,
+                curr_frame: 0,
+                playback: Playback::new(1),
             },
-        }
+            seed: None,
+            transfer: TransferFunction::default(),
+            counters: PixelCounters::default(),
+        };
+        scene.cull_surfaces();
+        scene
+    }
+
+    /// Mark the surfaces that lie outside the view of the camera
+    /// Camera rays skip these surfaces, they still cast shadows and show up in reflections
+    fn cull_surfaces(&mut self) {
+        let frustum = self.camera().frustum();
+        self.visible = self
+            .surfaces
+            .iter()
+            .map(|s| !s.outside_frustum(&frustum))
+            .collect();
+    }
+
+    /// Switch to `camera` from the frame with index `frame` onwards
+    /// The camera has to have the same resolution as the camera of the scene
+    pub fn add_camera_switch(&mut self, frame: usize, camera: Camera) {
+        let index = self.cameras.partition_point(|(f, _)| *f <= frame);
+        self.cameras.insert(index, (frame, camera));
+        self.set_frame(self.animated.curr_frame);
+    }
+
+    /// Return the camera of the current frame
+    #[must_use]
+    pub fn camera(&self) -> &Camera {
+        &self.cameras[self.active_camera].1
+    }
+
+    /// Set the background seen by camera rays
+    pub fn set_background(&mut self, background: Background) {
+        self.background = background;
+    }
+
+    /// Set a separate background for reflected and refracted rays
+    /// Without one, these rays see the same background as camera rays
+    pub fn set_secondary_background(&mut self, background: Background) {
+        self.secondary_background = Some(background);
     }
 
     /// Add the number of samples for the scene
@@ -55,10 +197,75 @@ impl Scene {
         self.samples = samples;
     }
 
-    /// Set the scene to have an animation with the specified number of frames and fps
-    pub fn set_animation(&mut self, frames: usize, fps: u16) {
+    /// Set the most rays that a camera sample may branch into at glossy reflections and soft
+    /// shadows
+    /// The samples of the features along a path multiply (8 reflection rays that each hit a
+    /// surface lit by a light with 16 shadow rays are 128 rays), so they are reduced to stay
+    /// within this budget. Every pixel spends up to its samples times this many rays
+    pub fn set_max_feature_rays(&mut self, max_feature_rays: u32) {
+        self.max_feature_rays = max_feature_rays.max(1);
+    }
+
+    /// Render the time of the camera rays as a shade of gray instead of the scene, from black at
+    /// the start to white at the end of the animation
+    /// Reflected, refracted and shadow rays inherit the time of their camera ray, so the image
+    /// shows at which time every pixel sees the scene
+    pub fn set_debug_time(&mut self, debug_time: bool) {
+        self.debug_time = debug_time;
+    }
+
+    /// Return the most rays that a camera sample may branch into, see
+    /// [`Scene::set_max_feature_rays`]
+    #[must_use]
+    pub fn get_max_feature_rays(&self) -> u32 {
+        self.max_feature_rays
+    }
+
+    /// Return the number of rays for a glossy reflection or soft shadow with the given `samples`
+    /// Without samples of its own, a feature shares `FEATURE_RAYS_PER_PIXEL` among the
+    /// super-samples of the pixel. The result is clamped to the `rays` left in the budget
+    fn feature_samples(&self, samples: Option<u32>, rays: u32) -> u32 {
+        samples
+            .unwrap_or_else(|| FEATURE_RAYS_PER_PIXEL.div_ceil(self.samples.max(1)))
+            .clamp(1, rays.max(1))
+    }
+
+    /// Seed the random sampling (super-sampling and depth of field) so renders are reproducible
+    /// Every pixel derives its own generator from the seed, so the result does not depend on the
+    /// order in which pixels are traced
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+    }
+
+    /// Return the seed of the random sampling, None if the sampling is not reproducible
+    #[must_use]
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Set how the colors are encoded in the saved image
+    pub fn set_transfer_function(&mut self, transfer: TransferFunction) {
+        self.transfer = transfer;
+    }
+
+    /// Return how the colors should be encoded in the saved image
+    #[must_use]
+    pub fn transfer_function(&self) -> TransferFunction {
+        self.transfer
+    }
+
+    /// Let camera rays carry ray differentials, which are used for texture filtering
+    pub fn enable_ray_differentials(&mut self) {
+        for (_, camera) in &mut self.cameras {
+            camera.enable_differentials();
+        }
+    }
+
+    /// Set the scene to have an animation with the specified number of frames and playback
+    /// settings
+    pub fn set_animation(&mut self, frames: usize, playback: Playback) {
         self.animated.total_frames = frames;
-        self.animated.fps = fps;
+        self.animated.playback = playback;
     }
 
     /// Return a reference to the output file name
@@ -67,11 +274,62 @@ impl Scene {
         &self.output
     }
 
+    /// Set the template of the output path, which replaces the output file
+    pub fn set_output_template(&mut self, template: Option<String>) {
+        self.output_template = template;
+    }
+
+    /// Return the template of the output path, if the scene has one
+    #[must_use]
+    pub fn get_output_template(&self) -> Option<&str> {
+        self.output_template.as_deref()
+    }
+
+    /// Set the texture files that could not be loaded and were replaced with a placeholder
+    pub fn set_missing_textures(&mut self, missing: Vec<String>) {
+        self.missing_textures = missing;
+    }
+
+    /// Return the texture files that could not be loaded and were replaced with a placeholder
+    #[must_use]
+    pub fn get_missing_textures(&self) -> &[String] {
+        &self.missing_textures
+    }
+
     #[must_use]
     pub fn is_animated(&self) -> bool {
         self.animated.total_frames > 1
     }
 
+    /// Return true if any object in the scene changes between frames, switching cameras counts as
+    /// a change as well
+    #[must_use]
+    pub fn has_animated_objects(&self) -> bool {
+        self.cameras.len() > 1 || self.surfaces.iter().any(Surface::is_animated)
+    }
+
+    /// Cross-check the animation settings against the objects in the scene
+    /// Returns a warning message if the scene is animated but nothing moves, or if objects have
+    /// end parameters but the scene is not animated
+    #[must_use]
+    pub fn animation_warning(&self) -> Option<&'static str> {
+        match (self.is_animated(), self.has_animated_objects()) {
+            (true, false) => Some(
+                "Scene is animated, but no object has end parameters. All frames will be identical",
+            ),
+            (false, true) => Some(
+                "Some objects have end parameters, but the scene is not animated. The end parameters will be ignored",
+            ),
+            _ => None,
+        }
+    }
+
+    /// Return the number of samples per pixel, 0 if the scene is not super-sampled
+    #[must_use]
+    pub fn get_samples(&self) -> u32 {
+        self.samples
+    }
+
     #[must_use]
     pub fn get_frames(&self) -> usize {
         self.animated.total_frames
@@ -79,104 +337,735 @@ impl Scene {
 
     #[must_use]
     pub fn get_fps(&self) -> u16 {
-        self.animated.fps
+        self.animated.playback.fps
+    }
+
+    /// Return the playback settings of the animation
+    #[must_use]
+    pub fn get_playback(&self) -> &Playback {
+        &self.animated.playback
+    }
+
+    /// change the scene to the frame with index `frame` (starting at 0)
+    /// The first frame shows the start and the last frame the end parameters of the objects
+    /// Frames can be set in any order
+    pub fn set_frame(&mut self, frame: usize) {
+        self.animated.curr_frame = frame;
+        let w = self.frame_percentage(frame);
+        let time = self.time_at_frame(frame);
+        self.surfaces.iter_mut().for_each(|s| {
+            s.frame_perc(w);
+            s.set_time(time);
+        });
+        self.active_camera = self.camera_index(frame);
+        self.cull_surfaces();
+    }
+
+    /// Return the percentage of the animation that is finished in the frame `frame`
+    #[allow(clippy::cast_precision_loss)]
+    fn frame_percentage(&self, frame: usize) -> f32 {
+        if self.animated.total_frames > 1 {
+            frame as f32 / (self.animated.total_frames - 1) as f32
+        } else {
+            0.
+        }
+    }
+
+    /// Return the time of the frame `frame` in seconds since the start of the animation
+    #[must_use]
+    pub fn time_at_frame(&self, frame: usize) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        let frame = frame as f32;
+        frame / f32::from(self.get_fps().max(1))
+    }
+
+    /// Return the index of the camera that is active in the frame `frame`
+    fn camera_index(&self, frame: usize) -> usize {
+        self.cameras
+            .partition_point(|(f, _)| *f <= frame)
+            .saturating_sub(1)
+    }
+
+    /// Return the motion of the surface point seen through the center of pixel `(u, v)` from
+    /// the current frame to the next one, in pixels of the camera
+    /// The point on the surface is moved to where it lies in the next frame and projected with the
+    /// camera of the next frame, so both moving surfaces and moving cameras contribute. `u` and
+    /// the motion along it point to the right, `v` and the motion along it up
+    /// Returns no motion for the last frame, for pixels that only show the background, and for
+    /// points that lie behind one of the cameras
+    #[must_use]
+    pub fn motion_vector(&self, u: u32, v: u32) -> (f32, f32) {
+        let next = self.animated.curr_frame + 1;
+        if next >= self.animated.total_frames {
+            return (0., 0.);
+        }
+        let camera = self.camera();
+        let ray = self.camera_ray(u, v);
+        let Some(hit) = self.closest_intersection(&ray) else {
+            return (0., 0.);
+        };
+        let moved =
+            self.surfaces[hit.surface_index].advect(&hit.point, self.frame_percentage(next));
+        let next_camera = &self.cameras[self.camera_index(next)].1;
+        match (camera.project(&hit.point), next_camera.project(&moved)) {
+            (Some((u0, v0)), Some((u1, v1))) => (u1 - u0, v1 - v0),
+            _ => (0., 0.),
+        }
     }
 
     /// change the scene to the next frame
     /// might change the properties of some objects
     pub fn next_frame(&mut self) {
-        self.animated.curr_frame += 1;
-        #[allow(clippy::cast_precision_loss)]
-        let w = self.animated.curr_frame as f32 / self.animated.total_frames as f32;
-        self.surfaces.iter_mut().for_each(|s| s.frame_perc(w));
+        self.set_frame(self.animated.curr_frame + 1);
+    }
+
+    /// Return the minimum and maximum corner of the box around all surfaces in the current frame
+    /// Surfaces without a bounding box are left out, `None` if no surface has one
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        self.surfaces
+            .iter()
+            .filter_map(Surface::bounding_box)
+            .reduce(|(min_a, max_a), (min_b, max_b)| {
+                (
+                    Vec3::new(
+                        min_a[0].min(min_b[0]),
+                        min_a[1].min(min_b[1]),
+                        min_a[2].min(min_b[2]),
+                    ),
+                    Vec3::new(
+                        max_a[0].max(max_b[0]),
+                        max_a[1].max(max_b[1]),
+                        max_a[2].max(max_b[2]),
+                    ),
+                )
+            })
+    }
+
+    /// Move all cameras along their view direction, so that they look at the center of the scene
+    /// and the whole scene fits into the image with a margin of 10%
+    /// Returns false and leaves the cameras unchanged if the scene has no bounding box
+    pub fn auto_frame(&mut self) -> bool {
+        let Some((min, max)) = self.bounding_box() else {
+            return false;
+        };
+        for (_, camera) in &mut self.cameras {
+            camera.frame_box(min, max, 0.1);
+        }
+        self.cull_surfaces();
+        true
+    }
+
+    /// Return a slice of all surfaces in the scene
+    #[must_use]
+    pub fn get_surfaces(&self) -> &[Surface] {
+        &self.surfaces
+    }
+
+    /// Merge all static meshes that share the same appearance into a single mesh each
+    /// The transformations of the merged meshes are baked into their triangles, meshes without a
+    /// partner and all other surfaces are left untouched
+    /// The merged mesh takes the place of the first mesh of its group
+    /// Returns the number of surfaces before and after merging
+    pub fn merge_static_meshes(&mut self) -> (usize, usize) {
+        let before = self.surfaces.len();
+        // first surface of the group, the triangles of all members and the number of members
+        let mut groups: Vec<(usize, Vec<Triangle>, usize)> = Vec::new();
+        let mut keep = vec![true; before];
+        for (i, surface) in self.surfaces.iter().enumerate() {
+            let Some(triangles) = surface.world_triangles() else {
+                continue;
+            };
+            let group = groups
+                .iter_mut()
+                .find(|(first, ..)| self.surfaces[*first].shares_appearance(surface));
+            match group {
+                Some((_, group_triangles, members)) => {
+                    group_triangles.extend(triangles);
+                    *members += 1;
+                    keep[i] = false;
+                }
+                None => groups.push((i, triangles, 1)),
+            }
+        }
+        for (first, triangles, members) in groups {
+            if members > 1 {
+                self.surfaces[first] = self.surfaces[first].with_triangles(triangles);
+            }
+        }
+        let mut keep = keep.into_iter();
+        self.surfaces.retain(|_| keep.next().unwrap_or(true));
+        self.cull_surfaces();
+        (before, self.surfaces.len())
+    }
+
+    /// Add gizmos that show the lights of the scene, for debug renders
+    /// The gizmos are sized relative to the box around all surfaces and only seen by camera rays,
+    /// so they cast no shadows and don't show up in reflections
+    /// Returns the number of added gizmos
+    pub fn add_light_gizmos(&mut self) -> usize {
+        let (center, extent) = self
+            .bounding_box()
+            .map_or((Point3::zero(), 1.), |(min, max)| {
+                (0.5 * (min + max), 0.5 * (max - min).length())
+            });
+        let size = 0.04 * extent.max(1e-3);
+        let gizmos = gizmos::light_gizmos(&self.lights, size, center, extent);
+        let count = gizmos.len();
+        self.surfaces.extend(gizmos);
+        self.cull_surfaces();
+        count
+    }
+
+    /// Return the ids of all surfaces, in the same order as [`Scene::get_surfaces`]
+    /// The id is the kind of the surface followed by its index among the surfaces of that kind,
+    /// e.g. `sphere0` for the first sphere of the scene
+    #[must_use]
+    pub fn surface_ids(&self) -> Vec<String> {
+        surface_ids(&self.surfaces)
+    }
+
+    /// Replace the material of the surface with the id `filter`, or of every surface if there is
+    /// no filter
+    /// See [`Scene::surface_ids`] for the ids of the surfaces
+    /// Returns the number of surfaces whose material was replaced
+    pub fn override_materials(&mut self, filter: Option<&str>, material: &Material) -> usize {
+        let ids = self.surface_ids();
+        let mut count = 0;
+        for (surface, id) in self.surfaces.iter_mut().zip(ids) {
+            if filter.is_none_or(|f| f == id) {
+                surface.set_material(material.clone());
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Multiply the material property `name` of every surface by `factor`
+    /// See [`Material::scale_property`] for the properties and how they are clamped
+    ///
+    /// # Errors
+    ///
+    /// Returns a message with the valid names if `name` is not a material property
+    pub fn scale_material_property(&mut self, name: &str, factor: f32) -> Result<(), String> {
+        if !Material::PROPERTIES.contains(&name) {
+            return Err(format!(
+                "Unknown material property '{name}', expected one of: {}",
+                Material::PROPERTIES.join(", ")
+            ));
+        }
+        for surface in &mut self.surfaces {
+            surface.material_mut().scale_property(name, factor);
+        }
+        Ok(())
+    }
+
+    /// Return a slice of all lights in the scene
+    #[must_use]
+    pub fn get_lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Return the number of lights in the scene
+    #[must_use]
+    pub fn light_count(&self) -> usize {
+        self.lights.len()
+    }
+
+    /// Return the number of triangles of every mesh in the scene
+    /// Every ray that hits the bounding box of a mesh is tested against all of its triangles
+    #[must_use]
+    pub fn triangle_counts(&self) -> Vec<usize> {
+        self.surfaces
+            .iter()
+            .filter_map(Surface::triangle_count)
+            .collect()
+    }
+
+    /// Return the total number of bytes used by all textures and environment maps in the scene
+    #[must_use]
+    pub fn texture_memory(&self) -> usize {
+        self.surfaces
+            .iter()
+            .map(|s| s.material().texture_memory())
+            .sum::<usize>()
+            + self.background.memory_size()
+            + self
+                .secondary_background
+                .as_ref()
+                .map_or(0, Background::memory_size)
     }
 
     /// Return the dimensions of the image
     #[must_use]
     pub fn get_dimensions(&self) -> (u32, u32) {
-        self.camera.get_dimensions()
+        self.camera().get_dimensions()
+    }
+
+    /// Change the dimensions of the image for all cameras
+    pub fn set_dimensions(&mut self, horizontal: u32, vertical: u32) {
+        for (_, camera) in &mut self.cameras {
+            camera.set_dimensions(horizontal, vertical);
+        }
+        self.cull_surfaces();
+    }
+
+    /// Scale the dimensions of the image for all cameras by `factor`, keeping their framing
+    /// See [`Camera::scale_dimensions`]
+    pub fn scale_dimensions(&mut self, factor: f32) {
+        for (_, camera) in &mut self.cameras {
+            camera.scale_dimensions(factor);
+        }
+        self.cull_surfaces();
+    }
+
+    /// Return the maximum bounces of the current camera
+    #[must_use]
+    pub fn get_max_bounces(&self) -> u32 {
+        self.camera().get_max_bounces()
+    }
+
+    /// Set the maximum bounces for all cameras
+    pub fn set_max_bounces(&mut self, max_bounces: u32) {
+        for (_, camera) in &mut self.cameras {
+            camera.set_max_bounces(max_bounces);
+        }
     }
 
     /// Boolean test if a ray intersects any surface in the scene
     fn intersects_any(&self, with: &Ray) -> bool {
+        stats::count_ray();
         self.surfaces
             .iter()
-            .any(|surface| surface.has_intersection(with))
+            .any(|surface| !surface.is_camera_only() && surface.has_intersection(with))
     }
 
-    /// Find the closest intersection of a ray with any surface in the scene
+    /// Find the closest intersection of a ray with any surface in the scene together with the
+    /// index of the surface
     /// Returns None if no surface intersects with the ray
-    fn closest_intersection(&self, with: &Ray) -> Option<Intersection<'_>> {
-        self.surfaces
-            .iter()
-            // map each sphere to it's intersection with the ray if it exists
-            .filter_map(|surface| surface.intersection(with))
-            // sort the intersections by 't'
-            .min_by(|lhs, rhs| lhs.t.partial_cmp(&rhs.t).expect("t shouldn't be NaN"))
+    /// The ray is bounded by the closest hit found so far, so surfaces further away can exit early
+    /// For camera rays (`camera_ray`) surfaces outside the view of the camera are skipped, all
+    /// other rays skip the surfaces that only camera rays see
+    fn nearest_intersection(
+        &self,
+        with: &Ray,
+        camera_ray: bool,
+    ) -> Option<(usize, Intersection<'_>)> {
+        stats::count_ray();
+        let mut ray = *with;
+        let mut closest: Option<(usize, Intersection<'_>)> = None;
+        for (i, (surface, _)) in
+            self.surfaces
+                .iter()
+                .zip(&self.visible)
+                .enumerate()
+                .filter(|(_, (surface, visible))| {
+                    if camera_ray {
+                        **visible
+                    } else {
+                        !surface.is_camera_only()
+                    }
+                })
+        {
+            if let Some(hit) = surface.intersection(&ray) {
+                // on ties keep the first hit
+                if closest.as_ref().is_none_or(|(_, c)| hit.t < c.t) {
+                    ray = ray.set_bounds(hit.t);
+                    closest = Some((i, hit));
+                }
+            }
+        }
+        closest
     }
 
-    /// Calculate the color of an intersection
-    /// iterates over all lights and sums up their color at the intersection, if they are in los of
-    /// the intersection point
-    fn intersection_color(&self, intersect: &Intersection, ray: &Ray) -> Color {
-        self.lights
-            .iter()
-            // filter lights whose shadow ray intersects with any surfaces in the scene
-            .filter(|light| {
-                light
-                    .shadow_ray(&intersect.point)
-                    .is_none_or(|ray| !self.intersects_any(&ray))
+    /// Find the closest point where `ray` hits any surface in the scene
+    /// Returns None if no surface intersects with the ray within its bounds
+    /// Surfaces that only camera rays see are skipped
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rt::math::{Color, Point3, Ray, Vec3};
+    /// use rt::objects::{Camera, Material, Scene, ShadingModel, Surface, Texture};
+    ///
+    /// # let material = Material::new(
+    /// #     Texture::Color(Color::new(1., 1., 1.)),
+    /// #     0.,
+    /// #     0.,
+    /// #     0.,
+    /// #     ShadingModel::Phong { ka: 1., kd: 0., ks: 0., exp: 1 },
+    /// # );
+    /// # let camera = Camera::new(
+    /// #     Point3::zero(),
+    /// #     Point3::new(0., 0., -1.),
+    /// #     Vec3::new(0., 1., 0.),
+    /// #     1.,
+    /// #     1,
+    /// #     1,
+    /// #     1,
+    /// # );
+    /// let sphere = Surface::sphere(Point3::new(0., 0., -5.), 1., material);
+    /// let scene = Scene::new(String::new(), Color::zero(), camera, vec![], vec![sphere]);
+    ///
+    /// let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+    /// let hit = scene.closest_intersection(&ray).unwrap();
+    /// assert_eq!(hit.surface_index, 0);
+    /// assert!((hit.point - Point3::new(0., 0., -4.)).length() < 1e-5);
+    /// assert!((hit.normal - Vec3::new(0., 0., 1.)).length() < 1e-5);
+    /// ```
+    #[must_use]
+    pub fn closest_intersection(&self, ray: &Ray) -> Option<HitInfo> {
+        self.nearest_intersection(ray, false)
+            .map(|(surface_index, hit)| HitInfo {
+                point: hit.point,
+                t: hit.t,
+                normal: hit.normal,
+                texel: hit.texel,
+                surface_index,
             })
-            // calculate the color for each light
-            .map(|light| intersect.get_color(light, ray))
-            // sum them together
-            .reduce(|lhs, rhs| lhs + rhs)
-            // if there was no light in sight, the object is black
-            .unwrap_or(Color::zero())
+    }
+
+    /// Cast a ray from `origin` in `direction` and return the closest hit that is at most `max_t`
+    /// away from the origin
+    /// The direction doesn't have to be normalized, `t` of the hit is the distance to the origin
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rt::math::{Color, Point3, Vec3};
+    /// use rt::objects::{Camera, Material, Scene, ShadingModel, Surface, Texture};
+    ///
+    /// # let material = Material::new(
+    /// #     Texture::Color(Color::new(1., 1., 1.)),
+    /// #     0.,
+    /// #     0.,
+    /// #     0.,
+    /// #     ShadingModel::Phong { ka: 1., kd: 0., ks: 0., exp: 1 },
+    /// # );
+    /// # let camera = Camera::new(
+    /// #     Point3::zero(),
+    /// #     Point3::new(0., 0., -1.),
+    /// #     Vec3::new(0., 1., 0.),
+    /// #     1.,
+    /// #     1,
+    /// #     1,
+    /// #     1,
+    /// # );
+    /// let sphere = Surface::sphere(Point3::new(0., 0., -5.), 1., material);
+    /// let scene = Scene::new(String::new(), Color::zero(), camera, vec![], vec![sphere]);
+    ///
+    /// let (origin, direction) = (Point3::zero(), Vec3::new(0., 0., -2.));
+    /// let hit = scene.ray_cast(origin, direction, 10.).unwrap();
+    /// assert!((hit.t - 4.).abs() < 1e-5);
+    /// // the sphere is too far away
+    /// assert!(scene.ray_cast(origin, direction, 3.).is_none());
+    /// ```
+    #[must_use]
+    pub fn ray_cast(&self, origin: Point3, direction: Vec3, max_t: f32) -> Option<HitInfo> {
+        let ray = Ray::new(origin, Vec3::normal(&direction)).set_bounds(max_t);
+        self.closest_intersection(&ray)
+    }
+
+    /// Return true if any surface lies between the points `a` and `b`
+    /// Surfaces that touch `a` or `b` don't count as occluders
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rt::math::{Color, Point3, Vec3};
+    /// use rt::objects::{Camera, Material, Scene, ShadingModel, Surface, Texture};
+    ///
+    /// # let material = Material::new(
+    /// #     Texture::Color(Color::new(1., 1., 1.)),
+    /// #     0.,
+    /// #     0.,
+    /// #     0.,
+    /// #     ShadingModel::Phong { ka: 1., kd: 0., ks: 0., exp: 1 },
+    /// # );
+    /// # let camera = Camera::new(
+    /// #     Point3::zero(),
+    /// #     Point3::new(0., 0., -1.),
+    /// #     Vec3::new(0., 1., 0.),
+    /// #     1.,
+    /// #     1,
+    /// #     1,
+    /// #     1,
+    /// # );
+    /// let sphere = Surface::sphere(Point3::new(0., 0., -5.), 1., material);
+    /// let scene = Scene::new(String::new(), Color::zero(), camera, vec![], vec![sphere]);
+    ///
+    /// assert!(scene.is_occluded(Point3::zero(), Point3::new(0., 0., -10.)));
+    /// assert!(!scene.is_occluded(Point3::zero(), Point3::new(5., 0., -5.)));
+    /// ```
+    #[must_use]
+    pub fn is_occluded(&self, a: Point3, b: Point3) -> bool {
+        let direction = b - a;
+        let length = direction.length();
+        if length <= 2. * BIAS {
+            return false;
+        }
+        let direction = direction / length;
+        let ray = Ray::new(a + BIAS * direction, direction).set_bounds(length - 2. * BIAS);
+        self.intersects_any(&ray)
+    }
+
+    /// Calculate the color of an intersection
+    /// sums up the color of all lights, that are in los of the intersection point
+    /// Area lights send up to `rays` shadow rays for soft shadows
+    fn intersection_color(&self, intersect: &Intersection, ray: &Ray, rays: u32) -> RadianceSplit {
+        let rotation = point_rotation(&intersect.point);
+        intersect.get_split_color(
+            &self.lights,
+            // a shadow ray is blocked if it intersects with any surface in the scene
+            |light, point| {
+                let samples = if light.is_area_light() {
+                    self.feature_samples(light.samples(), rays)
+                } else {
+                    1
+                };
+                light.visibility(point, samples, rotation, ray.time(), |shadow| {
+                    debug_assert_eq!(
+                        shadow.time(),
+                        ray.time(),
+                        "shadow rays have to see the scene at the time of the ray that hit the point"
+                    );
+                    self.intersects_any(shadow)
+                })
+            },
+            ray,
+        )
+    }
+
+    /// Return the width of the ray cone of a camera ray at distance 1
+    /// With super-sampling each sample covers only a part of the pixel
+    #[allow(clippy::cast_precision_loss)]
+    fn ray_spread(&self) -> f32 {
+        let spread = self.camera().pixel_spread();
+        if self.samples > 1 {
+            spread / (self.samples as f32).sqrt()
+        } else {
+            spread
+        }
     }
 
     /// Recursively ray trace a ray shot into the Scene
     /// `depth` should be the allowed maximum depth, and will be _decreased_ with every iteration
-    fn recursive_trace(&self, ray: &Ray, depth: u32) -> Color {
-        match self.closest_intersection(ray) {
-            Some(intersection) => {
-                let color = self.intersection_color(&intersection, ray);
-                let mut reflected_color = Color::zero();
-                let mut refracted_color = Color::zero();
-                if depth == 0 {
-                    return color;
-                }
-                if intersection.get_reflectance() > 0. {
-                    let reflected_ray = intersection.reflected_ray(ray);
-                    reflected_color = self.recursive_trace(&reflected_ray, depth - 1);
-                }
-                if intersection.get_transmittance() > 0. {
-                    let refracted_ray = intersection.refracted_ray(ray);
-                    refracted_color = self.recursive_trace(&refracted_ray, depth - 1);
-                }
-                color
-                    * max(
-                        1. - intersection.get_reflectance() - intersection.get_transmittance(),
-                        0.0,
-                    )
-                    + reflected_color * intersection.get_reflectance()
-                    + refracted_color * intersection.get_transmittance()
+    /// `travelled` is the distance the ray travelled before, which widens the ray cone
+    /// `rays` is the number of rays the ray may still branch into, see
+    /// [`Scene::set_max_feature_rays`]
+    /// Rays that miss every surface show the secondary background
+    fn recursive_trace(&self, ray: &Ray, depth: u32, travelled: f32, rays: u32) -> Color {
+        match self.nearest_intersection(ray, false) {
+            Some((_, intersection)) => self
+                .shade(intersection, ray, depth, travelled, rays)
+                .total(),
+            None => self
+                .secondary_background
+                .as_ref()
+                .unwrap_or(&self.background)
+                .color(ray),
+        }
+    }
+
+    /// Return the color that replaces a secondary ray that is not traced anymore, because the
+    /// maximum number of bounces was reached
+    fn bounce_limit_color(&self, ray: &Ray) -> Color {
+        match self.camera().get_bounce_limit() {
+            BounceLimit::Black => Color::zero(),
+            BounceLimit::Background => self
+                .secondary_background
+                .as_ref()
+                .unwrap_or(&self.background)
+                .color(ray),
+            // the ambient light that a surface facing along the ray would receive
+            BounceLimit::Ambient => self.lights.iter().fold(Color::zero(), |sum, light| {
+                sum + light.ambient_color(ray.dir())
+            }),
+        }
+    }
+
+    /// Trace a ray shot from the camera into the scene
+    /// Returns the color and whether the ray hit a surface
+    /// The background counts as emission
+    fn trace_camera_ray(&self, ray: &Ray) -> (RadianceSplit, bool) {
+        let depth = self.camera().get_max_bounces();
+        if self.debug_time {
+            let time = ray.time().unwrap_or(0.);
+            let hit = self.nearest_intersection(ray, true).is_some();
+            return (RadianceSplit::emission(Color::new(time, time, time)), hit);
+        }
+        match self.nearest_intersection(ray, true) {
+            Some((_, intersection)) => (
+                self.shade(intersection, ray, depth, 0., self.max_feature_rays),
+                true,
+            ),
+            None => (RadianceSplit::emission(self.background.color(ray)), false),
+        }
+    }
+
+    /// Calculate the color of the intersection of `ray` with a surface
+    /// Traces reflected and refracted rays if the material reflects or transmits light
+    /// The whole color of the reflected and refracted rays counts as reflection and refraction,
+    /// the light of the surface itself is split into its diffuse and specular part
+    fn shade(
+        &self,
+        mut intersection: Intersection,
+        ray: &Ray,
+        depth: u32,
+        travelled: f32,
+        rays: u32,
+    ) -> RadianceSplit {
+        let travelled = travelled + intersection.t;
+        intersection.set_footprint(travelled * self.ray_spread(), ray);
+        let direct = self.intersection_color(&intersection, ray, rays);
+        let mut refracted_color = Color::zero();
+        if depth == 0 && self.camera().get_bounce_limit() == BounceLimit::Black {
+            return direct;
+        }
+        let trace = |secondary: &Ray, rays: u32| {
+            debug_assert_eq!(
+                secondary.time(),
+                ray.time(),
+                "secondary rays have to inherit the time of the primary ray"
+            );
+            match depth {
+                0 => self.bounce_limit_color(secondary),
+                _ => self.recursive_trace(secondary, depth - 1, travelled, rays),
             }
-            None => self.background_color,
+        };
+        let (reflected_color, coat_color) = self.reflected_colors(&intersection, ray, rays, trace);
+        if intersection.get_transmittance() > 0. {
+            refracted_color = trace(&intersection.refracted_ray(ray), rays);
+        }
+        // the direct light already passed through the coat, see `Material::get_color`
+        let coat = intersection.get_coat_reflectance(ray);
+        let surface = max(
+            1. - intersection.get_reflectance() - intersection.get_transmittance(),
+            0.0,
+        );
+        RadianceSplit {
+            reflection: (1. - coat)
+                * reflected_color
+                * intersection.get_reflection_tint(ray)
+                * intersection.get_reflectance()
+                + coat_color * coat,
+            refraction: (1. - coat) * refracted_color * intersection.get_transmittance(),
+            ..direct * surface
         }
     }
 
-    /// trace the pixel with super-sampling
-    /// will panic if `samples` is 0 (0 samples doesn't really make sense, does it?)
+    /// Return the color reflected by the material and the color reflected by its clear coat
+    /// A smooth clear coat mirrors the scene on top of the reflections of the base. A rough
+    /// material averages several reflection rays, each of which may branch into an equal share of
+    /// the `rays` that are left
+    fn reflected_colors(
+        &self,
+        intersection: &Intersection,
+        ray: &Ray,
+        rays: u32,
+        trace: impl Fn(&Ray, u32) -> Color,
+    ) -> (Color, Color) {
+        let material = intersection.material;
+        let coat = intersection.get_coat_reflectance(ray) > 0.;
+        let reflects = intersection.get_reflectance() > 0.;
+        let glossy = reflects && material.reflection_roughness() > 0.;
+        let mirrored = if coat || (reflects && !glossy) {
+            trace(&intersection.reflected_ray(ray), rays)
+        } else {
+            Color::zero()
+        };
+        if !glossy {
+            return (mirrored, mirrored);
+        }
+        let samples = self.feature_samples(material.reflection_samples(), rays);
+        let glossy_color = intersection
+            .glossy_rays(ray, samples)
+            .fold(Color::zero(), |sum, r| sum + trace(&r, rays / samples));
+        #[allow(clippy::cast_precision_loss)]
+        let glossy_color = glossy_color / samples as f32;
+        (glossy_color, mirrored)
+    }
+
+    /// Trace a camera ray and return its color together with the render layer of the surface
+    /// that was hit first
+    /// The layer is `None` if the ray hit no surface or a surface without layer
+    fn trace_primary(&self, ray: &Ray) -> (Color, Option<&str>) {
+        match self.nearest_intersection(ray, true) {
+            Some((_, intersection)) => {
+                let layer = intersection.layer;
+                let depth = self.camera().get_max_bounces();
+                let color = self.shade(intersection, ray, depth, 0., self.max_feature_rays);
+                (color.total(), layer)
+            }
+            None => (self.background.color(ray), None),
+        }
+    }
+
+    /// Return the random number generator for the pixel
+    /// Seeded from the scene seed and the pixel position if the scene has a seed
+    fn pixel_rng(&self, u: u32, v: u32) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(
+                seed ^ (u64::from(u) << 32 | u64::from(v)).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+            ),
+            None => StdRng::from_rng(&mut rand::rng()),
+        }
+    }
+
+    /// Return the time of the camera rays as the percentage of the animation that is finished in
+    /// the current frame
+    fn ray_time(&self) -> f32 {
+        self.frame_percentage(self.animated.curr_frame)
+    }
+
+    /// Return the single camera ray through the pixel, used when the scene has no super-sampling
+    fn camera_ray(&self, u: u32, v: u32) -> Ray {
+        self.camera()
+            .get_ray_through(u, v, &mut self.pixel_rng(u, v))
+            .with_time(Some(self.ray_time()))
+    }
+
+    /// Return the ray of the super-sample `index` of the pixel
+    /// The samples of a pixel follow a Halton sequence over the pixel and the lens, shifted per
+    /// pixel (see [`pixel_shift`]) so that the pattern doesn't repeat across the image. Every
+    /// sample depends only on its index, so a range of samples can be traced without tracing the
+    /// samples before it, and the samples of a pixel are the same however they are split up
     #[allow(clippy::cast_precision_loss)]
-    fn ssaa_trace_pixel(&self, u: u32, v: u32) -> Color {
-        let mut final_color = Color::zero();
-        for _ in 0..self.samples {
-            let ray = self.camera.get_sample_ray_through(u, v);
-            final_color += self.recursive_trace(&ray, self.camera.get_max_bounces());
+    fn sample_ray(&self, u: u32, v: u32, index: u32) -> Ray {
+        let shift = pixel_shift(u, v, self.seed.unwrap_or(0));
+        let ray = self
+            .camera()
+            .get_sample_ray_through(u, v, halton_sample(index, shift))
+            .with_time(Some(self.ray_time()));
+        // each sample covers only a part of the pixel
+        match ray.differentials() {
+            Some(d) => ray.with_differentials(d.scaled(1. / (self.samples as f32).sqrt())),
+            None => ray,
         }
+    }
 
-        final_color / self.samples as f32
+    /// trace the super-samples `samples` of the pixel
+    /// Returns the sum of their colors and whether any of them hit a surface
+    fn sum_samples(&self, u: u32, v: u32, samples: Range<u32>) -> (RadianceSplit, bool) {
+        let mut sum = RadianceSplit::zero();
+        let mut any_hit = false;
+        for index in samples {
+            let ray = self.sample_ray(u, v, index);
+            let (color, hit) = self.trace_camera_ray(&ray);
+            sum += color;
+            any_hit |= hit;
+        }
+        (sum, any_hit)
+    }
+
+    /// Count the pixel as one that hit a surface or only showed the background
+    fn count_pixel(&self, hit: bool) {
+        let counter = if hit {
+            &self.counters.geometry
+        } else {
+            &self.counters.background
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
     }
 
     /// ray trace a pixel
@@ -184,11 +1073,1322 @@ impl Scene {
     /// then perform lighting calculations at the closest intersection
     #[must_use]
     pub fn trace_pixel(&self, u: u32, v: u32) -> Color {
-        if self.samples != 0 {
-            return self.ssaa_trace_pixel(u, v);
+        self.trace_pixel_split(u, v).total()
+    }
+
+    /// ray trace a pixel with `samples` super-samples instead of the number of samples of the
+    /// scene
+    /// 0 samples shoot a single ray through the pixel, like a scene without super-sampling
+    #[must_use]
+    pub fn trace_pixel_with_samples(&self, u: u32, v: u32, samples: u32) -> Color {
+        self.trace_pixel_split_with_samples(u, v, samples).total()
+    }
+
+    /// ray trace a pixel like [`Scene::trace_pixel`], with its color split into additive
+    /// components for compositing
+    /// The components add up to the color of [`Scene::trace_pixel`]
+    #[must_use]
+    pub fn trace_pixel_split(&self, u: u32, v: u32) -> RadianceSplit {
+        self.trace_pixel_split_with_samples(u, v, self.samples)
+    }
+
+    /// ray trace a pixel like [`Scene::trace_pixel_with_samples`], with its color split into
+    /// additive components for compositing
+    #[must_use]
+    pub fn trace_pixel_split_with_samples(&self, u: u32, v: u32, samples: u32) -> RadianceSplit {
+        let (split, hit) = self.split_pixel(u, v, samples);
+        self.count_pixel(hit);
+        split
+    }
+
+    /// Trace the pixel with `samples` super-samples and return the average of their colors and
+    /// whether any of them hit a surface
+    #[allow(clippy::cast_precision_loss)]
+    fn split_pixel(&self, u: u32, v: u32, samples: u32) -> (RadianceSplit, bool) {
+        if samples == 0 {
+            self.trace_camera_ray(&self.camera_ray(u, v))
+        } else {
+            let (sum, hit) = self.sum_samples(u, v, 0..samples);
+            (sum / samples as f32, hit)
+        }
+    }
+
+    /// ray trace the super-samples `samples` of a pixel and return the sum of their colors
+    /// Tracing the ranges `0..n` and `n..m` separately adds up to the same sum as tracing `0..m`
+    /// at once in a seeded scene, so a pixel can be refined over several passes. The pixel is
+    /// only counted in the render stats for the pass that starts at sample 0
+    #[must_use]
+    pub fn trace_pixel_samples(&self, u: u32, v: u32, samples: Range<u32>) -> Color {
+        self.trace_pixel_split_samples(u, v, samples).total()
+    }
+
+    /// ray trace the super-samples `samples` of a pixel like [`Scene::trace_pixel_samples`], with
+    /// the sum split into additive components for compositing
+    #[must_use]
+    pub fn trace_pixel_split_samples(&self, u: u32, v: u32, samples: Range<u32>) -> RadianceSplit {
+        let first = samples.start == 0;
+        let (sum, hit) = self.sum_samples(u, v, samples);
+        if first {
+            self.count_pixel(hit);
+        }
+        sum
+    }
+
+    /// Return how many of the pixels traced so far hit a surface or only showed the background
+    /// Counts every pixel traced with `trace_pixel` since the scene was created or the last
+    /// call to `render_with_stats`
+    #[must_use]
+    pub fn render_stats(&self) -> RenderStats {
+        RenderStats {
+            background_pixels: self.counters.background.load(Ordering::Relaxed),
+            geometry_pixels: self.counters.geometry.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Render the current frame and count how many pixels hit a surface
+    /// The colors are encoded with the transfer function of the scene
+    /// Scenes with a seed render to the same image regardless of the number of threads, the
+    /// samples of a pixel are always accumulated in the same order
+    #[must_use]
+    pub fn render_with_stats(&self) -> (Image, RenderStats) {
+        self.counters.background.store(0, Ordering::Relaxed);
+        self.counters.geometry.store(0, Ordering::Relaxed);
+        let (width, height) = self.get_dimensions();
+        let mut img = Image::new(width, height, 1);
+        img.set_transfer_function(self.transfer);
+        img.par_init_pixels(0, |(x, y)| {
+            // invert y to 'unflip' the image
+            self.transfer
+                .encode(self.trace_pixel(*x, Camera::row_from_image(*y, height)))
+                .to_rgb()
+        });
+        (img, self.render_stats())
+    }
+
+    /// Return the color of the background seen through the pixel, without testing any surface
+    #[must_use]
+    pub fn background_pixel(&self, u: u32, v: u32) -> Color {
+        self.background.color(&self.camera_ray(u, v))
+    }
+
+    /// ray trace a pixel into separate render layers
+    /// Returns the color (premultiplied by alpha) and the alpha of the pixel for every layer
+    /// A layer only receives the samples whose camera ray hit one of its surfaces first. All other
+    /// surfaces are held out: they still occlude, cast shadows and show up in reflections, but
+    /// leave the pixel transparent
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn trace_pixel_layers(&self, u: u32, v: u32, layers: &[String]) -> Vec<(Color, f32)> {
+        let mut accum = vec![(Color::zero(), 0.); layers.len()];
+        let samples = self.samples.max(1);
+        for index in 0..samples {
+            let ray = if self.samples == 0 {
+                self.camera_ray(u, v)
+            } else {
+                self.sample_ray(u, v, index)
+            };
+            let (color, layer) = self.trace_primary(&ray);
+            if let Some(i) = layer.and_then(|layer| layers.iter().position(|l| l == layer)) {
+                accum[i].0 += color;
+                accum[i].1 += 1.;
+            }
+        }
+
+        accum
+            .into_iter()
+            .map(|(color, alpha)| (color / samples as f32, alpha / samples as f32))
+            .collect()
+    }
+
+    /// Return true if any surface in the scene is tagged with `layer`
+    #[must_use]
+    pub fn has_layer(&self, layer: &str) -> bool {
+        self.surfaces.iter().any(|s| s.layer() == Some(layer))
+    }
+}
+
+/// Return the ids of `surfaces`, see [`Scene::surface_ids`]
+#[must_use]
+pub fn surface_ids(surfaces: &[Surface]) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    surfaces
+        .iter()
+        .map(|surface| {
+            let count = counts.entry(surface.kind()).or_default();
+            *count += 1;
+            format!("{}{}", surface.kind(), *count - 1)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::math::{lerp, Point3, Vec3};
+    use crate::objects::{Material, ShadingModel, Texel, Texture, Traceable, Waves};
+
+    /// plane facing the camera at `z`, counts how many hits it reported
+    #[derive(Clone, Debug)]
+    struct CountingPlane {
+        z: f32,
+        hits: Arc<AtomicUsize>,
+    }
+
+    impl Traceable for CountingPlane {
+        fn has_intersection(&self, ray: &Ray) -> bool {
+            self.intersection(ray).is_some()
+        }
+
+        fn intersection(&self, ray: &Ray) -> Option<(f32, Vec3, Texel)> {
+            let t = (self.z - ray.orig()[2]) / ray.dir()[2];
+            if !ray.t_in_range(t) {
+                return None;
+            }
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            Some((t, Vec3::new(0., 0., 1.), (0., 0.)))
+        }
+    }
+
+    /// sphere that counts how many intersection tests were performed with it
+    #[derive(Clone, Debug)]
+    struct CountingSphere {
+        center: Point3,
+        radius: f32,
+        tests: Arc<AtomicUsize>,
+    }
+
+    impl Traceable for CountingSphere {
+        fn has_intersection(&self, ray: &Ray) -> bool {
+            self.intersection(ray).is_some()
+        }
+
+        fn intersection(&self, ray: &Ray) -> Option<(f32, Vec3, Texel)> {
+            self.tests.fetch_add(1, Ordering::Relaxed);
+            let oc = self.center - *ray.orig();
+            let h = ray.dir().dot(&oc);
+            let discr = h * h - (oc.length_squared() - self.radius * self.radius);
+            let t = h - discr.sqrt();
+            let p = ray.at(t)?;
+            Some((t, p - self.center, (0., 0.)))
+        }
+
+        fn bounding_box(&self) -> Option<(Point3, Point3)> {
+            let r = Vec3::new(self.radius, self.radius, self.radius);
+            Some((self.center - r, self.center + r))
+        }
+    }
+
+    /// plane facing the camera at `z`, with the texture mapped onto a 2x2 area
+    #[derive(Clone, Debug)]
+    struct TexturedPlane {
+        z: f32,
+    }
+
+    impl Traceable for TexturedPlane {
+        fn has_intersection(&self, ray: &Ray) -> bool {
+            self.intersection(ray).is_some()
+        }
+
+        fn intersection(&self, ray: &Ray) -> Option<(f32, Vec3, Texel)> {
+            let t = (self.z - ray.orig()[2]) / ray.dir()[2];
+            let p = ray.at(t)?;
+            Some((t, Vec3::new(0., 0., 1.), (p[0] / 2., p[1] / 2.)))
+        }
+
+        fn texel_density(&self) -> f32 {
+            0.5
+        }
+    }
+
+    fn material() -> Material {
+        Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        )
+    }
+
+    #[test]
+    fn closest_intersection_bounds_ray() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let surfaces = [-2., -3., -4., -5.]
+            .iter()
+            .map(|z| {
+                Surface::new(
+                    Box::new(CountingPlane {
+                        z: *z,
+                        hits: hits.clone(),
+                    }),
+                    material(),
+                )
+            })
+            .collect();
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            1,
+            1,
+            1,
+        );
+        let scene = Scene::new(String::new(), Color::zero(), camera, vec![], surfaces);
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+
+        let closest = scene.closest_intersection(&ray).unwrap();
+        assert!((closest.t - 2.).abs() < f32::EPSILON);
+        // only the closest plane reported a hit, the others were out of range
+        assert_eq!(hits.load(Ordering::Relaxed), 1);
+
+        // an unbounded scan finds the same closest hit but has to report every plane
+        hits.store(0, Ordering::Relaxed);
+        let unbounded = scene
+            .surfaces
+            .iter()
+            .filter_map(|surface| surface.intersection(&ray))
+            .min_by(|lhs, rhs| lhs.t.total_cmp(&rhs.t))
+            .unwrap();
+        assert!((unbounded.t - closest.t).abs() < f32::EPSILON);
+        assert_eq!(hits.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn ray_cast_hits_transformed_surfaces() {
+        use crate::math::Mat4;
+
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            1,
+            1,
+            1,
+        );
+        // a unit sphere at the origin, moved to (3, 0, -5) and scaled by 2 along x
+        let mut moved = Surface::sphere(Point3::zero(), 1., material());
+        let inv_transform = &Mat4::from_scaling(Vec3::new(0.5, 1., 1.))
+            * &Mat4::from_translation(Vec3::new(-3., 0., 5.));
+        moved.set_transform(inv_transform, Mat4::transpose(&inv_transform));
+        let front = Surface::sphere(Point3::new(0., 0., -5.), 1., material());
+        let scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![],
+            vec![front, moved],
+        );
+
+        // hits the scaled side of the moved sphere
+        let hit = scene
+            .ray_cast(Point3::new(10., 0., -5.), Vec3::new(-1., 0., 0.), 100.)
+            .unwrap();
+        assert_eq!(hit.surface_index, 1);
+        assert!((hit.t - 5.).abs() < 1e-4);
+        assert!((hit.point - Point3::new(5., 0., -5.)).length() < 1e-4);
+        assert!((hit.normal - Vec3::new(1., 0., 0.)).length() < 1e-4);
+
+        // the front sphere lies in between and the bound cuts off the moved sphere
+        let hit = scene
+            .ray_cast(Point3::new(-10., 0., -5.), Vec3::new(1., 0., 0.), 100.)
+            .unwrap();
+        assert_eq!(hit.surface_index, 0);
+        assert!((hit.t - 9.).abs() < 1e-4);
+        assert!(scene
+            .ray_cast(Point3::new(10., 0., -5.), Vec3::new(-1., 0., 0.), 4.9)
+            .is_none());
+
+        assert!(scene.is_occluded(Point3::new(10., 0., -5.), Point3::new(-10., 0., -5.)));
+        assert!(!scene.is_occluded(Point3::new(10., 0., -5.), Point3::new(5.5, 0., -5.)));
+        // endpoints on a surface don't occlude
+        assert!(!scene.is_occluded(Point3::new(5., 0., -5.), Point3::new(10., 0., -5.)));
+    }
+
+    #[test]
+    fn merging_static_meshes_keeps_the_image() {
+        use crate::math::Mat4;
+        use crate::objects::Triangle;
+
+        let diffuse = |color| {
+            Material::new(
+                Texture::Color(color),
+                0.,
+                0.,
+                1.,
+                ShadingModel::Phong {
+                    ka: 0.1,
+                    kd: 0.8,
+                    ks: 0.3,
+                    exp: 20,
+                },
+            )
+        };
+        let quad = |origin: Point3, size: f32| {
+            let corners = [
+                origin,
+                origin + Vec3::new(size, 0., 0.),
+                origin + Vec3::new(size, size, 0.),
+                origin + Vec3::new(0., size, 0.),
+            ];
+            let normal = Vec3::new(0., 0., 1.);
+            [[0, 1, 2], [0, 2, 3]]
+                .map(|i| Triangle::new(i.map(|i| corners[i]), [normal; 3], [(0., 0.); 3]))
+                .to_vec()
+        };
+        let white = diffuse(Color::new(1., 1., 1.));
+        let red = diffuse(Color::new(1., 0., 0.));
+        let mut rotated = Surface::mesh(quad(Point3::new(-0.5, -0.5, 0.), 1.), white.clone());
+        let inv_transform =
+            &Mat4::from_y_rotation(-0.4) * &Mat4::from_translation(Vec3::new(1.5, -0.5, 6.));
+        rotated.set_transform(inv_transform, Mat4::transpose(&inv_transform));
+        let scene = || {
+            let surfaces = vec![
+                Surface::mesh(quad(Point3::new(-2., -2., -8.), 4.), white.clone()),
+                Surface::sphere(Point3::new(0., 0., -5.), 0.5, white.clone()),
+                Surface::mesh(quad(Point3::new(-1.5, 0.5, -6.), 1.), red.clone()),
+                rotated.clone(),
+                Surface::mesh(quad(Point3::new(0.5, 0.5, -6.), 1.), red.clone()),
+            ];
+            let camera = Camera::new(
+                Point3::zero(),
+                Point3::new(0., 0., -1.),
+                Vec3::new(0., 1., 0.),
+                std::f32::consts::FRAC_PI_4,
+                16,
+                16,
+                1,
+            );
+            let lights = vec![
+                Light::Ambient {
+                    color: Color::new(1., 1., 1.),
+                    hemisphere: None,
+                },
+                Light::Point {
+                    color: Color::new(1., 1., 1.),
+                    position: Point3::new(1., 2., 0.),
+                    radius: 0.,
+                    samples: None,
+                },
+            ];
+            Scene::new(String::new(), Color::zero(), camera, lights, surfaces)
+        };
+        let original = scene();
+        let mut merged = scene();
+
+        assert_eq!(merged.merge_static_meshes(), (5, 3));
+        // the sphere stays in place between the merged meshes
+        assert!(merged.surfaces[1].world_triangles().is_none());
+        for (x, y) in (0..16).flat_map(|x| (0..16).map(move |y| (x, y))) {
+            let (a, b) = (original.trace_pixel(x, y), merged.trace_pixel(x, y));
+            assert!((a - b).length() < 1e-4, "pixel ({x}, {y}): {a:?} != {b:?}");
+        }
+
+        // surfaces seen only by the camera are merged among themselves and stay camera only
+        let camera_only = |indices: &[usize]| {
+            let mut scene = scene();
+            for i in indices {
+                scene.surfaces[*i].set_camera_only(true);
+            }
+            scene
+        };
+        assert_eq!(camera_only(&[2]).merge_static_meshes(), (5, 4));
+        let mut camera_only = camera_only(&[2, 4]);
+        assert_eq!(camera_only.merge_static_meshes(), (5, 3));
+        assert!(camera_only.surfaces[2].is_camera_only());
+        assert!(!camera_only.surfaces[0].is_camera_only());
+    }
+
+    #[test]
+    fn ray_differentials_match_plane_uv_derivatives() {
+        let (width, height, distance) = (9, 7, 3.);
+        let fov_x = std::f32::consts::FRAC_PI_4;
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            fov_x,
+            width,
+            height,
+            1,
+        );
+        let surfaces = vec![Surface::new(
+            Box::new(TexturedPlane { z: -distance }),
+            material(),
+        )];
+        let mut scene = Scene::new(String::new(), Color::zero(), camera, vec![], surfaces);
+        scene.enable_ray_differentials();
+
+        // u = x / 2 on the plane, and a pixel covers 2 * tan(fov) * distance / width in x
+        #[allow(clippy::cast_precision_loss)]
+        let dudx = 0.5 * 2. * fov_x.tan() * distance / width as f32;
+        for (u, v) in [(4, 3), (0, 0), (8, 6)] {
+            let ray = scene.camera().get_ray_through(u, v, &mut rand::rng());
+            let (_, mut hit) = scene.nearest_intersection(&ray, true).unwrap();
+            hit.set_footprint(0., &ray);
+            assert!((hit.footprint - dudx).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn total_color_skips_occluded_lights() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let surface = Surface::new(Box::new(CountingPlane { z: -1., hits }), material());
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let hit = surface.intersection(&ray).unwrap();
+        let lights = [
+            Light::Ambient {
+                color: Color::new(0.25, 0., 0.),
+                hemisphere: None,
+            },
+            Light::Ambient {
+                color: Color::new(0., 0.5, 0.),
+                hemisphere: None,
+            },
+        ];
+
+        let all = hit.get_total_color(&lights, |_, _| 1., &ray);
+        assert_eq!(all, Color::new(0.25, 0.5, 0.));
+
+        let green = Color::new(0., 0.5, 0.);
+        let occluded = hit.get_total_color(
+            &lights,
+            |light, _| {
+                if matches!(light, Light::Ambient { color, .. } if *color == green) {
+                    1.
+                } else {
+                    0.
+                }
+            },
+            &ray,
+        );
+        assert_eq!(occluded, green);
+
+        let none = hit.get_total_color(&lights, |_, _| 0., &ray);
+        assert_eq!(none, Color::zero());
+    }
+
+    #[test]
+    fn layers_recomposite_to_single_pass() {
+        let shiny = Material::new(
+            Texture::Color(Color::new(0.8, 0.3, 0.1)),
+            0.3,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 0.1,
+                kd: 0.8,
+                ks: 0.5,
+                exp: 20,
+            },
+        );
+        let mut hero = Surface::sphere(Point3::new(0.3, 0., -3.), 0.6, shiny);
+        hero.set_layer(Some("hero".to_string()));
+        let mut env = Surface::sphere(Point3::new(-0.5, 0.2, -4.5), 1.2, material());
+        env.set_layer(Some("env".to_string()));
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            16,
+            16,
+            2,
+        );
+        let lights = vec![
+            Light::Ambient {
+                color: Color::new(0.2, 0.2, 0.2),
+                hemisphere: None,
+            },
+            Light::Parallel {
+                color: Color::new(1., 1., 1.),
+                direction: Vec3::new(-1., -0.5, -1.),
+            },
+        ];
+        let background = Color::new(0.1, 0.2, 0.3);
+        let scene = Scene::new(String::new(), background, camera, lights, vec![hero, env]);
+        let layers = ["hero".to_string(), "env".to_string()];
+
+        let mut coverage = [0.; 2];
+        for v in 0..16 {
+            for u in 0..16 {
+                let full = scene.trace_pixel(u, v);
+                let split = scene.trace_pixel_layers(u, v, &layers);
+                let (hero, env) = (split[0], split[1]);
+                // hero over env over the background
+                let composite = hero.0 + (1. - hero.1) * (env.0 + (1. - env.1) * background);
+                for c in 0..3 {
+                    assert!((composite[c] - full[c]).abs() < 1e-4);
+                }
+                coverage[0] += hero.1;
+                coverage[1] += env.1;
+            }
+        }
+        // both layers are visible somewhere
+        assert!(coverage[0] > 0. && coverage[1] > 0.);
+    }
+
+    #[test]
+    fn mirror_reflects_secondary_background() {
+        let mirror = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            1.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 0.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            9,
+            9,
+            1,
+        );
+        let primary = Color::new(0., 1., 0.);
+        let secondary = Color::new(1., 0., 0.);
+        let mut scene = Scene::new(
+            String::new(),
+            primary,
+            camera,
+            vec![],
+            vec![Surface::sphere(Point3::new(0., 0., -3.), 1., mirror)],
+        );
+        // without a secondary background the mirror reflects the primary one
+        assert_eq!(scene.trace_pixel(4, 4), primary);
+
+        scene.set_secondary_background(Background::Solid(secondary));
+        assert_eq!(scene.trace_pixel(4, 4), secondary);
+        assert_eq!(scene.trace_pixel(0, 0), primary);
+        assert_eq!(scene.trace_pixel(8, 8), primary);
+    }
+
+    #[test]
+    fn area_lights_cast_soft_shadows() {
+        // a ball hangs between the floor and a light
+        let ball = Surface::sphere(Point3::new(0., 1., 0.), 0.5, material());
+        let camera = Camera::new(
+            Point3::new(0., 0., 5.),
+            Point3::zero(),
+            Vec3::new(0., 1., 0.),
+            1.,
+            1,
+            1,
+            1,
+        );
+        let scene = Scene::new(String::new(), Color::zero(), camera, vec![], vec![ball]);
+        let light = |radius| Light::Point {
+            color: Color::new(1., 1., 1.),
+            position: Point3::new(0., 3., 0.),
+            radius,
+            samples: None,
+        };
+        let visibility = |light: &Light, x: f32| {
+            let point = Point3::new(x, 0., 0.);
+            light.visibility(&point, 64, point_rotation(&point), None, |ray| {
+                scene.intersects_any(ray)
+            })
+        };
+
+        // the shadow of a point light ends at about x = 0.775
+        let (point, area) = (light(0.), light(1.));
+        assert!(visibility(&point, 0.7) < 1e-6 && visibility(&point, 0.85) > 1. - 1e-6);
+        assert!(visibility(&area, 0.) < 1e-6 && visibility(&area, 3.) > 1. - 1e-6);
+        let penumbra = visibility(&area, 0.775);
+        assert!(0.2 < penumbra && penumbra < 0.8, "{penumbra}");
+        // the penumbra brightens from the inside out
+        assert!(visibility(&area, 0.6) < penumbra && penumbra < visibility(&area, 0.95));
+    }
+
+    #[test]
+    fn glossy_reflections_average_their_rays() {
+        let glossy = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            1.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 0.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        )
+        .with_glossy_reflection(0.5, Some(8));
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            9,
+            9,
+            1,
+        );
+        let sky = Color::new(0.2, 0.4, 0.8);
+        let ball = Surface::sphere(Point3::new(0., 0., -3.), 1., glossy);
+        let mut scene = Scene::new(String::new(), sky, camera, vec![], vec![ball]);
+        // the rays scatter, but all of them see the same background
+        assert!((scene.trace_pixel(4, 4) - sky).length() < 1e-5);
+
+        let ray = scene.camera().get_ray_through(4, 4, &mut rand::rng());
+        let (_, hit) = scene.nearest_intersection(&ray, true).unwrap();
+        let mirror = *hit.reflected_ray(&ray).dir();
+        let rays: Vec<_> = hit.glossy_rays(&ray, 8).collect();
+        assert_eq!(rays.len(), 8);
+        assert!(rays.iter().all(|r| r.dir().dot(&hit.normal) > 0.));
+        assert!(rays.iter().any(|r| r.dir().dot(&mirror) < 0.99));
+
+        // features share the default among super-samples and are clamped to the budget
+        assert_eq!(scene.feature_samples(None, 256), FEATURE_RAYS_PER_PIXEL);
+        scene.add_samples(4);
+        assert_eq!(scene.feature_samples(None, 256), 4);
+        assert_eq!(scene.feature_samples(Some(64), 256), 64);
+        assert_eq!(scene.feature_samples(Some(64), 16), 16);
+        assert_eq!(scene.feature_samples(Some(64), 0), 1);
+    }
+
+    #[test]
+    fn bounce_limit_replaces_untraced_rays() {
+        let mirror = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            1.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 0.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let ambient = Color::new(0.2, 0.3, 0.4);
+        let secondary = Color::new(1., 0., 0.);
+        // without bounces the reflection of the mirror is never traced
+        let render = |at_limit| {
+            let mut camera = Camera::new(
+                Point3::zero(),
+                Point3::new(0., 0., -1.),
+                Vec3::new(0., 1., 0.),
+                std::f32::consts::FRAC_PI_4,
+                9,
+                9,
+                0,
+            );
+            camera.set_bounce_limit(at_limit);
+            let mut scene = Scene::new(
+                String::new(),
+                Color::new(0., 1., 0.),
+                camera,
+                vec![Light::Ambient {
+                    color: ambient,
+                    hemisphere: None,
+                }],
+                vec![Surface::sphere(
+                    Point3::new(0., 0., -3.),
+                    1.,
+                    mirror.clone(),
+                )],
+            );
+            scene.set_secondary_background(Background::Solid(secondary));
+            scene.trace_pixel(4, 4)
+        };
+        assert_eq!(render(BounceLimit::Black), Color::zero());
+        assert_eq!(render(BounceLimit::Background), secondary);
+        assert_eq!(render(BounceLimit::Ambient), ambient);
+    }
+
+    #[test]
+    fn auto_frame_fits_scene_into_image() {
+        let camera = Camera::new(
+            Point3::new(3., 1., 2.),
+            Point3::new(2., 0.5, 1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_6,
+            64,
+            32,
+            1,
+        );
+        let direction = camera.direction();
+        let mut scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![],
+            vec![
+                Surface::sphere(Point3::new(-10., 0., -40.), 2., material()),
+                Surface::sphere(Point3::new(25., 8., -60.), 5., material()),
+            ],
+        );
+        let (min, max) = scene.bounding_box().unwrap();
+        assert!(
+            (min - Point3::new(-12., -2., -65.)).length() < 0.1,
+            "{min:?}"
+        );
+        assert!(
+            (max - Point3::new(30., 13., -38.)).length() < 0.1,
+            "{max:?}"
+        );
+
+        assert!(scene.auto_frame());
+        let camera = scene.camera();
+        assert!((camera.direction() - direction).length() < 1e-5);
+        // the center of the image shows the center of the box
+        let (u, v) = camera.project(&(0.5 * (min + max))).unwrap();
+        assert!((u - 32.).abs() < 1e-2 && (v - 16.).abs() < 1e-2, "{u} {v}");
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            );
+            let (u, v) = camera.project(&corner).unwrap();
+            assert!(
+                (0. ..64.).contains(&u) && (0. ..32.).contains(&v),
+                "{u} {v}"
+            );
+        }
+
+        // projecting a point on a camera ray gives back the pixel of the ray
+        let ray = camera.get_ray_through(10, 20, &mut rand::rng());
+        let (u, v) = camera.project(&(*ray.orig() + 7. * *ray.dir())).unwrap();
+        assert!(
+            (u - 10.5).abs() < 1e-2 && (v - 20.5).abs() < 1e-2,
+            "{u} {v}"
+        );
+    }
+
+    #[test]
+    fn surfaces_outside_view_are_culled() {
+        let counting_sphere = |center, tests: &Arc<AtomicUsize>| {
+            Surface::new(
+                Box::new(CountingSphere {
+                    center,
+                    radius: 1.,
+                    tests: tests.clone(),
+                }),
+                material(),
+            )
+        };
+        let visible = Arc::new(AtomicUsize::new(0));
+        let hidden = Arc::new(AtomicUsize::new(0));
+        // the camera sees 45 degrees to each side, the second sphere lies 45 degrees further out
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            8,
+            8,
+            1,
+        );
+        let scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![],
+            vec![
+                counting_sphere(Point3::new(0., 0., -5.), &visible),
+                counting_sphere(Point3::new(5., 0., 0.), &hidden),
+            ],
+        );
+        assert_eq!(scene.visible, [true, false]);
+
+        for v in 0..8 {
+            for u in 0..8 {
+                let _ = scene.trace_pixel(u, v);
+            }
+        }
+        // rays that miss the bounding box of the visible sphere don't test it either
+        assert!((1..64).contains(&visible.load(Ordering::Relaxed)));
+        assert_eq!(hidden.load(Ordering::Relaxed), 0);
+
+        // secondary rays still see the culled sphere
+        let ray = Ray::new(Point3::zero(), Vec3::new(1., 0., 0.));
+        assert!(scene.nearest_intersection(&ray, false).is_some());
+        assert!(scene.nearest_intersection(&ray, true).is_none());
+    }
+
+    #[test]
+    fn light_gizmos_are_only_seen_by_the_camera() {
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            8,
+            8,
+            1,
+        );
+        let light = Light::Point {
+            color: Color::new(1., 1., 1.),
+            position: Point3::new(0., 0., -5.),
+            radius: 0.,
+            samples: None,
+        };
+        let floor = Surface::sphere(Point3::new(0., -10., -5.), 9., material());
+        let mut scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![light],
+            vec![floor],
+        );
+        assert_eq!(scene.add_light_gizmos(), 1);
+        assert_eq!(scene.visible.len(), 2);
+
+        // the camera sees the gizmo in the color of the light
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let (index, _) = scene.nearest_intersection(&ray, true).unwrap();
+        assert_eq!(index, 1);
+        let (color, _) = scene.trace_camera_ray(&ray);
+        assert!((color.emission - Color::new(1., 1., 1.)).length() < 1e-6);
+
+        // it casts no shadow on the floor below and doesn't show up in reflections
+        let up = Ray::new(Point3::new(0., -1., -5.), Vec3::new(0., 1., 0.));
+        assert!(!scene.intersects_any(&up));
+        assert!(scene.nearest_intersection(&up, false).is_none());
+    }
+
+    #[test]
+    fn frames_render_out_of_order() {
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            8,
+            8,
+            1,
+        );
+        let mut sphere = Surface::sphere(Point3::new(-1., 0., -4.), 0.5, material());
+        sphere.set_sphere_end((Point3::new(1., 0.5, -4.), 1.));
+        let mut scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![Light::Ambient {
+                color: Color::new(1., 1., 1.),
+                hemisphere: None,
+            }],
+            vec![sphere],
+        );
+        scene.set_animation(4, Playback::new(1));
+        let render = |scene: &Scene| -> Vec<Color> {
+            (0..8)
+                .flat_map(|v| (0..8).map(move |u| (u, v)))
+                .map(|(u, v)| scene.trace_pixel(u, v))
+                .collect()
+        };
+
+        let mut in_order = vec![render(&scene)];
+        for _ in 1..4 {
+            scene.next_frame();
+            in_order.push(render(&scene));
+        }
+        assert_ne!(in_order[0], in_order[3]);
+
+        for frame in [3, 0, 2, 1, 3] {
+            scene.set_frame(frame);
+            assert_eq!(render(&scene), in_order[frame], "frame {frame} differs");
+        }
+    }
+
+    #[test]
+    fn split_components_add_up_to_the_pixel() {
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            16,
+            8,
+            1,
+        );
+        let phong = |reflectance, transmittance| {
+            Material::new(
+                Texture::Color(Color::new(0.8, 0.5, 0.2)),
+                reflectance,
+                transmittance,
+                1.5,
+                ShadingModel::Phong {
+                    ka: 0.2,
+                    kd: 0.8,
+                    ks: 0.8,
+                    exp: 20,
+                },
+            )
+        };
+        let surfaces = vec![
+            Surface::sphere(Point3::new(-1.6, 0., -4.), 0.7, phong(0., 0.)),
+            Surface::sphere(Point3::new(0., 0., -4.), 0.7, phong(0.8, 0.)),
+            Surface::sphere(Point3::new(1.6, 0., -4.), 0.7, phong(0., 0.8)),
+        ];
+        let lights = vec![
+            Light::Ambient {
+                color: Color::new(0.5, 0.5, 0.5),
+                hemisphere: None,
+            },
+            Light::Point {
+                color: Color::new(1., 1., 1.),
+                position: Point3::new(0.5, 0.5, 0.),
+                radius: 0.,
+                samples: None,
+            },
+        ];
+        let mut scene = Scene::new(
+            String::new(),
+            Color::new(0.2, 0.3, 0.9),
+            camera,
+            lights,
+            surfaces,
+        );
+        scene.add_samples(4);
+        scene.set_seed(3);
+
+        let mut seen = RadianceSplit::zero();
+        for (u, v) in (0..16).flat_map(|u| (0..8).map(move |v| (u, v))) {
+            let split = scene.trace_pixel_split(u, v);
+            let color = scene.trace_pixel(u, v);
+            assert!((split.total() - color).length() < 1e-5, "{u}, {v}");
+            seen += split;
+        }
+        for (name, component) in RadianceSplit::NAMES.iter().zip(seen.components()) {
+            assert!(component.length() > 0.1, "{name} is empty");
+        }
+
+        // the corners only show the background
+        let corner = scene.trace_pixel_split(0, 0);
+        assert_eq!(corner.emission, Color::new(0.2, 0.3, 0.9));
+        assert_eq!(corner.total(), corner.emission);
+        assert_eq!(
+            corner.component("emission"),
+            Some(corner.emission),
+            "{corner:?}"
+        );
+        assert!(corner.component("depth").is_none());
+    }
+
+    #[test]
+    fn waves_ripple_between_frames() {
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., -0.5, -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            4,
+            4,
+            1,
+        );
+        let mut water = Surface::quad(
+            Point3::new(-5., -1., 0.),
+            Vec3::new(10., 0., 0.),
+            Vec3::new(0., 0., -10.),
+            material(),
+        );
+        water.set_quad_waves(Waves {
+            amplitude: 0.02,
+            frequency: 2.,
+            speed: 0.5,
+        });
+        let mut scene = Scene::new(String::new(), Color::zero(), camera, vec![], vec![water]);
+        scene.set_animation(48, Playback::new(24));
+        assert!(scene.is_animated());
+        assert!((scene.time_at_frame(12) - 0.5).abs() < 1e-6);
+
+        let ray = Ray::new(Point3::zero(), Vec3::new(0.1, -0.5, -1.));
+        let normal = |scene: &Scene| scene.closest_intersection(&ray).unwrap().normal;
+        let first = normal(&scene);
+        scene.set_frame(12);
+        let later = normal(&scene);
+        assert!(first[1] > 0.5 && later[1] > 0.5);
+        assert!((first - later).length() > 1e-3);
+        scene.set_frame(0);
+        assert!((normal(&scene) - first).length() < 1e-6);
+    }
+
+    #[test]
+    fn small_object_lands_in_expected_pixel() {
+        let fov_t = std::f32::consts::FRAC_PI_8.tan();
+        for (width, height, column, row) in
+            [(9, 9, 4, 4), (16, 8, 3, 6), (7, 12, 6, 0), (1, 1, 0, 0)]
+        {
+            let camera = Camera::new(
+                Point3::zero(),
+                Point3::new(0., 0., -1.),
+                Vec3::new(0., 1., 0.),
+                std::f32::consts::FRAC_PI_8,
+                width,
+                height,
+                1,
+            );
+            // a quad over the middle of the pixel in `column` and `row` (counted from the top), at
+            // distance 2 from the camera
+            #[allow(clippy::cast_precision_loss)]
+            let (w, h) = (width as f32, height as f32);
+            #[allow(clippy::cast_precision_loss)]
+            let v = (height - 1 - row) as f32;
+            #[allow(clippy::cast_precision_loss)]
+            let u = column as f32;
+            let image_x = |u: f32| 2. * ((2. * u / w) - 1.) * fov_t;
+            let image_y = |v: f32| 2. * ((2. * v / h) - 1.) * fov_t * h / w;
+            let corner = Point3::new(image_x(u + 0.1), image_y(v + 0.1), -2.);
+            let quad = Surface::quad(
+                corner,
+                Vec3::new(image_x(u + 0.9) - corner[0], 0., 0.),
+                Vec3::new(0., image_y(v + 0.9) - corner[1], 0.),
+                material(),
+            );
+            let light = Light::Ambient {
+                color: Color::new(1., 1., 1.),
+                hemisphere: None,
+            };
+            let mut scene = Scene::new(
+                String::new(),
+                Color::zero(),
+                camera,
+                vec![light],
+                vec![quad],
+            );
+            scene.set_seed(7);
+            // without super-sampling the ray goes through the center of the pixel, which the quad
+            // covers. Super-samples spread over the whole pixel, so only some of them hit the quad
+            for samples in [0, 8] {
+                scene.add_samples(samples);
+                let (img, stats) = scene.render_with_stats();
+                assert_eq!(stats.geometry_pixels, 1, "{width}x{height}");
+                for (x, y) in (0..width).flat_map(|x| (0..height).map(move |y| (x, y))) {
+                    #[allow(clippy::cast_precision_loss)]
+                    let pixel = img.get_pixel(0, (x as f32 + 0.5) / w, (y as f32 + 0.5) / h);
+                    assert_eq!(
+                        pixel != [0; 3],
+                        (x, y) == (column, row),
+                        "{width}x{height}, {samples} samples: pixel {x}, {y}"
+                    );
+                }
+            }
+            assert_eq!(Camera::row_from_image(row, height), height - 1 - row);
+        }
+    }
+
+    #[test]
+    fn render_stats_count_background_pixels() {
+        let camera = |lookat: Point3| {
+            Camera::new(
+                Point3::zero(),
+                lookat,
+                Vec3::new(0., 1., 0.),
+                std::f32::consts::FRAC_PI_4,
+                8,
+                8,
+                1,
+            )
+        };
+        // the sphere surrounds the camera, so it fills the whole image
+        let sphere = || Surface::sphere(Point3::new(0., 0., -1.), 4., material());
+        let mut scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera(Point3::new(0., 0., -1.)),
+            vec![],
+            vec![sphere()],
+        );
+        scene.add_samples(4);
+        let (img, stats) = scene.render_with_stats();
+        assert_eq!(img.dimensions(), (8, 8));
+        assert_eq!(
+            stats,
+            RenderStats {
+                background_pixels: 0,
+                geometry_pixels: 64
+            }
+        );
+        assert!(stats.warning().is_none());
+
+        // a small sphere behind the camera
+        let behind = Surface::sphere(Point3::new(0., 0., 5.), 1., material());
+        let scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera(Point3::new(0., 0., -1.)),
+            vec![],
+            vec![behind],
+        );
+        let (_, stats) = scene.render_with_stats();
+        assert_eq!(stats.geometry_pixels, 0);
+        assert_eq!(stats.background_pixels, 64);
+        assert!(stats.warning().unwrap().contains("100.0%"));
+        // the counters start over for every render
+        assert_eq!(scene.render_with_stats().1, stats);
+    }
+
+    #[test]
+    fn seeded_render_is_independent_of_thread_count() {
+        let mut scene = crate::input::builtin_scene("spheres", (48, 32)).unwrap();
+        scene.set_seed(7);
+        scene.add_samples(9);
+        let render = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let (img, _) = pool.install(|| scene.render_with_stats());
+            let mut path = std::env::temp_dir().join(format!(
+                "rt_reproducible_{}_{threads}.png",
+                std::process::id()
+            ));
+            img.save_png(&mut path).unwrap();
+            let bytes = std::fs::read(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            bytes
+        };
+
+        let single = render(1);
+        assert_eq!(single, render(4));
+        assert_eq!(single, render(7));
+    }
+
+    #[test]
+    fn sample_ranges_add_up_to_the_full_pixel() {
+        let mut scene = crate::input::builtin_scene("spheres", (16, 12)).unwrap();
+        scene.set_seed(3);
+        scene.add_samples(10);
+        for (u, v) in [(8, 6), (2, 9), (15, 0)] {
+            let full = scene.trace_pixel(u, v);
+            let passes = [0..4, 4..8, 8..10]
+                .into_iter()
+                .map(|samples| scene.trace_pixel_samples(u, v, samples))
+                .fold(Color::zero(), |acc, sum| acc + sum);
+            assert!((passes / 10. - full).length() < 1e-5, "{u}, {v}");
+        }
+    }
+
+    #[test]
+    fn reflections_see_moving_surfaces_at_the_time_of_the_ray() {
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            8,
+            8,
+            1,
+        );
+        let mirror = Surface::quad(
+            Point3::new(-5., -5., -5.),
+            Vec3::new(10., 0., 0.),
+            Vec3::new(0., 10., 0.),
+            material(),
+        );
+        // moves from the side into the reflection of the camera, behind the camera
+        let mut moving = Surface::sphere(Point3::new(10., 0., 5.), 1., material());
+        moving.set_sphere_end((Point3::new(0., 0., 5.), 1.));
+        let mut scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![],
+            vec![mirror, moving],
+        );
+        scene.set_animation(3, Playback::new(1));
+        scene.set_frame(0);
+
+        let reflect = |time: Option<f32>| {
+            let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.)).with_time(time);
+            let (index, mirror) = scene.nearest_intersection(&ray, false).unwrap();
+            assert_eq!(index, 0);
+            let reflected = mirror.reflected_ray(&ray);
+            assert_eq!(reflected.time(), time);
+            scene.closest_intersection(&reflected)
+        };
+        // at the end of the animation the sphere lies on the reflected ray, although the scene
+        // is still in its first frame
+        let hit = reflect(Some(1.)).unwrap();
+        assert_eq!(hit.surface_index, 1);
+        assert!(
+            (hit.point - Point3::new(0., 0., 4.)).length() < 1e-4,
+            "{:?}",
+            hit.point
+        );
+        let hit = reflect(Some(0.95)).unwrap();
+        let center = lerp(Point3::new(10., 0., 5.), Point3::new(0., 0., 5.), 0.95);
+        assert!(((hit.point - center).length() - 1.).abs() < 1e-4);
+        // rays without a time see the sphere of the current frame, which is off to the side
+        assert!(reflect(None).is_none());
+        assert!(reflect(Some(0.)).is_none());
+
+        // camera rays and the rays they spawn carry the time of the frame
+        scene.set_frame(1);
+        assert_eq!(scene.sample_ray(4, 4, 0).time(), Some(0.5));
+        assert_eq!(scene.camera_ray(4, 4).time(), Some(0.5));
+        scene.set_debug_time(true);
+        assert_eq!(scene.trace_pixel(4, 4), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn motion_vectors_follow_moving_sphere() {
+        let fov_t = std::f32::consts::FRAC_PI_8.tan();
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_8,
+            32,
+            24,
+            1,
+        );
+        // moves one unit to the right per frame
+        let mut moving = Surface::sphere(Point3::new(-1., 0., -5.), 1., material());
+        moving.set_sphere_end((Point3::new(1., 0., -5.), 1.));
+        let fixed = Surface::sphere(Point3::new(0., -2., -8.), 0.5, material());
+        let mut scene = Scene::new(
+            String::new(),
+            Color::zero(),
+            camera,
+            vec![],
+            vec![moving, fixed],
+        );
+        scene.set_animation(3, Playback::new(10));
+
+        let (mut moving_pixels, mut fixed_pixels) = (0, 0);
+        for (u, v) in (0..32).flat_map(|u| (0..24).map(move |v| (u, v))) {
+            let ray = scene.camera().get_ray_through(u, v, &mut rand::rng());
+            let (du, dv) = scene.motion_vector(u, v);
+            match scene.closest_intersection(&ray) {
+                Some(hit) if hit.surface_index == 0 => {
+                    // a point at depth z that moves by 1 moves by 1 / z on the image plane
+                    let expected = 1. / -hit.point[2] / fov_t * 32. / 2.;
+                    assert!((du - expected).abs() < 1e-3, "{u}, {v}: {du} {expected}");
+                    assert!(dv.abs() < 1e-3);
+                    moving_pixels += 1;
+                }
+                Some(_) => {
+                    assert!(du.abs() < 1e-5 && dv.abs() < 1e-5);
+                    fixed_pixels += 1;
+                }
+                None => assert_eq!((du, dv), (0., 0.)),
+            }
         }
-        let ray = self.camera.get_ray_through(u, v);
+        assert!(moving_pixels > 10 && fixed_pixels > 0);
 
-        self.recursive_trace(&ray, self.camera.get_max_bounces())
+        // the last frame has no next frame
+        scene.set_frame(2);
+        assert_eq!(scene.motion_vector(26, 12), (0., 0.));
     }
 }
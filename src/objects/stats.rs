@@ -0,0 +1,132 @@
+//! intersection statistics
+//! Counts the rays that are traced and the bounding box and triangle tests they cause. The
+//! counters only exist with the `stats` feature, otherwise recording them compiles to nothing
+
+use std::fmt::Display;
+
+#[cfg(feature = "stats")]
+mod counters {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    pub static RAYS: AtomicU64 = AtomicU64::new(0);
+    pub static BOX_TESTS: AtomicU64 = AtomicU64::new(0);
+    pub static TRIANGLE_TESTS: AtomicU64 = AtomicU64::new(0);
+
+    #[inline]
+    pub fn add(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn load(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// Count a ray that is intersected with the scene
+#[inline]
+pub(crate) fn count_ray() {
+    #[cfg(feature = "stats")]
+    counters::add(&counters::RAYS);
+}
+
+/// Count a test of a ray against the bounding box of a mesh
+#[inline]
+pub(crate) fn count_box_test() {
+    #[cfg(feature = "stats")]
+    counters::add(&counters::BOX_TESTS);
+}
+
+/// Count a test of a ray against a single triangle
+#[inline]
+pub(crate) fn count_triangle_test() {
+    #[cfg(feature = "stats")]
+    counters::add(&counters::TRIANGLE_TESTS);
+}
+
+/// Totals of the intersection counters since the program started
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntersectionStats {
+    pub rays: u64,
+    pub box_tests: u64,
+    pub triangle_tests: u64,
+}
+
+impl IntersectionStats {
+    /// Read the current counters
+    /// Returns `None` if the program was built without the `stats` feature
+    #[must_use]
+    pub fn collect() -> Option<IntersectionStats> {
+        #[cfg(feature = "stats")]
+        {
+            use counters::{load, BOX_TESTS, RAYS, TRIANGLE_TESTS};
+            Some(IntersectionStats {
+                rays: load(&RAYS),
+                box_tests: load(&BOX_TESTS),
+                triangle_tests: load(&TRIANGLE_TESTS),
+            })
+        }
+        #[cfg(not(feature = "stats"))]
+        None
+    }
+
+    /// Return the counts that were added since `earlier` was collected
+    #[must_use]
+    pub fn since(&self, earlier: &IntersectionStats) -> IntersectionStats {
+        IntersectionStats {
+            rays: self.rays - earlier.rays,
+            box_tests: self.box_tests - earlier.box_tests,
+            triangle_tests: self.triangle_tests - earlier.triangle_tests,
+        }
+    }
+
+    /// Return the average number of `count` per ray
+    #[allow(clippy::cast_precision_loss)]
+    fn per_ray(&self, count: u64) -> f64 {
+        if self.rays == 0 {
+            0.
+        } else {
+            count as f64 / self.rays as f64
+        }
+    }
+}
+
+impl Display for IntersectionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Intersection statistics:")?;
+        writeln!(f, "    rays:           {}", self.rays)?;
+        writeln!(
+            f,
+            "    box tests:      {} ({:.2} per ray)",
+            self.box_tests,
+            self.per_ray(self.box_tests)
+        )?;
+        write!(
+            f,
+            "    triangle tests: {} ({:.2} per ray)",
+            self.triangle_tests,
+            self.per_ray(self.triangle_tests)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn averages_per_ray() {
+        let stats = IntersectionStats {
+            rays: 4,
+            box_tests: 2,
+            triangle_tests: 10,
+        };
+        let text = stats.to_string();
+        assert!(text.contains("2 (0.50 per ray)"), "{text}");
+        assert!(text.contains("10 (2.50 per ray)"), "{text}");
+        assert!(stats.since(&stats).to_string().contains("0 (0.00 per ray)"));
+        assert_eq!(
+            IntersectionStats::collect().is_some(),
+            cfg!(feature = "stats")
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use crate::math::{Point3, Ray, Vec3};
+
+/// Plane that cuts away the part of a surface on its positive side, for cutaway views
+/// Clip planes are given in world space, so they stay in place when the surface is transformed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipPlane {
+    point: Point3,
+    /// unit normal, pointing to the side that is cut away
+    normal: Vec3,
+}
+
+impl ClipPlane {
+    /// Create a clip plane through `point`, which cuts away everything on the side `normal`
+    /// points to
+    /// The normal must not be zero
+    #[must_use]
+    pub fn new(point: Point3, normal: Vec3) -> ClipPlane {
+        ClipPlane {
+            point,
+            normal: Vec3::normal(&normal),
+        }
+    }
+
+    /// Return the unit normal of the plane, pointing to the side that is cut away
+    #[must_use]
+    pub fn normal(&self) -> Vec3 {
+        self.normal
+    }
+
+    /// Return true if `point` lies on the side of the plane that is cut away
+    #[must_use]
+    pub fn clips(&self, point: &Point3) -> bool {
+        (*point - self.point).dot(&self.normal) > 0.
+    }
+
+    /// Return the `t` value where the line of `ray` crosses the plane and whether it leaves the
+    /// kept side there
+    /// Returns `None` if the ray runs parallel to the plane
+    pub(super) fn crossing(&self, ray: &Ray) -> Option<(f32, bool)> {
+        let speed = self.normal.dot(ray.dir());
+        if speed == 0. {
+            return None;
+        }
+        let t = (self.point - *ray.orig()).dot(&self.normal) / speed;
+        Some((t, speed > 0.))
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::math::{Point3, Vec3};
+
+use super::mesh::BoundingBox;
+use super::{Texel, Triangle};
+
+/// Number of grid sizes that are tried while searching for the target triangle count
+const SEARCH_STEPS: usize = 32;
+
+/// Largest number of cells along the longest side of the mesh
+const MAX_RESOLUTION: f32 = 1_048_576.;
+
+/// Triangles that are left after clustering, as the index of the original triangle and the cells
+/// of its points
+type Survivors = Vec<(usize, [u64; 3])>;
+
+/// Uniform grid of cubic cells over the bounding box of a mesh
+struct Grid {
+    min: Point3,
+    cell_size: f32,
+    dims: [u64; 3],
+}
+
+impl Grid {
+    /// Create a grid with `resolution` cells along the longest side of the box
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn new(bounds: &BoundingBox, resolution: f32) -> Grid {
+        let (min, max) = bounds.corners();
+        let extent = max - min;
+        let longest = extent[0].max(extent[1]).max(extent[2]);
+        let cell_size = if longest > 0. {
+            longest / resolution
+        } else {
+            1.
+        };
+        let dims = [0, 1, 2].map(|i| (extent[i] / cell_size) as u64 + 1);
+        Grid {
+            min,
+            cell_size,
+            dims,
+        }
+    }
+
+    /// Return the index of the cell that contains `point`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn cell(&self, point: &Point3) -> u64 {
+        let [x, y, z] = [0, 1, 2]
+            .map(|i| (((point[i] - self.min[i]) / self.cell_size) as u64).min(self.dims[i] - 1));
+        x + self.dims[0] * (y + self.dims[1] * z)
+    }
+
+    /// Return the triangles that survive clustering
+    /// Triangles with two points in the same cell collapse, of triangles that connect the same
+    /// cells only the first one is kept
+    fn cluster(&self, triangles: &[Triangle]) -> Survivors {
+        let mut seen = HashSet::new();
+        triangles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, tri)| {
+                let cells = tri.points().map(|p| self.cell(&p));
+                let mut key = cells;
+                key.sort_unstable();
+                let distinct = key[0] != key[1] && key[1] != key[2];
+                (distinct && seen.insert(key)).then_some((i, cells))
+            })
+            .collect()
+    }
+}
+
+/// Sum of the vertex attributes of all points inside a cell
+#[derive(Default)]
+struct Cluster {
+    count: f32,
+    position: [f32; 3],
+    normal: [f32; 3],
+    texel: [f32; 2],
+    min: [f32; 3],
+    max: [f32; 3],
+}
+
+impl Cluster {
+    fn add(&mut self, point: &Point3, normal: &Vec3, texel: Texel) {
+        if self.count == 0. {
+            self.min = [point[0], point[1], point[2]];
+            self.max = self.min;
+        }
+        self.count += 1.;
+        for i in 0..3 {
+            self.position[i] += point[i];
+            self.normal[i] += normal[i];
+            self.min[i] = self.min[i].min(point[i]);
+            self.max[i] = self.max[i].max(point[i]);
+        }
+        self.texel[0] += texel.0;
+        self.texel[1] += texel.1;
+    }
+
+    /// Return the position, normal and texel that represent all points of the cell
+    /// The position is the average of the points, except on axes where the cell contains the
+    /// extreme points of the whole mesh. Those are kept, so the bounding box doesn't shrink
+    fn representative(&self, bounds: &BoundingBox, fallback: Vec3) -> (Point3, Vec3, Texel) {
+        let (min, max) = bounds.corners();
+        let [x, y, z] = [0, 1, 2].map(|i| {
+            if self.min[i] <= min[i] {
+                min[i]
+            } else if self.max[i] >= max[i] {
+                max[i]
+            } else {
+                self.position[i] / self.count
+            }
+        });
+        let normal = Vec3::new(self.normal[0], self.normal[1], self.normal[2]);
+        let normal = if normal.length_squared() > 0. {
+            Vec3::normal(&normal)
+        } else {
+            fallback
+        };
+        let texel = (self.texel[0] / self.count, self.texel[1] / self.count);
+        (Point3::new(x, y, z), normal, texel)
+    }
+}
+
+/// Reduce a triangle mesh to about `target` triangles with vertex clustering
+/// The bounding box of the mesh is divided into a grid of cubic cells and all points in a cell are
+/// merged into one. The cell size is searched so that the number of remaining triangles is as
+/// close to `target` as possible. Vertex normals and texture coordinates are averaged per cell,
+/// so texture seams get blurred. The result is meant for previews, not for final renders
+/// Meshes with at most `target` triangles are returned unchanged
+#[must_use]
+pub fn decimate(triangles: &[Triangle], target: usize) -> Vec<Triangle> {
+    if triangles.len() <= target {
+        return triangles.to_vec();
+    }
+    let bounds = BoundingBox::from(
+        &triangles
+            .iter()
+            .flat_map(|tri| *tri.points())
+            .collect::<Vec<_>>(),
+    );
+
+    let (grid, survivors) = search_grid(triangles, &bounds, target);
+
+    let mut clusters: HashMap<u64, Cluster> = HashMap::new();
+    for tri in triangles {
+        for ((point, normal), texel) in tri.points().iter().zip(tri.normals()).zip(tri.texcoords())
+        {
+            clusters
+                .entry(grid.cell(point))
+                .or_default()
+                .add(point, normal, *texel);
+        }
+    }
+
+    survivors
+        .into_iter()
+        .map(|(i, cells)| {
+            let original = &triangles[i];
+            let vertices =
+                cells.map(|c| clusters[&c].representative(&bounds, original.flat_normal()));
+            let mut triangle = Triangle::new(
+                vertices.map(|v| v.0),
+                vertices.map(|v| v.1),
+                vertices.map(|v| v.2),
+            );
+            triangle.set_shading(original.shading());
+            triangle
+        })
+        .collect()
+}
+
+/// Search the grid whose clustering keeps the number of triangles closest to `target`
+/// The number of triangles grows with the resolution of the grid, so the resolution is found by
+/// doubling it until there are enough triangles and then bisecting, until the count is within
+/// one percent of the target
+fn search_grid(triangles: &[Triangle], bounds: &BoundingBox, target: usize) -> (Grid, Survivors) {
+    let mut best: Option<(Grid, Survivors)> = None;
+    let mut consider = |resolution: f32| {
+        let grid = Grid::new(bounds, resolution);
+        let survivors = grid.cluster(triangles);
+        let count = survivors.len();
+        let better = best
+            .as_ref()
+            .is_none_or(|(_, b)| count.abs_diff(target) < b.len().abs_diff(target));
+        if better {
+            best = Some((grid, survivors));
+        }
+        count
+    };
+
+    let mut low = 1.;
+    let mut high = 2.;
+    while high < MAX_RESOLUTION && consider(high) < target {
+        low = high;
+        high *= 2.;
+    }
+    for _ in 0..SEARCH_STEPS {
+        let mid = f32::midpoint(low, high);
+        let count = consider(mid);
+        if count.abs_diff(target) <= target / 100 {
+            break;
+        }
+        if count < target {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    best.expect("atleast one grid should have been tried")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sphere of `radius` around `center` with `rings` x `segments` quads split into triangles
+    #[allow(clippy::cast_precision_loss)]
+    fn uv_sphere(center: Point3, radius: f32, rings: usize, segments: usize) -> Vec<Triangle> {
+        let vertex = |ring: usize, segment: usize| {
+            let theta = std::f32::consts::PI * ring as f32 / rings as f32;
+            let phi = std::f32::consts::TAU * segment as f32 / segments as f32;
+            let normal = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            let texel = (segment as f32 / segments as f32, ring as f32 / rings as f32);
+            (center + normal * radius, normal, texel)
+        };
+        let mut triangles = Vec::new();
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let a = vertex(ring, segment);
+                let b = vertex(ring + 1, segment);
+                let c = vertex(ring + 1, segment + 1);
+                let d = vertex(ring, segment + 1);
+                for [p, q, r] in [[a, b, c], [a, c, d]] {
+                    if (q.0 - p.0).cross(&(r.0 - p.0)).length() > 1e-9 {
+                        triangles.push(Triangle::new(
+                            [p.0, q.0, r.0],
+                            [p.1, q.1, r.1],
+                            [p.2, q.2, r.2],
+                        ));
+                    }
+                }
+            }
+        }
+        triangles
+    }
+
+    fn bounds(triangles: &[Triangle]) -> (Point3, Point3) {
+        BoundingBox::from(
+            &triangles
+                .iter()
+                .flat_map(|t| *t.points())
+                .collect::<Vec<_>>(),
+        )
+        .corners()
+    }
+
+    #[test]
+    fn decimated_sphere_keeps_its_bounds() {
+        let sphere = uv_sphere(Point3::new(1., -2., 3.), 2., 120, 240);
+        let (min, max) = bounds(&sphere);
+        for target in [500, 2_000, 10_000] {
+            let decimated = decimate(&sphere, target);
+            #[allow(clippy::cast_precision_loss)]
+            let error = decimated.len().abs_diff(target) as f32 / target as f32;
+            assert!(
+                error <= 0.1,
+                "{} triangles for target {target}",
+                decimated.len()
+            );
+
+            let (dmin, dmax) = bounds(&decimated);
+            for i in 0..3 {
+                assert!((dmin[i] - min[i]).abs() < 1e-5, "{dmin:?} != {min:?}");
+                assert!((dmax[i] - max[i]).abs() < 1e-5, "{dmax:?} != {max:?}");
+            }
+            // the normals still point away from the center
+            for tri in &decimated {
+                let centroid = (tri.points()[0] + tri.points()[1] + tri.points()[2]) / 3.;
+                assert!(tri.normals()[0].dot(&(centroid - Point3::new(1., -2., 3.))) > 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn decimated_plane_stays_flat() {
+        let grid = 100;
+        let mut plane = Vec::new();
+        for y in 0..grid {
+            for x in 0..grid {
+                #[allow(clippy::cast_precision_loss)]
+                let p = |dx: usize, dy: usize| Point3::new((x + dx) as f32, (y + dy) as f32, 0.);
+                let n = [Vec3::new(0., 0., 1.); 3];
+                plane.push(Triangle::new([p(0, 0), p(1, 0), p(1, 1)], n, [(0., 0.); 3]));
+                plane.push(Triangle::new([p(0, 0), p(1, 1), p(0, 1)], n, [(0., 0.); 3]));
+            }
+        }
+
+        let decimated = decimate(&plane, 2_000);
+        assert!(
+            decimated.len().abs_diff(2_000) <= 200,
+            "{}",
+            decimated.len()
+        );
+        assert_eq!(bounds(&decimated), bounds(&plane));
+        assert!(decimated
+            .iter()
+            .all(|t| t.points().iter().all(|p| p[2] == 0.) && t.flat_normal()[2] > 0.99));
+    }
+
+    #[test]
+    fn small_meshes_are_unchanged() {
+        let sphere = uv_sphere(Point3::zero(), 1., 8, 16);
+        assert_eq!(decimate(&sphere, sphere.len()), sphere);
+    }
+}
@@ -0,0 +1,126 @@
+use crate::math::{Point3, Ray, Vec3};
+
+use super::{Texel, Traceable};
+
+/// Rays with a smaller cosine to the plane of a planar primitive are treated as parallel to it
+pub(super) const PARALLEL_EPS: f32 = 1e-6;
+
+/// Return the `t` value where the ray hits the plane through `point` with the normal `normal`
+/// Rays parallel to the plane never hit it, even if they lie inside of it
+pub(super) fn plane_intersection(with: &Ray, point: &Point3, normal: &Vec3) -> Option<f32> {
+    let denom = normal.dot(with.dir());
+    if denom.abs() <= PARALLEL_EPS * with.dir().length() * normal.length() {
+        return None;
+    }
+    let t = normal.dot(&(*point - *with.orig())) / denom;
+    with.t_in_range(t).then_some(t)
+}
+
+/// struct to represent a flat disc in 3D-Space
+/// The front side of the disc faces in the direction of its normal
+#[derive(Clone, Debug)]
+pub(super) struct Disc {
+    center: Point3,
+    normal: Vec3,
+    radius: f32,
+    /// axes in the plane of the disc along which the texture is mapped
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Disc {
+    /// Create a new disc
+    /// The normal has to be non-zero and the radius positive
+    pub fn new(center: Point3, normal: Vec3, radius: f32) -> Disc {
+        let normal = Vec3::normal(&normal);
+        // use the axis least aligned with the normal to build the tangent frame
+        let helper = if normal[0].abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let tangent = Vec3::normal(&helper.cross(&normal));
+        let bitangent = normal.cross(&tangent);
+        Disc {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+        }
+    }
+
+    /// Compute the texel of the point `p` in the plane of the disc
+    /// The texture is mapped onto the square around the disc, so its corners are cut off
+    fn get_texel_at(&self, p: &Point3) -> Texel {
+        let d = *p - self.center;
+        let u = 0.5 + d.dot(&self.tangent) / (2. * self.radius);
+        let v = 0.5 - d.dot(&self.bitangent) / (2. * self.radius);
+        (u, v)
+    }
+}
+
+impl Traceable for Disc {
+    fn kind(&self) -> &'static str {
+        "disc"
+    }
+
+    /// The extent of the disc along an axis is the radius times the sine of the angle between the
+    /// axis and the normal
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        let extent = Vec3::new(
+            self.radius * (1. - self.normal[0] * self.normal[0]).max(0.).sqrt(),
+            self.radius * (1. - self.normal[1] * self.normal[1]).max(0.).sqrt(),
+            self.radius * (1. - self.normal[2] * self.normal[2]).max(0.).sqrt(),
+        );
+        Some((self.center - extent, self.center + extent))
+    }
+
+    fn texel_density(&self) -> f32 {
+        1. / (2. * self.radius)
+    }
+
+    fn has_intersection(&self, with: &Ray) -> bool {
+        self.intersection(with).is_some()
+    }
+
+    /// Calculates the intersection of the disc and the `with` Ray if present
+    /// Points exactly on the rim count as hits
+    fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+        let t = plane_intersection(with, &self.center, &self.normal)?;
+        let point = *with.orig() + t * *with.dir();
+        if (point - self.center).length_squared() > self.radius * self.radius {
+            return None;
+        }
+        Some((t, self.normal, self.get_texel_at(&point)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disc_intersection() {
+        let disc = Disc::new(Point3::new(0., -1., 0.), Vec3::new(0., 2., 0.), 1.);
+        let ray = Ray::new(Point3::new(0.5, 1., 0.), Vec3::new(0., -1., 0.));
+        let (t, normal, (u, v)) = disc.intersection(&ray).unwrap();
+        assert!((t - 2.).abs() < 1e-5);
+        assert!((normal - Vec3::new(0., 1., 0.)).length() < 1e-5);
+        assert!((0. ..=1.).contains(&u) && (0. ..=1.).contains(&v));
+
+        // the rim belongs to the disc
+        let rim = Ray::new(Point3::new(1., 1., 0.), Vec3::new(0., -1., 0.));
+        assert!(disc.has_intersection(&rim));
+        let outside = Ray::new(Point3::new(1.001, 1., 0.), Vec3::new(0., -1., 0.));
+        assert!(!disc.has_intersection(&outside));
+
+        // rays parallel to the disc miss it, even inside its plane
+        let parallel = Ray::new(Point3::new(-2., -1., 0.), Vec3::new(1., 0., 0.));
+        assert!(disc.intersection(&parallel).is_none());
+
+        let (min, max) = disc.bounding_box().unwrap();
+        assert!((min - Point3::new(-1., -1., -1.)).length() < 1e-5);
+        assert!((max - Point3::new(1., -1., 1.)).length() < 1e-5);
+    }
+}
@@ -0,0 +1,142 @@
+use std::f32::consts::PI;
+
+use crate::math::{Point3, Ray, Vec3};
+
+use super::{HitPoint, Texel, Traceable};
+
+/// struct to represent an axis aligned ellipsoid in 3D-Space
+/// Rays are intersected in the space where the ellipsoid is the unit sphere, which gives the same
+/// result as a sphere with a non-uniform scale, without the transformation of the surface
+#[derive(Clone, Debug)]
+pub(super) struct Ellipsoid {
+    center: Point3,
+    /// radius along the x, y and z axis
+    radii: Vec3,
+}
+
+impl Ellipsoid {
+    /// Create a new ellipsoid
+    /// All radii have to be positive
+    pub fn new(center: Point3, radii: Vec3) -> Ellipsoid {
+        Ellipsoid { center, radii }
+    }
+
+    /// Map a point or a direction into the space where the ellipsoid is the unit sphere around the
+    /// origin
+    fn to_unit(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            v[0] / self.radii[0],
+            v[1] / self.radii[1],
+            v[2] / self.radii[2],
+        )
+    }
+
+    /// Return the `t` values where the line of the ray enters and exits the ellipsoid, in this
+    /// order, or `None` if it misses the ellipsoid
+    fn roots(&self, with: &Ray) -> Option<[f32; 2]> {
+        let orig = self.to_unit(*with.orig() - self.center);
+        let dir = self.to_unit(*with.dir());
+        let a = dir.length_squared();
+        let h = -dir.dot(&orig);
+        let c = orig.length_squared() - 1.;
+        let discr = h * h - a * c;
+        if discr < 0. {
+            return None;
+        }
+        let discr = discr.sqrt();
+        Some([(h - discr) / a, (h + discr) / a])
+    }
+
+    /// Return the intersection at `t` on the ray
+    fn hit_at(&self, with: &Ray, t: f32) -> (f32, Vec3, Texel) {
+        let point = self.to_unit(*with.orig() - self.center) + t * self.to_unit(*with.dir());
+        (t, self.to_unit(point), Self::get_texel_at(&point))
+    }
+
+    /// Compute the texel of the point `p` on the unit sphere
+    /// Uses the same mapping as spheres, so the texture is stretched with the ellipsoid
+    fn get_texel_at(p: &Point3) -> Texel {
+        let d = -Vec3::normal(p);
+        let u = 0.5 + (d[0].atan2(d[2])) / (2. * PI);
+        let v = 0.5 - (d[1].asin()) / (PI);
+
+        (u, v)
+    }
+}
+
+impl Traceable for Ellipsoid {
+    fn kind(&self) -> &'static str {
+        "ellipsoid"
+    }
+
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        Some((self.center - self.radii, self.center + self.radii))
+    }
+
+    /// The whole texture is mapped onto the surface area, which is approximated with Thomsen's
+    /// formula
+    fn texel_density(&self) -> f32 {
+        const P: f32 = 1.6075;
+        let [a, b, c] = [0, 1, 2].map(|i| self.radii[i].powf(P));
+        let area = 4. * PI * ((a * b + a * c + b * c) / 3.).powf(1. / P);
+        1. / area.sqrt()
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    fn intersection_interval(&self, with: &Ray) -> Option<(HitPoint, HitPoint)> {
+        let [entry, exit] = self.roots(with)?;
+        Some((self.hit_at(with, entry), self.hit_at(with, exit)))
+    }
+
+    fn has_intersection(&self, with: &Ray) -> bool {
+        self.intersection(with).is_some()
+    }
+
+    /// Calculates the intersection of the ellipsoid and the `with` Ray if present
+    /// The `t` value of the unit sphere is the same as the one of the ellipsoid, since the
+    /// mapping is linear. The normal is the one of the unit sphere mapped with the inverse
+    /// transpose of the mapping
+    fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+        // use the exit if the entry lies behind or at the origin of the ray
+        let t = self
+            .roots(with)?
+            .into_iter()
+            .find(|t| with.t_in_range(*t))?;
+        Some(self.hit_at(with, t))
+    }
+
+    /// Report the entry and the exit of the ray, if they lie within its bounds
+    fn intersections_all(&self, with: &Ray, hit: &mut dyn FnMut(f32, Vec3, Texel)) {
+        for t in self.roots(with).into_iter().flatten() {
+            if with.t_in_range(t) {
+                let (t, normal, texel) = self.hit_at(with, t);
+                hit(t, normal, texel);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ellipsoid_intersection() {
+        let ellipsoid = Ellipsoid::new(Point3::new(0., 0., -5.), Vec3::new(1., 2., 3.));
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let (t, normal, _) = ellipsoid.intersection(&ray).unwrap();
+        assert!((t - 2.).abs() < 1e-5);
+        assert!((Vec3::normal(&normal) - Vec3::new(0., 0., 1.)).length() < 1e-5);
+
+        // from the inside the far side is hit
+        let inside = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 1., 0.));
+        assert!((ellipsoid.intersection(&inside).unwrap().0 - 2.).abs() < 1e-5);
+
+        let miss = Ray::new(Point3::new(1.5, 0., 0.), Vec3::new(0., 0., -1.));
+        assert!(ellipsoid.intersection(&miss).is_none());
+        assert!(!ellipsoid.has_intersection(&miss));
+    }
+}
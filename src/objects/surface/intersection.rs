@@ -1,6 +1,6 @@
 use crate::{
-    math::{Color, Point3, Ray, Vec3, BIAS},
-    objects::Light,
+    math::{disc_samples, point_rotation, tangent_frame, Color, Differentials, Point3, Ray, Vec3},
+    objects::{Light, RadianceSplit},
 };
 
 use super::{Material, Texel};
@@ -12,25 +12,165 @@ pub struct Intersection<'a> {
     pub t: f32,
     pub normal: Vec3,
     pub texel: Texel,
+    /// texture space units per world space unit at the intersection
+    pub uv_scale: f32,
+    /// size of the area covered by the ray in texture space
+    pub footprint: f32,
+    /// distance secondary rays start away from the surface, at least `BIAS`
+    pub offset: f32,
     pub material: &'a Material,
+    /// render layer of the surface that was hit
+    pub layer: Option<&'a str>,
 }
 
 impl Intersection<'_> {
-    /// Calculate the color of the intersection point
-    pub fn get_color(&self, light: &Light, ray: &Ray) -> Color {
+    /// Return the color of the texture at the intersection point
+    pub fn base_color(&self) -> Color {
+        self.material.texture_color(self.texel, self.footprint)
+    }
+
+    /// Calculate the color of the intersection point for a single light
+    /// `base_color` should be the result of [`Intersection::base_color`]
+    pub fn get_color(&self, light: &Light, base_color: Color, ray: &Ray) -> Color {
         self.material
-            .get_color(&self.point, &self.normal, light, self.texel, ray)
+            .get_color(&self.point, &self.normal, light, base_color, ray)
+    }
+
+    /// Calculate the combined color of the intersection point for all `lights`
+    /// `visibility_fn` should return the fraction of the light that reaches the given point,
+    /// 0 if it is occluded. Occluded lights don't contribute to the color
+    /// The texture is only sampled once, no matter how many lights there are
+    /// Materials that show normals are not lit at all
+    pub fn get_total_color(
+        &self,
+        lights: &[Light],
+        visibility_fn: impl Fn(&Light, &Point3) -> f32,
+        ray: &Ray,
+    ) -> Color {
+        self.get_split_color(lights, visibility_fn, ray).total()
+    }
+
+    /// Calculate the combined color like [`Intersection::get_total_color`], split into the
+    /// diffuse and the specular part
+    /// The color of materials that show normals or are unlit counts as emission
+    pub fn get_split_color(
+        &self,
+        lights: &[Light],
+        visibility_fn: impl Fn(&Light, &Point3) -> f32,
+        ray: &Ray,
+    ) -> RadianceSplit {
+        if let Some(color) = self.material.normal_color(&self.normal) {
+            return RadianceSplit::emission(color);
+        }
+        let base_color = self.base_color();
+        if self.material.is_unlit() {
+            return RadianceSplit::emission(base_color);
+        }
+        // if there was no light in sight, the object is black
+        let mut split = RadianceSplit::zero();
+        for light in lights {
+            let visibility = visibility_fn(light, &self.point);
+            if visibility > 0. {
+                let (diffuse, specular) = self.material.get_split_color(
+                    &self.point,
+                    &self.normal,
+                    light,
+                    base_color,
+                    ray,
+                );
+                split.diffuse += diffuse * visibility;
+                split.specular += specular * visibility;
+            }
+        }
+        split
+    }
+
+    /// Set the footprint from the width of the ray cone at the intersection in world space
+    /// The footprint is stretched the more grazing the angle between the ray and the surface is
+    /// If the ray carries differentials, they are used instead of the cone width
+    pub fn set_footprint(&mut self, cone_width: f32, ray: &Ray) {
+        if let Some((dpdx, dpdy)) = self.position_differentials(ray) {
+            self.footprint = dpdx.length().max(dpdy.length()) * self.uv_scale;
+            return;
+        }
+        let cos = self.normal.dot(&Vec3::normal(ray.dir())).abs().max(0.05);
+        self.footprint = cone_width / cos * self.uv_scale;
+    }
+
+    /// Derivatives of the intersection point with respect to the screen coordinates
+    /// The surface is assumed to be locally flat
+    /// Returns `None` if the ray doesn't carry differentials
+    fn position_differentials(&self, ray: &Ray) -> Option<(Vec3, Vec3)> {
+        let d = ray.differentials()?;
+        let d_dot_n = ray.dir().dot(&self.normal);
+        if d_dot_n.abs() < f32::EPSILON {
+            return None;
+        }
+        let transfer = |dodx: Vec3, dddx: Vec3| {
+            let dp = dodx + self.t * dddx;
+            let dt = -dp.dot(&self.normal) / d_dot_n;
+            dp + dt * *ray.dir()
+        };
+        Some((transfer(d.dodx, d.dddx), transfer(d.dody, d.dddy)))
+    }
+
+    /// Return the origin for a secondary ray leaving the surface in direction `dir`
+    /// The origin is moved along the normal to the side of the surface the ray leaves to, so
+    /// it is outside of the epsilon of primitives that are not intersected exactly
+    fn secondary_origin(&self, dir: &Vec3) -> Point3 {
+        if dir.dot(&self.normal) < 0. {
+            self.point - self.offset * self.normal
+        } else {
+            self.point + self.offset * self.normal
+        }
     }
 
     /// Reflect the given ray at the intersection point
+    /// The reflected ray keeps the time of `ray`
+    #[allow(clippy::similar_names)]
     pub fn reflected_ray(&self, ray: &Ray) -> Ray {
         let dir = Vec3::reflect(ray.dir(), &self.normal);
-        Ray::new(self.point + BIAS * dir, dir)
+        let reflected = Ray::new(self.secondary_origin(&dir), dir).with_time(ray.time());
+        match (ray.differentials(), self.position_differentials(ray)) {
+            (Some(d), Some((dpdx, dpdy))) => {
+                let reflect = |dd: Vec3| dd - 2. * dd.dot(&self.normal) * self.normal;
+                reflected.with_differentials(Differentials {
+                    dodx: dpdx,
+                    dody: dpdy,
+                    dddx: reflect(d.dddx),
+                    dddy: reflect(d.dddy),
+                })
+            }
+            _ => reflected,
+        }
+    }
+
+    /// Return `count` rays that spread the reflection of `ray` over a cone that widens with the
+    /// roughness of the material
+    /// The directions follow [`disc_samples`], turned differently at every point. Directions that
+    /// would leave through the other side of the surface are replaced with the mirror direction
+    pub fn glossy_rays<'s>(&'s self, ray: &Ray, count: u32) -> impl Iterator<Item = Ray> + 's {
+        let mirror = *self.reflected_ray(ray).dir();
+        let side = mirror.dot(&self.normal);
+        let roughness = self.material.reflection_roughness();
+        let (tangent, bitangent) = tangent_frame(&mirror);
+        let time = ray.time();
+        disc_samples(count, point_rotation(&self.point)).map(move |(x, y)| {
+            let dir = Vec3::normal(&(mirror + roughness * (x * tangent + y * bitangent)));
+            let dir = if dir.dot(&self.normal) * side > 0. {
+                dir
+            } else {
+                mirror
+            };
+            Ray::new(self.secondary_origin(&dir), dir).with_time(time)
+        })
     }
 
     /// Refract the ray at the intersection point
     /// returns the reflected ray if total interal refraction happens
+    /// The refracted ray keeps the time of `ray`
     /// See [here](https://www.scratchapixel.com/lessons/3d-basic-rendering/introduction-to-shading/reflection-refraction-fresnel.html) for derivation
+    #[allow(clippy::similar_names)]
     pub fn refracted_ray(&self, ray: &Ray) -> Ray {
         let v = ray.dir();
         let mut n = self.normal;
@@ -54,8 +194,26 @@ impl Intersection<'_> {
         }
 
         let t = n1_nt * (*v + n * n_dot_v) - n * discr.sqrt();
+        let refracted = Ray::new(self.secondary_origin(&t), t).with_time(ray.time());
 
-        Ray::new(self.point + BIAS * t, t)
+        match (ray.differentials(), self.position_differentials(ray)) {
+            (Some(d), Some((dpdx, dpdy))) => {
+                // derivative of t, with the derivative of the normal ignored
+                let refract = |dd: Vec3| {
+                    let dn_dot_v = -dd.dot(&n);
+                    let dmu = n1_nt * dn_dot_v
+                        - n1_nt * n1_nt * n_dot_v * dn_dot_v / discr.sqrt().max(f32::EPSILON);
+                    n1_nt * dd + dmu * n
+                };
+                refracted.with_differentials(Differentials {
+                    dodx: dpdx,
+                    dody: dpdy,
+                    dddx: refract(d.dddx),
+                    dddy: refract(d.dddy),
+                })
+            }
+            _ => refracted,
+        }
     }
 
     /// Return the reflectence parameter from the material that was hit
@@ -63,6 +221,17 @@ impl Intersection<'_> {
         self.material.reflectance()
     }
 
+    /// Return the part of the light that the clear coat of the material reflects towards the
+    /// origin of `ray`, 0 if the material has no smooth coat
+    pub fn get_coat_reflectance(&self, ray: &Ray) -> f32 {
+        self.material.coat_reflectance(&self.normal, ray)
+    }
+
+    /// Return the factor for the color of the scene reflected by the material that was hit
+    pub fn get_reflection_tint(&self, ray: &Ray) -> Color {
+        self.material.reflection_tint(&self.normal, ray)
+    }
+
     /// Return the transmittance parameter from the material that was hit
     pub fn get_transmittance(&self) -> f32 {
         self.material.transmittance()
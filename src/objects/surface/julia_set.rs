@@ -1,24 +1,27 @@
 use core::f32;
 
 use crate::{
-    math::{lerp, min, Point3, Quat, Ray, Vec3},
-    objects::surface::Texel,
+    math::{lerp, Mat4, Point3, Quat, Ray, Vec3},
+    objects::surface::{Texel, Traceable},
 };
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct Animation {
-    startc: Quat,
-    endc: Option<Quat>,
+    endc: Quat,
+    /// percentage of the animation that is finished
+    w: f32,
 }
 
 /// Struct to represent a ray-tracable 4d julia set
-#[derive(Debug)]
+/// `c` is the starting constant, the constant of the current frame is resolved from the animation
+/// on every query
+#[derive(Clone, Debug)]
 pub struct JuliaSet {
     pos: Point3,
     c: Quat,
     max_iterations: u32,
     epsilon: f32,
-    animation: Box<Animation>,
+    animation: Option<Box<Animation>>,
 }
 
 impl JuliaSet {
@@ -26,6 +29,8 @@ impl JuliaSet {
     const BOUNDING_RADIUS_2: f32 = 2.5;
     const ESCAPE_THRESHOLD: f32 = 1e1;
     const DEL: f32 = 1e-4;
+    /// fraction of the ray footprint used as epsilon when the ray carries differentials
+    const FOOTPRINT_EPSILON: f32 = 0.5;
 
     /// Create a new julia set
     pub fn new(pos: Point3, c: Quat, max_iterations: u32, epsilon: f32) -> JuliaSet {
@@ -34,32 +39,31 @@ impl JuliaSet {
             c,
             max_iterations,
             epsilon,
-            animation: Box::new(Animation {
-                startc: c,
-                endc: None,
-            }),
+            animation: None,
         }
     }
 
     /// Set the endconstant
     pub fn set_end(&mut self, ec: Quat) {
-        self.animation.endc = Some(ec);
+        self.animation = Some(Box::new(Animation { endc: ec, w: 0. }));
     }
 
-    /// set the frame percentage the lerp between starting and ending constant
-    pub fn set_frame(&mut self, w: f32) {
-        if let Some(ec) = self.animation.endc {
-            self.c = lerp(self.animation.startc, ec, w);
+    /// Return the constant at the time of the ray, or in the current frame if the ray has no time
+    fn constant(&self, with: &Ray) -> Quat {
+        match &self.animation {
+            Some(a) => lerp(self.c, a.endc, with.time().unwrap_or(a.w)),
+            None => self.c,
         }
     }
 
-    /// iterate the given quaternion to find the intersection in the julia set
+    /// iterate the given quaternion with the constant `c` to find the intersection in the julia
+    /// set
     /// taken from [this paper](https://www.cs.cmu.edu/~kmcrane/Projects/QuaternionJulia/paper.pdf)
-    fn iterate_intersect(&self, q: &mut Quat) -> Quat {
+    fn iterate_intersect(&self, q: &mut Quat, c: Quat) -> Quat {
         let mut qp = Quat::new(1., 0., 0., 0.);
         for _ in 0..self.max_iterations {
             qp = (&*q * &qp) * 2.;
-            *q = q.square() + self.c;
+            *q = q.square() + c;
 
             if q.length_squared() > Self::ESCAPE_THRESHOLD {
                 break;
@@ -71,29 +75,41 @@ impl JuliaSet {
 
     /// Calculate the distance to the intersection point with the julia set
     /// No intersection, if the distance is smaller than the epsilon
+    /// `t` is the ray parameter at the start of the march and is used to grow the epsilon with
+    /// the ray footprint, so that distant parts of the set aren't resolved finer than a pixel
+    /// Returns the last distance, the point and the epsilon at that point
     /// taken from [this paper](https://www.cs.cmu.edu/~kmcrane/Projects/QuaternionJulia/paper.pdf)
-    fn intersection_dist(&self, with: &Ray) -> (f32, Point3) {
+    fn intersection_dist(&self, with: &Ray, t: f32) -> (f32, Point3, f32) {
         let mut dist;
-        let mut orig = *with.orig();
+        let mut travelled = t;
+        let mut epsilon;
+        let mut orig = *with.orig() + t * *with.dir();
         let dir = *with.dir();
+        let c = self.constant(with);
         loop {
             let mut z = Quat::new(orig[0], orig[1], orig[2], 0.);
-            let zp = self.iterate_intersect(&mut z);
+            let zp = self.iterate_intersect(&mut z, c);
 
             let norm_z = z.length();
             dist = 0.5 * norm_z * norm_z.log2() / zp.length();
 
             orig += dir * dist;
+            travelled += dist;
+            epsilon = self
+                .epsilon
+                .max(Self::FOOTPRINT_EPSILON * with.footprint_at(travelled));
 
-            if dist < self.epsilon || orig.length_squared() > Self::BOUNDING_RADIUS_2 {
+            if dist < epsilon || orig.length_squared() > Self::BOUNDING_RADIUS_2 {
                 break;
             }
         }
 
-        (dist, orig)
+        (dist, orig, epsilon)
     }
 
-    /// Calculate the intersection with the bounding sphere
+    /// Calculate where the ray enters the bounding sphere
+    /// Rays that start inside of the sphere enter it at `t = 0`, so secondary rays can hit the set
+    /// again
     /// doesn't use the sphere struct, since radius is constant and center is at 0
     fn sphere_intersect(with: &Ray) -> Option<f32> {
         let a = with.dir().length_squared();
@@ -104,13 +120,17 @@ impl JuliaSet {
             return None;
         }
         let discr = discr.sqrt();
-        Some(min(-h + discr, -h - discr) / a)
+        let (near, far) = ((-h - discr) / a, (-h + discr) / a);
+        if far < 0. || near > with.max_t() {
+            return None;
+        }
+        Some(near.max(0.))
     }
 
-    /// Normal estimation for point on a julia set
+    /// Normal estimation for point on a julia set with the constant `c`
     /// taken from [this paper](https://www.cs.cmu.edu/~kmcrane/Projects/QuaternionJulia/paper.pdf)
     #[allow(clippy::similar_names)]
-    fn estimate_normal(&self, p: Point3) -> Vec3 {
+    fn estimate_normal(&self, p: Point3, c: Quat) -> Vec3 {
         let qp = Quat::new(p[0], p[1], p[2], 0.);
 
         let mut gx1 = qp - Quat::new(Self::DEL, 0., 0., 0.);
@@ -121,12 +141,12 @@ impl JuliaSet {
         let mut gz2 = qp + Quat::new(0., 0., Self::DEL, 0.);
 
         for _ in 0..self.max_iterations {
-            gx1 = gx1.square() + self.c;
-            gx2 = gx2.square() + self.c;
-            gy1 = gy1.square() + self.c;
-            gy2 = gy2.square() + self.c;
-            gz1 = gz1.square() + self.c;
-            gz2 = gz2.square() + self.c;
+            gx1 = gx1.square() + c;
+            gx2 = gx2.square() + c;
+            gy1 = gy1.square() + c;
+            gy2 = gy2.square() + c;
+            gz1 = gz1.square() + c;
+            gz2 = gz2.square() + c;
         }
 
         Vec3::normal(&Vec3::new(
@@ -135,34 +155,63 @@ impl JuliaSet {
             gz2.length() - gz1.length(),
         ))
     }
+}
+
+impl Traceable for JuliaSet {
+    fn kind(&self) -> &'static str {
+        "julia_set"
+    }
 
-    pub fn has_intersection(&self, with: &Ray) -> bool {
-        let with = Ray::new(*with.orig() - self.pos, *with.dir());
+    /// set the frame percentage the lerp between starting and ending constant
+    fn set_frame(&mut self, w: f32) {
+        if let Some(a) = &mut self.animation {
+            a.w = w;
+        }
+    }
+
+    /// Return true if an endconstant was set
+    fn is_animated(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Rays march until they are closer to the set than the epsilon
+    fn intersection_epsilon(&self) -> f32 {
+        self.epsilon
+    }
+
+    /// The set lies within the bounding sphere
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        let r = Self::BOUNDING_RADIUS_2.sqrt();
+        let r = Vec3::new(r, r, r);
+        Some((self.pos - r, self.pos + r))
+    }
+
+    fn has_intersection(&self, with: &Ray) -> bool {
+        let with = with.transform(&Mat4::from_translation(-self.pos));
 
         let Some(t) = Self::sphere_intersect(&with) else {
             return false;
         };
-        let Some(p) = with.at(t) else {
-            return false;
-        };
-        let r = Ray::new(p, *with.dir());
-        let (dist, _) = self.intersection_dist(&r);
+        let (dist, _, epsilon) = self.intersection_dist(&with, t);
 
-        dist < self.epsilon
+        dist < epsilon && with.t_in_range(t + dist)
     }
 
     /// Calculate the nearest intersection point with the julia set
     /// Most calculations are taken from [this paper](https://www.cs.cmu.edu/~kmcrane/Projects/QuaternionJulia/paper.pdf)
-    pub fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
-        let with = Ray::new(*with.orig() - self.pos, *with.dir());
+    fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+        let with = with.transform(&Mat4::from_translation(-self.pos));
         let t = Self::sphere_intersect(&with)?;
-        let r = Ray::new(with.at(t)?, *with.dir());
-        let (dist, p) = self.intersection_dist(&r);
+        let (dist, p, epsilon) = self.intersection_dist(&with, t);
 
-        if dist >= self.epsilon {
+        if dist >= epsilon || !with.t_in_range(t + dist) {
             return None;
         }
 
-        Some((t + dist, self.estimate_normal(p), (0., 0.)))
+        Some((
+            t + dist,
+            self.estimate_normal(p, self.constant(&with)),
+            (0., 0.),
+        ))
     }
 }
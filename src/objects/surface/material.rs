@@ -1,39 +1,105 @@
 use std::f32::consts::PI;
 
 use crate::{
-    image::Image,
-    math::{max, smoothstep, Color, Point3, Ray, Vec3},
+    image::{uv_grid_color, Image, MipMap},
+    math::{lerp, max, Color, Point3, Ray, Vec3},
     objects::Light,
 };
 
 use super::Texel;
 
+/// Texture coordinate that a gradient texture is laid out along
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientAxis {
+    U,
+    V,
+}
+
 /// Texture that defines the color of a material
-/// can be either a solid color or a defined by an image
-#[derive(Clone, Debug)]
+/// can be either a solid color, an image, a color ramp along one texture axis or a uv debug grid
+#[derive(Clone, Debug, PartialEq)]
 pub enum Texture {
     Color(Color),
-    Image(Image),
+    Image(MipMap),
+    /// `colors` are (position, color) stops sorted by position
+    Gradient {
+        colors: Vec<(f32, Color)>,
+        axis: GradientAxis,
+    },
+    /// procedural grid to check texture coordinates, see [`crate::image::uv_grid_color`]
+    /// `line_width` is the width of the lines between the cells as a fraction of a cell
+    DebugGrid {
+        cells: u32,
+        line_width: f32,
+    },
 }
 
 impl Texture {
+    /// Create an image texture and generate its mip pyramid
+    #[must_use]
+    pub fn image(image: Image) -> Texture {
+        Texture::Image(MipMap::new(image))
+    }
+
     /// return the color at a given texel
+    /// `footprint` is the area covered by the lookup in texture space and is used to filter
+    /// minified image textures
     #[must_use]
-    pub fn get_color(&self, texel: Texel) -> Color {
+    pub fn get_color(&self, texel: Texel, footprint: f32) -> Color {
         match self {
             Texture::Color(c) => *c,
-            Texture::Image(i) => Color::from(i.get_pixel(0, texel.0, texel.1)),
+            Texture::Image(i) => i.sample(texel.0, texel.1, footprint),
+            Texture::Gradient { colors, axis } => {
+                let t = match axis {
+                    GradientAxis::U => texel.0,
+                    GradientAxis::V => texel.1,
+                };
+                gradient_color(colors, t)
+            }
+            Texture::DebugGrid { cells, line_width } => {
+                uv_grid_color(texel.0, texel.1, *cells, *line_width)
+            }
         }
     }
 }
 
-#[derive(Clone, Debug)]
+/// Linearly interpolate between the two stops of `colors` around `t`
+/// Outside of the stops, the color of the closest stop is used
+fn gradient_color(colors: &[(f32, Color)], t: f32) -> Color {
+    let next = colors.partition_point(|(pos, _)| *pos <= t);
+    match (
+        next.checked_sub(1).map(|i| colors[i]),
+        colors.get(next).copied(),
+    ) {
+        (Some((p0, c0)), Some((p1, c1))) => lerp(c0, c1, (t - p0) / (p1 - p0)),
+        (Some((_, c)), None) | (None, Some((_, c))) => c,
+        (None, None) => Color::zero(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum ShadingModel {
-    Phong { ka: f32, kd: f32, ks: f32, exp: u32 },
-    CookTorrance { ka: f32, ks: f32, roughness: f32 },
+    Phong {
+        ka: f32,
+        kd: f32,
+        ks: f32,
+        exp: u32,
+    },
+    CookTorrance {
+        ka: f32,
+        ks: f32,
+        roughness: f32,
+    },
+    /// Ignore lights and textures and show the direction of the normal as a color
+    Normals,
+    /// Ignore lights and show the color of the texture as it is, like a glowing surface
+    Unlit,
 }
 
 impl ShadingModel {
+    /// Reflectance of Cook-Torrance materials at normal incidence
+    const COOK_TORRANCE_F0: Vec3 = Vec3::new(0.56, 0.57, 0.58);
+
     /// part of ggx geometric shadowing
     fn g1(x: Vec3, h: Vec3, n: Vec3, alpha2: f32) -> f32 {
         let xdotn = max(x.dot(&n), 0.0);
@@ -69,7 +135,17 @@ impl ShadingModel {
         f0 + (Vec3::new(1., 1., 1.) - f0) * (1.0 - vdoth).powf(5.0)
     }
 
+    /// Return the weight of the diffuse part of a Cook-Torrance material seen from `eye`
+    /// The diffuse part only gets the light that is neither reflected specularly (`ks`) nor at
+    /// the surface (the Fresnel term towards the viewer), so together with the specular part it
+    /// never reflects more light than arrives and it fades out at grazing angles
+    fn cook_torrance_diffuse_weight(ks: f32, normal: &Vec3, eye: &Vec3) -> Color {
+        let fresnel = Self::fresnel(Self::COOK_TORRANCE_F0, *normal, *eye);
+        (Color::new(1., 1., 1.) - fresnel) * (1. - ks)
+    }
+
     /// Calculates the color according to the [cook-torrance model](https://graphicscompendium.com/references/cook-torrance)
+    /// Returns the diffuse and the specular part of the color
     #[allow(clippy::similar_names)]
     fn cook_torrance_color(
         ctparams: (f32, f32),
@@ -78,17 +154,16 @@ impl ShadingModel {
         vnormal: &Vec3,
         neg_veye: &Vec3,
         frag_color: Color,
-    ) -> Color {
+        tint: impl Fn(f32) -> Color,
+    ) -> (Color, Color) {
         let (ks, alpha) = ctparams;
         let alpha2: f32 = alpha * alpha;
-        let f0 = Vec3::new(0.56, 0.57, 0.58);
+        let f0 = Self::COOK_TORRANCE_F0;
 
         let light = -Vec3::normal(neg_light);
         let normal = Vec3::normal(vnormal);
         let eye = -Vec3::normal(neg_veye);
         let half = Vec3::normal(&(eye + light));
-        let s = ks;
-        let d = 1. - s;
 
         let ndotl = max(normal.dot(&light), 0.);
         let ndote = max(normal.dot(&eye), 0.);
@@ -98,18 +173,19 @@ impl ShadingModel {
         // Geometric shadowing function (microfacets shadow or obstruct light)
         let geo_shadowing = Self::g_ggx(normal, half, eye, light, alpha2);
         // Fresnel effect
-        let fresnel = Self::fresnel(f0, half, eye);
+        let fresnel = Self::fresnel(f0, half, eye) * tint(max(eye.dot(&half), 0.));
 
         // specular reflection using the cook-torrance model: (DGF) / 4 * (n*l) * (n*v)
         let r_s = (distribution * geo_shadowing * fresnel) / max(4.0 * ndotl * ndote, 0.00001); // dont divide by zero
 
-        let diffuse = frag_color;
-        let brdf = d * diffuse + s * r_s;
+        let diffuse_weight = Self::cook_torrance_diffuse_weight(ks, &normal, &eye);
 
-        *light_color * ndotl * brdf
+        let incoming = *light_color * ndotl;
+        (incoming * frag_color * diffuse_weight, incoming * r_s * ks)
     }
 
     /// Calculate the color of the material with a light color
+    /// Returns the diffuse and the specular part of the color
     fn phong_color(
         phparams: (f32, f32, u32),
         light_color: &Color,
@@ -117,7 +193,8 @@ impl ShadingModel {
         vnormal: &Vec3,
         neg_veye: &Vec3,
         frag_color: Color,
-    ) -> Color {
+        tint: impl Fn(f32) -> Color,
+    ) -> (Color, Color) {
         let (kd, ks, exp) = phparams;
         let l = Vec3::normal(neg_light);
         let n = -Vec3::normal(vnormal);
@@ -126,7 +203,7 @@ impl ShadingModel {
         let e = -Vec3::normal(neg_veye);
         #[allow(clippy::cast_precision_loss)]
         let specular = *light_color * ks * max(e.dot(&r), 0.0).powf(exp as f32);
-        diffuse + specular
+        (diffuse, specular * tint(n.dot(&e).abs()))
     }
 
     /// Calculate the color of the material with a light color using the specified shading model
@@ -139,6 +216,29 @@ impl ShadingModel {
         neg_veye: &Vec3,
         frag_color: Color,
     ) -> Color {
+        let (diffuse, specular) = self.tinted_shading_color(
+            light_color,
+            neg_light,
+            vnormal,
+            neg_veye,
+            frag_color,
+            |_| Color::new(1., 1., 1.),
+        );
+        diffuse + specular
+    }
+
+    /// Calculate the diffuse and the specular part of the color like
+    /// [`ShadingModel::shading_color`], with the specular reflection multiplied by `tint`, which
+    /// maps the cosine of the angle of incidence to a color
+    fn tinted_shading_color(
+        &self,
+        light_color: &Color,
+        neg_light: &Vec3,
+        vnormal: &Vec3,
+        neg_veye: &Vec3,
+        frag_color: Color,
+        tint: impl Fn(f32) -> Color,
+    ) -> (Color, Color) {
         match self {
             Self::Phong { ka: _, kd, ks, exp } => Self::phong_color(
                 (*kd, *ks, *exp),
@@ -147,6 +247,7 @@ impl ShadingModel {
                 vnormal,
                 neg_veye,
                 frag_color,
+                tint,
             ),
             Self::CookTorrance {
                 ka: _,
@@ -159,27 +260,203 @@ impl ShadingModel {
                 vnormal,
                 neg_veye,
                 frag_color,
+                tint,
             ),
+            Self::Normals | Self::Unlit => (Color::zero(), Color::zero()),
         }
     }
 
-    /// get the ambient coefficent of the shading model
+    /// get the part of the ambient light that the shading model reflects towards `neg_veye`
+    /// Cook-Torrance materials weight the ambient coefficient like their diffuse part, see
+    /// [`ShadingModel::cook_torrance_diffuse_weight`]
     #[must_use]
-    pub fn ambient(&self) -> f32 {
+    pub fn ambient(&self, vnormal: &Vec3, neg_veye: &Vec3) -> Color {
         match self {
-            Self::Phong { ka, .. } | Self::CookTorrance { ka, .. } => *ka,
+            Self::Phong { ka, .. } => Color::new(*ka, *ka, *ka),
+            Self::CookTorrance { ka, ks, .. } => {
+                let normal = Vec3::normal(vnormal);
+                let eye = -Vec3::normal(neg_veye);
+                // the side of the surface that faces the viewer
+                let normal = if normal.dot(&eye) < 0. {
+                    -normal
+                } else {
+                    normal
+                };
+                Self::cook_torrance_diffuse_weight(*ks, &normal, &eye) * *ka
+            }
+            Self::Normals | Self::Unlit => Color::zero(),
+        }
+    }
+}
+
+/// Thin transparent film on the surface, like a soap bubble or an oil slick
+/// Light reflected at the top and the bottom of the film interferes, which tints reflections
+/// depending on the thickness of the film and the viewing angle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThinFilm {
+    /// current thickness in nanometers
+    thickness: f32,
+    ior: f32,
+    /// thickness at the start and the end of an animation
+    start: f32,
+    end: Option<f32>,
+}
+
+impl ThinFilm {
+    /// representative wavelengths of the red, green and blue channel in nanometers
+    const WAVELENGTHS: [f32; 3] = [650., 510., 475.];
+
+    /// Create a new film with a thickness in nanometers and an index of refraction
+    #[must_use]
+    pub fn new(thickness: f32, ior: f32) -> ThinFilm {
+        ThinFilm {
+            thickness,
+            ior,
+            start: thickness,
+            end: None,
+        }
+    }
+
+    /// Return the film with the thickness it grows or shrinks to over an animation
+    #[must_use]
+    pub fn with_end(mut self, end: f32) -> ThinFilm {
+        self.end = Some(end);
+        self
+    }
+
+    /// Return the current thickness in nanometers
+    #[must_use]
+    pub fn thickness(&self) -> f32 {
+        self.thickness
+    }
+
+    /// Set the thickness for the frame percentage `w`
+    fn set_frame(&mut self, w: f32) {
+        if let Some(end) = self.end {
+            self.thickness = lerp(self.start, end, w);
+        }
+    }
+
+    /// Return the amplitudes reflected at the top of the film (from air) and at its bottom (on a
+    /// substrate with the index of refraction `substrate`) at normal incidence
+    fn amplitudes(&self, substrate: f32) -> (f32, f32) {
+        (
+            (1. - self.ior) / (1. + self.ior),
+            (self.ior - substrate) / (self.ior + substrate),
+        )
+    }
+
+    /// Return the reflectance of the film at `wavelength` (in nanometers) for light with the
+    /// cosine `cos` of the angle of incidence
+    /// The phase difference of the two reflected waves depends on the angle, the amplitudes are
+    /// approximated by the ones at normal incidence
+    #[must_use]
+    pub fn reflectance(&self, wavelength: f32, cos: f32, substrate: f32) -> f32 {
+        let (r12, r23) = self.amplitudes(substrate);
+        let sin2_t = (1. - cos * cos).max(0.) / (self.ior * self.ior);
+        let cos_t = (1. - sin2_t).max(0.).sqrt();
+        let phase = (4. * PI * self.ior * self.thickness * cos_t / wavelength).cos();
+        let cross = 2. * r12 * r23 * phase;
+        (r12 * r12 + r23 * r23 + cross) / (1. + r12 * r12 * r23 * r23 + cross)
+    }
+
+    /// Return the factor for the Fresnel term of every color channel
+    /// The reflectance is divided by its average over all thicknesses, so the film shifts the hue
+    /// of reflections without changing their overall brightness
+    #[must_use]
+    pub fn tint(&self, cos: f32, substrate: f32) -> Color {
+        let (r12, r23) = self.amplitudes(substrate);
+        let (a, b) = (r12 * r12, r23 * r23);
+        let average = (a + b - 2. * a * b) / (1. - a * b);
+        if average <= f32::EPSILON {
+            return Color::new(1., 1., 1.);
         }
+        let [r, g, b] = Self::WAVELENGTHS.map(|l| self.reflectance(l, cos, substrate) / average);
+        Color::new(r, g, b)
+    }
+}
+
+/// Glossy clear coat layered over the base material, like the varnish of wood or car paint
+/// The coat reflects light with a GGX lobe of its own roughness, the base only receives the light
+/// that the coat lets through
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClearCoat {
+    /// how much of the coat is applied, from 0 to 1
+    pub strength: f32,
+    pub roughness: f32,
+}
+
+impl ClearCoat {
+    /// reflectance of the coat at normal incidence, the one of a varnish with an index of
+    /// refraction of 1.5
+    const F0: f32 = 0.04;
+    /// coats up to this roughness also reflect the scene with a reflection ray
+    const SMOOTH_ROUGHNESS: f32 = 0.1;
+
+    /// Return the part of the light that the coat reflects, for the cosine of the angle between
+    /// the light and the normal of the reflecting microfacets
+    #[must_use]
+    pub fn fresnel(self, cos: f32) -> f32 {
+        self.strength * (Self::F0 + (1. - Self::F0) * (1. - cos.clamp(0., 1.)).powi(5))
+    }
+
+    /// Return true if the coat is smooth enough to reflect the scene
+    #[must_use]
+    pub fn is_smooth(self) -> bool {
+        self.roughness <= Self::SMOOTH_ROUGHNESS
+    }
+
+    /// Layer the coat over `base`, the diffuse and specular color of the base material lit by the
+    /// light from `neg_light` with the color `light_color`
+    /// Both layers together never reflect more light than the base alone could. The highlight of
+    /// the coat is added to the specular color
+    #[allow(clippy::similar_names)]
+    fn layer(
+        self,
+        (diffuse, specular): (Color, Color),
+        light_color: &Color,
+        neg_light: &Vec3,
+        vnormal: &Vec3,
+        neg_veye: &Vec3,
+    ) -> (Color, Color) {
+        let light = -Vec3::normal(neg_light);
+        let normal = Vec3::normal(vnormal);
+        let eye = -Vec3::normal(neg_veye);
+        let half = Vec3::normal(&(eye + light));
+        let alpha2 = self.roughness * self.roughness;
+
+        let ndotl = max(normal.dot(&light), 0.);
+        let ndote = max(normal.dot(&eye), 0.);
+        let fresnel = self.fresnel(eye.dot(&half));
+        let distribution = ShadingModel::d_ggx(normal, half, alpha2);
+        let geo_shadowing = ShadingModel::g_ggx(normal, half, eye, light, alpha2);
+        let coat_specular =
+            distribution * geo_shadowing * fresnel / max(4.0 * ndotl * ndote, 0.00001) * ndotl;
+
+        // the base is weakened by everything the coat reflects towards the viewer, which is also
+        // the weight of the reflection ray of smooth coats
+        let through = 1. - self.fresnel(ndote);
+        (
+            diffuse * through,
+            specular * through + *light_color * coat_specular,
+        )
     }
 }
 
 /// Struct to represent a Material
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Material {
     reflectance: f32,
     transmittance: f32,
     refraction: f32,
     texture: Texture,
     shading: ShadingModel,
+    clearcoat: Option<ClearCoat>,
+    thin_film: Option<ThinFilm>,
+    /// spread of the reflection, 0 for a mirror
+    reflection_roughness: f32,
+    /// number of reflection rays of a glossy reflection, overrides the default of the scene
+    reflection_samples: Option<u32>,
 }
 
 impl Material {
@@ -198,63 +475,264 @@ impl Material {
             refraction,
             texture,
             shading,
+            clearcoat: None,
+            thin_film: None,
+            reflection_roughness: 0.,
+            reflection_samples: None,
+        }
+    }
+
+    /// Return the material with a glossy reflection that spreads the reflected rays over a cone
+    /// `roughness` between 0 (a mirror) and 1, `samples` is the number of reflection rays
+    #[must_use]
+    pub fn with_glossy_reflection(mut self, roughness: f32, samples: Option<u32>) -> Material {
+        self.reflection_roughness = roughness;
+        self.reflection_samples = samples;
+        self
+    }
+
+    /// Return the roughness of the reflection, 0 for a mirror
+    #[must_use]
+    pub fn reflection_roughness(&self) -> f32 {
+        self.reflection_roughness
+    }
+
+    /// Return the number of reflection rays, if the material overrides the default
+    #[must_use]
+    pub fn reflection_samples(&self) -> Option<u32> {
+        self.reflection_samples
+    }
+
+    /// Return the material with a thin film on its surface
+    #[must_use]
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Material {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    /// Return the thin film on the material, if it has one
+    #[must_use]
+    pub fn thin_film(&self) -> Option<&ThinFilm> {
+        self.thin_film.as_ref()
+    }
+
+    /// Set the frame percentage
+    /// w is the percentage that the animation is finished
+    pub fn set_frame(&mut self, w: f32) {
+        if let Some(film) = &mut self.thin_film {
+            film.set_frame(w);
+        }
+    }
+
+    /// Return true if the material changes between frames
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        self.thin_film.is_some_and(|film| film.end.is_some())
+    }
+
+    /// Return the factor for the color of the scene reflected along `ray`
+    /// White unless the material has a thin film
+    #[must_use]
+    pub fn reflection_tint(&self, normal: &Vec3, ray: &Ray) -> Color {
+        self.film_tint(Vec3::normal(normal).dot(&Vec3::normal(ray.dir())).abs())
+    }
+
+    /// Return the tint of the thin film for the cosine of the angle of incidence
+    fn film_tint(&self, cos: f32) -> Color {
+        self.thin_film.map_or(Color::new(1., 1., 1.), |film| {
+            film.tint(cos, self.refraction)
+        })
+    }
+
+    /// Return the material with a clear coat over it
+    #[must_use]
+    pub fn with_clearcoat(mut self, clearcoat: ClearCoat) -> Material {
+        self.clearcoat = Some(clearcoat);
+        self
+    }
+
+    /// Return the clear coat of the material, if it has one
+    #[must_use]
+    pub fn clearcoat(&self) -> Option<&ClearCoat> {
+        self.clearcoat.as_ref()
+    }
+
+    /// Return the part of the light that a smooth clear coat reflects towards the end of `ray`
+    /// 0 if the material has no coat or the coat is too rough to reflect the scene
+    #[must_use]
+    pub fn coat_reflectance(&self, normal: &Vec3, ray: &Ray) -> f32 {
+        self.clearcoat
+            .filter(|coat| coat.is_smooth())
+            .map_or(0., |coat| {
+                coat.fresnel(Vec3::normal(normal).dot(&Vec3::normal(ray.dir())).abs())
+            })
+    }
+
+    /// Shade the material for a light from `neg_light` and layer the clear coat over it
+    /// Returns the diffuse and the specular part of the color
+    fn shade(
+        &self,
+        light_color: &Color,
+        neg_light: &Vec3,
+        normal: &Vec3,
+        ray: &Ray,
+        base_color: Color,
+    ) -> (Color, Color) {
+        let base = self.shading.tinted_shading_color(
+            light_color,
+            neg_light,
+            normal,
+            ray.dir(),
+            base_color,
+            |cos| self.film_tint(cos),
+        );
+        match &self.clearcoat {
+            Some(coat) => coat.layer(base, light_color, neg_light, normal, ray.dir()),
+            None => base,
         }
     }
 
+    /// Return the color of the texture at `texel`
+    /// `footprint` is the size of the area covered by the ray in texture space
+    #[must_use]
+    pub fn texture_color(&self, texel: Texel, footprint: f32) -> Color {
+        self.texture.get_color(texel, footprint)
+    }
+
+    /// Return the color that visualizes `normal` if the material shows normals instead of being
+    /// shaded, see [`ShadingModel::Normals`]
+    /// Every component of the normal is mapped from [-1, 1] to [0, 1]
+    #[must_use]
+    pub fn normal_color(&self, normal: &Vec3) -> Option<Color> {
+        (self.shading == ShadingModel::Normals).then(|| {
+            let n = Vec3::normal(normal);
+            Color::new(0.5 * (n[0] + 1.), 0.5 * (n[1] + 1.), 0.5 * (n[2] + 1.))
+        })
+    }
+
+    /// Return true if the material ignores lights and shows its texture as it is, see
+    /// [`ShadingModel::Unlit`]
+    #[must_use]
+    pub fn is_unlit(&self) -> bool {
+        self.shading == ShadingModel::Unlit
+    }
+
     /// Calculate the color for the given light source when hitting a point with this material with a ray
+    /// `base_color` is the color of the texture at the point, see [`Material::texture_color`]
     #[must_use]
     pub fn get_color(
         &self,
         point: &Point3,
         normal: &Vec3,
         light: &Light,
-        texel: Texel,
+        base_color: Color,
         ray: &Ray,
     ) -> Color {
+        let (diffuse, specular) = self.get_split_color(point, normal, light, base_color, ray);
+        diffuse + specular
+    }
+
+    /// Calculate the color like [`Material::get_color`], split into the diffuse and the specular
+    /// part
+    /// Ambient light only has a diffuse part
+    #[must_use]
+    pub fn get_split_color(
+        &self,
+        point: &Point3,
+        normal: &Vec3,
+        light: &Light,
+        base_color: Color,
+        ray: &Ray,
+    ) -> (Color, Color) {
         match light {
-            Light::Ambient { color } => {
-                *color * self.texture.get_color(texel) * self.shading.ambient()
-            }
-            Light::Parallel { color, direction } => self.shading.shading_color(
-                color,
-                direction,
-                normal,
-                ray.dir(),
-                self.texture.get_color(texel),
+            Light::Ambient { .. } => (
+                light.ambient_color(normal) * base_color * self.shading.ambient(normal, ray.dir()),
+                Color::zero(),
             ),
-            Light::Point { color, position } => {
+            Light::Parallel { color, direction } => {
+                self.shade(color, direction, normal, ray, base_color)
+            }
+            Light::Point {
+                color, position, ..
+            } => {
                 let dir = *point - *position;
-                self.shading.shading_color(
-                    color,
-                    &dir,
-                    normal,
-                    ray.dir(),
-                    self.texture.get_color(texel),
-                )
+                self.shade(color, &dir, normal, ray, base_color)
             }
             Light::Spot {
-                color,
-                position,
-                direction,
-                falloff,
+                color, position, ..
             } => {
-                let dir = Vec3::normal(&(*point - *position));
-                let dot_from_dir = dir.dot(&Vec3::normal(direction));
-                let in_light = smoothstep(falloff.1, falloff.0, dot_from_dir);
+                let in_light = light.intensity(point);
                 if in_light == 0. {
-                    Color::zero()
+                    (Color::zero(), Color::zero())
                 } else {
-                    in_light
-                        * self.shading.shading_color(
-                            color,
-                            &dir,
-                            normal,
-                            ray.dir(),
-                            self.texture.get_color(texel),
-                        )
+                    let dir = Vec3::normal(&(*point - *position));
+                    let (diffuse, specular) = self.shade(color, &dir, normal, ray, base_color);
+                    (diffuse * in_light, specular * in_light)
+                }
+            }
+        }
+    }
+
+    /// Return the number of bytes used by the texture image
+    /// Solid colors don't use any texture memory
+    #[must_use]
+    pub fn texture_memory(&self) -> usize {
+        match &self.texture {
+            Texture::Color(_) | Texture::Gradient { .. } | Texture::DebugGrid { .. } => 0,
+            Texture::Image(i) => i.memory_size(),
+        }
+    }
+
+    /// Names of the properties that [`Material::scale_property`] can scale
+    pub const PROPERTIES: [&'static str; 7] = [
+        "reflectance",
+        "transmittance",
+        "ka",
+        "kd",
+        "ks",
+        "roughness",
+        "exponent",
+    ];
+
+    /// Multiply the property `name` by `factor` and clamp it to its valid range
+    /// `roughness` scales the roughness of the shading model, the glossy reflection and the clear
+    /// coat. Properties the material doesn't have (like `exponent` of a Cook-Torrance material)
+    /// are left alone
+    /// Returns false if `name` is not one of [`Material::PROPERTIES`]
+    pub fn scale_property(&mut self, name: &str, factor: f32) -> bool {
+        let scale = |value: &mut f32, max: f32| *value = (*value * factor).clamp(0., max);
+        match (name, &mut self.shading) {
+            ("reflectance", _) => scale(&mut self.reflectance, 1.),
+            ("transmittance", _) => scale(&mut self.transmittance, 1.),
+            ("ka", ShadingModel::Phong { ka, .. } | ShadingModel::CookTorrance { ka, .. }) => {
+                scale(ka, 1.);
+            }
+            ("kd", ShadingModel::Phong { kd, .. }) => scale(kd, 1.),
+            ("ks", ShadingModel::Phong { ks, .. } | ShadingModel::CookTorrance { ks, .. }) => {
+                scale(ks, 1.);
+            }
+            ("roughness", shading) => {
+                if let ShadingModel::CookTorrance { roughness, .. } = shading {
+                    // a roughness of 0 breaks the GGX distribution
+                    *roughness = (*roughness * factor).clamp(0.001, 1.);
+                }
+                scale(&mut self.reflection_roughness, 1.);
+                if let Some(coat) = &mut self.clearcoat {
+                    coat.roughness = (coat.roughness * factor).clamp(0.001, 1.);
                 }
             }
+            ("exponent", ShadingModel::Phong { exp, .. }) => {
+                #[allow(
+                    clippy::cast_possible_truncation,
+                    clippy::cast_sign_loss,
+                    clippy::cast_precision_loss
+                )]
+                let scaled = (*exp as f32 * factor).round().max(1.) as u32;
+                *exp = scaled;
+            }
+            _ => return Self::PROPERTIES.contains(&name),
         }
+        true
     }
 
     /// Getter for the reflectance
@@ -275,3 +753,339 @@ impl Material {
         self.refraction
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_stop_gradient_interpolates_linearly() {
+        let red = Color::new(1., 0., 0.);
+        let blue = Color::new(0., 0., 1.);
+        let gradient = Texture::Gradient {
+            colors: vec![(0., red), (1., blue)],
+            axis: GradientAxis::U,
+        };
+        assert_eq!(gradient.get_color((0., 0.7), 0.), red);
+        assert_eq!(gradient.get_color((1., 0.7), 0.), blue);
+        assert_eq!(gradient.get_color((0.5, 0.7), 0.), (red + blue) / 2.);
+        // outside of the stops the closest stop is used
+        assert_eq!(gradient.get_color((-1., 0.), 0.), red);
+        assert_eq!(gradient.get_color((2., 0.), 0.), blue);
+
+        let vertical = Texture::Gradient {
+            colors: vec![(0., red), (1., blue)],
+            axis: GradientAxis::V,
+        };
+        assert_eq!(vertical.get_color((0., 1.), 0.), blue);
+    }
+
+    /// Integrate the light that `material` reflects towards the viewer over all directions of a
+    /// white parallel light, relative to a white lambertian surface
+    #[allow(clippy::cast_precision_loss)]
+    fn directional_albedo(material: &Material, view_angle: f32) -> f32 {
+        let normal = Vec3::new(0., 0., 1.);
+        let eye = Vec3::new(view_angle.sin(), 0., view_angle.cos());
+        let ray = Ray::new(eye, -eye);
+        let (n_theta, n_phi) = (256, 512);
+        let (d_theta, d_phi) = (PI / 2. / n_theta as f32, 2. * PI / n_phi as f32);
+        let mut total = 0.;
+        for i in 0..n_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let light = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let parallel = Light::Parallel {
+                    color: Color::new(1., 1., 1.),
+                    direction: -light,
+                };
+                let color = material.get_color(
+                    &Point3::zero(),
+                    &normal,
+                    &parallel,
+                    Color::new(1., 1., 1.),
+                    &ray,
+                );
+                total += color[1] * theta.sin() * d_theta * d_phi;
+            }
+        }
+        total / PI
+    }
+
+    #[test]
+    fn clearcoat_does_not_add_energy() {
+        let white = |clearcoat: Option<ClearCoat>| {
+            let material = Material::new(
+                Texture::Color(Color::new(1., 1., 1.)),
+                0.,
+                0.,
+                1.,
+                ShadingModel::Phong {
+                    ka: 0.,
+                    kd: 1.,
+                    ks: 0.,
+                    exp: 1,
+                },
+            );
+            match clearcoat {
+                Some(coat) => material.with_clearcoat(coat),
+                None => material,
+            }
+        };
+        let coat = ClearCoat {
+            strength: 1.,
+            roughness: 0.05,
+        };
+        let coated = white(Some(coat));
+        for degrees in [0f32, 30., 60., 80.] {
+            let angle = degrees.to_radians();
+            let base = directional_albedo(&white(None), angle);
+            let layered = directional_albedo(&coated, angle);
+            assert!((base - 1.).abs() < 0.01, "{base}");
+            assert!(layered <= 1., "{degrees}: {layered}");
+            // the base only loses the light that the coat reflects towards the viewer
+            assert!(
+                layered >= 1. - coat.fresnel(angle.cos()) - 0.01,
+                "{degrees}: {layered}"
+            );
+        }
+    }
+
+    /// Integrate the Cook-Torrance BRDF times the cosine of the light over the hemisphere for a
+    /// white surface, the part of the light from all directions that is reflected to the viewer
+    #[allow(clippy::cast_precision_loss)]
+    fn cook_torrance_albedo(ks: f32, roughness: f32, view_angle: f32) -> f32 {
+        let normal = Vec3::new(0., 0., 1.);
+        let eye = Vec3::new(view_angle.sin(), 0., view_angle.cos());
+        let (n_theta, n_phi) = (256, 512);
+        let (d_theta, d_phi) = (PI / 2. / n_theta as f32, 2. * PI / n_phi as f32);
+        let mut total = 0.;
+        for i in 0..n_theta {
+            let theta = (i as f32 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f32 + 0.5) * d_phi;
+                let light = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                let (diffuse, specular) = ShadingModel::cook_torrance_color(
+                    (ks, roughness),
+                    &Color::new(1., 1., 1.),
+                    &-light,
+                    &normal,
+                    &-eye,
+                    Color::new(1., 1., 1.),
+                    |_| Color::new(1., 1., 1.),
+                );
+                // the diffuse part is the lambertian BRDF times pi
+                let reflected = diffuse[1] / PI + specular[1];
+                total += reflected * theta.sin() * d_theta * d_phi;
+            }
+        }
+        total
+    }
+
+    #[test]
+    fn cook_torrance_does_not_add_energy() {
+        for roughness in [0.1, 0.3, 0.6, 1.] {
+            for degrees in [0f32, 45., 80., 89.] {
+                let angle = degrees.to_radians();
+                // without specular reflection the material is lambertian, minus the light
+                // reflected at the surface
+                let diffuse = cook_torrance_albedo(0., roughness, angle);
+                let fresnel = 0.57 + 0.43 * (1. - angle.cos()).powi(5);
+                assert!((diffuse - (1. - fresnel)).abs() < 1e-3, "{diffuse}");
+                for ks in [0.5, 1.] {
+                    let albedo = cook_torrance_albedo(ks, roughness, angle);
+                    assert!(albedo <= 1.001, "{roughness} {ks} {degrees}: {albedo}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cook_torrance_diffuse_leaves_the_specular_light_out() {
+        let normal = Vec3::new(0., 0., 1.);
+        let light = Vec3::new(0., 0.6, 0.8);
+        let diffuse = |ks: f32, view_angle: f32| {
+            let eye = Vec3::new(view_angle.sin(), 0., view_angle.cos());
+            let (diffuse, _) = ShadingModel::cook_torrance_color(
+                (ks, 0.5),
+                &Color::new(1., 1., 1.),
+                &-light,
+                &normal,
+                &-eye,
+                Color::new(1., 1., 1.),
+                |_| Color::new(1., 1., 1.),
+            );
+            diffuse[1]
+        };
+        // a fully specular material has no diffuse part
+        assert!(diffuse(1., 0.) < 1e-6, "{}", diffuse(1., 0.));
+        // the diffuse part is weighted by 1 - ks and fades out at grazing angles
+        let front = diffuse(0.5, 0.);
+        assert!((front - 0.8 * 0.5 * (1. - 0.57)).abs() < 1e-4, "{front}");
+        assert!(diffuse(0.5, 85f32.to_radians()) < 0.5 * front);
+
+        // the ambient part is weighted the same way
+        let ambient = |ks: f32, view: Vec3| {
+            let shading = ShadingModel::CookTorrance {
+                ka: 0.5,
+                ks,
+                roughness: 0.5,
+            };
+            shading.ambient(&normal, &-view)[1]
+        };
+        assert!(ambient(1., normal) < 1e-6);
+        let front = ambient(0.5, normal);
+        assert!((front - 0.5 * 0.5 * (1. - 0.57)).abs() < 1e-4, "{front}");
+        let grazing = Vec3::new(85f32.to_radians().sin(), 0., 85f32.to_radians().cos());
+        assert!(ambient(0.5, grazing) < 0.5 * front);
+        let phong = ShadingModel::Phong {
+            ka: 0.5,
+            kd: 1.,
+            ks: 1.,
+            exp: 8,
+        };
+        assert_eq!(phong.ambient(&normal, &-normal), Color::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn thin_film_matches_reflectance_at_normal_incidence() {
+        // a soap film in air reflects nothing when it is much thinner than the wavelength or half
+        // a wavelength thick, and the most light when it is a quarter wavelength thick
+        let wavelength = 550.;
+        let quarter = wavelength / (4. * 1.33);
+        let soap = |thickness| ThinFilm::new(thickness, 1.33).reflectance(wavelength, 1., 1.);
+        assert!(soap(0.).abs() < 1e-6);
+        assert!((soap(quarter) - 0.0771).abs() < 1e-3, "{}", soap(quarter));
+        assert!(soap(2. * quarter).abs() < 1e-5, "{}", soap(2. * quarter));
+
+        // without thickness the film vanishes and only the glass below reflects
+        let on_glass = ThinFilm::new(0., 1.33).reflectance(wavelength, 1., 1.5);
+        assert!((on_glass - 0.04).abs() < 1e-3, "{on_glass}");
+    }
+
+    #[test]
+    fn thin_film_tint_averages_to_white() {
+        let mut average = Color::zero();
+        for thickness in 0..5000 {
+            #[allow(clippy::cast_precision_loss)]
+            let film = ThinFilm::new(thickness as f32, 1.33);
+            average += film.tint(0.8, 1.5) * (1. / 5000.);
+        }
+        assert!(
+            (average - Color::new(1., 1., 1.)).length() < 0.05,
+            "{average:?}"
+        );
+    }
+
+    #[test]
+    fn animated_thin_film_cycles_hues() {
+        let base = Material::new(
+            Texture::Color(Color::zero()),
+            0.5,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 0.,
+                kd: 0.,
+                ks: 1.,
+                exp: 10,
+            },
+        );
+        let mut material = base
+            .clone()
+            .with_thin_film(ThinFilm::new(300., 1.33).with_end(500.));
+        assert!(material.is_animated() && !base.is_animated());
+
+        let normal = Vec3::new(0., 0., 1.);
+        let ray = Ray::new(Point3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+        assert_eq!(base.reflection_tint(&normal, &ray), Color::new(1., 1., 1.));
+        let start = material.reflection_tint(&normal, &ray);
+        material.set_frame(0.5);
+        assert!((material.thin_film().unwrap().thickness() - 400.).abs() < 1e-3);
+        let middle = material.reflection_tint(&normal, &ray);
+        // the strongest channel changes while the film grows
+        let strongest = |c: Color| (0..3).max_by(|&a, &b| c[a].total_cmp(&c[b])).unwrap();
+        assert_ne!(strongest(start), strongest(middle), "{start:?} {middle:?}");
+    }
+
+    #[test]
+    fn scale_property_multiplies_and_clamps() {
+        let phong = Material::new(
+            Texture::Color(Color::zero()),
+            0.4,
+            0.2,
+            1.,
+            ShadingModel::Phong {
+                ka: 0.1,
+                kd: 0.6,
+                ks: 0.5,
+                exp: 20,
+            },
+        )
+        .with_glossy_reflection(0.2, None);
+        let mut material = phong.clone();
+        for (name, factor) in [
+            ("kd", 0.5),
+            ("ks", 4.),
+            ("exponent", 1.26),
+            ("roughness", 2.),
+        ] {
+            assert!(material.scale_property(name, factor));
+        }
+        assert_eq!(
+            material.shading,
+            ShadingModel::Phong {
+                ka: 0.1,
+                kd: 0.3,
+                ks: 1.,
+                exp: 25,
+            }
+        );
+        assert!((material.reflection_roughness() - 0.4).abs() < 1e-6);
+        material.scale_property("exponent", 0.);
+        assert!(matches!(
+            material.shading,
+            ShadingModel::Phong { exp: 1, .. }
+        ));
+
+        let mut material = phong.clone();
+        assert!(material.scale_property("reflectance", 3.));
+        assert!(material.scale_property("transmittance", -1.));
+        assert!((material.reflectance() - 1.).abs() < 1e-6 && material.transmittance() == 0.);
+        assert!(!material.scale_property("shininess", 2.));
+
+        let mut rough = Material::new(
+            Texture::Color(Color::zero()),
+            0.,
+            0.,
+            1.,
+            ShadingModel::CookTorrance {
+                ka: 0.1,
+                ks: 0.5,
+                roughness: 0.6,
+            },
+        )
+        .with_clearcoat(ClearCoat {
+            strength: 1.,
+            roughness: 0.3,
+        });
+        // properties that the shading model doesn't have are valid names but change nothing
+        assert!(rough.scale_property("exponent", 2.) && rough.scale_property("kd", 2.));
+        rough.scale_property("roughness", 2.);
+        assert!(matches!(
+            rough.shading,
+            ShadingModel::CookTorrance { roughness, .. } if (roughness - 1.).abs() < 1e-6
+        ));
+        assert!((rough.clearcoat().unwrap().roughness - 0.6).abs() < 1e-6);
+        rough.scale_property("roughness", 0.);
+        assert!(rough.clearcoat().unwrap().roughness > 0.);
+    }
+}
@@ -1,40 +1,129 @@
-use crate::math::{max, min, Point3, Ray, Vec3};
+use std::sync::Arc;
 
-use super::Texel;
+use crate::math::{max, min, Mat4, Point3, Ray, Vec3};
+use crate::objects::stats;
+
+use super::{Texel, Traceable};
+
+/// How the normal of a triangle is determined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriangleShading {
+    /// the face normal is used everywhere on the triangle
+    Flat,
+    /// the vertex normals are interpolated
+    Smooth,
+}
 
 /// struct to represent a triangle in 3D-Space
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Triangle {
     points: [Point3; 3],
     normals: [Vec3; 3],
     texcoords: [Texel; 3],
+    flat_normal: Vec3,
+    shading: TriangleShading,
 }
 
 impl Triangle {
     const INTERSECT_EPS: f32 = 1e-8;
 
-    /// Create a new triangle from the edge points and the corresponding normals
+    /// Create a new smooth shaded triangle from the edge points and the corresponding normals
     /// The normals and the points should be in the same order in the arrays
     #[must_use]
     pub fn new(points: [Point3; 3], normals: [Vec3; 3], texcoords: [Texel; 3]) -> Triangle {
+        let e1 = points[1] - points[0];
+        let e2 = points[2] - points[0];
+        let mut flat_normal = Vec3::normal(&e1.cross(&e2));
+        // face the same side as the vertex normals, in case the winding order doesn't match them
+        if flat_normal.dot(&(normals[0] + normals[1] + normals[2])) < 0. {
+            flat_normal = -flat_normal;
+        }
         Triangle {
             points,
             normals,
             texcoords,
+            flat_normal,
+            shading: TriangleShading::Smooth,
         }
     }
 
+    /// Set how the normal of the triangle is determined
+    pub fn set_shading(&mut self, shading: TriangleShading) {
+        self.shading = shading;
+    }
+
+    /// Return the points of the triangle
+    #[must_use]
+    pub fn points(&self) -> &[Point3; 3] {
+        &self.points
+    }
+
+    /// Return the vertex normals of the triangle
+    #[must_use]
+    pub fn normals(&self) -> &[Vec3; 3] {
+        &self.normals
+    }
+
+    /// Return the texture coordinates of the points of the triangle
+    #[must_use]
+    pub fn texcoords(&self) -> &[Texel; 3] {
+        &self.texcoords
+    }
+
+    /// Return the face normal of the triangle
+    #[must_use]
+    pub fn flat_normal(&self) -> Vec3 {
+        self.flat_normal
+    }
+
+    /// Return how the normal of the triangle is determined
+    #[must_use]
+    pub fn shading(&self) -> TriangleShading {
+        self.shading
+    }
+
+    /// Return the triangle with the points transformed by `transform` and the normals by
+    /// `normal_transform`
+    #[must_use]
+    pub fn transformed(&self, transform: &Mat4, normal_transform: &Mat4) -> Triangle {
+        let mut triangle = Triangle::new(
+            self.points.map(|p| transform.transform_point(&p)),
+            self.normals.map(|n| normal_transform.transform_vector(&n)),
+            self.texcoords,
+        );
+        triangle.shading = self.shading;
+        triangle
+    }
+
+    /// Return the area of the triangle in object space and in texture space
+    fn areas(&self) -> (f32, f32) {
+        let e1 = self.points[1] - self.points[0];
+        let e2 = self.points[2] - self.points[0];
+        let t = self.texcoords;
+        let (du1, dv1) = (t[1].0 - t[0].0, t[1].1 - t[0].1);
+        let (du2, dv2) = (t[2].0 - t[0].0, t[2].1 - t[0].1);
+        (
+            0.5 * e1.cross(&e2).length(),
+            0.5 * (du1 * dv2 - du2 * dv1).abs(),
+        )
+    }
+
     /// Return the normal for the given barycentric coordinates
     fn normal_at(&self, a: f32, b: f32) -> Vec3 {
-        (1. - a - b) * self.normals[0] + a * self.normals[1] + b * self.normals[2]
+        match self.shading {
+            TriangleShading::Flat => self.flat_normal,
+            TriangleShading::Smooth => {
+                (1. - a - b) * self.normals[0] + a * self.normals[1] + b * self.normals[2]
+            }
+        }
     }
 
     /// Return the texel at the given barycentric coordinates
     fn texel_at(&self, a: f32, b: f32) -> (f32, f32) {
         let t = self.texcoords;
         (
-            ((1. - a - b) * t[0].0 + a * t[1].0 + b * t[2].0) % 1.,
-            ((1. - a - b) * t[0].1 + a * t[1].1 + b * t[2].1) % 1.,
+            ((1. - a - b) * t[0].0 + a * t[1].0 + b * t[2].0).rem_euclid(1.),
+            ((1. - a - b) * t[0].1 + a * t[1].1 + b * t[2].1).rem_euclid(1.),
         )
     }
 
@@ -42,91 +131,89 @@ impl Triangle {
     /// using the [Moeller-Trombore algorithm](https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection.html)
     #[must_use]
     pub fn has_intersection(&self, with: &Ray) -> bool {
-        let e1 = self.points[1] - self.points[0];
-        let e2 = self.points[2] - self.points[0];
-        let dxe2 = with.dir().cross(&e2);
-        let det = e1.dot(&dxe2);
-
-        if det.abs() < Self::INTERSECT_EPS {
-            return false;
-        }
+        Edges::new(&self.points).intersection(with).is_some()
+    }
 
-        let inv_det = 1. / det;
+    /// Calculates the normal, the texel and the t value of the triangle and the `with` Ray if present
+    /// using the [Moeller-Trombore algorithm](https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection.html)
+    /// Returns `None` if there is no intersection
+    #[must_use]
+    pub fn intersection(&self, with: &Ray) -> Option<(Vec3, Texel, f32)> {
+        let (t, a, b) = Edges::new(&self.points).intersection(with)?;
+        Some((self.normal_at(a, b), self.texel_at(a, b), t))
+    }
+}
 
-        let s = *with.orig() - self.points[0];
-        let a = s.dot(&dxe2) * inv_det;
-        if !(0. ..=1.).contains(&a) {
-            return false;
-        }
+/// The first point of a triangle and the two edges that start at it, as used by the intersection
+/// test
+#[derive(Clone, Copy, Debug)]
+struct Edges {
+    origin: Point3,
+    e1: Vec3,
+    e2: Vec3,
+}
 
-        let sxe1 = s.cross(&e1);
-        let b = with.dir().dot(&sxe1) * inv_det;
-        if b < 0. || a + b > 1. {
-            return false;
+impl Edges {
+    /// Return the edges of the triangle with the corners `points`
+    #[inline]
+    fn new(points: &[Point3; 3]) -> Edges {
+        Edges {
+            origin: points[0],
+            e1: points[1] - points[0],
+            e2: points[2] - points[0],
         }
-
-        let t = e2.dot(&sxe1) * inv_det;
-
-        with.t_in_range(t)
     }
 
-    /// Calculates the normal, the texel and the t value of the triangle and the `with` Ray if present
+    /// Calculate the t value and the barycentric coordinates of the intersection with the ray
     /// using the [Moeller-Trombore algorithm](https://www.scratchapixel.com/lessons/3d-basic-rendering/ray-tracing-rendering-a-triangle/moller-trumbore-ray-triangle-intersection.html)
     /// Returns `None` if there is no intersection
-    #[must_use]
-    pub fn intersection(&self, with: &Ray) -> Option<(Vec3, Texel, f32)> {
-        let e1 = self.points[1] - self.points[0];
-        let e2 = self.points[2] - self.points[0];
-        let dxe2 = with.dir().cross(&e2);
-        let det = e1.dot(&dxe2);
+    #[inline]
+    fn intersection(&self, with: &Ray) -> Option<(f32, f32, f32)> {
+        let dxe2 = with.dir().cross(&self.e2);
+        let det = self.e1.dot(&dxe2);
 
-        if det.abs() < Self::INTERSECT_EPS {
+        if det.abs() < Triangle::INTERSECT_EPS {
             return None;
         }
 
         let inv_det = 1. / det;
 
-        let s = *with.orig() - self.points[0];
+        let s = *with.orig() - self.origin;
         let a = s.dot(&dxe2) * inv_det;
         if !(0. ..=1.).contains(&a) {
             return None;
         }
 
-        let sxe1 = s.cross(&e1);
+        let sxe1 = s.cross(&self.e1);
         let b = with.dir().dot(&sxe1) * inv_det;
         if b < 0. || a + b > 1. {
             return None;
         }
 
-        let t = e2.dot(&sxe1) * inv_det;
+        let t = self.e2.dot(&sxe1) * inv_det;
 
-        if with.t_in_range(t) {
-            Some((self.normal_at(a, b), self.texel_at(a, b), t))
-        } else {
-            None
-        }
+        with.t_in_range(t).then_some((t, a, b))
     }
 }
 
 /// Axis-aligned bounding box (AABB)
 #[derive(Clone, Debug)]
-struct BoundingBox {
+pub(super) struct BoundingBox {
     min: Vec3,
     max: Vec3,
 }
 
 impl BoundingBox {
     /// Constructs a bounding box that encapsulates all given points
+    /// Coordinates that are NaN are ignored
     pub fn from(points: &[Point3]) -> BoundingBox {
-        let cmp_f32 =
-            |lhs: &f32, rhs: &f32| lhs.partial_cmp(rhs).expect("Points should not contain NaN");
-
-        let min_x = points.iter().map(|p| p[0]).min_by(cmp_f32).unwrap_or(0.);
-        let max_x = points.iter().map(|p| p[0]).max_by(cmp_f32).unwrap_or(0.);
-        let min_y = points.iter().map(|p| p[1]).min_by(cmp_f32).unwrap_or(0.);
-        let max_y = points.iter().map(|p| p[1]).max_by(cmp_f32).unwrap_or(0.);
-        let min_z = points.iter().map(|p| p[2]).min_by(cmp_f32).unwrap_or(0.);
-        let max_z = points.iter().map(|p| p[2]).max_by(cmp_f32).unwrap_or(0.);
+        let axis = |i: usize| points.iter().map(move |p| p[i]).filter(|c| !c.is_nan());
+        let min_x = axis(0).min_by(f32::total_cmp).unwrap_or(0.);
+        let max_x = axis(0).max_by(f32::total_cmp).unwrap_or(0.);
+        let min_y = axis(1).min_by(f32::total_cmp).unwrap_or(0.);
+        let max_y = axis(1).max_by(f32::total_cmp).unwrap_or(0.);
+        let min_z = axis(2).min_by(f32::total_cmp).unwrap_or(0.);
+        let max_z = axis(2).max_by(f32::total_cmp).unwrap_or(0.);
 
         BoundingBox {
             min: Vec3::new(min_x, min_y, min_z),
@@ -134,6 +221,11 @@ impl BoundingBox {
         }
     }
 
+    /// Return the minimum and the maximum corner of the box
+    pub fn corners(&self) -> (Point3, Point3) {
+        (self.min, self.max)
+    }
+
     /// Determine if bounding box intersects with the ray
     /// using [Smits method](https://people.csail.mit.edu/amy/papers/box-jgt.pdf)
     #[allow(clippy::similar_names)]
@@ -190,15 +282,69 @@ impl BoundingBox {
 
         (tmin < with.max_t()) && (tmax > 0.)
     }
+
+    /// Constructs the bounding box of the box from `min` to `max` after applying `transform`
+    /// to it, grown by a small margin so rounding can't push surface points outside of it
+    /// Returns `None` if the transformed corners are not finite
+    pub fn transformed(min: Point3, max: Point3, transform: &Mat4) -> Option<BoundingBox> {
+        let corners: [Point3; 8] = std::array::from_fn(|i| {
+            transform.transform_point(&Point3::new(
+                if i & 1 == 0 { min[0] } else { max[0] },
+                if i & 2 == 0 { min[1] } else { max[1] },
+                if i & 4 == 0 { min[2] } else { max[2] },
+            ))
+        });
+        if !corners.iter().all(Vec3::is_finite) {
+            return None;
+        }
+        let bounds = BoundingBox::from(&corners);
+        let margin = 1e-4 * (bounds.max - bounds.min).length().max(1.);
+        let margin = Vec3::new(margin, margin, margin);
+        Some(BoundingBox {
+            min: bounds.min - margin,
+            max: bounds.max + margin,
+        })
+    }
+
+    /// Conservatively determine if the ray may hit the bounding box in the range of the ray
+    /// Unlike [`BoundingBox::has_intersection`], rays that are parallel to a side of the box are
+    /// handled explicitly, so a ray is never rejected because of a division of zero by zero
+    pub fn may_intersect(&self, with: &Ray) -> bool {
+        let (mut t_near, mut t_far) = (0f32, with.max_t());
+        for axis in 0..3 {
+            let (o, d) = (with.orig()[axis], with.dir()[axis]);
+            if d == 0. {
+                if o < self.min[axis] || o > self.max[axis] {
+                    return false;
+                }
+                continue;
+            }
+            let t1 = (self.min[axis] - o) / d;
+            let t2 = (self.max[axis] - o) / d;
+            t_near = t_near.max(t1.min(t2));
+            t_far = t_far.min(t1.max(t2));
+        }
+        t_near <= t_far
+    }
 }
 
 /// struct to represent a mesh in a 3D-Space
 /// Holds a Triangle 'soup' and material
 /// also contains a bounding box to speed up intersection tests
-#[derive(Debug)]
+/// The positions, normals and texture coordinates of the triangles are stored in separate arrays,
+/// so the intersection loop only walks over the positions. The other attributes are only looked
+/// up for the closest hit, the [`Triangle`] is rebuilt from the arrays when it is needed
+/// The arrays are shared between clones, so copies of a mesh don't duplicate its geometry
+#[derive(Clone, Debug)]
 pub(super) struct Mesh {
-    triangles: Vec<Triangle>,
+    positions: Arc<[[Point3; 3]]>,
+    normals: Arc<[[Vec3; 3]]>,
+    texcoords: Arc<[[Texel; 3]]>,
+    shading: Arc<[TriangleShading]>,
+    /// running sum of the areas of the triangles, to pick triangles by their area
+    cumulative_areas: Arc<[f32]>,
     bounding_box: BoundingBox,
+    texel_density: f32,
 }
 
 impl Mesh {
@@ -210,35 +356,133 @@ impl Mesh {
                 .flat_map(|tri| tri.points)
                 .collect::<Vec<_>>(),
         );
+        let mut cumulative_areas = Vec::with_capacity(triangles.len());
+        let (area, uv_area) =
+            triangles
+                .iter()
+                .map(Triangle::areas)
+                .fold((0., 0.), |acc: (f32, f32), a| {
+                    cumulative_areas.push(acc.0 + a.0);
+                    (acc.0 + a.0, acc.1 + a.1)
+                });
+        let texel_density = if area > 0. {
+            (uv_area / area).sqrt()
+        } else {
+            0.
+        };
         Mesh {
-            triangles,
+            positions: triangles.iter().map(|tri| tri.points).collect(),
+            normals: triangles.iter().map(|tri| tri.normals).collect(),
+            texcoords: triangles.iter().map(|tri| tri.texcoords).collect(),
+            cumulative_areas: cumulative_areas.into(),
             bounding_box,
+            texel_density,
+            shading: triangles.into_iter().map(|tri| tri.shading).collect(),
         }
     }
 
+    /// Return the number of triangles of the mesh
+    pub fn triangle_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Return the triangle `index` of the mesh
+    fn triangle(&self, index: usize) -> Triangle {
+        let mut triangle = Triangle::new(
+            self.positions[index],
+            self.normals[index],
+            self.texcoords[index],
+        );
+        triangle.shading = self.shading[index];
+        triangle
+    }
+
+    /// Return the triangles of the mesh
+    pub fn triangles(&self) -> impl Iterator<Item = Triangle> + '_ {
+        (0..self.triangle_count()).map(|index| self.triangle(index))
+    }
+
+    /// Return the index, the t value and the barycentric coordinates of every triangle that the
+    /// ray hits
+    fn hits<'a>(&'a self, with: &'a Ray) -> impl Iterator<Item = (usize, (f32, f32, f32))> + 'a {
+        self.positions
+            .iter()
+            .inspect(|_| stats::count_triangle_test())
+            .enumerate()
+            .filter_map(|(i, points)| Some((i, Edges::new(points).intersection(with)?)))
+    }
+
+    /// Return the intersection with the triangle `index` at the barycentric coordinates `a`, `b`
+    fn hit_at(&self, index: usize, (t, a, b): (f32, f32, f32)) -> (f32, Vec3, Texel) {
+        let triangle = self.triangle(index);
+        (t, triangle.normal_at(a, b), triangle.texel_at(a, b))
+    }
+}
+
+impl Traceable for Mesh {
+    fn kind(&self) -> &'static str {
+        "mesh"
+    }
+
+    /// Average ratio of texture space to object space over all triangles
+    fn texel_density(&self) -> f32 {
+        self.texel_density
+    }
+
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        Some((self.bounding_box.min, self.bounding_box.max))
+    }
+
+    fn intersection_epsilon(&self) -> f32 {
+        Triangle::INTERSECT_EPS
+    }
+
     /// Test if the mesh intersects with the ray
-    pub fn has_intersection(&self, with: &Ray) -> bool {
-        if self.bounding_box.has_intersection(with) {
-            self.triangles.iter().any(|t| t.has_intersection(with))
-        } else {
-            false
+    fn has_intersection(&self, with: &Ray) -> bool {
+        stats::count_box_test();
+        self.bounding_box.has_intersection(with) && self.hits(with).next().is_some()
+    }
+
+    /// Report the intersections with every triangle that the ray hits
+    fn intersections_all(&self, with: &Ray, hit: &mut dyn FnMut(f32, Vec3, Texel)) {
+        stats::count_box_test();
+        if !self.bounding_box.has_intersection(with) {
+            return;
+        }
+        for (index, coords) in self.hits(with) {
+            let (t, normal, texel) = self.hit_at(index, coords);
+            hit(t, normal, texel);
         }
     }
 
+    /// Pick a triangle with a probability proportional to its area, then a uniform point on it
+    fn sample_area(&self, pick: f32, (a, b): (f32, f32)) -> Option<(Point3, Vec3)> {
+        let total = self.cumulative_areas.last().copied().filter(|&a| a > 0.)?;
+        let index = self
+            .cumulative_areas
+            .partition_point(|&area| area <= pick * total)
+            .min(self.triangle_count() - 1);
+        let triangle = self.triangle(index);
+        let [p0, p1, p2] = triangle.points;
+        // the square root spreads the points evenly instead of bunching them at the first point
+        let s = a.sqrt();
+        let point = (1. - s) * p0 + s * (1. - b) * p1 + s * b * p2;
+        Some((point, triangle.flat_normal))
+    }
+
     /// Calculates the intersection of the mesh and the `with` Ray if present
     /// Returns `None` if there is no intersection
-    pub fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+    fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+        stats::count_box_test();
         if !self.bounding_box.has_intersection(with) {
             return None;
         }
 
-        let (normal, texel, t) = self
-            .triangles
-            .iter()
-            .filter_map(|t| t.intersection(with))
-            .min_by(|lhs, rhs| lhs.2.partial_cmp(&rhs.2).expect("t should not be NaN"))?;
+        let (index, coords) = self
+            .hits(with)
+            .min_by(|(_, (lhs_t, _, _)), (_, (rhs_t, _, _))| lhs_t.total_cmp(rhs_t))?;
 
-        Some((t, normal, texel))
+        Some(self.hit_at(index, coords))
     }
 }
 
@@ -272,6 +516,181 @@ mod tests {
         assert!(triangle.intersection(&no_hit).is_none());
     }
 
+    #[test]
+    fn transformed_bounding_box() {
+        let (min, max) = (Point3::new(-1., -1., -1.), Point3::new(1., 1., 1.));
+        let rotated = Mat4::from_y_rotation(std::f32::consts::FRAC_PI_4);
+        let aabb = BoundingBox::transformed(min, max, &rotated).unwrap();
+        // the corners of the rotated cube lie sqrt(2) away from the y-axis
+        assert!((aabb.max[0] - 2f32.sqrt()).abs() < 1e-3);
+        assert!((aabb.min[2] + 2f32.sqrt()).abs() < 1e-3);
+        assert!((aabb.max[1] - 1.).abs() < 1e-3);
+
+        let huge = Mat4::from_translation(Vec3::new(f32::INFINITY, 0., 0.));
+        assert!(BoundingBox::transformed(min, max, &huge).is_none());
+    }
+
+    #[test]
+    fn bounding_box_may_intersect() {
+        let aabb = BoundingBox::from(&[Point3::new(-1., 0., -3.), Point3::new(0., 1., -2.)]);
+        // parallel to the sides and starting on the planes of two of them
+        let on_side = Ray::new(Point3::new(0., 0., 0.), Vec3::new(0., 0., -1.));
+        assert!(aabb.may_intersect(&on_side));
+
+        let beside = Ray::new(Point3::new(0.5, 0.5, 0.), Vec3::new(0., 0., -1.));
+        assert!(!aabb.may_intersect(&beside));
+        let behind = Ray::new(Point3::new(-0.5, 0.5, 0.), Vec3::new(0., 0., 1.));
+        assert!(!aabb.may_intersect(&behind));
+        let too_short = Ray::new(Point3::new(-0.5, 0.5, 0.), Vec3::new(0., 0., -1.)).set_bounds(1.);
+        assert!(!aabb.may_intersect(&too_short));
+        let inside = Ray::new(Point3::new(-0.5, 0.5, -2.5), Vec3::new(1., 1., 1.)).set_bounds(1.);
+        assert!(aabb.may_intersect(&inside));
+    }
+
+    #[test]
+    fn flat_shading_splits_normals_at_shared_edge() {
+        // two triangles folded along the edge from (0, 0, 0) to (0, 1, 0)
+        let shared = Vec3::new(0., 0., 1.);
+        let mut left = Triangle::new(
+            [
+                Point3::new(-1., 0., -1.),
+                Point3::new(0., 0., 0.),
+                Point3::new(0., 1., 0.),
+            ],
+            [Vec3::normal(&Vec3::new(-1., 0., 1.)), shared, shared],
+            [(0., 0.); 3],
+        );
+        let mut right = Triangle::new(
+            [
+                Point3::new(0., 0., 0.),
+                Point3::new(1., 0., -1.),
+                Point3::new(0., 1., 0.),
+            ],
+            [shared, Vec3::normal(&Vec3::new(1., 0., 1.)), shared],
+            [(0., 0.); 3],
+        );
+        // hits both triangles on the shared edge
+        let ray = Ray::new(Point3::new(0., 0.5, 2.), Vec3::new(0., 0., -1.));
+
+        let (smooth_left, _, _) = left.intersection(&ray).unwrap();
+        let (smooth_right, _, _) = right.intersection(&ray).unwrap();
+        assert!((smooth_left - smooth_right).length() < 1e-6);
+
+        left.set_shading(TriangleShading::Flat);
+        right.set_shading(TriangleShading::Flat);
+        let (flat_left, _, _) = left.intersection(&ray).unwrap();
+        let (flat_right, _, _) = right.intersection(&ray).unwrap();
+        assert!((flat_left - Vec3::normal(&Vec3::new(-1., 0., 1.))).length() < 1e-6);
+        assert!((flat_right - Vec3::normal(&Vec3::new(1., 0., 1.))).length() < 1e-6);
+    }
+
+    /// Return a wavy grid of `2 * n * n` triangles over the unit square at z = -1
+    fn grid(n: u16) -> Vec<Triangle> {
+        let point = |x: u16, y: u16| {
+            let (x, y) = (f32::from(x) / f32::from(n), f32::from(y) / f32::from(n));
+            Point3::new(
+                x - 0.5,
+                y - 0.5,
+                -1. - 0.1 * (10. * x).sin() * (7. * y).cos(),
+            )
+        };
+        let normal = Vec3::new(0., 0., 1.);
+        (0..n)
+            .flat_map(|x| (0..n).map(move |y| (x, y)))
+            .flat_map(|(x, y)| {
+                let texel =
+                    |x: u16, y: u16| (f32::from(x) / f32::from(n), f32::from(y) / f32::from(n));
+                [
+                    Triangle::new(
+                        [point(x, y), point(x + 1, y), point(x, y + 1)],
+                        [normal; 3],
+                        [texel(x, y), texel(x + 1, y), texel(x, y + 1)],
+                    ),
+                    Triangle::new(
+                        [point(x + 1, y), point(x + 1, y + 1), point(x, y + 1)],
+                        [normal; 3],
+                        [texel(x + 1, y), texel(x + 1, y + 1), texel(x, y + 1)],
+                    ),
+                ]
+            })
+            .collect()
+    }
+
+    /// Closest hit of the ray, found by testing the triangles one by one
+    fn closest_triangle_hit(triangles: &[Triangle], ray: &Ray) -> Option<(Vec3, Texel, f32)> {
+        triangles
+            .iter()
+            .filter_map(|t| t.intersection(ray))
+            .min_by(|lhs, rhs| lhs.2.partial_cmp(&rhs.2).unwrap())
+    }
+
+    /// Rays from the origin through a `count` x `count` grid on the mesh
+    fn grid_rays(count: u16) -> impl Iterator<Item = Ray> {
+        (0..count).flat_map(move |x| {
+            (0..count).map(move |y| {
+                let (x, y) = (
+                    f32::from(x) / f32::from(count),
+                    f32::from(y) / f32::from(count),
+                );
+                Ray::new(Point3::zero(), Vec3::new(0.8 * x - 0.4, 0.8 * y - 0.4, -1.))
+            })
+        })
+    }
+
+    #[test]
+    fn mesh_intersection_matches_triangles() {
+        let triangles = grid(8);
+        let mesh = Mesh::new(triangles.clone());
+        assert!(mesh.triangles().eq(triangles.iter().cloned()));
+        for ray in grid_rays(20) {
+            let (t, normal, texel) = mesh.intersection(&ray).unwrap();
+            let (expected_normal, expected_texel, expected_t) =
+                closest_triangle_hit(&triangles, &ray).unwrap();
+            assert!((t - expected_t).abs() < 1e-6);
+            assert!((normal - expected_normal).length() < 1e-6);
+            assert!((texel.0 - expected_texel.0).abs() < 1e-6);
+            assert!((texel.1 - expected_texel.1).abs() < 1e-6);
+            assert!(mesh.has_intersection(&ray));
+        }
+        let miss = Ray::new(Point3::zero(), Vec3::new(1., 0., -1.));
+        assert!(mesh.intersection(&miss).is_none());
+        assert!(!mesh.has_intersection(&miss));
+    }
+
+    /// Benchmark of the position array against testing whole triangles, run with
+    /// `cargo test --release -- --ignored --nocapture`
+    #[test]
+    #[ignore = "benchmark, intersects a mesh with 500k triangles"]
+    fn bench_mesh_intersection() {
+        let triangles = grid(500);
+        let mesh = Mesh::new(triangles.clone());
+        let rays: Vec<_> = grid_rays(16).collect();
+
+        let start = std::time::Instant::now();
+        let packed: f32 = rays
+            .iter()
+            .filter_map(|r| mesh.intersection(r))
+            .map(|h| h.0)
+            .sum();
+        let packed_time = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let whole: f32 = rays
+            .iter()
+            .filter_map(|r| closest_triangle_hit(&triangles, r))
+            .map(|h| h.2)
+            .sum();
+        let whole_time = start.elapsed();
+
+        assert!((packed - whole).abs() < 1e-3);
+        println!(
+            "{} rays against {} triangles: position array {packed_time:?}, whole triangles \
+             {whole_time:?}",
+            rays.len(),
+            triangles.len()
+        );
+    }
+
     #[test]
     fn construct_bounding_box() {
         let points = vec![
@@ -284,6 +703,14 @@ mod tests {
 
         assert_eq!(aabb.min, Vec3::new(-1., 0., -1.));
         assert_eq!(aabb.max, Vec3::new(1., 1., -1.));
+
+        // NaN coordinates are ignored, infinite ones widen the box
+        let aabb = BoundingBox::from(&[
+            Point3::new(-1., 0., -1.),
+            Point3::new(f32::NAN, f32::INFINITY, f32::NEG_INFINITY),
+        ]);
+        assert_eq!(aabb.min, Vec3::new(-1., 0., f32::NEG_INFINITY));
+        assert_eq!(aabb.max, Vec3::new(-1., f32::INFINITY, -1.));
     }
 
     #[test]
@@ -1,63 +1,157 @@
-use crate::math::{Mat4, Point3, Quat, Ray, Vec3};
+use std::any::Any;
+
+use crate::math::{Mat4, Point3, Quat, Ray, Vec3, BIAS};
+use crate::objects::camera::Frustum;
+use crate::objects::surface::disc::Disc;
+use crate::objects::surface::ellipsoid::Ellipsoid;
 use crate::objects::surface::julia_set::JuliaSet;
-use crate::objects::surface::mesh::Mesh;
+use crate::objects::surface::mesh::{BoundingBox, Mesh};
+use crate::objects::surface::quad::Quad;
 use crate::objects::surface::sphere::Sphere;
+use crate::objects::surface::spin::Spin;
 
+mod clip;
+mod decimate;
+mod disc;
+mod ellipsoid;
 mod intersection;
 mod julia_set;
 mod material;
 mod mesh;
+mod quad;
+mod scatter;
 mod sphere;
+mod spin;
+mod traceable;
+mod waves;
 
+pub use clip::ClipPlane;
+pub use decimate::decimate;
 pub use intersection::Intersection;
-pub use material::{Material, ShadingModel, Texture};
-pub use mesh::Triangle;
+pub use material::{ClearCoat, GradientAxis, Material, ShadingModel, Texture, ThinFilm};
+pub use mesh::{Triangle, TriangleShading};
+pub use scatter::Scatter;
+pub use traceable::Traceable;
+pub use waves::Waves;
 
-type Texel = (f32, f32);
+/// texture coordinates `(u, v)`
+pub type Texel = (f32, f32);
 
-/// either a sphere or a mesh
-#[derive(Debug)]
-enum Object {
-    Sphere(Sphere),
-    Mesh(Box<Mesh>), // Box to keep the enum small
-    JuliaSet(Box<JuliaSet>),
-}
+/// a point where a ray crosses the surface of a primitive
+/// the `t` value, the (not necessarily normalized) outward normal and the texel
+pub type HitPoint = (f32, Vec3, Texel);
 
 /// struct that bundles the (inverse) transformation
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 struct Transform {
     transform: Mat4,
     normal_transform: Mat4,
 }
 
 /// struct to represent any surface in 3D
-/// Either a `Sphere` or a `Mesh`
-#[derive(Debug)]
+/// Holds any `Traceable` primitive together with its transformation and material
+#[derive(Clone, Debug)]
 pub struct Surface {
-    obj: Object,
+    obj: Box<dyn Traceable>,
     transform: Option<Box<Transform>>,
     material: Box<Material>, // box to keep the type small
+    layer: Option<String>,
+    /// flip normals towards the ray, so both sides of open meshes are shaded as front faces
+    both_sides: bool,
+    /// only camera rays see the surface, it casts no shadows and doesn't show up in reflections
+    /// or refractions
+    camera_only: bool,
+    /// planes that cut away the parts of the surface on their positive side
+    clip: Vec<ClipPlane>,
+    /// bounding box in world space of the current frame, tested before the ray is transformed
+    /// into object space
+    bounds: Option<BoundingBox>,
+    /// percentage of the animation that is finished in the current frame
+    w: f32,
 }
 
 impl Surface {
-    /// Create a new sphere object from a radius and center
+    /// Multiple of the intersection epsilon that secondary rays are moved away from the surface
+    const EPSILON_OFFSET: f32 = 4.;
+
+    /// Create a new surface from any traceable primitive
     #[must_use]
-    pub fn sphere(center: Point3, radius: f32, material: Material) -> Surface {
-        Surface {
-            obj: Object::Sphere(Sphere::new(center, radius)),
+    pub fn new(obj: Box<dyn Traceable>, material: Material) -> Surface {
+        let mut surface = Surface {
+            obj,
             transform: None,
             material: Box::new(material),
-        }
+            layer: None,
+            both_sides: false,
+            camera_only: false,
+            clip: Vec::new(),
+            bounds: None,
+            w: 0.,
+        };
+        surface.update_bounds();
+        surface
+    }
+
+    /// Recompute the bounding box in world space from the box of the primitive
+    /// Has to be called whenever the primitive or the transformation changes
+    fn update_bounds(&mut self) {
+        self.bounds = self.obj.bounding_box().and_then(|(min, max)| {
+            let to_world = match &self.transform {
+                // the stored transformation maps from world to object space
+                Some(t) => t.transform.inverse_affine()?,
+                None => Mat4::identity(),
+            };
+            BoundingBox::transformed(min, max, &to_world)
+        });
+    }
+
+    /// Return true if the ray misses the bounding box of the current frame
+    /// Rays at another time than the current frame may see an animated primitive elsewhere, so
+    /// they are never culled
+    fn outside_bounds(&self, with: &Ray) -> bool {
+        let other_time = with
+            .time()
+            .is_some_and(|w| (w - self.w).abs() > f32::EPSILON)
+            && self.obj.is_animated();
+        !other_time && self.bounds.as_ref().is_some_and(|b| !b.may_intersect(with))
+    }
+
+    /// Return the minimum and maximum corner of the bounding box of the surface in world space
+    /// `None` if the primitive has no bounding box
+    #[must_use]
+    pub fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        self.bounds.as_ref().map(BoundingBox::corners)
+    }
+
+    /// Create a new sphere object from a radius and center
+    #[must_use]
+    pub fn sphere(center: Point3, radius: f32, material: Material) -> Surface {
+        Surface::new(Box::new(Sphere::new(center, radius)), material)
+    }
+
+    /// Create a new axis aligned ellipsoid from its center and the radii along the x, y and z axis
+    #[must_use]
+    pub fn ellipsoid(center: Point3, radii: Vec3, material: Material) -> Surface {
+        Surface::new(Box::new(Ellipsoid::new(center, radii)), material)
+    }
+
+    /// Create a new flat disc from its center, the normal of its front side and its radius
+    #[must_use]
+    pub fn disc(center: Point3, normal: Vec3, radius: f32, material: Material) -> Surface {
+        Surface::new(Box::new(Disc::new(center, normal, radius)), material)
+    }
+
+    /// Create a new quad (parallelogram) from a corner and the two edges leaving it
+    /// The front side faces in the direction of `edge_u x edge_v`
+    #[must_use]
+    pub fn quad(corner: Point3, edge_u: Vec3, edge_v: Vec3, material: Material) -> Surface {
+        Surface::new(Box::new(Quad::new(corner, edge_u, edge_v)), material)
     }
 
     /// Create a new mesh object from a triangle soup
     #[must_use]
     pub fn mesh(triangles: Vec<Triangle>, material: Material) -> Surface {
-        Surface {
-            obj: Object::Mesh(Box::new(Mesh::new(triangles))),
-            transform: None,
-            material: Box::new(material),
-        }
+        Surface::new(Box::new(Mesh::new(triangles)), material)
     }
 
     /// Create a new julia set object with a position, a constant and the given maximum iterations
@@ -70,91 +164,1062 @@ impl Surface {
         epsilon: f32,
         material: Material,
     ) -> Surface {
-        Surface {
-            obj: Object::JuliaSet(Box::new(JuliaSet::new(pos, c, max_iterations, epsilon))),
-            transform: None,
-            material: Box::new(material),
-        }
+        Surface::new(
+            Box::new(JuliaSet::new(pos, c, max_iterations, epsilon)),
+            material,
+        )
     }
 
     /// Set end parameters for a sphere
     /// does not have any effect if object is not a sphere
     pub fn set_sphere_end(&mut self, e: (Point3, f32)) {
-        if let Object::Sphere(s) = &mut self.obj {
+        if let Some(s) = (self.obj.as_mut() as &mut dyn Any).downcast_mut::<Sphere>() {
             s.set_end(e);
         }
+        self.update_bounds();
     }
 
     /// Set end parameters for a julia set
     /// does not have any effect if object is not a julia set
     pub fn set_julia_end(&mut self, e: Quat) {
-        if let Object::JuliaSet(j) = &mut self.obj {
+        if let Some(j) = (self.obj.as_mut() as &mut dyn Any).downcast_mut::<JuliaSet>() {
             j.set_end(e);
         }
+        self.update_bounds();
+    }
+
+    /// Ripple the shading normal of a quad with procedural waves
+    /// does not have any effect if object is not a quad
+    pub fn set_quad_waves(&mut self, waves: Waves) {
+        if let Some(q) = (self.obj.as_mut() as &mut dyn Any).downcast_mut::<Quad>() {
+            q.set_waves(waves);
+        }
+    }
+
+    /// Rotate the surface around the y-axis of its object space, from 0 at the start to `angle`
+    /// radians at the end of the animation
+    /// End parameters of spheres and julia sets have to be set before
+    pub fn set_spin(&mut self, angle: f32) {
+        self.obj = Box::new(Spin::new(self.obj.clone(), angle));
+        self.update_bounds();
     }
 
     /// Set the frame percentage
     /// w is the percentage that the animation is finished
     pub fn frame_perc(&mut self, w: f32) {
-        match &mut self.obj {
-            Object::Sphere(s) => s.set_frame(w),
-            Object::JuliaSet(j) => j.set_frame(w),
-            Object::Mesh(_) => (),
+        self.w = w;
+        self.obj.set_frame(w);
+        self.material.set_frame(w);
+        if self.obj.is_animated() {
+            self.update_bounds();
+        }
+    }
+
+    /// Set the time of the current frame in seconds
+    pub fn set_time(&mut self, time: f32) {
+        self.obj.set_time(time);
+    }
+
+    /// Return where the point `point` on the surface in the current frame lies at the frame
+    /// percentage `w`, both in world space
+    /// Points on static surfaces don't move
+    #[must_use]
+    pub fn advect(&self, point: &Point3, w: f32) -> Point3 {
+        if !self.obj.is_animated() {
+            return *point;
+        }
+        match &self.transform {
+            Some(t) => {
+                let local = t.transform.transform_point(point);
+                let moved = self.obj.advect(&local, w);
+                // the stored transformation maps from world to object space
+                t.transform
+                    .inverse_affine()
+                    .map_or(*point, |to_world| to_world.transform_point(&moved))
+            }
+            None => self.obj.advect(point, w),
         }
     }
 
+    /// Return true if the surface has end parameters and changes between frames
+    #[must_use]
+    pub fn is_animated(&self) -> bool {
+        self.obj.is_animated() || self.material.is_animated()
+    }
+
     /// Determine if this surface intersects with the ray
     #[must_use]
     pub fn has_intersection(&self, with: &Ray) -> bool {
+        if self.outside_bounds(with) {
+            return false;
+        }
+        if !self.clip.is_empty() {
+            return self.intersection(with).is_some();
+        }
         let with = if let Some(t) = &self.transform {
             with.transform(&t.transform)
         } else {
             *with
         };
 
-        match &self.obj {
-            Object::JuliaSet(j) => j.has_intersection(&with),
-            Object::Sphere(s) => s.has_intersection(&with),
-            Object::Mesh(m) => m.has_intersection(&with),
-        }
+        self.obj.has_intersection(&with)
     }
 
     /// Calculate the intersection of the surface and the ray if it exists
     #[must_use]
     pub fn intersection(&self, with: &Ray) -> Option<Intersection<'_>> {
-        let original_ray = with;
-        let with = if let Some(t) = &self.transform {
+        if self.outside_bounds(with) {
+            return None;
+        }
+        let object_ray = self.object_ray(with);
+        if !self.clip.is_empty() {
+            let mut closest: Option<Intersection> = None;
+            self.clipped_intersections(with, &object_ray, &mut |hit| {
+                if closest.as_ref().is_none_or(|c| hit.t < c.t) {
+                    closest = Some(hit);
+                }
+            });
+            return closest;
+        }
+        let hit = self.obj.intersection(&object_ray)?;
+        let normal = self.world_normal(&hit.1);
+        self.world_intersection(with, &object_ray, (hit.0, normal, hit.2))
+    }
+
+    /// Append every intersection of the surface and the ray to `hits`
+    /// `hits` stays sorted by `t`, if it was sorted before, so the hits of several surfaces can be
+    /// collected into the same buffer. Hits at the same `t` keep the order they were added in
+    /// The buffer is not cleared, so it can be reused between rays without allocating
+    pub fn intersections_all<'s>(&'s self, with: &Ray, hits: &mut Vec<Intersection<'s>>) {
+        if self.outside_bounds(with) {
+            return;
+        }
+        let object_ray = self.object_ray(with);
+        let mut insert = |hit: Intersection<'s>| {
+            let index = hits.partition_point(|h| h.t <= hit.t);
+            hits.insert(index, hit);
+        };
+        if !self.clip.is_empty() {
+            self.clipped_intersections(with, &object_ray, &mut insert);
+            return;
+        }
+        self.obj
+            .intersections_all(&object_ray, &mut |t, normal, texel| {
+                let normal = self.world_normal(&normal);
+                if let Some(hit) = self.world_intersection(with, &object_ray, (t, normal, texel)) {
+                    insert(hit);
+                }
+            });
+    }
+
+    /// Report every intersection of the ray `with` and the surface that is left after cutting it
+    /// with the clip planes to `hit`, in any order
+    /// Solid primitives are closed again with caps in the clip planes, that are shaded like the
+    /// surface with the normal of the plane. The ray hits a cap where it enters or exits the solid
+    /// through a clip plane. Other primitives only lose the hits that are cut away
+    fn clipped_intersections<'s>(
+        &'s self,
+        with: &Ray,
+        object_ray: &Ray,
+        hit: &mut dyn FnMut(Intersection<'s>),
+    ) {
+        if !self.obj.is_solid() {
+            self.obj
+                .intersections_all(object_ray, &mut |t, normal, texel| {
+                    let point = *with.orig() + t * *with.dir();
+                    if self.clip.iter().any(|plane| plane.clips(&point)) {
+                        return;
+                    }
+                    let normal = self.world_normal(&normal);
+                    if let Some(h) = self.world_intersection(with, object_ray, (t, normal, texel)) {
+                        hit(h);
+                    }
+                });
+            return;
+        }
+
+        let Some((entry, exit)) = self.obj.intersection_interval(object_ray) else {
+            return;
+        };
+        let mut entry = (entry.0, self.world_normal(&entry.1), entry.2);
+        let mut exit = (exit.0, self.world_normal(&exit.1), exit.2);
+        for plane in &self.clip {
+            match plane.crossing(with) {
+                // the ray enters the kept side at the plane, the part before it is cut away
+                Some((t, false)) if t > entry.0 => entry = (t, plane.normal(), entry.2),
+                // the ray leaves the kept side at the plane, the part after it is cut away
+                Some((t, true)) if t < exit.0 => exit = (t, plane.normal(), exit.2),
+                None if plane.clips(with.orig()) => return,
+                _ => {}
+            }
+        }
+        if entry.0 > exit.0 {
+            return;
+        }
+        for h in [entry, exit] {
+            if let Some(h) = self.world_intersection(with, object_ray, h) {
+                hit(h);
+            }
+        }
+    }
+
+    /// Return the unit normal in world space of the normal `normal` of the primitive in object
+    /// space
+    fn world_normal(&self, normal: &Vec3) -> Vec3 {
+        if let Some(t) = &self.transform {
+            Vec3::normal(&t.normal_transform.transform_vector(normal))
+        } else {
+            Vec3::normal(normal)
+        }
+    }
+
+    /// Return the ray `with` in object space
+    fn object_ray(&self, with: &Ray) -> Ray {
+        if let Some(t) = &self.transform {
             with.transform(&t.transform)
         } else {
             *with
-        };
+        }
+    }
 
-        let (t, normal, texel) = match &self.obj {
-            Object::JuliaSet(j) => j.intersection(&with),
-            Object::Sphere(s) => s.intersection(&with),
-            Object::Mesh(m) => m.intersection(&with),
-        }?;
+    /// Convert the hit of the primitive with `object_ray` to an intersection of the surface and
+    /// the `original_ray` in world space
+    /// The normal of the hit has to be in world space already, see [`Surface::world_normal`]
+    /// Returns `None` if the hit lies outside the bounds of the ray
+    fn world_intersection(
+        &self,
+        original_ray: &Ray,
+        object_ray: &Ray,
+        (t, mut normal, texel): HitPoint,
+    ) -> Option<Intersection<'_>> {
+        if self.both_sides && normal.dot(original_ray.dir()) > 0. {
+            normal = -normal;
+        }
 
-        let normal = if let Some(t) = &self.transform {
-            Vec3::normal(&t.normal_transform.transform_vector(&normal))
-        } else {
-            Vec3::normal(&normal)
-        };
+        // the transformed ray direction is scaled by the transformation
+        let object_scale = object_ray.dir().length() / original_ray.dir().length();
+        let uv_scale = self.obj.texel_density() * object_scale;
+        let offset = BIAS.max(Self::EPSILON_OFFSET * self.intersection_epsilon() / object_scale);
 
         Some(Intersection {
             point: original_ray.at(t)?,
             t,
             normal,
             texel,
+            uv_scale,
+            footprint: 0.,
+            offset,
             material: &self.material,
+            layer: self.layer(),
         })
     }
 
+    /// Return the distance to the surface in object space, within which the primitive reports
+    /// intersections
+    /// 0 for primitives that are intersected exactly
+    #[must_use]
+    pub fn intersection_epsilon(&self) -> f32 {
+        self.obj.intersection_epsilon()
+    }
+
+    /// Tag the surface with the render layer it belongs to
+    pub fn set_layer(&mut self, layer: Option<String>) {
+        self.layer = layer;
+    }
+
+    /// Shade both sides of the surface by always flipping the normal towards the incoming ray
+    /// Meant for open meshes, refraction can no longer tell the inside from the outside
+    pub fn set_both_sides(&mut self, both_sides: bool) {
+        self.both_sides = both_sides;
+    }
+
+    /// Set whether only camera rays see the surface
+    /// Such surfaces cast no shadows and don't show up in reflections or refractions, like
+    /// overlays that are drawn into the image
+    pub fn set_camera_only(&mut self, camera_only: bool) {
+        self.camera_only = camera_only;
+    }
+
+    /// Cut away the parts of the surface on the positive side of any of the `planes`
+    /// Replaces the clip planes that were set before
+    pub fn set_clip_planes(&mut self, planes: Vec<ClipPlane>) {
+        self.clip = planes;
+    }
+
+    /// Return the planes that cut the surface
+    #[must_use]
+    pub fn clip_planes(&self) -> &[ClipPlane] {
+        &self.clip
+    }
+
+    /// Return true if only camera rays see the surface
+    #[must_use]
+    pub fn is_camera_only(&self) -> bool {
+        self.camera_only
+    }
+
+    /// Return the render layer of the surface, if it was tagged with one
+    #[must_use]
+    pub fn layer(&self) -> Option<&str> {
+        self.layer.as_deref()
+    }
+
+    /// Return true if the surface lies completely outside of the `frustum`, so no camera ray can
+    /// hit it
+    #[must_use]
+    pub fn outside_frustum(&self, frustum: &Frustum) -> bool {
+        let Some((min, max)) = self.obj.bounding_box() else {
+            return false;
+        };
+        match &self.transform {
+            // test in object space, since the transformation of the box would not be axis aligned
+            Some(t) => frustum.transform(&t.transform).excludes_box(min, max),
+            None => frustum.excludes_box(min, max),
+        }
+    }
+
+    /// Return the number of triangles of a mesh
+    /// Returns `None` for other primitives
+    #[must_use]
+    pub fn triangle_count(&self) -> Option<usize> {
+        let mesh = (self.obj.as_ref() as &dyn Any).downcast_ref::<Mesh>()?;
+        Some(mesh.triangle_count())
+    }
+
+    /// Return the triangles of a static mesh in world space
+    /// Returns `None` for other primitives and animated meshes, or if the transformation can't be
+    /// inverted
+    #[must_use]
+    pub fn world_triangles(&self) -> Option<Vec<Triangle>> {
+        let mesh = (self.obj.as_ref() as &dyn Any).downcast_ref::<Mesh>()?;
+        match &self.transform {
+            Some(t) => {
+                // the stored transformation maps from world to object space
+                let to_world = t.transform.inverse_affine()?;
+                Some(
+                    mesh.triangles()
+                        .map(|tri| tri.transformed(&to_world, &t.normal_transform))
+                        .collect(),
+                )
+            }
+            None => Some(mesh.triangles().collect()),
+        }
+    }
+
+    /// Return a point on the surface in world space and the unit normal there, for the random
+    /// numbers `pick` and `uv` in `[0, 1)`
+    /// See [`Traceable::sample_area`], returns `None` if the primitive can't be sampled or the
+    /// transformation can't be inverted
+    #[must_use]
+    pub fn sample_area(&self, pick: f32, uv: (f32, f32)) -> Option<(Point3, Vec3)> {
+        let (point, normal) = self.obj.sample_area(pick, uv)?;
+        let (point, normal) = match &self.transform {
+            Some(t) => (
+                t.transform.inverse_affine()?.transform_point(&point),
+                t.normal_transform.transform_vector(&normal),
+            ),
+            None => (point, normal),
+        };
+        Some((point, Vec3::normal(&normal)))
+    }
+
+    /// Return true if `other` is shaded exactly like this surface, so the geometry of both can be
+    /// combined into one surface
+    #[must_use]
+    pub fn shares_appearance(&self, other: &Surface) -> bool {
+        self.material == other.material
+            && self.layer == other.layer
+            && self.both_sides == other.both_sides
+            && self.camera_only == other.camera_only
+            && self.clip == other.clip
+    }
+
+    /// Create a mesh from `triangles` that is shaded like this surface
+    #[must_use]
+    pub fn with_triangles(&self, triangles: Vec<Triangle>) -> Surface {
+        let mut surface = Surface::mesh(triangles, self.material().clone());
+        surface.layer.clone_from(&self.layer);
+        surface.both_sides = self.both_sides;
+        surface.camera_only = self.camera_only;
+        surface.clip.clone_from(&self.clip);
+        surface
+    }
+
+    /// Return a reference to the material of the surface
+    #[must_use]
+    pub fn material(&self) -> &Material {
+        &self.material
+    }
+
+    /// Replace the material of the surface
+    /// The texture coordinates of the primitive are kept
+    pub fn set_material(&mut self, material: Material) {
+        *self.material = material;
+    }
+
+    /// Return the material of the surface for changing it in place
+    pub fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    /// Return the name of the kind of primitive of the surface, e.g. `sphere` or `mesh`
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        self.obj.kind()
+    }
+
     /// set the transformation of the surface
+    /// replaces any transformation that was set before
     pub fn set_transform(&mut self, transform: Mat4, normal_transform: Mat4) {
         self.transform = Some(Box::new(Transform {
             transform,
             normal_transform,
         }));
+        self.update_bounds();
+    }
+
+    /// compose a transformation with the current transformation of the surface
+    /// The new transformation is applied after the existing one. Like in `set_transform`, the
+    /// matrices map from world to object space
+    pub fn append_transform(&mut self, transform: Mat4, normal_transform: Mat4) {
+        let Some(current) = &mut self.transform else {
+            self.set_transform(transform, normal_transform);
+            return;
+        };
+        // the inverse of a product is the product of the inverses in reverse order
+        current.transform = &current.transform * &transform;
+        current.normal_transform = &normal_transform * &current.normal_transform;
+        self.update_bounds();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Color, Point3};
+    use crate::objects::{Camera, Light, Scene};
+
+    /// axis aligned box that is not part of the core primitives
+    #[derive(Clone, Debug)]
+    struct CustomBox {
+        min: Point3,
+        max: Point3,
+    }
+
+    impl Traceable for CustomBox {
+        fn has_intersection(&self, ray: &Ray) -> bool {
+            self.intersection(ray).is_some()
+        }
+
+        fn intersection(&self, ray: &Ray) -> Option<(f32, Vec3, Texel)> {
+            let mut t_near = f32::NEG_INFINITY;
+            let mut t_far = f32::INFINITY;
+            let mut normal = Vec3::zero();
+            for axis in 0..3 {
+                let t1 = (self.min[axis] - ray.orig()[axis]) / ray.dir()[axis];
+                let t2 = (self.max[axis] - ray.orig()[axis]) / ray.dir()[axis];
+                let (t1, t2) = if t1 < t2 { (t1, t2) } else { (t2, t1) };
+                if t1 > t_near {
+                    t_near = t1;
+                    let mut n = [0.; 3];
+                    n[axis] = -ray.dir()[axis].signum();
+                    normal = Vec3::new(n[0], n[1], n[2]);
+                }
+                t_far = t_far.min(t2);
+            }
+            (t_near <= t_far && ray.t_in_range(t_near)).then_some((t_near, normal, (0., 0.)))
+        }
+    }
+
+    #[test]
+    fn custom_traceable_in_scene() {
+        let red = Color::new(1., 0., 0.);
+        let material = Material::new(
+            Texture::Color(red),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let custom = Surface::new(
+            Box::new(CustomBox {
+                min: Point3::new(-0.5, -0.5, -3.5),
+                max: Point3::new(0.5, 0.5, -2.5),
+            }),
+            material,
+        );
+        let camera = Camera::new(
+            Point3::zero(),
+            Point3::new(0., 0., -1.),
+            Vec3::new(0., 1., 0.),
+            std::f32::consts::FRAC_PI_4,
+            9,
+            9,
+            1,
+        );
+        let lights = vec![Light::Ambient {
+            color: Color::new(1., 1., 1.),
+            hemisphere: None,
+        }];
+        let scene = Scene::new(String::new(), Color::zero(), camera, lights, vec![custom]);
+
+        assert_eq!(scene.trace_pixel(4, 4), red);
+        assert_eq!(scene.trace_pixel(0, 0), Color::zero());
+    }
+
+    #[test]
+    fn texture_is_sampled_once_per_intersection() {
+        let image =
+            crate::image::Image::from_pixels(2, 2, vec![[255; 3], [0; 3], [0; 3], [255; 3]]);
+        let material = Material::new(
+            Texture::image(image),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 0.2,
+                kd: 0.8,
+                ks: 0.5,
+                exp: 10,
+            },
+        );
+        let intersection = Intersection {
+            point: Point3::zero(),
+            t: 1.,
+            normal: Vec3::new(0., 0., 1.),
+            texel: (0.25, 0.25),
+            uv_scale: 1.,
+            footprint: 0.,
+            offset: BIAS,
+            material: &material,
+            layer: None,
+        };
+        let mut lights: Vec<_> = (0..8)
+            .map(|i| Light::Point {
+                color: Color::new(1., 1., 1.),
+                #[allow(clippy::cast_precision_loss)]
+                position: Point3::new(i as f32 - 3.5, 1., 2.),
+                radius: 0.,
+                samples: None,
+            })
+            .collect();
+        lights.push(Light::Ambient {
+            color: Color::new(1., 1., 1.),
+            hemisphere: None,
+        });
+        let ray = Ray::new(Point3::new(0., 0., 1.), Vec3::new(0., 0., -1.));
+
+        crate::image::PIXEL_LOOKUPS.with(|n| n.set(0));
+        let color = intersection.get_total_color(&lights, |_, _| 1., &ray);
+        let lookups = crate::image::PIXEL_LOOKUPS.with(std::cell::Cell::get);
+
+        assert_eq!(lookups, 1);
+        // same as shading every light on its own
+        let base_color = intersection.base_color();
+        let expected = lights
+            .iter()
+            .map(|light| intersection.get_color(light, base_color, &ray))
+            .fold(Color::zero(), |lhs, rhs| lhs + rhs);
+        // the diffuse and specular parts are summed separately, which rounds differently
+        assert!((color - expected).length() < 1e-6, "{color:?} {expected:?}");
+    }
+
+    #[test]
+    fn both_sides_lights_floor_from_below() {
+        let white = Color::new(1., 1., 1.);
+        let material = Material::new(
+            Texture::Color(white),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 0.,
+                kd: 1.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        // floor at y = 0 with the normals pointing up
+        let corners = [
+            Point3::new(-5., 0., 5.),
+            Point3::new(5., 0., 5.),
+            Point3::new(5., 0., -5.),
+            Point3::new(-5., 0., -5.),
+        ];
+        let up = Vec3::new(0., 1., 0.);
+        let triangles = [[0, 1, 2], [0, 2, 3]]
+            .map(|i| Triangle::new(i.map(|i| corners[i]), [up; 3], [(0., 0.); 3]))
+            .to_vec();
+        let lights = vec![Light::Point {
+            color: white,
+            position: Point3::new(0., -2., 0.),
+            radius: 0.,
+            samples: None,
+        }];
+        let render = |both_sides| {
+            let mut floor = Surface::mesh(triangles.clone(), material.clone());
+            floor.set_both_sides(both_sides);
+            // camera and light below the floor
+            let camera = Camera::new(
+                Point3::new(0., -3., 0.),
+                Point3::zero(),
+                Vec3::new(0., 0., -1.),
+                std::f32::consts::FRAC_PI_4,
+                9,
+                9,
+                0,
+            );
+            let scene = Scene::new(
+                String::new(),
+                Color::zero(),
+                camera,
+                lights.clone(),
+                vec![floor],
+            );
+            scene.trace_pixel(4, 4)
+        };
+
+        assert_eq!(render(false), Color::zero());
+        let lit = render(true);
+        assert!((lit - white).length() < 1e-4, "{lit:?}");
+    }
+
+    #[test]
+    fn cloned_surface_is_independent() {
+        let material = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let original = Surface::sphere(Point3::new(0., 0., -5.), 1., material);
+        let mut clone = original.clone();
+        // move the clone 3 units to the right
+        let inv_transform = Mat4::from_translation(Vec3::new(-3., 0., 0.));
+        clone.set_transform(inv_transform, Mat4::transpose(&inv_transform));
+
+        let to_original = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let to_clone = Ray::new(Point3::new(3., 0., 0.), Vec3::new(0., 0., -1.));
+        assert!(original.has_intersection(&to_original));
+        assert!(!original.has_intersection(&to_clone));
+        assert!(clone.has_intersection(&to_clone));
+        assert!(!clone.has_intersection(&to_original));
+
+        let hit = clone.intersection(&to_clone).unwrap();
+        assert!((hit.t - 4.).abs() < 1e-5);
+        assert!((hit.point - Point3::new(3., 0., -4.)).length() < 1e-5);
+        assert_eq!(clone.material(), original.material());
+    }
+
+    #[test]
+    fn all_intersections_are_sorted_by_distance() {
+        let material = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            0.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let outer = Surface::sphere(Point3::new(0., 0., -5.), 2., material.clone());
+        let mut inner = Surface::sphere(Point3::zero(), 1., material.clone());
+        let inv_transform = Mat4::from_translation(Vec3::new(0., 0., 5.));
+        inner.set_transform(inv_transform, Mat4::transpose(&inv_transform));
+        let ellipsoid =
+            Surface::ellipsoid(Point3::new(0., 0., -10.), Vec3::new(1., 1., 0.5), material);
+
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let mut hits = Vec::new();
+        for surface in [&ellipsoid, &inner, &outer] {
+            surface.intersections_all(&ray, &mut hits);
+        }
+        let ts: Vec<_> = hits.iter().map(|h| h.t).collect();
+        let expected = [3., 4., 6., 7., 9.5, 10.5];
+        assert_eq!(ts.len(), expected.len(), "{ts:?}");
+        for (t, e) in ts.iter().zip(expected) {
+            assert!((t - e).abs() < 1e-4, "{ts:?}");
+        }
+        // the closest hit is the same as the single intersection
+        let closest = outer.intersection(&ray).unwrap();
+        assert!((closest.t - hits[0].t).abs() < 1e-6);
+        assert!((closest.normal - hits[0].normal).length() < 1e-6);
+
+        // hits behind the origin are not reported, and the buffer can be reused
+        hits.clear();
+        let inside = Ray::new(Point3::new(0., 0., -5.), Vec3::new(0., 0., -1.));
+        inner.intersections_all(&inside, &mut hits);
+        assert_eq!(hits.len(), 1);
+        assert!((hits[0].t - 1.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn appended_transforms_match_combined_transform() {
+        let material = || {
+            Material::new(
+                Texture::Color(Color::new(1., 1., 1.)),
+                0.,
+                0.,
+                1.,
+                ShadingModel::Phong {
+                    ka: 1.,
+                    kd: 0.,
+                    ks: 0.,
+                    exp: 1,
+                },
+            )
+        };
+        // inverse transformations of a 2x scale followed by a translation
+        let inv_scale = Mat4::from_scaling(Vec3::new(0.5, 0.5, 0.5));
+        let inv_translate = Mat4::from_translation(Vec3::new(-3., 0., 5.));
+
+        let mut appended = Surface::sphere(Point3::zero(), 1., material());
+        appended.append_transform(inv_scale, Mat4::transpose(&inv_scale));
+        appended.append_transform(inv_translate, Mat4::transpose(&inv_translate));
+
+        let combined_inv = &inv_scale * &inv_translate;
+        let mut combined = Surface::sphere(Point3::zero(), 1., material());
+        combined.set_transform(combined_inv, Mat4::transpose(&combined_inv));
+
+        for dir in [
+            Vec3::new(0., 0., -1.),
+            Vec3::new(0.2, 0.1, -1.),
+            Vec3::new(0., -0.3, -1.),
+        ] {
+            let ray = Ray::new(Point3::new(3., 0., 0.), dir);
+            let a = appended.intersection(&ray).unwrap();
+            let b = combined.intersection(&ray).unwrap();
+            assert!((a.t - b.t).abs() < 1e-4);
+            assert!((a.point - b.point).length() < 1e-4);
+            assert!((a.normal - b.normal).length() < 1e-4);
+        }
+        // the scaled sphere has a radius of 2
+        let ray = Ray::new(Point3::new(3., 0., 0.), Vec3::new(0., 0., -1.));
+        assert!((appended.intersection(&ray).unwrap().t - 3.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn reflected_ray_does_not_self_intersect() {
+        let material = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            1.,
+            1.,
+            1.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let sphere = Surface::sphere(Point3::new(0., 0., -3.), 1., material);
+        for i in -4..=4 {
+            for j in -4..=4 {
+                #[allow(clippy::cast_precision_loss)]
+                let dir = Vec3::new(i as f32 * 0.05, j as f32 * 0.05, -1.);
+                let ray = Ray::new(Point3::zero(), Vec3::normal(&dir));
+                let hit = sphere.intersection(&ray).unwrap();
+
+                let reflected = hit.reflected_ray(&ray);
+                assert!(sphere.intersection(&reflected).is_none());
+                assert!(!sphere.has_intersection(&reflected));
+
+                // a ray into the sphere hits the other side instead of its origin
+                let refracted = hit.refracted_ray(&ray);
+                assert!(sphere.intersection(&refracted).unwrap().t > 1.);
+            }
+        }
+    }
+
+    #[test]
+    fn secondary_rays_leave_the_epsilon_of_julia_sets() {
+        let material = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            1.,
+            1.,
+            1.5,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        let epsilon = 0.003;
+        let mut julia = Surface::julia_set(
+            Point3::new(0., 0., -2.),
+            Quat::new(-0.5, -0.3, 0.6, 0.),
+            8,
+            epsilon,
+            material.clone(),
+        );
+        // scale the set by 2, so the epsilon is twice as large in world space
+        let half = Mat4::from_scaling(Vec3::new(0.5, 0.5, 0.5));
+        julia.set_transform(half, half);
+        assert!((julia.intersection_epsilon() - epsilon).abs() < f32::EPSILON);
+
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let hit = julia.intersection(&ray).unwrap();
+        assert!((hit.offset - Surface::EPSILON_OFFSET * 2. * epsilon).abs() < 1e-5);
+
+        // reflected rays start in front of and refracted rays behind the surface
+        let reflected = hit.reflected_ray(&ray);
+        assert!(((*reflected.orig() - hit.point).dot(&hit.normal) - hit.offset).abs() < 1e-5);
+        let refracted = hit.refracted_ray(&ray);
+        assert!(((*refracted.orig() - hit.point).dot(&hit.normal) + hit.offset).abs() < 1e-5);
+
+        // rays that start inside of the bounds of the set can hit it again
+        let back = Ray::new(*reflected.orig(), -hit.normal);
+        assert!(julia.intersection(&back).is_some());
+        assert!(julia.has_intersection(&back));
+
+        // primitives that are intersected exactly only need the bias
+        let sphere = Surface::sphere(Point3::new(0., 0., -3.), 1., material);
+        assert!((sphere.intersection(&ray).unwrap().offset - BIAS).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn ellipsoid_matches_scaled_sphere() {
+        let material = || {
+            Material::new(
+                Texture::Color(Color::new(1., 1., 1.)),
+                0.,
+                0.,
+                1.,
+                ShadingModel::Phong {
+                    ka: 1.,
+                    kd: 0.,
+                    ks: 0.,
+                    exp: 1,
+                },
+            )
+        };
+        let center = Point3::new(1., -0.5, -6.);
+        let radii = Vec3::new(2., 1., 0.5);
+        let ellipsoid = Surface::ellipsoid(center, radii, material());
+
+        // unit sphere scaled by the radii and moved to the center, like a scene file would do it
+        let mut sphere = Surface::sphere(Point3::zero(), 1., material());
+        let inverse = &Mat4::from_scaling(Vec3::new(1. / radii[0], 1. / radii[1], 1. / radii[2]))
+            * &Mat4::from_translation(-center);
+        sphere.set_transform(inverse, Mat4::transpose(&inverse));
+
+        for i in -4..=4 {
+            for j in -4..=4 {
+                #[allow(clippy::cast_precision_loss)]
+                let dir = Vec3::new(0.1 + i as f32 * 0.06, j as f32 * 0.03, -1.);
+                let ray = Ray::new(Point3::zero(), dir);
+                let (Some(a), Some(b)) = (ellipsoid.intersection(&ray), sphere.intersection(&ray))
+                else {
+                    assert!(ellipsoid.intersection(&ray).is_none());
+                    assert!(sphere.intersection(&ray).is_none());
+                    continue;
+                };
+                assert!((a.t - b.t).abs() < 1e-4);
+                assert!(
+                    (a.normal - b.normal).length() < 1e-4,
+                    "{:?} {:?}",
+                    a.normal,
+                    b.normal
+                );
+                assert!((a.texel.0 - b.texel.0).abs() < 1e-4);
+                assert!((a.texel.1 - b.texel.1).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn transformed_two_sided_quad_and_disc() {
+        let material = || {
+            Material::new(
+                Texture::Color(Color::new(1., 1., 1.)),
+                0.,
+                0.,
+                1.,
+                ShadingModel::Phong {
+                    ka: 1.,
+                    kd: 0.,
+                    ks: 0.,
+                    exp: 1,
+                },
+            )
+        };
+        // unit floor quad, moved down and below the camera
+        let mut quad = Surface::quad(
+            Point3::new(-1., 0., 1.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 0., -2.),
+            material(),
+        );
+        let inverse = Mat4::from_translation(Vec3::new(0., 1., 5.));
+        quad.set_transform(inverse, Mat4::transpose(&inverse));
+        let down = Ray::new(Point3::zero(), Vec3::new(0., -1., -5.));
+        let hit = quad.intersection(&down).unwrap();
+        assert!((hit.point - Point3::new(0., -1., -5.)).length() < 1e-4);
+        assert!(hit.normal.dot(&Vec3::new(0., 1., 0.)) > 0.99);
+
+        // from below the normal of a one-sided quad faces away, a two-sided one faces the ray
+        let up = Ray::new(Point3::new(0., -3., -5.), Vec3::new(0., 1., 0.));
+        assert!(quad.intersection(&up).unwrap().normal[1] > 0.);
+        quad.set_both_sides(true);
+        assert!(quad.intersection(&up).unwrap().normal[1] < 0.);
+
+        let mut disc = Surface::disc(Point3::zero(), Vec3::new(0., 0., 1.), 1., material());
+        let rotation = Mat4::from_y_rotation(std::f32::consts::FRAC_PI_2);
+        disc.set_transform(rotation, Mat4::transpose(&rotation));
+        let side = Ray::new(Point3::new(-3., 0., 0.), Vec3::new(1., 0., 0.));
+        let hit = disc.intersection(&side).unwrap();
+        assert!((hit.t - 3.).abs() < 1e-4);
+        assert!(disc
+            .intersection(&Ray::new(Point3::new(0., 0., 3.), Vec3::new(0., 0., -1.)))
+            .is_none());
+    }
+
+    #[test]
+    fn grid_mesh_counts_triangles() {
+        let material = Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        );
+        // 4x4 grid of quads in the plane z = -2, split into two triangles each
+        let mut triangles = Vec::new();
+        for i in 0..4u8 {
+            for j in 0..4u8 {
+                let (x, y) = (f32::from(i) - 2., f32::from(j) - 2.);
+                let p = |dx: f32, dy: f32| Point3::new(x + dx, y + dy, -2.);
+                let n = [Vec3::new(0., 0., 1.); 3];
+                triangles.push(Triangle::new(
+                    [p(0., 0.), p(1., 0.), p(1., 1.)],
+                    n,
+                    [(0., 0.); 3],
+                ));
+                triangles.push(Triangle::new(
+                    [p(0., 0.), p(1., 1.), p(0., 1.)],
+                    n,
+                    [(0., 0.); 3],
+                ));
+            }
+        }
+        let grid = Surface::mesh(triangles, material);
+        assert_eq!(grid.triangle_count(), Some(32));
+        assert_eq!(
+            Surface::disc(
+                Point3::zero(),
+                Vec3::new(0., 0., 1.),
+                1.,
+                grid.material().clone()
+            )
+            .triangle_count(),
+            None
+        );
+
+        let before = crate::objects::IntersectionStats::collect();
+        let ray = Ray::new(Point3::new(0.5, 0.5, 0.), Vec3::new(0., 0., -1.));
+        assert!(grid.intersection(&ray).is_some());
+        let miss = Ray::new(Point3::new(0.5, 0.5, 0.), Vec3::new(0., 0., 1.));
+        assert!(!grid.has_intersection(&miss));
+        if let (Some(before), Some(after)) = (before, crate::objects::IntersectionStats::collect())
+        {
+            // other tests count concurrently, so only lower bounds hold
+            let stats = after.since(&before);
+            assert!(stats.triangle_tests >= 32);
+            assert!(stats.box_tests >= 1);
+        }
+    }
+
+    fn clip_material() -> Material {
+        Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 1.,
+                kd: 0.,
+                ks: 0.,
+                exp: 1,
+            },
+        )
+    }
+
+    #[test]
+    fn half_clipped_sphere_silhouette() {
+        let mut sphere = Surface::sphere(Point3::zero(), 1., clip_material());
+        sphere.set_clip_planes(vec![ClipPlane::new(Point3::zero(), Vec3::new(1., 0., 0.))]);
+        // look at the sphere from the front, only the half with x <= 0 is left
+        for i in -12..=12i8 {
+            for j in -12..=12i8 {
+                let (x, y) = (f32::from(i) / 10. + 0.01, f32::from(j) / 10. + 0.01);
+                let ray = Ray::new(Point3::new(x, y, 5.), Vec3::new(0., 0., -1.));
+                let inside = x < 0. && x * x + y * y < 1.;
+                assert_eq!(sphere.intersection(&ray).is_some(), inside, "at ({x}, {y})");
+                assert_eq!(sphere.has_intersection(&ray), inside, "at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_sphere_is_capped() {
+        let mut sphere = Surface::sphere(Point3::zero(), 1., clip_material());
+        sphere.set_clip_planes(vec![ClipPlane::new(Point3::zero(), Vec3::new(1., 0., 0.))]);
+        // the entry at x > 0 is cut away, the ray hits the cap in the plane instead
+        let ray = Ray::new(Point3::new(5., 0.2, 0.), Vec3::new(-1., 0., 0.));
+        let hit = sphere.intersection(&ray).unwrap();
+        assert!((hit.t - 5.).abs() < 1e-5);
+        assert!((hit.normal - Vec3::new(1., 0., 0.)).length() < 1e-5);
+
+        let mut hits = Vec::new();
+        sphere.intersections_all(&ray, &mut hits);
+        assert_eq!(hits.len(), 2);
+        assert!((hits[1].t - 5. - 0.96_f32.sqrt()).abs() < 1e-5);
+        assert!(hits[1].normal.dot(&Vec3::new(-1., 0., 0.)) > 0.9);
+
+        // from inside the capped half the ray leaves through the cap
+        let inside = Ray::new(Point3::new(-0.5, 0., 0.), Vec3::new(1., 0., 0.));
+        let hit = sphere.intersection(&inside).unwrap();
+        assert!((hit.t - 0.5).abs() < 1e-5);
+        assert!((hit.normal - Vec3::new(1., 0., 0.)).length() < 1e-5);
+
+        // the plane only caps where it cuts through the sphere
+        let above = Ray::new(Point3::new(5., 1.5, 0.), Vec3::new(-1., 0., 0.));
+        assert!(sphere.intersection(&above).is_none());
+    }
+
+    #[test]
+    fn clipped_quad_rejects_hits() {
+        let mut quad = Surface::quad(
+            Point3::new(-1., -1., 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+            clip_material(),
+        );
+        quad.set_clip_planes(vec![ClipPlane::new(Point3::zero(), Vec3::new(1., 0., 0.))]);
+        let ray = |x| Ray::new(Point3::new(x, 0., 1.), Vec3::new(0., 0., -1.));
+        assert!(quad.intersection(&ray(-0.5)).is_some());
+        // open primitives are not capped
+        assert!(quad.intersection(&ray(0.5)).is_none());
+        assert!(!quad.has_intersection(&ray(0.5)));
     }
 }
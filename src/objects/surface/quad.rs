@@ -0,0 +1,140 @@
+use crate::math::{Point3, Ray, Vec3};
+
+use super::disc::plane_intersection;
+use super::{Texel, Traceable, Waves};
+
+/// struct to represent a parallelogram in 3D-Space, spanned by two edges from a corner
+/// The front side faces in the direction of `edge_u x edge_v`
+#[derive(Clone, Debug)]
+pub(super) struct Quad {
+    corner: Point3,
+    edge_u: Vec3,
+    edge_v: Vec3,
+    /// `edge_u x edge_v`, its length is the area of the quad
+    normal: Vec3,
+    /// waves that ripple the shading normal
+    waves: Option<Waves>,
+    /// time of the current frame in seconds, moves the waves
+    time: f32,
+}
+
+impl Quad {
+    /// Create a new quad
+    /// The edges must not be parallel
+    pub fn new(corner: Point3, edge_u: Vec3, edge_v: Vec3) -> Quad {
+        Quad {
+            corner,
+            edge_u,
+            edge_v,
+            normal: edge_u.cross(&edge_v),
+            waves: None,
+            time: 0.,
+        }
+    }
+
+    /// Ripple the shading normal of the quad with `waves`
+    pub fn set_waves(&mut self, waves: Waves) {
+        self.waves = Some(waves);
+    }
+
+    /// Return the coordinates of `p` along the two edges
+    /// Points inside the quad have both coordinates in `[0, 1]`
+    fn local_coordinates(&self, p: &Point3) -> (f32, f32) {
+        let d = *p - self.corner;
+        let w = self.normal * (1. / self.normal.length_squared());
+        (w.dot(&d.cross(&self.edge_v)), w.dot(&self.edge_u.cross(&d)))
+    }
+}
+
+impl Traceable for Quad {
+    fn kind(&self) -> &'static str {
+        "quad"
+    }
+
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        let c = self.corner;
+        let corners = [
+            c,
+            c + self.edge_u,
+            c + self.edge_v,
+            c + self.edge_u + self.edge_v,
+        ];
+        let min = corners.iter().fold(c, |m, p| {
+            Vec3::new(m[0].min(p[0]), m[1].min(p[1]), m[2].min(p[2]))
+        });
+        let max = corners.iter().fold(c, |m, p| {
+            Vec3::new(m[0].max(p[0]), m[1].max(p[1]), m[2].max(p[2]))
+        });
+        Some((min, max))
+    }
+
+    /// The whole texture is mapped onto the quad
+    fn texel_density(&self) -> f32 {
+        1. / self.normal.length().sqrt()
+    }
+
+    fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Quads with waves change between frames, unless the waves stand still
+    fn is_animated(&self) -> bool {
+        self.waves.is_some_and(|waves| waves.speed != 0.)
+    }
+
+    /// The point lies at `(u, v)` along the edges, the normal ignores the waves
+    fn sample_area(&self, _pick: f32, (u, v): (f32, f32)) -> Option<(Point3, Vec3)> {
+        Some((self.corner + u * self.edge_u + v * self.edge_v, self.normal))
+    }
+
+    fn has_intersection(&self, with: &Ray) -> bool {
+        self.intersection(with).is_some()
+    }
+
+    /// Calculates the intersection of the quad and the `with` Ray if present
+    /// Points exactly on the edges count as hits. The texel is the position along the edges, with
+    /// `v` flipped so that images are upright if `edge_v` points up
+    /// The normal is tilted by the waves of the quad, if it has any
+    fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+        let t = plane_intersection(with, &self.corner, &self.normal)?;
+        let point = *with.orig() + t * *with.dir();
+        let (u, v) = self.local_coordinates(&point);
+        if !(0. ..=1.).contains(&u) || !(0. ..=1.).contains(&v) {
+            return None;
+        }
+        let normal = self.waves.map_or(self.normal, |waves| {
+            waves.perturb(&Vec3::normal(&self.normal), &point, self.time)
+        });
+        Some((t, normal, (u, 1. - v)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quad_intersection() {
+        let quad = Quad::new(
+            Point3::new(-1., -1., -3.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 4., 0.),
+        );
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let (t, normal, (u, v)) = quad.intersection(&ray).unwrap();
+        assert!((t - 3.).abs() < 1e-5);
+        assert!((Vec3::normal(&normal) - Vec3::new(0., 0., 1.)).length() < 1e-5);
+        assert!((u - 0.5).abs() < 1e-5 && (v - 0.75).abs() < 1e-5);
+
+        // the edges belong to the quad
+        let edge = Ray::new(Point3::new(1., 3., 0.), Vec3::new(0., 0., -1.));
+        let (_, _, (u, v)) = quad.intersection(&edge).unwrap();
+        assert!((u - 1.).abs() < 1e-5 && v.abs() < 1e-5);
+        let outside = Ray::new(Point3::new(1.001, 0., 0.), Vec3::new(0., 0., -1.));
+        assert!(!quad.has_intersection(&outside));
+
+        // rays parallel to the quad miss it, even inside its plane
+        let parallel = Ray::new(Point3::new(-2., 0., -3.), Vec3::new(1., 0., 0.));
+        assert!(quad.intersection(&parallel).is_none());
+    }
+}
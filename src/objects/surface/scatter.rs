@@ -0,0 +1,137 @@
+use std::f32::consts::TAU;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::math::{tangent_frame, Mat4, Vec3};
+
+use super::Surface;
+
+/// Settings to scatter copies of a surface over the area of another surface, like rocks or
+/// plants on the ground
+/// The copies share the geometry of the prototype, only their transformations differ
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scatter {
+    /// number of copies
+    pub count: usize,
+    /// seed of the random placement, the same seed places the copies at the same spots
+    pub seed: u64,
+    /// smallest and largest uniform scale of the copies
+    pub scale: (f32, f32),
+    /// stand the copies upright on the surface instead of along the y-axis
+    pub align_to_normal: bool,
+}
+
+impl Scatter {
+    /// Return `count` copies of `prototype` at random points of `target`
+    /// Every copy is scaled, turned by a random angle around its up axis and moved so that the
+    /// origin of the prototype lies on the target
+    ///
+    /// # Errors
+    ///
+    /// Returns a message if points can't be picked on `target` or the scale bounds are invalid
+    pub fn instances(&self, prototype: &Surface, target: &Surface) -> Result<Vec<Surface>, String> {
+        let (min, max) = self.scale;
+        if !(min > 0. && min <= max) {
+            return Err(format!(
+                "The scale bounds have to be positive and in order, got scale_min={min} and scale_max={max}"
+            ));
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        (0..self.count)
+            .map(|_| {
+                let (point, normal) = target
+                    .sample_area(rng.random(), (rng.random(), rng.random()))
+                    .ok_or_else(|| {
+                        format!("Can't pick points on a {} to scatter on", target.kind())
+                    })?;
+                let angle = rng.random_range(0. ..TAU);
+                let scale = rng.random_range(min..=max);
+                let up = if self.align_to_normal {
+                    normal
+                } else {
+                    Vec3::new(0., 1., 0.)
+                };
+                // the tangent is orthogonal to `up`, so `up` becomes the y-axis of the frame
+                let (tangent, _) = tangent_frame(&up);
+                let to_world = &(&Mat4::look_at(point, point - tangent, up)
+                    * &Mat4::from_y_rotation(angle))
+                    * &Mat4::from_scaling(Vec3::new(scale, scale, scale));
+                let transform = to_world
+                    .inverse_affine()
+                    .ok_or_else(|| "The placement of a copy can't be inverted".to_string())?;
+                let mut instance = prototype.clone();
+                instance.append_transform(transform, Mat4::transpose(&transform));
+                Ok(instance)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Color, Point3};
+    use crate::objects::{Material, ShadingModel, Texture};
+
+    fn material() -> Material {
+        Material::new(
+            Texture::Color(Color::new(1., 1., 1.)),
+            0.,
+            0.,
+            1.,
+            ShadingModel::Phong {
+                ka: 0.2,
+                kd: 0.8,
+                ks: 0.,
+                exp: 1,
+            },
+        )
+    }
+
+    #[test]
+    fn scatter_places_copies_on_the_target() {
+        let ground = Surface::quad(
+            Point3::new(-2., 1., 2.),
+            Vec3::new(4., 0., 0.),
+            Vec3::new(0., 0., -4.),
+            material(),
+        );
+        let pebble = Surface::sphere(Point3::zero(), 0.1, material());
+        let scatter = Scatter {
+            count: 20,
+            seed: 7,
+            scale: (0.5, 1.5),
+            align_to_normal: true,
+        };
+        let copies = scatter.instances(&pebble, &ground).unwrap();
+        assert_eq!(copies.len(), 20);
+        for copy in &copies {
+            let (min, max) = copy.bounding_box().unwrap();
+            let center = 0.5 * (min + max);
+            // turning around the up axis doesn't change the height of the box
+            let radius = 0.5 * (max[1] - min[1]);
+            assert!((center[1] - 1.).abs() < 1e-4);
+            assert!(center[0].abs() <= 2. && center[2].abs() <= 2.);
+            assert!((0.05 - 1e-4..=0.15 + 1e-4).contains(&radius));
+        }
+
+        // the same seed places the copies at the same spots, another seed doesn't
+        let boxes = |copies: &[Surface]| -> Vec<_> {
+            copies.iter().map(|c| c.bounding_box().unwrap()).collect()
+        };
+        let again = scatter.instances(&pebble, &ground).unwrap();
+        assert_eq!(boxes(&copies), boxes(&again));
+        let other = Scatter { seed: 8, ..scatter };
+        assert_ne!(
+            boxes(&copies),
+            boxes(&other.instances(&pebble, &ground).unwrap())
+        );
+
+        let inverted = Scatter {
+            scale: (2., 1.),
+            ..scatter
+        };
+        assert!(inverted.instances(&pebble, &ground).is_err());
+        assert!(scatter.instances(&pebble, &pebble).is_err());
+    }
+}
@@ -2,20 +2,23 @@ use std::f32::consts::PI;
 
 use crate::math::{lerp, Point3, Ray, Vec3};
 
-use super::Texel;
+use super::{HitPoint, Texel, Traceable};
 
 #[derive(Clone, Debug)]
 struct Animation {
-    start: (Point3, f32),
-    end: Option<(Point3, f32)>,
+    end: (Point3, f32),
+    /// percentage of the animation that is finished
+    w: f32,
 }
 
 /// struct to represent a Sphere in 3D-Space
+/// `center` and `radius` are the starting parameters, the parameters of the current frame are
+/// resolved from the animation on every query
 #[derive(Clone, Debug)]
 pub(super) struct Sphere {
     center: Point3,
     radius: f32,
-    animation: Box<Animation>,
+    animation: Option<Box<Animation>>,
 }
 
 impl Sphere {
@@ -24,68 +27,73 @@ impl Sphere {
         Sphere {
             center,
             radius,
-            animation: Box::new(Animation {
-                start: (center, radius),
-                end: None,
-            }),
+            animation: None,
         }
     }
 
-    /// Set the frame percentage to lerp between starting and end parameters
-    pub fn set_frame(&mut self, w: f32) {
-        if let Some((ec, er)) = self.animation.end {
-            self.center = lerp(self.animation.start.0, ec, w);
-            self.radius = lerp(self.animation.start.1, er, w);
+    /// Set the end parameters (endposition, endradius)
+    pub fn set_end(&mut self, e: (Point3, f32)) {
+        self.animation = Some(Box::new(Animation { end: e, w: 0. }));
+    }
+
+    /// Return the center and radius in the current frame
+    fn params(&self) -> (Point3, f32) {
+        match &self.animation {
+            Some(a) => self.params_at(a.w),
+            None => (self.center, self.radius),
         }
     }
 
-    /// Set the end parameters (endposition, endradius)
-    pub fn set_end(&mut self, e: (Point3, f32)) {
-        self.animation.end = Some(e);
+    /// Return the center and radius at the frame percentage `w`
+    fn params_at(&self, w: f32) -> (Point3, f32) {
+        match &self.animation {
+            Some(a) => (lerp(self.center, a.end.0, w), lerp(self.radius, a.end.1, w)),
+            None => (self.center, self.radius),
+        }
+    }
+
+    /// Return the center and radius at the time of the ray, or in the current frame if the ray has
+    /// no time
+    fn params_seen_by(&self, with: &Ray) -> (Point3, f32) {
+        match with.time() {
+            Some(w) => self.params_at(w),
+            None => self.params(),
+        }
     }
 
     /// Calculates the coefficients (a, h, c) of the intersection formula
     fn intersection_coefficients(&self, with: &Ray) -> (f32, f32, f32) {
-        let oc = self.center - *with.orig();
+        let (center, radius) = self.params_seen_by(with);
+        let oc = center - *with.orig();
         let a = with.dir().length_squared();
         let h = with.dir().dot(&oc);
-        let c = oc.length_squared() - self.radius * self.radius;
+        let c = oc.length_squared() - radius * radius;
         (a, h, c)
     }
 
-    /// Test if any object intersects with the ray
-    pub fn has_intersection(&self, with: &Ray) -> bool {
-        let (a, h, c) = self.intersection_coefficients(with);
-        let discr = h * h - a * c;
-        discr >= 0. && with.at((h - discr.sqrt()) / a).is_some()
-    }
-
-    /// Calculates the intersection of the sphere and the `with` Ray if present
-    /// The normal in the intersection object will not necessarily be normalized
-    /// Returns `None` if there is no intersection
-    pub fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+    /// Return the `t` values where the line of the ray enters and exits the sphere, in this order
+    /// Returns `None` if the line misses the sphere
+    fn roots(&self, with: &Ray) -> Option<(f32, f32)> {
         let (a, h, c) = self.intersection_coefficients(with);
         let discr = h * h - a * c;
         if discr < 0. {
             return None;
         }
-
         let discr = discr.sqrt();
-        let t = if h - discr < 0. {
-            (h + discr) / a
-        } else {
-            (h - discr) / a
-        };
-        let point = with.at(t)?;
-        let normal = point - self.center;
+        Some(((h - discr) / a, (h + discr) / a))
+    }
 
-        Some((t, normal, self.get_texel_at(&point)))
+    /// Return the hit point at `t` along the ray, with the outward normal and the texel there
+    fn hit_at(&self, with: &Ray, t: f32) -> HitPoint {
+        let center = self.params_seen_by(with).0;
+        let point = *with.orig() + t * *with.dir();
+        (t, point - center, Self::get_texel_at(center, &point))
     }
 
-    /// Compute the texel on the given point on the spheres surface
+    /// Compute the texel on the given point on the surface of the sphere around `center`
     /// Maps the texel according to [this](https://en.wikipedia.org/wiki/UV_mapping#Finding_UV_on_a_sphere) routine
-    fn get_texel_at(&self, p: &Point3) -> Texel {
-        let d = Vec3::normal(&(self.center - *p));
+    fn get_texel_at(center: Point3, p: &Point3) -> Texel {
+        let d = Vec3::normal(&(center - *p));
         let u = 0.5 + (d[0].atan2(d[2])) / (2. * PI);
         let v = 0.5 - (d[1].asin()) / (PI);
 
@@ -93,6 +101,87 @@ impl Sphere {
     }
 }
 
+impl Traceable for Sphere {
+    fn kind(&self) -> &'static str {
+        "sphere"
+    }
+
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    /// Return the point where the ray enters the sphere and the point where it exits again, in
+    /// this order
+    /// Both roots are returned even if they lie outside the bounds of the ray, so the entry has a
+    /// negative `t` if the ray starts inside the sphere. A tangent ray enters and exits at the same
+    /// point. Both normals point outwards
+    /// Returns `None` if the line of the ray misses the sphere
+    fn intersection_interval(&self, with: &Ray) -> Option<(HitPoint, HitPoint)> {
+        let (entry, exit) = self.roots(with)?;
+        Some((self.hit_at(with, entry), self.hit_at(with, exit)))
+    }
+
+    /// Set the frame percentage to lerp between starting and end parameters
+    fn set_frame(&mut self, w: f32) {
+        if let Some(a) = &mut self.animation {
+            a.w = w;
+        }
+    }
+
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        let (center, radius) = self.params();
+        let r = Vec3::new(radius, radius, radius);
+        Some((center - r, center + r))
+    }
+
+    /// The whole texture is mapped onto the surface area `4 * PI * r^2`
+    fn texel_density(&self) -> f32 {
+        1. / (2. * self.params().1 * PI.sqrt())
+    }
+
+    /// Return true if end parameters were set
+    fn is_animated(&self) -> bool {
+        self.animation.is_some()
+    }
+
+    /// Keep the point at the same place relative to the center and the radius
+    fn advect(&self, point: &Point3, w: f32) -> Point3 {
+        let (center, radius) = self.params();
+        let (next_center, next_radius) = self.params_at(w);
+        if radius <= 0. {
+            return *point - center + next_center;
+        }
+        next_center + (next_radius / radius) * (*point - center)
+    }
+
+    /// Report the entry and the exit of the ray, if they lie within its bounds
+    fn intersections_all(&self, with: &Ray, hit: &mut dyn FnMut(f32, Vec3, Texel)) {
+        if let Some((entry, exit)) = self.roots(with) {
+            for t in [entry, exit].into_iter().filter(|t| with.t_in_range(*t)) {
+                let (t, normal, texel) = self.hit_at(with, t);
+                hit(t, normal, texel);
+            }
+        }
+    }
+
+    /// Test if any object intersects with the ray
+    fn has_intersection(&self, with: &Ray) -> bool {
+        let (a, h, c) = self.intersection_coefficients(with);
+        let discr = h * h - a * c;
+        discr >= 0. && with.at((h - discr.sqrt()) / a).is_some()
+    }
+
+    /// Calculates the intersection of the sphere and the `with` Ray if present
+    /// The normal in the intersection object will not necessarily be normalized
+    /// Returns `None` if there is no intersection
+    fn intersection(&self, with: &Ray) -> Option<(f32, Vec3, Texel)> {
+        let (entry, exit) = self.roots(with)?;
+        // use the exit if the entry lies behind or at the origin of the ray
+        let t = [entry, exit].into_iter().find(|t| with.t_in_range(*t))?;
+        Some(self.hit_at(with, t))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::math::Vec3;
@@ -112,4 +201,70 @@ mod tests {
         let behind = Ray::new(Point3::zero(), Vec3::new(0., 0., 1.));
         assert!(sphere.intersection(&behind).is_none());
     }
+
+    fn assert_hit(hit: HitPoint, t: f32, normal: Vec3) {
+        assert!((hit.0 - t).abs() < 1e-5, "t = {}, expected {t}", hit.0);
+        let n = Vec3::normal(&hit.1);
+        assert!(
+            (n - normal).length() < 1e-5,
+            "normal = {n:?}, expected {normal:?}"
+        );
+    }
+
+    #[test]
+    fn interval_from_outside() {
+        let sphere = Sphere::new(Point3::new(0., 0., -3.), 1.);
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let (entry, exit) = sphere.intersection_interval(&ray).unwrap();
+        assert_hit(entry, 2., Vec3::new(0., 0., 1.));
+        assert_hit(exit, 4., Vec3::new(0., 0., -1.));
+        assert_eq!(sphere.intersection(&ray).map(|hit| hit.0), Some(entry.0));
+    }
+
+    #[test]
+    fn interval_from_inside() {
+        let sphere = Sphere::new(Point3::zero(), 2.);
+        let ray = Ray::new(Point3::new(1., 0., 0.), Vec3::new(1., 0., 0.));
+        let (entry, exit) = sphere.intersection_interval(&ray).unwrap();
+        assert_hit(entry, -3., Vec3::new(-1., 0., 0.));
+        assert_hit(exit, 1., Vec3::new(1., 0., 0.));
+        assert_eq!(sphere.intersection(&ray).map(|hit| hit.0), Some(exit.0));
+    }
+
+    #[test]
+    fn ray_from_inside_hits_far_side() {
+        let sphere = Sphere::new(Point3::new(0., 0., -1.), 2.);
+        // the camera sits inside the sphere, the entry lies behind it
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let (t, normal, _) = sphere.intersection(&ray).unwrap();
+        assert!((t - 3.).abs() < 1e-5, "t = {t}, expected the exit at 3");
+        assert!((Vec3::normal(&normal) - Vec3::new(0., 0., -1.)).length() < 1e-5);
+        // the far side doesn't block shadow rays that start inside of the sphere
+        assert!(!sphere.has_intersection(&ray));
+
+        // rays that end before the exit don't hit the sphere
+        assert!(sphere.intersection(&ray.set_bounds(2.)).is_none());
+    }
+
+    #[test]
+    fn interval_of_tangent_ray() {
+        let sphere = Sphere::new(Point3::new(0., 1., -2.), 1.);
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -1.));
+        let (entry, exit) = sphere.intersection_interval(&ray).unwrap();
+        assert_hit(entry, 2., Vec3::new(0., -1., 0.));
+        assert_hit(exit, 2., Vec3::new(0., -1., 0.));
+
+        let miss = Ray::new(Point3::new(0., -0.01, 0.), Vec3::new(0., 0., -1.));
+        assert!(sphere.intersection_interval(&miss).is_none());
+    }
+
+    #[test]
+    fn interval_behind_ray() {
+        let sphere = Sphere::new(Point3::new(0., 0., 3.), 1.);
+        let ray = Ray::new(Point3::zero(), Vec3::new(0., 0., -2.));
+        let (entry, exit) = sphere.intersection_interval(&ray).unwrap();
+        assert_hit(entry, -2., Vec3::new(0., 0., 1.));
+        assert_hit(exit, -1., Vec3::new(0., 0., -1.));
+        assert!(sphere.intersection(&ray).is_none());
+    }
 }
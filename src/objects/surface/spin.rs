@@ -0,0 +1,110 @@
+use crate::math::{Mat4, Point3, Ray, Vec3};
+
+use super::{Texel, Traceable};
+
+/// Wraps a primitive and rotates it around the y-axis of its object space over the course of the
+/// animation
+#[derive(Clone, Debug)]
+pub(super) struct Spin {
+    inner: Box<dyn Traceable>,
+    /// rotation at the end of the animation in radians
+    end_angle: f32,
+    /// inverse rotation of the current frame
+    transform: Mat4,
+    /// rotation of the current frame, used for the normals
+    normal_transform: Mat4,
+    /// percentage of the animation that is finished
+    w: f32,
+}
+
+impl Spin {
+    /// Wrap `inner`, which will be rotated by `end_angle` radians at the end of the animation
+    pub fn new(inner: Box<dyn Traceable>, end_angle: f32) -> Spin {
+        Spin {
+            inner,
+            end_angle,
+            transform: Mat4::identity(),
+            normal_transform: Mat4::identity(),
+            w: 0.,
+        }
+    }
+
+    /// Return the inverse rotation and the rotation at the time of the ray
+    /// Rays without a time or at the time of the current frame use the rotation of the frame
+    fn rotations(&self, ray: &Ray) -> (Mat4, Mat4) {
+        match ray.time() {
+            Some(w) if (w - self.w).abs() > f32::EPSILON => {
+                let transform = Mat4::from_y_rotation(-w * self.end_angle);
+                (transform, Mat4::transpose(&transform))
+            }
+            _ => (self.transform, self.normal_transform),
+        }
+    }
+}
+
+impl Traceable for Spin {
+    fn set_frame(&mut self, w: f32) {
+        self.inner.set_frame(w);
+        self.w = w;
+        self.transform = Mat4::from_y_rotation(-w * self.end_angle);
+        // the inverse transpose of a rotation is the rotation itself
+        self.normal_transform = Mat4::transpose(&self.transform);
+    }
+
+    fn set_time(&mut self, time: f32) {
+        self.inner.set_time(time);
+    }
+
+    fn is_animated(&self) -> bool {
+        true
+    }
+
+    /// Turn the point back to the inner primitive, follow it there and turn it by the rotation at
+    /// `w`
+    fn advect(&self, point: &Point3, w: f32) -> Point3 {
+        let inner = self.inner.advect(&self.transform.transform_point(point), w);
+        Mat4::from_y_rotation(w * self.end_angle).transform_point(&inner)
+    }
+
+    /// Sample the inner primitive and turn the point by the rotation of the current frame
+    fn sample_area(&self, pick: f32, uv: (f32, f32)) -> Option<(Point3, Vec3)> {
+        let (point, normal) = self.inner.sample_area(pick, uv)?;
+        Some((
+            self.normal_transform.transform_point(&point),
+            self.normal_transform.transform_vector(&normal),
+        ))
+    }
+
+    fn kind(&self) -> &'static str {
+        self.inner.kind()
+    }
+
+    fn texel_density(&self) -> f32 {
+        self.inner.texel_density()
+    }
+
+    fn intersection_epsilon(&self) -> f32 {
+        self.inner.intersection_epsilon()
+    }
+
+    /// The box of the primitive swept around the y-axis, so it holds for every frame
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        let (min, max) = self.inner.bounding_box()?;
+        let r = [min[0], max[0]]
+            .into_iter()
+            .flat_map(|x| [min[2], max[2]].map(|z| (x * x + z * z).sqrt()))
+            .fold(0., f32::max);
+        Some((Point3::new(-r, min[1], -r), Point3::new(r, max[1], r)))
+    }
+
+    fn has_intersection(&self, ray: &Ray) -> bool {
+        self.inner
+            .has_intersection(&ray.transform(&self.rotations(ray).0))
+    }
+
+    fn intersection(&self, ray: &Ray) -> Option<(f32, Vec3, Texel)> {
+        let (transform, normal_transform) = self.rotations(ray);
+        let (t, normal, texel) = self.inner.intersection(&ray.transform(&transform))?;
+        Some((t, normal_transform.transform_vector(&normal), texel))
+    }
+}
@@ -0,0 +1,119 @@
+use std::any::Any;
+use std::fmt::Debug;
+
+use crate::math::{Point3, Ray, Vec3};
+
+use super::{HitPoint, Texel};
+
+/// Trait for any primitive that can be intersected with a ray
+/// All calculations happen in object space; transformations and materials are handled by the
+/// `Surface` that owns the primitive
+/// Primitives need to be `Clone`, so that surfaces can be duplicated
+pub trait Traceable: Any + Debug + Send + Sync + TraceableClone {
+    /// Determine if the primitive intersects with the ray
+    fn has_intersection(&self, ray: &Ray) -> bool;
+
+    /// Calculate the closest intersection of the primitive and the ray if it exists
+    /// Returns the `t` value, the (not necessarily normalized) normal and the texel
+    fn intersection(&self, ray: &Ray) -> Option<(f32, Vec3, Texel)>;
+
+    /// Report every intersection of the primitive and the ray within the bounds of the ray to
+    /// `hit`, in any order
+    /// Defaults to the closest intersection, primitives that a ray can pass through more than once
+    /// should report all of them
+    fn intersections_all(&self, ray: &Ray, hit: &mut dyn FnMut(f32, Vec3, Texel)) {
+        if let Some((t, normal, texel)) = self.intersection(ray) {
+            hit(t, normal, texel);
+        }
+    }
+
+    /// Return true if the primitive is a closed convex solid, which every ray that hits it enters
+    /// once and exits once
+    /// Solids report both hits with `intersection_interval` and get caps where clip planes cut
+    /// them open
+    fn is_solid(&self) -> bool {
+        false
+    }
+
+    /// Return the point where the line of the ray enters a solid primitive and the point where it
+    /// exits again, in this order, with outward normals
+    /// Both hits are returned even if they lie outside the bounds of the ray. Returns `None` if the
+    /// line misses the primitive or the primitive is not a solid
+    fn intersection_interval(&self, _ray: &Ray) -> Option<(HitPoint, HitPoint)> {
+        None
+    }
+
+    /// Return the minimum and maximum corner of the axis aligned bounding box of the primitive
+    /// in its current frame
+    /// Primitives without a bounding box are never culled
+    fn bounding_box(&self) -> Option<(Point3, Point3)> {
+        None
+    }
+
+    /// Return an estimate of the texture space units per object space unit on the surface
+    /// Used to select the mip level for textures, 0 disables filtering
+    fn texel_density(&self) -> f32 {
+        0.
+    }
+
+    /// Return the distance to the surface in object space, within which the primitive reports
+    /// intersections
+    /// Secondary rays are moved further away from the surface than that, so they don't hit the
+    /// primitive again right where they start
+    fn intersection_epsilon(&self) -> f32 {
+        0.
+    }
+
+    /// Set the frame percentage
+    /// w is the percentage that the animation is finished
+    fn set_frame(&mut self, _w: f32) {}
+
+    /// Set the time of the current frame in seconds
+    /// Only needed by primitives that move at a speed instead of between start and end parameters
+    fn set_time(&mut self, _time: f32) {}
+
+    /// Return true if the primitive changes between frames
+    fn is_animated(&self) -> bool {
+        false
+    }
+
+    /// Return where the point `point` on the primitive in the current frame lies at the frame
+    /// percentage `w`
+    /// Defaults to the point itself, primitives that move or change their size should follow the
+    /// point. Primitives that change their shape in a way that has no such correspondence keep the
+    /// default
+    fn advect(&self, point: &Point3, _w: f32) -> Point3 {
+        *point
+    }
+
+    /// Return a point on the primitive and the (not necessarily normalized) normal there, for the
+    /// random numbers `pick` and `(a, b)` in `[0, 1)`
+    /// Uniform random numbers give points that are spread evenly over the area of the primitive.
+    /// Primitives that can't be sampled return `None`
+    fn sample_area(&self, _pick: f32, _uv: (f32, f32)) -> Option<(Point3, Vec3)> {
+        None
+    }
+
+    /// Return the name of the kind of primitive, used to build the ids of surfaces
+    fn kind(&self) -> &'static str {
+        "surface"
+    }
+}
+
+/// Helper trait to clone boxed primitives
+/// Implemented for every `Traceable` that is `Clone`
+pub trait TraceableClone {
+    fn clone_box(&self) -> Box<dyn Traceable>;
+}
+
+impl<T: Traceable + Clone> TraceableClone for T {
+    fn clone_box(&self) -> Box<dyn Traceable> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Traceable> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
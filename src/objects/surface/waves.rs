@@ -0,0 +1,77 @@
+use std::f32::consts::TAU;
+
+use crate::math::{tangent_frame, Point3, Vec3};
+
+/// Procedural waves that ripple the shading normal of a flat surface, like the surface of water
+/// The geometry stays flat, only the normal is tilted by the slope of a sum of sine waves that
+/// travel over the surface with time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Waves {
+    /// height of the waves
+    pub amplitude: f32,
+    /// number of waves per unit of the longest wave
+    pub frequency: f32,
+    /// distance the waves travel per second
+    pub speed: f32,
+}
+
+impl Waves {
+    /// direction (as angle in radians), frequency multiplier and share of the amplitude of the
+    /// summed waves
+    /// The directions and frequencies don't line up, so the pattern doesn't look regular
+    const COMPONENTS: [(f32, f32, f32); 4] = [
+        (0., 1., 0.5),
+        (2.1, 1.7, 0.25),
+        (4.0, 2.9, 0.15),
+        (5.3, 4.3, 0.1),
+    ];
+
+    /// Return the unit normal of the surface with the unit normal `normal` at `point` after
+    /// `time` seconds, tilted by the slope of the waves
+    #[must_use]
+    pub fn perturb(&self, normal: &Vec3, point: &Point3, time: f32) -> Vec3 {
+        let (tangent, bitangent) = tangent_frame(normal);
+        let (x, y) = (point.dot(&tangent), point.dot(&bitangent));
+        let (dx, dy) = Self::COMPONENTS
+            .iter()
+            .fold((0., 0.), |(dx, dy), (angle, scale, share)| {
+                let (dir_y, dir_x) = angle.sin_cos();
+                let k = TAU * self.frequency * scale;
+                let phase = k * (dir_x * x + dir_y * y - self.speed * time);
+                let slope = self.amplitude * share * k * phase.cos();
+                (dx + slope * dir_x, dy + slope * dir_y)
+            });
+        Vec3::normal(&(*normal - dx * tangent - dy * bitangent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_tilt_the_normal_and_move_with_time() {
+        let waves = Waves {
+            amplitude: 0.02,
+            frequency: 2.,
+            speed: 1.,
+        };
+        let up = Vec3::new(0., 1., 0.);
+        let at = |x: f32, time: f32| waves.perturb(&up, &Point3::new(x, 0., 0.3), time);
+
+        let normal = at(0.1, 0.);
+        assert!((normal.length() - 1.).abs() < 1e-5);
+        assert!(normal.dot(&up) > 0.5);
+        assert!((normal - at(0.2, 0.)).length() > 1e-3);
+        assert!((normal - at(0.1, 0.25)).length() > 1e-3);
+
+        // the pattern repeats after the longest wave has travelled a full period
+        assert!((normal - at(0.1, 10.)).length() < 1e-3);
+
+        let flat = Waves {
+            amplitude: 0.,
+            ..waves
+        };
+        assert!((flat.perturb(&up, &Point3::new(0.1, 0., 0.3), 0.) - up).length() < 1e-6);
+    }
+}
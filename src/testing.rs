@@ -0,0 +1,433 @@
+//! Random scenes for robustness tests
+//! Generates valid scene files with random surfaces, lights, materials and transformations,
+//! including pathological ones (tiny and flattening scales, nearly degenerate quads and mesh
+//! triangles, coincident spheres), renders them at a low resolution and checks that this neither
+//! panics nor produces pixels that are NaN or infinite
+//! Only compiled for tests and with the `testing` feature
+
+use std::{
+    fmt::Write,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    input::{str_to_scene, LoadOptions},
+    math::Vec3,
+    objects::Camera,
+};
+
+/// Width and height of the renders of [`check_random_scene`]
+pub const RESOLUTION: u32 = 32;
+
+/// Meshes of the `scenes` directory that random scenes use
+const MESHES: [&str; 5] = [
+    "box.obj",
+    "cone.obj",
+    "cylinder.obj",
+    "plane_small.obj",
+    "sliver.obj",
+];
+
+/// Return a random scene file for `seed` with about `complexity` surfaces and lights
+/// The same seed and complexity always give the same file. Meshes are referenced relative to the
+/// `scenes` directory of the repository
+#[must_use]
+pub fn random_scene(seed: u64, complexity: usize) -> String {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut xml = String::from("<scene output_file=\"random.png\">\n");
+    let _ = writeln!(xml, "<background_color {}/>", rgb(&mut rng));
+    if rng.random_bool(0.5) {
+        let samples = rng.random_range(1..=3);
+        let _ = writeln!(xml, "<super_sampling samples=\"{samples}\"/>");
+    }
+    xml += &camera(&mut rng);
+
+    xml += "<lights>\n";
+    for _ in 0..rng.random_range(1..=complexity.max(1)) {
+        xml += &light(&mut rng);
+    }
+    xml += "</lights>\n<surfaces>\n";
+    let mut spheres = Vec::new();
+    for _ in 0..complexity.max(1) {
+        xml += &surface(&mut rng, &mut spheres);
+    }
+    xml += "</surfaces>\n</scene>\n";
+    xml
+}
+
+/// Load `xml` like a file in the `scenes` directory, render it at [`RESOLUTION`] and check every
+/// pixel
+///
+/// # Errors
+///
+/// Returns a description of the problem followed by the scene file if loading fails, rendering
+/// panics or a pixel is not finite
+pub fn check_scene(xml: &str) -> Result<(), String> {
+    let report = |problem: String| format!("{problem}\nscene:\n{xml}");
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("scenes")
+        .join("random.xml");
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<(), String> {
+        let scene = str_to_scene(xml, &path, &LoadOptions::default())
+            .map_err(|err| format!("loading failed: {}", err.msg()))?;
+        let mut problems = String::new();
+        for y in 0..RESOLUTION {
+            for x in 0..RESOLUTION {
+                let color = scene.trace_pixel(x, Camera::row_from_image(y, RESOLUTION));
+                if !(color[0].is_finite() && color[1].is_finite() && color[2].is_finite()) {
+                    let _ = writeln!(problems, "pixel ({x}, {y}) is {color:?}");
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }));
+    match result {
+        Ok(result) => result.map_err(report),
+        Err(panic) => {
+            let msg = panic
+                .downcast_ref::<&str>()
+                .map(ToString::to_string)
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(report(format!("rendering panicked: {msg}")))
+        }
+    }
+}
+
+/// Generate the scene for `seed` and `complexity` and check it, see [`check_scene`]
+///
+/// # Errors
+///
+/// Returns the problem and the generated scene file to reproduce it
+pub fn check_random_scene(seed: u64, complexity: usize) -> Result<(), String> {
+    check_scene(&random_scene(seed, complexity)).map_err(|msg| format!("seed {seed}: {msg}"))
+}
+
+/// Return a random number, mostly of moderate size but sometimes tiny or zero
+fn number(rng: &mut StdRng, range: f32) -> f32 {
+    match rng.random_range(0..20) {
+        0 => 0.,
+        1 => 1e-6,
+        _ => rng.random_range(-range..range),
+    }
+}
+
+/// Return a random positive number below `max`, sometimes tiny
+fn positive(rng: &mut StdRng, max: f32) -> f32 {
+    if rng.random_range(0..10) == 0 {
+        1e-6
+    } else {
+        rng.random_range(1e-3..max)
+    }
+}
+
+/// Return a random vector, which is never zero but can lie on an axis
+fn nonzero(rng: &mut StdRng, range: f32) -> Vec3 {
+    let mut v = [number(rng, range), number(rng, range), number(rng, range)];
+    if v.iter().all(|c| c.abs() < 1e-3) {
+        v[rng.random_range(0..3)] = range;
+    }
+    Vec3::new(v[0], v[1], v[2])
+}
+
+/// Return the attributes of a random direction
+fn direction(rng: &mut StdRng) -> String {
+    attributes(nonzero(rng, 1.))
+}
+
+fn vector(rng: &mut StdRng, range: f32) -> String {
+    attributes(Vec3::new(
+        number(rng, range),
+        number(rng, range),
+        number(rng, range),
+    ))
+}
+
+fn attributes(v: Vec3) -> String {
+    format!("x=\"{}\" y=\"{}\" z=\"{}\"", v[0], v[1], v[2])
+}
+
+fn rgb(rng: &mut StdRng) -> String {
+    let (r, g, b): (f32, f32, f32) = (rng.random(), rng.random(), rng.random());
+    format!("r=\"{r}\" g=\"{g}\" b=\"{b}\"")
+}
+
+fn camera(rng: &mut StdRng) -> String {
+    let position = format!(
+        "x=\"{}\" y=\"{}\" z=\"{}\"",
+        rng.random_range(-2f32..2.),
+        rng.random_range(-2f32..2.),
+        rng.random_range(3f32..6.)
+    );
+    let dof = if rng.random_bool(0.3) {
+        let focal_length: f32 = rng.random_range(1. ..8.);
+        let aperture: f32 = rng.random_range(0. ..0.3);
+        format!("<depth_of_field focal_length=\"{focal_length}\" aperture=\"{aperture}\"/>")
+    } else {
+        String::new()
+    };
+    let fov = rng.random_range(20..90);
+    let bounces = rng.random_range(0..5);
+    format!(
+        "<camera>
+  <position {position}/>
+  <lookat x=\"0\" y=\"0\" z=\"0\"/>
+  <up x=\"0\" y=\"1\" z=\"0\"/>
+  <horizontal_fov angle=\"{fov}\"/>
+  {dof}
+  <resolution horizontal=\"{RESOLUTION}\" vertical=\"{RESOLUTION}\"/>
+  <max_bounces n=\"{bounces}\"/>
+</camera>\n"
+    )
+}
+
+fn light(rng: &mut StdRng) -> String {
+    let color = rgb(rng);
+    let position = vector(rng, 5.);
+    let direction = direction(rng);
+    match rng.random_range(0..4) {
+        0 => format!("<ambient_light><color {color}/></ambient_light>\n"),
+        1 => {
+            let area = if rng.random_bool(0.3) {
+                let radius: f32 = rng.random_range(0. ..1.);
+                format!(" radius=\"{radius}\" samples=\"4\"")
+            } else {
+                String::new()
+            };
+            format!("<point_light{area}><color {color}/><position {position}/></point_light>\n")
+        }
+        2 => format!("<parallel_light><color {color}/><direction {direction}/></parallel_light>\n"),
+        _ => {
+            let inner: f32 = rng.random_range(0. ..60.);
+            let outer: f32 = inner + rng.random_range(0.1..30.);
+            let profile = if rng.random_bool(0.3) {
+                let values: Vec<String> = (0..rng.random_range(1..6))
+                    .map(|_| rng.random::<f32>().to_string())
+                    .collect();
+                format!("<profile values=\"{}\"/>", values.join(" "))
+            } else {
+                String::new()
+            };
+            format!(
+                "<spot_light><color {color}/><position {position}/><direction {direction}/>\
+                 <falloff alpha1=\"{inner}\" alpha2=\"{outer}\"/>{profile}</spot_light>\n"
+            )
+        }
+    }
+}
+
+fn material(rng: &mut StdRng) -> String {
+    let shading = if rng.random_bool(0.5) {
+        let exponent = rng.random_range(1..200);
+        format!(
+            "<phong ka=\"{}\" kd=\"{}\" ks=\"{}\" exponent=\"{exponent}\"/>",
+            rng.random::<f32>(),
+            rng.random::<f32>(),
+            rng.random::<f32>()
+        )
+    } else {
+        format!(
+            "<cook_torrance ka=\"{}\" ks=\"{}\" roughness=\"{}\"/>",
+            rng.random::<f32>(),
+            rng.random::<f32>(),
+            positive(rng, 1.)
+        )
+    };
+    let iof = [0., 1., 1.33, 1.5, 2.4][rng.random_range(0..5)];
+    format!(
+        "<material_solid><color {}/>{shading}<reflectance r=\"{}\"/>\
+         <transmittance t=\"{}\"/><refraction iof=\"{iof}\"/></material_solid>",
+        rgb(rng),
+        rng.random::<f32>() * 0.8,
+        rng.random::<f32>() * 0.8
+    )
+}
+
+/// Return a random transformation, sometimes with tiny or flattening scales
+fn transform(rng: &mut StdRng) -> String {
+    let mut steps = String::new();
+    for _ in 0..rng.random_range(0..4) {
+        let step = match rng.random_range(0..5) {
+            0 => format!("<translate {}/>", vector(rng, 2.)),
+            1 => {
+                let tiny = rng.random_bool(0.3);
+                let mut factor = || {
+                    if tiny && rng.random_bool(0.5) {
+                        1e-6
+                    } else {
+                        rng.random_range(0.1f32..2.)
+                    }
+                };
+                format!(
+                    "<scale x=\"{}\" y=\"{}\" z=\"{}\"/>",
+                    factor(),
+                    factor(),
+                    factor()
+                )
+            }
+            axis => {
+                let angle: f32 = rng.random_range(-360. ..360.);
+                let axis = ["X", "Y", "Z"][axis - 2];
+                format!("<rotate{axis} theta=\"{angle}\"/>")
+            }
+        };
+        steps += &step;
+    }
+    if steps.is_empty() {
+        steps
+    } else {
+        format!("<transform>{steps}</transform>")
+    }
+}
+
+/// Return a random surface
+/// `spheres` holds the positions and radii of the spheres so far, new spheres sometimes
+/// coincide with one of them
+fn surface(rng: &mut StdRng, spheres: &mut Vec<(String, f32)>) -> String {
+    let material = material(rng);
+    let transform = transform(rng);
+    match rng.random_range(0..5) {
+        0 => {
+            let (position, radius) = match spheres.last() {
+                Some(sphere) if rng.random_bool(0.3) => sphere.clone(),
+                _ => (vector(rng, 2.), positive(rng, 1.5)),
+            };
+            spheres.push((position.clone(), radius));
+            format!(
+                "<sphere radius=\"{radius}\"><position {position}/>{material}{transform}</sphere>\n"
+            )
+        }
+        1 => format!(
+            "<ellipsoid rx=\"{}\" ry=\"{}\" rz=\"{}\"><position {}/>{material}{transform}</ellipsoid>\n",
+            positive(rng, 1.),
+            positive(rng, 1.),
+            positive(rng, 1.),
+            vector(rng, 2.)
+        ),
+        2 => format!(
+            "<disc radius=\"{}\" both_sides=\"{}\"><position {}/><normal {}/>{material}{transform}</disc>\n",
+            positive(rng, 2.),
+            rng.random_bool(0.5),
+            vector(rng, 2.),
+            direction(rng)
+        ),
+        3 => {
+            let edge_u = nonzero(rng, 2.);
+            // the axis of the smallest component of `edge_u` is never parallel to it
+            let axis = match (0..3).min_by(|&a, &b| edge_u[a].abs().total_cmp(&edge_u[b].abs())) {
+                Some(0) => Vec3::new(1., 0., 0.),
+                Some(1) => Vec3::new(0., 1., 0.),
+                _ => Vec3::new(0., 0., 1.),
+            };
+            let edge_v = if rng.random_bool(0.3) {
+                // nearly parallel edges make a nearly degenerate quad
+                edge_u + 1e-4 * axis
+            } else {
+                let edge_v = nonzero(rng, 2.);
+                if edge_u.cross(&edge_v).length_squared() == 0. {
+                    edge_v + axis
+                } else {
+                    edge_v
+                }
+            };
+            format!(
+                "<quad both_sides=\"{}\"><corner {}/><edge_u {}/><edge_v {}/>\
+                 {material}{transform}</quad>\n",
+                rng.random_bool(0.5),
+                vector(rng, 2.),
+                attributes(edge_u),
+                attributes(edge_v)
+            )
+        }
+        _ => format!(
+            "<mesh name=\"{}\" smooth_shading=\"{}\">{material}{transform}</mesh>\n",
+            MESHES[rng.random_range(0..MESHES.len())],
+            rng.random_bool(0.5)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_scenes_are_reproducible() {
+        assert_eq!(random_scene(3, 5), random_scene(3, 5));
+        assert_ne!(random_scene(3, 5), random_scene(4, 5));
+    }
+
+    #[test]
+    fn random_scenes_render_without_panics_or_nan() {
+        for seed in 0..16 {
+            if let Err(msg) = check_random_scene(seed, 8) {
+                panic!("{msg}");
+            }
+        }
+    }
+
+    #[test]
+    fn thin_mesh_triangles_render_without_nan() {
+        for seed in 0..8 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut xml = String::from("<scene output_file=\"sliver.png\">\n");
+            let _ = writeln!(xml, "<background_color {}/>", rgb(&mut rng));
+            xml += &camera(&mut rng);
+            xml += "<lights>\n";
+            xml += &light(&mut rng);
+            xml += "</lights>\n<surfaces>\n";
+            let _ = writeln!(
+                xml,
+                "<mesh name=\"sliver.obj\" smooth_shading=\"{}\" both_sides=\"true\">{}{}</mesh>",
+                seed % 2 == 0,
+                material(&mut rng),
+                transform(&mut rng)
+            );
+            xml += "</surfaces>\n</scene>\n";
+            if let Err(msg) = check_scene(&xml) {
+                panic!("seed {seed}: {msg}");
+            }
+        }
+    }
+
+    #[test]
+    fn non_finite_mesh_vertices_render_without_panics_or_nan() {
+        // the ply reader passes non-finite coordinates on, unlike the obj reader
+        let path = std::env::temp_dir().join(format!("rt_non_finite_{}.ply", std::process::id()));
+        std::fs::write(
+            &path,
+            "ply\nformat ascii 1.0\nelement vertex 4\nproperty float x\nproperty float y\n\
+             property float z\nelement face 2\nproperty list uchar int vertex_indices\n\
+             end_header\n-1 -1 0\n1 -1 0\n0 1 0\nnan inf -inf\n3 0 1 2\n3 0 1 3\n",
+        )
+        .unwrap();
+        for seed in 0..8 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut xml = String::from("<scene output_file=\"non_finite.png\">\n");
+            let _ = writeln!(xml, "<background_color {}/>", rgb(&mut rng));
+            xml += &camera(&mut rng);
+            xml += "<lights>\n";
+            xml += &light(&mut rng);
+            xml += "</lights>\n<surfaces>\n";
+            let _ = writeln!(
+                xml,
+                "<ply name=\"{}\" smooth_shading=\"false\">{}{}</ply>",
+                path.display(),
+                material(&mut rng),
+                transform(&mut rng)
+            );
+            xml += "</surfaces>\n</scene>\n";
+            if let Err(msg) = check_scene(&xml) {
+                panic!("seed {seed}: {msg}");
+            }
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}